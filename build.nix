@@ -204,6 +204,12 @@ let
     postInstall = ''
       echo "Built whisp-away with acceleration type: ${accelerationType}"
       
+      # Completions and manpages are generated from the CLI definition itself
+      $out/bin/whisp-away completions bash | install -Dm644 /dev/stdin $out/share/bash-completion/completions/whisp-away
+      $out/bin/whisp-away completions zsh | install -Dm644 /dev/stdin $out/share/zsh/site-functions/_whisp-away
+      $out/bin/whisp-away completions fish | install -Dm644 /dev/stdin $out/share/fish/vendor_completions.d/whisp-away.fish
+      $out/bin/whisp-away man --out-dir $out/share/man/man1
+      
       # Install both Python scripts as RAW Python (not wrapped)
       # We'll call these with Python directly and inject environment from Rust
       install -Dm755 ${./src/faster_whisper/scripts/transcribe_faster.py} $out/share/whisp-away/transcribe_faster.py
@@ -76,7 +76,6 @@ in {
       home.sessionVariables = {
         WA_WHISPER_MODEL = cfg.defaultModel;
         WA_WHISPER_BACKEND = cfg.defaultBackend;
-        WA_WHISPER_SOCKET = "/tmp/whisp-away-daemon.sock";
       } // optionalAttrs (cfg.accelerationType == "cuda") {
         CUDA_VISIBLE_DEVICES = "0";
         LD_LIBRARY_PATH = "${pkgs.cudaPackages.cudatoolkit}/lib:${pkgs.cudaPackages.cudnn}/lib:\${LD_LIBRARY_PATH}";
@@ -8,7 +8,7 @@
   download-whisper-model = writeShellScriptBin "download-whisper-model" ''
     #!${pkgs.bash}/bin/bash
     
-    MODEL_DIR="$HOME/.cache/whisper-cpp/models"
+    MODEL_DIR="''${XDG_CACHE_HOME:-$HOME/.cache}/whisper-cpp/models"
     mkdir -p "$MODEL_DIR"
     
     MODEL="''${1:-medium.en}"
@@ -62,7 +62,7 @@
     echo ""
     echo "Downloaded models:"
     
-    MODEL_DIR="$HOME/.cache/whisper-cpp/models"
+    MODEL_DIR="''${XDG_CACHE_HOME:-$HOME/.cache}/whisper-cpp/models"
     if [ -d "$MODEL_DIR" ]; then
       for model in "$MODEL_DIR"/ggml-*.bin; do
         if [ -f "$model" ]; then
@@ -0,0 +1,117 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Annotations whisper emits for non-speech audio: `[BLANK_AUDIO]`, `[MUSIC]`,
+/// `(laughs)`, `*applause*` and music notes
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    r"\[[^\]]*\]",
+    r"\([^)]*\)",
+    r"\*[^*]*\*",
+    r"[♪♫🎵🎶]+",
+];
+
+/// Whether whisper should suppress non-speech tokens while decoding.
+/// On unless WA_SUPPRESS_NON_SPEECH is "0" or "false".
+pub fn suppress_non_speech() -> bool {
+    !matches!(std::env::var("WA_SUPPRESS_NON_SPEECH").as_deref(), Ok("0") | Ok("false"))
+}
+
+/// Regex strip list applied to every transcript after decoding
+pub struct ArtifactFilter {
+    patterns: Vec<Regex>,
+}
+
+impl ArtifactFilter {
+    /// Compile `DEFAULT_PATTERNS` (unless `use_defaults` is false) followed by `extra`.
+    /// Invalid patterns are reported and skipped rather than failing transcription.
+    pub fn new(use_defaults: bool, extra: &[String]) -> Self {
+        let defaults = DEFAULT_PATTERNS.iter().filter(|_| use_defaults).map(|p| p.to_string());
+        let patterns = defaults
+            .chain(extra.iter().cloned())
+            .filter_map(|pattern| match Regex::new(&pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    eprintln!("WARNING: Ignoring invalid strip pattern {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Self { patterns }
+    }
+    
+    /// Filter configured by WA_STRIP_PATTERNS (extra patterns, one per line) and
+    /// WA_STRIP_DEFAULTS ("0" or "false" drops the built-in list)
+    pub fn from_env() -> Self {
+        let use_defaults = !matches!(std::env::var("WA_STRIP_DEFAULTS").as_deref(), Ok("0") | Ok("false"));
+        let extra: Vec<String> = std::env::var("WA_STRIP_PATTERNS")
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self::new(use_defaults, &extra)
+    }
+    
+    /// Remove every match and tidy the whitespace left behind
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            text = pattern.replace_all(&text, " ").into_owned();
+        }
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Strip artifacts using the filter configured in the environment
+pub fn strip(text: &str) -> String {
+    static FILTER: OnceLock<ArtifactFilter> = OnceLock::new();
+    FILTER.get_or_init(ArtifactFilter::from_env).apply(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn the_defaults_strip_bracketed_annotations() {
+        let filter = ArtifactFilter::new(true, &[]);
+        assert_eq!(filter.apply("[BLANK_AUDIO]"), "");
+        assert_eq!(filter.apply(" [MUSIC] Hello there. [MUSIC]"), "Hello there.");
+        assert_eq!(filter.apply("That's funny (laughs) really."), "That's funny really.");
+        assert_eq!(filter.apply("*applause* Thank you."), "Thank you.");
+        assert_eq!(filter.apply("♪ la la ♪ ♫♫"), "la la");
+        assert_eq!(filter.apply("Nothing to strip here."), "Nothing to strip here.");
+    }
+    
+    #[test]
+    fn a_customized_list_keeps_parenthesized_speech() {
+        let filter = ArtifactFilter::new(false, &[r"\[[^\]]*\]".to_string()]);
+        assert_eq!(
+            filter.apply("[BLANK_AUDIO] The results (as expected) were fine. [MUSIC]"),
+            "The results (as expected) were fine."
+        );
+    }
+    
+    #[test]
+    fn extra_patterns_add_to_the_defaults() {
+        let filter = ArtifactFilter::new(true, &[r"(?i)\bum\b,?".to_string()]);
+        assert_eq!(filter.apply("Um, so [MUSIC] we ship (sighs) today."), "so we ship today.");
+    }
+    
+    #[test]
+    fn invalid_patterns_are_skipped() {
+        let filter = ArtifactFilter::new(false, &["[unclosed".to_string(), "foo".to_string()]);
+        assert_eq!(filter.apply("foo [bar] baz"), "[bar] baz");
+    }
+    
+    #[test]
+    fn the_list_is_customized_from_the_environment() {
+        let vars = [
+            ("WA_STRIP_DEFAULTS", Some("0")),
+            ("WA_STRIP_PATTERNS", Some("\\[[^\\]]*\\]\n\n  \\bumm\\b  \n")),
+        ];
+        let filter = crate::helpers::with_env(&vars, ArtifactFilter::from_env);
+        assert_eq!(filter.apply("[NOISE] Umm, yes (really) umm."), "Umm, yes (really) .");
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::Result;
+use std::time::Duration;
+use crate::wav::TARGET_RATE;
+
+/// Cutoff of the preprocessing high-pass: below speech, above fan and mains rumble
+pub const HIGH_PASS_HZ: f32 = 80.0;
+/// Peak level preprocessing normalizes to
+const NORMALIZE_PEAK_DBFS: f32 = -3.0;
+/// Most gain normalization applies, so a near-silent recording's noise isn't
+/// blown up to full scale
+const NORMALIZE_MAX_GAIN_DB: f32 = 20.0;
+
+/// Decoded audio: one f32 buffer per channel, all at `sample_rate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buffer {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Buffer {
+    pub fn mono(sample_rate: u32, samples: Vec<f32>) -> Self {
+        Buffer { sample_rate, channels: vec![samples] }
+    }
+    
+    /// Decode a WAV file, or a FLAC one since kept recordings may have been
+    /// compressed
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if crate::flac::is_flac(bytes) {
+            let (sample_rate, channels) = crate::flac::decode(bytes)?;
+            return Ok(Buffer { sample_rate, channels });
+        }
+        let wav = crate::wav::parse(bytes)?;
+        Ok(Buffer { sample_rate: wav.format.sample_rate, channels: wav.channels() })
+    }
+    
+    /// Whole frames: the shortest channel's length
+    pub fn frames(&self) -> usize {
+        self.channels.iter().map(Vec::len).min().unwrap_or(0)
+    }
+    
+    /// The first channel, which is all there is after a downmix
+    pub fn into_samples(self) -> Vec<f32> {
+        self.channels.into_iter().next().unwrap_or_default()
+    }
+}
+
+/// One step of a pipeline. Each is a function from buffer to buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// Drop this much from the start, never more than the whole buffer
+    TrimLead(Duration),
+    /// Average the channels into one
+    Downmix,
+    Resample(u32),
+    /// Second-order high-pass at this cutoff
+    HighPass(f32),
+    /// Bring the peak to -3 dBFS, by at most +20 dB
+    Normalize,
+}
+
+/// What a pipeline made of its input, and what its stages did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub buffer: Buffer,
+    /// Dropped by TrimLead, at the input's rate
+    pub trimmed: Duration,
+    /// Applied by Normalize
+    pub gain_db: Option<f32>,
+}
+
+/// Audio conversion as an ordered list of stages. Every way audio comes in
+/// (recordings, `transcribe`, the daemons and direct backends) goes through
+/// one of these, so each conversion exists once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// No stages: the input as it is
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+    
+    /// 16 kHz mono, which is what whisper takes
+    pub fn target() -> Self {
+        Pipeline::new().then(Stage::Downmix).then(Stage::Resample(TARGET_RATE))
+    }
+    
+    /// Add `stage` after the ones so far
+    pub fn then(mut self, stage: Stage) -> Self {
+        self.stages.push(stage);
+        self
+    }
+    
+    pub fn run(&self, buffer: Buffer) -> Output {
+        let mut output = Output { buffer, trimmed: Duration::ZERO, gain_db: None };
+        for stage in &self.stages {
+            let buffer = &mut output.buffer;
+            match *stage {
+                Stage::TrimLead(lead) => {
+                    let frames = trim_lead(buffer, lead);
+                    output.trimmed += frames_to_duration(frames, buffer.sample_rate);
+                }
+                Stage::Downmix => buffer.channels = vec![downmix(&buffer.channels)],
+                Stage::Resample(rate) => {
+                    for channel in buffer.channels.iter_mut() {
+                        *channel = resample(channel, buffer.sample_rate, rate);
+                    }
+                    buffer.sample_rate = rate;
+                }
+                Stage::HighPass(cutoff_hz) => {
+                    for channel in buffer.channels.iter_mut() {
+                        high_pass(channel, cutoff_hz, buffer.sample_rate);
+                    }
+                }
+                Stage::Normalize => output.gain_db = Some(peak_normalize(&mut buffer.channels)),
+            }
+        }
+        output
+    }
+    
+    /// Decode a WAV or FLAC file and run it through, returning the first
+    /// channel: the samples, for a pipeline that downmixes
+    pub fn samples(&self, bytes: &[u8]) -> Result<Vec<f32>> {
+        Ok(self.run(Buffer::decode(bytes)?).buffer.into_samples())
+    }
+}
+
+fn frames_to_duration(frames: usize, sample_rate: u32) -> Duration {
+    Duration::from_millis(frames as u64 * 1000 / sample_rate as u64)
+}
+
+/// Drop `lead` from the start of every channel, returning the frames dropped
+pub fn trim_lead(buffer: &mut Buffer, lead: Duration) -> usize {
+    let lead_frames = (lead.as_millis() as u64 * buffer.sample_rate as u64 / 1000) as usize;
+    let trimmed = lead_frames.min(buffer.frames());
+    for channel in buffer.channels.iter_mut() {
+        channel.drain(..trimmed);
+    }
+    trimmed
+}
+
+/// Average each frame's samples across channels; frames past the shortest
+/// channel are dropped
+pub fn downmix(channels: &[Vec<f32>]) -> Vec<f32> {
+    match channels {
+        [] => Vec::new(),
+        [mono] => mono.clone(),
+        _ => {
+            let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+            (0..frames)
+                .map(|i| channels.iter().map(|channel| channel[i]).sum::<f32>() / channels.len() as f32)
+                .collect()
+        }
+    }
+}
+
+/// Linear-interpolation resampler; good enough for speech going into whisper
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    
+    let out_len = (samples.len() as u64 * to_rate as u64 / from_rate as u64) as usize;
+    let step = from_rate as f64 / to_rate as f64;
+    (0..out_len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let idx = pos as usize;
+            let frac = (pos - idx as f64) as f32;
+            let a = samples[idx.min(samples.len() - 1)];
+            let b = samples[(idx + 1).min(samples.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Second-order Butterworth high-pass (RBJ cookbook biquad, Q = 1/√2)
+pub fn high_pass(samples: &mut [f32], cutoff_hz: f32, sample_rate: u32) {
+    let w0 = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate as f32;
+    let alpha = w0.sin() / std::f32::consts::SQRT_2;
+    let cos = w0.cos();
+    let a0 = 1.0 + alpha;
+    let b0 = (1.0 + cos) / 2.0 / a0;
+    let b1 = -(1.0 + cos) / a0;
+    let b2 = b0;
+    let a1 = -2.0 * cos / a0;
+    let a2 = (1.0 - alpha) / a0;
+    
+    // Transposed direct form II: two state variables, one pass
+    let (mut z1, mut z2) = (0.0f32, 0.0f32);
+    for sample in samples.iter_mut() {
+        let x = *sample;
+        let y = b0 * x + z1;
+        z1 = b1 * x - a1 * y + z2;
+        z2 = b2 * x - a2 * y;
+        *sample = y;
+    }
+}
+
+/// Scale every channel by the same gain so the loudest peak sits at -3 dBFS,
+/// by at most +20 dB, returning the gain applied in dB (0 for silence)
+pub fn peak_normalize(channels: &mut [Vec<f32>]) -> f32 {
+    let peak = channels.iter().flatten().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak == 0.0 || !peak.is_finite() {
+        return 0.0;
+    }
+    let gain_db = (NORMALIZE_PEAK_DBFS - 20.0 * peak.log10()).min(NORMALIZE_MAX_GAIN_DB);
+    let gain = 10.0f32.powf(gain_db / 20.0);
+    for sample in channels.iter_mut().flatten() {
+        *sample *= gain;
+    }
+    gain_db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Fixed-seed LCG standing in for a property-testing crate: each case is
+    /// reproducible from its number
+    struct Cases(u64);
+    
+    impl Cases {
+        fn next(&mut self) -> u32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) as u32
+        }
+        
+        fn below(&mut self, n: usize) -> usize {
+            self.next() as usize % n
+        }
+        
+        fn pick<T: Copy>(&mut self, from: &[T]) -> T {
+            from[self.below(from.len())]
+        }
+        
+        /// 1 to 3 channels of different lengths, at a common rate, scaled
+        /// from near silence up to full scale
+        fn buffer(&mut self) -> Buffer {
+            let sample_rate = self.pick(&[8000, 11025, 16000, 22050, 32000, 44100, 48000, 96000]);
+            let scale = self.pick(&[0.0, 1e-4, 0.01, 0.5, 1.0]);
+            let frames = self.below(20_000);
+            let channels = (0..1 + self.below(3))
+                .map(|_| {
+                    let len = frames + self.below(50);
+                    (0..len).map(|_| (self.next() as f32 / u32::MAX as f32 * 2.0 - 1.0) * scale).collect()
+                })
+                .collect();
+            Buffer { sample_rate, channels }
+        }
+    }
+    
+    const CASES: u64 = 200;
+    
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+    
+    #[test]
+    fn the_target_pipeline_has_the_length_the_rates_give() {
+        for case in 0..CASES {
+            let input = Cases(case).buffer();
+            let expected = input.frames() as u64 * TARGET_RATE as u64 / input.sample_rate as u64;
+            let output = Pipeline::target().run(input.clone()).buffer;
+            assert_eq!(output.sample_rate, TARGET_RATE, "case {}", case);
+            assert_eq!(output.channels.len(), 1, "case {}", case);
+            assert_eq!(output.frames() as u64, expected, "case {}: {} Hz, {} frames", case, input.sample_rate, input.frames());
+        }
+    }
+    
+    #[test]
+    fn every_stage_keeps_samples_finite() {
+        for case in 0..CASES {
+            let mut cases = Cases(case);
+            let input = cases.buffer();
+            let pipeline = Pipeline::new()
+                .then(Stage::TrimLead(Duration::from_millis(cases.below(500) as u64)))
+                .then(Stage::Downmix)
+                .then(Stage::Resample(TARGET_RATE))
+                .then(Stage::HighPass(HIGH_PASS_HZ))
+                .then(Stage::Normalize);
+            let output = pipeline.run(input);
+            assert!(output.buffer.channels[0].iter().all(|s| s.is_finite()), "case {}", case);
+            assert!(output.gain_db.is_some_and(f32::is_finite), "case {}", case);
+        }
+    }
+    
+    #[test]
+    fn normalize_brings_peaks_to_at_most_minus_3_dbfs() {
+        let target = 10.0f32.powf(NORMALIZE_PEAK_DBFS / 20.0);
+        for case in 0..CASES {
+            let input = Cases(case).buffer();
+            let before = input.channels.iter().map(|channel| peak(channel)).fold(0.0, f32::max);
+            let output = Pipeline::new().then(Stage::Normalize).run(input);
+            let after = output.buffer.channels.iter().map(|channel| peak(channel)).fold(0.0, f32::max);
+            let gain_db = output.gain_db.unwrap();
+            
+            assert!(after <= target * 1.0001, "case {}: peak {} after normalizing", case, after);
+            assert!(gain_db <= NORMALIZE_MAX_GAIN_DB, "case {}: {} dB", case, gain_db);
+            if before == 0.0 {
+                assert_eq!(gain_db, 0.0, "case {}", case);
+            } else if gain_db < NORMALIZE_MAX_GAIN_DB {
+                // Not limited by the most gain allowed, so right at the target
+                assert!((after - target).abs() <= target * 1e-4, "case {}: peak {} from {}", case, after, before);
+            }
+        }
+    }
+    
+    #[test]
+    fn trimming_a_trimmed_buffer_changes_nothing() {
+        for case in 0..CASES {
+            let mut cases = Cases(case);
+            let mut buffer = cases.buffer();
+            let lead = Duration::from_millis(cases.below(2000) as u64);
+            let frames = buffer.frames();
+            
+            let output = Pipeline::new().then(Stage::TrimLead(lead)).run(buffer.clone());
+            assert!(output.trimmed <= lead, "case {}", case);
+            let trimmed = trim_lead(&mut buffer, lead);
+            assert_eq!(buffer, output.buffer, "case {}", case);
+            assert_eq!(buffer.frames(), frames - trimmed, "case {}", case);
+            
+            // Nothing more to drop once the lead is gone
+            let once = buffer.clone();
+            assert_eq!(trim_lead(&mut buffer, Duration::ZERO), 0, "case {}", case);
+            assert_eq!(buffer, once, "case {}", case);
+            
+            // Trimming everything, then again, leaves it empty either way
+            let all = Duration::from_secs(10);
+            trim_lead(&mut buffer, all);
+            let empty = buffer.clone();
+            assert_eq!(empty.frames(), 0, "case {}", case);
+            assert_eq!(trim_lead(&mut buffer, all), 0, "case {}", case);
+            assert_eq!(buffer, empty, "case {}", case);
+        }
+    }
+    
+    #[test]
+    fn downmixing_copies_of_one_channel_gives_that_channel() {
+        for case in 0..CASES {
+            let mono = Cases(case).buffer().into_samples();
+            assert_eq!(downmix(&[mono.clone(), mono.clone()]), mono, "case {}", case);
+            assert_eq!(resample(&mono, 44100, 44100), mono, "case {}", case);
+        }
+    }
+    
+    /// One second of `offset` plus a sine at `hz`, 16 kHz
+    fn tone(hz: f32, amplitude: f32, offset: f32) -> Vec<f32> {
+        (0..16000).map(|i| offset + amplitude * (2.0 * std::f32::consts::PI * hz * i as f32 / 16000.0).sin()).collect()
+    }
+    
+    /// RMS of the second half, once the filter has settled
+    fn settled_rms(samples: &[f32]) -> f32 {
+        let tail = &samples[samples.len() / 2..];
+        (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+    
+    #[test]
+    fn high_pass_removes_dc() {
+        let mut samples = vec![0.5; 16000];
+        high_pass(&mut samples, HIGH_PASS_HZ, 16000);
+        assert!(peak(&samples[8000..]) < 1e-3, "{}", peak(&samples[8000..]));
+        
+        // An offset under speech goes, the speech stays
+        let mut samples = tone(1000.0, 0.3, 0.2);
+        high_pass(&mut samples, HIGH_PASS_HZ, 16000);
+        let mean = samples[8000..].iter().sum::<f32>() / 8000.0;
+        assert!(mean.abs() < 1e-3, "mean {}", mean);
+        assert!((settled_rms(&samples) - settled_rms(&tone(1000.0, 0.3, 0.0))).abs() < 0.005);
+    }
+    
+    #[test]
+    fn high_pass_keeps_the_speech_band() {
+        for hz in [200.0, 300.0, 1000.0, 3000.0, 6000.0] {
+            let clean = tone(hz, 0.5, 0.0);
+            let mut filtered = clean.clone();
+            high_pass(&mut filtered, HIGH_PASS_HZ, 16000);
+            let gain = settled_rms(&filtered) / settled_rms(&clean);
+            assert!((0.97..=1.01).contains(&gain), "{} Hz passed at {}", hz, gain);
+        }
+    }
+    
+    #[test]
+    fn high_pass_cuts_rumble() {
+        // Two octaves under the cutoff a second-order filter is down 24 dB
+        let clean = tone(20.0, 0.5, 0.0);
+        let mut filtered = clean.clone();
+        high_pass(&mut filtered, HIGH_PASS_HZ, 16000);
+        let gain = settled_rms(&filtered) / settled_rms(&clean);
+        assert!(gain < 0.1, "20 Hz passed at {}", gain);
+    }
+}
@@ -0,0 +1,560 @@
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use crate::helpers;
+use crate::tarball::{self, Kind, Member};
+
+/// Manifest at the top of every bundle
+const MANIFEST: &str = "whisp-away-bundle.json";
+/// Bumped when the layout changes, so an older whisp-away refuses a newer bundle
+const FORMAT: u32 = 1;
+/// Files at least this big are taken to be the same when their sizes match,
+/// rather than read twice (models)
+const COMPARE_LIMIT_BYTES: u64 = 1 << 20;
+/// A manifest beyond this is not one of ours
+const MAX_MANIFEST_BYTES: u64 = 1 << 20;
+/// Directories followed below a bundled one, against symlink loops
+const MAX_DEPTH: usize = 32;
+
+/// XDG directory a part of the bundle belongs in. Members are stored under
+/// `config/` or `cache/` relative to it, so they land in the importing user's
+/// own directories whatever their username.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Root {
+    Config,
+    Cache,
+}
+
+impl Root {
+    const ALL: [Root; 2] = [Root::Config, Root::Cache];
+    
+    fn name(self) -> &'static str {
+        match self {
+            Root::Config => "config",
+            Root::Cache => "cache",
+        }
+    }
+}
+    
+/// The directories a bundle is made from or unpacked into
+struct Dirs {
+    home: String,
+    config: String,
+    cache: String,
+}
+
+impl Dirs {
+    fn current() -> Self {
+        Self {
+            home: std::env::var("HOME").unwrap_or_default(),
+            config: helpers::config_dir(),
+            cache: helpers::cache_dir(),
+        }
+    }
+    
+    fn root(&self, root: Root) -> &str {
+        match root {
+            Root::Config => &self.config,
+            Root::Cache => &self.cache,
+        }
+    }
+}
+
+/// Settings: the environment.d file `setup` writes, plus profiles, glossaries
+/// and hooks. whisp-away has no config file of its own. Together with MODELS
+/// this is all a bundle may hold: anything else is refused on import, so a
+/// bundle can't drop autostart entries or systemd units.
+const SETTINGS: &[(Root, &str)] = &[
+    (Root::Config, "environment.d/60-whisp-away.conf"),
+    (Root::Config, "whisp-away"),
+];
+
+/// Downloaded models, bundled with `--with-models`
+const MODELS: &[(Root, &str)] = &[
+    (Root::Cache, "whisper-cpp/models"),
+    (Root::Cache, "faster-whisper"),
+];
+
+/// Where the bundle was made, for re-rooting paths written into settings
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    version: String,
+    home: String,
+    config_dir: String,
+    cache_dir: String,
+    /// What was bundled, as `<root>/<path>`
+    contents: Vec<String>,
+}
+
+/// `<root>/<path>` for every part of the bundle, as listed in the manifest
+fn allowed_parts() -> impl Iterator<Item = String> {
+    SETTINGS.iter().chain(MODELS).map(|(root, relative)| format!("{}/{}", root.name(), relative))
+}
+
+/// Files under `path` (or `path` itself), relative to `path`, following
+/// symlinks: models in the Hugging Face cache are links into its blobs
+fn files_following(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![(PathBuf::new(), 0)];
+    while let Some((relative, depth)) = pending.pop() {
+        // Joining an empty path would add a trailing slash, which a file doesn't take
+        let current = if relative.as_os_str().is_empty() { path.to_path_buf() } else { path.join(&relative) };
+        let Ok(meta) = fs::metadata(&current) else {
+            continue;
+        };
+        if meta.is_file() {
+            files.push(relative);
+        } else if meta.is_dir() && depth < MAX_DEPTH {
+            for entry in fs::read_dir(&current).into_iter().flatten().flatten() {
+                pending.push((relative.join(entry.file_name()), depth + 1));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Bundle settings (and models with `with_models`) into a .tar.gz at `file`
+pub fn export(file: &str, with_models: bool) -> Result<()> {
+    export_from(file, with_models, &Dirs::current())?;
+    println!("Exported to {}", file);
+    Ok(())
+}
+
+fn export_from(file: &str, with_models: bool, dirs: &Dirs) -> Result<()> {
+    let parts = SETTINGS.iter().chain(if with_models { MODELS } else { &[] });
+    let mut members = Vec::new();
+    let mut contents = Vec::new();
+    for (root, relative) in parts {
+        let source = Path::new(dirs.root(*root)).join(relative);
+        if !source.exists() {
+            continue;
+        }
+        println!("{}", source.display());
+        let part = format!("{}/{}", root.name(), relative);
+        for file in files_following(&source) {
+            let name = if file.as_os_str().is_empty() { part.clone() } else { format!("{}/{}", part, file.display()) };
+            let path = if file.as_os_str().is_empty() { source.clone() } else { source.join(&file) };
+            members.push((name, path));
+        }
+        contents.push(part);
+    }
+    if contents.is_empty() {
+        return Err(anyhow!("Nothing to export: no settings, profiles, glossaries or hooks found"));
+    }
+    
+    let manifest = Manifest {
+        format: FORMAT,
+        version: crate::upgrade::VERSION.to_string(),
+        home: dirs.home.clone(),
+        config_dir: dirs.config.clone(),
+        cache_dir: dirs.cache.clone(),
+        contents,
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+    let out = fs::File::create(file).with_context(|| format!("Failed to create {}", file))?;
+    let mut builder = tarball::Builder::new(GzEncoder::new(out, flate2::Compression::default()));
+    builder.append_file(MANIFEST, 0o644, manifest.len() as u64, &mut &manifest[..])?;
+    for (name, path) in members {
+        let mut source = fs::File::open(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let meta = source.metadata()?;
+        builder.append_file(&name, meta.mode() & 0o7777, meta.len(), &mut source)
+            .with_context(|| format!("Failed to bundle {}", path.display()))?;
+    }
+    builder.finish()?.finish().with_context(|| format!("Failed to write {}", file))?;
+    Ok(())
+}
+
+/// A member's path without a leading `./`, refused unless it is plainly
+/// relative: no absolute paths, `..` or empty parts
+fn member_path(member: &Member) -> Result<&str> {
+    let path = member.path.trim_start_matches("./");
+    let path = if member.kind == Kind::Dir { path.trim_end_matches('/') } else { path };
+    if path.is_empty() && member.kind == Kind::Dir {
+        return Ok(path);
+    }
+    if path.starts_with('/') || path.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+        return Err(anyhow!("Bundle member {:?} isn't a relative path; refusing to import", member.path));
+    }
+    Ok(path)
+}
+
+/// Whether `path` belongs to one of the bundle's `contents`, or is a
+/// directory above one
+fn within(path: &str, kind: Kind, contents: &[String]) -> bool {
+    contents.iter().any(|part| {
+        path == part
+            || path.strip_prefix(part.as_str()).is_some_and(|rest| rest.starts_with('/'))
+            || (kind == Kind::Dir && (path.is_empty() || part.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))))
+    })
+}
+
+/// Read the archive once without writing anything: every member must be a
+/// plain file or directory under a relative path, within the parts the
+/// manifest lists, and those parts must be ones whisp-away bundles
+fn check_members(file: &str) -> Result<Manifest> {
+    let mut members = Vec::new();
+    let mut manifest = None;
+    let archive = fs::File::open(file).with_context(|| format!("Failed to open {}", file))?;
+    tarball::read(GzDecoder::new(archive), |member, data| {
+        let path = member_path(member)?.to_string();
+        if path == MANIFEST && member.kind == Kind::File {
+            if member.size > MAX_MANIFEST_BYTES {
+                return Err(anyhow!("Bundle manifest is too big"));
+            }
+            let mut content = String::new();
+            data.read_to_string(&mut content).context("Failed to read bundle manifest")?;
+            manifest = Some(content);
+        } else {
+            members.push((path, member.kind));
+        }
+        Ok(())
+    })
+    .with_context(|| format!("Failed to read {}", file))?;
+    
+    let manifest: Manifest = match manifest {
+        Some(content) => serde_json::from_str(&content).context("Invalid bundle manifest")?,
+        None => return Err(anyhow!("Not a whisp-away bundle: {} is missing", MANIFEST)),
+    };
+    if manifest.format > FORMAT {
+        return Err(anyhow!(
+            "Bundle was made by whisp-away {} in a newer format; upgrade to import it",
+            manifest.version
+        ));
+    }
+    let allowed: Vec<String> = allowed_parts().collect();
+    if let Some(part) = manifest.contents.iter().find(|part| !allowed.contains(part)) {
+        return Err(anyhow!("Bundle lists {:?}, which isn't part of whisp-away's settings or models; refusing to import", part));
+    }
+    if let Some((path, _)) = members.iter().find(|(path, kind)| !within(path, *kind, &manifest.contents)) {
+        return Err(anyhow!("Bundle member {:?} isn't part of whisp-away's settings or models; refusing to import", path));
+    }
+    Ok(manifest)
+}
+
+/// Unpack the (checked) archive's files into `stage`. Only plain files are
+/// created, never over an existing path, and without their executable bits:
+/// an imported script stays off until it is looked at and made executable.
+fn unpack(file: &str, stage: &Path) -> Result<()> {
+    let archive = fs::File::open(file).with_context(|| format!("Failed to open {}", file))?;
+    tarball::read(GzDecoder::new(archive), |member, data| {
+        let path = member_path(member)?;
+        if member.kind != Kind::File || path == MANIFEST {
+            return Ok(());
+        }
+        let target = stage.join(path);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut out = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(member.mode & 0o666)
+            .open(&target)
+            .with_context(|| format!("Failed to unpack {}", path))?;
+        std::io::copy(data, &mut out).with_context(|| format!("Failed to unpack {}", path))?;
+        Ok(())
+    })
+}
+
+/// Regular files under `dir`, relative to it. Symlinks aren't followed.
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current).into_iter().flatten().flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                if let Ok(relative) = entry.path().strip_prefix(dir) {
+                    files.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Point paths in a settings file at this machine's directories instead of
+/// the ones the bundle was made on
+fn reroot(content: &str, manifest: &Manifest, dirs: &Dirs) -> String {
+    // Most specific first, so a config dir inside the old home moves as a whole
+    let moves = [
+        (manifest.config_dir.as_str(), dirs.config.as_str()),
+        (manifest.cache_dir.as_str(), dirs.cache.as_str()),
+        (manifest.home.as_str(), dirs.home.as_str()),
+    ];
+    moves
+        .iter()
+        .filter(|(from, _)| !from.is_empty())
+        .fold(content.to_string(), |content, (from, to)| content.replace(from, to))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    let (Ok(meta_a), Ok(meta_b)) = (fs::metadata(a), fs::metadata(b)) else {
+        return false;
+    };
+    if meta_a.len() != meta_b.len() {
+        return false;
+    }
+    meta_a.len() >= COMPARE_LIMIT_BYTES || fs::read(a).ok() == fs::read(b).ok()
+}
+
+fn confirm_replace(path: &str) -> Result<bool> {
+    print!("{} differs from the bundle. Replace it? [y/N] ", path);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Move a file into place, copying when the staging directory is on another
+/// filesystem. Permissions are kept either way.
+fn place(from: &Path, to: &Path) -> Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).with_context(|| format!("Failed to write {}", to.display()))?;
+    }
+    Ok(())
+}
+
+/// Unpack a bundle into this user's XDG directories. Files that differ from
+/// what's there are only replaced with `overwrite` or when confirmed.
+pub fn import(file: &str, overwrite: bool) -> Result<()> {
+    import_into(file, overwrite, std::io::stdin().is_terminal(), &Dirs::current())
+}
+
+fn import_into(file: &str, overwrite: bool, interactive: bool, dirs: &Dirs) -> Result<()> {
+    let manifest = check_members(file)?;
+    
+    // Next to the models, so big files are moved rather than copied
+    let stage = format!("{}/whisp-away/import-{}", dirs.cache, std::process::id());
+    let _ = fs::remove_dir_all(&stage);
+    fs::create_dir_all(&stage).with_context(|| format!("Failed to create {}", stage))?;
+    let result = unpack(file, Path::new(&stage))
+        .and_then(|_| import_staged(&manifest, overwrite, interactive, dirs, Path::new(&stage)));
+    let _ = fs::remove_dir_all(&stage);
+    result
+}
+
+/// hooks.toml as a bundle member. It names commands to run on every dictation,
+/// so it is imported next to the real one, to be looked over and renamed.
+const HOOKS: &str = "whisp-away/hooks.toml";
+
+fn import_staged(manifest: &Manifest, overwrite: bool, interactive: bool, dirs: &Dirs, stage: &Path) -> Result<()> {
+    let (mut imported, mut unchanged, mut kept) = (0, 0, Vec::new());
+    let mut hooks = None;
+    for root in Root::ALL {
+        let staged_root = stage.join(root.name());
+        for relative in files_under(&staged_root) {
+            let staged = staged_root.join(&relative);
+            if relative.extension().is_some_and(|ext| ext == "conf") {
+                let content = fs::read_to_string(&staged)
+                    .with_context(|| format!("Failed to read {}", relative.display()))?;
+                fs::write(&staged, reroot(&content, manifest, dirs))?;
+            }
+            
+            let mut target = Path::new(dirs.root(root)).join(&relative);
+            if root == Root::Config && relative == Path::new(HOOKS) {
+                target.set_extension("toml.imported");
+                hooks = Some(target.display().to_string());
+            }
+            let target_display = target.display().to_string();
+            if target.exists() {
+                if same_file(&staged, &target) {
+                    unchanged += 1;
+                    continue;
+                }
+                let replace = overwrite || (interactive && confirm_replace(&target_display)?);
+                if !replace {
+                    kept.push(target_display);
+                    continue;
+                }
+            }
+            place(&staged, &target)?;
+            println!("{}", target_display);
+            imported += 1;
+        }
+    }
+    
+    println!(
+        "Imported {} file(s) from whisp-away {} ({} already up to date)",
+        imported, manifest.version, unchanged
+    );
+    if !kept.is_empty() {
+        let hint = if interactive { "" } else { "; pass --overwrite to replace them" };
+        eprintln!("Kept {} existing file(s) that differ from the bundle{}:", kept.len(), hint);
+        for path in &kept {
+            eprintln!("  {}", path);
+        }
+    }
+    if let Some(path) = hooks {
+        eprintln!(
+            "The bundle's hooks are in {}: look over the commands it runs, then rename it to hooks.toml to use them",
+            path
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    
+    fn temp_dirs(name: &str) -> Dirs {
+        let base = std::env::temp_dir().join(format!("whisp-away-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&base);
+        let dir = |sub: &str| {
+            let path = base.join(sub);
+            fs::create_dir_all(&path).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        Dirs { home: dir("home"), config: dir("home/.config"), cache: dir("home/.cache") }
+    }
+    
+    /// A bundle holding `manifest` and `files`, written by hand so tests can
+    /// put in what export never would
+    fn write_bundle(path: &Path, manifest: &Manifest, files: &[(&str, &[u8])]) {
+        let manifest = serde_json::to_vec(manifest).unwrap();
+        let out = fs::File::create(path).unwrap();
+        let mut builder = tarball::Builder::new(GzEncoder::new(out, flate2::Compression::default()));
+        builder.append_file(MANIFEST, 0o644, manifest.len() as u64, &mut &manifest[..]).unwrap();
+        for (name, data) in files {
+            builder.append_file(name, 0o644, data.len() as u64, &mut &data[..]).unwrap();
+        }
+        builder.finish().unwrap().finish().unwrap();
+    }
+    
+    fn manifest(contents: &[&str]) -> Manifest {
+        Manifest {
+            format: FORMAT,
+            version: "test".to_string(),
+            home: "/home/old".to_string(),
+            config_dir: "/home/old/.config".to_string(),
+            cache_dir: "/home/old/.cache".to_string(),
+            contents: contents.iter().map(|part| part.to_string()).collect(),
+        }
+    }
+    
+    #[test]
+    fn export_then_import_round_trips() {
+        let from = temp_dirs("export");
+        let env = Path::new(&from.config).join("environment.d/60-whisp-away.conf");
+        fs::create_dir_all(env.parent().unwrap()).unwrap();
+        fs::write(&env, format!("WA_PROMPT_FILE={}/whisp-away/prompt.txt\n", from.config)).unwrap();
+        let profile = Path::new(&from.config).join("whisp-away/profiles/work.env");
+        fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        fs::write(&profile, "WA_LANGUAGE=de\n").unwrap();
+        let model = Path::new(&from.cache).join("whisper-cpp/models/ggml-tiny.bin");
+        fs::create_dir_all(model.parent().unwrap()).unwrap();
+        fs::write(&model, b"model").unwrap();
+        
+        let bundle = Path::new(&from.home).join("bundle.tar.gz");
+        export_from(bundle.to_str().unwrap(), false, &from).unwrap();
+        
+        let to = temp_dirs("import");
+        import_into(bundle.to_str().unwrap(), false, false, &to).unwrap();
+        let imported = fs::read_to_string(Path::new(&to.config).join("environment.d/60-whisp-away.conf")).unwrap();
+        assert_eq!(imported, format!("WA_PROMPT_FILE={}/whisp-away/prompt.txt\n", to.config));
+        let profile = fs::read_to_string(Path::new(&to.config).join("whisp-away/profiles/work.env")).unwrap();
+        assert_eq!(profile, "WA_LANGUAGE=de\n");
+        // Models only come along with --with-models
+        assert!(!Path::new(&to.cache).join("whisper-cpp/models/ggml-tiny.bin").exists());
+        
+        let _ = fs::remove_dir_all(Path::new(&from.home).parent().unwrap());
+        let _ = fs::remove_dir_all(Path::new(&to.home).parent().unwrap());
+    }
+    
+    #[test]
+    fn refuses_files_outside_the_allowlist() {
+        let dirs = temp_dirs("outside");
+        let bundle = Path::new(&dirs.home).join("bundle.tar.gz");
+        let cases: &[(&[&str], &str)] = &[
+            // Listed in the manifest, but not something whisp-away bundles
+            (&["config/whisp-away", "config/autostart"], "config/autostart/evil.desktop"),
+            // Allowlisted, but not what this bundle says it holds
+            (&["config/whisp-away"], "cache/faster-whisper/model.bin"),
+            // Neither
+            (&["config/whisp-away"], "config/systemd/user/evil.service"),
+            (&["config/whisp-away"], "config/whisp-away/../autostart/evil.desktop"),
+        ];
+        for (contents, extra) in cases {
+            write_bundle(&bundle, &manifest(contents), &[("config/whisp-away/hooks.txt", b"ok"), (extra, b"evil")]);
+            let error = import_into(bundle.to_str().unwrap(), true, false, &dirs).unwrap_err();
+            assert!(format!("{:#}", error).contains("refusing to import"), "{}: {:#}", extra, error);
+            // Nothing at all is written when any member is refused
+            assert!(!Path::new(&dirs.config).join("whisp-away/hooks.txt").exists(), "{}", extra);
+        }
+        let _ = fs::remove_dir_all(Path::new(&dirs.home).parent().unwrap());
+    }
+    
+    #[test]
+    fn refuses_links_in_the_archive() {
+        let dirs = temp_dirs("links");
+        let bundle = Path::new(&dirs.home).join("bundle.tar.gz");
+        write_bundle(&bundle, &manifest(&["config/whisp-away"]), &[("config/whisp-away/a", b"a")]);
+        // Turn the last member into a symlink, fixing up the header checksum
+        let mut tar = Vec::new();
+        GzDecoder::new(fs::File::open(&bundle).unwrap()).read_to_end(&mut tar).unwrap();
+        let header = tar.len() - 3 * 512 - 512;
+        assert_eq!(&tar[header..header + 20], b"config/whisp-away/a\0");
+        tar[header + 156] = b'2';
+        tar[header + 157..header + 168].copy_from_slice(b"/etc/passwd");
+        tar[header + 148..header + 156].copy_from_slice(b"        ");
+        let sum: u32 = tar[header..header + 512].iter().map(|b| *b as u32).sum();
+        tar[header + 148..header + 155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        let mut out = GzEncoder::new(fs::File::create(&bundle).unwrap(), flate2::Compression::default());
+        out.write_all(&tar).unwrap();
+        out.finish().unwrap();
+        
+        let error = import_into(bundle.to_str().unwrap(), true, false, &dirs).unwrap_err();
+        assert!(format!("{:#}", error).contains("symlink"), "{:#}", error);
+        assert!(!Path::new(&dirs.config).join("whisp-away/a").exists());
+        let _ = fs::remove_dir_all(Path::new(&dirs.home).parent().unwrap());
+    }
+    
+    #[test]
+    fn imported_files_lose_their_executable_bits() {
+        let dirs = temp_dirs("modes");
+        let bundle = Path::new(&dirs.home).join("bundle.tar.gz");
+        let manifest = serde_json::to_vec(&manifest(&["config/whisp-away"])).unwrap();
+        let mut builder = tarball::Builder::new(GzEncoder::new(fs::File::create(&bundle).unwrap(), flate2::Compression::default()));
+        builder.append_file(MANIFEST, 0o644, manifest.len() as u64, &mut &manifest[..]).unwrap();
+        builder.append_file("config/whisp-away/hooks/post.sh", 0o4755, 2, &mut &b"id"[..]).unwrap();
+        builder.finish().unwrap().finish().unwrap();
+        
+        import_into(bundle.to_str().unwrap(), false, false, &dirs).unwrap();
+        let mode = fs::metadata(Path::new(&dirs.config).join("whisp-away/hooks/post.sh")).unwrap().mode();
+        assert_eq!(mode & 0o7111, 0);
+        let _ = fs::remove_dir_all(Path::new(&dirs.home).parent().unwrap());
+    }
+    
+    #[test]
+    fn imported_hooks_wait_for_review() {
+        let dirs = temp_dirs("hooks");
+        let hooks = Path::new(&dirs.config).join(HOOKS);
+        fs::create_dir_all(hooks.parent().unwrap()).unwrap();
+        fs::write(&hooks, "# mine\n").unwrap();
+        let bundle = Path::new(&dirs.home).join("bundle.tar.gz");
+        let theirs: &[u8] = b"[[hooks]]\nevent = \"transcribed\"\ncommand = [\"curl\"]\n";
+        write_bundle(&bundle, &manifest(&["config/whisp-away"]), &[("config/whisp-away/hooks.toml", theirs)]);
+        
+        import_into(bundle.to_str().unwrap(), true, false, &dirs).unwrap();
+        assert_eq!(fs::read_to_string(&hooks).unwrap(), "# mine\n");
+        assert_eq!(fs::read(Path::new(&dirs.config).join("whisp-away/hooks.toml.imported")).unwrap(), theirs);
+        let _ = fs::remove_dir_all(Path::new(&dirs.home).parent().unwrap());
+    }
+}
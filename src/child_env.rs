@@ -0,0 +1,128 @@
+use std::ffi::OsStr;
+use std::process::Command;
+
+/// Variables every child process gets when they are set: enough to find
+/// binaries, talk to the session (Wayland, D-Bus, PipeWire) and pick a locale
+const BASE_ENV: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL",
+    "LANG", "LANGUAGE", "LC_ALL", "LC_CTYPE", "LC_MESSAGES", "TZ",
+    "XDG_RUNTIME_DIR", "XDG_CACHE_HOME", "XDG_CONFIG_HOME", "XDG_DATA_HOME", "XDG_DATA_DIRS",
+    "XDG_SESSION_TYPE", "XDG_CURRENT_DESKTOP",
+    "WAYLAND_DISPLAY", "DISPLAY", "DBUS_SESSION_BUS_ADDRESS",
+    "HYPRLAND_INSTANCE_SIGNATURE", "SWAYSOCK",
+    "PIPEWIRE_RUNTIME_DIR", "PIPEWIRE_REMOTE", "PULSE_SERVER",
+    "RUST_LOG", "RUST_BACKTRACE",
+];
+
+/// Extra variables for children that load models: GPU selection, native
+/// libraries and the faster-whisper Python environment
+pub const MODEL_ENV: &[&str] = &[
+    "CUDA_VISIBLE_DEVICES", "CUDA_HOME", "CUDA_PATH",
+    "LD_LIBRARY_PATH", "LIBRARY_PATH",
+    "FASTER_WHISPER_PYTHON", "FASTER_WHISPER_PYTHONPATH",
+    "FASTER_WHISPER_SCRIPT", "FASTER_WHISPER_DAEMON_SCRIPT",
+    "WHISPER_CPP_PATH", "WHISPER_CPP_MODEL_PATH", "WHISPER_DEVICE", "WHISPER_COMPUTE",
+    "HF_HOME", "HF_HUB_OFFLINE",
+];
+
+/// Extra variables for children that download models
+pub const NETWORK_ENV: &[&str] = &[
+    "http_proxy", "https_proxy", "no_proxy", "HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY",
+    "SSL_CERT_FILE", "SSL_CERT_DIR",
+];
+
+/// Whether children inherit the full environment (`--inherit-env` or WA_INHERIT_ENV=1)
+pub fn inherit_env() -> bool {
+    matches!(std::env::var("WA_INHERIT_ENV").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Names listed in WA_ENV_PASSTHROUGH (comma or whitespace separated)
+fn configured_extras() -> Vec<String> {
+    std::env::var("WA_ENV_PASSTHROUGH")
+        .unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Pick the variables a child may see from `vars`: the base allowlist, whisp-away's
+/// own WA_* settings, `extras` and anything named in `configured`
+pub fn filter_env(
+    vars: impl IntoIterator<Item = (String, String)>,
+    extras: &[&str],
+    configured: &[String],
+) -> Vec<(String, String)> {
+    vars.into_iter()
+        .filter(|(key, _)| {
+            key.starts_with("WA_")
+                || BASE_ENV.contains(&key.as_str())
+                || extras.contains(&key.as_str())
+                || configured.iter().any(|name| name == key)
+        })
+        .collect()
+}
+
+/// Build a Command whose environment is the allowlist plus `extras`, rather than
+/// whatever the hotkey daemon or session that started us happened to have
+pub fn command(program: impl AsRef<OsStr>, extras: &[&str]) -> Command {
+    let mut cmd = Command::new(program.as_ref());
+    if inherit_env() {
+        return cmd;
+    }
+    
+    let vars = filter_env(std::env::vars(), extras, &configured_extras());
+    eprintln!(
+        "DEBUG: Environment for {}: {}",
+        program.as_ref().to_string_lossy(),
+        vars.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(" ")
+    );
+    cmd.env_clear().envs(vars);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// What `env` prints when run through `command` with `extras`
+    fn child_env(extras: &[&str]) -> String {
+        let output = command("env", extras).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+    
+    #[test]
+    fn unexpected_variables_dont_reach_the_child() {
+        let vars = [
+            ("WHISP_AWAY_TEST_SECRET", Some("hunter2")),
+            ("LD_LIBRARY_PATH", Some("/opt/broken/lib")),
+            ("WA_TEST_SETTING", Some("kept")),
+            ("WA_INHERIT_ENV", None),
+            ("WA_ENV_PASSTHROUGH", None),
+        ];
+        let (plain, for_models) = crate::helpers::with_env(&vars, || (child_env(&[]), child_env(MODEL_ENV)));
+        assert!(!plain.contains("WHISP_AWAY_TEST_SECRET"), "{}", plain);
+        assert!(!plain.contains("LD_LIBRARY_PATH"), "{}", plain);
+        assert!(plain.contains("WA_TEST_SETTING=kept\n"), "{}", plain);
+        assert!(plain.lines().any(|line| line.starts_with("PATH=")), "{}", plain);
+        // Model loaders get their libraries, still not the secret
+        assert!(for_models.contains("LD_LIBRARY_PATH=/opt/broken/lib\n"), "{}", for_models);
+        assert!(!for_models.contains("WHISP_AWAY_TEST_SECRET"), "{}", for_models);
+    }
+    
+    #[test]
+    fn passthrough_and_inherit_env_let_variables_through() {
+        let vars = [
+            ("WHISP_AWAY_TEST_SECRET", Some("hunter2")),
+            ("WA_ENV_PASSTHROUGH", Some("FOO, WHISP_AWAY_TEST_SECRET")),
+            ("WA_INHERIT_ENV", None),
+        ];
+        let passed = crate::helpers::with_env(&vars, || child_env(&[]));
+        assert!(passed.contains("WHISP_AWAY_TEST_SECRET=hunter2\n"), "{}", passed);
+        
+        let vars = [("WHISP_AWAY_TEST_SECRET", Some("hunter2")), ("WA_INHERIT_ENV", Some("1")), ("WA_ENV_PASSTHROUGH", None)];
+        let inherited = crate::helpers::with_env(&vars, || child_env(&[]));
+        assert!(inherited.contains("WHISP_AWAY_TEST_SECRET=hunter2\n"), "{}", inherited);
+    }
+}
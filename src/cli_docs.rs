@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use clap::{Arg, ArgAction, Command};
+use clap_complete::Shell;
+use serde_json::{json, Value};
+use std::io::Write;
+
+/// Write a completion script for `shell` to stdout
+pub fn completions(mut cmd: Command, shell: Shell) -> Result<()> {
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+    Ok(())
+}
+
+/// Write the top-level manpage to stdout, or with `out_dir` one page per
+/// (visible) subcommand as well, named the way `man whisp-away-stop` expects
+pub fn man(cmd: Command, out_dir: Option<&str>) -> Result<()> {
+    match out_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir))?;
+            clap_mangen::generate_to(cmd, dir)
+                .with_context(|| format!("Failed to write manpages to {}", dir))?;
+            eprintln!("Wrote manpages to {}", dir);
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            clap_mangen::Man::new(cmd).render(&mut stdout)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether an argument takes a value on the command line (as opposed to a switch)
+fn takes_value(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Set | ArgAction::Append)
+}
+
+fn arg_json(arg: &Arg) -> Value {
+    let possible_values: Vec<Value> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| json!({
+            "name": value.get_name(),
+            "aliases": value.get_name_and_aliases().skip(1).collect::<Vec<_>>(),
+            "help": value.get_help().map(|help| help.to_string()),
+        }))
+        .collect();
+    
+    json!({
+        "id": arg.get_id().as_str(),
+        "long": arg.get_long(),
+        "short": arg.get_short().map(String::from),
+        "help": arg.get_help().map(|help| help.to_string()),
+        "value_names": arg.get_value_names()
+            .filter(|_| takes_value(arg))
+            .map(|names| names.iter().map(|name| name.as_str()).collect::<Vec<_>>()),
+        "takes_value": takes_value(arg),
+        "positional": arg.is_positional(),
+        "required": arg.is_required_set(),
+        "global": arg.is_global_set(),
+        "hidden": arg.is_hide_set(),
+        "default_values": arg.get_default_values().iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect::<Vec<_>>(),
+        "possible_values": possible_values,
+    })
+}
+
+fn command_json(cmd: &Command) -> Value {
+    json!({
+        "name": cmd.get_name(),
+        "about": cmd.get_about().map(|about| about.to_string()),
+        "hidden": cmd.is_hide_set(),
+        "args": cmd.get_arguments()
+            .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+            .map(arg_json)
+            .collect::<Vec<_>>(),
+        "subcommands": cmd.get_subcommands()
+            .filter(|sub| sub.get_name() != "help")
+            .map(command_json)
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// The whole command tree (subcommands, flags, defaults, possible values) as JSON,
+/// for packaging scripts and other tools that shouldn't parse --help output
+pub fn cli_json(mut cmd: Command) -> Value {
+    // Building propagates global args into subcommands and fills in derived settings
+    cmd.build();
+    command_json(&cmd)
+}
+
+pub fn dump_json(cmd: Command) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&cli_json(cmd))?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    
+    /// Checked-in copy of `--dump-cli-json`. After changing the CLI on
+    /// purpose, regenerate it with
+    /// `cargo run -- --dump-cli-json > tests/cli.json`
+    const SNAPSHOT: &str = include_str!("../tests/cli.json");
+    
+    /// The mock backend only exists in builds with the mock feature
+    fn without_mock(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::Array(values)) = map.get_mut("possible_values") {
+                    values.retain(|value| value["name"] != "mock");
+                }
+                map.values_mut().for_each(without_mock);
+            }
+            Value::Array(values) => values.iter_mut().for_each(without_mock),
+            _ => {}
+        }
+    }
+    
+    #[test]
+    fn cli_matches_its_snapshot() {
+        let mut current = cli_json(crate::Cli::command());
+        let mut snapshot: Value = serde_json::from_str(SNAPSHOT).unwrap();
+        without_mock(&mut current);
+        without_mock(&mut snapshot);
+        assert!(
+            current == snapshot,
+            "The CLI changed; if that's intended, regenerate tests/cli.json:\n{}",
+            serde_json::to_string_pretty(&current).unwrap()
+        );
+    }
+    
+    #[test]
+    fn cli_definition_is_consistent() {
+        crate::Cli::command().debug_assert();
+    }
+    
+    #[test]
+    fn completions_and_manpage_cover_every_subcommand() {
+        let mut cmd = crate::Cli::command();
+        let mut bash = Vec::new();
+        clap_complete::generate(Shell::Bash, &mut cmd, "whisp-away", &mut bash);
+        let bash = String::from_utf8(bash).unwrap();
+        let mut roff = Vec::new();
+        clap_mangen::Man::new(crate::Cli::command()).render(&mut roff).unwrap();
+        let roff = String::from_utf8(roff).unwrap();
+        for sub in crate::Cli::command().get_subcommands().filter(|sub| !sub.is_hide_set()) {
+            assert!(bash.contains(sub.get_name()), "completions lack {}", sub.get_name());
+            assert!(roff.contains(&sub.get_name().replace('-', "\\-")), "manpage lacks {}", sub.get_name());
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::Result;
+use std::fs;
+use std::time::{Duration, Instant};
+use crate::chain::{StepKind, Transcriber};
+use crate::events::{self, Event};
+use crate::notify;
+use crate::recording;
+use crate::run::Run;
+use crate::socket;
+use crate::helpers::OutputMode;
+use crate::typing::{self, TypingError};
+
+/// What differs between backends when stopping a recording and transcribing
+/// it through the daemon: how they word their notifications, and how they
+/// transcribe directly when the daemon can't be used
+pub trait TranscriptionBackend {
+    /// Backend name, as in requests and notifications ("whisper-cpp")
+    fn name(&self) -> &'static str;
+    
+    /// Summary of notifications about the daemon, e.g. "Voice Input (mock daemon)"
+    fn daemon_summary(&self) -> &'static str;
+    
+    /// Summary and body of the notification when the recording file is missing
+    fn no_audio_notice(&self) -> (String, String) {
+        ("Voice Input".to_string(), format!("❌ No audio recorded\nBackend: {}", self.name()))
+    }
+    
+    /// Backend line of the "Transcribing..." notification
+    fn transcribing_backend(&self, model: &str) -> String {
+        format!("{} ({}) | Model: {}", self.name(), crate::helpers::get_acceleration_type(), crate::helpers::model_display_name(model))
+    }
+    
+    /// Summary and body of the notification announcing direct transcription
+    fn fallback_notice(&self, reason: &str, model: &str) -> (String, String);
+    
+    /// Transcribe and type `audio_file` without the daemon, in `run`'s language
+    fn transcribe_direct(&self, audio_file: &str, model: &str, prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()>;
+}
+
+/// Turnaround `quick` aims for, from the hotkey to the text
+const FAST_TARGET: Duration = Duration::from_millis(300);
+
+/// ", fast tiny.en in 212 ms (daemon 180 ms)" for the result notification of
+/// a fast stop, measured from `since`, when it began, or "" otherwise. Logs a
+/// warning when the turnaround misses FAST_TARGET.
+pub fn fast_report(since: Option<Instant>, model: Option<&str>, daemon_ms: Option<u64>) -> String {
+    let Some(since) = since else {
+        return String::new();
+    };
+    let total = since.elapsed();
+    let daemon = daemon_ms.map(|ms| format!(" (daemon {} ms)", ms)).unwrap_or_default();
+    eprintln!("DEBUG: Fast stop took {} ms end to end{}", total.as_millis(), daemon);
+    if total > FAST_TARGET {
+        eprintln!("WARNING: Fast stop took {} ms, over the {} ms target", total.as_millis(), FAST_TARGET.as_millis());
+    }
+    match model {
+        Some(model) => format!(", fast {} in {} ms{}", crate::helpers::model_display_name(model), total.as_millis(), daemon),
+        None => format!(", no fast model, {} ms{}", total.as_millis(), daemon),
+    }
+}
+
+/// Everything `stop` hands the client besides the backend
+pub struct StopOptions<'a> {
+    pub wtype_path: &'a str,
+    /// wtype and wl-copy as found when `stop` began
+    pub tools: typing::Tools,
+    pub daemon: &'a socket::DaemonOptions,
+    /// Transcribe this file instead of the recording (`stop --audio-file`)
+    pub audio_file_override: Option<&'a str>,
+    pub model: Option<String>,
+    /// `--prompt-file`, over the focused application's glossary
+    pub prompt_file: Option<&'a str>,
+    /// `--profile`, over the focused window's and the tray's
+    pub profile: Option<&'a str>,
+    /// Fallback chain steps left out with `--skip`
+    pub skip: &'a [StepKind],
+    /// `--output`, over the profile's and the tray's
+    pub output: Option<OutputMode>,
+    /// `--notify-preview`, over WA_NOTIFY_PREVIEW
+    pub notify_preview: Option<typing::NotifyPreview>,
+    /// `--trim-lead` and `--min-duration` in ms, over the tray's preset
+    pub trim_lead_ms: Option<u64>,
+    pub min_duration_ms: Option<u64>,
+    /// `--audio-format` kept recordings are archived in, over WA_AUDIO_FORMAT
+    pub audio_format: Option<crate::history::AudioFormat>,
+    /// When `stop --fast` (or the stopping `quick`) began. Set, it sends the
+    /// recording to the daemon's fast model and skips VAD, preprocessing and
+    /// the "Transcribing..." notification, which cost more than they're worth
+    /// on a couple of words.
+    pub fast_since: Option<Instant>,
+}
+
+/// How this run's transcript will get out, decided from `options.tools`:
+/// typed with the wtype found, or, when there is none, copied to the
+/// clipboard whatever else is configured. Fails when neither would work,
+/// keeping the recording.
+fn delivery(backend: &dyn TranscriptionBackend, options: &StopOptions, audio_file: &str) -> Result<typing::Delivery> {
+    let delivery = |wtype_path: String| typing::Delivery {
+        wtype_path,
+        output: options.output,
+        preview: options.notify_preview,
+        profile: options.profile.map(str::to_string),
+    };
+    let tools = &options.tools;
+    match (typing::output_mode(options.output, options.profile), &tools.wtype, &tools.wl_copy) {
+        (OutputMode::Type, Some(wtype), _) => Ok(delivery(wtype.display().to_string())),
+        (OutputMode::Clipboard, _, Some(_)) => Ok(delivery(options.wtype_path.to_string())),
+        (OutputMode::Type, None, Some(_)) => {
+            eprintln!("WARNING: {}, copying the transcript to the clipboard instead", tools.describe_wtype());
+            notify::Notification::normal(
+                backend.daemon_summary(),
+                &format!("📋 {}, the transcript will be copied to the clipboard\nBackend: {}", tools.describe_wtype(), backend.name()),
+            )
+            .send();
+            Ok(typing::Delivery {
+                output: Some(OutputMode::Clipboard),
+                ..delivery(options.wtype_path.to_string())
+            })
+        }
+        (mode, _, None) => {
+            let missing = match mode {
+                OutputMode::Type => format!("{} and wl-copy", tools.wtype_path),
+                OutputMode::Clipboard => "wl-copy".to_string(),
+            };
+            let kept = recording::keep_failed(audio_file);
+            notify::Notification::error(
+                backend.daemon_summary(),
+                &format!("❌ {} not found, the transcript would be lost\nRecording kept: {}", missing, kept),
+            )
+            .timeout(5000)
+            .send();
+            Err(anyhow::anyhow!("{} not found; not transcribing {}", missing, kept))
+        }
+    }
+}
+
+/// Stop the recording, check it's worth transcribing, and type what the
+/// first usable step of the fallback chain makes of it: by default the
+/// daemon, else direct transcription with `backend`. What it came to is
+/// noted in `run`.
+pub fn stop_and_transcribe(backend: &dyn TranscriptionBackend, options: &StopOptions, run: &mut Run) -> Result<()> {
+    let fast = options.fast_since.is_some();
+    let stopped = recording::stop_recording(options.audio_file_override, options.trim_lead_ms, options.audio_format, options.profile, fast, run)?;
+    let audio_file = match stopped {
+        Some(path) => path,
+        None => {
+            run.fail("no_recording");
+            notify::Notification::error(backend.daemon_summary(), "❌ No recording found")
+                .send();
+            return Ok(());
+        }
+    };
+    
+    events::emit(Event::RecordingStopped);
+    if !std::path::Path::new(&audio_file).exists() {
+        run.fail("no_audio");
+        let (summary, body) = backend.no_audio_notice();
+        notify::Notification::error(&summary, &body)
+            .send();
+        return Ok(());
+    }
+    
+    // Settled before any transcription, so a missing wtype doesn't cost the text
+    let delivery = delivery(backend, options, &audio_file)?;
+    
+    if recording::discard_if_too_short(&audio_file, backend.name(), options.min_duration_ms) || (!fast && recording::discard_if_silent(&audio_file)) {
+        return Ok(());
+    }
+    
+    let start_time = std::time::Instant::now();
+    eprintln!("DEBUG: Starting transcription at {:?}", start_time);
+    
+    // Without the daemon, a fast stop still transcribes with the small model
+    let model = match crate::helpers::resolve_fast_model(None, options.profile).filter(|_| fast) {
+        Some(fast_model) => fast_model,
+        None => crate::helpers::resolve_model(options.model.clone(), options.profile),
+    };
+    run.duration = recording::measure(&audio_file);
+    run.started(&model);
+    events::emit(Event::TranscriptionStarted);
+    if !fast {
+        let transcribe_msg = format!(
+            "⏳ Transcribing...\nBackend: {}{}",
+            backend.transcribing_backend(&model),
+            run.resolution_note
+        );
+        notify::Notification::transient("Voice Input", &transcribe_msg)
+            .send();
+    }
+    
+    let steps = crate::chain::steps(options.profile, options.skip)?;
+    // Why the steps tried so far failed, for the next one's notice
+    let mut failures: Vec<String> = Vec::new();
+    for (index, step) in steps.iter().enumerate() {
+        run.step_note = crate::chain::step_note(index, steps.len(), step, options.profile);
+        let daemon = match &step.transcriber {
+            Transcriber::Daemon => socket::DaemonOptions {
+                timeout: step.timeout,
+                hand_over: index + 1 == steps.len(),
+                ..options.daemon.clone()
+            },
+            Transcriber::Remote(address) => socket::DaemonOptions {
+                socket_path: address.clone(),
+                // Whatever the other machine runs is what the user asked for
+                use_running_backend: true,
+                require_ready: false,
+                timeout: step.timeout,
+                hand_over: index + 1 == steps.len(),
+                ..options.daemon.clone()
+            },
+            Transcriber::Direct(step_model) => {
+                let model = step_model.clone().unwrap_or_else(|| model.clone());
+                if let Some(reason) = failures.last() {
+                    let (summary, body) = backend.fallback_notice(reason, &model);
+                    notify::Notification::transient(&summary, &body)
+                        .send();
+                }
+                eprintln!("DEBUG: Chain step {}/{}: {}", index + 1, steps.len(), step);
+    
+                run.model = Some(model.clone());
+                // It may queue behind another direct transcription, during
+                // which a start would clean the recording up
+                let audio_file = recording::claim(&audio_file);
+                let result = backend.transcribe_direct(&audio_file, &model, options.prompt_file, &delivery, run);
+                
+                let _ = fs::remove_file(&audio_file);
+                if result.is_err() {
+                    run.fail("transcription_failed");
+                }
+                
+                return result.map_err(|err| {
+                    if failures.is_empty() {
+                        anyhow::anyhow!("Direct transcription failed: {}", err)
+                    } else {
+                        anyhow::anyhow!("Fallback transcription failed (earlier steps: {}): {}", failures.join("; "), err)
+                    }
+                });
+            }
+        };
+        
+        eprintln!("DEBUG: Chain step {}/{}: {}", index + 1, steps.len(), step);
+        eprintln!("DEBUG: Connecting to daemon socket at: {}", daemon.socket_path);
+        match socket::send_transcription_request(&daemon, &audio_file, &delivery, run, backend.name(), options.prompt_file, options.fast_since) {
+            Ok(_) => {
+                eprintln!("DEBUG: Total time: {:?}", start_time.elapsed());
+                // A recording in the daemon's inbox is the daemon's to delete
+                if !socket::managed(&daemon, &audio_file) {
+                    let _ = fs::remove_file(&audio_file);
+                }
+                return Ok(());
+            }
+            Err(e) if e.downcast_ref::<TypingError>().is_some() => {
+                // Transcription worked and only typing failed, so don't transcribe again
+                if let Some(text) = e.downcast_ref::<TypingError>().and_then(TypingError::text) {
+                    println!("{}", text);
+                }
+                let _ = fs::remove_file(&audio_file);
+                return Err(e);
+            }
+            Err(e) => {
+                eprintln!("DEBUG: Chain step {} failed: {:#}", step, e);
+                let reason = socket::fallback_reason(&e);
+                // The direct step says so in its own notice
+                if let Some(next) = steps.get(index + 1).filter(|next| next.kind() != StepKind::Direct) {
+                    notify::Notification::transient("Voice Input", &format!("⚠️ {}, trying {}\nBackend: {}", reason, next, backend.name()))
+                        .send();
+                }
+                failures.push(format!("{}: {}", step, reason));
+            }
+        }
+    }
+    
+    // Every step was a daemon, and none could be used
+    run.fail("chain_exhausted");
+    let kept = recording::keep_failed(&audio_file);
+    notify::Notification::error(
+        backend.daemon_summary(),
+        &format!("❌ No transcriber could be used\n{}\nRecording kept: {}", failures.join("\n"), kept),
+    )
+    .timeout(10000)
+    .send();
+    Err(anyhow::anyhow!("Every step of the fallback chain failed: {}", failures.join("; ")))
+}
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+use crate::protocol::{Priority, TranscriptionRequest, TranscriptionResponse};
+use crate::replay::{align, error_count, render_diff, tokens};
+
+/// What `compare` transcribes and with which models
+pub struct CompareOptions {
+    pub audio: String,
+    pub models: Vec<String>,
+    pub backend: String,
+    pub language: String,
+    /// Print JSON instead of a table
+    pub json: bool,
+    /// Download models that aren't on disk instead of skipping them
+    pub download: bool,
+}
+
+/// One model's transcript and how it measures up against the reference
+#[derive(Debug, Serialize)]
+struct Outcome {
+    model: String,
+    /// "daemon" when a running daemon had the model loaded, "direct" when it
+    /// was loaded for this comparison (which the time then includes)
+    via: &'static str,
+    time_ms: u64,
+    /// Mean segment confidence, when the daemon reports segments
+    confidence: Option<f32>,
+    text: String,
+    /// Word errors against the reference model; None for the reference itself
+    errors: Option<usize>,
+    words: Option<usize>,
+    diff: Option<String>,
+}
+
+/// A model left out of the comparison and why
+#[derive(Debug, Serialize)]
+struct Skipped {
+    model: String,
+    reason: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    audio: String,
+    backend: String,
+    /// Model the others are diffed against: the largest that worked
+    reference: Option<String>,
+    results: Vec<Outcome>,
+    skipped: Vec<Skipped>,
+}
+
+/// Why `model` isn't on disk for `backend`, if it isn't
+fn missing(backend: &str, model: &str) -> Option<String> {
+    match backend {
+        "whisper-cpp" => {
+            let path = crate::helpers::whisper_cpp_model_path(model);
+            let missing = if crate::helpers::is_model_path(model) { "not found" } else { "not downloaded" };
+            (!Path::new(&path).exists()).then(|| format!("{} ({})", missing, path))
+        }
+        "faster-whisper" => {
+            let on_disk = Path::new(model).exists() || crate::faster_whisper::model::cached_path(model).is_some();
+            (!on_disk).then(|| format!("not in {}", crate::faster_whisper::model::models_dir()))
+        }
+        _ => None,
+    }
+}
+
+fn download(backend: &str, model: &str) -> Result<()> {
+    match backend {
+        "whisper-cpp" => {
+            let path = crate::helpers::whisper_cpp_model_path(model);
+            crate::whisper_cpp::model::download_model_with_progress(model, &path)
+        }
+        "faster-whisper" => crate::faster_whisper::model::download(model).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Socket of a ready daemon that already has `model` loaded on `backend`
+fn daemon_for(backend: &str, model: &str) -> Option<String> {
+    crate::daemons::list()
+        .into_iter()
+        .find(|entry| matches!(&entry.status, Ok(info) if info.model == model && info.backend == backend))
+        .map(|entry| entry.socket_path)
+}
+
+/// Transcribe through a daemon running the model, asking for segments so the
+/// confidence can be reported
+fn via_daemon(socket_path: &str, options: &CompareOptions) -> Result<(String, Option<f32>, u64)> {
+    let mut request = TranscriptionRequest::new(&options.audio, &options.language);
+    request.backend = Some(options.backend.clone());
+    request.priority = Priority::Batch;
+    request.allow_long = true;
+    request.segments = true;
+    
+    let started = Instant::now();
+    match crate::socket::request_transcription(socket_path, &request)? {
+        Some(TranscriptionResponse { success: true, text: Some(text), segments, latency_ms, .. }) => {
+            let confidences: Vec<f32> = segments.iter().filter_map(|segment| segment.confidence).collect();
+            let confidence = (!confidences.is_empty()).then(|| confidences.iter().sum::<f32>() / confidences.len() as f32);
+            let time_ms = latency_ms.unwrap_or(started.elapsed().as_millis() as u64);
+            Ok((text, confidence, time_ms))
+        }
+        Some(TranscriptionResponse { error, .. }) => Err(anyhow!(error.unwrap_or_else(|| "Transcription failed".to_string()))),
+        None => Err(anyhow!("Could not parse daemon response")),
+    }
+}
+
+/// Transcribe with `model`, through a daemon that has it loaded if there is one
+fn transcribe(model: &str, options: &CompareOptions) -> Result<Outcome> {
+    let daemon = daemon_for(&options.backend, model);
+    let (via, text, confidence, time_ms) = match daemon.as_deref().map(|socket| via_daemon(socket, options)) {
+        Some(Ok((text, confidence, time_ms))) => ("daemon", text, confidence, time_ms),
+        failed => {
+            if let Some(Err(e)) = failed {
+                eprintln!("DEBUG: Daemon running {} failed ({:#}), loading it directly", model, e);
+            }
+            let started = Instant::now();
+            let text = crate::transcribe::transcribe_direct(&options.backend, &options.audio, Some(model.to_string()), &options.language, None)?;
+            ("direct", text, None, started.elapsed().as_millis() as u64)
+        }
+    };
+    Ok(Outcome {
+        model: model.to_string(),
+        via,
+        time_ms,
+        confidence,
+        text: crate::normalize::transcript(&text),
+        errors: None,
+        words: None,
+        diff: None,
+    })
+}
+
+/// The model to diff against: the largest by memory needs, the last listed
+/// among equals (and among models of unknown size)
+fn reference(results: &[Outcome]) -> Option<&str> {
+    results
+        .iter()
+        .max_by_key(|outcome| crate::whisper_cpp::model::required_ram_mb(&outcome.model).unwrap_or(0))
+        .map(|outcome| outcome.model.as_str())
+}
+
+fn print_table(report: &Report) {
+    println!("{} ({})", report.audio, report.backend);
+    let names = report.results.iter().map(|outcome| &outcome.model).chain(report.skipped.iter().map(|skipped| &skipped.model));
+    let width = names.map(String::len).max().unwrap_or(0).max("MODEL".len());
+    println!("{:<width$}  {:<6}  {:>9}  {:>5}  WER", "MODEL", "VIA", "TIME", "CONF", width = width);
+    for outcome in &report.results {
+        let confidence = outcome.confidence.map(|c| format!("{:.2}", c)).unwrap_or_else(|| "-".to_string());
+        let wer = match (outcome.errors, outcome.words) {
+            (Some(errors), Some(words)) => {
+                let rate = if words == 0 { 0.0 } else { 100.0 * errors as f64 / words as f64 };
+                format!("{:.1}% ({}/{})", rate, errors, words)
+            }
+            _ => "reference".to_string(),
+        };
+        let time = format!("{} ms", outcome.time_ms);
+        println!("{:<width$}  {:<6}  {:>9}  {:>5}  {}", outcome.model, outcome.via, time, confidence, wer, width = width);
+    }
+    for skipped in &report.skipped {
+        println!("{:<width$}  skipped: {}", skipped.model, skipped.reason, width = width);
+    }
+    
+    for outcome in &report.results {
+        println!();
+        println!("{}: {}", outcome.model, outcome.text);
+        if let Some(diff) = &outcome.diff {
+            println!("  diff: {}", diff);
+        }
+    }
+}
+
+/// Transcribe one file with each model and report the transcripts side by
+/// side, diffed word by word against the largest model's. Nothing is typed.
+pub fn run(mut options: CompareOptions) -> Result<()> {
+    // Daemons resolve the path on their side
+    options.audio = std::fs::canonicalize(&options.audio)
+        .with_context(|| format!("Failed to read {}", options.audio))?
+        .to_string_lossy()
+        .into_owned();
+    
+    let mut results = Vec::new();
+    let mut skipped = Vec::new();
+    for model in &options.models {
+        if daemon_for(&options.backend, model).is_none() {
+            if let Some(reason) = missing(&options.backend, model) {
+                if !options.download {
+                    skipped.push(Skipped { model: model.clone(), reason: format!("{}, pass --download", reason) });
+                    continue;
+                }
+                if let Err(e) = download(&options.backend, model) {
+                    skipped.push(Skipped { model: model.clone(), reason: format!("download failed: {:#}", e) });
+                    continue;
+                }
+            }
+        }
+        eprintln!("DEBUG: Transcribing {} with {}", options.audio, model);
+        match transcribe(model, &options) {
+            Ok(outcome) => results.push(outcome),
+            Err(e) => skipped.push(Skipped { model: model.clone(), reason: format!("failed: {:#}", e) }),
+        }
+    }
+    if results.is_empty() {
+        let reasons: Vec<String> = skipped.iter().map(|skipped| format!("{}: {}", skipped.model, skipped.reason)).collect();
+        return Err(anyhow!("No model could transcribe {}\n{}", options.audio, reasons.join("\n")));
+    }
+    
+    let reference = reference(&results).map(str::to_string);
+    if let Some(reference_model) = &reference {
+        let reference_tokens = results
+            .iter()
+            .find(|outcome| &outcome.model == reference_model)
+            .map(|outcome| tokens(&outcome.text))
+            .unwrap_or_default();
+        for outcome in results.iter_mut().filter(|outcome| &outcome.model != reference_model) {
+            let edits = align(&reference_tokens, &tokens(&outcome.text));
+            outcome.errors = Some(error_count(&edits));
+            outcome.words = Some(reference_tokens.len());
+            outcome.diff = Some(render_diff(&edits));
+        }
+    }
+    
+    let report = Report { audio: options.audio, backend: options.backend, reference, results, skipped };
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_table(&report);
+    }
+    Ok(())
+}
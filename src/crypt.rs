@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::process::{Command, Stdio};
+
+/// Start of an encrypted recording: the magic, then the nonce, then the
+/// ciphertext. Plaintext WAV and FLAC start with "RIFF" and "fLaC".
+const MAGIC: &[u8] = b"WAENC1\0\0";
+/// Start of an encrypted journal line, followed by the nonce and ciphertext
+/// in hex. Plaintext lines start with `{`.
+const LINE_PREFIX: &str = "waenc1:";
+#[cfg(feature = "encrypt")]
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+/// Attributes the key is stored under in the Secret Service keyring
+const KEYRING_ATTRIBUTES: [&str; 4] = ["service", "whisp-away", "key", "history"];
+
+/// Whether kept recordings and the journal are encrypted (WA_ENCRYPT=1)
+pub fn enabled() -> bool {
+    let wanted = matches!(std::env::var("WA_ENCRYPT").as_deref(), Ok("1") | Ok("true"));
+    if wanted && !cfg!(feature = "encrypt") {
+        eprintln!("WARNING: WA_ENCRYPT needs a build with the encrypt feature, keeping history unencrypted");
+        return false;
+    }
+    wanted
+}
+
+/// `~/.config/whisp-away/history.key`, used when there is no keyring
+pub fn key_file() -> String {
+    format!("{}/whisp-away/history.key", crate::helpers::config_dir())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+fn parse_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    from_hex(hex.trim())?.try_into().ok()
+}
+
+/// The key from the keyring, through secret-tool
+fn keyring_key() -> Option<[u8; KEY_LEN]> {
+    crate::helpers::find_program("secret-tool")?;
+    let output = Command::new("secret-tool")
+        .arg("lookup")
+        .args(KEYRING_ATTRIBUTES)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_key(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn file_key() -> Option<[u8; KEY_LEN]> {
+    parse_key(&fs::read_to_string(key_file()).ok()?)
+}
+
+/// The keyring's key, or the key file's. Looked up once per process, since
+/// asking the keyring may mean unlocking it.
+#[cfg(feature = "encrypt")]
+fn key() -> Result<[u8; KEY_LEN]> {
+    static KEY: std::sync::OnceLock<Option<[u8; KEY_LEN]>> = std::sync::OnceLock::new();
+    KEY.get_or_init(|| keyring_key().or_else(file_key))
+        .ok_or_else(|| anyhow!("No history encryption key in the keyring or {}; run `whisp-away setup --only encrypt`", key_file()))
+}
+
+/// Store a new key in the keyring, or in the key file when there is no
+/// keyring to store it in
+fn store_key(key: &[u8; KEY_LEN]) -> Result<String> {
+    if crate::helpers::find_program("secret-tool").is_some() {
+        let stored = Command::new("secret-tool")
+            .arg("store")
+            .arg("--label=whisp-away history key")
+            .args(KEYRING_ATTRIBUTES)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(to_hex(key).as_bytes())?;
+                }
+                child.wait_with_output()
+            });
+        match stored {
+            Ok(output) if output.status.success() => return Ok("the keyring".to_string()),
+            Ok(output) => eprintln!("WARNING: secret-tool could not store the key: {}", String::from_utf8_lossy(&output.stderr).trim()),
+            Err(e) => eprintln!("WARNING: Could not run secret-tool: {}", e),
+        }
+    }
+    
+    let path = key_file();
+    if let Some(dir) = std::path::Path::new(&path).parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path))?;
+    writeln!(file, "{}", to_hex(key)).with_context(|| format!("Failed to write {}", path))?;
+    Ok(path)
+}
+
+/// Make sure there is a key, generating one if needed, and say where it is
+pub fn ensure_key() -> Result<String> {
+    if keyring_key().is_some() {
+        return Ok("the keyring".to_string());
+    }
+    if file_key().is_some() {
+        return Ok(key_file());
+    }
+    let mut key = [0u8; KEY_LEN];
+    crate::helpers::random_bytes(&mut key)?;
+    store_key(&key)
+}
+
+/// Whether `bytes` are an encrypted recording
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+#[cfg(feature = "encrypt")]
+fn cipher() -> Result<chacha20poly1305::XChaCha20Poly1305> {
+    use chacha20poly1305::aead::KeyInit;
+    chacha20poly1305::XChaCha20Poly1305::new_from_slice(&key()?).map_err(|e| anyhow!("Invalid key: {}", e))
+}
+
+/// Nonce followed by the ciphertext of `plain`
+#[cfg(feature = "encrypt")]
+fn encrypt(plain: &[u8]) -> Result<Vec<u8>> {
+    use chacha20poly1305::aead::Aead;
+    let mut nonce = [0u8; NONCE_LEN];
+    crate::helpers::random_bytes(&mut nonce)?;
+    let ciphertext = cipher()?
+        .encrypt(chacha20poly1305::XNonce::from_slice(&nonce), plain)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    Ok([&nonce[..], &ciphertext].concat())
+}
+
+#[cfg(not(feature = "encrypt"))]
+fn encrypt(_plain: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("whisp-away was built without the encrypt feature"))
+}
+
+/// `encrypt` undone, failing if the data was changed or the key is another one
+#[cfg(feature = "encrypt")]
+fn decrypt(sealed: &[u8]) -> Result<Vec<u8>> {
+    use chacha20poly1305::aead::Aead;
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow!("Encrypted data is truncated"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher()?
+        .decrypt(chacha20poly1305::XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Decryption failed: the data is corrupt or was encrypted with another key"))
+}
+
+#[cfg(not(feature = "encrypt"))]
+fn decrypt(_sealed: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("This is encrypted, but whisp-away was built without the encrypt feature"))
+}
+
+/// Encrypt `plain` as a recording
+pub fn seal(plain: &[u8]) -> Result<Vec<u8>> {
+    Ok([MAGIC, &encrypt(plain)?].concat())
+}
+
+/// The plaintext of a recording, decrypted if it was encrypted
+pub fn open(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    match bytes.strip_prefix(MAGIC) {
+        Some(sealed) => decrypt(sealed),
+        None => Ok(bytes),
+    }
+}
+
+/// Write `plain` to `path` encrypted, readable only by the user
+pub fn seal_to(plain: &[u8], path: &str) -> Result<()> {
+    let sealed = seal(plain)?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to create {}", path))?;
+    file.write_all(&sealed).with_context(|| format!("Failed to write {}", path))
+}
+
+/// Encrypt the file at `path` in place. The encrypted copy replaces it in
+/// one rename, so a failure leaves the plaintext rather than nothing.
+pub fn seal_file(path: &str) -> Result<()> {
+    let plain = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+    if is_sealed(&plain) {
+        return Ok(());
+    }
+    let partial = format!("{}.partial", path);
+    seal_to(&plain, &partial)?;
+    fs::rename(&partial, path).with_context(|| format!("Failed to replace {}", path))
+}
+
+/// Encrypt one journal line
+pub fn seal_line(line: &str) -> Result<String> {
+    Ok(format!("{}{}", LINE_PREFIX, to_hex(&encrypt(line.as_bytes())?)))
+}
+
+/// A journal line as written, decrypted if it was encrypted
+pub fn open_line(line: &str) -> Result<String> {
+    let Some(hex) = line.strip_prefix(LINE_PREFIX) else {
+        return Ok(line.to_string());
+    };
+    let sealed = from_hex(hex.trim()).ok_or_else(|| anyhow!("Encrypted journal line isn't valid hex"))?;
+    String::from_utf8(decrypt(&sealed)?).context("Decrypted journal line isn't UTF-8")
+}
+
+/// A recording readable by path: the file itself, or a decrypted copy in the
+/// runtime directory that is removed again on drop
+pub struct PlainFile {
+    pub path: String,
+    temporary: bool,
+}
+
+impl PlainFile {
+    /// `path` as is when it isn't encrypted, else a decrypted copy
+    pub fn open(path: &str) -> Result<PlainFile> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+        if !is_sealed(&bytes) {
+            return Ok(PlainFile { path: path.to_string(), temporary: false });
+        }
+        let plain = open(bytes).with_context(|| format!("Failed to decrypt {}", path))?;
+        let name = std::path::Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let copy = format!("{}/decrypted-{}-{}", crate::daemons::socket_dir(), std::process::id(), name);
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&copy)
+            .with_context(|| format!("Failed to create {}", copy))?;
+        file.write_all(&plain).with_context(|| format!("Failed to write {}", copy))?;
+        eprintln!("DEBUG: Decrypted {} to {}", path, copy);
+        Ok(PlainFile { path: copy, temporary: true })
+    }
+}
+
+impl Drop for PlainFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
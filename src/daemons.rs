@@ -0,0 +1,412 @@
+use anyhow::{anyhow, Context, Result};
+use std::net::SocketAddr;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use crate::helpers;
+use crate::ready::{self, NotReady, ReadyInfo};
+
+/// Socket all unnamed daemons shared before each backend got its own; one
+/// started by an older whisp-away may still be listening on it
+pub const LEGACY_SOCKET: &str = "/tmp/whisp-away-daemon.sock";
+
+/// Backends whose unnamed daemons live in the socket dir, named after them
+const UNNAMED: [&str; 3] = ["whisper-cpp", "faster-whisper", "mock"];
+
+/// Ready file that goes with LEGACY_SOCKET
+pub fn legacy_ready_file() -> String {
+    helpers::runtime_file("whisp-away-daemon.ready")
+}
+
+/// Directory named daemons keep their socket and ready file in, without creating it
+pub fn socket_dir_path() -> String {
+    helpers::runtime_file("whisp-away")
+}
+
+/// Directory named daemons keep their socket and ready file in
+pub fn socket_dir() -> String {
+    let dir = socket_dir_path();
+    std::fs::create_dir_all(&dir).ok();
+    dir
+}
+
+pub fn named_socket(name: &str) -> String {
+    format!("{}/daemon-{}.sock", socket_dir(), name)
+}
+
+pub fn named_ready_file(name: &str) -> String {
+    format!("{}/daemon-{}.ready", socket_dir(), name)
+}
+
+/// Socket of the unnamed daemon for `backend`, which the tray manages and
+/// clients use by default. Each backend has its own, so a whisper.cpp daemon
+/// for dictation and a faster-whisper one for batch work can run side by
+/// side. WA_WHISPER_SOCKET overrides it.
+pub fn default_socket(backend: &str) -> String {
+    socket_source(None, backend).0
+}
+
+/// The socket path and where it came from, in order of precedence: the
+/// `--socket-path` flag, WA_WHISPER_SOCKET, the backend's own socket
+fn socket_source(arg: Option<String>, backend: &str) -> (String, &'static str) {
+    if let Some(path) = arg.filter(|path| !path.is_empty()) {
+        return (path, "--socket-path");
+    }
+    match std::env::var("WA_WHISPER_SOCKET").ok().filter(|path| !path.is_empty()) {
+        Some(path) => (path, "WA_WHISPER_SOCKET"),
+        None => (named_socket(backend), "default"),
+    }
+}
+
+/// Socket of the unnamed daemon for `backend`, `--socket-path` given as `arg`.
+/// The daemon binds it, and the tray and clients connect to it, by this same
+/// precedence: the flag, then WA_WHISPER_SOCKET, then the backend's default in
+/// the socket dir. Logs which one won, once per process that resolves it.
+pub fn resolve_socket(arg: Option<String>, backend: &str) -> String {
+    let (path, source) = socket_source(arg, backend);
+    eprintln!("DEBUG: Socket for {}: {} (from {})", backend, path, source);
+    path
+}
+
+/// Ready file of the unnamed daemon for `backend`
+pub fn default_ready_file(backend: &str) -> String {
+    named_ready_file(backend)
+}
+
+/// The unnamed daemon for `backend`, or a daemon of that backend still
+/// serving on LEGACY_SOCKET from before the upgrade while it has none of its own
+fn default_target(backend: &str) -> Target {
+    let socket_path = resolve_socket(None, backend);
+    let ready_file = default_ready_file(backend);
+    // Anyone could have bound the shared /tmp socket since, so it must be ours
+    let legacy = helpers::owned_by_us(LEGACY_SOCKET)
+        && ready::check(&legacy_ready_file(), LEGACY_SOCKET).is_ok_and(|info| info.backend == backend);
+    if legacy && ready::check(&ready_file, &socket_path).is_err() {
+        eprintln!("DEBUG: Using the daemon on {}, started before each backend had its own socket", LEGACY_SOCKET);
+        return Target { socket_path: LEGACY_SOCKET.to_string(), ready_file: legacy_ready_file(), any_backend: false };
+    }
+    Target { socket_path, ready_file, any_backend: false }
+}
+
+/// Name a daemon gets from a bare `--name`: `<backend>-<model>`, with
+/// anything that doesn't belong in a file name replaced
+pub fn default_name(backend: &str, model: &str) -> String {
+    format!("{}-{}", backend, model)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Reject names that would put the socket outside the socket dir
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(anyhow!("Invalid daemon name {:?}", name));
+    }
+    Ok(())
+}
+
+/// Scheme of daemon addresses reached over TCP (`daemon --listen`, `--daemon-url`)
+pub const TCP_SCHEME: &str = "tcp://";
+
+/// `HOST:PORT` of a `tcp://HOST:PORT` daemon address, `None` for a socket path
+pub fn tcp_address(address: &str) -> Option<&str> {
+    address.strip_prefix(TCP_SCHEME)
+}
+
+/// Shared secret TCP clients present and the TCP listener checks: WA_DAEMON_TOKEN,
+/// or the one a local daemon generated into token_file()
+pub fn tcp_token() -> Option<String> {
+    std::env::var("WA_DAEMON_TOKEN").ok().filter(|token| !token.is_empty()).or_else(generated_token)
+}
+
+/// Where a daemon listening on loopback without WA_DAEMON_TOKEN keeps the
+/// token it generated, readable only by the user
+pub fn token_file() -> String {
+    format!("{}/daemon-token", socket_dir_path())
+}
+
+fn generated_token() -> Option<String> {
+    let path = token_file();
+    // Another user could have put one there only if the runtime dir is shared
+    if helpers::owner(&path)? != helpers::uid() {
+        return None;
+    }
+    let token = std::fs::read_to_string(path).ok()?;
+    Some(token.trim().to_string()).filter(|token| !token.is_empty())
+}
+
+/// Generate a token into token_file(), replacing one an earlier daemon left
+fn generate_token() -> Result<String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    
+    let mut bytes = [0u8; 16];
+    helpers::random_bytes(&mut bytes)?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let path = format!("{}/daemon-token", socket_dir());
+    // A fresh file, so the mode applies even if an old one had looser permissions
+    let _ = std::fs::remove_file(&path);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path))?;
+    file.write_all(token.as_bytes()).with_context(|| format!("Failed to write {}", path))?;
+    Ok(token)
+}
+
+/// Address a `--daemon-url` client connects to, checked before anything is recorded
+pub fn daemon_url(url: &str) -> Result<String> {
+    match tcp_address(url) {
+        Some(address) if address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => Ok(url.to_string()),
+        _ => Err(anyhow!("Daemon URL {:?} isn't of the form tcp://HOST:PORT", url)),
+    }
+}
+
+/// TCP listener a daemon serves next to its Unix socket (`daemon --listen`)
+#[derive(Debug, Clone)]
+pub struct TcpListen {
+    pub address: SocketAddr,
+    /// Clients must present this in their hello
+    pub token: String,
+}
+
+impl TcpListen {
+    /// Parse `--listen tcp://IP:PORT`. Anything beyond loopback is reachable
+    /// from the network, so it takes `--insecure-bind` and a WA_DAEMON_TOKEN.
+    /// Loopback is reachable by every local user, so without WA_DAEMON_TOKEN
+    /// a token is generated into token_file(), where this user's clients find it.
+    pub fn configure(url: &str, insecure_bind: bool) -> Result<Self> {
+        let address: SocketAddr = tcp_address(url)
+            .ok_or_else(|| anyhow!("--listen {:?} isn't of the form tcp://IP:PORT", url))?
+            .parse()
+            .with_context(|| format!("--listen {:?} isn't of the form tcp://IP:PORT", url))?;
+        let token = std::env::var("WA_DAEMON_TOKEN").ok().filter(|token| !token.is_empty());
+        let token = match token {
+            Some(token) => token,
+            None if address.ip().is_loopback() => {
+                let token = generate_token()?;
+                eprintln!("No WA_DAEMON_TOKEN set; TCP clients must present the token in {}", token_file());
+                token
+            }
+            None => return Err(anyhow!("Listening on {} requires WA_DAEMON_TOKEN to be set", address)),
+        };
+        if !address.ip().is_loopback() && !insecure_bind {
+            return Err(anyhow!("Listening on {} exposes the daemon to the network; pass --insecure-bind to confirm", address));
+        }
+        Ok(Self { address, token })
+    }
+}
+
+/// Remove a socket left behind by a daemon that is gone, but refuse to take
+/// over one another daemon is still listening on
+pub fn claim_socket(socket_path: &str) -> Result<()> {
+    if !Path::new(socket_path).exists() {
+        return Ok(());
+    }
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(anyhow!("Another daemon is already listening on {}", socket_path));
+    }
+    std::fs::remove_file(socket_path)?;
+    Ok(())
+}
+
+/// A daemon found on disk
+pub struct DaemonEntry {
+    /// The backend for an unnamed daemon, "legacy" for one on LEGACY_SOCKET
+    pub name: String,
+    pub socket_path: String,
+    pub ready_file: String,
+    pub status: Result<ReadyInfo, NotReady>,
+}
+
+impl DaemonEntry {
+    fn new(name: &str, socket_path: String, ready_file: String) -> Self {
+        let status = ready::check(&ready_file, &socket_path);
+        Self { name: name.to_string(), socket_path, ready_file, status }
+    }
+}
+
+/// Every daemon with a socket or ready file: one left on LEGACY_SOCKET first,
+/// then those in the socket dir sorted by name, the unnamed ones among them
+pub fn list() -> Vec<DaemonEntry> {
+    let mut entries = Vec::new();
+    
+    let legacy_ready = legacy_ready_file();
+    if helpers::owned_by_us(LEGACY_SOCKET) || Path::new(&legacy_ready).exists() {
+        entries.push(DaemonEntry::new("legacy", LEGACY_SOCKET.to_string(), legacy_ready));
+    }
+    
+    let mut names: Vec<String> = std::fs::read_dir(socket_dir_path())
+        .map(|dir| {
+            dir.flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter_map(|file| {
+                    let stem = file.strip_prefix("daemon-")?;
+                    let name = stem.strip_suffix(".sock").or_else(|| stem.strip_suffix(".ready"))?;
+                    Some(name.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names.dedup();
+    
+    for name in names {
+        // An unnamed daemon is named after its backend, and may have been
+        // given its socket through WA_WHISPER_SOCKET
+        let socket_path = if UNNAMED.contains(&name.as_str()) { default_socket(&name) } else { named_socket(&name) };
+        entries.push(DaemonEntry::new(&name, socket_path, named_ready_file(&name)));
+    }
+    entries
+}
+
+/// Which daemon a client talks to (`--daemon`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// The unnamed daemon for the requested backend
+    Default,
+    /// Whichever daemon runs the requested model, else any running one
+    Auto,
+    Named(String),
+}
+
+impl Selector {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "default" => Selector::Default,
+            "auto" => Selector::Auto,
+            name => Selector::Named(name.to_string()),
+        }
+    }
+    
+    /// `--daemon` if given, else WA_DAEMON, else the default daemon
+    pub fn resolve(arg: Option<String>) -> Self {
+        arg.or_else(|| std::env::var("WA_DAEMON").ok())
+            .map(|value| Self::parse(&value))
+            .unwrap_or(Selector::Default)
+    }
+}
+
+/// Where a client should send its request
+pub struct Target {
+    pub socket_path: String,
+    /// Ready file belonging to that socket, for --require-ready
+    pub ready_file: String,
+    /// The selected daemon runs a different backend and should be used anyway
+    pub any_backend: bool,
+}
+
+/// Pick the daemon for a request. `auto` prefers a ready daemon running `model`
+/// (on `backend` if there are several), then any ready daemon; with none it
+/// returns the default socket, so the client falls back to direct
+/// transcription the usual way.
+pub fn select(selector: &Selector, backend: &str, model: &str) -> Result<Target> {
+    match selector {
+        Selector::Default => Ok(default_target(backend)),
+        Selector::Named(name) => {
+            validate_name(name)?;
+            Ok(Target {
+                socket_path: named_socket(name),
+                ready_file: named_ready_file(name),
+                any_backend: false,
+            })
+        }
+        Selector::Auto => {
+            let ready: Vec<(DaemonEntry, ReadyInfo)> = list()
+                .into_iter()
+                .filter_map(|entry| {
+                    let info = entry.status.as_ref().ok()?.clone();
+                    Some((entry, info))
+                })
+                .collect();
+            
+            let rank = |info: &ReadyInfo| match (info.model == model, info.backend == backend) {
+                (true, true) => 0,
+                (true, false) => 1,
+                (false, true) => 2,
+                (false, false) => 3,
+            };
+            match ready.into_iter().min_by_key(|(_, info)| rank(info)) {
+                Some((entry, info)) => {
+                    eprintln!(
+                        "DEBUG: Auto-selected daemon {} ({} {}) for {} {}",
+                        entry.name, info.backend, info.model, backend, model
+                    );
+                    Ok(Target {
+                        socket_path: entry.socket_path,
+                        ready_file: entry.ready_file,
+                        any_backend: info.backend != backend,
+                    })
+                }
+                None => {
+                    eprintln!("DEBUG: No daemon is ready, trying the default one");
+                    select(&Selector::Default, backend, model)
+                }
+            }
+        }
+    }
+}
+
+/// Print every daemon with its state, for `whisp-away status`
+pub fn print_status() -> Result<()> {
+    if let Ok(path) = std::env::var("WA_WHISPER_SOCKET") {
+        eprintln!("DEBUG: Unnamed daemons' socket: {} (from WA_WHISPER_SOCKET)", path);
+    }
+    let entries = list();
+    if entries.is_empty() {
+        println!("No daemons running");
+        return Ok(());
+    }
+    
+    for entry in entries {
+        match &entry.status {
+            Ok(info) => {
+                println!(
+                    "{}: ready, {} {} (language {}, pid {}) on {}",
+                    entry.name, info.backend, info.model, info.language, info.pid, entry.socket_path
+                );
+                if let Some(queue) = &info.queue {
+                    println!("  queue: {}", queue);
+                }
+                if let Some(health) = &info.health {
+                    println!("  health: {}", health);
+                }
+                if let Some(acceleration) = &info.acceleration {
+                    if info.openvino_active == Some(true) {
+                        println!("  acceleration: {} (OpenVINO active)", acceleration);
+                    } else if info.acceleration_degraded() {
+                        println!("  acceleration: {} (OpenVINO inactive, running on CPU)", acceleration);
+                    } else {
+                        println!("  acceleration: {}", acceleration);
+                    }
+                }
+                if let Some(device) = &info.openvino_device {
+                    println!("  OpenVINO device: {}", device);
+                }
+                if let Some(model_path) = &info.model_path {
+                    println!("  model file: {}", model_path);
+                }
+                if let Some(fast_model) = &info.fast_model {
+                    println!("  fast model: {} (kept loaded for `quick` and `stop --fast`)", fast_model);
+                }
+                if let Some(memory) = &info.memory {
+                    println!("  memory: {}", memory);
+                }
+                if let Some(inbox) = &info.inbox {
+                    println!("  inbox: {}", inbox);
+                }
+                match crate::upgrade::mismatch(info.version.as_deref()) {
+                    Some(daemon) => println!("  version: {} (this is {}, restart the daemon)", daemon, crate::upgrade::VERSION),
+                    None => println!("  version: {}", crate::upgrade::VERSION),
+                }
+            }
+            // A socket nobody answers on was left behind by a daemon that is gone
+            Err(NotReady::Starting { .. }) if UnixStream::connect(&entry.socket_path).is_err() => {
+                println!("{}: not running, stale socket {}", entry.name, entry.socket_path)
+            }
+            Err(not_ready) => println!("{}: {} ({})", entry.name, not_ready, entry.socket_path),
+        }
+    }
+    Ok(())
+}
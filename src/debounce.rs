@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How close together identical hotkey commands count as one by default
+const DEFAULT_WINDOW_MS: u64 = 250;
+
+/// Commands not run for this long are dropped from the file
+const FORGET_AFTER: Duration = Duration::from_secs(60);
+
+/// When each hotkey command was last run: a JSON object of command line to
+/// milliseconds since the epoch
+pub fn state_path() -> String {
+    crate::helpers::runtime_file("whisp-away-hotkeys.json")
+}
+
+/// WA_DEBOUNCE_MS, or 250 ms; 0 turns debouncing off
+pub fn window() -> Duration {
+    let ms = std::env::var("WA_DEBOUNCE_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_WINDOW_MS);
+    Duration::from_millis(ms)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Note that `command` runs now, and say whether it already ran within
+/// `window`. Every run counts, ignored ones too, so a held key stays ignored
+/// for as long as it repeats.
+fn note(command: &str, window: Duration) -> Result<bool> {
+    let path = state_path();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path))?;
+    // Concurrent runs take turns, so of a burst exactly one finds no earlier run
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to lock the hotkey file");
+    }
+    
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    // A damaged file is started over rather than blocking every hotkey
+    let mut runs: HashMap<String, u64> = serde_json::from_str(&content).unwrap_or_default();
+    let now = now_ms();
+    // A clock set back makes an earlier run look like it is in the future; it doesn't count
+    let repeated = runs
+        .get(command)
+        .is_some_and(|&at| now >= at && now - at < window.as_millis() as u64);
+    runs.retain(|_, at| now.saturating_sub(*at) < FORGET_AFTER.as_millis() as u64);
+    runs.insert(command.to_string(), now);
+    
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(serde_json::to_string(&runs)?.as_bytes())?;
+    Ok(repeated)
+}
+
+/// Whether this run of `command` (its command line) repeats one from within
+/// the debounce window and should exit without doing anything. Fails open:
+/// when the file can't be used, the command runs.
+pub fn repeated(command: &str) -> bool {
+    let window = window();
+    if window.is_zero() {
+        return false;
+    }
+    match note(command, window) {
+        Ok(true) => {
+            eprintln!("DEBUG: Ignoring `{}`, run again within {} ms", command, window.as_millis());
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            eprintln!("WARNING: Could not debounce `{}`: {:#}", command, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::Read;
+use std::process::{Child, ChildStdout, Stdio};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::protocol::Priority;
+use crate::transcribe::TranscribeOptions;
+use crate::wav::{self, TARGET_RATE};
+
+/// Samples per analysis frame (100 ms)
+pub const FRAME_SAMPLES: usize = TARGET_RATE as usize / 10;
+/// Quiet frames that end a phrase, which is then transcribed while recording goes on (0.8 s)
+const PAUSE_FRAMES: usize = 8;
+/// Quiet frames kept in front of a phrase so its first word isn't cut off
+const PRE_ROLL_FRAMES: usize = 3;
+/// How long the transcriber waits for the next frame before checking for stop()
+const FRAME_WAIT: Duration = Duration::from_millis(200);
+
+/// Always-on microphone capture as a raw s16 mono stream
+pub struct Capture {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Capture {
+    pub fn spawn() -> Result<Self> {
+        let mut child = crate::child_env::command("pw-record", &[])
+            .args([
+                "--channels", "1",
+                "--rate", "16000",
+                "--format", "s16",
+                "--raw",
+                "-",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start pw-record")?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| anyhow!("pw-record has no stdout"))?;
+        Ok(Self { child, stdout })
+    }
+    
+    /// Next 100 ms frame, or None once pw-record exits
+    pub fn next_frame(&mut self) -> Result<Option<Vec<f32>>> {
+        let mut bytes = [0u8; FRAME_SAMPLES * 2];
+        match self.stdout.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(
+                bytes.chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+                    .collect(),
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e).context("Failed to read from pw-record"),
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+type Callback<T> = Box<dyn FnMut(T) + Send>;
+
+/// What a dictation reports, delivered to the callbacks in order
+enum Event {
+    Level(f32),
+    Partial(String),
+    Final(String),
+    Error(anyhow::Error),
+}
+
+/// The callbacks, run one at a time on the delivery thread or runtime
+#[derive(Default)]
+struct Callbacks {
+    on_partial: Option<Callback<String>>,
+    on_final: Option<Callback<String>>,
+    on_level: Option<Callback<f32>>,
+    on_error: Option<Callback<anyhow::Error>>,
+}
+
+impl Callbacks {
+    fn deliver(mut self, events: mpsc::Receiver<Event>) {
+        for event in events {
+            match (event, &mut self) {
+                (Event::Level(level), Callbacks { on_level: Some(f), .. }) => f(level),
+                (Event::Partial(text), Callbacks { on_partial: Some(f), .. }) => f(text),
+                (Event::Final(text), Callbacks { on_final: Some(f), .. }) => f(text),
+                (Event::Error(e), Callbacks { on_error: Some(f), .. }) => f(e),
+                (Event::Error(e), _) => eprintln!("WARNING: Dictation: {:#}", e),
+                _ => {}
+            }
+        }
+    }
+}
+
+const RUNNING: u8 = 0;
+const STOPPING: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Event-driven dictation: record from the microphone, transcribe each phrase
+/// as the speaker pauses, and report levels, phrases and the final text
+/// through callbacks.
+///
+/// ```ignore
+/// let dictation = Dictation::builder()
+///     .backend("whisper-cpp")
+///     .on_level(|rms| meter.set(rms))
+///     .on_partial(|phrase| println!("… {}", phrase))
+///     .on_final(|text| println!("{}", text))
+///     .start()?;
+/// // later
+/// let text = dictation.stop()?;
+/// ```
+pub struct Dictation;
+
+impl Dictation {
+    pub fn builder() -> DictationBuilder {
+        DictationBuilder {
+            options: TranscribeOptions {
+                backend: "whisper-cpp".to_string(),
+                model: None,
+                language: crate::helpers::resolve_language(None, None),
+                prompt_file: None,
+                socket_path: crate::daemons::default_socket("whisper-cpp"),
+                allow_long: false,
+                priority: Priority::Interactive,
+            },
+            runtime: None,
+            callbacks: Callbacks::default(),
+        }
+    }
+}
+
+/// Configures a dictation; see Dictation
+pub struct DictationBuilder {
+    options: TranscribeOptions,
+    runtime: Option<tokio::runtime::Handle>,
+    callbacks: Callbacks,
+}
+
+impl DictationBuilder {
+    /// Also picks that backend's daemon, unless `socket_path` was set
+    pub fn backend(mut self, backend: &str) -> Self {
+        if self.options.socket_path == crate::daemons::default_socket(&self.options.backend) {
+            self.options.socket_path = crate::daemons::default_socket(backend);
+        }
+        self.options.backend = backend.to_string();
+        self
+    }
+    
+    pub fn model(mut self, model: Option<String>) -> Self {
+        self.options.model = model;
+        self
+    }
+    
+    pub fn language(mut self, language: &str) -> Self {
+        self.options.language = language.to_string();
+        self
+    }
+    
+    /// Daemon to transcribe with; each phrase is transcribed directly when none answers
+    pub fn socket_path(mut self, socket_path: &str) -> Self {
+        self.options.socket_path = socket_path.to_string();
+        self
+    }
+    
+    /// Run the callbacks on this runtime's blocking pool instead of a thread
+    /// of their own
+    pub fn runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+    
+    /// Called with each phrase once the speaker pauses after it
+    pub fn on_partial(mut self, f: impl FnMut(String) + Send + 'static) -> Self {
+        self.callbacks.on_partial = Some(Box::new(f));
+        self
+    }
+    
+    /// Called once with the whole text after stop()
+    pub fn on_final(mut self, f: impl FnMut(String) + Send + 'static) -> Self {
+        self.callbacks.on_final = Some(Box::new(f));
+        self
+    }
+    
+    /// Called with the RMS level of every 100 ms of audio
+    pub fn on_level(mut self, f: impl FnMut(f32) + Send + 'static) -> Self {
+        self.callbacks.on_level = Some(Box::new(f));
+        self
+    }
+    
+    /// Called when a phrase can't be transcribed or capture fails; logged when unset
+    pub fn on_error(mut self, f: impl FnMut(anyhow::Error) + Send + 'static) -> Self {
+        self.callbacks.on_error = Some(Box::new(f));
+        self
+    }
+    
+    /// Start recording. Fails right away if the microphone can't be opened.
+    pub fn start(self) -> Result<DictationHandle> {
+        let capture = Capture::spawn()?;
+        let state = Arc::new(AtomicU8::new(RUNNING));
+        
+        // Callbacks run apart from capture and transcription, so a slow one
+        // holds up neither
+        let (events, received) = mpsc::channel();
+        let (delivered, done) = mpsc::channel();
+        let callbacks = self.callbacks;
+        let deliver = move || {
+            callbacks.deliver(received);
+            let _ = delivered.send(());
+        };
+        match &self.runtime {
+            Some(runtime) => drop(runtime.spawn_blocking(deliver)),
+            None => drop(std::thread::spawn(deliver)),
+        }
+        
+        // Frames are read on their own thread, so pw-record never waits on a
+        // transcription
+        let (frames, captured) = mpsc::channel();
+        let capture_state = Arc::clone(&state);
+        std::thread::spawn(move || read_frames(capture, &capture_state, frames));
+        
+        let worker_state = Arc::clone(&state);
+        let options = self.options;
+        let worker = std::thread::spawn(move || transcribe_phrases(captured, &worker_state, &options, events));
+        
+        Ok(DictationHandle { state, worker: Some(worker), done })
+    }
+}
+
+/// A running dictation
+pub struct DictationHandle {
+    state: Arc<AtomicU8>,
+    worker: Option<JoinHandle<String>>,
+    done: mpsc::Receiver<()>,
+}
+
+impl DictationHandle {
+    /// Stop recording, transcribe the last phrase and return the whole text,
+    /// once on_final has been called with it
+    pub fn stop(mut self) -> Result<String> {
+        self.state.store(STOPPING, Ordering::SeqCst);
+        let text = self.worker.take()
+            .map(|worker| worker.join().map_err(|_| anyhow!("Dictation thread panicked")))
+            .transpose()?
+            .unwrap_or_default();
+        let _ = self.done.recv();
+        Ok(text)
+    }
+    
+    /// Stop recording and drop what hasn't been transcribed yet; on_final isn't called
+    pub fn cancel(mut self) {
+        self.state.store(CANCELLED, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for DictationHandle {
+    fn drop(&mut self) {
+        if self.worker.is_some() {
+            self.state.store(CANCELLED, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Pass frames on until the dictation is stopped or pw-record goes away
+fn read_frames(mut capture: Capture, state: &AtomicU8, frames: mpsc::Sender<Result<Vec<f32>>>) {
+    while state.load(Ordering::SeqCst) == RUNNING {
+        let frame = match capture.next_frame() {
+            Ok(Some(frame)) => Ok(frame),
+            Ok(None) => Err(anyhow!("pw-record stopped")),
+            Err(e) => Err(e),
+        };
+        let failed = frame.is_err();
+        if frames.send(frame).is_err() || failed {
+            break;
+        }
+    }
+}
+
+/// Cut the audio into phrases at pauses and transcribe each as it ends,
+/// returning the whole text once capture stops
+fn transcribe_phrases(
+    frames: mpsc::Receiver<Result<Vec<f32>>>,
+    state: &AtomicU8,
+    options: &TranscribeOptions,
+    events: mpsc::Sender<Event>,
+) -> String {
+    let threshold = crate::listen::speech_threshold(crate::listen::DEFAULT_SENSITIVITY);
+    let mut texts = Vec::new();
+    let mut phrase: Vec<Vec<f32>> = Vec::new();
+    let mut heard_speech = false;
+    let mut quiet = 0;
+    
+    let transcribe = |phrase: &mut Vec<Vec<f32>>, texts: &mut Vec<String>| {
+        let samples: Vec<f32> = phrase.drain(..).flatten().collect();
+        match crate::transcribe::transcribe_samples(&samples, options, crate::prompt::current(options.prompt_file.as_deref(), None)) {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => {
+                texts.push(text.clone());
+                let _ = events.send(Event::Partial(text));
+            }
+            Err(e) => {
+                let _ = events.send(Event::Error(e.context("Failed to transcribe a phrase")));
+            }
+        }
+    };
+    
+    loop {
+        if state.load(Ordering::SeqCst) == CANCELLED {
+            return String::new();
+        }
+        // Once stopped, whatever was captured is still drained before finishing
+        let frame = match frames.recv_timeout(FRAME_WAIT) {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(e)) => {
+                let _ = events.send(Event::Error(e));
+                break;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) if state.load(Ordering::SeqCst) == RUNNING => continue,
+            Err(_) => break,
+        };
+        
+        let level = wav::rms(&frame);
+        let _ = events.send(Event::Level(level));
+        phrase.push(frame);
+        if level >= threshold {
+            heard_speech = true;
+            quiet = 0;
+        } else {
+            quiet += 1;
+        }
+        
+        if !heard_speech {
+            // Nothing said yet: only keep enough quiet to lead into the phrase
+            if phrase.len() > PRE_ROLL_FRAMES {
+                phrase.remove(0);
+            }
+        } else if quiet >= PAUSE_FRAMES {
+            transcribe(&mut phrase, &mut texts);
+            heard_speech = false;
+        }
+    }
+    
+    if state.load(Ordering::SeqCst) == CANCELLED {
+        return String::new();
+    }
+    if heard_speech {
+        transcribe(&mut phrase, &mut texts);
+    }
+    let text = texts.join(" ");
+    let _ = events.send(Event::Final(text.clone()));
+    text
+}
+
+/// Dictate in the terminal on top of Dictation: a level meter and each phrase
+/// on stderr as it's transcribed, the whole text on stdout after Enter
+pub fn run(options: TranscribeOptions) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let dictation = Dictation::builder()
+        .backend(&options.backend)
+        .model(options.model.clone())
+        .language(&options.language)
+        .socket_path(&options.socket_path)
+        .runtime(runtime.handle().clone())
+        .on_level(|level| {
+            // Same scale as the wake gate: full bar is loud speech
+            let bars = ((level / 0.1).min(1.0) * 20.0) as usize;
+            eprint!("\r[{:<20}] ", "#".repeat(bars));
+        })
+        .on_partial(|phrase| eprintln!("\r{:<23}\r… {}", "", phrase))
+        .on_final(|text| println!("{}", text))
+        .on_error(|e| eprintln!("\rWARNING: {:#}", e))
+        .start()?;
+    
+    eprintln!("Recording, press Enter to stop or Ctrl-D to discard");
+    let mut line = String::new();
+    let read = std::io::stdin().read_line(&mut line);
+    eprint!("\r{:<23}\r", "");
+    if matches!(read, Ok(0)) {
+        dictation.cancel();
+        eprintln!("Discarded");
+        return Ok(());
+    }
+    dictation.stop()?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+use crate::notify;
+
+/// How long a direct transcription waits for the one ahead of it by default
+const DEFAULT_MAX_WAIT_SECS: u64 = 120;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Held while a direct (daemon-less) transcription has a model loaded, so
+/// several quick stops with the daemon down can't load several models at once
+/// and run the machine out of memory. The flock goes away with the file
+/// descriptor, so a panic or crash can't leave it held.
+pub struct DirectLock {
+    _file: File,
+}
+
+/// The lock file shared by this user's whisp-away processes. It is per user,
+/// in the runtime dir, so nobody else can create it first and hold it.
+pub fn lock_path() -> String {
+    crate::helpers::runtime_file("whisp-away-direct.lock")
+}
+
+/// WA_DIRECT_MAX_WAIT (seconds) or two minutes
+pub fn max_wait() -> Duration {
+    let secs = std::env::var("WA_DIRECT_MAX_WAIT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_WAIT_SECS);
+    Duration::from_secs(secs)
+}
+
+fn try_lock(file: &File) -> Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    if error.kind() == std::io::ErrorKind::WouldBlock {
+        return Ok(false);
+    }
+    Err(error).context("Failed to lock")
+}
+
+/// Open the lock file at `path`, creating it (0600) when nobody has yet.
+/// Symlinks are never followed.
+fn open(path: &str) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file {}", path))
+}
+
+/// Take the lock at `path`, waiting up to `max_wait` for whoever holds it.
+/// `on_queued` runs once if we have to wait.
+pub fn acquire_at(path: &str, max_wait: Duration, on_queued: impl FnOnce()) -> Result<DirectLock> {
+    let file = open(path)?;
+    
+    if try_lock(&file)? {
+        return Ok(DirectLock { _file: file });
+    }
+    
+    eprintln!("DEBUG: Another direct transcription is running, waiting up to {:?}", max_wait);
+    on_queued();
+    let started = Instant::now();
+    while started.elapsed() < max_wait {
+        std::thread::sleep(POLL_INTERVAL);
+        if try_lock(&file)? {
+            eprintln!("DEBUG: Got the transcription lock after {:?}", started.elapsed());
+            return Ok(DirectLock { _file: file });
+        }
+    }
+    Err(anyhow!(
+        "Gave up after {} s queued behind another transcription (WA_DIRECT_MAX_WAIT)",
+        max_wait.as_secs()
+    ))
+}
+
+/// Take the direct transcription lock, telling the user when they are
+/// queued behind another transcription
+pub fn acquire() -> Result<DirectLock> {
+    acquire_at(&lock_path(), max_wait(), || {
+        notify::Notification::transient("Voice Input", "⏳ Queued behind another transcription")
+            .send();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    
+    fn temp_lock(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("whisp-away-test-{}-{}.lock", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+    
+    #[cfg(feature = "mock")]
+    #[test]
+    fn contending_threads_transcribe_one_at_a_time() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+        
+        let path = temp_lock("contend");
+        let audio = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/jfk.wav");
+        let inside = Arc::new(AtomicUsize::new(0));
+        let overlapped = Arc::new(AtomicBool::new(false));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let (path, inside, overlapped, queued) = (path.clone(), inside.clone(), overlapped.clone(), queued.clone());
+                std::thread::spawn(move || {
+                    let _lock = acquire_at(&path, Duration::from_secs(10), || {
+                        queued.fetch_add(1, Ordering::SeqCst);
+                    })
+                    .unwrap();
+                    if inside.fetch_add(1, Ordering::SeqCst) > 0 {
+                        overlapped.store(true, Ordering::SeqCst);
+                    }
+                    let text = crate::mock::direct::transcribe_audio(audio).unwrap();
+                    // Long enough for the other thread to find the lock held
+                    std::thread::sleep(Duration::from_millis(300));
+                    inside.fetch_sub(1, Ordering::SeqCst);
+                    text
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert!(!thread.join().unwrap().is_empty());
+        }
+        assert!(!overlapped.load(Ordering::SeqCst), "both threads held the lock at once");
+        assert_eq!(queued.load(Ordering::SeqCst), 1, "exactly one thread should have queued");
+        let _ = std::fs::remove_file(path);
+    }
+    
+    #[test]
+    fn gives_up_after_max_wait() {
+        let path = temp_lock("give-up");
+        let _held = acquire_at(&path, Duration::ZERO, || {}).unwrap();
+        let started = Instant::now();
+        let waiting = std::thread::spawn({
+            let path = path.clone();
+            move || acquire_at(&path, Duration::from_millis(300), || {}).map(|_| ())
+        });
+        let error = waiting.join().unwrap().unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(error.to_string().contains("WA_DIRECT_MAX_WAIT"), "{}", error);
+        let _ = std::fs::remove_file(path);
+    }
+    
+    #[test]
+    fn released_on_drop() {
+        let path = temp_lock("drop");
+        drop(acquire_at(&path, Duration::ZERO, || {}).unwrap());
+        let queued = AtomicBool::new(false);
+        acquire_at(&path, Duration::ZERO, || queued.store(true, Ordering::SeqCst)).unwrap();
+        assert!(!queued.load(Ordering::SeqCst));
+        let _ = std::fs::remove_file(path);
+    }
+    
+    #[test]
+    fn never_follows_a_symlink() {
+        let path = temp_lock("symlink");
+        let target = temp_lock("symlink-target");
+        std::fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        assert!(acquire_at(&path, Duration::ZERO, || {}).is_err());
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(target);
+    }
+}
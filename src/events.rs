@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+/// What happened, written to the events FIFO as one JSON line for scripts on
+/// setups without DBus
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    RecordingStarted,
+    /// What the daemon the recording will go to is doing: ready, busy,
+    /// starting, offline or no_answer
+    DaemonState { state: String, model: Option<String> },
+    RecordingStopped,
+    TranscriptionStarted,
+    /// The transcript is in, about to be typed or copied
+    TranscriptionDone { chars: usize },
+    /// Something went wrong; `code` is stable for scripts to match on
+    Error { code: String },
+    /// The daemon moved its whisper state to another OpenVINO device (AUTO,
+    /// CPU, or none) after `failures` failed transcriptions in a row
+    DeviceFallback { from: String, to: String, failures: u32 },
+}
+
+/// An event as written: what happened, when and in which process
+#[derive(Debug, Serialize, Deserialize)]
+struct Line {
+    #[serde(flatten)]
+    event: Event,
+    /// Seconds since the epoch
+    time: i64,
+    pid: u32,
+}
+
+/// `$XDG_RUNTIME_DIR/whisp-away/events`
+pub fn fifo_path() -> String {
+    format!("{}/events", crate::daemons::socket_dir_path())
+}
+
+/// Create the FIFO if it isn't there yet. Something else in its place is
+/// left alone, and events aren't written.
+fn ensure_fifo(path: &str) -> Result<()> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => return Ok(()),
+        Ok(_) => return Err(anyhow!("{} exists and isn't a FIFO", path)),
+        Err(_) => {}
+    }
+    crate::daemons::socket_dir();
+    let c_path = CString::new(path)?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        let error = std::io::Error::last_os_error();
+        // Another process may have just made it
+        if error.kind() != ErrorKind::AlreadyExists {
+            return Err(error).with_context(|| format!("Failed to create {}", path));
+        }
+    }
+    Ok(())
+}
+
+/// Write an event for whoever follows the FIFO. With no reader, or one too
+/// slow to keep the pipe from filling, the event is dropped: nothing here
+/// ever waits.
+pub fn emit(event: Event) {
+    if let Err(e) = write(event) {
+        eprintln!("DEBUG: Event not written: {:#}", e);
+    }
+}
+
+fn write(event: Event) -> Result<()> {
+    let path = fifo_path();
+    ensure_fifo(&path)?;
+    
+    let line = Line {
+        event,
+        time: unsafe { libc::time(std::ptr::null_mut()) } as i64,
+        pid: std::process::id(),
+    };
+    let mut bytes = serde_json::to_vec(&line)?;
+    bytes.push(b'\n');
+    // Only writes up to PIPE_BUF are atomic; a longer one could interleave
+    // with another process's event
+    if bytes.len() > libc::PIPE_BUF {
+        return Err(anyhow!("{} bytes is more than a FIFO writes atomically", bytes.len()));
+    }
+    
+    // Without O_NONBLOCK, opening waits for a reader
+    let mut fifo = match OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(&path) {
+        Ok(fifo) => fifo,
+        Err(e) if e.raw_os_error() == Some(libc::ENXIO) => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {}", path)),
+    };
+    // One write(), so the line arrives whole or not at all
+    match fifo.write(&bytes) {
+        Ok(written) if written == bytes.len() => Ok(()),
+        Ok(written) => Err(anyhow!("Only {} of {} bytes written", written, bytes.len())),
+        // The reader went away, or isn't keeping up
+        Err(e) if e.kind() == ErrorKind::BrokenPipe || e.kind() == ErrorKind::WouldBlock => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// One event as `events` prints it
+fn pretty(line: &Line) -> String {
+    let time = crate::helpers::format_local_time(line.time as libc::time_t);
+    let what = match &line.event {
+        Event::RecordingStarted => "recording started".to_string(),
+        Event::DaemonState { state, model: Some(model) } => format!("daemon {} ({})", state, model),
+        Event::DaemonState { state, model: None } => format!("daemon {}", state),
+        Event::RecordingStopped => "recording stopped".to_string(),
+        Event::TranscriptionStarted => "transcription started".to_string(),
+        Event::TranscriptionDone { chars } => format!("transcription done, {} chars", chars),
+        Event::Error { code } => format!("error: {}", code),
+        Event::DeviceFallback { from, to, failures } => format!("device {} -> {} after {} failures", from, to, failures),
+    };
+    format!("{}  {:<7}  {}", time, line.pid, what)
+}
+
+/// Print events as they are written. Without `follow` it stops once every
+/// writer has closed the FIFO; with it, it waits for more. `raw` prints the
+/// JSON lines as they are.
+pub fn run(follow: bool, raw: bool) -> Result<()> {
+    let path = fifo_path();
+    ensure_fifo(&path)?;
+    eprintln!("DEBUG: Reading events from {}", path);
+    
+    // Holding the write end too means the FIFO never reads as closed
+    let fifo = OpenOptions::new()
+        .read(true)
+        .write(follow)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path))?;
+    for line in BufReader::new(fifo).lines() {
+        let line = line?;
+        if raw {
+            println!("{}", line);
+            continue;
+        }
+        match serde_json::from_str::<Line>(&line) {
+            Ok(parsed) => println!("{}", pretty(&parsed)),
+            Err(_) => println!("{}", line),
+        }
+    }
+    Ok(())
+}
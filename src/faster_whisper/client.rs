@@ -1,89 +1,30 @@
 use anyhow::Result;
-use std::fs;
-use std::process::Command;
-use crate::recording;
-use crate::socket;
+use crate::client::{self, StopOptions, TranscriptionBackend};
+use crate::run::Run;
+use crate::typing;
 use super::direct::transcribe_with_faster_whisper;
 
-pub fn stop_and_transcribe_daemon(wtype_path: &str, socket_path: &str) -> Result<()> {
-    let audio_file = match recording::stop_recording(None)? {
-        Some(path) => path,
-        None => {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input (daemon)",
-                    "❌ No recording found",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            return Ok(());
-        }
-    };
+/// faster-whisper, falling back to a one-off Python run
+pub struct FasterWhisper;
 
-    let audio_path = std::path::Path::new(&audio_file);
-    if !audio_path.exists() {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    "❌ No audio recorded\nBackend: faster-whisper",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-        return Ok(());
+impl TranscriptionBackend for FasterWhisper {
+    fn name(&self) -> &'static str {
+        "faster-whisper"
     }
     
-    if let Ok(metadata) = fs::metadata(&audio_file) {
-        if metadata.len() <= 44 {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    "❌ Audio file is empty\nBackend: faster-whisper",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            let _ = fs::remove_file(&audio_file);
-            return Ok(());
-        }
+    fn daemon_summary(&self) -> &'static str {
+        "Voice Input (daemon)"
     }
-
-    // Get model for notification
-    let model = crate::helpers::resolve_model(None);
-    let acceleration = crate::helpers::get_acceleration_type();
-    let transcribe_msg = format!("⏳ Transcribing...\nBackend: faster-whisper ({}) | Model: {}", acceleration, model);
     
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
-
-    match socket::send_transcription_request(socket_path, &audio_file, wtype_path, "faster-whisper") {
-        Ok(_) => {
-            let _ = fs::remove_file(&audio_file);
-        }
-        Err(e) => {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input (daemon)",
-                    "⚠️ Daemon not running, using direct mode",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            
-            let result = transcribe_with_faster_whisper(&audio_file, "base.en", wtype_path);
-            
-            let _ = fs::remove_file(&audio_file);
-            
-            return result.map_err(|err| anyhow::anyhow!("Fallback transcription failed (daemon was: {}): {}", e, err));
-        }
+    fn fallback_notice(&self, reason: &str, _model: &str) -> (String, String) {
+        (self.daemon_summary().to_string(), format!("⚠️ {}, using direct mode", reason))
     }
 
-    Ok(())
-}
\ No newline at end of file
+    fn transcribe_direct(&self, audio_file: &str, model: &str, prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+        transcribe_with_faster_whisper(audio_file, model, prompt_file, delivery, run)
+    }
+}
+    
+pub fn stop_and_transcribe_daemon(options: &StopOptions, run: &mut Run) -> Result<()> {
+    client::stop_and_transcribe(&FasterWhisper, options, run)
+}
@@ -1,7 +1,6 @@
 use anyhow::{Context, Result};
-use std::process::Command;
 
-pub fn run_daemon(model: &str, socket_path: &str) -> Result<()> {
+pub fn run_daemon(model: &str, socket_path: &str, language: &str, ready_file: &str) -> Result<()> {
     // Get Python interpreter and script paths from environment
     let python_path = std::env::var("FASTER_WHISPER_PYTHON")
         .context("FASTER_WHISPER_PYTHON not set")?;
@@ -15,12 +14,32 @@ pub fn run_daemon(model: &str, socket_path: &str) -> Result<()> {
         return Err(anyhow::anyhow!("whisper_daemon.py not found at {}", script_path));
     }
     
+    // A broken environment otherwise shows only after seconds of imports,
+    // and then only in the daemon's log
+    if let Err(e) = super::environment::check(&python_path, &pythonpath) {
+        crate::notify::Notification::error("Voice Input (faster-whisper)", &format!("❌ Python environment broken
+{:#}", e))
+            .timeout(10000)
+            .send();
+        return Err(e);
+    }
+    
+    // Fetch a missing model here, with progress, rather than inside Python
+    let model_path = super::model::ensure(model)?;
+    // Swept from here while Python deletes what it transcribes
+    let inbox = crate::inbox::open(ready_file)?;
+    
     // Run Python with injected environment
-    let status = Command::new(&python_path)
+    let status = crate::child_env::command(&python_path, crate::child_env::MODEL_ENV)
         .arg(&script_path)
         .env("PYTHONPATH", &pythonpath)
         .env("WA_WHISPER_MODEL", model)
+        .env("WA_WHISPER_MODEL_PATH", &model_path)
         .env("WA_WHISPER_SOCKET", socket_path)
+        .env("WA_WHISPER_LANGUAGE", language)
+        .env("WA_READY_FILE", ready_file)
+        .env("WA_DAEMON_VERSION", crate::upgrade::VERSION)
+        .env("WA_INBOX", &inbox)
         // Pass through CUDA environment if present
         .env("CUDA_VISIBLE_DEVICES", std::env::var("CUDA_VISIBLE_DEVICES").unwrap_or_default())
         .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default())
@@ -1,21 +1,11 @@
 use anyhow::{Context, Result};
-use std::process::Command;
+use crate::notify;
+use crate::run::Run;
 use crate::typing;
 
-/// Transcribe audio with faster-whisper and type the result
-pub fn transcribe_with_faster_whisper(audio_file: &str, model: &str, wtype_path: &str) -> Result<()> {
-    let acceleration = crate::helpers::get_acceleration_type();
-    let transcribe_msg = format!("⏳ Transcribing... ({})", acceleration);
-    
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input (faster-whisper)",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
-
+/// Run the transcribe_faster.py script on a file.
+/// Returns the text and the language faster-whisper detected, if reported.
+pub fn transcribe_audio(audio_file: &str, model: &str, language: &str, prompt: Option<&str>) -> Result<(String, Option<String>)> {
     let python_path = std::env::var("FASTER_WHISPER_PYTHON")
         .unwrap_or_else(|_| "python3".to_string());
     let pythonpath = std::env::var("FASTER_WHISPER_PYTHONPATH")
@@ -23,32 +13,59 @@ pub fn transcribe_with_faster_whisper(audio_file: &str, model: &str, wtype_path:
     let script_path = std::env::var("FASTER_WHISPER_SCRIPT")
         .unwrap_or_else(|_| "/run/current-system/sw/bin/transcribe_faster.py".to_string());
     
-    let output = Command::new(&python_path)
+    // One model load at a time across the machine, which also keeps two
+    // stops from downloading the same model at once
+    let _lock = crate::direct_lock::acquire()?;
+    let model_path = super::model::ensure(model)?;
+    // The same decoding options a daemon request would carry
+    let parameters = serde_json::to_string(&crate::protocol::DecodeParameters::configured())?;
+    
+    let output = crate::child_env::command(&python_path, crate::child_env::MODEL_ENV)
         .arg(&script_path)
-        .args(&[audio_file, model])
+        .args([audio_file, model, language])
         .env("PYTHONPATH", &pythonpath)
+        .env("WA_WHISPER_MODEL_PATH", &model_path)
+        .env("WA_WHISPER_PROMPT", prompt.unwrap_or_default())
+        .env("WA_DECODE_PARAMETERS", &parameters)
         .env("CUDA_VISIBLE_DEVICES", std::env::var("CUDA_VISIBLE_DEVICES").unwrap_or_default())
         .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default())
         .output()
         .context("Failed to run faster-whisper transcription")?;
     
-    let transcribed_text = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("Transcription failed: {}", stderr.trim()));
+    }
 
-    if output.status.success() {
-        let clean_text = transcribed_text.trim();
+    // The script reports the detected language as "language=xx" on stderr
+    let detected = stderr.lines()
+        .find_map(|line| line.strip_prefix("language="))
+        .map(str::to_string);
         
-        typing::type_text(&clean_text, wtype_path, "faster-whisper")?;
-    } else {
-        Command::new("notify-send")
-            .args(&[
-                "Voice Input (faster-whisper)",
-                "❌ Transcription failed",
-                "-t", "2000",
-                "-h", "string:x-canonical-private-synchronous:voice"
-            ])
-            .spawn()?;
-        return Err(anyhow::anyhow!("Transcription failed"));
+    Ok((crate::artifacts::strip(&String::from_utf8_lossy(&output.stdout)), detected))
+}
+        
+/// Transcribe audio with faster-whisper and type the result
+pub fn transcribe_with_faster_whisper(audio_file: &str, model: &str, prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+    let acceleration = crate::helpers::get_acceleration_type();
+    let transcribe_msg = format!("⏳ Transcribing... ({})", acceleration);
+    
+    notify::Notification::transient("Voice Input (faster-whisper)", &transcribe_msg)
+        .send();
+    
+    run.prompt = crate::prompt::current(prompt_file, delivery.profile.as_deref());
+    match transcribe_audio(audio_file, model, &run.language, run.prompt.as_deref()) {
+        Ok((clean_text, detected)) => {
+            let label = format!("faster-whisper{}", crate::helpers::language_note(&run.language, detected.as_deref()));
+            run.detected_language = detected;
+            typing::type_text(&clean_text, delivery, run, &label)?;
+        }
+        Err(e) => {
+            notify::Notification::error("Voice Input (faster-whisper)", "❌ Transcription failed")
+                .send();
+            return Err(e);
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
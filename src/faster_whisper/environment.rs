@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use crate::helpers::{fnv1a, FNV1A_OFFSET};
+
+/// What the daemon and the transcribe script need from Python
+const IMPORTS: &str = "import faster_whisper, ctranslate2";
+
+/// Which Python environment a passed check is for: it is checked again once
+/// the interpreter, PYTHONPATH or what is installed in its site-packages changes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Environment {
+    python: String,
+    python_modified: u64,
+    /// FNV-1a of PYTHONPATH
+    pythonpath: String,
+    /// FNV-1a of the site-packages dirs on PYTHONPATH and next to the interpreter
+    /// with their modification times, which installing or removing a package changes
+    site_packages: String,
+}
+
+/// How a check went
+pub enum Checked {
+    /// Passed before in this same environment
+    Cached,
+    /// Imported fine, taking this long
+    Passed(Duration),
+}
+
+fn cache_path() -> String {
+    format!("{}/whisp-away/python-check.json", crate::helpers::cache_dir())
+}
+
+/// Whether a passed check is remembered (WA_PYTHON_CHECK_CACHE=0 checks every time)
+fn cache_enabled() -> bool {
+    !matches!(std::env::var("WA_PYTHON_CHECK_CACHE").as_deref(), Ok("0") | Ok("false"))
+}
+
+/// How long the imports may take before the environment counts as broken
+/// (WA_PYTHON_CHECK_TIMEOUT seconds, default 60: torch is slow from a cold disk)
+fn timeout() -> Duration {
+    let seconds = std::env::var("WA_PYTHON_CHECK_TIMEOUT").ok().and_then(|s| s.parse().ok()).unwrap_or(60);
+    Duration::from_secs(seconds)
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    Some(fs::metadata(path).ok()?.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// The interpreter `python` runs, looked up in PATH unless it is a path
+fn locate(python: &str) -> Option<PathBuf> {
+    if python.contains('/') {
+        return Some(PathBuf::from(python));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(python))
+        .find(|candidate| candidate.is_file())
+}
+
+/// `lib/python3.*/site-packages` under the prefix `interpreter` is in, for
+/// venvs and installs that don't go through PYTHONPATH
+fn prefix_site_packages(interpreter: &Path) -> Vec<PathBuf> {
+    let Some(lib) = interpreter.parent().and_then(Path::parent).map(|prefix| prefix.join("lib")) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&lib) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("python"))
+        .map(|entry| entry.path().join("site-packages"))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+fn identify(python: &str, pythonpath: &str) -> Option<Environment> {
+    let located = locate(python)?;
+    let canonical = fs::canonicalize(&located).ok()?;
+    let mut site_packages: Vec<PathBuf> = std::env::split_paths(pythonpath).filter(|dir| !dir.as_os_str().is_empty()).collect();
+    // A venv's interpreter is a symlink out of it, so both prefixes count
+    site_packages.extend(prefix_site_packages(&located));
+    site_packages.extend(prefix_site_packages(&canonical));
+    let fingerprint: Vec<(&PathBuf, Option<u64>)> = site_packages.iter().map(|dir| (dir, modified(dir))).collect();
+    Some(Environment {
+        python: canonical.to_string_lossy().into_owned(),
+        python_modified: modified(&canonical)?,
+        pythonpath: format!("{:016x}", fnv1a(FNV1A_OFFSET, pythonpath.as_bytes())),
+        site_packages: format!("{:016x}", fnv1a(FNV1A_OFFSET, format!("{:?}", fingerprint).as_bytes())),
+    })
+}
+
+fn read_cache() -> Vec<Environment> {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn remember(environment: Environment) {
+    let mut passed = read_cache();
+    // A changed environment takes its old entry's place
+    passed.retain(|known| (&known.python, &known.pythonpath) != (&environment.python, &environment.pythonpath));
+    passed.push(environment);
+    let path = cache_path();
+    if let Some(dir) = Path::new(&path).parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&passed) {
+        let _ = fs::write(&path, json);
+    }
+}
+
+/// Import faster_whisper and ctranslate2 with `python` and `pythonpath`,
+/// unless that already worked in the same environment. Takes seconds where
+/// the daemon would take them anyway, and fails with Python's own error
+/// (the ImportError, usually) rather than leaving it in a log.
+pub fn check(python: &str, pythonpath: &str) -> Result<Checked> {
+    let environment = identify(python, pythonpath);
+    if cache_enabled() && environment.as_ref().is_some_and(|environment| read_cache().contains(environment)) {
+        eprintln!("DEBUG: Python environment of {} checked before, not importing again", python);
+        return Ok(Checked::Cached);
+    }
+    
+    let took = import(python, pythonpath)?;
+    eprintln!("DEBUG: {} imported faster_whisper in {:?}", python, took);
+    if let Some(environment) = environment.filter(|_| cache_enabled()) {
+        remember(environment);
+    }
+    Ok(Checked::Passed(took))
+}
+
+fn import(python: &str, pythonpath: &str) -> Result<Duration> {
+    let started = Instant::now();
+    let mut child = crate::child_env::command(python, crate::child_env::MODEL_ENV)
+        .args(["-c", IMPORTS])
+        .env("PYTHONPATH", pythonpath)
+        .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", python))?;
+    // Read on the side, so a long traceback can't fill the pipe and stall it
+    let mut stderr = child.stderr.take().context("Python has no stderr")?;
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+    
+    let deadline = started + timeout();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("{} took over {:?} to import faster_whisper", python, timeout()));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    if status.success() {
+        return Ok(started.elapsed());
+    }
+    
+    let output = reader.join().unwrap_or_default();
+    eprintln!("DEBUG: {} failed to import faster_whisper:\n{}", python, output.trim_end());
+    // The traceback ends with the error itself
+    let error = output.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("no error output");
+    Err(anyhow!("{} can't import faster_whisper ({}): {}", python, status, error.trim()))
+}
@@ -1,6 +1,8 @@
 pub mod client;
 pub mod daemon;
 pub mod direct;
+pub mod environment;
+pub mod model;
 
 pub use client::stop_and_transcribe_daemon;
 pub use daemon::run_daemon;
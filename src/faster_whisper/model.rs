@@ -0,0 +1,246 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use crate::notify;
+
+/// Files faster-whisper needs from a CTranslate2 model repo; the rest (README,
+/// .gitattributes, ...) are skipped like huggingface_hub's allow_patterns does
+const MODEL_FILES: &[&str] = &["config.json", "preprocessor_config.json", "model.bin", "tokenizer.json"];
+
+/// Written into a model directory once every file is downloaded and verified
+const VERIFIED_MARKER: &str = ".verified";
+
+/// How often the progress notification is refreshed while downloading
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One file of a model repo as listed by the Hugging Face API
+#[derive(Debug, Deserialize)]
+struct RemoteFile {
+    path: String,
+    #[serde(default)]
+    size: u64,
+    /// Present for LFS files, which is where the large weights live
+    lfs: Option<LfsInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LfsInfo {
+    /// sha256 of the file contents
+    oid: String,
+}
+
+impl RemoteFile {
+    fn wanted(&self) -> bool {
+        MODEL_FILES.contains(&self.path.as_str()) || self.path.starts_with("vocabulary.")
+    }
+}
+
+/// Whether missing models are downloaded (not with WA_NO_DOWNLOAD=1 or HF_HUB_OFFLINE=1)
+pub fn download_allowed() -> bool {
+    let set = |name| matches!(std::env::var(name).as_deref(), Ok("1") | Ok("true"));
+    !set("WA_NO_DOWNLOAD") && !set("HF_HUB_OFFLINE")
+}
+
+fn endpoint() -> String {
+    std::env::var("HF_ENDPOINT")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "https://huggingface.co".to_string())
+}
+
+/// Hugging Face repo a model name refers to, the same mapping faster-whisper uses.
+/// Anything with a slash is taken to be a repo id already.
+pub fn repo_id(model: &str) -> String {
+    if model.contains('/') {
+        return model.to_string();
+    }
+    match model {
+        "large-v3-turbo" | "turbo" => "mobiuslabsgmbh/faster-whisper-large-v3-turbo".to_string(),
+        _ => match model.strip_prefix("distil-") {
+            Some(rest) => format!("Systran/faster-distil-whisper-{}", rest),
+            None => format!("Systran/faster-whisper-{}", model),
+        },
+    }
+}
+
+/// Directory models are kept in, shared with the Python scripts' download_root
+pub fn models_dir() -> String {
+    crate::helpers::find_in_cache("faster-whisper")
+}
+
+/// Where whisp-away downloads `model` to
+pub fn model_dir(model: &str) -> String {
+    format!("{}/{}", models_dir(), repo_id(model).replace('/', "--"))
+}
+
+/// A complete copy of `model` on disk: one whisp-away downloaded and verified,
+/// or a snapshot huggingface_hub left in the same cache dir
+pub fn cached_path(model: &str) -> Option<String> {
+    let dir = model_dir(model);
+    if Path::new(&dir).join(VERIFIED_MARKER).exists() {
+        return Some(dir);
+    }
+    
+    let snapshots = format!("{}/models--{}/snapshots", models_dir(), repo_id(model).replace('/', "--"));
+    fs::read_dir(snapshots)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.join("model.bin").exists() && path.join("config.json").exists())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+fn list_files(repo: &str) -> Result<Vec<RemoteFile>> {
+    let url = format!("{}/api/models/{}/tree/main", endpoint(), repo);
+    let output = crate::child_env::command("curl", crate::child_env::NETWORK_ENV)
+        .args(["-fsSL", "--retry", "3", &url])
+        .output()
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        return Err(anyhow!("Listing {} failed: {}", repo, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let files: Vec<RemoteFile> = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Unexpected file list for {}", repo))?;
+    Ok(files.into_iter().filter(RemoteFile::wanted).collect())
+}
+
+fn sha256(path: &str) -> Result<String> {
+    let output = crate::child_env::command("sha256sum", &[])
+        .arg(path)
+        .output()
+        .context("Failed to run sha256sum")?;
+    if !output.status.success() {
+        return Err(anyhow!("Hashing {} failed", path));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Hashing {} failed", path))
+}
+
+fn file_size(path: &str) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+/// Check a downloaded file against the size and, for LFS files, the sha256 the API listed
+fn verify(path: &str, file: &RemoteFile) -> Result<()> {
+    let size = file_size(path);
+    if size != file.size {
+        return Err(anyhow!("{} is {} bytes, expected {}", file.path, size, file.size));
+    }
+    if let Some(lfs) = &file.lfs {
+        let hash = sha256(path)?;
+        if hash != lfs.oid {
+            return Err(anyhow!("{} has sha256 {}, expected {}", file.path, hash, lfs.oid));
+        }
+    }
+    Ok(())
+}
+
+fn show_progress(model: &str, done: u64, total: u64) {
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    let body = format!("⬇️ Downloading {} ({}%, {} of {} MB)", model, percent, done / 1_000_000, total / 1_000_000);
+    notify::Notification::progress("Voice Input (faster-whisper)", &body).send();
+}
+
+/// Download one file into `dest`, resuming a `.part` left by an earlier attempt.
+/// `before` is what the other files already contributed to the progress.
+fn fetch(repo: &str, model: &str, file: &RemoteFile, dest: &str, before: u64, total: u64) -> Result<()> {
+    let part = format!("{}.part", dest);
+    // A partial download larger than the file can't be resumed
+    if file_size(&part) > file.size {
+        fs::remove_file(&part).ok();
+    }
+    
+    let url = format!("{}/{}/resolve/main/{}", endpoint(), repo, file.path);
+    let mut child = crate::child_env::command("curl", crate::child_env::NETWORK_ENV)
+        .args(["-fsSL", "--retry", "3", "-C", "-", "-o", &part, &url])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("Failed to run curl")?;
+    
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        show_progress(model, before + file_size(&part), total);
+        std::thread::sleep(PROGRESS_INTERVAL);
+    };
+    if !status.success() {
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            std::io::Read::read_to_string(&mut pipe, &mut stderr).ok();
+        }
+        return Err(anyhow!("Downloading {} failed: {}", file.path, stderr.trim()));
+    }
+    
+    let size = file_size(&part);
+    if size < file.size {
+        // Kept so the next attempt resumes it
+        return Err(anyhow!("Download of {} stopped at {} of {} bytes", file.path, size, file.size));
+    }
+    if let Err(e) = verify(&part, file) {
+        // Corrupt rather than short: start this file over next time
+        fs::remove_file(&part).ok();
+        return Err(e);
+    }
+    fs::rename(&part, dest).with_context(|| format!("Failed to move {} into place", dest))?;
+    Ok(())
+}
+
+/// Download `model` into its directory, verifying every file. Files already
+/// there and intact are kept, so an interrupted download picks up where it stopped.
+pub fn download(model: &str) -> Result<String> {
+    let repo = repo_id(model);
+    let dir = model_dir(model);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir))?;
+    
+    let files = list_files(&repo)?;
+    if !files.iter().any(|file| file.path == "model.bin") {
+        return Err(anyhow!("{} has no model.bin; is {} a faster-whisper model?", repo, model));
+    }
+    let total: u64 = files.iter().map(|file| file.size).sum();
+    eprintln!("DEBUG: Downloading {} ({} files, {} bytes) to {}", repo, files.len(), total, dir);
+    
+    let mut done = 0;
+    for file in &files {
+        let dest = format!("{}/{}", dir, file.path);
+        if verify(&dest, file).is_err() {
+            fs::remove_file(&dest).ok();
+            fetch(&repo, model, file, &dest, done, total)?;
+        }
+        done += file.size;
+    }
+    
+    fs::write(format!("{}/{}", dir, VERIFIED_MARKER), "").ok();
+    notify::Notification::normal("Voice Input (faster-whisper)", &format!("✅ Downloaded {}", model))
+        .send();
+    Ok(dir)
+}
+
+/// Make sure `model` is on disk before Python loads it and return the path to
+/// hand it. Local paths are used as they are; a missing model is downloaded
+/// here with progress, or refused when downloads are turned off.
+pub fn ensure(model: &str) -> Result<String> {
+    if Path::new(model).exists() {
+        return Ok(model.to_string());
+    }
+    if let Some(path) = cached_path(model) {
+        eprintln!("DEBUG: Using cached faster-whisper model {}", path);
+        return Ok(path);
+    }
+    if !download_allowed() {
+        return Err(anyhow!(
+            "Model {} is not in {} and downloads are off (--no-download / WA_NO_DOWNLOAD)",
+            model,
+            models_dir()
+        ));
+    }
+    
+    download(model).inspect_err(|e| {
+        notify::Notification::error("Voice Input (faster-whisper)", &format!("❌ Downloading {} failed\n{}", model, e))
+            .send();
+    })
+}
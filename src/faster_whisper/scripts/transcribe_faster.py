@@ -6,15 +6,64 @@ Used by voice-input-rust for the start-faster/stop-faster commands.
 
 import sys
 import os
+import json
 from faster_whisper import WhisperModel
 
+# [-1] suppresses non-speech tokens (music notes, bracketed annotations);
+# WA_SUPPRESS_NON_SPEECH=0 turns it off, matching the whisper.cpp backend
+SUPPRESS_TOKENS = [] if os.environ.get("WA_SUPPRESS_NON_SPEECH") in ("0", "false") else [-1]
+
+# Decoding options WA_DECODE_PARAMETERS (JSON, as in a daemon request) may
+# override, with the values used otherwise; other keys in it are ignored
+DECODE_DEFAULTS = {
+    "beam_size": 5,
+    "vad_filter": True,
+    "word_timestamps": False,
+    "condition_on_previous_text": True,
+}
+
+def decode_options():
+    """DECODE_DEFAULTS with WA_DECODE_PARAMETERS applied, leaving out the
+    options not known here."""
+    options = dict(DECODE_DEFAULTS)
+    try:
+        parameters = json.loads(os.environ.get('WA_DECODE_PARAMETERS') or '{}')
+    except json.JSONDecodeError as e:
+        print(f"Ignoring WA_DECODE_PARAMETERS: {e}", file=sys.stderr)
+        return options
+    if not isinstance(parameters, dict):
+        return options
+    for key, value in parameters.items():
+        if key in options:
+            options[key] = value
+        else:
+            print(f"Ignoring unknown parameter {key}", file=sys.stderr)
+    return options
+
+def model_cache_dir():
+    """Model cache directory, honoring XDG_CACHE_HOME.
+
+    Falls back to the legacy ~/.cache location when only it exists, so models
+    downloaded before XDG support keep being found.
+    """
+    cache_home = os.environ.get("XDG_CACHE_HOME")
+    if not cache_home or not os.path.isabs(cache_home):
+        cache_home = os.path.expanduser("~/.cache")
+    path = os.path.join(cache_home, "faster-whisper")
+    legacy = os.path.expanduser("~/.cache/faster-whisper")
+    if not os.path.exists(path) and os.path.exists(legacy):
+        return legacy
+    return path
+
 def main():
     if len(sys.argv) < 3:
-        print("Usage: transcribe_faster.py <audio_file> <model>", file=sys.stderr)
+        print("Usage: transcribe_faster.py <audio_file> <model> [language]", file=sys.stderr)
         sys.exit(1)
     
     audio_file = sys.argv[1]
     model_name = sys.argv[2]
+    language = sys.argv[3] if len(sys.argv) > 3 else os.environ.get('WA_WHISPER_LANGUAGE') or 'en'
+
     
     # Check if audio file exists
     if not os.path.exists(audio_file):
@@ -38,12 +87,13 @@ def main():
         compute_type = 'float16' if device == 'cuda' else 'int8'
     
     # Load model
-    cache_dir = os.path.expanduser('~/.cache/faster-whisper')
+    cache_dir = model_cache_dir()
     os.makedirs(cache_dir, exist_ok=True)
     
     try:
+        # The Rust side downloads the model and passes where it put it
         model = WhisperModel(
-            model_name,
+            os.environ.get('WA_WHISPER_MODEL_PATH') or model_name,
             device=device,
             compute_type=compute_type,
             download_root=cache_dir
@@ -52,14 +102,17 @@ def main():
         # Transcribe
         segments, info = model.transcribe(
             audio_file,
-            language='en',
-            beam_size=5,
-            vad_filter=True,
+            language=None if language == 'auto' else language,
+            initial_prompt=os.environ.get('WA_WHISPER_PROMPT') or None,
+            suppress_tokens=SUPPRESS_TOKENS,
+            **decode_options(),
             vad_parameters=dict(min_silence_duration_ms=500)
         )
         
         # Output transcribed text
         text = ' '.join(segment.text.strip() for segment in segments)
+        # Report the detected language on stderr so stdout stays pure text
+        print(f"language={info.language}", file=sys.stderr)
         if text:
             print(text)
     except Exception as e:
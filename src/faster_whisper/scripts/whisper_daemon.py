@@ -8,11 +8,35 @@ import sys
 import os
 import socket
 import json
+import math
 import signal
 import logging
 from pathlib import Path
 from faster_whisper import WhisperModel
 
+# Backend name this daemon answers to in requests and responses
+BACKEND = "faster-whisper"
+
+# Protocol versions spoken, and what is offered in the hello exchange;
+# must match src/protocol.rs
+PROTOCOL_VERSION = 1
+MIN_PROTOCOL_VERSION = 1
+CAPABILITIES = ["transcribe", "detect_language", "initial_prompt", "segments"]
+
+# [-1] suppresses non-speech tokens (music notes, bracketed annotations);
+# WA_SUPPRESS_NON_SPEECH=0 turns it off, matching the whisper.cpp backend
+SUPPRESS_TOKENS = [] if os.environ.get("WA_SUPPRESS_NON_SPEECH") in ("0", "false") else [-1]
+
+# Decoding options a request's "parameters" may override, with the values
+# used otherwise; anything else in "parameters" is ignored, so a newer client
+# can send options this script doesn't know
+DECODE_DEFAULTS = {
+    "beam_size": 5,
+    "vad_filter": True,
+    "word_timestamps": False,
+    "condition_on_previous_text": True,
+}
+
 # Configure logging
 logging.basicConfig(
     level=logging.INFO,
@@ -20,10 +44,110 @@ logging.basicConfig(
 )
 logger = logging.getLogger(__name__)
 
+def version_range(low, high):
+    return str(low) if low == high else f"{low}-{high}"
+
+def version_field():
+    """whisp-away version of the binary that started us, for hello and the ready file."""
+    version = os.environ.get("WA_DAEMON_VERSION")
+    return {"version": version} if version else {}
+
+def inbox_field():
+    """Directory the Rust wrapper made for client recordings, for hello and the ready file."""
+    inbox = os.environ.get("WA_INBOX")
+    return {"inbox": inbox} if inbox else {}
+
+def release_managed(request):
+    """Delete a request's audio once answered, when the client recorded it
+    into our inbox and left deleting it to us."""
+    inbox = os.environ.get("WA_INBOX")
+    audio_path = request.get("audio_path")
+    if not inbox or not request.get("managed") or not audio_path:
+        return
+    if os.path.dirname(audio_path) != inbox:
+        logger.warning(f"Not deleting {audio_path}: managed requests must be in {inbox}")
+        return
+    try:
+        os.unlink(audio_path)
+    except OSError:
+        pass
+
+def negotiate(hello):
+    """Daemon side of the hello exchange: (reply, None) or (None, error response)."""
+    client_version = hello.get("protocol_version", 0)
+    client_min = hello.get("min_protocol_version", client_version)
+    version = min(client_version, PROTOCOL_VERSION)
+    if version < max(client_min, MIN_PROTOCOL_VERSION):
+        return None, {
+            "success": False,
+            "error": f"Client speaks protocol {version_range(client_min, client_version)} "
+                     f"but the daemon speaks {version_range(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)}",
+            "error_code": "unsupported_protocol",
+        }
+    wanted = hello.get("capabilities", [])
+    return {
+        "protocol_version": version,
+        "min_protocol_version": MIN_PROTOCOL_VERSION,
+        "capabilities": [c for c in CAPABILITIES if c in wanted],
+        "backend": BACKEND,
+        **version_field(),
+        **inbox_field(),
+    }, None
+
+def read_message(conn, buffer):
+    """Next message and the bytes after it: a newline-terminated frame, or a
+    bare JSON request from a client that predates framing. The message is
+    None if the client hung up first."""
+    while True:
+        if b"\n" in buffer:
+            line, _, rest = buffer.partition(b"\n")
+            return json.loads(line), rest
+        if buffer:
+            try:
+                return json.loads(buffer), b""
+            except json.JSONDecodeError:
+                pass
+        chunk = conn.recv(4096)
+        if not chunk:
+            return None, buffer
+        buffer += chunk
+
+def decode_options(parameters):
+    """DECODE_DEFAULTS with a request's parameters applied, leaving out the
+    ones not known here."""
+    options = dict(DECODE_DEFAULTS)
+    if not isinstance(parameters, dict):
+        return options
+    for key, value in parameters.items():
+        if key in options:
+            options[key] = value
+        else:
+            logger.warning(f"Ignoring unknown parameter {key}")
+    return options
+
+def send_message(conn, message):
+    conn.sendall(json.dumps(message).encode('utf-8') + b"\n")
+
+def model_cache_dir():
+    """Model cache directory, honoring XDG_CACHE_HOME.
+
+    Falls back to the legacy ~/.cache location when only it exists, so models
+    downloaded before XDG support keep being found.
+    """
+    cache_home = os.environ.get("XDG_CACHE_HOME")
+    if not cache_home or not os.path.isabs(cache_home):
+        cache_home = os.path.expanduser("~/.cache")
+    path = os.path.join(cache_home, "faster-whisper")
+    legacy = os.path.expanduser("~/.cache/faster-whisper")
+    if not os.path.exists(path) and os.path.exists(legacy):
+        return legacy
+    return path
+
 class WhisperDaemon:
-    def __init__(self, model_name="medium.en", socket_path="/tmp/whisp-away-daemon.sock"):
+    def __init__(self, model_name="medium.en", socket_path=None, language="en"):
         self.model_name = model_name
-        self.socket_path = socket_path
+        self.socket_path = socket_path or default_socket_path()
+        self.language = language
         self.model = None
         self.server_socket = None
         self.running = True
@@ -38,8 +162,35 @@ class WhisperDaemon:
         self.running = False
         if self.server_socket:
             self.server_socket.close()
+        self.remove_ready_file()
         sys.exit(0)
         
+    def write_ready_file(self):
+        """Write pid, socket and model as JSON to WA_READY_FILE once serving."""
+        ready_file = os.environ.get("WA_READY_FILE")
+        if not ready_file:
+            return
+        info = {
+            "pid": os.getpid(),
+            "backend": BACKEND,
+            "socket_path": self.socket_path,
+            "model": self.model_name,
+            "language": self.language,
+            **version_field(),
+            **inbox_field(),
+        }
+        # Write then rename so readers never see half a file
+        tmp = ready_file + ".tmp"
+        with open(tmp, "w") as f:
+            json.dump(info, f, indent=2)
+        os.replace(tmp, ready_file)
+        logger.info(f"Ready file written to {ready_file}")
+        
+    def remove_ready_file(self):
+        ready_file = os.environ.get("WA_READY_FILE")
+        if ready_file and os.path.exists(ready_file):
+            os.unlink(ready_file)
+        
     def load_model(self):
         """Load the Whisper model into memory."""
         logger.info(f"Loading model {self.model_name}...")
@@ -50,12 +201,13 @@ class WhisperDaemon:
         compute_type = os.environ.get("WHISPER_COMPUTE", "int8_float16" if device == "cuda" else "int8")
         
         # Model cache directory
-        cache_dir = os.path.expanduser("~/.cache/faster-whisper")
+        cache_dir = model_cache_dir()
         os.makedirs(cache_dir, exist_ok=True)
         
         try:
+            # The Rust wrapper downloads the model and passes where it put it
             self.model = WhisperModel(
-                self.model_name,
+                os.environ.get("WA_WHISPER_MODEL_PATH") or self.model_name,
                 device=device,
                 compute_type=compute_type,
                 download_root=cache_dir,
@@ -66,16 +218,20 @@ class WhisperDaemon:
             logger.error(f"Failed to load model: {e}")
             sys.exit(1)
             
-    def transcribe(self, audio_path):
-        """Transcribe an audio file."""
+    def transcribe(self, audio_path, language=None, initial_prompt=None, with_segments=False, parameters=None):
+        """Transcribe an audio file. language "auto" lets whisper detect it.
+        with_segments adds each segment's timing and confidence. parameters
+        override DECODE_DEFAULTS."""
+        language = language or self.language
         try:
             segments, info = self.model.transcribe(
                 audio_path,
-                language="en",
-                beam_size=5,
+                language=None if language == "auto" else language,
                 best_of=5,
                 temperature=0.0,
-                vad_filter=True,
+                initial_prompt=initial_prompt,
+                suppress_tokens=SUPPRESS_TOKENS,
+                **decode_options(parameters),
                 vad_parameters=dict(
                     min_silence_duration_ms=300,  # Reduced for snappier detection
                     speech_pad_ms=100,  # Reduced padding
@@ -84,8 +240,22 @@ class WhisperDaemon:
             )
             
             # Collect text
+            segments = list(segments)
             text = " ".join(segment.text.strip() for segment in segments)
-            return {"success": True, "text": text}
+            response = {"success": True, "text": text, "language": info.language, "audio_seconds": info.duration}
+            if with_segments:
+                # avg_logprob is the mean log probability of the segment's
+                # tokens; exp of it is close to whisper.cpp's mean probability
+                response["segments"] = [
+                    {
+                        "start_ms": int(segment.start * 1000),
+                        "end_ms": int(segment.end * 1000),
+                        "text": segment.text,
+                        "confidence": math.exp(segment.avg_logprob),
+                    }
+                    for segment in segments
+                ]
+            return response
             
         except Exception as e:
             logger.error(f"Transcription error: {e}")
@@ -98,38 +268,67 @@ class WhisperDaemon:
             os.unlink(self.socket_path)
             
         # Create Unix socket
+        os.makedirs(os.path.dirname(self.socket_path) or ".", mode=0o700, exist_ok=True)
         self.server_socket = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
         self.server_socket.bind(self.socket_path)
         self.server_socket.listen(1)
         
-        # Set socket permissions
-        os.chmod(self.socket_path, 0o666)
+        # Only this user may connect
+        os.chmod(self.socket_path, 0o600)
         
         logger.info(f"Daemon listening on {self.socket_path}")
+        self.write_ready_file()
         
         while self.running:
             try:
                 # Accept connections
                 conn, _ = self.server_socket.accept()
                 
-                # Receive request
-                data = conn.recv(4096).decode('utf-8')
-                if not data:
+                # Hello exchange, or a bare request from an older client
+                request, rest = read_message(conn, b"")
+                if request is None:
                     conn.close()
                     continue
+                if "protocol_version" in request:
+                    reply, refusal = negotiate(request)
+                    if refusal:
+                        refusal["backend"] = BACKEND
+                        logger.warning(refusal["error"])
+                        send_message(conn, refusal)
+                        conn.close()
+                        continue
+                    send_message(conn, reply)
+                    request, _ = read_message(conn, rest)
+                    if request is None:
+                        conn.close()
+                        continue
                     
-                request = json.loads(data)
                 audio_path = request.get('audio_path')
+                expected = request.get('backend')
                 
-                if not audio_path or not os.path.exists(audio_path):
+                if expected and expected != BACKEND:
+                    response = {
+                        "success": False,
+                        "error": f"Request was for {expected} but the running daemon is {BACKEND}",
+                        "error_code": "wrong_backend",
+                    }
+                elif not audio_path or not os.path.exists(audio_path):
                     response = {"success": False, "error": "Invalid audio path"}
                 else:
                     # Transcribe
-                    response = self.transcribe(audio_path)
+                    response = self.transcribe(
+                        audio_path,
+                        request.get('language'),
+                        request.get('initial_prompt'),
+                        bool(request.get('segments')),
+                        request.get('parameters'),
+                    )
                     
                 # Send response
-                conn.send(json.dumps(response).encode('utf-8'))
+                response["backend"] = BACKEND
+                send_message(conn, response)
                 conn.close()
+                release_managed(request)
                 
             except socket.error as e:
                 if self.running:
@@ -147,13 +346,21 @@ class WhisperDaemon:
         # Start server
         self.start_server()
 
+def default_socket_path():
+    """The faster-whisper daemon's socket in the per-user runtime dir, as whisp-away picks it."""
+    runtime_dir = os.environ.get("XDG_RUNTIME_DIR", "")
+    if not runtime_dir.startswith("/"):
+        runtime_dir = f"/tmp/whisp-away-{os.getuid()}"
+    return os.path.join(runtime_dir, "whisp-away", "daemon-faster-whisper.sock")
+
 def main():
     # Get model from environment or use default
     model_name = os.environ.get("WA_WHISPER_MODEL", "medium.en")
-    socket_path = os.environ.get("WA_WHISPER_SOCKET", "/tmp/whisp-away-daemon.sock")
+    socket_path = os.environ.get("WA_WHISPER_SOCKET") or default_socket_path()
+    language = os.environ.get("WA_WHISPER_LANGUAGE") or "en"
     
     # Create and run daemon
-    daemon = WhisperDaemon(model_name, socket_path)
+    daemon = WhisperDaemon(model_name, socket_path, language)
     daemon.run()
 
 if __name__ == "__main__":
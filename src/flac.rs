@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+
+/// Whether `bytes` start like a FLAC stream rather than a WAV file
+pub fn is_flac(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"fLaC")
+}
+
+/// Decode a FLAC stream into its sample rate and each channel as f32 samples
+#[cfg(feature = "flac")]
+pub fn decode(bytes: &[u8]) -> Result<(u32, Vec<Vec<f32>>)> {
+    use anyhow::Context;
+
+    let mut reader = claxon::FlacReader::new(bytes).context("Invalid FLAC file")?;
+    let info = reader.streaminfo();
+    if info.channels == 0 || info.sample_rate == 0 {
+        return Err(anyhow!("Invalid FLAC file: {} channels at {} Hz", info.channels, info.sample_rate));
+    }
+    let scale = (1u64 << (info.bits_per_sample - 1)) as f32;
+
+    // Samples come interleaved, one per channel for each frame
+    let mut channels = vec![Vec::new(); info.channels as usize];
+    for (i, sample) in reader.samples().enumerate() {
+        let sample = sample.context("Corrupt FLAC file")?;
+        channels[i % info.channels as usize].push(sample as f32 / scale);
+    }
+    Ok((info.sample_rate, channels))
+}
+
+#[cfg(not(feature = "flac"))]
+pub fn decode(_bytes: &[u8]) -> Result<(u32, Vec<Vec<f32>>)> {
+    Err(anyhow!("This is a FLAC file, but whisp-away was built without the flac feature"))
+}
+
+/// Losslessly compress the WAV at `wav_path` into `flac_path`. Only integer
+/// PCM up to 24 bits fits a FLAC stream unchanged, which covers everything
+/// pw-record is asked for.
+#[cfg(feature = "flac")]
+pub fn encode_file(wav_path: &str, flac_path: &str) -> Result<()> {
+    use anyhow::Context;
+    use crate::wav::SampleFormat;
+
+    let bytes = std::fs::read(wav_path).with_context(|| format!("Failed to read {}", wav_path))?;
+    let wav = crate::wav::parse(&bytes)?;
+    let (bits, width) = match wav.format.sample_format {
+        SampleFormat::S16 => (16, 2),
+        SampleFormat::S24 => (24, 3),
+        other => return Err(anyhow!("Only 16 and 24-bit PCM can be stored as FLAC, not {:?}", other)),
+    };
+    let channels = wav.format.channels as usize;
+    let frames = wav.data.len() / (width * channels);
+    let samples: Vec<i32> = wav.data[..frames * width * channels]
+        .chunks_exact(width)
+        .map(|b| match width {
+            2 => i16::from_le_bytes([b[0], b[1]]) as i32,
+            _ => i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8,
+        })
+        .collect();
+
+    let mut file = std::fs::File::create(flac_path).with_context(|| format!("Failed to create {}", flac_path))?;
+    let mut output = flac_bound::WriteWrapper(&mut file);
+    let mut encoder = flac_bound::FlacEncoder::new()
+        .ok_or_else(|| anyhow!("Could not create a FLAC encoder"))?
+        .channels(channels as u32)
+        .bits_per_sample(bits)
+        .sample_rate(wav.format.sample_rate)
+        .compression_level(8)
+        .init_write(&mut output)
+        .map_err(|e| anyhow!("Could not start the FLAC encoder: {:?}", e))?;
+    encoder
+        .process_interleaved(&samples, frames as u32)
+        .map_err(|_| anyhow!("FLAC encoder rejected the samples"))?;
+    encoder.finish().map_err(|_| anyhow!("Could not finish the FLAC stream"))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "flac"))]
+pub fn encode_file(_wav_path: &str, _flac_path: &str) -> Result<()> {
+    Err(anyhow!("whisp-away was built without the flac feature"))
+}
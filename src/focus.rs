@@ -0,0 +1,321 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use crate::child_env;
+use crate::helpers::{fnv1a, FNV1A_OFFSET};
+
+/// Window classes / app ids transcripts are never typed into: password and
+/// authentication prompts
+pub const DEFAULT_NEVER_TYPE_INTO: &[&str] = &[
+    "gcr-prompter",
+    "polkit-gnome-authentication-agent",
+    "polkit-kde-authentication-agent",
+    "lxqt-policykit-agent",
+    "pinentry",
+];
+
+/// Window classes / app ids of remote desktop and VM viewers, which drop
+/// keystrokes when wtype types at full speed
+pub const DEFAULT_SLOW_TARGETS: &[&str] = &[
+    "vncviewer",
+    "looking-glass",
+    "remmina",
+    "virt-viewer",
+    "virt-manager",
+    "freerdp",
+    "spicy",
+    "gnome-connections",
+    "krdc",
+];
+
+/// Compile a window pattern list: `var` (one regex per line) if set, the
+/// defaults otherwise. Patterns match anywhere in the id, ignoring case.
+fn patterns(var: &str, defaults: &[&str], what: &str) -> Vec<Regex> {
+    let configured = std::env::var(var).ok();
+    let patterns: Vec<&str> = match &configured {
+        Some(list) => list.lines().map(str::trim).filter(|line| !line.is_empty()).collect(),
+        None => defaults.to_vec(),
+    };
+    patterns
+        .into_iter()
+        .filter_map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("WARNING: Ignoring invalid {} pattern {:?}: {}", what, pattern, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Compile the denylist from WA_NEVER_TYPE_INTO or DEFAULT_NEVER_TYPE_INTO
+pub fn denylist() -> Vec<Regex> {
+    patterns("WA_NEVER_TYPE_INTO", DEFAULT_NEVER_TYPE_INTO, "never-type-into")
+}
+
+/// Compile the slow targets from WA_SLOW_TARGETS or DEFAULT_SLOW_TARGETS
+pub fn slow_targets() -> Vec<Regex> {
+    patterns("WA_SLOW_TARGETS", DEFAULT_SLOW_TARGETS, "slow-target")
+}
+
+/// Whether a window id matches any of the patterns
+pub fn matches(window_id: &str, patterns: &[Regex]) -> bool {
+    patterns.iter().any(|pattern| pattern.is_match(window_id))
+}
+
+/// Run a compositor IPC command and parse its JSON output
+fn query_json(program: &str, args: &[&str]) -> Option<Value> {
+    let output = child_env::command(program, &[]).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// Hyprland's active window
+fn hyprland_window() -> Option<Value> {
+    query_json("hyprctl", &["activewindow", "-j"])
+}
+
+/// Class and initial class of Hyprland's active window
+fn hyprland_focused() -> Vec<String> {
+    let Some(window) = hyprland_window() else {
+        return Vec::new();
+    };
+    ["class", "initialClass"]
+        .iter()
+        .filter_map(|key| window.get(*key).and_then(Value::as_str))
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Depth-first search of the sway tree for the focused node
+fn find_focused(node: &Value) -> Option<&Value> {
+    if node.get("focused").and_then(Value::as_bool) == Some(true) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node.get(*key).and_then(Value::as_array))
+        .flatten()
+        .find_map(find_focused)
+}
+
+/// Sway's focused node
+fn sway_window() -> Option<Value> {
+    let tree = query_json("swaymsg", &["-t", "get_tree", "-r"])?;
+    find_focused(&tree).cloned()
+}
+
+/// app_id (Wayland) or window class (Xwayland) of sway's focused window
+fn sway_focused() -> Vec<String> {
+    let Some(node) = sway_window() else {
+        return Vec::new();
+    };
+    let class = node.get("window_properties").and_then(|props| props.get("class"));
+    [node.get("app_id"), class]
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Ids of the focused window from whichever compositor we're running under;
+/// empty when there is no supported compositor
+pub fn focused_window_ids() -> Vec<String> {
+    if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
+        hyprland_focused()
+    } else if std::env::var("SWAYSOCK").is_ok() {
+        sway_focused()
+    } else {
+        Vec::new()
+    }
+}
+
+/// The focused window's id if it is on the never-type-into denylist.
+/// Fails open: None whenever the focused window can't be determined.
+pub fn denied_focused_window() -> Option<String> {
+    let ids = focused_window_ids();
+    if ids.is_empty() {
+        return None;
+    }
+    let denylist = denylist();
+    ids.into_iter().find(|id| matches(id, &denylist))
+}
+
+/// The focused window's id if it is a slow target, to be typed into with the
+/// slow pacing profile. None whenever the focused window can't be determined.
+pub fn slow_focused_window() -> Option<String> {
+    let ids = focused_window_ids();
+    if ids.is_empty() {
+        return None;
+    }
+    let targets = slow_targets();
+    ids.into_iter().find(|id| matches(id, &targets))
+}
+
+/// Longest window title kept, in characters
+const MAX_TITLE_CHARS: usize = 200;
+
+/// Most time looking up the window a transcript goes to may take
+const WINDOW_BUDGET: Duration = Duration::from_millis(100);
+
+/// The window a transcript was typed or copied into, as history records it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetWindow {
+    /// Class or app id
+    #[serde(rename = "window_app_id")]
+    pub app_id: String,
+    /// Title, cut to MAX_TITLE_CHARS, or its hash with WA_RECORD_WINDOW=hash
+    #[serde(rename = "window_title", default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// How much of the focused window history keeps (WA_RECORD_WINDOW)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordWindow {
+    /// App id and title
+    Full,
+    /// App id and a hash of the title, so entries from one window can be
+    /// told apart without keeping what it showed
+    Hash,
+    /// Nothing
+    Off,
+}
+
+/// WA_RECORD_WINDOW: full (default), hash or off
+fn record_window() -> RecordWindow {
+    match std::env::var("WA_RECORD_WINDOW").as_deref() {
+        Err(_) | Ok("") | Ok("full") => RecordWindow::Full,
+        Ok("hash") => RecordWindow::Hash,
+        Ok("off") | Ok("0") | Ok("false") => RecordWindow::Off,
+        Ok(value) => {
+            eprintln!("WARNING: Ignoring WA_RECORD_WINDOW={:?}; expected full, hash or off", value);
+            RecordWindow::Full
+        }
+    }
+}
+
+/// `title` as recorded: cut to MAX_TITLE_CHARS with an ellipsis, or hashed.
+/// The whole title is hashed, so long titles sharing a start stay apart.
+fn recorded_title(title: &str, mode: RecordWindow) -> Option<String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return None;
+    }
+    match mode {
+        RecordWindow::Full if title.chars().count() > MAX_TITLE_CHARS => {
+            let cut: String = title.chars().take(MAX_TITLE_CHARS - 1).collect();
+            Some(format!("{}…", cut))
+        }
+        RecordWindow::Full => Some(title.to_string()),
+        RecordWindow::Hash => Some(format!("fnv1a:{:016x}", fnv1a(FNV1A_OFFSET, title.as_bytes()))),
+        RecordWindow::Off => None,
+    }
+}
+
+/// App id and title of the focused window, straight from the compositor
+fn focused_window() -> Option<(String, String)> {
+    let (window, title_key) = if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
+        (hyprland_window()?, "title")
+    } else if std::env::var("SWAYSOCK").is_ok() {
+        (sway_window()?, "name")
+    } else {
+        return None;
+    };
+    // Hyprland has a class, sway an app_id or, for Xwayland, a window class
+    let app_id = ["app_id", "class", "initialClass"]
+        .iter()
+        .filter_map(|key| window.get(*key))
+        .chain(window.pointer("/window_properties/class"))
+        .filter_map(Value::as_str)
+        .find(|id| !id.is_empty())?;
+    let title = window.get(title_key).and_then(Value::as_str).unwrap_or_default();
+    Some((app_id.to_string(), title.to_string()))
+}
+
+/// Which window the transcript about to be output goes to, for history.
+/// Best effort: a compositor that doesn't answer within WINDOW_BUDGET is
+/// given up on, so typing is never held up by it.
+pub fn capture_target() -> Option<TargetWindow> {
+    let mode = record_window();
+    if mode == RecordWindow::Off {
+        return None;
+    }
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(focused_window());
+    });
+    let window = match receiver.recv_timeout(WINDOW_BUDGET) {
+        Ok(window) => window,
+        Err(_) => {
+            eprintln!("DEBUG: The focused window took over {} ms to look up, not recording it", WINDOW_BUDGET.as_millis());
+            None
+        }
+    };
+    window.map(|(app_id, title)| TargetWindow {
+        app_id,
+        title: recorded_title(&title, mode),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn compiled(defaults: &[&str]) -> Vec<Regex> {
+        crate::helpers::with_env(&[("WA_TEST_PATTERNS", None)], || patterns("WA_TEST_PATTERNS", defaults, "test"))
+    }
+    
+    #[test]
+    fn password_prompts_are_denied() {
+        let denied = compiled(DEFAULT_NEVER_TYPE_INTO);
+        for id in ["gcr-prompter", "Gcr-prompter", "polkit-gnome-authentication-agent-1", "org.kde.polkit-kde-authentication-agent-1", "pinentry-qt", "Pinentry-gtk-2"] {
+            assert!(matches(id, &denied), "{} should be denied", id);
+        }
+        for id in ["firefox", "org.gnome.Nautilus", "kitty", "Alacritty", "code-url-handler"] {
+            assert!(!matches(id, &denied), "{} should not be denied", id);
+        }
+    }
+    
+    #[test]
+    fn remote_viewers_are_slow_targets() {
+        let slow = compiled(DEFAULT_SLOW_TARGETS);
+        for id in ["org.remmina.Remmina", "Vncviewer", "looking-glass-client", "virt-manager", "xfreerdp", "gnome-connections"] {
+            assert!(matches(id, &slow), "{} should be slow", id);
+        }
+        for id in ["firefox", "org.gnome.Terminal"] {
+            assert!(!matches(id, &slow), "{} should not be slow", id);
+        }
+    }
+    
+    #[test]
+    fn configured_patterns_replace_the_defaults() {
+        let list = "^org\\.keepassxc\\.KeePassXC$\n\n  1password  \n";
+        let denied = crate::helpers::with_env(&[("WA_TEST_PATTERNS", Some(list))], || patterns("WA_TEST_PATTERNS", DEFAULT_NEVER_TYPE_INTO, "test"));
+        assert_eq!(denied.len(), 2);
+        assert!(matches("org.keepassxc.KeePassXC", &denied));
+        assert!(matches("org.keepassxc.keepassxc", &denied));
+        assert!(!matches("org.keepassxc.KeePassXC.browser", &denied));
+        assert!(matches("1Password", &denied));
+        assert!(!matches("gcr-prompter", &denied));
+    }
+    
+    #[test]
+    fn invalid_patterns_are_skipped() {
+        let denied = crate::helpers::with_env(&[("WA_TEST_PATTERNS", Some("pinentry\n(unclosed"))], || patterns("WA_TEST_PATTERNS", &[], "test"));
+        assert_eq!(denied.len(), 1);
+        assert!(matches("pinentry-curses", &denied));
+    }
+    
+    #[test]
+    fn nothing_matches_an_empty_list() {
+        let none = crate::helpers::with_env(&[("WA_TEST_PATTERNS", Some(""))], || patterns("WA_TEST_PATTERNS", DEFAULT_NEVER_TYPE_INTO, "test"));
+        assert!(none.is_empty());
+        assert!(!matches("gcr-prompter", &none));
+    }
+}
@@ -1,10 +1,14 @@
-use anyhow::{anyhow, Result};
-use std::process::Command;
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use serde::{Deserialize, Serialize};
 
+/// Whether `pid` is alive; a dead one is an answer, not an error, so kill's
+/// "No such process" is kept off stderr
 pub fn is_process_running(pid: u32) -> bool {
-    Command::new("kill")
-        .args(&["-0", &pid.to_string()])
+    crate::child_env::command("kill", &[])
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
         .status()
         .map(|s| s.success())
         .unwrap_or(false)
@@ -13,42 +17,279 @@ pub fn is_process_running(pid: u32) -> bool {
 
 
 pub fn wav_to_samples(wav_data: &[u8]) -> Result<Vec<f32>> {
-    // Skip WAV header (44 bytes) and convert to f32 samples
-    // This assumes 16-bit PCM mono audio at 16kHz
-    
-    if wav_data.len() < 44 {
-        return Err(anyhow::anyhow!("Invalid WAV file: too short"));
-    }
-    
-    let raw_samples = &wav_data[44..];
-    let mut samples = Vec::with_capacity(raw_samples.len() / 2);
-    
-    for chunk in raw_samples.chunks_exact(2) {
-        let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-        samples.push(sample as f32 / i16::MAX as f32);
-    }
-    
-    Ok(samples)
+    // Parse the real header and convert to 16 kHz mono f32 samples,
+    // whatever format the file was actually written in
+    crate::audio::Pipeline::target().samples(wav_data)
 }
 
 /// Tray state stored in runtime dir
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TrayState {
     pub model: String,
     pub backend: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Missing in state files written before the tray had the toggle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_mode: Option<OutputMode>,
+    /// Profile picked in the tray; its settings win over the ones above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Recording settings menu: the threshold preset, and whether silent
+    /// recordings are dropped and recordings stop after a pause
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensitivity: Option<crate::recording::Sensitivity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vad: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_stop: Option<bool>,
+}
+
+/// What happens to a transcript: typed into the focused window, or only put
+/// on the clipboard (for setups where wtype is unreliable)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    #[default]
+    Type,
+    Clipboard,
 }
 
-/// Get the runtime directory (XDG_RUNTIME_DIR or /tmp fallback)
-pub fn get_runtime_dir() -> String {
-    std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| {
-        let uid = unsafe { libc::getuid() };
-        format!("/tmp/whisp-away-{}", uid)
+impl std::fmt::Display for OutputMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputMode::Type => write!(f, "type"),
+            OutputMode::Clipboard => write!(f, "clipboard"),
+        }
+    }
+}
+
+pub fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Fill `buf` from the kernel's random number generator
+pub fn random_bytes(buf: &mut [u8]) -> Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = unsafe { libc::getrandom(buf[filled..].as_mut_ptr().cast(), buf.len() - filled, 0) };
+        if read < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() != std::io::ErrorKind::Interrupted {
+                return Err(error).context("Failed to get random bytes");
+            }
+            continue;
+        }
+        filled += read as usize;
+    }
+    Ok(())
+}
+
+/// Where an FNV-1a hash starts
+pub const FNV1A_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// FNV-1a of `bytes`, carrying on from `hash` (FNV1A_OFFSET for a new one).
+/// Stable across Rust versions unlike DefaultHasher, so it can name files.
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Get the runtime directory (XDG_RUNTIME_DIR or /tmp fallback), creating it
+/// if it doesn't exist yet. The fallback lives in a shared /tmp, so it is
+/// created private to the user, and not used unless it is ours.
+pub fn runtime_dir() -> String {
+    static DIR: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    
+    DIR.get_or_init(|| {
+        let dir = std::env::var("XDG_RUNTIME_DIR")
+            .ok()
+            .filter(|dir| dir.starts_with('/'))
+            .unwrap_or_else(tmp_runtime_dir);
+        use std::os::unix::fs::DirBuilderExt;
+        if let Err(e) = std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir) {
+            eprintln!("WARNING: Could not create runtime dir {}: {}", dir, e);
+        }
+        dir
     })
+    .clone()
+}
+
+/// /tmp/whisp-away-<uid>, made private if it isn't. Anyone can create that
+/// name first, so when it isn't a directory of ours the cache dir is used instead.
+fn tmp_runtime_dir() -> String {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    
+    let dir = format!("/tmp/whisp-away-{}", uid());
+    let _ = std::fs::DirBuilder::new().mode(0o700).create(&dir);
+    match std::fs::symlink_metadata(&dir) {
+        Ok(meta) if meta.is_dir() && meta.uid() == uid() => {
+            if meta.mode() & 0o077 != 0 {
+                let _ = std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700));
+            }
+            dir
+        }
+        _ => {
+            let fallback = format!("{}/whisp-away/run", cache_dir());
+            eprintln!("WARNING: {} is not a directory of ours, using {} instead", dir, fallback);
+            fallback
+        }
+    }
+}
+
+/// Owner of `path`, not following symlinks; None when it doesn't exist
+pub fn owner(path: &str) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::symlink_metadata(path).ok().map(|meta| meta.uid())
+}
+
+/// Whether `path` exists and belongs to this user. Anyone can put files in a
+/// shared dir like /tmp, so what is read from one is only acted on when this holds.
+pub fn owned_by_us(path: &str) -> bool {
+    owner(path) == Some(uid())
+}
+
+/// Path of a file in the runtime dir
+pub fn runtime_file(name: &str) -> String {
+    format!("{}/{}", runtime_dir(), name)
+}
+
+/// `path`, unless only the copy an older release left at `legacy` exists.
+/// Callers remove whichever file they were given once done with it, so the
+/// legacy location is read at most once after an upgrade. A legacy file
+/// another user owns is ignored: those were in /tmp, where anyone can plant one.
+pub fn with_legacy_fallback(path: String, legacy: &str) -> String {
+    if std::path::Path::new(&path).exists() {
+        return path;
+    }
+    match owner(legacy) {
+        Some(owner) if owner == uid() => {
+            eprintln!("DEBUG: Using {} left by an older version", legacy);
+            legacy.to_string()
+        }
+        Some(owner) => {
+            eprintln!("WARNING: Ignoring {}, which belongs to uid {}", legacy, owner);
+            path
+        }
+        None => path,
+    }
+}
+
+/// Read an XDG base directory variable, ignoring empty or relative values as the spec requires
+fn xdg_dir(var: &str, home_fallback: &str) -> String {
+    std::env::var(var)
+        .ok()
+        .filter(|dir| dir.starts_with('/'))
+        .unwrap_or_else(|| {
+            let home = std::env::var("HOME").unwrap_or_default();
+            format!("{}/{}", home, home_fallback)
+        })
+}
+
+/// Held by tests that change the environment, which the whole test process shares
+#[cfg(test)]
+pub fn env_lock() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Run `f` with `vars` set, or removed where None, holding env_lock, and put
+/// them back after
+#[cfg(test)]
+pub fn with_env<R>(vars: &[(&str, Option<&str>)], f: impl FnOnce() -> R) -> R {
+    let _lock = env_lock();
+    let saved: Vec<(String, Option<String>)> = vars.iter().map(|(name, _)| (name.to_string(), std::env::var(name).ok())).collect();
+    let set = |name: &str, value: Option<&str>| match value {
+        Some(value) => std::env::set_var(name, value),
+        None => std::env::remove_var(name),
+    };
+    for (name, value) in vars {
+        set(name, *value);
+    }
+    let result = f();
+    for (name, value) in &saved {
+        set(name, value.as_deref());
+    }
+    result
+}
+
+/// Get the cache directory (XDG_CACHE_HOME or ~/.cache fallback)
+pub fn cache_dir() -> String {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Get the config directory (XDG_CONFIG_HOME or ~/.config fallback)
+pub fn config_dir() -> String {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Get the data directory (XDG_DATA_HOME or ~/.local/share fallback)
+pub fn data_dir() -> String {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Resolve a path relative to the cache dir. If it only exists in the legacy
+/// ~/.cache location (from before XDG_CACHE_HOME was respected), use that instead
+/// so previously downloaded models don't disappear.
+pub fn find_in_cache(relative: &str) -> String {
+    let path = format!("{}/{}", cache_dir(), relative);
+    if !std::path::Path::new(&path).exists() {
+        let home = std::env::var("HOME").unwrap_or_default();
+        let legacy = format!("{}/.cache/{}", home, relative);
+        if std::path::Path::new(&legacy).exists() {
+            return legacy;
+        }
+    }
+    path
+}
+
+/// Directory where whisper.cpp GGML models are downloaded to
+pub fn whisper_cpp_models_dir() -> String {
+    format!("{}/whisper-cpp/models", cache_dir())
+}
+
+/// Whether `model` is a model file rather than the name of an official
+/// model: anything with a directory in it, or a .gguf file
+pub fn is_model_path(model: &str) -> bool {
+    model.contains('/') || model.ends_with(".gguf")
+}
+
+/// A model given as a path, made absolute so a daemon started from another
+/// directory loads the same file. Names are returned as they are.
+pub fn absolute_model(model: &str) -> String {
+    if !is_model_path(model) || model.starts_with('/') {
+        return model.to_string();
+    }
+    match std::env::current_dir() {
+        Ok(dir) => dir.join(model.trim_start_matches("./")).to_string_lossy().into_owned(),
+        Err(_) => model.to_string(),
+    }
+}
+
+/// Model as notifications and the tray show it: its name, or the file stem
+/// of a model given as a path
+pub fn model_display_name(model: &str) -> String {
+    if !is_model_path(model) {
+        return model.to_string();
+    }
+    std::path::Path::new(model)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| model.to_string())
+}
+
+/// Full path of a whisper.cpp model given its name (e.g. "base.en"), or the
+/// model file itself, made absolute, when given a path
+pub fn whisper_cpp_model_path(model: &str) -> String {
+    if is_model_path(model) {
+        return absolute_model(model);
+    }
+    let model_extension = if model.ends_with(".bin") { "" } else { ".bin" };
+    find_in_cache(&format!("whisper-cpp/models/ggml-{}{}", model, model_extension))
 }
 
 /// Get the tray state file path
-fn get_state_file() -> String {
-    format!("{}/whisp-away-state.json", get_runtime_dir())
+pub fn get_state_file() -> String {
+    runtime_file("whisp-away-state.json")
 }
 
 /// Read current tray state if available
@@ -64,35 +305,223 @@ pub fn read_tray_state() -> Option<TrayState> {
 /// Write tray state
 pub fn write_tray_state(state: &TrayState) -> Result<()> {
     let state_file = get_state_file();
-    let runtime_dir = get_runtime_dir();
-    
-    // Ensure runtime dir exists
-    std::fs::create_dir_all(&runtime_dir).ok();
-    
     let json = serde_json::to_string_pretty(state)?;
     std::fs::write(state_file, json)?;
     Ok(())
 }
 
+/// Where a resolved setting came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    CommandLine,
+    /// The named profile in effect
+    Profile(String),
+    /// The tray's state file, with when it was last written
+    TrayState { path: String, modified: Option<String> },
+    Env(&'static str),
+    Default,
+}
+
+impl Source {
+    /// The source with the file and its age for the tray state
+    fn detailed(&self) -> String {
+        match self {
+            Source::TrayState { path, modified: Some(modified) } => format!("tray state {} (written {})", path, modified),
+            Source::TrayState { path, modified: None } => format!("tray state {} (missing)", path),
+            Source::Profile(name) => format!("profile {} ({})", name, crate::profile::path(name)),
+            source => source.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Profile(name) => write!(f, "profile {}", name),
+            Source::TrayState { .. } => write!(f, "tray state"),
+            Source::Env(name) => write!(f, "{}", name),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A setting resolved from the command line, profile, tray state,
+/// environment and default, keeping every candidate so the choice can be explained
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub value: String,
+    pub source: Source,
+    /// Every place consulted, highest priority first, with what it held
+    pub chain: Vec<(Source, Option<String>)>,
+}
+
+impl Resolution {
+    /// Take the first candidate that holds a value; the default always does
+    fn pick(chain: Vec<(Source, Option<String>)>) -> Self {
+        let (source, value) = chain
+            .iter()
+            .find_map(|(source, value)| Some((source.clone(), value.clone()?)))
+            .unwrap_or((Source::Default, String::new()));
+        Self { value, source, chain }
+    }
+    
+    /// One line per candidate, marking the one used
+    pub fn describe(&self, name: &str) -> String {
+        let mut out = format!("{}: {} (from {})", name, self.value, self.source);
+        for (source, value) in &self.chain {
+            let marker = if *source == self.source { "->" } else { "  " };
+            let value = value.as_deref().unwrap_or("(not set)");
+            out.push_str(&format!("\n  {} {}: {}", marker, source.detailed(), value));
+        }
+        out
+    }
+}
+
+/// The tray state file as a resolution source, if it exists
+fn tray_state_source() -> Source {
+    let path = get_state_file();
+    let modified = std::fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|age| format_local_time(age.as_secs() as libc::time_t));
+    Source::TrayState { path, modified }
+}
+
+/// Resolve a setting: command line, the active profile, tray state, `env`,
+/// then `default`. Empty values count as unset; without a profile the profile
+/// isn't a candidate at all.
+fn resolve_setting(
+    arg: Option<String>,
+    profile: Option<&str>,
+    from_profile: impl FnOnce(crate::profile::Profile) -> Option<String>,
+    from_state: impl FnOnce(TrayState) -> Option<String>,
+    env: &'static str,
+    default: &str,
+) -> Resolution {
+    let profile = crate::profile::active(profile).map(|profile| (profile.name.clone(), from_profile(profile)));
+    choose_setting(arg, profile, vec![
+        (tray_state_source(), read_tray_state().and_then(from_state)),
+        (Source::Env(env), std::env::var(env).ok()),
+        (Source::Default, Some(default.to_string())),
+    ])
+}
+
+/// Pick a setting from the command line, the active profile's `(name, value)`
+/// and then `fallbacks` in order, each as read by resolve_setting
+pub fn choose_setting(arg: Option<String>, profile: Option<(String, Option<String>)>, fallbacks: Vec<(Source, Option<String>)>) -> Resolution {
+    let profile = profile.map(|(name, value)| (Source::Profile(name), value));
+    let chain = std::iter::once((Source::CommandLine, arg))
+        .chain(profile)
+        .chain(fallbacks)
+        .map(|(source, value)| (source, value.filter(|value| !value.is_empty())))
+        .collect();
+    Resolution::pick(chain)
+}
+
+/// Backend for `--backend tray` (or the default); `arg` is an explicit backend
+pub fn resolve_backend_source(arg: Option<String>, profile: Option<&str>) -> Resolution {
+    resolve_setting(arg, profile, |profile| profile.backend, |state| Some(state.backend), "WA_WHISPER_BACKEND", "faster-whisper")
+}
+
+/// Model with its provenance, see resolve_model. A relative model path is
+/// made absolute, since the daemon it goes to may run elsewhere.
+pub fn resolve_model_source(arg: Option<String>, profile: Option<&str>) -> Resolution {
+    let mut resolution = resolve_setting(arg, profile, |profile| profile.model, |state| Some(state.model), "WA_WHISPER_MODEL", "base.en");
+    resolution.value = absolute_model(&resolution.value);
+    resolution
+}
+
+/// Language with its provenance, see resolve_language
+pub fn resolve_language_source(arg: Option<String>, profile: Option<&str>) -> Resolution {
+    resolve_setting(arg, profile, |profile| profile.language, |state| state.language, "WA_WHISPER_LANGUAGE", "en")
+}
+
 /// Resolves the model to use with priority:
 /// 1. Command-line argument
-/// 2. Tray state file
-/// 3. WA_WHISPER_MODEL env var
-/// 4. Default to "medium.en"
-pub fn resolve_model(arg: Option<String>) -> String {
-    // Priority 1: Command-line argument
-    if let Some(model) = arg {
-        return model;
+/// 2. Active profile (`profile` from --profile, the tray's pick, or WA_PROFILE)
+/// 3. Tray state file
+/// 4. WA_WHISPER_MODEL env var
+/// 5. Default to "base.en"
+pub fn resolve_model(arg: Option<String>, profile: Option<&str>) -> String {
+    resolve_model_source(arg, profile).value
+}
+
+/// Resolves the transcription language with priority:
+/// 1. Command-line argument
+/// 2. Active profile
+/// 3. Tray state file
+/// 4. WA_WHISPER_LANGUAGE env var
+/// 5. Default to "en"
+///
+/// "auto" asks whisper to detect the language.
+pub fn resolve_language(arg: Option<String>, profile: Option<&str>) -> String {
+    resolve_language_source(arg, profile).value
+}
+
+/// Resolves the small model a daemon keeps loaded for `fast` requests with priority:
+/// 1. Command-line argument
+/// 2. Active profile's fast_model
+/// 3. WA_FAST_MODEL env var
+///
+/// None leaves the daemon with only its main model.
+pub fn resolve_fast_model(arg: Option<String>, profile: Option<&str>) -> Option<String> {
+    arg.or_else(|| crate::profile::active(profile).and_then(|profile| profile.fast_model))
+        .or_else(|| std::env::var("WA_FAST_MODEL").ok())
+        .filter(|model| !model.is_empty())
+}
+
+/// Resolves where transcripts go with priority:
+/// 1. Command-line argument
+/// 2. Active profile
+/// 3. Tray state file
+/// 4. WA_OUTPUT_MODE env var ("type" or "clipboard")
+/// 5. Default to typing
+pub fn resolve_output_mode(arg: Option<OutputMode>, profile: Option<&str>) -> OutputMode {
+    use clap::ValueEnum;
+    arg.or_else(|| crate::profile::active(profile).and_then(|profile| profile.output_mode))
+        .or_else(|| read_tray_state().and_then(|state| state.output_mode))
+        .or_else(|| {
+            let value = std::env::var("WA_OUTPUT_MODE").ok()?;
+            OutputMode::from_str(&value, true)
+                .map_err(|_| eprintln!("WARNING: Ignoring WA_OUTPUT_MODE={:?}, expected type or clipboard", value))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Notification suffix naming the detected language whenever whisper was
+/// asked to detect it, so a misdetection shows before the text is read
+pub fn language_note(requested: &str, detected: Option<&str>) -> String {
+    match detected {
+        Some(lang) if requested == "auto" => format!(" | Language: {}", lang),
+        _ => String::new(),
     }
-    
-    // Priority 2: Tray state
-    if let Some(state) = read_tray_state() {
-        return state.model;
+}
+
+/// Where `program` would run from: the program itself when it is given as a
+/// path, else the first executable of that name on PATH
+pub fn find_program(program: &str) -> Option<std::path::PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let executable = |path: &std::path::Path| {
+        path.metadata().is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        let path = std::path::PathBuf::from(program);
+        return executable(&path).then_some(path);
     }
-    
-    // Priority 3: Environment variable
-    // Priority 4: Default
-    std::env::var("WA_WHISPER_MODEL").unwrap_or_else(|_| "base.en".to_string())
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).map(|dir| dir.join(program)).find(|path| executable(path))
+}
+
+/// Threads whisper runs on: WA_THREADS (`--threads`), else every available CPU
+pub fn threads() -> usize {
+    std::env::var("WA_THREADS")
+        .ok()
+        .and_then(|threads| threads.parse().ok())
+        .filter(|threads| *threads > 0)
+        .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(8))
 }
 
 /// Get the acceleration type from environment variable
@@ -100,3 +529,174 @@ pub fn get_acceleration_type() -> String {
     std::env::var("WA_ACCELERATION_TYPE").unwrap_or_else(|_| "unknown".to_string())
 }
 
+/// Whether to stay off the GPU for this transcription (`stop --no-gpu` or WA_NO_GPU=1)
+pub fn no_gpu() -> bool {
+    matches!(std::env::var("WA_NO_GPU").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Directory for whisp-away's own log files
+pub fn log_dir() -> String {
+    format!("{}/whisp-away", cache_dir())
+}
+
+/// Log file the tray writes a daemon's output to
+pub fn daemon_log_path(daemon_type: &str) -> String {
+    format!("{}/daemon-{}.log", log_dir(), daemon_type)
+}
+
+/// Current local time formatted as "YYYY-MM-DD HH:MM:SS"
+pub fn local_timestamp() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    format_local_time(now)
+}
+
+/// Format seconds since the epoch as local "YYYY-MM-DD HH:MM:SS"
+pub fn format_local_time(secs: libc::time_t) -> String {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday,
+        tm.tm_hour, tm.tm_min, tm.tm_sec
+    )
+}
+
+/// Append-only writer that rotates by size: `path` is the live file and
+/// `path.1` .. `path.N` hold older generations, the oldest being dropped.
+pub struct RotatingWriter {
+    path: String,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingWriter {
+    /// Open `path` for appending, keeping at most `keep` files of `max_bytes` each
+    pub fn open(path: &str, max_bytes: u64, keep: usize) -> Result<Self> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_string(),
+            max_bytes,
+            keep: keep.max(1),
+            file,
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        // Shift path.N-1 -> path.N, ..., path -> path.1; the oldest falls off
+        let _ = std::fs::remove_file(format!("{}.{}", self.path, self.keep - 1));
+        for i in (1..self.keep.saturating_sub(1)).rev() {
+            let _ = std::fs::rename(format!("{}.{}", self.path, i), format!("{}.{}", self.path, i + 1));
+        }
+        if self.keep > 1 {
+            std::fs::rename(&self.path, format!("{}.1", self.path))?;
+        } else {
+            std::fs::remove_file(&self.path)?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A scratch directory for `name`; tests running meanwhile may write to
+    /// the dirs pointed there
+    fn scratch(name: &str) -> String {
+        std::env::temp_dir().join(format!("whisp-away-test-{}-{}", std::process::id(), name)).to_string_lossy().into_owned()
+    }
+    
+    #[test]
+    fn fnv1a_matches_the_reference_values_in_pieces_or_whole() {
+        assert_eq!(fnv1a(FNV1A_OFFSET, b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(FNV1A_OFFSET, b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(FNV1A_OFFSET, b"foobar"), 0x85944171f73967e8);
+        assert_eq!(fnv1a(fnv1a(FNV1A_OFFSET, b"foo"), b"bar"), 0x85944171f73967e8);
+    }
+    
+    #[test]
+    fn xdg_dirs_follow_the_variables() {
+        let root = scratch("xdg-set");
+        let dir = |name: &str| format!("{}/{}", root, name);
+        let vars = [
+            ("XDG_CACHE_HOME", Some(dir("cache"))),
+            ("XDG_CONFIG_HOME", Some(dir("config"))),
+            ("XDG_DATA_HOME", Some(dir("data"))),
+            ("HOME", Some(dir("home"))),
+        ];
+        let vars: Vec<(&str, Option<&str>)> = vars.iter().map(|(name, value)| (*name, value.as_deref())).collect();
+        with_env(&vars, || {
+            assert_eq!(cache_dir(), dir("cache"));
+            assert_eq!(config_dir(), dir("config"));
+            assert_eq!(data_dir(), dir("data"));
+            assert_eq!(whisper_cpp_models_dir(), dir("cache/whisper-cpp/models"));
+        });
+        let _ = std::fs::remove_dir_all(root);
+    }
+    
+    #[test]
+    fn xdg_dirs_fall_back_to_home_for_unset_empty_or_relative_values() {
+        let home = scratch("xdg-home");
+        let vars = [
+            ("XDG_CACHE_HOME", None),
+            ("XDG_CONFIG_HOME", Some("")),
+            ("XDG_DATA_HOME", Some("relative/data")),
+            ("HOME", Some(home.as_str())),
+        ];
+        with_env(&vars, || {
+            assert_eq!(cache_dir(), format!("{}/.cache", home));
+            assert_eq!(config_dir(), format!("{}/.config", home));
+            assert_eq!(data_dir(), format!("{}/.local/share", home));
+        });
+        let _ = std::fs::remove_dir_all(home);
+    }
+    
+    #[test]
+    fn models_in_the_legacy_cache_are_still_found() {
+        let root = std::path::PathBuf::from(scratch("xdg-legacy"));
+        let home = root.join("home");
+        let cache = root.join("cache");
+        let model = "whisper-cpp/models/ggml-base.en.bin";
+        let legacy = home.join(".cache").join(model);
+        let current = cache.join(model);
+        let vars = [("XDG_CACHE_HOME", cache.to_str()), ("HOME", home.to_str())];
+        with_env(&vars, || {
+            // Nowhere yet: where a download would go
+            assert_eq!(find_in_cache(model), current.to_str().unwrap());
+            
+            std::fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+            std::fs::write(&legacy, b"ggml").unwrap();
+            assert_eq!(find_in_cache(model), legacy.to_str().unwrap());
+            
+            // Once it is in the new place, that copy wins
+            std::fs::create_dir_all(current.parent().unwrap()).unwrap();
+            std::fs::write(&current, b"ggml").unwrap();
+            assert_eq!(find_in_cache(model), current.to_str().unwrap());
+        });
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use crate::run::Run;
+
+/// One dictation that was typed, with the audio it came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Local time the text was typed, "YYYY-MM-DD HH:MM:SS"
+    pub time: String,
+    /// Kept copy of the recording
+    pub audio: String,
+    pub text: String,
+    /// What produced the text, as shown in the notification ("whisper-cpp daemon", ...)
+    pub source: String,
+    pub model: String,
+    /// Language transcription was asked for, "auto" to detect it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Language whisper reported, when it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    /// Initial prompt whisper was given, glossary included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Share of the recording that clipped, when enough did to warn about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipped: Option<f32>,
+    /// Requested and recorded format, device and pw-record's own output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<crate::recording::RecordingInfo>,
+    /// High-pass and normalization gain, when the recording was preprocessed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preprocessing: Option<crate::recording::Preprocessing>,
+    /// Length of the recording, to the sample
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_seconds: Option<f64>,
+    /// Words in `text`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<usize>,
+    /// `words` over the length of the recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words_per_minute: Option<f64>,
+    /// Window the text went to, as `window_app_id` and `window_title`
+    #[serde(flatten)]
+    pub window: Option<crate::focus::TargetWindow>,
+}
+
+/// Whether recordings and what was typed from them are kept (WA_KEEP_AUDIO=1).
+/// `replay` needs both: the recording to transcribe again and the typed text
+/// to compare against. Off by default, since they hold everything dictated.
+pub fn enabled() -> bool {
+    matches!(std::env::var("WA_KEEP_AUDIO").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// How kept recordings are archived once transcribed. Transcription itself
+/// always works on the WAV, so compressing never adds latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AudioFormat {
+    #[default]
+    Wav,
+    /// Lossless, about half the size (needs the flac feature)
+    Flac,
+}
+
+/// `arg` (--audio-format), then WA_AUDIO_FORMAT ("wav" or "flac"), then WAV
+pub fn audio_format(arg: Option<AudioFormat>) -> AudioFormat {
+    use clap::ValueEnum;
+    arg
+        .or_else(|| {
+            let value = std::env::var("WA_AUDIO_FORMAT").ok()?;
+            AudioFormat::from_str(&value, true)
+                .map_err(|_| eprintln!("WARNING: Ignoring WA_AUDIO_FORMAT={:?}, expected wav or flac", value))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Where kept recordings and the journal live
+pub fn dir() -> String {
+    format!("{}/whisp-away/recordings", crate::helpers::data_dir())
+}
+
+/// One JournalEntry per line, appended as each dictation is typed; `replay`
+/// takes the text typed from a kept recording from here
+pub fn journal_path() -> String {
+    format!("{}/journal.jsonl", dir())
+}
+
+/// Keep a copy of a recording about to be transcribed, when enabled, to be
+/// archived as `format`. Returns the copy and its format, for record_typed.
+pub fn keep(audio_file: &str, format: AudioFormat) -> Option<(String, AudioFormat)> {
+    if !enabled() {
+        return None;
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let kept = format!("{}/{}.wav", dir(), timestamp);
+    let result = fs::create_dir_all(dir()).map_err(anyhow::Error::from).and_then(|_| store_copy(audio_file, &kept, format));
+    match result {
+        Ok(_) => Some((kept, format)),
+        Err(e) => {
+            eprintln!("WARNING: Could not keep recording {}: {:#}", audio_file, e);
+            None
+        }
+    }
+}
+
+/// Copy a recording to `kept`, encrypted when WA_ENCRYPT is set. One that
+/// will be compressed stays plain until then, since the encoder reads the
+/// WAV; the compressed copy is encrypted instead.
+pub fn store_copy(audio_file: &str, kept: &str, format: AudioFormat) -> Result<()> {
+    if !crate::crypt::enabled() || archived_path(kept, format) != kept {
+        fs::copy(audio_file, kept).with_context(|| format!("Failed to copy to {}", kept))?;
+        return Ok(());
+    }
+    let bytes = fs::read(audio_file).with_context(|| format!("Failed to read {}", audio_file))?;
+    crate::crypt::seal_to(&bytes, kept)
+}
+
+/// Add a line to the journal, encrypted when WA_ENCRYPT is set
+fn append(entry: &JournalEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    if crate::crypt::enabled() {
+        line = crate::crypt::seal_line(&line)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path())
+        .context("Failed to open journal")?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// Journal what was typed from the run's kept recording, if there is one,
+/// and start compressing it
+pub fn record_typed(text: &str, source: &str, run: &mut Run) {
+    let Some((audio, format)) = run.kept.take() else {
+        return;
+    };
+    let stats = crate::speech::Stats::of(text, run.duration);
+    let entry = JournalEntry {
+        time: crate::helpers::local_timestamp(),
+        audio: archived_path(&audio, format),
+        text: text.to_string(),
+        source: source.to_string(),
+        model: run.model.clone().unwrap_or_default(),
+        language: Some(run.language.clone()),
+        detected_language: run.detected_language.clone(),
+        prompt: run.prompt.clone(),
+        clipped: run.clipped,
+        recording: run.recording.clone(),
+        preprocessing: run.preprocessing,
+        audio_seconds: stats.as_ref().map(|stats| stats.duration.as_secs_f64()),
+        words: Some(crate::speech::word_count(text)),
+        words_per_minute: stats.and_then(|stats| stats.words_per_minute),
+        window: run.target.clone(),
+    };
+    if let Err(e) = append(&entry) {
+        eprintln!("WARNING: Could not write journal: {}", e);
+    }
+    archive_later(&audio, format);
+    start_archiving();
+}
+
+/// Kept WAVs waiting for transcription to finish before they are compressed to FLAC
+static PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Compressions running in the background
+static ARCHIVING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// Where a kept WAV ends up once archived as `format`
+pub fn archived_path(wav_path: &str, format: AudioFormat) -> String {
+    match format {
+        AudioFormat::Flac if cfg!(feature = "flac") => match wav_path.strip_suffix(".wav") {
+            Some(stem) => format!("{}.flac", stem),
+            None => wav_path.to_string(),
+        },
+        _ => wav_path.to_string(),
+    }
+}
+
+/// Compress a kept WAV once transcription is over, if `format` is a compressed one
+pub fn archive_later(wav_path: &str, format: AudioFormat) {
+    match format {
+        AudioFormat::Wav => {}
+        AudioFormat::Flac if !cfg!(feature = "flac") => {
+            eprintln!("WARNING: Archiving as FLAC needs a build with the flac feature, keeping {} as WAV", wav_path);
+        }
+        AudioFormat::Flac => PENDING.lock().unwrap().push(wav_path.to_string()),
+    }
+}
+
+/// Compress the pending WAVs on a background thread. The WAV is only removed
+/// once its FLAC copy is complete, so a failed encode never loses a recording.
+/// With WA_ENCRYPT, whichever copy is kept is then encrypted.
+fn start_archiving() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    if pending.is_empty() {
+        return;
+    }
+    let handle = std::thread::spawn(move || {
+        let encrypt = crate::crypt::enabled();
+        for wav_path in pending {
+            let flac_path = archived_path(&wav_path, AudioFormat::Flac);
+            let kept = match crate::flac::encode_file(&wav_path, &flac_path) {
+                Ok(()) => {
+                    let _ = fs::remove_file(&wav_path);
+                    eprintln!("DEBUG: Compressed {} to {}", wav_path, flac_path);
+                    flac_path
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&flac_path);
+                    eprintln!("WARNING: Could not compress {}, keeping the WAV: {:#}", wav_path, e);
+                    crate::notify::Notification::normal(
+                        "Voice Input",
+                        &format!("⚠️ Could not compress recording, kept {}", wav_path),
+                    )
+                    .send();
+                    wav_path
+                }
+            };
+            if encrypt {
+                if let Err(e) = crate::crypt::seal_file(&kept) {
+                    eprintln!("WARNING: Could not encrypt {}, it is kept unencrypted: {:#}", kept, e);
+                }
+            }
+        }
+    });
+    ARCHIVING.lock().unwrap().push(handle);
+}
+
+/// Compress whatever is still pending and wait for all compressions, so a
+/// short-lived `stop` doesn't exit halfway through one
+pub fn finish_archiving() {
+    start_archiving();
+    let handles = std::mem::take(&mut *ARCHIVING.lock().unwrap());
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+/// The kept recording at `audio`, or its WAV or FLAC sibling if it was
+/// archived in the other format (or compressing it failed)
+pub fn locate(audio: &str) -> Option<String> {
+    if Path::new(audio).exists() {
+        return Some(audio.to_string());
+    }
+    let sibling = if let Some(stem) = audio.strip_suffix(".flac") {
+        format!("{}.wav", stem)
+    } else {
+        format!("{}.flac", audio.strip_suffix(".wav")?)
+    };
+    Path::new(&sibling).exists().then_some(sibling)
+}
+
+/// Print the newest journal entry, or with `audio` only what was recorded for it
+pub fn print_last(audio: bool) -> Result<()> {
+    let Some(entry) = read_since("")?.pop() else {
+        return Err(anyhow!("The journal {} is empty (recordings are only kept with WA_KEEP_AUDIO=1)", journal_path()));
+    };
+    if !audio {
+        println!("{}", serde_json::to_string_pretty(&entry)?);
+        return Ok(());
+    }
+    
+    println!("{}  {}", entry.time, entry.audio);
+    let Some(info) = entry.recording else {
+        println!("No recording details (journaled by an older version, or not recorded by whisp-away)");
+        return Ok(());
+    };
+    if let Some(command) = &info.command {
+        println!("Command:   {}", command);
+    }
+    if let Some(device) = &info.device {
+        println!("Device:    {}", device);
+    }
+    println!("Requested: {}", info.requested);
+    let mismatch = if info.recorded != info.requested { "  (converted)" } else { "" };
+    println!("Recorded:  {}{}", info.recorded, mismatch);
+    let duration = entry.audio_seconds.unwrap_or(info.duration_ms as f64 / 1000.0);
+    println!("Size:      {} bytes, {:.2} s", info.bytes, duration);
+    match &entry.preprocessing {
+        Some(preprocessing) => println!("Preprocess: {}", preprocessing),
+        None => println!("Preprocess: off"),
+    }
+    if !info.recorder_output.is_empty() {
+        println!("pw-record output:");
+        for line in info.recorder_output.lines() {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Journal entries from `since` ("YYYY-MM-DD" or any prefix of the time format) on.
+/// Encrypted lines are decrypted; without the key to do so this fails
+/// rather than leave them out.
+pub fn read_since(since: &str) -> Result<Vec<JournalEntry>> {
+    let content = match fs::read_to_string(journal_path()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read journal"),
+    };
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = crate::crypt::open_line(line)
+            .with_context(|| format!("Failed to decrypt line {} of {}", number + 1, journal_path()))?;
+        if let Ok(entry) = serde_json::from_str::<JournalEntry>(&line) {
+            entries.push(entry);
+        }
+    }
+    // Local timestamps sort as strings
+    entries.retain(|entry| entry.time.as_str() >= since);
+    Ok(entries)
+}
@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::Read;
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant};
+use crate::replacements::{split_entry, unquote};
+use crate::run::Run;
+
+/// Seconds a hook may run before it is killed, unless WA_HOOK_TIMEOUT says otherwise
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// When hooks run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Before the text is typed or copied; a hook's output replaces the text
+    PreType,
+    /// After the text was typed or copied, in the background
+    Transcribed,
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::PreType => "pre_type",
+            Event::Transcribed => "transcribed",
+        }
+    }
+    
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pre_type" => Some(Event::PreType),
+            "transcribed" => Some(Event::Transcribed),
+            _ => None,
+        }
+    }
+}
+
+/// One `[[hooks]]` entry of hooks.toml
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    pub event: Event,
+    /// Program (on PATH, or a path) and its arguments
+    pub command: Vec<String>,
+}
+
+impl Hook {
+    fn describe(&self) -> String {
+        self.command.join(" ")
+    }
+}
+
+/// `~/.config/whisp-away/hooks.toml`
+pub fn path() -> String {
+    format!("{}/whisp-away/hooks.toml", crate::helpers::config_dir())
+}
+
+fn timeout() -> Duration {
+    let secs = std::env::var("WA_HOOK_TIMEOUT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// A TOML array of strings, `["my-script", "--flag"]`
+fn parse_array(number: usize, value: &str) -> Result<Vec<String>> {
+    let inner = value
+        .trim()
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| anyhow!("line {}: expected an array like [\"my-script\", \"--flag\"], got {}", number + 1, value.trim()))?;
+    let mut items = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&inner[start..]);
+    items
+        .into_iter()
+        .map(str::trim)
+        // A trailing comma is fine
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            if !item.starts_with('"') {
+                return Err(anyhow!("line {}: expected a string, got {}", number + 1, item));
+            }
+            unquote(number, item)
+        })
+        .collect()
+}
+
+/// Parse hooks.toml: `[[hooks]]` entries, each with an `event` (`pre_type`
+/// or `transcribed`) and a `command` array. `#` starts a comment.
+pub fn parse(content: &str) -> Result<Vec<Hook>> {
+    let mut entries: Vec<(usize, Option<Event>, Option<Vec<String>>)> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[[hooks]]" {
+            entries.push((number, None, None));
+            continue;
+        }
+        let Some((_, event, command)) = entries.last_mut() else {
+            return Err(anyhow!("line {}: expected [[hooks]] before {:?}", number + 1, line));
+        };
+        let (key, value) = split_entry(line)
+            .ok_or_else(|| anyhow!("line {}: expected key = value, got {:?}", number + 1, line))?;
+        match key.trim() {
+            "event" => {
+                let name = unquote(number, value)?;
+                *event = Some(Event::parse(&name)
+                    .ok_or_else(|| anyhow!("line {}: unknown event {:?}, expected pre_type or transcribed", number + 1, name))?);
+            }
+            "command" => *command = Some(parse_array(number, value)?),
+            other => return Err(anyhow!("line {}: unknown key {:?}, expected event or command", number + 1, other)),
+        }
+    }
+    entries
+        .into_iter()
+        .map(|(number, event, command)| match (event, command) {
+            (Some(event), Some(command)) if !command.is_empty() => Ok(Hook { event, command }),
+            (None, _) => Err(anyhow!("line {}: [[hooks]] entry without an event", number + 1)),
+            _ => Err(anyhow!("line {}: [[hooks]] entry without a command", number + 1)),
+        })
+        .collect()
+}
+
+/// The configured hooks for `event`, in file order. No hooks.toml (or one
+/// that doesn't parse, which is warned about) means the event costs nothing.
+fn commands(event: Event) -> Vec<Hook> {
+    let Ok(content) = fs::read_to_string(path()) else {
+        return Vec::new();
+    };
+    match parse(&content) {
+        Ok(hooks) => hooks.into_iter().filter(|hook| hook.event == event).collect(),
+        Err(e) => {
+            eprintln!("WARNING: Ignoring {}: {:#}", path(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// Start a hook with `text` on stdin and the details of `run` in its
+/// environment. The text comes from an unlinked file rather than a pipe, so
+/// a hook that never reads it can't block us. A `background` hook is cut off
+/// from our output, so nothing reading it waits for the hook, and coreutils'
+/// `timeout` kills it even after whisp-away has exited.
+fn spawn(hook: &Hook, text: &str, run: &Run, backend: &str, background: bool) -> Result<Child> {
+    let input_path = crate::helpers::runtime_file(&format!("{}.txt", crate::recording::unique_stem("whisp-away-hook")));
+    fs::write(&input_path, text).context("Failed to write hook input")?;
+    let input = fs::File::open(&input_path);
+    let _ = fs::remove_file(&input_path);
+    
+    let (program, args) = hook.command.split_first().context("Hook has no command")?;
+    let mut cmd = if background {
+        let mut cmd = crate::child_env::command("timeout", &[]);
+        cmd.args(["-k", "2", &timeout().as_secs().to_string()])
+            .arg(program)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        cmd
+    } else {
+        let mut cmd = crate::child_env::command(program, &[]);
+        cmd.stdout(Stdio::piped());
+        cmd
+    };
+    cmd.args(args)
+        .env("WA_HOOK_EVENT", hook.event.name())
+        // The label may carry notes like "daemon"; hooks get the backend itself
+        .env("WA_BACKEND", backend.split_whitespace().next().unwrap_or(backend))
+        .env("WA_MODEL", run.model.as_deref().unwrap_or_default());
+    if let Some(duration) = run.duration {
+        cmd.env("WA_DURATION_MS", duration.as_millis().to_string());
+    }
+    cmd.stdin(input.context("Failed to open hook input")?)
+        .spawn()
+        .with_context(|| format!("Failed to run hook {}", hook.describe()))
+}
+
+/// Run `pre_type` hooks in order, each getting the text the previous one left.
+/// A hook that prints something replaces the text with it; one that prints
+/// nothing, fails or runs out of time leaves the text alone.
+pub fn pre_type(text: String, run: &Run, backend: &str) -> String {
+    run_pre_type(&commands(Event::PreType), text, run, backend)
+}
+
+fn run_pre_type(hooks: &[Hook], text: String, run: &Run, backend: &str) -> String {
+    let mut text = text;
+    for hook in hooks {
+        match filter(hook, &text, run, backend) {
+            Ok(output) if !output.trim().is_empty() => {
+                eprintln!("DEBUG: Hook {} rewrote the text", hook.describe());
+                text = output.trim_end_matches('\n').to_string();
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("WARNING: Ignoring pre_type hook {}: {:#}", hook.describe(), e),
+        }
+    }
+    text
+}
+
+/// Run one pre_type hook to completion and return what it printed
+fn filter(hook: &Hook, text: &str, run: &Run, backend: &str) -> Result<String> {
+    let mut child = spawn(hook, text, run, backend, false)?;
+    // Read on the side, so a hook printing more than a pipe holds still finishes
+    let mut stdout = child.stdout.take().context("Hook has no stdout")?;
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    
+    let deadline = Instant::now() + timeout();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("timed out after {:?}", timeout()));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    if !status.success() {
+        return Err(anyhow!("exited with {}", status));
+    }
+    reader
+        .join()
+        .map_err(|_| anyhow!("reading its output failed"))?
+        .context("Failed to read hook output")
+}
+
+/// Start `transcribed` hooks and return without waiting for them
+pub fn transcribed(text: &str, run: &Run, backend: &str) {
+    run_transcribed(&commands(Event::Transcribed), text, run, backend);
+}
+
+fn run_transcribed(hooks: &[Hook], text: &str, run: &Run, backend: &str) {
+    for hook in hooks {
+        match spawn(hook, text, run, backend, true) {
+            Ok(mut child) => {
+                eprintln!("DEBUG: Started hook {}", hook.describe());
+                // Reaped here while we live, by init otherwise
+                std::thread::spawn(move || {
+                    if let Ok(status) = child.wait() {
+                        if !status.success() {
+                            eprintln!("WARNING: transcribed hook exited with {}", status);
+                        }
+                    }
+                });
+            }
+            Err(e) => eprintln!("WARNING: Could not start transcribed hook {}: {:#}", hook.describe(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    
+    /// An executable shell script in a directory of its own
+    fn script(name: &str, body: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-hooks-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hook.sh");
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+    
+    fn hook(event: Event, script: &std::path::Path) -> Hook {
+        Hook { event, command: vec![script.display().to_string()] }
+    }
+    
+    fn run() -> Run {
+        let mut run = Run::new("en");
+        run.model = Some("base.en".to_string());
+        run.duration = Some(Duration::from_millis(1500));
+        run
+    }
+    
+    #[test]
+    fn parses_hooks_entries() {
+        let hooks = parse(r#"
+# Daily note
+[[hooks]]
+event = "transcribed"
+command = ["my-script", "--note, \"daily\"",]
+
+[[hooks]]
+command = ["llm-cleanup"]
+event = "pre_type"
+"#).unwrap();
+        assert_eq!(hooks, vec![
+            Hook { event: Event::Transcribed, command: vec!["my-script".to_string(), "--note, \"daily\"".to_string()] },
+            Hook { event: Event::PreType, command: vec!["llm-cleanup".to_string()] },
+        ]);
+        assert_eq!(parse("").unwrap(), Vec::new());
+    }
+    
+    #[test]
+    fn refuses_malformed_entries() {
+        for (content, expected) in [
+            ("event = \"pre_type\"", "expected [[hooks]]"),
+            ("[[hooks]]\nevent = \"after\"\ncommand = [\"x\"]", "unknown event"),
+            ("[[hooks]]\nevent = \"pre_type\"", "without a command"),
+            ("[[hooks]]\nevent = \"pre_type\"\ncommand = []", "without a command"),
+            ("[[hooks]]\ncommand = [\"x\"]", "without an event"),
+            ("[[hooks]]\nevent = \"pre_type\"\ncommand = \"x\"", "expected an array"),
+            ("[[hooks]]\nevent = \"pre_type\"\ncommand = [x]", "expected a string"),
+            ("[[hooks]]\nwhen = \"pre_type\"", "unknown key"),
+        ] {
+            let error = parse(content).unwrap_err();
+            assert!(format!("{:#}", error).contains(expected), "{:?}: {:#}", content, error);
+        }
+    }
+    
+    #[test]
+    fn pre_type_output_replaces_the_text() {
+        let upper = script("upper", "tr a-z A-Z; printf ' %s %s %s %s' \"$WA_HOOK_EVENT\" \"$WA_BACKEND\" \"$WA_MODEL\" \"$WA_DURATION_MS\"");
+        let silent = script("silent", "cat >/dev/null");
+        let failing = script("failing", "echo ignored; exit 3");
+        let hooks = [hook(Event::PreType, &upper), hook(Event::PreType, &silent), hook(Event::PreType, &failing)];
+        let text = run_pre_type(&hooks, "hello there".to_string(), &run(), "mock daemon, step 1/2");
+        assert_eq!(text, "HELLO THERE pre_type mock base.en 1500");
+    }
+    
+    #[test]
+    fn no_hooks_leave_the_text_alone() {
+        assert_eq!(run_pre_type(&[], "hello".to_string(), &run(), "mock"), "hello");
+    }
+    
+    #[test]
+    fn transcribed_hooks_get_the_text_in_the_background() {
+        let out = std::env::temp_dir().join(format!("whisp-away-test-{}-hooks-transcribed.txt", std::process::id()));
+        let _ = fs::remove_file(&out);
+        let note = script("note", &format!("{{ cat; echo \" $WA_HOOK_EVENT\"; }} > {}.tmp && mv {}.tmp {}", out.display(), out.display(), out.display()));
+        run_transcribed(&[hook(Event::Transcribed, &note)], "dear diary", &run(), "mock");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !out.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(fs::read_to_string(&out).unwrap(), "dear diary transcribed\n");
+        let _ = fs::remove_file(&out);
+    }
+}
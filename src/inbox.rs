@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Files left in an inbox this long are swept: their client went away
+/// before sending them
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// How often a daemon sweeps its inbox
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Directory a daemon owns for the recordings clients send it, named after
+/// its ready file (`daemon-<backend>.inbox`, `daemon-<name>.inbox`). It sits
+/// next to the ready file, unless recordings go somewhere other than the
+/// runtime dir.
+pub fn path_for(ready_file: &str) -> String {
+    let next_to_ready = format!("{}.inbox", ready_file.strip_suffix(".ready").unwrap_or(ready_file));
+    let recordings_dir = crate::recording::recordings_dir();
+    if recordings_dir == crate::helpers::runtime_dir() {
+        return next_to_ready;
+    }
+    let name = Path::new(&next_to_ready).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or(next_to_ready);
+    format!("{}/{}", recordings_dir, name)
+}
+
+/// Whether `audio_file` sits directly in `inbox`. Anything else, `..` included,
+/// isn't the daemon's to delete.
+pub fn contains(inbox: &str, audio_file: &str) -> bool {
+    Path::new(audio_file).parent() == Some(Path::new(inbox))
+}
+
+/// Daemon side: create the inbox for `ready_file`, readable by this user
+/// only, clear out what an earlier run left and keep sweeping it
+pub fn open(ready_file: &str) -> Result<String> {
+    let inbox = path_for(ready_file);
+    fs::create_dir_all(&inbox).with_context(|| format!("Failed to create inbox {}", inbox))?;
+    fs::set_permissions(&inbox, fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("Failed to restrict inbox {}", inbox))?;
+    sweep(&inbox);
+    
+    let swept = inbox.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SWEEP_INTERVAL);
+        sweep(&swept);
+    });
+    Ok(inbox)
+}
+
+/// Delete files older than MAX_AGE
+fn sweep(inbox: &str) {
+    let mut swept = 0;
+    for entry in fs::read_dir(inbox).into_iter().flatten().flatten() {
+        let stale = entry
+            .metadata()
+            .ok()
+            .filter(|meta| meta.is_file())
+            .and_then(|meta| meta.modified().ok())
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > MAX_AGE);
+        if stale && fs::remove_file(entry.path()).is_ok() {
+            swept += 1;
+        }
+    }
+    if swept > 0 {
+        eprintln!("DEBUG: Swept {} stale file(s) from {}", swept, inbox);
+    }
+}
+
+/// A managed recording the daemon is handling, deleted once it's done with it
+pub struct Claim {
+    path: String,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Daemon side: take over deleting a request's audio when the client marked
+/// it `managed` and it is in this daemon's inbox
+pub fn claim(inbox: Option<&str>, managed: bool, audio_path: &str) -> Option<Claim> {
+    let inbox = inbox?;
+    if !managed {
+        return None;
+    }
+    if !contains(inbox, audio_path) {
+        eprintln!("WARNING: Not deleting {}: managed requests must be in {}", audio_path, inbox);
+        return None;
+    }
+    Some(Claim { path: audio_path.to_string() })
+}
+
+/// Client side: the inbox of the daemon with `ready_file`, when that daemon
+/// is up and advertises one
+pub fn advertised(ready_file: &str) -> Option<String> {
+    crate::ready::read(ready_file)
+        .filter(|info| crate::helpers::is_process_running(info.pid))
+        .and_then(|info| info.inbox)
+        .filter(|inbox| Path::new(inbox).is_dir())
+}
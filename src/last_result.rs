@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use crate::helpers::{self, OutputMode};
+use crate::run::Run;
+
+/// The most recent transcription, for scripts that can't capture the output
+/// of a `stop` bound to a hotkey
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastResult {
+    /// Local time the text was output, "YYYY-MM-DD HH:MM:SS"
+    pub time: String,
+    pub text: String,
+    /// What produced the text, as shown in the notification ("whisper-cpp daemon", ...)
+    pub backend: String,
+    pub model: String,
+    /// Whether the text was typed or only copied to the clipboard
+    pub output_mode: OutputMode,
+    /// Language transcription was asked for, "auto" to detect it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Language whisper reported, when it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    /// Length of the recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_seconds: Option<f64>,
+    /// Words in `text`
+    #[serde(default)]
+    pub words: usize,
+    /// `words` over the length of the recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words_per_minute: Option<f64>,
+    /// From sending the recording off until the text was output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcription_ms: Option<u64>,
+    /// Window the text went to, as `window_app_id` and `window_title`
+    #[serde(flatten)]
+    pub window: Option<crate::focus::TargetWindow>,
+}
+
+pub fn path() -> String {
+    helpers::runtime_file("whisp-away/last-result.json")
+}
+
+/// Whether transcripts are kept out of the result file (WA_PRIVACY_MODE=1)
+fn privacy_mode() -> bool {
+    matches!(std::env::var("WA_PRIVACY_MODE").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Replace the result file with `result`, readable only by the user. It is
+/// written next to its final name and renamed over it, so readers never see
+/// half a file.
+fn write(result: &LastResult) -> Result<()> {
+    let path = path();
+    if let Some(dir) = std::path::Path::new(&path).parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Per process, so overlapping stops don't write into each other's file
+    let tmp = format!("{}.{}.tmp", path, std::process::id());
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .with_context(|| format!("Failed to write {}", tmp))?;
+    file.write_all(serde_json::to_string_pretty(result)?.as_bytes())
+        .with_context(|| format!("Failed to write {}", tmp))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to move {} into place", path))
+}
+
+/// Record the text just output by `run`. Called while this dictation holds
+/// its turn to type, so of overlapping stops the one that typed last is kept.
+pub fn record(text: &str, backend: &str, output_mode: OutputMode, run: &Run) {
+    if privacy_mode() {
+        let _ = fs::remove_file(path());
+        return;
+    }
+    let stats = crate::speech::Stats::of(text, run.duration);
+    let result = LastResult {
+        time: helpers::local_timestamp(),
+        text: text.to_string(),
+        backend: backend.to_string(),
+        model: run.model.clone().unwrap_or_default(),
+        output_mode,
+        language: Some(run.language.clone()),
+        detected_language: run.detected_language.clone(),
+        audio_seconds: stats.as_ref().map(|stats| stats.duration.as_secs_f64()),
+        words: crate::speech::word_count(text),
+        words_per_minute: stats.and_then(|stats| stats.words_per_minute),
+        transcription_ms: run.transcribing_since.map(|at| at.elapsed().as_millis() as u64),
+        window: run.target.clone(),
+    };
+    if let Err(e) = write(&result) {
+        eprintln!("WARNING: Could not write the last result: {:#}", e);
+    }
+}
+
+/// Print the last result as JSON, or just one of its fields
+pub fn print(field: Option<&str>) -> Result<()> {
+    let path = path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(anyhow!("No transcription result yet at {} (none is kept with WA_PRIVACY_MODE=1)", path));
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path)),
+    };
+    let result: serde_json::Value = serde_json::from_str(&content).with_context(|| format!("Invalid result file {}", path))?;
+    
+    let Some(field) = field else {
+        println!("{}", content.trim_end());
+        return Ok(());
+    };
+    match result.get(field) {
+        // Strings bare, so `$(whisp-away last --field text)` is just the text
+        Some(serde_json::Value::String(value)) => println!("{}", value),
+        Some(value) => println!("{}", value),
+        None => {
+            let fields: Vec<&str> = result.as_object().map(|object| object.keys().map(String::as_str).collect()).unwrap_or_default();
+            return Err(anyhow!("No field {:?} in the last result (has: {})", field, fields.join(", ")));
+        }
+    }
+    Ok(())
+}
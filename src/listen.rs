@@ -0,0 +1,251 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
+use crate::dictation::{Capture, FRAME_SAMPLES};
+use crate::notify;
+use crate::socket;
+use crate::run::Run;
+use crate::typing;
+use crate::wav::{self, TARGET_RATE};
+
+/// Length of the window the wake model listens to (2 s)
+const WAKE_WINDOW_SAMPLES: usize = 2 * TARGET_RATE as usize;
+/// Quiet frames kept before a loud one so the start of the phrase isn't cut off
+const PRE_ROLL_FRAMES: usize = 3;
+/// Quiet frames that end a dictation after the wake phrase (1.5 s)
+const END_SILENCE_FRAMES: usize = 15;
+/// Longest dictation recorded after one wake phrase (60 s)
+const MAX_UTTERANCE_SAMPLES: usize = 60 * TARGET_RATE as usize;
+/// Shortest dictation worth transcribing (0.3 s)
+const MIN_UTTERANCE_SAMPLES: usize = 3 * FRAME_SAMPLES;
+
+/// Default wake phrase, model and sensitivity when neither flags nor env vars set them
+pub const DEFAULT_PHRASE: &str = "hey whisper";
+pub const DEFAULT_WAKE_MODEL: &str = "tiny.en";
+pub const DEFAULT_SENSITIVITY: f32 = 0.5;
+
+/// Configuration of `listen`, as given on the command line
+pub struct ListenOptions {
+    /// Small whisper.cpp model used only to spot the wake phrase
+    pub wake_model: String,
+    pub phrase: String,
+    /// 0.0 (strict) ..= 1.0 (eager); lowers the energy gate and accepts partial matches
+    pub sensitivity: f32,
+    /// Backend and model that transcribe the dictation itself
+    pub backend: String,
+    pub model: Option<String>,
+    pub language: String,
+    /// `--prompt-file`, over the focused application's glossary
+    pub prompt_file: Option<String>,
+    pub socket_path: String,
+    pub ready_file: String,
+    pub delivery: typing::Delivery,
+}
+
+/// Energy a frame needs to count as speech. This gate is all that runs while
+/// the room is quiet, which keeps idle CPU usage negligible.
+pub fn speech_threshold(sensitivity: f32) -> f32 {
+    0.003 + 0.03 * (1.0 - sensitivity.clamp(0.0, 1.0))
+}
+
+/// Lowercase alphanumeric words of a transcript
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether a transcript of a wake window contains the phrase. Higher sensitivity
+/// accepts partial matches, since tiny models often mishear one of the words.
+pub fn matches_phrase(transcript: &str, phrase: &str, sensitivity: f32) -> bool {
+    let wanted = words(phrase);
+    if wanted.is_empty() {
+        return false;
+    }
+    let heard = words(transcript);
+    let found = wanted.iter().filter(|w| heard.contains(w)).count();
+    found as f32 / wanted.len() as f32 >= 1.0 - sensitivity.clamp(0.0, 1.0) * 0.5
+}
+
+/// Keyword spotter: a small whisper model kept loaded next to (not instead of)
+/// the dictation model, run on CPU with few threads
+struct WakeSpotter {
+    ctx: WhisperContext,
+}
+
+impl WakeSpotter {
+    fn load(model: &str) -> Result<Self> {
+        let model_path = crate::helpers::whisper_cpp_model_path(model);
+        if !std::path::Path::new(&model_path).exists() && crate::helpers::is_model_path(model) {
+            return Err(anyhow!("Wake model file not found: {}", model_path));
+        }
+        if !std::path::Path::new(&model_path).exists() {
+            return Err(anyhow!("Wake model not found: {} (download it with: download-whisper-model {})", model_path, model));
+        }
+        crate::whisper_cpp::model::validate_model(&model_path, model)?;
+        
+        let mut ctx_params = WhisperContextParameters::default();
+        ctx_params.use_gpu(false);
+        let ctx = WhisperContext::new_with_params(&model_path, ctx_params)
+            .context("Failed to load wake model")?;
+        Ok(Self { ctx })
+    }
+    
+    fn transcribe(&self, samples: &[f32]) -> Result<String> {
+        let mut state = self.ctx.create_state()
+            .context("Failed to create whisper state")?;
+        
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_n_threads(2);
+        params.set_language(Some("en"));
+        params.set_single_segment(true);
+        params.set_no_context(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_timestamps(false);
+        params.set_suppress_blank(true);
+        params.set_suppress_nst(true);
+        
+        state.full(params, samples)
+            .context("Failed to run wake model")?;
+        
+        let segments: Vec<String> = (0..state.full_n_segments())
+            .filter_map(|i| state.get_segment(i))
+            .map(|segment| segment.to_str().unwrap_or_default().to_string())
+            .collect();
+        Ok(crate::normalize::join_segments(segments.iter().map(String::as_str)).trim().to_string())
+    }
+}
+
+/// Record after the wake phrase until END_SILENCE_FRAMES of quiet or the length cap
+fn record_utterance(capture: &mut Capture, threshold: f32) -> Result<Vec<f32>> {
+    let mut samples = Vec::new();
+    let mut quiet = 0;
+    let mut heard_speech = false;
+    while samples.len() < MAX_UTTERANCE_SAMPLES {
+        let frame = match capture.next_frame()? {
+            Some(frame) => frame,
+            None => break,
+        };
+        if wav::rms(&frame) < threshold {
+            quiet += 1;
+            // Give the user a moment to start talking before giving up
+            if quiet >= END_SILENCE_FRAMES * if heard_speech { 1 } else { 2 } {
+                break;
+            }
+        } else {
+            quiet = 0;
+            heard_speech = true;
+        }
+        samples.extend(frame);
+    }
+    if !heard_speech {
+        samples.clear();
+    }
+    Ok(samples)
+}
+
+/// Transcribe a dictation through the running daemon, or directly if there is none, and type it
+fn transcribe_and_type(samples: &[f32], options: &ListenOptions) -> Result<()> {
+    let audio_file = crate::recording::spool_file(&format!("voice-listen-{}.wav", std::process::id()));
+    fs::write(&audio_file, wav::encode_s16_mono(samples, TARGET_RATE))
+        .context("Failed to write dictation audio")?;
+    
+    let daemon = socket::DaemonOptions {
+        socket_path: options.socket_path.clone(),
+        use_running_backend: true,
+        ready_file: options.ready_file.clone(),
+        require_ready: false,
+        no_queue: false,
+        timeout: None,
+        hand_over: true,
+    };
+    // Each dictation is a run of its own
+    let mut run = Run::new(&options.language);
+    run.started(&crate::helpers::resolve_model(options.model.clone(), None));
+    let result = match socket::send_transcription_request(
+        &daemon,
+        &audio_file,
+        &options.delivery,
+        &mut run,
+        &options.backend,
+        options.prompt_file.as_deref(),
+        None,
+    ) {
+        Ok(()) => Ok(()),
+        Err(e) if e.downcast_ref::<typing::TypingError>().is_some() => Err(e),
+        Err(e) => {
+            eprintln!("DEBUG: {}, transcribing directly", e);
+            run.prompt = crate::prompt::current(options.prompt_file.as_deref(), options.delivery.profile.as_deref());
+            crate::transcribe::transcribe_direct(&options.backend, &audio_file, options.model.clone(), &options.language, run.prompt.as_deref())
+                .and_then(|text| typing::type_text(&text, &options.delivery, &mut run, &format!("{} (listen)", options.backend)))
+                .map(|_| ())
+        }
+    };
+    // Don't hold up other dictations when this one never got to typing
+    crate::order::finish_turn();
+    let _ = fs::remove_file(&audio_file);
+    result
+}
+
+/// Listen for the wake phrase, then record, transcribe and type what follows.
+/// Runs until pw-record goes away or the process is killed.
+pub fn run(options: ListenOptions) -> Result<()> {
+    let spotter = WakeSpotter::load(&options.wake_model)?;
+    let threshold = speech_threshold(options.sensitivity);
+    eprintln!(
+        "DEBUG: Listening for {:?} (wake model {}, sensitivity {}, threshold {:.4})",
+        options.phrase, options.wake_model, options.sensitivity, threshold
+    );
+    
+    notify::Notification::normal("Voice Input", &format!("👂 Listening for \"{}\"", options.phrase))
+        .send();
+    
+    let mut capture = Capture::spawn()?;
+    let mut pre_roll: Vec<Vec<f32>> = Vec::new();
+    loop {
+        let frame = match capture.next_frame()? {
+            Some(frame) => frame,
+            None => return Err(anyhow!("pw-record stopped")),
+        };
+        
+        if wav::rms(&frame) < threshold {
+            pre_roll.push(frame);
+            if pre_roll.len() > PRE_ROLL_FRAMES {
+                pre_roll.remove(0);
+            }
+            continue;
+        }
+        
+        // Something loud: fill a wake window and let the wake model have a look
+        let mut window: Vec<f32> = pre_roll.drain(..).flatten().collect();
+        window.extend(frame);
+        while window.len() < WAKE_WINDOW_SAMPLES {
+            match capture.next_frame()? {
+                Some(frame) => window.extend(frame),
+                None => break,
+            }
+        }
+        
+        let heard = spotter.transcribe(&window)?;
+        eprintln!("DEBUG: Wake window: {:?}", heard);
+        if !matches_phrase(&heard, &options.phrase, options.sensitivity) {
+            continue;
+        }
+        
+        notify::Notification::transient("Voice Input", "🎙️ Wake phrase heard, speak now")
+            .send();
+        let utterance = record_utterance(&mut capture, threshold)?;
+        
+        // Audio piles up in the pipe while transcribing, so start a fresh capture afterwards
+        drop(capture);
+        if utterance.len() < MIN_UTTERANCE_SAMPLES {
+            notify::Notification::transient("Voice Input", "⚠️ Nothing heard after the wake phrase")
+                .send();
+        } else if let Err(e) = transcribe_and_type(&utterance, &options) {
+            eprintln!("Listen: transcription failed: {}", e);
+        }
+        capture = Capture::spawn()?;
+    }
+}
@@ -1,20 +1,87 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 
 mod tray;
 mod helpers;
 mod recording;
 mod typing;
 mod socket;
+mod notify;
+mod protocol;
+mod wav;
+mod audio;
+mod flac;
+mod artifacts;
+mod normalize;
+mod speech;
+mod prompt;
+mod child_env;
+mod client;
+mod chain;
+mod ready;
+mod daemons;
+mod direct_lock;
+mod history;
+mod crypt;
+mod last_result;
+mod result_sink;
+mod run;
+mod hooks;
+mod events;
+mod order;
+mod debounce;
+mod purge;
+mod setup;
+mod replay;
+mod compare;
+mod focus;
+mod transcribe;
+mod subtitles;
+mod punctuation;
+mod replacements;
+mod listen;
+mod dictation;
+mod profile;
+mod retry;
+mod upgrade;
+mod bundle;
+mod tarball;
+mod inbox;
+mod cli_docs;
 mod whisper_cpp;
 mod faster_whisper;
+#[cfg(feature = "mock")]
+mod mock;
 
 #[derive(Parser)]
 #[command(name = "whisp-away")]
 #[command(about = "Simple dictation tool using whisper.cpp or faster-whisper", long_about = None)]
+#[command(arg_required_else_help = true)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+    
+    /// Print the command tree (subcommands, flags, defaults) as JSON and exit
+    #[arg(long, hide = true)]
+    dump_cli_json: bool,
+    
+    /// Give child processes our full environment instead of an allowlist (same as WA_INHERIT_ENV=1)
+    #[arg(long, global = true)]
+    inherit_env: bool,
+    
+    /// Where notifications go: auto prints them to stderr when it is a
+    /// terminal and shows them otherwise (overrides WA_NOTIFY env var)
+    #[arg(long, global = true, value_enum)]
+    notify: Option<notify::Route>,
+    
+    /// Show desktop notifications even when run from a terminal (same as --notify desktop)
+    #[arg(long, global = true, conflicts_with = "notify")]
+    force_notify: bool,
+    
+    /// Write notifications and tray menu text without emoji, for screen
+    /// readers (same as WA_NO_EMOJI=1)
+    #[arg(long, global = true)]
+    no_emoji: bool,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -28,6 +95,10 @@ enum Backend {
     /// Use the backend defined in the tray state
     #[value(name = "tray-defined", alias = "tray")]
     TrayDefined,
+    /// Fake backend that answers instantly without a model (development/CI)
+    #[cfg(feature = "mock")]
+    #[value(name = "mock")]
+    Mock,
 }
 
 #[derive(Subcommand)]
@@ -37,6 +108,19 @@ enum Commands {
         /// Backend to use for transcription
         #[arg(short, long, default_value = "tray")]
         backend: Backend,
+        
+        /// Settings profile, $XDG_CONFIG_HOME/whisp-away/profiles/<PROFILE>.conf, whose
+        /// values win over the tray's (overrides the tray's profile and WA_PROFILE env var)
+        #[arg(long)]
+        profile: Option<String>,
+        
+        /// Audio source to record: microphone, speaker monitor, or both mixed
+        #[arg(short, long, value_enum, default_value = "mic")]
+        source: recording::AudioSource,
+        
+        /// Wait this many milliseconds before recording, e.g. for a hotkey sound (at most 2000)
+        #[arg(long, default_value_t = 0)]
+        delay_start: u64,
     },
     
     /// Stop recording and transcribe
@@ -45,29 +129,369 @@ enum Commands {
         #[arg(short, long, default_value = "tray")]
         backend: Backend,
         
-        /// Use whisper-rs bindings for fallback (default: true, whisper-cpp only)
-        #[arg(long, default_value_t = true)]
+        /// Settings profile, $XDG_CONFIG_HOME/whisp-away/profiles/<PROFILE>.conf, whose
+        /// values win over the tray's (overrides the tray's profile and WA_PROFILE env var)
+        #[arg(long)]
+        profile: Option<String>,
+        
+        /// Use whisper-rs bindings for fallback; `--bindings false` runs the whisper.cpp
+        /// CLI instead (default: true, whisper-cpp only)
+        #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
         bindings: bool,
         
         /// Model to use for transcription (overrides WA_WHISPER_MODEL env var)
         #[arg(short, long)]
         model: Option<String>,
         
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
         /// Path to wtype binary
         #[arg(long, default_value = "wtype")]
         wtype_path: String,
         
+        /// Type the transcript or only copy it to the clipboard (overrides the tray
+        /// toggle and WA_OUTPUT_MODE env var)
+        #[arg(long, value_enum)]
+        output: Option<helpers::OutputMode>,
+        
+        /// Show the transcript in the success notification: none, firstline, full or
+        /// full(MAXCHARS) (overrides WA_NOTIFY_PREVIEW env var)
+        #[arg(long)]
+        notify_preview: Option<typing::NotifyPreview>,
+        
+        /// Glossary of terms to prime whisper with, one per line (overrides the focused
+        /// application's glossary and WA_PROMPT_FILE env var)
+        #[arg(long)]
+        prompt_file: Option<String>,
+        
+        /// Milliseconds cut from the start of the recording, e.g. a hotkey click
+        /// (overrides the tray's recording preset and WA_TRIM_LEAD_MS, default 150; 0 keeps everything)
+        #[arg(long)]
+        trim_lead: Option<u64>,
+        
+        /// Recordings shorter than this many milliseconds are dropped without
+        /// transcribing (overrides the tray's recording preset and WA_MIN_DURATION_MS,
+        /// default 400; 0 disables)
+        #[arg(long)]
+        min_duration: Option<u64>,
+        
         /// Optional audio file to transcribe (instead of recorded audio)
         #[arg(short, long)]
         audio_file: Option<String>,
         
-        /// Unix socket path for daemon communication
+        /// Unix socket path for daemon communication (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
         #[arg(long)]
         socket_path: Option<String>,
         
         /// Path to whisper.cpp binary (for whisper-cpp backend)
         #[arg(long)]
         whisper_path: Option<String>,
+        
+        /// CPU threads whisper uses (overrides WA_THREADS env var; default: all of them)
+        #[arg(long)]
+        threads: Option<usize>,
+        
+        /// Daemon to use: "default", a --name given to `daemon`, or "auto" to pick one
+        /// running the requested model (overrides WA_DAEMON env var)
+        #[arg(long)]
+        daemon: Option<String>,
+        
+        /// Daemon serving `daemon --listen` to send the audio to, as tcp://HOST:PORT
+        /// (presents WA_DAEMON_TOKEN, or the token a local daemon generated)
+        #[arg(long, conflicts_with_all = ["socket_path", "daemon"])]
+        daemon_url: Option<String>,
+        
+        /// Use the running daemon even if it runs a different backend than --backend
+        #[arg(long)]
+        use_running_backend: bool,
+        
+        /// Only use the daemon if its ready file says it is serving; report why not otherwise
+        #[arg(long)]
+        require_ready: bool,
+        
+        /// Ready file to check with --require-ready (default: the selected daemon's,
+        /// $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.ready for the unnamed one)
+        #[arg(long)]
+        ready_file: Option<String>,
+        
+        /// Drop the dictation with a notification if the daemon is still busy with
+        /// earlier requests, instead of waiting in its queue
+        #[arg(long)]
+        no_queue: bool,
+        
+        /// Name the backend and model in use, and where each setting came from, in the
+        /// notifications (same as WA_VERBOSE_NOTIFY=1)
+        #[arg(long)]
+        verbose_notify: bool,
+        
+        /// Show how the backend, model and language are resolved and exit without stopping
+        #[arg(long)]
+        print_resolution: bool,
+        
+        /// Archive kept recordings (WA_KEEP_AUDIO=1) and calls as wav or flac, compressed
+        /// after transcription (overrides WA_AUDIO_FORMAT env var)
+        #[arg(long, value_enum)]
+        audio_format: Option<history::AudioFormat>,
+        
+        /// Transcribe a short command with the daemon's fast model (`daemon --fast-model`),
+        /// skipping VAD, preprocessing and the glossary, and report the turnaround
+        #[arg(long)]
+        fast: bool,
+        
+        /// Transcribe on the CPU, e.g. while the GPU driver hangs: the daemon uses
+        /// a CPU-only copy of its model, and the fallback never touches the GPU
+        #[arg(long)]
+        no_gpu: bool,
+        
+        /// Leave a kind of step out of the fallback chain (WA_FALLBACK_CHAIN or the
+        /// profile's fallback_chain; default: daemon, then direct). Repeatable
+        #[arg(long, value_enum)]
+        skip: Vec<chain::StepKind>,
+        
+        /// Write the result (text, backend, timings, error code) as one JSON document to
+        /// this inherited file descriptor, which is closed after it and nothing else uses
+        #[arg(long, value_name = "N")]
+        result_fd: Option<i32>,
+        
+        /// Write the result as with --result-fd to this file instead, replacing it
+        #[arg(long, value_name = "PATH", conflicts_with = "result_fd")]
+        result_file: Option<String>,
+    },
+    
+    /// Start recording, or stop and transcribe with --fast if already recording:
+    /// one hotkey for short commands like "next tab"
+    Quick {
+        /// Backend to use for transcription
+        #[arg(short, long, default_value = "tray")]
+        backend: Backend,
+        
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// Path to wtype binary
+        #[arg(long, default_value = "wtype")]
+        wtype_path: String,
+        
+        /// Unix socket path for daemon communication (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
+        #[arg(long)]
+        socket_path: Option<String>,
+        
+        /// Daemon to use: "default", a --name given to `daemon`, or "auto" to pick one
+        /// running the requested model (overrides WA_DAEMON env var)
+        #[arg(long)]
+        daemon: Option<String>,
+    },
+    
+    /// Transcribe an audio file or stdin and print the text (for scripting)
+    ///
+    /// Exit codes: 0 on success, 1 on transcription failure, 2 for invalid or
+    /// too-short input, 3 when no speech was detected.
+    Transcribe {
+        /// Audio file to transcribe
+        #[arg(required_unless_present = "stdin", conflicts_with = "stdin")]
+        file: Option<String>,
+        
+        /// Read audio from stdin instead of a file
+        #[arg(long)]
+        stdin: bool,
+        
+        /// Input encoding
+        #[arg(short, long, value_enum, default_value = "wav")]
+        format: transcribe::InputFormat,
+        
+        /// Sample rate of raw input
+        #[arg(short, long, default_value_t = 16000)]
+        rate: u32,
+        
+        /// Backend to use for transcription
+        #[arg(short, long, default_value = "tray")]
+        backend: Backend,
+        
+        /// Model to use for transcription (overrides WA_WHISPER_MODEL env var)
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// Unix socket path for daemon communication (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
+        #[arg(long)]
+        socket_path: Option<String>,
+        
+        /// Daemon to use: "default", a --name given to `daemon`, or "auto" to pick one
+        /// running the requested model (overrides WA_DAEMON env var)
+        #[arg(long)]
+        daemon: Option<String>,
+        
+        /// Daemon serving `daemon --listen` to send the audio to, as tcp://HOST:PORT
+        /// (presents WA_DAEMON_TOKEN, or the token a local daemon generated)
+        #[arg(long, conflicts_with_all = ["socket_path", "daemon"])]
+        daemon_url: Option<String>,
+        
+        /// Let the daemon accept audio longer than its --max-audio-seconds
+        #[arg(long)]
+        allow_long: bool,
+        
+        /// Queue class in the daemon: interactive requests go before batch ones
+        #[arg(long, value_enum, default_value = "interactive")]
+        priority: protocol::Priority,
+        
+        /// Same as --priority batch
+        #[arg(long)]
+        low_priority: bool,
+        
+        /// Glossary of terms to prime whisper with, one per line (overrides the focused
+        /// application's glossary and WA_PROMPT_FILE env var)
+        #[arg(long)]
+        prompt_file: Option<String>,
+        
+        /// Treat a 2-channel WAV as mic (left) and call audio (right), transcribe each
+        /// channel separately and print the conversation as "Me:"/"Them:" turns
+        #[arg(long, conflicts_with_all = ["format", "rate"])]
+        diarize_channels: bool,
+        
+        /// Print the --diarize-channels conversation as SRT subtitles
+        #[arg(long, requires = "diarize_channels")]
+        srt: bool,
+        
+        /// What to print: the text, SRT or WebVTT subtitles with a cue per segment, or
+        /// JSON with each segment's timing and confidence
+        #[arg(long, value_enum, default_value = "text", conflicts_with = "diarize_channels")]
+        output_format: subtitles::OutputFormat,
+        
+        /// How subtitle cues show whisper's confidence: not at all, its value, or
+        /// wrapped in markers below the threshold (overrides WA_CONFIDENCE_MARK env var)
+        #[arg(long, value_enum)]
+        confidence_mark: Option<subtitles::ConfidenceMark>,
+        
+        /// Mean token probability below which `wrap` marks a cue, 0 to 1
+        /// (overrides WA_CONFIDENCE_THRESHOLD env var, default 0.6)
+        #[arg(long)]
+        confidence_threshold: Option<f32>,
+        
+        /// Markers `wrap` puts around low-confidence cues, as OPEN,CLOSE
+        /// (overrides WA_CONFIDENCE_MARKERS env var, default "<i>,</i>")
+        #[arg(long)]
+        confidence_markers: Option<String>,
+    },
+    
+    /// Listen for a wake phrase, then record, transcribe and type what follows
+    Listen {
+        /// Wake phrase (overrides WA_WAKE_PHRASE env var, default "hey whisper")
+        #[arg(short, long)]
+        phrase: Option<String>,
+        
+        /// 0.0 (strict) to 1.0 (eager) (overrides WA_WAKE_SENSITIVITY env var, default 0.5)
+        #[arg(short, long)]
+        sensitivity: Option<f32>,
+        
+        /// Small whisper.cpp model used only to spot the wake phrase (overrides WA_WAKE_MODEL env var, default tiny.en)
+        #[arg(short, long)]
+        wake_model: Option<String>,
+        
+        /// Backend to use for transcribing the dictation
+        #[arg(short, long, default_value = "tray")]
+        backend: Backend,
+        
+        /// Model to use for transcription (overrides WA_WHISPER_MODEL env var)
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// Path to wtype binary
+        #[arg(long, default_value = "wtype")]
+        wtype_path: String,
+        
+        /// Type the transcript or only copy it to the clipboard (overrides the tray
+        /// toggle and WA_OUTPUT_MODE env var)
+        #[arg(long, value_enum)]
+        output: Option<helpers::OutputMode>,
+        
+        /// Show the transcript in the success notification: none, firstline, full or
+        /// full(MAXCHARS) (overrides WA_NOTIFY_PREVIEW env var)
+        #[arg(long)]
+        notify_preview: Option<typing::NotifyPreview>,
+        
+        /// Glossary of terms to prime whisper with, one per line (overrides the focused
+        /// application's glossary and WA_PROMPT_FILE env var)
+        #[arg(long)]
+        prompt_file: Option<String>,
+        
+        /// Unix socket path for daemon communication (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
+        #[arg(long)]
+        socket_path: Option<String>,
+        
+        /// Daemon to use: "default", a --name given to `daemon`, or "auto" to pick one
+        /// running the requested model (overrides WA_DAEMON env var)
+        #[arg(long)]
+        daemon: Option<String>,
+    },
+    
+    /// Dictate in the terminal: shows the input level and each phrase as it's
+    /// transcribed, and prints the whole text once Enter is pressed
+    Dictate {
+        /// Backend to use for transcription
+        #[arg(short, long, default_value = "tray")]
+        backend: Backend,
+        
+        /// Model to use for transcription (overrides WA_WHISPER_MODEL env var)
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// Unix socket path for daemon communication (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
+        #[arg(long)]
+        socket_path: Option<String>,
+        
+        /// Daemon to use: "default", a --name given to `daemon`, or "auto" to pick one
+        /// running the requested model (overrides WA_DAEMON env var)
+        #[arg(long)]
+        daemon: Option<String>,
+    },
+    
+    /// Record the microphone to a WAV file without transcribing, e.g. an
+    /// interview to transcribe later. Runs until Ctrl-C or --max-duration and
+    /// fails when no audio was captured; dictation keeps working meanwhile.
+    Record {
+        /// WAV file to write (must not exist yet)
+        #[arg(short, long)]
+        out: String,
+        
+        /// Stop after this many seconds
+        #[arg(long)]
+        max_duration: Option<u64>,
+        
+        /// Source to record, a name from `whisp-away devices` (overrides WA_AUDIO_DEVICE env var)
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+    
+    /// Keep the recording notification updated and stop after a pause (spawned by `start`)
+    #[command(hide = true)]
+    RecordingTimer {
+        #[arg(long)]
+        pid: u32,
+        
+        #[arg(long)]
+        audio_file: String,
+        
+        #[arg(long)]
+        detail: String,
     },
     
     /// Run as a daemon server with model preloaded
@@ -76,13 +500,149 @@ enum Commands {
         #[arg(short, long, default_value = "tray")]
         backend: Backend,
         
+        /// Settings profile, $XDG_CONFIG_HOME/whisp-away/profiles/<PROFILE>.conf, whose
+        /// values win over the tray's (overrides the tray's profile and WA_PROFILE env var)
+        #[arg(long)]
+        profile: Option<String>,
+        
         /// Model to use (overrides WA_WHISPER_MODEL env var)
         #[arg(short, long)]
         model: Option<String>,
         
-        /// Unix socket path for daemon communication
+        /// Small model, e.g. tiny.en, to keep loaded next to --model for `quick` and
+        /// `stop --fast` (overrides the profile's fast_model and WA_FAST_MODEL env var;
+        /// whisper-cpp only)
+        #[arg(long)]
+        fast_model: Option<String>,
+        
+        /// Default language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// CPU threads whisper uses (overrides WA_THREADS env var; default: all of them)
+        #[arg(long)]
+        threads: Option<usize>,
+        
+        /// Unix socket path for daemon communication (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
         #[arg(long)]
         socket_path: Option<String>,
+        
+        /// Re-download the model if it's missing or fails validation (whisper-cpp only)
+        #[arg(long)]
+        auto_download: bool,
+        
+        /// Reject requests with longer audio unless they set allow_long (whisper-cpp only)
+        #[arg(long, default_value_t = 600)]
+        max_audio_seconds: u64,
+        
+        /// Write pid, socket and model as JSON here once serving, removed on shutdown
+        /// (default: $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.ready)
+        #[arg(long)]
+        ready_file: Option<String>,
+        
+        /// Run as a named daemon next to others, with its socket and ready file in
+        /// $XDG_RUNTIME_DIR/whisp-away/ (a bare --name means <backend>-<model>)
+        #[arg(long, num_args = 0..=1, default_missing_value = "")]
+        name: Option<String>,
+        
+        /// Load the model even if there clearly isn't enough free memory (same as WA_FORCE_LOAD=1)
+        #[arg(long)]
+        force: bool,
+        
+        /// Check the model is intact and fits in memory, then exit without serving
+        #[arg(long)]
+        check: bool,
+        
+        /// Fail instead of downloading a missing model (faster-whisper only, same as WA_NO_DOWNLOAD=1)
+        #[arg(long)]
+        no_download: bool,
+        
+        /// Also serve the protocol on tcp://IP:PORT for clients that can't reach the
+        /// Unix socket (whisper-cpp only). Clients must send audio inline and present
+        /// WA_DAEMON_TOKEN, or on loopback without it, the token generated into
+        /// $XDG_RUNTIME_DIR/whisp-away/daemon-token.
+        #[arg(long)]
+        listen: Option<String>,
+        
+        /// Allow --listen on an address other than loopback; requires WA_DAEMON_TOKEN
+        #[arg(long, requires = "listen")]
+        insecure_bind: bool,
+    },
+    
+    /// Show every running daemon with its backend, model and state
+    Status,
+    
+    /// Print recording and transcription events from the events FIFO
+    /// ($XDG_RUNTIME_DIR/whisp-away/events), for scripts on setups without DBus
+    Events {
+        /// Keep waiting for events instead of stopping when the writers close
+        #[arg(short, long)]
+        follow: bool,
+        
+        /// Print the JSON lines as written
+        #[arg(long)]
+        raw: bool,
+    },
+    
+    /// Print the most recent transcription as JSON (text, backend, model, timings,
+    /// output mode), from $XDG_RUNTIME_DIR/whisp-away/last-result.json
+    Last {
+        /// Print only this field, e.g. text
+        #[arg(long)]
+        field: Option<String>,
+    },
+    
+    /// Re-transcribe kept recordings (WA_KEEP_AUDIO=1) and diff them against what was typed
+    Replay {
+        /// Oldest recording to replay: a date like 2024-05-01, or a longer prefix of
+        /// "YYYY-MM-DD HH:MM:SS"
+        #[arg(long)]
+        since: String,
+        
+        /// Model to re-transcribe with (overrides WA_WHISPER_MODEL env var)
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// Second model to run and diff side by side with --model
+        #[arg(long)]
+        compare: Option<String>,
+        
+        /// Backend to transcribe with
+        #[arg(short, long, default_value = "tray")]
+        backend: Backend,
+        
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+    },
+    
+    /// Transcribe one file with several models and compare the transcripts,
+    /// without typing anything
+    Compare {
+        /// Audio file to transcribe
+        #[arg(long)]
+        audio: String,
+        
+        /// Models to compare, comma separated; the others are diffed against the largest
+        #[arg(long, value_delimiter = ',', required = true)]
+        models: Vec<String>,
+        
+        /// Backend to transcribe with
+        #[arg(short, long, default_value = "tray")]
+        backend: Backend,
+        
+        /// Language code, or "auto" to detect it (overrides WA_WHISPER_LANGUAGE env var)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// Print the report as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+        
+        /// Download models that aren't on disk instead of skipping them
+        #[arg(long)]
+        download: bool,
     },
     
     /// Run system tray icon for daemon control
@@ -90,77 +650,633 @@ enum Commands {
         /// Backend to monitor
         #[arg(short, long, default_value = "tray")]
         backend: Backend,
+        
+        /// Model the daemon starts with (overrides the saved tray state)
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// Language the daemon starts with (overrides the saved tray state)
+        #[arg(short, long)]
+        language: Option<String>,
+        
+        /// Start the daemon as soon as the tray is up
+        #[arg(long)]
+        autostart_daemon: bool,
+        
+        /// Replace a daemon a previous tray left running from another
+        /// whisp-away version, instead of only suggesting a restart
+        #[arg(long)]
+        auto_restart_on_upgrade: bool,
+        
+        /// Unix socket path for the tray's daemon, whichever the backend (overrides WA_WHISPER_SOCKET env var;
+        /// default: the backend's own, $XDG_RUNTIME_DIR/whisp-away/daemon-<BACKEND>.sock)
+        #[arg(long)]
+        socket_path: Option<String>,
+    },
+    
+    /// List audio sources, marking which ones are output monitors
+    Devices,
+    
+    /// Walk through first-run configuration: detect the session, pick a
+    /// microphone and backend, download a model, write the settings to
+    /// ~/.config/environment.d/60-whisp-away.conf, optionally install a user
+    /// service and autostart entry, then check everything with a test dictation
+    Setup {
+        /// Take the recommended answer to every question (for scripts)
+        #[arg(long)]
+        non_interactive: bool,
+        
+        /// Leave out a step (repeatable)
+        #[arg(long, value_enum)]
+        skip: Vec<setup::Step>,
+        
+        /// Run only this step, e.g. to redo it (repeatable)
+        #[arg(long, value_enum, conflicts_with = "skip")]
+        only: Vec<setup::Step>,
+        
+        /// Use this backend instead of asking
+        #[arg(short, long, value_parser = ["whisper-cpp", "faster-whisper"])]
+        backend: Option<String>,
+        
+        /// Use this model instead of asking
+        #[arg(short, long)]
+        model: Option<String>,
+        
+        /// Record from this source (a name from `devices`, or "default") instead of asking
+        #[arg(short, long)]
+        device: Option<String>,
+        
+        /// Write the settings to ~/.config/environment.d/60-whisp-away.conf without
+        /// asking; with --non-interactive they are only printed otherwise
+        #[arg(long)]
+        write_config: bool,
+    },
+    
+    /// Remove the files whisp-away leaves behind: runtime files, logs and caches,
+    /// and with --models / --history the downloaded models and kept recordings.
+    /// Glossaries in the config dir are never removed.
+    Purge {
+        /// Also remove downloaded whisper.cpp and faster-whisper models
+        #[arg(long)]
+        models: bool,
+        
+        /// Also remove kept recordings, the journal and saved calls
+        #[arg(long)]
+        history: bool,
+        
+        /// Same as --models --history
+        #[arg(long)]
+        all: bool,
+        
+        /// Print what would be removed, one "<kind>\t<path>" line each, and exit
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Stop running daemons and recordings first instead of refusing
+        #[arg(long)]
+        force: bool,
+        
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    
+    /// Inspect the journal of kept recordings (WA_KEEP_AUDIO=1)
+    Journal {
+        #[command(subcommand)]
+        action: JournalAction,
+    },
+    
+    /// Manage cached artifacts
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    
+    /// Move settings, profiles, glossaries and hooks between machines
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+    
+    /// Print the manpage (roff) to stdout
+    Man {
+        /// Write whisp-away.1 and a page per subcommand into this directory instead
+        #[arg(long)]
+        out_dir: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum JournalAction {
+    /// Print the most recent entry
+    Last {
+        /// Print only what was recorded: requested and actual format, device,
+        /// size, duration and pw-record's output
+        #[arg(long)]
+        audio: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Delete OpenVINO compile caches for models that no longer exist
+    Prune,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Bundle the environment.d settings `setup` writes, profiles, glossaries
+    /// and hooks into a .tar.gz
+    Export {
+        /// Bundle to write, e.g. whisp-away.tar.gz
+        file: String,
+        
+        /// Also bundle downloaded whisper.cpp and faster-whisper models
+        #[arg(long)]
+        with_models: bool,
+    },
+    
+    /// Unpack a bundle into this user's config and cache directories, asking
+    /// before replacing files that differ
+    Import {
+        /// Bundle made by `config export`
+        file: String,
+        
+        /// Replace differing files without asking
+        #[arg(long)]
+        overwrite: bool,
     },
 }
 
 /// Resolves the backend to use, handling TrayDefined case
-fn resolve_backend(backend: &Backend) -> String {
-    match backend {
-        Backend::WhisperCpp => "whisper-cpp".to_string(),
-        Backend::FasterWhisper => "faster-whisper".to_string(),
-        Backend::TrayDefined => {
-            // Check tray state first, then env var, then default
-            if let Some(state) = helpers::read_tray_state() {
-                state.backend
-            } else {
-                std::env::var("WA_WHISPER_BACKEND").unwrap_or_else(|_| "faster-whisper".to_string())
-            }
-        }
+fn resolve_backend(backend: &Backend, profile: Option<&str>) -> String {
+    backend_resolution(backend, profile).value
+}
+
+/// The backend with where it came from: an explicit --backend, else the
+/// active profile (`profile` from --profile), the tray state,
+/// WA_WHISPER_BACKEND, then the default
+fn backend_resolution(backend: &Backend, profile: Option<&str>) -> helpers::Resolution {
+    let explicit = match backend {
+        Backend::WhisperCpp => Some("whisper-cpp"),
+        Backend::FasterWhisper => Some("faster-whisper"),
+        #[cfg(feature = "mock")]
+        Backend::Mock => Some("mock"),
+        Backend::TrayDefined => None,
+    };
+    helpers::resolve_backend_source(explicit.map(str::to_string), profile)
+}
+
+/// Fail on a --profile that can't be read, so a typo on the command line
+/// doesn't silently fall back to other settings
+fn check_profile(profile: Option<&str>) -> Result<()> {
+    if let Some(name) = profile {
+        profile::load(name)?;
+    }
+    Ok(())
+}
+
+/// Daemon a client talks to: --daemon-url or --socket-path if given, otherwise
+/// the one --daemon selects
+fn daemon_target(daemon_url: Option<String>, socket_path: Option<String>, daemon: Option<String>, backend: &str, model: &str) -> Result<daemons::Target> {
+    if let Some(url) = daemon_url {
+        return Ok(daemons::Target {
+            socket_path: daemons::daemon_url(&url)?,
+            ready_file: daemons::default_ready_file(backend),
+            any_backend: false,
+        });
+    }
+    match socket_path {
+        Some(socket_path) => Ok(daemons::Target {
+            socket_path: daemons::resolve_socket(Some(socket_path), backend),
+            ready_file: daemons::default_ready_file(backend),
+            any_backend: false,
+        }),
+        None => daemons::select(&daemons::Selector::resolve(daemon), backend, model),
     }
 }
 
 fn main() -> Result<()> {
+    let mut sink = None;
+    let result = run(&mut sink);
+    // Nothing to write unless `stop --result-fd` or `--result-file` asked,
+    // and then also when stop gave up before transcribing
+    if let Some(pending) = sink {
+        pending.finish(&result);
+    }
+    result
+}
+
+/// Run the command; `sink` is where `stop` left its result to be written
+fn run(sink: &mut Option<result_sink::Pending>) -> Result<()> {
     let cli = Cli::parse();
 
-    match cli.command {
+    if cli.dump_cli_json {
+        return cli_docs::dump_json(Cli::command());
+    }
+    let Some(command) = cli.command else {
+        return Ok(());
+    };
+    // A held hotkey repeats its command many times a second; only the first
+    // of a burst does anything. Stopping with a given file is scripted, not a key.
+    let hotkey = match &command {
+        Commands::Start { .. } | Commands::Quick { .. } => true,
+        Commands::Stop { audio_file, .. } => audio_file.is_none(),
+        _ => false,
+    };
+    if hotkey && debounce::repeated(&std::env::args().skip(1).collect::<Vec<_>>().join(" ")) {
+        return Ok(());
+    }
+    
+    if cli.inherit_env {
+        // Set before any threads start; WA_* variables reach children, so a
+        // daemon spawned by the tray inherits the setting too
+        std::env::set_var("WA_INHERIT_ENV", "1");
+    }
+    if cli.no_emoji {
+        // Like --inherit-env, so the daemons the tray starts write their
+        // notifications the same way
+        std::env::set_var("WA_NO_EMOJI", "1");
+    }
+    if let Some(route) = cli.notify.or(cli.force_notify.then_some(notify::Route::Desktop)) {
+        // Like --no-emoji, so the daemons the tray starts route their
+        // notifications the same way
+        std::env::set_var("WA_NOTIFY", route.name());
+    }
+    
+    match command {
         // New unified commands
-        Commands::Start { backend } => {
+        Commands::Start { backend, profile, source, delay_start } => {
+            check_profile(profile.as_deref())?;
+            let profile = profile.as_deref();
+            let delay_start = std::time::Duration::from_millis(delay_start);
             // Resolve backend if TrayDefined
-            let resolved_backend = resolve_backend(&backend);
+            let resolved_backend = resolve_backend(&backend, profile);
             
             match resolved_backend.as_str() {
-                "whisper-cpp" => recording::start_recording("whisper-cpp"),
-                "faster-whisper" => recording::start_recording("faster-whisper"),
+                "whisper-cpp" => recording::start_recording("whisper-cpp", source, delay_start, profile),
+                "faster-whisper" => recording::start_recording("faster-whisper", source, delay_start, profile),
+                #[cfg(feature = "mock")]
+                "mock" => recording::start_recording("mock", source, delay_start, profile),
                 unknown => Err(anyhow::anyhow!("Unknown backend: {}", unknown)),
             }
         }
         
-        Commands::Stop { backend, bindings, model, wtype_path, audio_file, socket_path, whisper_path } => {
-            // Resolve backend (handles TrayDefined case)
-            let resolved_backend = resolve_backend(&backend);
+        Commands::Stop { backend, profile, bindings, model, language, wtype_path, output, notify_preview, prompt_file, trim_lead, min_duration, audio_file, socket_path, whisper_path, threads, daemon, daemon_url, use_running_backend, require_ready, ready_file, no_queue, verbose_notify, print_resolution, audio_format, fast, no_gpu, skip, result_fd, result_file } => {
+            // First, so the reported total and turnaround cover the whole stop
+            let fast_since = fast.then(std::time::Instant::now);
+            if let Some(fd) = result_fd {
+                *sink = Some(result_sink::Pending::new(result_sink::ResultSink::fd(fd)?));
+            } else if let Some(path) = result_file {
+                *sink = Some(result_sink::Pending::new(result_sink::ResultSink::File(path)));
+            }
+            if no_gpu {
+                // Read where contexts are created and requests built, like the env var it stands for
+                std::env::set_var("WA_NO_GPU", "1");
+            }
+            check_profile(profile.as_deref())?;
+            let profile = profile::follow_focus(profile.as_deref());
+            let profile = profile.as_deref();
+            // Resolve backend (handles TrayDefined case), keeping track of why
+            let backend = backend_resolution(&backend, profile);
+            let model_choice = helpers::resolve_model_source(model.clone(), profile);
+            let language_choice = helpers::resolve_language_source(language, profile);
+            let explanation = [
+                backend.describe("backend"),
+                model_choice.describe("model"),
+                language_choice.describe("language"),
+            ]
+            .join("\n");
+            if print_resolution {
+                println!("{}", explanation);
+                return Ok(());
+            }
+            eprintln!("DEBUG: Resolved settings:\n{}", explanation);
+            let mut run = run::Run::new(&language_choice.value);
+            if verbose_notify || matches!(std::env::var("WA_VERBOSE_NOTIFY").as_deref(), Ok("1") | Ok("true")) {
+                run.resolution_note = format!(
+                    "\nBackend from {}, model {} from {}",
+                    backend.source, model_choice.value, model_choice.source
+                );
+            }
+            let resolved_backend = backend.value;
+            if let Some(threads) = threads {
+                std::env::set_var("WA_THREADS", threads.to_string());
+            }
             
-            let socket_path = socket_path.unwrap_or_else(|| "/tmp/whisp-away-daemon.sock".to_string());
+            // A broken chain is reported before the recording is stopped
+            chain::steps(profile, &skip)?;
             
-            match resolved_backend.as_str() {
+            let target = daemon_target(daemon_url, socket_path, daemon, &resolved_backend, &helpers::resolve_model(model.clone(), profile))?;
+            let daemon = socket::DaemonOptions {
+                socket_path: target.socket_path,
+                use_running_backend: use_running_backend || target.any_backend,
+                ready_file: ready_file.unwrap_or(target.ready_file),
+                require_ready,
+                no_queue,
+                timeout: None,
+                hand_over: true,
+            };
+            
+            let options = client::StopOptions {
+                wtype_path: &wtype_path,
+                tools: typing::Tools::resolve(&wtype_path),
+                daemon: &daemon,
+                audio_file_override: audio_file.as_deref(),
+                model,
+                prompt_file: prompt_file.as_deref(),
+                profile,
+                skip: &skip,
+                output,
+                notify_preview,
+                trim_lead_ms: trim_lead,
+                min_duration_ms: min_duration,
+                audio_format,
+                fast_since,
+            };
+            
+            let result = match resolved_backend.as_str() {
                 "whisper-cpp" => {
                     // Pass bindings flag to daemon client (will be used in fallback)
-                    whisper_cpp::stop_and_transcribe_daemon(&wtype_path, &socket_path, audio_file.as_deref(), model, bindings, whisper_path)
+                    whisper_cpp::stop_and_transcribe_daemon(&options, &mut run, bindings, whisper_path)
                 }
                 "faster-whisper" => {
                     // faster-whisper doesn't use bindings flag
-                    faster_whisper::stop_and_transcribe_daemon(&wtype_path, &socket_path)
+                    faster_whisper::stop_and_transcribe_daemon(&options, &mut run)
                 }
+                #[cfg(feature = "mock")]
+                "mock" => mock::stop_and_transcribe_daemon(&options, &mut run),
                 _ => Err(anyhow::anyhow!("Unknown backend: {}", resolved_backend))
+            };
+            if let Some(pending) = sink.as_mut() {
+                pending.note(&run);
             }
+            // Kept recordings are compressed in the background once the text is out
+            history::finish_archiving();
+            result
         }
         
-        Commands::Daemon { backend, model, socket_path } => {
-            let resolved_backend = resolve_backend(&backend);
-            let model = helpers::resolve_model(model);
+        Commands::Quick { backend, language, wtype_path, socket_path, daemon } => {
+            let resolved_backend = resolve_backend(&backend, None);
+            if recording::active_recorders().is_empty() {
+                return match resolved_backend.as_str() {
+                    "whisper-cpp" | "faster-whisper" => recording::start_recording(&resolved_backend, recording::AudioSource::Mic, std::time::Duration::ZERO, None),
+                    #[cfg(feature = "mock")]
+                    "mock" => recording::start_recording("mock", recording::AudioSource::Mic, std::time::Duration::ZERO, None),
+                    unknown => Err(anyhow::anyhow!("Unknown backend: {}", unknown)),
+                };
+            }
             
-            match resolved_backend.as_str() {
-                "whisper-cpp" => whisper_cpp::run_daemon(&model),
-                "faster-whisper" => {
-                    let socket_path = socket_path.unwrap_or_else(|| "/tmp/whisp-away-daemon.sock".to_string());
-                    faster_whisper::run_daemon(&model, &socket_path)
+            let fast_since = Some(std::time::Instant::now());
+            let mut run = run::Run::new(&helpers::resolve_language(language, None));
+            let target = daemon_target(None, socket_path, daemon, &resolved_backend, &helpers::resolve_model(None, None))?;
+            let daemon = socket::DaemonOptions {
+                socket_path: target.socket_path,
+                use_running_backend: target.any_backend,
+                ready_file: target.ready_file,
+                require_ready: false,
+                no_queue: false,
+                timeout: None,
+                hand_over: true,
+            };
+            let options = client::StopOptions {
+                wtype_path: &wtype_path,
+                tools: typing::Tools::resolve(&wtype_path),
+                daemon: &daemon,
+                audio_file_override: None,
+                model: None,
+                prompt_file: None,
+                profile: None,
+                skip: &[],
+                output: None,
+                notify_preview: None,
+                trim_lead_ms: None,
+                min_duration_ms: None,
+                audio_format: None,
+                fast_since,
+            };
+            let result = match resolved_backend.as_str() {
+                "whisper-cpp" => whisper_cpp::stop_and_transcribe_daemon(&options, &mut run, true, None),
+                "faster-whisper" => faster_whisper::stop_and_transcribe_daemon(&options, &mut run),
+                #[cfg(feature = "mock")]
+                "mock" => mock::stop_and_transcribe_daemon(&options, &mut run),
+                _ => Err(anyhow::anyhow!("Unknown backend: {}", resolved_backend))
+            };
+            history::finish_archiving();
+            result
+        }
+        
+        Commands::Transcribe { file, stdin: _, format, rate, backend, model, language, socket_path, daemon, daemon_url, allow_long, priority, low_priority, prompt_file, diarize_channels, srt, output_format, confidence_mark, confidence_threshold, confidence_markers } => {
+            let backend = resolve_backend(&backend, None);
+            let target = daemon_target(daemon_url, socket_path, daemon, &backend, &helpers::resolve_model(model.clone(), None))?;
+            let options = transcribe::TranscribeOptions {
+                backend,
+                model,
+                language: helpers::resolve_language(language, None),
+                prompt_file,
+                socket_path: target.socket_path,
+                allow_long,
+                priority: if low_priority { protocol::Priority::Batch } else { priority },
+            };
+            
+            let result = if diarize_channels {
+                transcribe::run_diarized(file.as_deref(), srt, options)
+            } else {
+                let style = subtitles::ConfidenceStyle::resolve(confidence_mark, confidence_threshold, confidence_markers)?;
+                transcribe::run(file.as_deref(), format, rate, output_format, style, options)
+            };
+            if let Err(e) = &result {
+                if let Some(error) = e.downcast_ref::<transcribe::TranscribeError>() {
+                    eprintln!("Error: {}", error);
+                    std::process::exit(error.exit_code());
+                }
+            }
+            result
+        }
+        
+        Commands::Dictate { backend, model, language, socket_path, daemon } => {
+            let backend = resolve_backend(&backend, None);
+            let target = daemon_target(None, socket_path, daemon, &backend, &helpers::resolve_model(model.clone(), None))?;
+            dictation::run(transcribe::TranscribeOptions {
+                backend,
+                model,
+                language: helpers::resolve_language(language, None),
+                prompt_file: None,
+                socket_path: target.socket_path,
+                allow_long: false,
+                priority: protocol::Priority::Interactive,
+            })
+        }
+        
+        Commands::Listen { phrase, sensitivity, wake_model, backend, model, language, wtype_path, output, notify_preview, prompt_file, socket_path, daemon } => {
+            let backend = resolve_backend(&backend, None);
+            let target = daemon_target(None, socket_path, daemon, &backend, &helpers::resolve_model(model.clone(), None))?;
+            let options = listen::ListenOptions {
+                wake_model: wake_model
+                    .or_else(|| std::env::var("WA_WAKE_MODEL").ok())
+                    .unwrap_or_else(|| listen::DEFAULT_WAKE_MODEL.to_string()),
+                phrase: phrase
+                    .or_else(|| std::env::var("WA_WAKE_PHRASE").ok())
+                    .unwrap_or_else(|| listen::DEFAULT_PHRASE.to_string()),
+                sensitivity: sensitivity
+                    .or_else(|| std::env::var("WA_WAKE_SENSITIVITY").ok().and_then(|s| s.parse().ok()))
+                    .unwrap_or(listen::DEFAULT_SENSITIVITY),
+                backend,
+                model,
+                language: helpers::resolve_language(language, None),
+                prompt_file,
+                socket_path: target.socket_path,
+                ready_file: target.ready_file,
+                delivery: typing::Delivery { wtype_path, output, preview: notify_preview, profile: None },
+            };
+            listen::run(options)
+        }
+        
+        Commands::Record { out, max_duration, device } => {
+            if let Some(device) = device {
+                // Read where pw-record is started, like the env var it overrides
+                std::env::set_var("WA_AUDIO_DEVICE", device);
+            }
+            recording::record_to_file(&out, max_duration.map(std::time::Duration::from_secs))
+        }
+        
+        Commands::RecordingTimer { pid, audio_file, detail } => recording::run_timer(pid, &audio_file, &detail),
+        
+        Commands::Daemon { backend, profile, model, fast_model, language, threads, socket_path, auto_download, max_audio_seconds, ready_file, name, force, check, no_download, listen, insecure_bind } => {
+            check_profile(profile.as_deref())?;
+            let profile = profile.as_deref();
+            let resolved_backend = resolve_backend(&backend, profile);
+            let model = helpers::resolve_model(model, profile);
+            let fast_model = helpers::resolve_fast_model(fast_model, profile);
+            let language = helpers::resolve_language(language, profile);
+            
+            if check {
+                return whisper_cpp::model::print_check(&resolved_backend, &model);
+            }
+            if force {
+                // Read where the model is loaded, like the env var it stands for
+                std::env::set_var("WA_FORCE_LOAD", "1");
+            }
+            if no_download {
+                std::env::set_var("WA_NO_DOWNLOAD", "1");
+            }
+            if let Some(threads) = threads {
+                // Read where whisper's parameters are set up, like the env var it overrides
+                std::env::set_var("WA_THREADS", threads.to_string());
+            }
+            
+            // Named daemons live in the socket dir; an explicit path still wins
+            let (socket_path, default_ready) = match name {
+                Some(name) => {
+                    let name = if name.is_empty() { daemons::default_name(&resolved_backend, &model) } else { name };
+                    daemons::validate_name(&name)?;
+                    (socket_path.unwrap_or_else(|| daemons::named_socket(&name)), daemons::named_ready_file(&name))
                 }
+                None => (daemons::resolve_socket(socket_path, &resolved_backend), daemons::default_ready_file(&resolved_backend)),
+            };
+            let ready_file = ready_file.unwrap_or(default_ready);
+            let tcp = listen.map(|url| daemons::TcpListen::configure(&url, insecure_bind)).transpose()?;
+            if tcp.is_some() && resolved_backend != "whisper-cpp" {
+                return Err(anyhow::anyhow!("--listen is only supported by the whisper-cpp daemon"));
+            }
+            if let (Some(fast_model), "faster-whisper") = (&fast_model, resolved_backend.as_str()) {
+                eprintln!("WARNING: The faster-whisper daemon keeps no fast model; ignoring {}", fast_model);
+            }
+            daemons::claim_socket(&socket_path)?;
+            
+            match resolved_backend.as_str() {
+                "whisper-cpp" => whisper_cpp::run_daemon(whisper_cpp::DaemonOptions {
+                    model,
+                    language,
+                    socket_path,
+                    ready_file,
+                    max_audio_seconds,
+                    auto_download,
+                    tcp,
+                    fast_model,
+                }),
+                "faster-whisper" => faster_whisper::run_daemon(&model, &socket_path, &language, &ready_file),
+                #[cfg(feature = "mock")]
+                "mock" => mock::run_daemon(&socket_path, &ready_file, fast_model.as_deref()),
                 unknown => Err(anyhow::anyhow!("Unknown backend: {}", unknown)),
             }
         }
         
-        Commands::Tray { backend } => {
-            let daemon_type = resolve_backend(&backend);
-            tokio::runtime::Runtime::new()?.block_on(tray::run_tray(daemon_type))
+        Commands::Tray { backend, model, language, autostart_daemon, auto_restart_on_upgrade, socket_path } => {
+            let daemon_type = resolve_backend(&backend, None);
+            if let Some(socket_path) = socket_path {
+                // Set before the runtime starts, like the env var it stands
+                // for, so every backend the tray switches to uses it too
+                std::env::set_var("WA_WHISPER_SOCKET", socket_path);
+            }
+            tokio::runtime::Runtime::new()?.block_on(tray::run_tray(daemon_type, model, language, autostart_daemon, auto_restart_on_upgrade))
         }
+        
+        Commands::Status => daemons::print_status(),
+        
+        Commands::Events { follow, raw } => events::run(follow, raw),
+        
+        Commands::Last { field } => last_result::print(field.as_deref()),
+        
+        Commands::Replay { since, model, compare, backend, language } => replay::run(replay::ReplayOptions {
+            since,
+            backend: resolve_backend(&backend, None),
+            model: helpers::resolve_model(model, None),
+            compare,
+            language: helpers::resolve_language(language, None),
+        }),
+        
+        Commands::Compare { audio, models, backend, language, json, download } => compare::run(compare::CompareOptions {
+            audio,
+            models: models.iter().map(|model| helpers::absolute_model(model)).collect(),
+            backend: resolve_backend(&backend, None),
+            language: helpers::resolve_language(language, None),
+            json,
+            download,
+        }),
+        
+        Commands::Devices => recording::list_devices(),
+        
+        Commands::Setup { non_interactive, skip, only, backend, model, device, write_config } => setup::run(setup::SetupOptions {
+            non_interactive,
+            skip,
+            only,
+            backend,
+            model,
+            device,
+            write_config,
+        }),
+        
+        Commands::Purge { models, history, all, dry_run, force, yes } => purge::run(purge::PurgeOptions {
+            models: models || all,
+            history: history || all,
+            dry_run,
+            force,
+            yes,
+        }),
+        
+        Commands::Journal { action } => match action {
+            JournalAction::Last { audio } => history::print_last(audio),
+        },
+        
+        Commands::Cache { action } => match action {
+            CacheAction::Prune => whisper_cpp::openvino_cache::prune(),
+        },
+        
+        Commands::Config { action } => match action {
+            ConfigAction::Export { file, with_models } => bundle::export(&file, with_models),
+            ConfigAction::Import { file, overwrite } => bundle::import(&file, overwrite),
+        },
+        
+        Commands::Completions { shell } => cli_docs::completions(Cli::command(), shell),
+        
+        Commands::Man { out_dir } => cli_docs::man(Cli::command(), out_dir.as_deref()),
     }
 }
\ No newline at end of file
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use crate::client::{self, StopOptions, TranscriptionBackend};
+use crate::run::Run;
+use crate::typing;
+use super::direct::transcribe_with_mock;
+
+/// The mock backend, which "transcribes" to WA_MOCK_TEXT without a model
+pub struct Mock;
+    
+impl TranscriptionBackend for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+    
+    fn daemon_summary(&self) -> &'static str {
+        "Voice Input (mock daemon)"
+    }
+    
+    fn transcribing_backend(&self, _model: &str) -> String {
+        self.name().to_string()
+    }
+    
+    fn fallback_notice(&self, reason: &str, _model: &str) -> (String, String) {
+        ("Voice Input (mock)".to_string(), format!("⚠️ {}, using direct mode", reason))
+    }
+    
+    fn transcribe_direct(&self, audio_file: &str, _model: &str, _prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+        transcribe_with_mock(audio_file, delivery, run)
+    }
+}
+
+pub fn stop_and_transcribe_daemon(options: &StopOptions, run: &mut Run) -> Result<()> {
+    client::stop_and_transcribe(&Mock, options, run)
+}
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use tokio::sync::broadcast;
+use crate::protocol::{self, ClientMessage, Command, DaemonEvent, Framing, Opening, TranscriptionResponse};
+use crate::ready::{self, ReadyInfo};
+use crate::whisper_cpp::device_fallback::{DeviceFallback, EncoderDevice, Inference};
+use super::direct::{detected_language, segments, transcribe_audio};
+
+/// Run the mock daemon: same socket and protocol as the real backends,
+/// but answers instantly without loading a model. A `fast_model` is only
+/// named, so `fast` requests can be exercised.
+pub fn run_daemon(socket_path: &str, ready_file: &str, fast_model: Option<&str>) -> Result<()> {
+    // Remove existing socket if it exists
+    if Path::new(socket_path).exists() {
+        fs::remove_file(socket_path)?;
+    }
+    
+    let listener = UnixListener::bind(socket_path)
+        .context("Failed to bind Unix socket")?;
+    
+    let mut perms = fs::metadata(socket_path)?.permissions();
+    perms.set_mode(0o600);
+    fs::set_permissions(socket_path, perms)?;
+    
+    eprintln!("Mock daemon listening on {}", socket_path);
+    let inbox = crate::inbox::open(ready_file)?;
+    let events = broadcast::channel(protocol::EVENT_BUFFER).0;
+    let mut devices = FlakyDevices::from_env(&events);
+    
+    let mut ready = ReadyInfo {
+        pid: std::process::id(),
+        backend: "mock".to_string(),
+        socket_path: socket_path.to_string(),
+        model: "mock".to_string(),
+        language: crate::helpers::resolve_language(None, None),
+        queue: None,
+        health: None,
+        acceleration: None,
+        openvino_active: None,
+        openvino_device: devices.as_ref().map(|devices| devices.fallback.device().to_string()),
+        model_path: None,
+        memory: None,
+        version: Some(crate::upgrade::VERSION.to_string()),
+        inbox: Some(inbox.clone()),
+        fast_model: fast_model.map(str::to_string),
+    };
+    ready::write(ready_file, &ready)?;
+    ready::remove_on_exit(ready_file);
+    
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(stream, &inbox, fast_model, &events, &mut devices) {
+                    eprintln!("Error handling connection: {}", e);
+                }
+                // Publish a move to another device for `status`
+                let device = devices.as_ref().map(|devices| devices.fallback.device().to_string());
+                if device != ready.openvino_device {
+                    ready.openvino_device = device;
+                    ready::write(ready_file, &ready)?;
+                }
+            }
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+    }
+    
+    Ok(())
+}
+
+/// Read the next message, leaving whatever follows it in `buffer`
+fn read_message<T: DeserializeOwned>(stream: &mut UnixStream, buffer: &mut Vec<u8>) -> Result<T> {
+    let mut chunk = [0; 4096];
+    loop {
+        if let Some(message) = protocol::take_message(buffer) {
+            return message.context("Failed to parse request");
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(anyhow!("Client hung up before sending a complete request"));
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// WA_MOCK_EMPTY_FIRST=1: the first pass hears nothing, so the retry on an
+/// empty transcript can be exercised
+fn empty_first() -> bool {
+    matches!(std::env::var("WA_MOCK_EMPTY_FIRST").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// WA_MOCK_FAIL_DEVICES=AUTO,CPU: transcriptions fail while the state is on
+/// one of these OpenVINO devices ("none" for plain whisper.cpp), so moving a
+/// daemon off a failing device can be exercised without one. Recordings
+/// under a second still transcribe, for a success between failures.
+struct FlakyDevices {
+    fallback: DeviceFallback,
+    inference: FlakyInference,
+}
+
+struct FlakyInference {
+    failing: Vec<String>,
+    device: EncoderDevice,
+}
+
+impl Inference for FlakyInference {
+    type Input = Option<std::time::Duration>;
+    type Output = ();
+    
+    fn infer(&mut self, audio: Option<std::time::Duration>) -> Result<()> {
+        let short = audio.is_some_and(|audio| audio < std::time::Duration::from_secs(1));
+        if self.failing.contains(&self.device.to_string()) && !short {
+            return Err(anyhow!("Mock inference failed on OpenVINO device {}", self.device));
+        }
+        Ok(())
+    }
+    
+    fn rebuild(&mut self, device: EncoderDevice) {
+        eprintln!("Mock state rebuilt on OpenVINO device {}", device);
+        self.device = device;
+    }
+}
+
+impl FlakyDevices {
+    fn from_env(events: &broadcast::Sender<DaemonEvent>) -> Option<Self> {
+        let failing = std::env::var("WA_MOCK_FAIL_DEVICES").ok()?;
+        let failing = failing.split(',').map(|device| device.trim().to_string()).collect();
+        let device = EncoderDevice::Auto;
+        Some(Self {
+            fallback: DeviceFallback::new(device, events.clone()),
+            inference: FlakyInference { failing, device },
+        })
+    }
+    
+    fn transcribe(&mut self, audio_path: &str) -> Result<()> {
+        self.fallback.run(&mut self.inference, crate::wav::file_duration(audio_path).ok())
+    }
+}
+
+fn send_response(stream: &mut UnixStream, framing: Framing, response: TranscriptionResponse) -> Result<()> {
+    stream.write_all(&framing.frame(&response.with_backend("mock"))?)?;
+    Ok(())
+}
+
+/// Send a subscriber the events on its own thread, so it doesn't hold up the
+/// connections after it. A hang-up is only noticed at the next event.
+fn subscribe(mut stream: UnixStream, mut events: broadcast::Receiver<DaemonEvent>) -> Result<()> {
+    stream.write_all(&protocol::frame(&DaemonEvent::Subscribed)?)?;
+    std::thread::spawn(move || {
+        // Lagging behind ends the subscription like it does on the real daemons
+        while let Ok(event) = events.blocking_recv() {
+            let sent = protocol::frame(&event).ok().map(|frame| stream.write_all(&frame));
+            if !matches!(sent, Some(Ok(()))) {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: UnixStream,
+    inbox: &str,
+    fast_model: Option<&str>,
+    events: &broadcast::Sender<DaemonEvent>,
+    devices: &mut Option<FlakyDevices>,
+) -> Result<()> {
+    let mut buffer = Vec::new();
+    let mut capabilities = vec![protocol::CAP_TRANSCRIBE, protocol::CAP_SEGMENTS, protocol::CAP_NO_GPU, protocol::CAP_SUBSCRIBE];
+    if fast_model.is_some() {
+        capabilities.push(protocol::CAP_FAST);
+    }
+    if cfg!(feature = "compress") {
+        capabilities.push(protocol::CAP_COMPRESS);
+    }
+    let (message, framing) = match read_message::<Opening>(&mut stream, &mut buffer)? {
+        Opening::Legacy(request) => (ClientMessage::Request(request), Framing::default()),
+        Opening::Command(command) => (ClientMessage::Command(command), Framing::default()),
+        Opening::Hello(hello) => match protocol::negotiate(&hello, &capabilities, "mock", Some(inbox)) {
+            Ok(reply) => {
+                stream.write_all(&protocol::frame(&reply)?)?;
+                (read_message(&mut stream, &mut buffer)?, Framing::agreed(&reply))
+            }
+            Err(error) => {
+                let response = TranscriptionResponse::err_with_code(protocol::UNSUPPORTED_PROTOCOL, error);
+                return send_response(&mut stream, Framing::default(), response);
+            }
+        },
+    };
+    let request = match message {
+        ClientMessage::Request(request) => request,
+        ClientMessage::Command(Command::Subscribe) => return subscribe(stream, events.subscribe()),
+    };
+    let _claim = crate::inbox::claim(Some(inbox), request.managed, &request.audio_path);
+    let received = std::time::Instant::now();
+    
+    let started = std::time::Instant::now();
+    let _ = events.send(DaemonEvent::ProcessingStarted);
+    let response = if let Some(response) = request.check_backend("mock") {
+        response
+    } else if !Path::new(&request.audio_path).exists() {
+        TranscriptionResponse::err(format!("Audio file not found: {}", request.audio_path))
+    } else if let Some(fast_model) = fast_model.filter(|_| request.fast) {
+        match transcribe_audio(&request.audio_path) {
+            Ok(text) => TranscriptionResponse::ok(text).with_model(fast_model),
+            Err(e) => TranscriptionResponse::err(e.to_string()),
+        }
+    } else if let Some(Err(e)) = devices.as_mut().map(|devices| devices.transcribe(&request.audio_path)) {
+        TranscriptionResponse::err(e.to_string())
+    } else {
+        let first = if empty_first() { Ok(String::new()) } else { transcribe_audio(&request.audio_path) };
+        match first {
+            Ok(text) if crate::retry::worth_retrying(&text, started.elapsed()) => match transcribe_audio(&request.audio_path) {
+                Ok(text) => TranscriptionResponse::ok(text).with_retried(true),
+                Err(e) => TranscriptionResponse::err(e.to_string()),
+            },
+            Ok(text) => TranscriptionResponse::ok(text),
+            Err(e) => TranscriptionResponse::err(e.to_string()),
+        }
+    };
+    
+    let response = match &response.text {
+        Some(text) if request.segments => {
+            let segments = segments(&request.audio_path, text)?;
+            response.with_segments(segments)
+        }
+        _ => response,
+    };
+    let _ = events.send(DaemonEvent::ProcessingDone {
+        chars: response.text.as_deref().map_or(0, |text| text.chars().count()),
+        ms: started.elapsed().as_millis() as u64,
+    });
+    let audio = crate::wav::file_duration(&request.audio_path).ok();
+    let language = request.language.unwrap_or_else(|| crate::helpers::resolve_language(None, None));
+    let response = if response.success { response.with_language(Some(detected_language(&language))) } else { response };
+    // Answered like a GPU daemon asked to stay off it would
+    send_response(&mut stream, framing, response.with_cpu(request.no_gpu).with_audio(audio).with_latency(received))
+}
@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use std::fs;
+use crate::helpers::wav_to_samples;
+use crate::protocol::Segment;
+use crate::run::Run;
+use crate::typing;
+
+/// Text returned when neither WA_MOCK_TEXT nor duration mode is set
+const FIXED_TEXT: &str = "mock transcription";
+
+/// Produce the mock "transcript" for an audio file:
+/// - the contents of WA_MOCK_TEXT if set
+/// - the audio duration if WA_MOCK_MODE=duration
+/// - a fixed string otherwise
+///
+/// WA_MOCK_DELAY_MS makes it take that long first, like loading a model would.
+/// WA_MOCK_REPEAT=N repeats the transcript N times, for responses as big as
+/// an hour-long recording's.
+pub fn transcribe_audio(audio_file: &str) -> Result<String> {
+    if let Some(delay) = std::env::var("WA_MOCK_DELAY_MS").ok().and_then(|ms| ms.parse().ok()) {
+        std::thread::sleep(std::time::Duration::from_millis(delay));
+    }
+    
+    let text = transcript(audio_file)?;
+    match std::env::var("WA_MOCK_REPEAT").ok().and_then(|count| count.parse::<usize>().ok()) {
+        Some(count) if count > 1 => Ok(vec![text; count].join(" ")),
+        _ => Ok(text),
+    }
+}
+
+/// The transcript WA_MOCK_REPEAT repeats
+fn transcript(audio_file: &str) -> Result<String> {
+    if let Ok(text) = std::env::var("WA_MOCK_TEXT") {
+        return Ok(crate::artifacts::strip(&text));
+    }
+    
+    if std::env::var("WA_MOCK_MODE").as_deref() == Ok("duration") {
+        let audio_data = fs::read(audio_file)
+            .context("Failed to read audio file")?;
+        let samples = wav_to_samples(&audio_data)?;
+        return Ok(format!("{:.2} seconds", samples.len() as f64 / 16000.0));
+    }
+    
+    Ok(FIXED_TEXT.to_string())
+}
+
+/// The language the mock reports as detected when asked for `requested`:
+/// that one, or for "auto" WA_MOCK_LANGUAGE ("en" if unset)
+pub fn detected_language(requested: &str) -> String {
+    if requested == "auto" {
+        std::env::var("WA_MOCK_LANGUAGE").ok().filter(|lang| !lang.is_empty()).unwrap_or_else(|| "en".to_string())
+    } else {
+        requested.to_string()
+    }
+}
+
+/// The mock's segments for a transcript: one covering the whole recording,
+/// with WA_MOCK_CONFIDENCE as its confidence if set
+pub fn segments(audio_file: &str, text: &str) -> Result<Vec<Segment>> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let audio_data = fs::read(audio_file)
+        .context("Failed to read audio file")?;
+    let samples = wav_to_samples(&audio_data)?;
+    Ok(vec![Segment {
+        start_ms: 0,
+        end_ms: samples.len() as u64 * 1000 / 16000,
+        text: text.to_string(),
+        confidence: std::env::var("WA_MOCK_CONFIDENCE").ok().and_then(|c| c.parse().ok()),
+    }])
+}
+
+/// Transcribe without a daemon, queueing on the direct transcription lock like
+/// the real backends do
+pub fn transcribe_direct(audio_file: &str) -> Result<String> {
+    let _lock = crate::direct_lock::acquire()?;
+    transcribe_audio(audio_file)
+}
+
+/// Mock transcription without a daemon, typed through the normal typing path
+pub fn transcribe_with_mock(audio_file: &str, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+    let text = transcribe_direct(audio_file)?;
+    let detected = detected_language(&run.language);
+    let label = format!("mock{}", crate::helpers::language_note(&run.language, Some(&detected)));
+    run.detected_language = Some(detected);
+    typing::type_text(&text, delivery, run, &label)?;
+    Ok(())
+}
@@ -0,0 +1,6 @@
+pub mod client;
+pub mod daemon;
+pub mod direct;
+
+pub use client::stop_and_transcribe_daemon;
+pub use daemon::run_daemon;
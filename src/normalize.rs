@@ -0,0 +1,167 @@
+/// Typographic punctuation whisper likes to emit, and the ASCII typed instead
+/// when WA_ASCII_PUNCTUATION is set (terminals, code editors)
+const ASCII_PUNCTUATION: &[(char, &str)] = &[
+    ('\u{2018}', "'"),   // ‘
+    ('\u{2019}', "'"),   // ’
+    ('\u{201A}', "'"),   // ‚
+    ('\u{201B}', "'"),   // ‛
+    ('\u{2032}', "'"),   // ′
+    ('\u{201C}', "\""),  // “
+    ('\u{201D}', "\""),  // ”
+    ('\u{201E}', "\""),  // „
+    ('\u{201F}', "\""),  // ‟
+    ('\u{2033}', "\""),  // ″
+    ('\u{00AB}', "\""),  // «
+    ('\u{00BB}', "\""),  // »
+    ('\u{2010}', "-"),   // ‐
+    ('\u{2011}', "-"),   // non-breaking hyphen
+    ('\u{2012}', "-"),   // ‒
+    ('\u{2013}', "-"),   // –
+    ('\u{2014}', "-"),   // —
+    ('\u{2212}', "-"),   // −
+    ('\u{2026}', "..."), // …
+];
+
+/// Characters with no width that would otherwise be typed invisibly
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Whether typographic punctuation is mapped to ASCII ("1" or "true")
+pub fn ascii_punctuation() -> bool {
+    matches!(std::env::var("WA_ASCII_PUNCTUATION").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Join whisper segments with exactly one space between them. Segments usually
+/// carry their own leading space, so one is only added where neither side has it.
+pub fn join_segments<'a>(segments: impl IntoIterator<Item = &'a str>) -> String {
+    let mut text = String::new();
+    for segment in segments {
+        let needs_space = !text.is_empty()
+            && !text.ends_with(char::is_whitespace)
+            && !segment.starts_with(char::is_whitespace);
+        if needs_space {
+            text.push(' ');
+        }
+        text.push_str(segment);
+    }
+    text
+}
+
+/// Tidy a transcript for typing: drop zero-width characters, collapse every run
+/// of whitespace (non-breaking spaces included) to one space, trim, and with
+/// `ascii` map quotes, dashes and ellipses to their ASCII forms
+pub fn normalize(text: &str, ascii: bool) -> String {
+    let mut mapped = String::with_capacity(text.len());
+    for c in text.chars().filter(|c| !is_invisible(*c)) {
+        match ASCII_PUNCTUATION.iter().find(|(from, _)| *from == c) {
+            Some((_, to)) if ascii => mapped.push_str(to),
+            _ => mapped.push(c),
+        }
+    }
+    mapped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Normalize a transcript with the configured punctuation setting
+pub fn transcript(text: &str) -> String {
+    normalize(text, ascii_punctuation())
+}
+
+/// Whitespace-flattened excerpt of a transcript of at most `max_chars`
+/// characters. Longer text is cut at the last word boundary that fits and
+/// gets an ellipsis; a single overlong word is cut mid-word.
+pub fn preview(text: &str, max_chars: usize) -> String {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= max_chars {
+        return flat;
+    }
+    
+    // Leave room for the ellipsis
+    let cut: String = flat.chars().take(max_chars.saturating_sub(1)).collect();
+    let next_is_space = flat.chars().nth(cut.chars().count()) == Some(' ');
+    let kept = match cut.rfind(' ') {
+        Some(space) if !next_is_space => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", kept.trim_end())
+}
+
+/// Rough whisper token count: its BPE averages about four characters a token
+/// on English text, which is close enough to stay under the prompt limit
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Initial prompt from a base prompt and glossary terms, within `budget`
+/// estimated tokens. Terms are listed oldest first, so the oldest are dropped
+/// first when they don't all fit; the base prompt is always kept whole.
+pub fn merge_prompt(base: &str, terms: &[String], budget: usize) -> String {
+    let base = base.trim();
+    let mut first = 0;
+    loop {
+        let glossary = terms[first..].join(", ");
+        let prompt = match (base.is_empty(), glossary.is_empty()) {
+            (_, true) => base.to_string(),
+            (true, false) => glossary,
+            (false, false) => format!("{} {}", base, glossary),
+        };
+        if first == terms.len() || estimate_tokens(&prompt) <= budget {
+            if first > 0 {
+                eprintln!("DEBUG: Glossary over the prompt budget, dropped {} oldest term(s)", first);
+            }
+            return prompt;
+        }
+        first += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn segments_join_with_exactly_one_space() {
+        // Whisper's usual leading space
+        assert_eq!(join_segments([" Hello", " world."]), " Hello world.");
+        // Neither side has one
+        assert_eq!(join_segments(["Hello", "world."]), "Hello world.");
+        // One side has one already
+        assert_eq!(join_segments(["Hello ", "world."]), "Hello world.");
+        assert_eq!(join_segments(["Hello", " world."]), "Hello world.");
+        // Both do: kept as is, normalize collapses them
+        assert_eq!(join_segments(["Hello ", " world."]), "Hello  world.");
+        assert_eq!(join_segments(Vec::<&str>::new()), "");
+        assert_eq!(join_segments(["", "only"]), "only");
+    }
+    
+    #[test]
+    fn whitespace_collapses_and_trims() {
+        assert_eq!(normalize("  Hello\u{00A0}\u{00A0}world\t\n again  ", false), "Hello world again");
+        assert_eq!(normalize("zero\u{200B}width\u{FEFF} joins", false), "zerowidth joins");
+        assert_eq!(normalize(&join_segments([" One.", " Two."]), false), "One. Two.");
+    }
+    
+    #[test]
+    fn typographic_punctuation_is_kept_unless_ascii() {
+        let text = "\u{201C}It\u{2019}s done\u{201D} \u{2014} mostly\u{2026} \u{00AB}oui\u{00BB}";
+        assert_eq!(normalize(text, false), text);
+        assert_eq!(normalize(text, true), "\"It's done\" - mostly... \"oui\"");
+        // Letters outside ASCII aren't punctuation
+        assert_eq!(normalize("Straße über café", true), "Straße über café");
+    }
+    
+    #[test]
+    fn previews_cut_at_word_boundaries() {
+        assert_eq!(preview("short  text", 20), "short text");
+        assert_eq!(preview("one two three four", 10), "one two…");
+        assert_eq!(preview("abcdefghijklmnop", 6), "abcde…");
+    }
+    
+    #[test]
+    fn prompts_drop_the_oldest_terms_first() {
+        let terms = vec!["Kubernetes".to_string(), "Grafana".to_string(), "Prometheus".to_string()];
+        assert_eq!(merge_prompt("Base.", &terms, 100), "Base. Kubernetes, Grafana, Prometheus");
+        assert_eq!(merge_prompt("Base.", &terms, 6), "Base. Prometheus");
+        assert_eq!(merge_prompt("", &terms, 0), "");
+    }
+}
@@ -0,0 +1,495 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// A transient notification replaces the one of the same category that went
+/// out within this window, instead of being shown next to it
+const COALESCE_WINDOW_MS: u64 = 500;
+/// Window for the global rate limit
+const RATE_WINDOW_MS: u64 = 10_000;
+/// Non-error notifications allowed per RATE_WINDOW_MS
+const RATE_LIMIT: usize = 6;
+
+/// How long notify-send may take before it is killed. Without a notification
+/// daemon it can sit out the D-Bus activation timeout, about 25 s.
+const NOTIFY_SEND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Synchronous tag most notifications share so they replace each other on screen
+pub const VOICE_TAG: &str = "voice";
+
+/// Where notifications go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Route {
+    /// The terminal when stderr is one, else the desktop
+    #[default]
+    Auto,
+    /// Always notify-send, even when run from a terminal
+    Desktop,
+    /// Always stderr lines, e.g. when debugging in a pipe
+    Terminal,
+}
+
+impl Route {
+    /// As written in WA_NOTIFY
+    pub fn name(self) -> &'static str {
+        match self {
+            Route::Auto => "auto",
+            Route::Desktop => "desktop",
+            Route::Terminal => "terminal",
+        }
+    }
+}
+
+/// Set when notify-send first fails; the rest of the process only logs
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// WA_NOTIFY ("auto", "desktop" or "terminal"), which --notify and
+/// --force-notify set, else auto
+fn route() -> Route {
+    use clap::ValueEnum;
+    std::env::var("WA_NOTIFY")
+        .ok()
+        .and_then(|value| {
+            Route::from_str(&value, true)
+                .map_err(|_| eprintln!("WARNING: Ignoring WA_NOTIFY={:?}, expected auto, desktop or terminal", value))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a notification is printed rather than shown: run by hand in a
+/// terminal there is someone reading stderr, while from a hotkey or the
+/// tray there isn't
+pub fn to_terminal(route: Route, stderr_is_terminal: bool) -> bool {
+    match route {
+        Route::Auto => stderr_is_terminal,
+        Route::Desktop => false,
+        Route::Terminal => true,
+    }
+}
+
+/// Emoji whose meaning the words next to them don't carry, spelled out
+/// under WA_NO_EMOJI; the others only decorate what the text already says
+const SPELLED_OUT: &[(&str, &str)] = &[("❌", "Error:"), ("⚠️", "Warning:")];
+
+/// Whether notification and tray text goes without emoji (`--no-emoji` or
+/// WA_NO_EMOJI=1), for screen readers that read out each one by name
+pub fn no_emoji() -> bool {
+    matches!(std::env::var("WA_NO_EMOJI").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Pictographs, symbols and the selectors and joiners that combine them.
+/// Arrows and block elements (the level meter) aren't emoji.
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x1F000..=0x1FAFF | 0xFE0F | 0x200D)
+}
+
+/// `line` without emoji, and without the spaces that set them apart
+fn strip_emoji(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut after_emoji = false;
+    for c in line.chars() {
+        if is_emoji(c) {
+            after_emoji = stripped.is_empty() || stripped.ends_with(' ');
+        } else if !(after_emoji && c == ' ') {
+            after_emoji = false;
+            stripped.push(c);
+        }
+    }
+    stripped.trim_end().to_string()
+}
+
+/// Text as shown to the user, in notifications and the tray menu: as is,
+/// or under WA_NO_EMOJI with the emoji that mean something spelled out
+/// and the rest dropped
+pub fn text(text: &str) -> String {
+    if !no_emoji() {
+        return text.to_string();
+    }
+    without_emoji(text)
+}
+
+/// `text` as WA_NO_EMOJI shows it, whether or not it's set
+pub fn without_emoji(text: &str) -> String {
+    let spelled = SPELLED_OUT.iter().fold(text.to_string(), |text, (emoji, word)| text.replace(emoji, word));
+    spelled.split('\n').map(strip_emoji).collect::<Vec<_>>().join("\n")
+}
+
+/// Stop running notify-send for the rest of this process, saying why once
+fn mark_unavailable(reason: &str) {
+    if !UNAVAILABLE.swap(true, Ordering::SeqCst) {
+        eprintln!("WARNING: Notifications unavailable ({}), only logging them from now on", reason);
+    }
+}
+
+/// Reap notify-send from a thread, killing it past NOTIFY_SEND_TIMEOUT, so a
+/// missing or stuck notification daemon never holds up the caller
+fn watch(mut child: Child) {
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => return,
+                Ok(Some(status)) => return mark_unavailable(&format!("notify-send failed, {}", status)),
+                Ok(None) if started.elapsed() >= NOTIFY_SEND_TIMEOUT => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return mark_unavailable(&format!("notify-send took over {} s", NOTIFY_SEND_TIMEOUT.as_secs()));
+                }
+                Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+                Err(e) => return mark_unavailable(&format!("could not wait for notify-send: {}", e)),
+            }
+        }
+    });
+}
+
+/// How eagerly a notification may be throttled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Progress and status updates that are stale within a second
+    Transient,
+    /// Results worth seeing, but still subject to the rate limit
+    Normal,
+    /// Failures; never dropped
+    Error,
+    /// In-place updates of a notification already on screen (e.g. the recording
+    /// timer); never dropped and not counted against the rate limit
+    Progress,
+}
+
+/// Outcome of running a notification through the throttle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Send,
+    /// Sent in place of the older one of its category, which is stale now;
+    /// counts against the rate limit only once between them
+    Replaces,
+    RateLimited,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sent {
+    at_ms: u64,
+    category: String,
+}
+
+/// Recent notification history, shared between whisp-away processes via a
+/// small state file so `start`, `stop` and the tray throttle each other
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Throttle {
+    sent: Vec<Sent>,
+}
+
+impl Throttle {
+    /// Decide whether a notification may be shown at `now_ms` and record it if so.
+    /// The clock is passed in so the policy doesn't depend on wall time.
+    pub fn admit(&mut self, now_ms: u64, category: &str, priority: Priority) -> Decision {
+        self.sent.retain(|s| now_ms.saturating_sub(s.at_ms) < RATE_WINDOW_MS);
+
+        let recent = self.sent.iter_mut().rev().find(|s| {
+            s.category == category && now_ms.saturating_sub(s.at_ms) < COALESCE_WINDOW_MS
+        });
+        let decision = match priority {
+            Priority::Progress => return Decision::Send,
+            Priority::Error => Decision::Send,
+            Priority::Transient if recent.is_some() => {
+                // The newest wins: it takes the older one's place
+                if let Some(recent) = recent {
+                    recent.at_ms = now_ms;
+                }
+                return Decision::Replaces;
+            }
+            _ if self.sent.len() >= RATE_LIMIT => Decision::RateLimited,
+            _ => Decision::Send,
+        };
+
+        if decision == Decision::Send {
+            self.sent.push(Sent { at_ms: now_ms, category: category.to_string() });
+        }
+        decision
+    }
+
+    pub fn state_path() -> String {
+        crate::helpers::runtime_file("whisp-away-notify.json")
+    }
+
+    /// Run the throttle on the shared state with the file locked, so two
+    /// processes notifying at once can't both read it before either writes
+    fn with_state<T>(path: &str, admit: impl FnOnce(&mut Throttle) -> T) -> T {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path);
+        let Ok(mut file) = file else {
+            // Throttling within this process is better than not notifying
+            return admit(&mut Throttle::default());
+        };
+        let locked = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0;
+        let mut throttle = Self::read(&mut file);
+        let result = admit(&mut throttle);
+        if locked {
+            throttle.write(&mut file);
+        }
+        // Unlocked when the file is closed
+        result
+    }
+
+    fn read(file: &mut File) -> Self {
+        let mut content = String::new();
+        file.read_to_string(&mut content).ok();
+        serde_json::from_str(&content).unwrap_or_default()
+    }
+
+    fn write(&self, file: &mut File) {
+        if let Ok(content) = serde_json::to_vec(self) {
+            let _ = file.set_len(0).and_then(|_| file.rewind()).and_then(|_| file.write_all(&content));
+        }
+    }
+}
+
+/// A desktop notification shown through notify-send
+pub struct Notification {
+    summary: String,
+    body: String,
+    timeout_ms: u32,
+    priority: Priority,
+    tag: Option<&'static str>,
+    urgent: bool,
+}
+
+impl Notification {
+    fn new(summary: &str, body: &str, priority: Priority) -> Self {
+        Self {
+            summary: text(summary),
+            body: text(body),
+            timeout_ms: 2000,
+            priority,
+            tag: Some(VOICE_TAG),
+            urgent: false,
+        }
+    }
+
+    /// Progress/status message, e.g. "Transcribing..."
+    pub fn transient(summary: &str, body: &str) -> Self {
+        Self::new(summary, body, Priority::Transient)
+    }
+
+    /// Result message, e.g. "Transcribed" or "daemon started"
+    pub fn normal(summary: &str, body: &str) -> Self {
+        Self::new(summary, body, Priority::Normal)
+    }
+
+    /// Failure message; always shown
+    pub fn error(summary: &str, body: &str) -> Self {
+        Self::new(summary, body, Priority::Error)
+    }
+
+    /// Update replacing a notification with the same tag, e.g. the recording timer
+    pub fn progress(summary: &str, body: &str) -> Self {
+        Self::new(summary, body, Priority::Progress)
+    }
+    
+    pub fn timeout(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Synchronous tag; notifications with the same tag replace each other
+    pub fn tag(mut self, tag: Option<&'static str>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Show with critical urgency, which most notification daemons keep on screen
+    pub fn urgent(mut self) -> Self {
+        self.urgent = true;
+        self
+    }
+
+    /// Category used for coalescing: what kind of notification it is and what
+    /// it comes from (the summary, e.g. "Voice Input (whisper-cpp)"), so a
+    /// burst of one kind doesn't hold back the others
+    fn category(&self) -> String {
+        format!("{:?} {}", self.priority, self.summary)
+    }
+
+    /// The notification as stderr lines: the summary and the body's first
+    /// line, then the rest of the body indented, without notify-send markup
+    fn terminal_lines(&self) -> String {
+        let body = self.body.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+        let mut lines = body.lines();
+        let mut text = format!("{}: {}", self.summary, lines.next().unwrap_or(""));
+        for line in lines {
+            text.push_str("\n    ");
+            text.push_str(line);
+        }
+        text
+    }
+
+    /// Show the notification unless the throttle drops it; dropped ones are
+    /// logged. In a terminal it is printed instead, never throttled, since
+    /// nothing piles up on screen and the tray's notifications shouldn't be
+    /// held back by it. Never fails: once notify-send can't be run, fails or
+    /// hangs, this process only logs its notifications.
+    pub fn send(self) {
+        if to_terminal(route(), std::io::stderr().is_terminal()) {
+            eprintln!("{}", self.terminal_lines());
+            return;
+        }
+        if UNAVAILABLE.load(Ordering::SeqCst) {
+            eprintln!("DEBUG: Notification not shown: {}", self.terminal_lines().replace("\n    ", " | "));
+            return;
+        }
+
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let category = self.category();
+        let decision = Throttle::with_state(&Throttle::state_path(), |throttle| throttle.admit(now_ms, &category, self.priority));
+        if decision == Decision::RateLimited {
+            eprintln!("DEBUG: Notification {:?}: {} - {}", decision, self.summary, self.body.replace('\n', " | "));
+            return;
+        }
+
+        let timeout = self.timeout_ms.to_string();
+        let mut cmd = crate::child_env::command("notify-send", &[]);
+        cmd.args([self.summary.as_str(), self.body.as_str(), "-t", timeout.as_str()]);
+        if self.urgent {
+            cmd.args(["-u", "critical"]);
+        }
+        // Untagged transient ones are tagged with their category, so the one
+        // that replaces another takes its place on screen
+        let tag = self.tag.map(str::to_string).or_else(|| (self.priority == Priority::Transient).then_some(category));
+        if let Some(tag) = tag {
+            cmd.args(["-h", &format!("string:x-canonical-private-synchronous:{}", tag)]);
+        }
+        match cmd.spawn() {
+            Ok(child) => watch(child),
+            Err(e) => {
+                mark_unavailable(&format!("could not run notify-send: {}", e));
+                eprintln!("DEBUG: Notification not shown: {}", self.terminal_lines().replace("\n    ", " | "));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Transient Voice Input";
+    const DAEMON: &str = "Transient Voice Input (whisper-cpp)";
+
+    #[test]
+    fn newer_transient_replaces_the_older_one() {
+        let mut throttle = Throttle::default();
+        assert_eq!(throttle.admit(1_000, STATUS, Priority::Transient), Decision::Send);
+        assert_eq!(throttle.admit(1_200, STATUS, Priority::Transient), Decision::Replaces);
+        // Measured from the one it replaced, which is now the newest
+        assert_eq!(throttle.admit(1_650, STATUS, Priority::Transient), Decision::Replaces);
+        assert_eq!(throttle.admit(2_200, STATUS, Priority::Transient), Decision::Send);
+    }
+
+    #[test]
+    fn categories_coalesce_separately() {
+        let mut throttle = Throttle::default();
+        assert_eq!(throttle.admit(1_000, STATUS, Priority::Transient), Decision::Send);
+        assert_eq!(throttle.admit(1_100, DAEMON, Priority::Transient), Decision::Send);
+        assert_eq!(throttle.admit(1_200, STATUS, Priority::Transient), Decision::Replaces);
+        assert_eq!(throttle.admit(1_300, DAEMON, Priority::Transient), Decision::Replaces);
+    }
+
+    #[test]
+    fn only_transient_ones_are_replaced() {
+        let mut throttle = Throttle::default();
+        let result = "Normal Voice Input";
+        assert_eq!(throttle.admit(1_000, result, Priority::Normal), Decision::Send);
+        assert_eq!(throttle.admit(1_100, result, Priority::Normal), Decision::Send);
+    }
+
+    #[test]
+    fn replacing_doesnt_count_against_the_rate_limit() {
+        let mut throttle = Throttle::default();
+        for i in 0..RATE_LIMIT as u64 * 3 {
+            let decision = throttle.admit(1_000 + i * 100, STATUS, Priority::Transient);
+            assert_ne!(decision, Decision::RateLimited, "notification {}", i);
+        }
+        assert_eq!(throttle.sent.len(), 1);
+    }
+
+    #[test]
+    fn rate_limit_holds_back_the_excess_until_the_window_passes() {
+        let mut throttle = Throttle::default();
+        for i in 0..RATE_LIMIT as u64 {
+            assert_eq!(throttle.admit(1_000 + i, &format!("Normal {}", i), Priority::Normal), Decision::Send);
+        }
+        assert_eq!(throttle.admit(2_000, "Normal late", Priority::Normal), Decision::RateLimited);
+        assert_eq!(throttle.admit(2_000, "Transient late", Priority::Transient), Decision::RateLimited);
+        assert_eq!(throttle.admit(1_000 + RATE_WINDOW_MS, "Normal later", Priority::Normal), Decision::Send);
+    }
+
+    #[test]
+    fn errors_and_progress_are_never_dropped() {
+        let mut throttle = Throttle::default();
+        for i in 0..RATE_LIMIT as u64 {
+            throttle.admit(1_000 + i, &format!("Normal {}", i), Priority::Normal);
+        }
+        for i in 0..3 {
+            assert_eq!(throttle.admit(2_000 + i, "Error Voice Input", Priority::Error), Decision::Send);
+            assert_eq!(throttle.admit(2_000 + i, "Progress Voice Input", Priority::Progress), Decision::Send);
+        }
+    }
+
+    #[test]
+    fn processes_notifying_at_once_share_the_rate_limit() {
+        let path = std::env::temp_dir().join(format!("whisp-away-test-notify-{}.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&path);
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    (0..5)
+                        .filter(|i| {
+                            let category = format!("Normal {} {}", thread, i);
+                            Throttle::with_state(&path, |throttle| throttle.admit(1_000, &category, Priority::Normal)) == Decision::Send
+                        })
+                        .count()
+                })
+            })
+            .collect();
+        let sent: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+        assert_eq!(sent, RATE_LIMIT);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn category_is_the_kind_and_the_source() {
+        let transcribing = Notification::transient("Voice Input (whisper-cpp)", "Transcribing...");
+        let failed = Notification::error("Voice Input (whisper-cpp)", "Failed");
+        let recording = Notification::transient("Voice Input", "Recording...");
+        assert_eq!(transcribing.category(), DAEMON);
+        assert_ne!(transcribing.category(), failed.category());
+        assert_ne!(transcribing.category(), recording.category());
+    }
+
+    #[test]
+    fn without_emoji_spells_out_the_ones_that_mean_something() {
+        assert_eq!(without_emoji("❌ Transcription failed\n⚠️ Model missing"), "Error: Transcription failed\nWarning: Model missing");
+        assert_eq!(without_emoji("📋 Copied to clipboard"), "Copied to clipboard");
+        assert_eq!(without_emoji("Daemon: ✅ running"), "Daemon: running");
+        // The level meter isn't emoji
+        assert_eq!(without_emoji("Level ▁▃▅"), "Level ▁▃▅");
+    }
+
+    #[test]
+    fn route_names_read_back_as_wa_notify() {
+        use clap::ValueEnum;
+        for route in Route::value_variants() {
+            assert_eq!(Route::from_str(route.name(), true), Ok(*route));
+        }
+    }
+}
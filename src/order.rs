@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long typing waits for earlier dictations by default
+const DEFAULT_MAX_WAIT_SECS: u64 = 300;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Whether this process holds a place in line
+static IN_LINE: AtomicBool = AtomicBool::new(false);
+
+/// Dictations line up in this file, one pid per line, in the order they were
+/// submitted, and type in that order
+pub fn queue_path() -> String {
+    crate::helpers::runtime_file("whisp-away-typing.order")
+}
+
+/// WA_QUEUE_MAX_WAIT (seconds) or five minutes: how long a dictation waits
+/// behind earlier ones, both for the daemon and for its turn to type
+pub fn max_wait() -> Duration {
+    let secs = std::env::var("WA_QUEUE_MAX_WAIT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_WAIT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Signal 0 checks the pid without running `kill` on every poll
+fn alive(pid: u32) -> bool {
+    unsafe { libc::kill(pid as i32, 0) == 0 }
+}
+
+/// Run `update` on the queued pids with the file locked, dropping pids of
+/// processes that exited without leaving the line
+fn with_queue<T>(update: impl FnOnce(&mut Vec<u32>) -> T) -> Result<T> {
+    let path = queue_path();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path))?;
+    // Held for a few syscalls at a time, and released when the file closes
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to lock the typing queue");
+    }
+    
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let mut pids: Vec<u32> = content.lines().filter_map(|line| line.trim().parse().ok()).collect();
+    pids.retain(|pid| alive(*pid));
+    let result = update(&mut pids);
+    
+    let content: String = pids.iter().map(|pid| format!("{}\n", pid)).collect();
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(content.as_bytes())?;
+    Ok(result)
+}
+
+/// Get in line to type, when the dictation is submitted
+pub fn take_turn() {
+    let me = std::process::id();
+    match with_queue(|pids| {
+        if !pids.contains(&me) {
+            pids.push(me);
+        }
+        pids.len() - 1
+    }) {
+        Ok(ahead) => {
+            IN_LINE.store(true, Ordering::SeqCst);
+            if ahead > 0 {
+                eprintln!("DEBUG: {} earlier dictation(s) will type first", ahead);
+            }
+        }
+        Err(e) => eprintln!("WARNING: Could not queue for typing, output order isn't guaranteed: {:#}", e),
+    }
+}
+
+/// Wait until every dictation submitted before this one has typed or given
+/// up. Returns right away for processes that never got in line (`listen`
+/// between dictations, `replay`), and types anyway after max_wait().
+pub fn wait_for_turn() {
+    if !IN_LINE.load(Ordering::SeqCst) {
+        return;
+    }
+    let me = std::process::id();
+    let started = Instant::now();
+    loop {
+        match with_queue(|pids| pids.first().copied()) {
+            Ok(Some(first)) if first != me => {}
+            Ok(_) => return,
+            Err(e) => {
+                eprintln!("WARNING: Could not check the typing queue: {:#}", e);
+                return;
+            }
+        }
+        if started.elapsed() > max_wait() {
+            eprintln!("WARNING: Typing after waiting {:?} for earlier dictations", started.elapsed());
+            return;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Leave the line, letting the next dictation type
+pub fn finish_turn() {
+    if !IN_LINE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let me = std::process::id();
+    if let Err(e) = with_queue(|pids| pids.retain(|pid| *pid != me)) {
+        eprintln!("WARNING: Could not leave the typing queue: {:#}", e);
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Context, Result};
+use crate::helpers::{self, OutputMode};
+use crate::typing::UnicodeMode;
+
+/// A named set of settings switched together, e.g. "work" and "personal".
+/// Anything a profile leaves out falls through to the tray state, environment
+/// and defaults as usual.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub backend: Option<String>,
+    pub model: Option<String>,
+    /// Small model daemons keep loaded for `quick` and `stop --fast`
+    pub fast_model: Option<String>,
+    pub language: Option<String>,
+    pub output_mode: Option<OutputMode>,
+    /// Glossary of this profile's vocabulary, used when the focused
+    /// application has none of its own
+    pub glossary: Option<String>,
+    /// How transcripts are handed to wtype, for applications that garble
+    /// accented letters
+    pub unicode_mode: Option<UnicodeMode>,
+    /// High-pass and normalize recordings before transcription
+    pub preprocess: Option<bool>,
+    /// Put sentence breaks at pauses when the model returns unpunctuated text
+    pub restore_punctuation: Option<bool>,
+    /// Transcribers `stop` tries in order, as in WA_FALLBACK_CHAIN
+    pub fallback_chain: Option<String>,
+    /// Regex of window classes / app ids this profile is picked for when
+    /// one of them is focused, e.g. "slack|discord"
+    pub window: Option<String>,
+}
+
+/// Directory of profiles: `<name>.conf`, one `key = value` per line
+pub fn dir() -> String {
+    format!("{}/whisp-away/profiles", helpers::config_dir())
+}
+
+pub fn path(name: &str) -> String {
+    format!("{}/{}.conf", dir(), name)
+}
+
+/// Names of the profiles on disk, sorted
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "conf" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// A true/false value of `key` on line `number` (from 0)
+fn flag(number: usize, key: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => Err(anyhow!("line {}: {} must be true or false, not {:?}", number + 1, key, value)),
+    }
+}
+
+/// Parse a profile file. Keys are backend, model, fast_model, language, output,
+/// glossary, unicode_mode, preprocess, restore_punctuation, fallback_chain and
+/// window; `#` starts a comment and values may be quoted.
+/// A relative glossary path is taken from the glossaries directory.
+pub fn parse(name: &str, content: &str) -> Result<Profile> {
+    use clap::ValueEnum;
+    
+    let mut profile = Profile { name: name.to_string(), ..Default::default() };
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected key = value, got {:?}", number + 1, line))?;
+        let value = value.trim().trim_matches('"').to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim() {
+            "backend" => {
+                if !matches!(value.as_str(), "whisper-cpp" | "faster-whisper" | "mock") {
+                    return Err(anyhow!("line {}: unknown backend {:?}", number + 1, value));
+                }
+                profile.backend = Some(value);
+            }
+            "model" => profile.model = Some(value),
+            "fast_model" => profile.fast_model = Some(value),
+            "language" => profile.language = Some(value),
+            "output" => {
+                let mode = OutputMode::from_str(&value, true)
+                    .map_err(|_| anyhow!("line {}: output must be type or clipboard, not {:?}", number + 1, value))?;
+                profile.output_mode = Some(mode);
+            }
+            "unicode_mode" => {
+                let mode = UnicodeMode::from_str(&value, true)
+                    .map_err(|_| anyhow!("line {}: unicode_mode must be argv, stdin or codepoints, not {:?}", number + 1, value))?;
+                profile.unicode_mode = Some(mode);
+            }
+            "preprocess" => profile.preprocess = Some(flag(number, "preprocess", &value)?),
+            "restore_punctuation" => profile.restore_punctuation = Some(flag(number, "restore_punctuation", &value)?),
+            "fallback_chain" => {
+                crate::chain::parse(&value).map_err(|e| anyhow!("line {}: {:#}", number + 1, e))?;
+                profile.fallback_chain = Some(value);
+            }
+            "window" => {
+                regex::RegexBuilder::new(&value)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| anyhow!("line {}: window isn't a valid regex: {}", number + 1, e))?;
+                profile.window = Some(value);
+            }
+            "glossary" => {
+                profile.glossary = Some(if value.starts_with('/') {
+                    value
+                } else {
+                    format!("{}/{}", crate::prompt::glossary_dir(), value)
+                });
+            }
+            other => return Err(anyhow!("line {}: unknown key {:?}", number + 1, other)),
+        }
+    }
+    Ok(profile)
+}
+
+pub fn load(name: &str) -> Result<Profile> {
+    if name.is_empty() || name.contains('/') {
+        return Err(anyhow!("Invalid profile name {:?}", name));
+    }
+    let path = path(name);
+    let content = std::fs::read_to_string(&path).with_context(|| format!("Failed to read profile {}", path))?;
+    parse(name, &content).with_context(|| format!("Invalid profile {}", path))
+}
+
+/// The profile for this process: `profile` (`--profile`) if given, else the
+/// first profile, by name, whose `window` matches the focused window's class
+/// or app id. Picked once, when the recording is stopped, so the window the
+/// text goes to decides.
+pub fn follow_focus(profile: Option<&str>) -> Option<String> {
+    if let Some(name) = profile {
+        return Some(name.to_string());
+    }
+    let ids = crate::focus::focused_window_ids();
+    let picked = if ids.is_empty() {
+        None
+    } else {
+        names().into_iter().find(|name| {
+            let Some(pattern) = load(name).ok().and_then(|profile| profile.window) else {
+                return false;
+            };
+            let Ok(pattern) = regex::RegexBuilder::new(&pattern).case_insensitive(true).build() else {
+                return false;
+            };
+            ids.iter().any(|id| pattern.is_match(id))
+        })
+    };
+    if let Some(name) = &picked {
+        eprintln!("DEBUG: Using profile {} for the focused window ({})", name, ids.join(", "));
+    }
+    picked
+}
+
+/// Name of the profile in effect: `profile` (`--profile`, or the one
+/// follow_focus picked), else the one picked in the tray, else WA_PROFILE
+pub fn active_name(profile: Option<&str>) -> Option<String> {
+    profile
+        .map(str::to_string)
+        .or_else(|| helpers::read_tray_state().and_then(|state| state.profile))
+        .or_else(|| std::env::var("WA_PROFILE").ok())
+        .filter(|name| !name.is_empty())
+}
+
+/// The profile in effect, read fresh so edits apply to the next dictation.
+/// One that can't be read is warned about and left out.
+pub fn active(profile: Option<&str>) -> Option<Profile> {
+    let name = active_name(profile)?;
+    load(&name)
+        .map_err(|e| eprintln!("WARNING: Ignoring profile {}: {:#}", name, e))
+        .ok()
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+/// Whisper keeps at most 224 prompt tokens and silently drops the rest from
+/// the front; stay under that with room for the estimate being off
+const PROMPT_TOKEN_BUDGET: usize = 200;
+
+/// Directory of per-application glossaries: `<app id>.txt`, named after the
+/// window class or app id (lowercase) the never-type-into list matches on too
+pub fn glossary_dir() -> String {
+    format!("{}/whisp-away/glossaries", crate::helpers::config_dir())
+}
+
+/// Glossary for this transcription: `prompt_file` (`--prompt-file`), else the
+/// focused application's glossary, else the active profile's, else WA_PROMPT_FILE
+fn glossary_path(prompt_file: Option<&str>, profile: Option<&str>) -> Option<String> {
+    if let Some(path) = prompt_file {
+        return Some(path.to_string());
+    }
+    let app_glossary = crate::focus::focused_window_ids()
+        .into_iter()
+        .map(|id| format!("{}/{}.txt", glossary_dir(), id.to_lowercase()))
+        .find(|path| Path::new(path).exists());
+    app_glossary
+        .or_else(|| crate::profile::active(profile).and_then(|profile| profile.glossary))
+        .or_else(|| std::env::var("WA_PROMPT_FILE").ok().filter(|path| !path.is_empty()))
+}
+
+/// Terms from a glossary file: one per line, oldest first, `#` starts a comment
+fn read_terms(path: &str) -> Vec<String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|term| !term.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(e) => {
+            eprintln!("WARNING: Could not read glossary {}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Initial prompt for a transcription starting now: WA_INITIAL_PROMPT with the
+/// active glossary merged in. Files are read every time, so edits apply to the
+/// next dictation without restarting anything.
+pub fn current(prompt_file: Option<&str>, profile: Option<&str>) -> Option<String> {
+    let base = std::env::var("WA_INITIAL_PROMPT").unwrap_or_default();
+    let terms = match glossary_path(prompt_file, profile) {
+        Some(path) => {
+            eprintln!("DEBUG: Using glossary {}", path);
+            read_terms(&path)
+        }
+        None => Vec::new(),
+    };
+    Some(crate::normalize::merge_prompt(&base, &terms, PROMPT_TOKEN_BUDGET))
+        .filter(|prompt| !prompt.is_empty())
+}
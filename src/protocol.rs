@@ -0,0 +1,801 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Protocol this build speaks. Bump it when messages change incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol this build still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Can transcribe audio files
+pub const CAP_TRANSCRIBE: &str = "transcribe";
+/// Reports the language whisper detected
+pub const CAP_DETECT_LANGUAGE: &str = "detect_language";
+/// Honors `priority: low`
+pub const CAP_PRIORITY: &str = "priority";
+/// Enforces a max audio length that `allow_long` lifts
+pub const CAP_ALLOW_LONG: &str = "allow_long";
+/// Passes `initial_prompt` on to whisper
+pub const CAP_INITIAL_PROMPT: &str = "initial_prompt";
+/// Takes the audio as bytes following the request (`audio_bytes`) instead of
+/// a path; required over TCP, where client and daemon share no files
+pub const CAP_INLINE_AUDIO: &str = "inline_audio";
+/// Keeps a small model loaded next to the main one for `fast` requests
+/// (`daemon --fast-model`)
+pub const CAP_FAST: &str = "fast";
+/// Returns the timed `segments` of a transcript with their confidence when
+/// the request asks for them
+pub const CAP_SEGMENTS: &str = "segments";
+/// Transcribes `no_gpu` requests on the CPU, with a CPU-only copy of the
+/// model it loads on first use
+pub const CAP_NO_GPU: &str = "no_gpu";
+/// Keeps a `subscribe` connection open and pushes DaemonEvents down it
+pub const CAP_SUBSCRIBE: &str = "subscribe";
+/// Sends frames over COMPRESS_THRESHOLD compressed with zstd and reads them
+/// back; only builds with the `compress` feature offer it
+pub const CAP_COMPRESS: &str = "zstd";
+
+/// Frames shorter than this go out as they are, as compressing saves little
+pub const COMPRESS_THRESHOLD: usize = 16 * 1024;
+/// Most a compressed frame may hold, before or after decompressing; bigger
+/// claims are refused before anything is read or allocated for them
+pub const MAX_COMPRESSED_FRAME: usize = 256 * 1024 * 1024;
+
+/// Events a daemon holds for a subscriber that hasn't read them yet. Past
+/// that the oldest are dropped: a slow subscriber never holds up transcription.
+pub const EVENT_BUFFER: usize = 64;
+
+/// `error_code` when client and daemon have no protocol version in common
+pub const UNSUPPORTED_PROTOCOL: &str = "unsupported_protocol";
+/// `error_code` for requests longer than the daemon's max_audio_seconds
+pub const AUDIO_TOO_LONG: &str = "audio_too_long";
+/// `error_code` for requests meant for a different backend than the one running
+pub const WRONG_BACKEND: &str = "wrong_backend";
+/// `error_code` once the daemon's model can't be used any more and it needs a restart
+pub const MODEL_UNAVAILABLE: &str = "model_unavailable";
+/// `error_code` while the model is unloaded for lack of memory and memory
+/// hasn't recovered enough to load it again
+pub const LOW_MEMORY: &str = "low_memory";
+/// `error_code` for TCP clients without the daemon's token
+pub const UNAUTHORIZED: &str = "unauthorized";
+
+/// Scheduling class of a request; interactive requests overtake queued batch ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Dictation someone is waiting on
+    #[default]
+    Interactive,
+    /// Files and long recordings that can wait
+    #[serde(alias = "low")]
+    Batch,
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Priority::Interactive => write!(f, "interactive"),
+            Priority::Batch => write!(f, "batch"),
+        }
+    }
+}
+
+/// faster-whisper's decoding options, for a request to override the
+/// daemon's defaults with; each one left out keeps the default. Daemons
+/// ignore the ones they don't know, and the whisper.cpp backend all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodeParameters {
+    /// Beams searched, 1 for greedy decoding (WA_BEAM_SIZE)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beam_size: Option<u32>,
+    /// Skip the silent stretches of the audio (WA_VAD_FILTER)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vad_filter: Option<bool>,
+    /// Time each word, which also splits segments more closely (WA_WORD_TIMESTAMPS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub word_timestamps: Option<bool>,
+    /// Prompt each window with the text before it, the opposite of
+    /// whisper.cpp's no_context (WA_CONDITION_ON_PREVIOUS_TEXT)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition_on_previous_text: Option<bool>,
+}
+
+/// A true/false environment variable, None when unset or not a boolean
+fn env_bool(var: &str) -> Option<bool> {
+    let value = std::env::var(var).ok().filter(|value| !value.is_empty())?;
+    match value.as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => {
+            eprintln!("WARNING: Ignoring {}={:?}, expected true or false", var, value);
+            None
+        }
+    }
+}
+
+impl DecodeParameters {
+    /// The options set in the environment
+    pub fn configured() -> Self {
+        let beam_size = std::env::var("WA_BEAM_SIZE").ok().filter(|value| !value.is_empty()).and_then(|value| {
+            value
+                .parse()
+                .ok()
+                .filter(|size| *size > 0)
+                .or_else(|| {
+                    eprintln!("WARNING: Ignoring WA_BEAM_SIZE={:?}, expected a number of beams", value);
+                    None
+                })
+        });
+        Self {
+            beam_size,
+            vad_filter: env_bool("WA_VAD_FILTER"),
+            word_timestamps: env_bool("WA_WORD_TIMESTAMPS"),
+            condition_on_previous_text: env_bool("WA_CONDITION_ON_PREVIOUS_TEXT"),
+        }
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Request sent by clients to a transcription daemon over the Unix socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionRequest {
+    pub audio_path: String,
+    /// Length of the WAV that follows this request on the connection, for
+    /// daemons that can't read `audio_path` (CAP_INLINE_AUDIO)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_bytes: Option<u64>,
+    /// Language code or "auto"; the daemon's default is used when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Accept audio longer than the daemon's max_audio_seconds
+    #[serde(default)]
+    pub allow_long: bool,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Backend the client expects; any running backend is accepted when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Text whisper is primed with, e.g. glossary terms to spell right
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    /// `audio_path` is in the daemon's inbox and the daemon deletes it once
+    /// transcribed, so the client doesn't have to
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub managed: bool,
+    /// A short command: transcribe with the fast model as one segment and
+    /// without the retry, trading accuracy for turnaround (CAP_FAST)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fast: bool,
+    /// Return the transcript's segments along with its text (CAP_SEGMENTS)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub segments: bool,
+    /// Stay off the GPU, e.g. while its driver hangs (CAP_NO_GPU)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_gpu: bool,
+    /// Decoding options overriding the daemon's defaults
+    #[serde(default, skip_serializing_if = "DecodeParameters::is_empty")]
+    pub parameters: DecodeParameters,
+}
+
+impl TranscriptionRequest {
+    /// A request for `audio_path` in `language`, with the decoding options
+    /// set in the environment
+    pub fn new(audio_path: &str, language: &str) -> Self {
+        Self {
+            audio_path: audio_path.to_string(),
+            audio_bytes: None,
+            language: Some(language.to_string()),
+            allow_long: false,
+            priority: Priority::Interactive,
+            backend: None,
+            initial_prompt: None,
+            managed: false,
+            fast: false,
+            segments: false,
+            no_gpu: false,
+            parameters: DecodeParameters::configured(),
+        }
+    }
+    
+    /// The wrong_backend response to send if this request was meant for another backend
+    pub fn check_backend(&self, running: &str) -> Option<TranscriptionResponse> {
+        match &self.backend {
+            Some(expected) if expected != running => Some(
+                TranscriptionResponse::err_with_code(
+                    WRONG_BACKEND,
+                    format!("Request was for {} but the running daemon is {}", expected, running),
+                )
+                .with_backend(running),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// One stretch of a transcript as whisper segmented it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    /// Offset into the audio where the segment starts, in milliseconds
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+    /// Mean probability whisper gave the segment's tokens, from 0 to 1;
+    /// null when the backend doesn't report one
+    #[serde(default)]
+    pub confidence: Option<f32>,
+}
+
+/// Daemon reply to a TranscriptionRequest
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscriptionResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Machine-readable failure reason, e.g. AUDIO_TOO_LONG
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// Language whisper detected, when the daemon reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Backend of the daemon that answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// The first pass came back empty and the daemon transcribed again with
+    /// more thorough decoding (WA_RETRY_ON_EMPTY)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retried: bool,
+    /// Model that transcribed the request, when the daemon reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Milliseconds from the daemon reading the request to answering it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Segments of the transcript, for requests that asked for them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+    /// Transcribed on the CPU although the daemon normally uses the GPU
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cpu: bool,
+    /// Length of the recording, to the sample, as the daemon measured it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_seconds: Option<f64>,
+}
+
+impl TranscriptionResponse {
+    pub fn ok(text: String) -> Self {
+        Self { success: true, text: Some(text), error: None, error_code: None, language: None, backend: None, retried: false, model: None, latency_ms: None, segments: Vec::new(), cpu: false, audio_seconds: None }
+    }
+    
+    pub fn err(error: String) -> Self {
+        Self { success: false, text: None, error: Some(error), error_code: None, language: None, backend: None, retried: false, model: None, latency_ms: None, segments: Vec::new(), cpu: false, audio_seconds: None }
+    }
+    
+    pub fn err_with_code(code: &str, error: String) -> Self {
+        Self { error_code: Some(code.to_string()), ..Self::err(error) }
+    }
+    
+    pub fn with_language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+    
+    pub fn with_retried(mut self, retried: bool) -> Self {
+        self.retried = retried;
+        self
+    }
+    
+    pub fn with_backend(mut self, backend: &str) -> Self {
+        self.backend = Some(backend.to_string());
+        self
+    }
+    
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+    
+    pub fn with_segments(mut self, segments: Vec<Segment>) -> Self {
+        self.segments = segments;
+        self
+    }
+    
+    pub fn with_cpu(mut self, cpu: bool) -> Self {
+        self.cpu = cpu;
+        self
+    }
+    
+    pub fn with_audio(mut self, duration: Option<std::time::Duration>) -> Self {
+        self.audio_seconds = duration.map(|duration| duration.as_secs_f64());
+        self
+    }
+    
+    /// Note how long the daemon took since `received`
+    pub fn with_latency(mut self, received: std::time::Instant) -> Self {
+        self.latency_ms = Some(received.elapsed().as_millis() as u64);
+        self
+    }
+}
+
+/// First message each side sends on a framed connection. The client lists the
+/// capabilities it wants; the daemon answers with the negotiated version and
+/// the capabilities both sides have.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub capabilities: Vec<String>,
+    /// Backend of the daemon, in its reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Shared secret a TCP listener may require, in the client's hello
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// whisp-away version of the daemon, in its reply, so clients notice a
+    /// daemon left running across an upgrade
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Directory the daemon owns for recordings, in its reply; requests for
+    /// files there may be marked `managed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inbox: Option<String>,
+}
+
+impl Hello {
+    pub fn new(capabilities: &[&str]) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            backend: None,
+            token: None,
+            version: None,
+            inbox: None,
+        }
+    }
+    
+    pub fn has(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+/// A request for something other than a transcription, e.g.
+/// `{"command":"subscribe"}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Keep the connection open and receive the daemon's events (CAP_SUBSCRIBE)
+    Subscribe,
+}
+
+/// What a client sends after the hello
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ClientMessage {
+    Command(Command),
+    Request(TranscriptionRequest),
+}
+
+/// What a daemon reads first. Clients from before the hello exchange send a
+/// bare request without framing; they are still served for one release.
+/// A command may come without a hello too, for scripts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Opening {
+    Hello(Hello),
+    Command(Command),
+    Legacy(TranscriptionRequest),
+}
+
+/// What a daemon pushes to its subscribers, one framed message each
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// First message of a subscription; events follow from here on
+    Subscribed,
+    /// The model started on a request
+    ProcessingStarted,
+    /// A request is done: `chars` of transcript (0 when it failed) after `ms`
+    ProcessingDone { chars: usize, ms: u64 },
+    /// The model was loaded again after being unloaded for lack of memory
+    ModelReloaded { model: String },
+    /// The daemon is exiting and will close the connection
+    ShuttingDown,
+    /// The model was unloaded: only `available_mb` left, below the `floor_mb`
+    /// of WA_MEMORY_FLOOR_MB
+    LowMemory { available_mb: u64, floor_mb: u64 },
+    /// The shared state moved to the OpenVINO device `to` after `failures`
+    /// transcriptions in a row failed on `from`
+    DeviceFallback { from: String, to: String, failures: u32 },
+    /// An event from a newer daemon than this build
+    #[serde(other)]
+    Other,
+}
+
+/// What a client reads first: the daemon's hello, or an error response when
+/// there is no common version (or the daemon predates the hello exchange)
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum HelloReply {
+    Hello(Hello),
+    Refused(TranscriptionResponse),
+}
+
+fn version_range(min: u32, max: u32) -> String {
+    if min == max { min.to_string() } else { format!("{}-{}", min, max) }
+}
+
+/// Daemon side of the hello exchange: the highest version both sides speak
+/// and the capabilities both have. The error names both version ranges and
+/// goes back to the client with UNSUPPORTED_PROTOCOL.
+pub fn negotiate(client: &Hello, capabilities: &[&str], backend: &str, inbox: Option<&str>) -> Result<Hello, String> {
+    let version = client.protocol_version.min(PROTOCOL_VERSION);
+    if version < client.min_protocol_version.max(MIN_PROTOCOL_VERSION) {
+        return Err(format!(
+            "Client speaks protocol {} but the daemon speaks {}",
+            version_range(client.min_protocol_version, client.protocol_version),
+            version_range(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+        ));
+    }
+    Ok(Hello {
+        protocol_version: version,
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        capabilities: capabilities.iter().filter(|c| client.has(c)).map(|c| c.to_string()).collect(),
+        backend: Some(backend.to_string()),
+        token: None,
+        version: Some(crate::upgrade::VERSION.to_string()),
+        inbox: inbox.map(str::to_string),
+    })
+}
+
+/// Client side of the hello exchange: check the daemon agreed on a version
+/// this build speaks
+pub fn check_reply(reply: &Hello) -> Result<(), String> {
+    if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&reply.protocol_version) {
+        return Ok(());
+    }
+    Err(format!(
+        "Daemon answered with protocol {} but this client speaks {}",
+        reply.protocol_version,
+        version_range(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+    ))
+}
+
+/// Whether a client asks for compressed frames: always with WA_COMPRESS=1,
+/// never with 0, and otherwise only over TCP, where responses with segments
+/// for long recordings cross the network
+pub fn compression_wanted(tcp: bool) -> bool {
+    if !cfg!(feature = "compress") {
+        return false;
+    }
+    match std::env::var("WA_COMPRESS").as_deref() {
+        Err(_) | Ok("") | Ok("auto") => tcp,
+        Ok(_) => env_bool("WA_COMPRESS").unwrap_or(tcp),
+    }
+}
+
+/// One framed message: compact JSON followed by a newline
+pub fn frame<T: Serialize>(message: &T) -> serde_json::Result<Vec<u8>> {
+    let mut bytes = serde_json::to_vec(message)?;
+    bytes.push(b'\n');
+    Ok(bytes)
+}
+
+/// Header line of a compressed frame, e.g. `{"compressed":"zstd","bytes":5120}`.
+/// `bytes` of compressed data follow the newline and decompress to the
+/// message's JSON.
+#[derive(Debug, Serialize, Deserialize)]
+struct CompressedHeader {
+    compressed: String,
+    bytes: usize,
+}
+
+/// How a compressed header line starts; no message starts this way
+const COMPRESSED_PREFIX: &[u8] = b"{\"compressed\":";
+
+/// How messages are framed on one connection, as agreed in the hello exchange
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Framing {
+    /// Compress frames of COMPRESS_THRESHOLD or more (CAP_COMPRESS)
+    compress: bool,
+}
+
+impl Framing {
+    /// The framing of a connection whose negotiated hello is `hello`
+    pub fn agreed(hello: &Hello) -> Self {
+        Self { compress: cfg!(feature = "compress") && hello.has(CAP_COMPRESS) }
+    }
+    
+    /// Frame `message`, compressed when agreed on, big enough and actually smaller
+    pub fn frame<T: Serialize>(&self, message: &T) -> serde_json::Result<Vec<u8>> {
+        let plain = frame(message)?;
+        if !self.compress || plain.len() < COMPRESS_THRESHOLD {
+            return Ok(plain);
+        }
+        match compress(&plain[..plain.len() - 1]) {
+            Some(body) if body.len() < plain.len() => {
+                let header = CompressedHeader { compressed: CAP_COMPRESS.to_string(), bytes: body.len() };
+                let mut bytes = frame(&header)?;
+                bytes.extend_from_slice(&body);
+                Ok(bytes)
+            }
+            _ => Ok(plain),
+        }
+    }
+}
+
+#[cfg(feature = "compress")]
+fn compress(json: &[u8]) -> Option<Vec<u8>> {
+    zstd::bulk::compress(json, 3).ok()
+}
+
+#[cfg(not(feature = "compress"))]
+fn compress(_json: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+/// Decompress a frame's body. Its size is read from the zstd frame header,
+/// so a corrupted one fails before a byte is decompressed.
+#[cfg(feature = "compress")]
+fn decompress(body: &[u8]) -> Result<Vec<u8>, String> {
+    let size = zstd::zstd_safe::get_frame_content_size(body)
+        .map_err(|_| "Compressed frame isn't zstd data".to_string())?
+        .ok_or("Compressed frame doesn't say how big it is")?;
+    if size > MAX_COMPRESSED_FRAME as u64 {
+        return Err(format!("Compressed frame decompresses to {} bytes, over {}", size, MAX_COMPRESSED_FRAME));
+    }
+    zstd::bulk::decompress(body, size as usize).map_err(|e| format!("Corrupt compressed frame: {}", e))
+}
+
+#[cfg(not(feature = "compress"))]
+fn decompress(_body: &[u8]) -> Result<Vec<u8>, String> {
+    Err("Got a compressed frame, but this build has no compression (the compress feature)".to_string())
+}
+
+/// Take a compressed frame whose header line ends at `end`, once all of it
+/// has been read. A header this build can't act on is an error right away.
+fn take_compressed<T: DeserializeOwned>(buffer: &mut Vec<u8>, end: usize) -> Option<serde_json::Result<T>> {
+    use serde::de::Error;
+    let header: CompressedHeader = match serde_json::from_slice(&buffer[..end]) {
+        Ok(header) => header,
+        Err(e) => {
+            buffer.drain(..=end);
+            return Some(Err(e));
+        }
+    };
+    if header.compressed != CAP_COMPRESS || header.bytes > MAX_COMPRESSED_FRAME {
+        buffer.drain(..=end);
+        return Some(Err(serde_json::Error::custom(format!(
+            "Refusing a compressed frame of {} bytes of {:?}",
+            header.bytes, header.compressed
+        ))));
+    }
+    if buffer.len() <= end + header.bytes {
+        return None;
+    }
+    let body: Vec<u8> = buffer.drain(..=end + header.bytes).skip(end + 1).collect();
+    Some(
+        decompress(&body)
+            .map_err(serde_json::Error::custom)
+            .and_then(|json| serde_json::from_slice(&json)),
+    )
+}
+
+/// Take the next message from the bytes read so far: a newline-terminated
+/// frame, a compressed frame, or a bare JSON value from a client that
+/// predates framing. `None` means more bytes are needed.
+pub fn take_message<T: DeserializeOwned>(buffer: &mut Vec<u8>) -> Option<serde_json::Result<T>> {
+    if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+        if buffer.starts_with(COMPRESSED_PREFIX) {
+            return take_compressed(buffer, end);
+        }
+        let line: Vec<u8> = buffer.drain(..=end).collect();
+        return Some(serde_json::from_slice(&line));
+    }
+    match serde_json::from_slice(buffer) {
+        Err(e) if e.is_eof() => None,
+        result => {
+            buffer.clear();
+            Some(result)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn client(min: u32, max: u32, capabilities: &[&str]) -> Hello {
+        Hello { protocol_version: max, min_protocol_version: min, ..Hello::new(capabilities) }
+    }
+    
+    #[test]
+    fn same_version_agrees_on_shared_capabilities() {
+        let hello = client(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, &[CAP_TRANSCRIBE, CAP_FAST, "teleport"]);
+        let reply = negotiate(&hello, &[CAP_TRANSCRIBE, CAP_SEGMENTS, CAP_FAST], "cpu", Some("/run/inbox")).unwrap();
+        assert_eq!(reply.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(reply.capabilities, vec![CAP_TRANSCRIBE, CAP_FAST]);
+        assert_eq!(reply.backend.as_deref(), Some("cpu"));
+        assert_eq!(reply.inbox.as_deref(), Some("/run/inbox"));
+        assert_eq!(reply.token, None);
+        assert_eq!(check_reply(&reply), Ok(()));
+    }
+    
+    #[test]
+    fn a_newer_client_is_answered_in_the_daemons_version() {
+        let hello = client(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION + 3, &[CAP_TRANSCRIBE]);
+        let reply = negotiate(&hello, &[CAP_TRANSCRIBE], "cpu", None).unwrap();
+        assert_eq!(reply.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(reply.inbox, None);
+    }
+    
+    #[test]
+    fn a_client_past_the_daemon_is_refused_naming_both_ranges() {
+        let hello = client(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 2, &[CAP_TRANSCRIBE]);
+        let error = negotiate(&hello, &[CAP_TRANSCRIBE], "cpu", None).unwrap_err();
+        assert_eq!(
+            error,
+            format!(
+                "Client speaks protocol {}-{} but the daemon speaks {}",
+                PROTOCOL_VERSION + 1,
+                PROTOCOL_VERSION + 2,
+                version_range(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+            )
+        );
+    }
+    
+    #[test]
+    fn a_client_before_the_daemons_minimum_is_refused() {
+        if MIN_PROTOCOL_VERSION == 0 {
+            return;
+        }
+        let hello = client(0, MIN_PROTOCOL_VERSION - 1, &[]);
+        let error = negotiate(&hello, &[], "cpu", None).unwrap_err();
+        assert!(error.starts_with("Client speaks protocol 0"), "{}", error);
+    }
+    
+    #[test]
+    fn replies_outside_the_clients_range_are_rejected() {
+        let mut reply = Hello::new(&[]);
+        reply.protocol_version = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            check_reply(&reply),
+            Err(format!(
+                "Daemon answered with protocol {} but this client speaks {}",
+                PROTOCOL_VERSION + 1,
+                version_range(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+            ))
+        );
+        reply.protocol_version = MIN_PROTOCOL_VERSION - 1;
+        assert!(check_reply(&reply).is_err());
+    }
+    
+    #[test]
+    fn openings_are_told_apart() {
+        let mut buffer = frame(&Hello::new(&[CAP_TRANSCRIBE])).unwrap();
+        buffer.extend_from_slice(b"{\"command\":\"subscribe\"}\n");
+        match take_message::<Opening>(&mut buffer) {
+            Some(Ok(Opening::Hello(hello))) => assert!(hello.has(CAP_TRANSCRIBE)),
+            other => panic!("expected a hello, got {:?}", other),
+        }
+        match take_message::<Opening>(&mut buffer) {
+            Some(Ok(Opening::Command(Command::Subscribe))) => {}
+            other => panic!("expected a subscribe, got {:?}", other),
+        }
+        assert!(buffer.is_empty());
+    }
+    
+    /// A transcript-like message about `len` bytes long
+    fn transcript(len: usize) -> String {
+        let mut text = String::with_capacity(len + 16);
+        let mut i = 0usize;
+        while text.len() < len {
+            text.push_str(&format!("word{} ", i % 97));
+            i += 1;
+        }
+        text
+    }
+    
+    fn compressed(hello_caps: &[&str]) -> Framing {
+        Framing::agreed(&Hello::new(hello_caps))
+    }
+    
+    #[test]
+    fn frames_stay_plain_unless_compression_was_agreed() {
+        let message = transcript(COMPRESS_THRESHOLD * 2);
+        assert_eq!(compressed(&[CAP_TRANSCRIBE]).frame(&message).unwrap(), frame(&message).unwrap());
+        assert_eq!(Framing::default().frame(&message).unwrap(), frame(&message).unwrap());
+    }
+    
+    #[test]
+    fn compressed_headers_this_build_cant_act_on_are_refused() {
+        for header in [
+            "{\"compressed\":\"brotli\",\"bytes\":4}\n".to_string(),
+            format!("{{\"compressed\":\"zstd\",\"bytes\":{}}}\n", MAX_COMPRESSED_FRAME + 1),
+            "{\"compressed\":\"zstd\"}\n".to_string(),
+        ] {
+            // Refused on the header alone, without waiting for a body
+            let mut buffer = header.into_bytes();
+            assert!(matches!(take_message::<String>(&mut buffer), Some(Err(_))));
+            assert!(buffer.is_empty());
+        }
+    }
+    
+    #[test]
+    fn a_compressed_frame_of_garbage_is_an_error() {
+        let mut buffer = b"{\"compressed\":\"zstd\",\"bytes\":4}\nabcd\"next\"\n".to_vec();
+        assert!(matches!(take_message::<String>(&mut buffer), Some(Err(_))));
+        assert_eq!(take_message::<String>(&mut buffer).unwrap().unwrap(), "next");
+    }
+    
+    #[cfg(feature = "compress")]
+    #[test]
+    fn frames_round_trip_at_every_size() {
+        let framing = compressed(&[CAP_TRANSCRIBE, CAP_COMPRESS]);
+        for len in [0, 100, COMPRESS_THRESHOLD - 100, COMPRESS_THRESHOLD, COMPRESS_THRESHOLD * 4, 3 << 20] {
+            let message = transcript(len);
+            let bytes = framing.frame(&message).unwrap();
+            let plain = frame(&message).unwrap();
+            assert_eq!(bytes.starts_with(COMPRESSED_PREFIX), plain.len() >= COMPRESS_THRESHOLD, "{} bytes", len);
+            assert!(bytes.len() <= plain.len());
+            
+            // Arriving a few KiB at a time, nothing comes out until all of it is there
+            let mut buffer = Vec::new();
+            let mut taken = None;
+            for (i, chunk) in bytes.chunks(4096).enumerate() {
+                buffer.extend_from_slice(chunk);
+                taken = take_message::<String>(&mut buffer);
+                if i + 1 < bytes.len().div_ceil(4096) {
+                    assert!(taken.is_none(), "{} bytes: a message from a partial frame", len);
+                }
+            }
+            assert_eq!(taken.unwrap().unwrap(), message);
+            assert!(buffer.is_empty());
+        }
+    }
+    
+    #[cfg(feature = "compress")]
+    #[test]
+    fn corrupted_compressed_frames_fail_cleanly() {
+        let framing = compressed(&[CAP_COMPRESS]);
+        let message = transcript(COMPRESS_THRESHOLD * 2);
+        let good = framing.frame(&message).unwrap();
+        let body = good.iter().position(|b| *b == b'\n').unwrap() + 1;
+        
+        // A fixed-seed LCG, so a failure reproduces
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+        for _ in 0..500 {
+            let mut bytes = good.clone();
+            for _ in 0..1 + next() % 4 {
+                let at = body + next() % (bytes.len() - body);
+                bytes[at] ^= 1 + (next() % 255) as u8;
+            }
+            bytes.extend_from_slice(b"\"next\"\n");
+            // Whatever the corruption did, the frame is consumed whole and
+            // the one after it still reads
+            assert!(take_message::<String>(&mut bytes).is_some());
+            assert_eq!(take_message::<String>(&mut bytes).unwrap().unwrap(), "next");
+        }
+    }
+    
+    #[cfg(feature = "compress")]
+    #[test]
+    fn a_frame_claiming_a_huge_size_is_refused_before_decompressing() {
+        // zstd frame header: magic, single segment with an 8-byte content size
+        let mut body = vec![0x28, 0xb5, 0x2f, 0xfd, 0xe0];
+        body.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        body.extend_from_slice(&[0; 8]);
+        let mut buffer = format!("{{\"compressed\":\"zstd\",\"bytes\":{}}}\n", body.len()).into_bytes();
+        buffer.extend_from_slice(&body);
+        let error = take_message::<String>(&mut buffer).unwrap().unwrap_err();
+        assert!(error.to_string().contains("decompresses to 1099511627776 bytes"), "{}", error);
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::protocol::Segment;
+
+/// Pause between segments that ends a sentence, unless WA_PUNCTUATION_GAP_MS says otherwise
+const DEFAULT_GAP_MS: u64 = 600;
+/// Text with at least one sentence end per this many words is left alone
+const WORDS_PER_SENTENCE: usize = 30;
+/// What ends a sentence
+const TERMINAL: [char; 4] = ['.', '!', '?', '…'];
+
+/// Whether the active profile has `restore_punctuation = true`
+pub fn enabled(profile: Option<&str>) -> bool {
+    crate::profile::active(profile)
+        .and_then(|profile| profile.restore_punctuation)
+        .unwrap_or(false)
+}
+
+/// Shortest pause that ends a sentence, from WA_PUNCTUATION_GAP_MS
+fn gap_ms() -> u64 {
+    std::env::var("WA_PUNCTUATION_GAP_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .unwrap_or(DEFAULT_GAP_MS)
+}
+
+/// Whether the model punctuated `text` itself: at least one sentence end
+/// every WORDS_PER_SENTENCE words
+fn punctuated(text: &str) -> bool {
+    let words = text.split_whitespace().count();
+    let ends = text.split_whitespace().filter(|word| word.ends_with(TERMINAL)).count();
+    ends > 0 && ends * WORDS_PER_SENTENCE >= words
+}
+
+/// Close a sentence: capitalize it and end it with a period, replacing a
+/// trailing comma, colon or semicolon
+fn finish(sentence: &str) -> String {
+    let sentence = sentence.trim_end_matches([',', ';', ':']);
+    let mut chars = sentence.chars();
+    let mut finished: String = match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => return String::new(),
+    };
+    if !finished.ends_with(TERMINAL) {
+        finished.push('.');
+    }
+    finished
+}
+
+/// Rebuild the text of unpunctuated segments with a sentence break at every
+/// pause of at least `min_gap_ms` between two of them. `None` when the
+/// segments are punctuated already (or empty), to keep the model's text.
+pub fn restore(segments: &[Segment], min_gap_ms: u64) -> Option<String> {
+    let text = segments.iter().map(|segment| segment.text.trim()).collect::<Vec<_>>().join(" ");
+    if text.trim().is_empty() || punctuated(&text) {
+        return None;
+    }
+    
+    let mut sentences: Vec<String> = Vec::new();
+    let mut previous_end: Option<u64> = None;
+    for segment in segments {
+        let piece = segment.text.trim();
+        if piece.is_empty() {
+            continue;
+        }
+        let pause = previous_end.map(|end| segment.start_ms.saturating_sub(end));
+        match sentences.last_mut() {
+            Some(sentence) if pause.is_some_and(|pause| pause < min_gap_ms) => {
+                sentence.push(' ');
+                sentence.push_str(piece);
+            }
+            _ => sentences.push(piece.to_string()),
+        }
+        previous_end = Some(segment.end_ms);
+    }
+    Some(sentences.iter().map(|sentence| finish(sentence)).collect::<Vec<_>>().join(" "))
+}
+
+/// The transcript to type for `text` and the segments it came from: with
+/// punctuation restored from their pauses when the profile asks for it and
+/// the model left it out
+pub fn apply(text: String, segments: &[Segment]) -> String {
+    if segments.is_empty() {
+        eprintln!("DEBUG: No segment timings from the backend, punctuation is left as is");
+        return text;
+    }
+    match restore(segments, gap_ms()) {
+        Some(restored) => {
+            eprintln!("DEBUG: Restored punctuation from the pauses between {} segment(s)", segments.len());
+            restored
+        }
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Segments from (start_ms, end_ms, text)
+    fn segments(parts: &[(u64, u64, &str)]) -> Vec<Segment> {
+        parts
+            .iter()
+            .map(|&(start_ms, end_ms, text)| Segment { start_ms, end_ms, text: text.to_string(), confidence: None })
+            .collect()
+    }
+    
+    #[test]
+    fn long_pauses_end_sentences_and_short_ones_dont() {
+        let segments = segments(&[
+            (0, 2000, " so i went to the store"),
+            (2100, 3500, " and bought milk"),
+            (4500, 6000, " then it rained"),
+        ]);
+        assert_eq!(
+            restore(&segments, 600).as_deref(),
+            Some("So i went to the store and bought milk. Then it rained.")
+        );
+    }
+    
+    #[test]
+    fn a_pause_of_exactly_the_gap_ends_a_sentence() {
+        let segments = segments(&[(0, 1000, "one"), (1599, 2000, "two"), (2600, 3000, "three")]);
+        assert_eq!(restore(&segments, 600).as_deref(), Some("One two. Three."));
+    }
+    
+    #[test]
+    fn the_gap_sets_where_sentences_break() {
+        let segments = segments(&[(0, 1000, "one"), (1300, 2000, "two"), (3000, 4000, "three")]);
+        assert_eq!(restore(&segments, 200).as_deref(), Some("One. Two. Three."));
+        assert_eq!(restore(&segments, 600).as_deref(), Some("One two. Three."));
+        assert_eq!(restore(&segments, 5000).as_deref(), Some("One two three."));
+    }
+    
+    #[test]
+    fn trailing_commas_and_colons_become_periods() {
+        let segments = segments(&[
+            (0, 1000, "first part,"),
+            (2000, 3000, "second part;"),
+            (4000, 5000, "third part:"),
+            (5100, 6000, "and more,"),
+            (7000, 8000, "done"),
+        ]);
+        assert_eq!(restore(&segments, 600).as_deref(), Some("First part. Second part. Third part: and more. Done."));
+    }
+    
+    #[test]
+    fn overlapping_segments_stay_in_one_sentence() {
+        let segments = segments(&[(0, 2000, "over"), (1500, 3000, "lapping"), (4000, 5000, "élan vital")]);
+        assert_eq!(restore(&segments, 600).as_deref(), Some("Over lapping. Élan vital."));
+    }
+    
+    #[test]
+    fn empty_segments_are_skipped_and_dont_bridge_pauses() {
+        let segments = segments(&[(0, 1000, "  "), (3000, 4000, "alone"), (4100, 4500, ""), (4600, 5000, "again")]);
+        assert_eq!(restore(&segments, 600).as_deref(), Some("Alone. Again."));
+        assert_eq!(restore(&segments, 700).as_deref(), Some("Alone again."));
+        assert_eq!(restore(&[], 600), None);
+        assert_eq!(restore(&segments[..1], 600), None);
+    }
+    
+    #[test]
+    fn densely_punctuated_text_is_left_alone() {
+        let segments = segments(&[
+            (0, 1000, "Hello there."),
+            (1100, 2000, "how are you"),
+            (5000, 6000, "fine thanks!"),
+        ]);
+        assert_eq!(restore(&segments, 600), None);
+    }
+    
+    #[test]
+    fn one_sentence_end_in_more_than_thirty_words_is_too_few() {
+        let words = |n: usize| (1..n).map(|i| format!("w{}", i)).collect::<Vec<_>>().join(" ") + " end.";
+        let dense = words(WORDS_PER_SENTENCE);
+        assert_eq!(restore(&segments(&[(0, 1000, &dense)]), 600), None);
+        let sparse = words(WORDS_PER_SENTENCE + 1);
+        assert_eq!(restore(&segments(&[(0, 1000, &sparse)]), 600), Some(format!("W{}", &sparse[1..])));
+    }
+    
+    #[test]
+    fn without_segments_the_text_is_kept() {
+        assert_eq!(apply("no timings here".to_string(), &[]), "no timings here");
+    }
+}
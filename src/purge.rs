@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::helpers::{self, is_process_running};
+use crate::{daemons, recording, ready};
+
+/// Prefixes of the audio files `stop`, `listen` and `transcribe` spool to the recordings dir
+const RUNTIME_AUDIO_PREFIXES: &[&str] = &["voice-recording-", "voice-listen-", "voice-transcribe-", "voice-remote-"];
+
+/// How long a stopped daemon gets to exit before purging gives up
+const DAEMON_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a path is, which decides the flag that removes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    /// Sockets, pidfiles, state files and spooled audio
+    Runtime,
+    /// Daemon logs and compiled OpenVINO encoders
+    Cache,
+    /// Downloaded models (--models)
+    Models,
+    /// Kept recordings, the journal and saved calls (--history)
+    History,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Runtime => write!(f, "runtime"),
+            Kind::Cache => write!(f, "cache"),
+            Kind::Models => write!(f, "models"),
+            Kind::History => write!(f, "history"),
+        }
+    }
+}
+
+pub struct PurgeOptions {
+    pub models: bool,
+    pub history: bool,
+    pub dry_run: bool,
+    pub force: bool,
+    pub yes: bool,
+}
+
+/// Every path whisp-away may have created that exists right now, in a stable
+/// order: by kind, then by path
+fn artifacts(options: &PurgeOptions) -> Vec<(Kind, String)> {
+    let mut paths = Vec::new();
+    
+    let runtime_dir = helpers::runtime_dir();
+    if runtime_dir == format!("/tmp/whisp-away-{}", helpers::uid()) {
+        // The /tmp fallback only ever holds our files
+        paths.push((Kind::Runtime, runtime_dir));
+    } else {
+        paths.extend(
+            [
+                helpers::get_state_file(),
+                daemons::legacy_ready_file(),
+                crate::order::queue_path(),
+                crate::debounce::state_path(),
+                crate::notify::Throttle::state_path(),
+                daemons::socket_dir_path(),
+            ]
+            .into_iter()
+            .map(|path| (Kind::Runtime, path)),
+        );
+    }
+    for dir in recording::recordings_dirs() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if RUNTIME_AUDIO_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) && name.ends_with(".wav") {
+                paths.push((Kind::Runtime, entry.path().to_string_lossy().into_owned()));
+            }
+        }
+    }
+    // Holds only recordings, and daemon inboxes while the runtime dir is short on space
+    paths.push((Kind::Runtime, recording::fallback_recordings_dir()));
+    paths.push((Kind::Runtime, daemons::LEGACY_SOCKET.to_string()));
+    paths.extend(recording::state_files().into_iter().map(|path| (Kind::Runtime, path)));
+    
+    paths.push((Kind::Cache, helpers::log_dir()));
+    
+    if options.models {
+        let home = std::env::var("HOME").unwrap_or_default();
+        for relative in ["whisper-cpp/models", "faster-whisper"] {
+            paths.push((Kind::Models, format!("{}/{}", helpers::cache_dir(), relative)));
+            // Models downloaded before XDG_CACHE_HOME was respected
+            paths.push((Kind::Models, format!("{}/.cache/{}", home, relative)));
+        }
+    }
+    if options.history {
+        paths.push((Kind::History, format!("{}/whisp-away", helpers::data_dir())));
+    }
+    
+    // Legacy files in /tmp may be another user's
+    paths.retain(|(_, path)| helpers::owned_by_us(path));
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Bytes a file or directory tree takes, not following symlinks
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(meta) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| disk_usage(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1_000_000_000 => format!("{:.1} GB", b as f64 / 1e9),
+        b if b >= 1_000_000 => format!("{:.1} MB", b as f64 / 1e6),
+        b if b >= 1_000 => format!("{:.1} kB", b as f64 / 1e3),
+        b => format!("{} B", b),
+    }
+}
+
+/// Daemons with a live pid in their ready file, as (name, pid)
+fn running_daemons() -> Vec<(String, u32)> {
+    daemons::list()
+        .into_iter()
+        .filter_map(|entry| {
+            let info = ready::read(&entry.ready_file)?;
+            is_process_running(info.pid).then_some((entry.name, info.pid))
+        })
+        .collect()
+}
+
+/// SIGTERM a daemon, along with the Python process of a faster-whisper daemon
+/// the tray started in its own process group, and wait for it to exit
+fn stop_daemon(name: &str, pid: u32) -> Result<()> {
+    eprintln!("DEBUG: Stopping daemon {} (pid {})", name, pid);
+    unsafe {
+        let pgid = libc::getpgid(pid as i32);
+        if pgid > 0 && pgid != libc::getpgid(0) {
+            libc::kill(-pgid, libc::SIGTERM);
+        } else {
+            libc::kill(pid as i32, libc::SIGTERM);
+        }
+    }
+    
+    let deadline = std::time::Instant::now() + DAEMON_EXIT_TIMEOUT;
+    while is_process_running(pid) {
+        if std::time::Instant::now() > deadline {
+            return Err(anyhow!("Daemon {} (pid {}) did not exit", name, pid));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+fn confirm(count: usize) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!("Not asking for confirmation without a terminal; pass --yes to remove these"));
+    }
+    print!("Remove {} path(s)? [y/N] ", count);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// List everything whisp-away left behind and remove it after confirmation.
+/// Refuses while a daemon or recording is running, unless forced to stop them.
+pub fn run(options: PurgeOptions) -> Result<()> {
+    if options.dry_run {
+        // Only the paths on stdout, so scripts can rely on the format
+        for (kind, path) in artifacts(&options) {
+            println!("{}\t{}", kind, path);
+        }
+        return Ok(());
+    }
+    
+    let recorders = recording::active_recorders();
+    let running = running_daemons();
+    if !recorders.is_empty() || !running.is_empty() {
+        let mut active: Vec<String> = running.iter().map(|(name, pid)| format!("daemon {} (pid {})", name, pid)).collect();
+        if !recorders.is_empty() {
+            active.push("a recording".to_string());
+        }
+        if !options.force {
+            return Err(anyhow!("Still running: {}. Stop it first or pass --force", active.join(", ")));
+        }
+        println!("Stopping {}", active.join(", "));
+        recording::abort_recording();
+        for (name, pid) in &running {
+            stop_daemon(name, *pid)?;
+        }
+    }
+    
+    let paths = artifacts(&options);
+    if paths.is_empty() {
+        println!("Nothing to remove");
+        return Ok(());
+    }
+    let mut total = 0;
+    for (kind, path) in &paths {
+        let size = disk_usage(Path::new(path));
+        total += size;
+        println!("{:>10}  {:<8} {}", human_size(size), kind.to_string(), path);
+    }
+    println!("{:>10}  total", human_size(total));
+    if !options.models {
+        println!("(downloaded models are kept; add --models to remove them too)");
+    }
+    if !options.history {
+        println!("(kept recordings and the journal stay; add --history to remove them too)");
+    }
+    
+    if !options.yes && !confirm(paths.len())? {
+        println!("Nothing removed");
+        return Ok(());
+    }
+    
+    let mut failed = 0;
+    for (_, path) in &paths {
+        let is_dir = Path::new(path).symlink_metadata().map(|meta| meta.is_dir()).unwrap_or(false);
+        let result = if is_dir { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        if let Err(e) = result.with_context(|| format!("Failed to remove {}", path)) {
+            eprintln!("WARNING: {:#}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} of {} path(s) could not be removed", failed, paths.len()));
+    }
+    println!("Removed {} path(s), {}", paths.len(), human_size(total));
+    Ok(())
+}
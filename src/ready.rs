@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "mock")]
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+#[cfg(feature = "mock")]
+use std::sync::OnceLock;
+use crate::protocol::Priority;
+
+/// Contents of the readiness file a daemon writes once it is serving,
+/// for supervisors (runit, openrc, ...) and clients that want to know
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadyInfo {
+    pub pid: u32,
+    pub backend: String,
+    pub socket_path: String,
+    pub model: String,
+    pub language: String,
+    /// What the daemon is working on, for daemons that queue requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue: Option<QueueStatus>,
+    /// Trouble with the loaded model, once there has been any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<EngineHealth>,
+    /// Acceleration the daemon was built for (WA_ACCELERATION_TYPE)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acceleration: Option<String>,
+    /// Whether OpenVINO initialized on the current whisper state, for daemons
+    /// built with OpenVINO; it falls back to the CPU silently otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub openvino_active: Option<bool>,
+    /// OpenVINO device the whisper state is on: AUTO, CPU, or none once
+    /// repeated failures moved it off OpenVINO
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub openvino_device: Option<String>,
+    /// File the model was loaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_path: Option<String>,
+    /// Memory use, for daemons that watch it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStatus>,
+    /// whisp-away version the daemon runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Directory the daemon owns for client recordings, see `inbox`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inbox: Option<String>,
+    /// Small model kept loaded for `fast` requests (`daemon --fast-model`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fast_model: Option<String>,
+}
+
+impl ReadyInfo {
+    /// The daemon was built for OpenVINO but isn't using it, so it runs at CPU speed
+    pub fn acceleration_degraded(&self) -> bool {
+        self.acceleration.as_deref() == Some("openvino") && self.openvino_active != Some(true)
+    }
+}
+
+/// How a daemon's model has held up since it was loaded
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineHealth {
+    /// Times the whisper state was rebuilt after a failed transcription
+    pub state_recreations: u64,
+    /// Why the daemon can't transcribe any more, once rebuilding failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unavailable: Option<String>,
+}
+
+impl fmt::Display for EngineHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.unavailable {
+            Some(reason) => write!(f, "model unavailable ({}), restart the daemon", reason)?,
+            None => write!(f, "ok")?,
+        }
+        if self.state_recreations > 0 {
+            write!(f, ", state recreated {} time(s)", self.state_recreations)?;
+        }
+        Ok(())
+    }
+}
+
+/// A daemon's memory use, and whether it unloaded its model to free memory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryStatus {
+    /// Resident set size of the daemon process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_mb: Option<u64>,
+    /// System-wide MemAvailable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_mb: Option<u64>,
+    /// WA_MEMORY_FLOOR_MB, when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor_mb: Option<u64>,
+    /// The model was dropped because available memory fell below the floor
+    #[serde(default)]
+    pub unloaded: bool,
+}
+
+impl fmt::Display for MemoryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rss_mb {
+            Some(rss) => write!(f, "{} MB resident", rss)?,
+            None => write!(f, "resident size unknown")?,
+        }
+        if let Some(available) = self.available_mb {
+            write!(f, ", {} MB available", available)?;
+        }
+        if let Some(floor) = self.floor_mb {
+            write!(f, " (floor {} MB)", floor)?;
+        }
+        if self.unloaded {
+            write!(f, ", model unloaded until memory recovers")?;
+        }
+        Ok(())
+    }
+}
+
+/// Snapshot of a daemon's request queue
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    /// Priority of the request being transcribed, if any
+    pub active: Option<Priority>,
+    pub interactive_waiting: usize,
+    pub batch_waiting: usize,
+}
+
+impl fmt::Display for QueueStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.active {
+            Some(priority) => write!(f, "transcribing ({})", priority)?,
+            None => write!(f, "idle")?,
+        }
+        if self.interactive_waiting + self.batch_waiting > 0 {
+            write!(f, ", waiting: {} interactive, {} batch", self.interactive_waiting, self.batch_waiting)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the readiness file atomically so readers never see half a file
+pub fn write(path: &str, info: &ReadyInfo) -> Result<()> {
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, serde_json::to_string_pretty(info)?)
+        .with_context(|| format!("Failed to write ready file {}", tmp))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to move ready file into place at {}", path))?;
+    Ok(())
+}
+
+pub fn remove(path: &str) {
+    let _ = fs::remove_file(path);
+}
+
+pub fn read(path: &str) -> Option<ReadyInfo> {
+    // Its pid gets signalled, so only a file of ours is believed
+    let owner = crate::helpers::owner(path)?;
+    if owner != crate::helpers::uid() {
+        eprintln!("WARNING: Ignoring {}, which belongs to uid {}", path, owner);
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// The real daemons remove their ready file on their own shutdown paths;
+// the mock daemon has none and leaves it to the signal handler
+#[cfg(feature = "mock")]
+static READY_FILE: OnceLock<CString> = OnceLock::new();
+
+#[cfg(feature = "mock")]
+extern "C" fn remove_and_exit(_signal: libc::c_int) {
+    // Only async-signal-safe calls in here
+    if let Some(path) = READY_FILE.get() {
+        unsafe { libc::unlink(path.as_ptr()) };
+    }
+    unsafe { libc::_exit(0) };
+}
+
+/// Remove the readiness file when the daemon is stopped with SIGTERM or SIGINT
+#[cfg(feature = "mock")]
+pub fn remove_on_exit(path: &str) {
+    let Ok(c_path) = CString::new(path) else {
+        return;
+    };
+    if READY_FILE.set(c_path).is_ok() {
+        let handler = remove_and_exit as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGTERM, handler);
+            libc::signal(libc::SIGINT, handler);
+        }
+    }
+}
+
+/// Why a daemon that was required to be ready isn't
+#[derive(Debug)]
+pub enum NotReady {
+    /// No ready file and no socket: nothing is running
+    NotRunning { ready_file: String },
+    /// The socket exists but there is no ready file: still loading, or left behind by a crash
+    Starting { ready_file: String },
+    /// The ready file was left behind by a daemon that is gone
+    Stale { ready_file: String, pid: u32 },
+}
+
+impl fmt::Display for NotReady {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotReady::NotRunning { ready_file } => write!(f, "Daemon not running (no {})", ready_file),
+            NotReady::Starting { ready_file } => write!(f, "Daemon isn't ready yet (socket exists but no {})", ready_file),
+            NotReady::Stale { ready_file, pid } => write!(f, "Daemon (pid {}) is gone, {} is stale", pid, ready_file),
+        }
+    }
+}
+
+impl std::error::Error for NotReady {}
+
+/// Check a daemon is serving on `socket_path` according to its ready file
+pub fn check(ready_file: &str, socket_path: &str) -> Result<ReadyInfo, NotReady> {
+    match read(ready_file) {
+        Some(info) if crate::helpers::is_process_running(info.pid) => Ok(info),
+        Some(info) => Err(NotReady::Stale { ready_file: ready_file.to_string(), pid: info.pid }),
+        None if std::path::Path::new(socket_path).exists() => {
+            Err(NotReady::Starting { ready_file: ready_file.to_string() })
+        }
+        None => Err(NotReady::NotRunning { ready_file: ready_file.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn a_ready_file_is_only_believed_while_its_daemon_runs() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-ready", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let ready_file = dir.join("daemon.ready").to_string_lossy().into_owned();
+        let socket_path = dir.join("daemon.sock").to_string_lossy().into_owned();
+        let write = |pid: u32| {
+            let info = serde_json::json!({"pid": pid, "backend": "mock", "socket_path": socket_path, "model": "mock", "language": "en"});
+            fs::write(&ready_file, info.to_string()).unwrap();
+        };
+        
+        assert!(matches!(check(&ready_file, &socket_path), Err(NotReady::NotRunning { .. })));
+        
+        write(std::process::id());
+        assert_eq!(check(&ready_file, &socket_path).unwrap().pid, std::process::id());
+        
+        // Left behind by a daemon that is gone
+        let mut gone = std::process::Command::new("true").spawn().unwrap();
+        gone.wait().unwrap();
+        write(gone.id());
+        assert!(matches!(check(&ready_file, &socket_path), Err(NotReady::Stale { pid, .. }) if pid == gone.id()));
+        
+        // A socket without a ready file is a daemon still loading
+        remove(&ready_file);
+        fs::write(&socket_path, "").unwrap();
+        assert!(matches!(check(&ready_file, &socket_path), Err(NotReady::Starting { .. })));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,54 +1,426 @@
 use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::process::Command;
-use crate::helpers::is_process_running;
+use std::io::Write;
+use crate::child_env;
+use crate::notify;
+use crate::helpers::{self, is_process_running};
+use crate::wav::AudioLength;
+use crate::run::Run;
 
-/// Stop the recording process and return the audio file path
-pub fn stop_recording(audio_file_override: Option<&str>) -> Result<Option<String>> {
-    let pidfile = "/tmp/whisp-away-recording.pid";
-    let uid = unsafe { libc::getuid() };
+/// Which PipeWire source(s) to capture from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AudioSource {
+    /// Default input device (microphone)
+    Mic,
+    /// Monitor of the default output device (what's playing through the speakers)
+    Monitor,
+    /// Microphone and monitor, mixed into one recording
+    Both,
+    /// Microphone on the left channel and monitor on the right, kept as a call
+    /// recording for `transcribe --diarize-channels`
+    Stereo,
+}
+
+impl AudioSource {
+    fn label(&self) -> &'static str {
+        match self {
+            AudioSource::Mic => "mic",
+            AudioSource::Monitor => "monitor",
+            AudioSource::Both => "mic+monitor",
+            AudioSource::Stereo => "mic|monitor",
+        }
+    }
+}
+
+/// Send SIGINT to a pw-record process, escalating to SIGTERM if it lingers
+fn stop_recorder(pid: u32) {
+    let _ = child_env::command("kill", &[])
+        .args(["-INT", &pid.to_string()])
+        .status();
+    
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    
+    // Force kill if still running
+    if is_process_running(pid) {
+        let _ = child_env::command("kill", &[])
+            .args(["-TERM", &pid.to_string()])
+            .status();
+    }
+}
+
+/// Pidfile holding the pid(s) of the running pw-record process(es)
+fn pidfile() -> String {
+    helpers::runtime_file("whisp-away-recording.pid")
+}
+
+/// Pointer file naming the file(s) the current recording is written to
+fn pointer_file() -> String {
+    helpers::runtime_file("voice-audio-file.tmp")
+}
+
+/// Where older releases kept the pidfile and pointer file, read once so a
+/// recording started before an upgrade can still be stopped
+const LEGACY_PIDFILE: &str = "/tmp/whisp-away-recording.pid";
+
+fn legacy_pointer_file() -> String {
+    format!("/run/user/{}/voice-audio-file.tmp", helpers::uid())
+}
+
+/// Lead-in trimmed when WA_TRIM_LEAD_MS isn't set: long enough for a
+/// hotkey's click, short enough not to clip the first word
+const DEFAULT_TRIM_LEAD_MS: u64 = 150;
+/// Shortest recording transcribed when WA_MIN_DURATION_MS isn't set; anything
+/// shorter is a brushed hotkey, not speech
+const DEFAULT_MIN_DURATION_MS: u64 = 400;
+/// Longest `--delay-start` honored; a hotkey sound never needs more
+const MAX_DELAY_START: std::time::Duration = std::time::Duration::from_secs(2);
+/// Input gain when WA_RECORD_GAIN isn't set
+const DEFAULT_RECORD_GAIN: &str = "1.5";
+
+/// How readily a recording counts as speech, picked from the tray's Recording
+/// settings menu or WA_SENSITIVITY
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Sensitivity {
+    /// Ignore short taps and quiet noise; for loud rooms
+    Strict,
+    #[default]
+    Normal,
+    /// Keep short and quiet recordings; for soft voices and single words
+    Lenient,
+}
+
+impl Sensitivity {
+    pub const ALL: [Sensitivity; 3] = [Sensitivity::Strict, Sensitivity::Normal, Sensitivity::Lenient];
+    
+    /// What each preset means. This is the one place their values are set.
+    pub fn thresholds(self) -> Thresholds {
+        match self {
+            Sensitivity::Strict => Thresholds {
+                min_duration_ms: 700,
+                trim_lead_ms: 200,
+                speech_level: 0.02,
+                silence_ms: 1200,
+            },
+            Sensitivity::Normal => Thresholds {
+                min_duration_ms: DEFAULT_MIN_DURATION_MS,
+                trim_lead_ms: DEFAULT_TRIM_LEAD_MS,
+                speech_level: 0.01,
+                silence_ms: 2000,
+            },
+            Sensitivity::Lenient => Thresholds {
+                min_duration_ms: 200,
+                trim_lead_ms: 100,
+                speech_level: 0.005,
+                silence_ms: 3000,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Sensitivity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sensitivity::Strict => write!(f, "strict"),
+            Sensitivity::Normal => write!(f, "normal"),
+            Sensitivity::Lenient => write!(f, "lenient"),
+        }
+    }
+}
+
+/// Thresholds a recording is judged by at stop time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// Shorter recordings are dropped as a brushed hotkey
+    pub min_duration_ms: u64,
+    /// Dropped from the start to cut the hotkey's own sound
+    pub trim_lead_ms: u64,
+    /// RMS level of a 30 ms frame that counts as speech
+    pub speech_level: f32,
+    /// Pause after speech that ends a recording when auto-stop is on
+    pub silence_ms: u64,
+}
+
+/// Recordings with less speech than this are dropped when VAD is on: a
+/// cough or a click, not a sentence
+const MIN_VOICED: std::time::Duration = std::time::Duration::from_millis(240);
+
+/// The preset in effect: the tray's pick, else WA_SENSITIVITY, else normal
+pub fn sensitivity() -> Sensitivity {
+    helpers::read_tray_state()
+        .and_then(|state| state.sensitivity)
+        .or_else(|| {
+            let value = std::env::var("WA_SENSITIVITY").ok()?;
+            Sensitivity::from_str(&value, true)
+                .map_err(|_| eprintln!("WARNING: Ignoring WA_SENSITIVITY={:?}; expected strict, normal or lenient", value))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// A toggle from the tray, else from `env` ("1" or "true")
+fn toggle(from_state: impl FnOnce(helpers::TrayState) -> Option<bool>, env: &str) -> bool {
+    helpers::read_tray_state()
+        .and_then(from_state)
+        .unwrap_or_else(|| matches!(std::env::var(env).as_deref(), Ok("1") | Ok("true")))
+}
+
+/// Whether recordings without speech are dropped before transcription (WA_VAD)
+pub fn vad_enabled() -> bool {
+    toggle(|state| state.vad, "WA_VAD")
+}
+
+/// Whether a recording stops itself after a pause in speech (WA_AUTO_STOP)
+pub fn auto_stop_enabled() -> bool {
+    toggle(|state| state.auto_stop, "WA_AUTO_STOP")
+}
+
+/// A threshold: `arg` from the command line, which wins over the tray's
+/// preset, then `env`, then the WA_SENSITIVITY preset
+fn threshold_ms(arg: Option<u64>, env: &str, preset: impl Fn(Thresholds) -> u64) -> std::time::Duration {
+    let from_tray = || helpers::read_tray_state()?.sensitivity.map(|sensitivity| preset(sensitivity.thresholds()));
+    let from_env = || std::env::var(env).ok()?.parse().ok();
+    let ms = arg
+        .or_else(from_tray)
+        .or_else(from_env)
+        .unwrap_or_else(|| preset(sensitivity().thresholds()));
+    std::time::Duration::from_millis(ms)
+}
+
+/// How much to drop from the start of each recording (`stop --trim-lead`, the
+/// tray's preset or WA_TRIM_LEAD_MS), to cut the hotkey's own sound
+fn trim_lead(arg: Option<u64>) -> std::time::Duration {
+    threshold_ms(arg, "WA_TRIM_LEAD_MS", |thresholds| thresholds.trim_lead_ms)
+}
+
+/// Drop the lead-in from a finished recording, keeping it as is if that fails
+fn trim_recording(path: &str, trim_lead_ms: Option<u64>) {
+    let lead = trim_lead(trim_lead_ms);
+    if lead.is_zero() {
+        return;
+    }
+    match crate::wav::trim_lead_file(path, lead) {
+        Ok((trimmed, clip)) => {
+            eprintln!("DEBUG: Trimmed {} ms lead-in from {} ms recording", trimmed.as_millis(), clip.as_millis());
+        }
+        Err(e) => eprintln!("WARNING: Could not trim lead-in of {}: {}", path, e),
+    }
+}
+
+/// Shortest recording worth transcribing (`stop --min-duration`, the tray's
+/// preset or WA_MIN_DURATION_MS, default 400 ms; 0 transcribes everything)
+pub fn min_duration(arg: Option<u64>) -> std::time::Duration {
+    threshold_ms(arg, "WA_MIN_DURATION_MS", |thresholds| thresholds.min_duration_ms)
+}
+
+/// Drop a recording without audio, or too short to hold speech, so an
+/// accidental tap of the hotkey doesn't reach the daemon or load a model.
+/// Returns whether it was dropped.
+pub fn discard_if_too_short(audio_file: &str, backend_name: &str, min_duration_ms: Option<u64>) -> bool {
+    let min = min_duration(min_duration_ms);
+    let notification = match crate::wav::measure_file(audio_file, min) {
+        Ok(AudioLength::Ok(duration)) => {
+            eprintln!("DEBUG: Recording is {} ms (minimum {} ms)", duration.as_millis(), min.as_millis());
+            return false;
+        }
+        Ok(AudioLength::TooShort(duration)) => {
+            eprintln!("DEBUG: Recording is {} ms (minimum {} ms)", duration.as_millis(), min.as_millis());
+            notify::Notification::transient("Voice Input", "🤏 Recording too short")
+        }
+        Ok(AudioLength::Empty) => {
+            notify::Notification::error("Voice Input", &format!("❌ Audio file is empty\nBackend: {}", backend_name))
+        }
+        Err(e) => {
+            // Leave unreadable files to the backend, which reports them properly
+            eprintln!("DEBUG: Could not measure {}: {}", audio_file, e);
+            return false;
+        }
+    };
+    
+    let _ = fs::remove_file(audio_file);
+    notification.send();
+    true
+}
+
+/// Drop a recording with too little speech in it when VAD is on, so a
+/// recording of a quiet room isn't turned into a hallucinated sentence.
+/// Returns whether it was dropped.
+pub fn discard_if_silent(audio_file: &str) -> bool {
+    if !vad_enabled() {
+        return false;
+    }
+    let samples = match fs::read(audio_file).map_err(anyhow::Error::from).and_then(|bytes| crate::audio::Pipeline::target().samples(&bytes)) {
+        Ok(samples) => samples,
+        Err(e) => {
+            eprintln!("DEBUG: Could not check {} for speech: {}", audio_file, e);
+            return false;
+        }
+    };
+    let level = sensitivity().thresholds().speech_level;
+    let voiced = crate::wav::voiced_duration(&samples, level);
+    eprintln!("DEBUG: Recording has {} ms at speech level {}", voiced.as_millis(), level);
+    if voiced >= MIN_VOICED {
+        return false;
+    }
+    
+    let _ = fs::remove_file(audio_file);
+    notify::Notification::transient("Voice Input", "🔇 No speech detected")
+        .send();
+    true
+}
+
+/// Second recording of a `stereo` source, which becomes the right channel
+/// instead of being mixed in
+const RIGHT_CHANNEL_SUFFIX: &str = "-right.wav";
+
+/// Where stereo call recordings are kept for diarizing later
+pub fn calls_dir() -> String {
+    format!("{}/whisp-away/calls", helpers::data_dir())
+}
+
+/// Where recordings that couldn't be transcribed are kept for trying again
+pub fn kept_dir() -> String {
+    format!("{}/whisp-away/kept", helpers::data_dir())
+}
+
+/// Move a recording that couldn't be transcribed to kept_dir(), out of reach
+/// of the cleanup in start_recording, and return where it is now (where it
+/// was, if it couldn't be moved)
+pub fn keep_failed(audio_file: &str) -> String {
+    use std::os::unix::fs::DirBuilderExt;
+    let kept = format!("{}/{}.wav", kept_dir(), unique_stem("voice-recording"));
+    let moved = fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(kept_dir())
+        // The recordings dir is often a tmpfs, so renaming may not do
+        .and_then(|_| fs::rename(audio_file, &kept).or_else(|_| fs::copy(audio_file, &kept).and_then(|_| fs::remove_file(audio_file))));
+    match moved {
+        Ok(()) => kept,
+        Err(e) => {
+            eprintln!("WARNING: Could not move {} to {}: {}", audio_file, kept_dir(), e);
+            audio_file.to_string()
+        }
+    }
+}
+
+/// Keep a copy of a stereo recording, which is only worth diarizing after the
+/// fact, and tell the user where it went
+fn keep_call(audio_file: &str, format: crate::history::AudioFormat) {
+    let kept = format!("{}/{}.wav", calls_dir(), unique_stem("call"));
+    match fs::create_dir_all(calls_dir()).map_err(anyhow::Error::from).and_then(|_| crate::history::store_copy(audio_file, &kept, format)) {
+        Ok(_) => {
+            eprintln!("DEBUG: Kept stereo recording as {}", kept);
+            // Named as it will be once compressed after transcription
+            let archived = crate::history::archived_path(&kept, format);
+            notify::Notification::normal(
+                "Voice Input",
+                &format!("📞 Call saved to {}\nwhisp-away transcribe --diarize-channels {}", archived, archived),
+            )
+            .send();
+            crate::history::archive_later(&kept, format);
+        }
+        Err(e) => eprintln!("WARNING: Could not keep stereo recording {}: {:#}", audio_file, e),
+    }
+}
+
+/// Downmix a stereo recording in place so it's transcribed like any other
+fn downmix(path: &str) -> Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    let samples = crate::audio::Pipeline::target().samples(&bytes)?;
+    fs::write(path, crate::wav::encode_s16_mono(&samples, crate::wav::TARGET_RATE))
+        .with_context(|| format!("Failed to write recording {}", path))
+}
+
+/// Recorder processes still running, from the pidfile (or one an older release left)
+pub fn active_recorders() -> Vec<u32> {
+    let pidfile = helpers::with_legacy_fallback(pidfile(), LEGACY_PIDFILE);
+    read_pidfile(&pidfile)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|pid| is_process_running(*pid))
+        .collect()
+}
+
+/// Stop any running recording without transcribing it
+pub fn abort_recording() {
+    for pid in active_recorders() {
+        stop_recorder(pid);
+    }
+}
+
+/// Pidfile, pointer file and their legacy locations, whether or not they exist
+pub fn state_files() -> Vec<String> {
+    vec![pidfile(), pointer_file(), LEGACY_PIDFILE.to_string(), legacy_pointer_file()]
+}
+
+/// Parse the pidfile, which holds one recorder pid per line
+/// (a single line for mic/monitor, two lines when recording both or stereo)
+fn read_pids(content: &str) -> Vec<u32> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().parse::<u32>().ok())
+        .collect()
+}
+
+/// Recorder pids in `pidfile`, if there is one. A pidfile someone else owns
+/// is never acted on, as the pids in it could be anyone's.
+fn read_pidfile(pidfile: &str) -> Option<Vec<u32>> {
+    let owner = helpers::owner(pidfile)?;
+    if owner != helpers::uid() {
+        eprintln!("WARNING: Ignoring {}, which belongs to uid {}", pidfile, owner);
+        return None;
+    }
+    fs::read_to_string(pidfile).ok().map(|content| read_pids(&content))
+}
+
+/// Stop the recording process and return the audio file path, noting what
+/// was recorded in `run`
+pub fn stop_recording(
+    audio_file_override: Option<&str>,
+    trim_lead_ms: Option<u64>,
+    audio_format: Option<crate::history::AudioFormat>,
+    profile: Option<&str>,
+    fast: bool,
+    run: &mut Run,
+) -> Result<Option<String>> {
+    let audio_format = crate::history::audio_format(audio_format);
+    let pidfile = pidfile();
     
     // Wait a bit for the pidfile to appear if it doesn't exist yet
     let mut attempts = 0;
-    while !std::path::Path::new(&pidfile).exists() && attempts < 10 {
+    while !std::path::Path::new(&pidfile).exists() && !helpers::owned_by_us(LEGACY_PIDFILE) && attempts < 10 {
         std::thread::sleep(std::time::Duration::from_millis(20));
         attempts += 1;
     }
+    let pidfile = helpers::with_legacy_fallback(pidfile, LEGACY_PIDFILE);
+    let pointer_file = helpers::with_legacy_fallback(pointer_file(), &legacy_pointer_file());
     
-    // Stop the recording process if it's running
-    if let Ok(pid_str) = fs::read_to_string(&pidfile) {
-        let pid_str = pid_str.trim();
-        if pid_str.is_empty() {
+    // Stop the recording process(es) if running
+    if let Some(pids) = read_pidfile(&pidfile) {
+        if pids.is_empty() {
             let _ = fs::remove_file(&pidfile);
             return Ok(None);
         }
         
-        if let Ok(pid) = pid_str.parse::<u32>() {
-            if !is_process_running(pid) {
-                // Process already stopped
-                let _ = fs::remove_file(&pidfile);
-                let _ = fs::remove_file(format!("/run/user/{}/voice-audio-file.tmp", uid));
-                return Ok(None);
-            }
-            
-            // Try graceful shutdown first
-            std::thread::sleep(std::time::Duration::from_millis(100));
-            
-            let _ = Command::new("kill")
-                .args(&["-INT", &pid.to_string()])
-                .status();
-            
-            std::thread::sleep(std::time::Duration::from_millis(50));
-            
-            // Force kill if still running
-            if is_process_running(pid) {
-                let _ = Command::new("kill")
-                    .args(&["-TERM", &pid.to_string()])
-                    .status();
-            }
-            
-            std::thread::sleep(std::time::Duration::from_millis(50));
+        let running: Vec<u32> = pids.into_iter().filter(|pid| is_process_running(*pid)).collect();
+        if running.is_empty() {
+            // Process already stopped
+            let _ = fs::remove_file(&pidfile);
+            let _ = fs::remove_file(&pointer_file);
+            return Ok(None);
         }
+        
+        // Try graceful shutdown first
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        
+        for pid in &running {
+            stop_recorder(*pid);
+        }
+        
+        std::thread::sleep(std::time::Duration::from_millis(50));
     }
     
     let _ = fs::remove_file(&pidfile);
@@ -56,21 +428,50 @@ pub fn stop_recording(audio_file_override: Option<&str>) -> Result<Option<String
     // Get the audio file path
     let audio_file = if let Some(override_path) = audio_file_override {
         // Copy the override file to a temporary location so it can be cleaned up
-        let runtime_dir = crate::helpers::get_runtime_dir();
-        let temp_audio = format!("{}/voice-recording-override-{}.wav", runtime_dir, 
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis());
+        let temp_audio = spool_file(&format!("{}.wav", unique_stem("voice-recording-override")));
         fs::copy(override_path, &temp_audio)
             .context("Failed to copy audio file to temporary location")?;
+        run.recording = inspect_recording(&temp_audio);
+        normalize_recording(&temp_audio);
         temp_audio
     } else {
-        match fs::read_to_string(format!("/run/user/{}/voice-audio-file.tmp", uid)) {
-            Ok(path) => {
-                let path = path.trim().to_string();
-                let _ = fs::remove_file(format!("/run/user/{}/voice-audio-file.tmp", uid));
-                path
+        match fs::read_to_string(&pointer_file) {
+            Ok(content) => {
+                let _ = fs::remove_file(&pointer_file);
+                let paths: Vec<&str> = content.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+                for (n, path) in paths.iter().enumerate() {
+                    // Inspected before conversion hides what pw-record wrote
+                    let info = inspect_recording(path);
+                    if n == 0 {
+                        run.recording = info;
+                    }
+                    normalize_recording(path);
+                }
+                let stereo = matches!(paths.as_slice(), [_, right] if right.ends_with(RIGHT_CHANNEL_SUFFIX));
+                let audio_file = match paths.as_slice() {
+                    [] => return Ok(None),
+                    [single] => single.to_string(),
+                    [left, right] if stereo => {
+                        crate::wav::merge_stereo_files(left, right)?;
+                        let _ = fs::remove_file(right);
+                        left.to_string()
+                    }
+                    [first, rest @ ..] => {
+                        // Both sources were recorded: mix them into the first file
+                        let mixed = mix_wav_files(first, rest)?;
+                        for path in rest {
+                            let _ = fs::remove_file(path);
+                        }
+                        mixed
+                    }
+                };
+                // Recorded by us, so it starts with whatever sound the hotkey made
+                trim_recording(&audio_file, trim_lead_ms);
+                if stereo {
+                    keep_call(&audio_file, audio_format);
+                    downmix(&audio_file)?;
+                }
+                audio_file
             },
             Err(_) => {
                 return Ok(None);
@@ -78,79 +479,1089 @@ pub fn stop_recording(audio_file_override: Option<&str>) -> Result<Option<String
         }
     };
     
+    run.clipped = check_clipping(&audio_file);
+    
+    // Copied before transcription, which may delete the recording. The copy
+    // is what was recorded, before preprocessing.
+    run.kept = crate::history::keep(&audio_file, audio_format);
+    run.preprocessing = preprocess_recording(&audio_file, profile, fast);
+    
     Ok(Some(audio_file))
 }
 
-/// Common function to start recording audio
-pub fn start_recording(backend_name: &str) -> Result<()> {
-    let pidfile = "/tmp/whisp-away-recording.pid";
-    let uid = unsafe { libc::getuid() };
+/// Clipped audio transcribes noticeably worse; returns the share of the
+/// recording that clipped when it is over wav::CLIP_WARN_FRACTION, so the
+/// user hears about it
+fn check_clipping(audio_file: &str) -> Option<f32> {
+    let samples = match fs::read(audio_file).map_err(anyhow::Error::from).and_then(|bytes| crate::audio::Pipeline::target().samples(&bytes)) {
+        Ok(samples) => samples,
+        Err(e) => {
+            eprintln!("DEBUG: Could not check {} for clipping: {}", audio_file, e);
+            return None;
+        }
+    };
+    let fraction = crate::wav::clipped_fraction(&samples);
+    if fraction <= crate::wav::CLIP_WARN_FRACTION {
+        return None;
+    }
+    eprintln!("WARNING: {:.1}% of the recording clipped (gain {})", fraction * 100.0, record_gain());
+    Some(fraction)
+}
+
+/// Length of the recording about to be transcribed, from its WAV data
+pub fn measure(audio_file: &str) -> Option<std::time::Duration> {
+    crate::wav::file_duration(audio_file)
+        .map_err(|e| eprintln!("DEBUG: Could not measure {}: {}", audio_file, e))
+        .ok()
+}
+
+/// Filtering applied to a recording before transcription
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Preprocessing {
+    pub high_pass_hz: f32,
+    /// Gain peak normalization applied, in dB
+    pub gain_db: f32,
+}
+
+impl std::fmt::Display for Preprocessing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "high-pass {} Hz, gain {:+.1} dB", self.high_pass_hz, self.gain_db)
+    }
+}
+
+/// Whether recordings are high-passed and normalized before transcription:
+/// the active profile's `preprocess`, else WA_PREPROCESS=1
+fn preprocess_enabled(profile: Option<&str>, fast: bool) -> bool {
+    if fast {
+        return false;
+    }
+    crate::profile::active(profile)
+        .and_then(|profile| profile.preprocess)
+        .unwrap_or_else(|| matches!(std::env::var("WA_PREPROCESS").as_deref(), Ok("1") | Ok("true")))
+}
+
+/// Take out low-frequency rumble (fans, desk bumps) and bring the level up to
+/// a consistent peak, keeping the recording as is if that fails. Returns what
+/// was applied, if anything.
+fn preprocess_recording(path: &str, profile: Option<&str>, fast: bool) -> Option<Preprocessing> {
+    if !preprocess_enabled(profile, fast) {
+        return None;
+    }
+    let started = std::time::Instant::now();
+    match crate::wav::preprocess_file(path) {
+        Ok(gain_db) => {
+            let preprocessing = Preprocessing { high_pass_hz: crate::audio::HIGH_PASS_HZ, gain_db };
+            eprintln!("DEBUG: Preprocessed recording ({}) in {:?}", preprocessing, started.elapsed());
+            Some(preprocessing)
+        }
+        Err(e) => {
+            eprintln!("WARNING: Could not preprocess {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Gain pw-record applies to the input (WA_RECORD_GAIN, default 1.5)
+fn record_gain() -> String {
+    std::env::var("WA_RECORD_GAIN")
+        .ok()
+        .filter(|gain| gain.parse::<f32>().is_ok_and(|gain| gain > 0.0))
+        .unwrap_or_else(|| DEFAULT_RECORD_GAIN.to_string())
+}
+
+/// pw-record can negotiate a different rate or sample format than requested on
+/// some devices; convert those recordings so whisper isn't fed misread bytes
+fn normalize_recording(path: &str) {
+    if let Err(e) = crate::wav::normalize_file(path) {
+        eprintln!("WARNING: Could not check format of {}: {}", path, e);
+    }
+}
+
+/// Mix several 16 kHz s16 mono WAV recordings into the first one, returning its path.
+/// Streams are summed with clamping; shorter streams are treated as trailing silence.
+fn mix_wav_files(base: &str, others: &[&str]) -> Result<String> {
+    let bytes = fs::read(base).context("Failed to read mic recording")?;
+    let Ok(wav) = crate::wav::parse(&bytes) else {
+        return Ok(base.to_string());
+    };
+    // Chunks before and after the samples (LIST, fact, ...) are kept as they are
+    let data_start = wav.data_offset;
+    let data_end = data_start + wav.data.len();
+    let mut data = wav.data.to_vec();
+    
+    for other in others {
+        let Ok(other_bytes) = fs::read(other) else { continue };
+        let Ok(other_wav) = crate::wav::parse(&other_bytes) else { continue };
+        let other_samples = other_wav.data;
+        
+        // Extend the base with silence if the other stream is longer
+        if other_samples.len() > data.len() {
+            data.resize(other_samples.len(), 0);
+        }
+        
+        for (dst, src) in data.chunks_exact_mut(2).zip(other_samples.chunks_exact(2)) {
+            let a = i16::from_le_bytes([dst[0], dst[1]]) as i32;
+            let b = i16::from_le_bytes([src[0], src[1]]) as i32;
+            let mixed = (a + b).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            dst.copy_from_slice(&mixed.to_le_bytes());
+        }
+    }
+    
+    // Chunks are padded to an even size
+    let trailer = bytes.get(data_end + (data_end - data_start) % 2..).unwrap_or_default();
+    let mut mixed = Vec::with_capacity(data_start + data.len() + 1 + trailer.len());
+    mixed.extend_from_slice(&bytes[..data_start]);
+    mixed.extend_from_slice(&data);
+    if data.len() % 2 == 1 && !trailer.is_empty() {
+        mixed.push(0);
+    }
+    mixed.extend_from_slice(trailer);
+    
+    // Fix up RIFF and data chunk sizes in case the samples grew
+    let riff_size = u32::try_from(mixed.len() - 8).unwrap_or(u32::MAX);
+    let data_size = u32::try_from(data.len()).unwrap_or(u32::MAX);
+    mixed[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    mixed[data_start - 4..data_start].copy_from_slice(&data_size.to_le_bytes());
+    
+    fs::write(base, &mixed).context("Failed to write mixed recording")?;
+    Ok(base.to_string())
+}
+
+/// Name of the default sink, whose monitor carries what's playing through the speakers
+fn default_sink_name() -> Result<String> {
+    let output = child_env::command("pactl", &[])
+        .arg("get-default-sink")
+        .output()
+        .context("Failed to run pactl to find the default sink")?;
+    
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || name.is_empty() {
+        return Err(anyhow::anyhow!("Could not determine the default output device"));
+    }
+    Ok(name)
+}
+
+/// How long pw-record gets to fail (no PipeWire, unknown target) before start
+/// reports it as recording
+const STARTUP_CHECK: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// How long the recording file gets to start growing before start warns
+const GROWTH_CHECK: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Where pw-record's stderr goes, next to its audio file: read by the startup
+/// check, and at stop time for the journal
+fn recorder_log(audio_file: &str) -> String {
+    format!("{}.log", audio_file.trim_end_matches(".wav"))
+}
+
+/// Header lines spawn_recorder writes into the log ahead of pw-record's output
+const LOG_COMMAND: &str = "# command: ";
+const LOG_DEVICE: &str = "# device: ";
+
+/// What was asked for and what actually got recorded, for debugging a bad transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingInfo {
+    /// The pw-record invocation, when we recorded it ourselves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Input device, "default", or the sink whose monitor was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Format requested from pw-record
+    pub requested: String,
+    /// Format in the WAV header, before any conversion
+    pub recorded: String,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    /// What pw-record printed, including the stream format it negotiated
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub recorder_output: String,
+}
+
+/// Spawn pw-record for one source, writing to `audio_file`
+fn spawn_recorder(audio_file: &str, monitor_of: Option<&str>) -> Result<std::process::Child> {
+    let mut cmd = child_env::command("pw-record", &[]);
+    let gain = record_gain();
+    cmd.args([
+        "--channels", "1",
+        "--rate", "16000",
+        "--format", "s16",
+        "--volume", &gain,
+    ]);
+    
+    if let Some(sink) = monitor_of {
+        // Capture the sink's monitor ports rather than an input device
+        cmd.args(["--target", sink, "-P", "{ stream.capture.sink=true }"]);
+    } else if let Some(device) = input_device() {
+        cmd.args(["--target", &device]);
+    }
+    
+    // Verbose, so the log shows the stream format pw-record negotiated
+    cmd.arg("--verbose").arg(audio_file);
+    
+    let mut log = fs::File::create(recorder_log(audio_file))
+        .context("Failed to create pw-record log")?;
+    let command: Vec<String> = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let device = match monitor_of {
+        Some(sink) => format!("monitor of {}", sink),
+        None => input_device().unwrap_or_else(|| "default".to_string()),
+    };
+    // pw-record appends to the same file after these
+    writeln!(log, "{}{}\n{}{}", LOG_COMMAND, command.join(" "), LOG_DEVICE, device)
+        .context("Failed to write pw-record log")?;
+    cmd.stderr(log)
+        .spawn()
+        .context("Failed to start pw-record")
+}
+
+/// Describe a finished recording before it is converted, from its WAV header
+/// and its pw-record log, and warn if pw-record didn't record what it was
+/// asked for. The log is removed once read.
+fn inspect_recording(path: &str) -> Option<RecordingInfo> {
+    let log_path = recorder_log(path);
+    let log = fs::read_to_string(&log_path).unwrap_or_default();
+    let _ = fs::remove_file(&log_path);
+    
+    let bytes = fs::read(path).ok()?;
+    let wav = crate::wav::parse(&bytes).ok()?;
+    if wav.format != crate::wav::EXPECTED_FORMAT {
+        eprintln!("WARNING: pw-record recorded {} instead of {}; converting", wav.format, crate::wav::EXPECTED_FORMAT);
+    }
+    
+    let header = |prefix: &str| log.lines().find_map(|line| line.strip_prefix(prefix)).map(str::to_string);
+    let recorder_output = log
+        .lines()
+        .filter(|line| !line.starts_with(LOG_COMMAND) && !line.starts_with(LOG_DEVICE))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(RecordingInfo {
+        command: header(LOG_COMMAND),
+        device: header(LOG_DEVICE),
+        requested: crate::wav::EXPECTED_FORMAT.to_string(),
+        recorded: wav.format.to_string(),
+        bytes: bytes.len() as u64,
+        duration_ms: wav.duration().as_millis() as u64,
+        recorder_output: recorder_output.trim().to_string(),
+    })
+}
+
+/// Check that every recorder survived its first moments. A recorder that
+/// exited is reported with what it printed; the others are stopped.
+fn check_recorders(children: &mut [(std::process::Child, String)]) -> Result<()> {
+    std::thread::sleep(STARTUP_CHECK);
+    let mut failure = None;
+    for (child, path) in children.iter_mut() {
+        if let Ok(Some(status)) = child.try_wait() {
+            let output = fs::read_to_string(recorder_log(path)).unwrap_or_default();
+            let output = output.trim();
+            let reason = if output.is_empty() { status.to_string() } else { output.to_string() };
+            failure = Some(anyhow::anyhow!("pw-record exited right away: {}", reason));
+            break;
+        }
+    }
+    for (child, path) in children.iter_mut() {
+        if failure.is_some() {
+            if let Ok(None) = child.try_wait() {
+                stop_recorder(child.id());
+            }
+            let _ = fs::remove_file(path.as_str());
+        }
+        // Kept for stop, which reads the negotiated format from it
+        if failure.is_some() {
+            let _ = fs::remove_file(recorder_log(path));
+        }
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Whether `audio_file` exists and keeps growing within GROWTH_CHECK of
+/// starting. pw-record can run happily on a device that never delivers samples.
+fn recording_grows(audio_file: &str) -> bool {
+    let deadline = std::time::Instant::now() + GROWTH_CHECK.saturating_sub(STARTUP_CHECK);
+    let first = fs::metadata(audio_file).map(|meta| meta.len()).ok();
+    loop {
+        let size = fs::metadata(audio_file).map(|meta| meta.len()).ok();
+        if matches!((first, size), (Some(first), Some(size)) if size > first) {
+            return true;
+        }
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+/// Free space the runtime dir needs before recordings go there: a couple of
+/// hours of 16 kHz mono. It is often a small tmpfs other programs need too.
+const RUNTIME_HEADROOM_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Bytes unprivileged users can still write to the filesystem holding `dir`
+fn free_bytes(dir: &str) -> Option<u64> {
+    let path = std::ffi::CString::new(dir).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Whether a runtime dir with `free` bytes available can take recordings.
+/// One whose free space can't be read gets the benefit of the doubt.
+fn runtime_has_headroom(free: Option<u64>) -> bool {
+    !matches!(free, Some(free) if free < RUNTIME_HEADROOM_BYTES)
+}
+
+/// Where recordings go when the runtime dir is short on space
+pub fn fallback_recordings_dir() -> String {
+    format!("{}/whisp-away/tmp", helpers::cache_dir())
+}
+
+/// Directory recordings and other spooled audio are written to (WA_RECORDINGS_DIR):
+/// by default the runtime dir, or $XDG_CACHE_HOME/whisp-away/tmp when the
+/// runtime dir has less than RUNTIME_HEADROOM_BYTES free. Decided once per process.
+pub fn recordings_dir() -> String {
+    static DIR: std::sync::OnceLock<String> = std::sync::OnceLock::new();
     
-    // Kill any existing recording process
-    if let Ok(pid_str) = fs::read_to_string(&pidfile) {
-        if let Ok(pid) = pid_str.trim().parse::<u32>() {
+    DIR.get_or_init(|| {
+        let configured = std::env::var("WA_RECORDINGS_DIR").ok().filter(|dir| dir.starts_with('/'));
+        if let Some(dir) = configured {
+            create_private_dir(&dir);
+            return dir;
+        }
+        let runtime_dir = helpers::runtime_dir();
+        let free = free_bytes(&runtime_dir);
+        if runtime_has_headroom(free) {
+            return runtime_dir;
+        }
+        let dir = fallback_recordings_dir();
+        eprintln!(
+            "DEBUG: Only {} MB free in {}, writing recordings to {} instead",
+            free.unwrap_or(0) / (1024 * 1024),
+            runtime_dir,
+            dir
+        );
+        create_private_dir(&dir);
+        dir
+    })
+    .clone()
+}
+
+fn create_private_dir(dir: &str) {
+    use std::os::unix::fs::DirBuilderExt;
+    if let Err(e) = fs::DirBuilder::new().recursive(true).mode(0o700).create(dir) {
+        eprintln!("WARNING: Could not create recordings dir {}: {}", dir, e);
+    }
+}
+
+/// Path of a spooled audio file in the recordings dir
+pub fn spool_file(name: &str) -> String {
+    format!("{}/{}", recordings_dir(), name)
+}
+
+/// A file name stem no other recording shares: `prefix`, this process, a
+/// counter and a random suffix. Milliseconds since the epoch collided when
+/// two starts landed in the same millisecond.
+pub fn unique_stem(prefix: &str) -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let mut random = [0u8; 4];
+    if let Err(e) = helpers::random_bytes(&mut random) {
+        // The pid and counter alone are unique among live processes
+        eprintln!("WARNING: {:#}", e);
+    }
+    format!(
+        "{}-{}-{}-{:08x}",
+        prefix,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        u32::from_le_bytes(random)
+    )
+}
+
+/// Prefix of recordings a `stop` has taken over for direct transcription
+const CLAIMED_PREFIX: &str = "voice-transcribing";
+
+/// Take `audio_file` out of reach of the cleanup in start_recording: direct
+/// transcription may queue behind another for minutes, and a `start` in that
+/// time would delete the recording. It is renamed in place under a stem
+/// carrying our pid, so the cleanup only takes it once we are gone. Falls
+/// back to the path as it was if it can't be renamed.
+pub fn claim(audio_file: &str) -> String {
+    let dir = std::path::Path::new(audio_file).parent().unwrap_or(std::path::Path::new("."));
+    let claimed = dir.join(format!("{}.wav", unique_stem(CLAIMED_PREFIX))).to_string_lossy().into_owned();
+    match fs::rename(audio_file, &claimed) {
+        Ok(()) => claimed,
+        Err(e) => {
+            eprintln!("WARNING: Could not claim {}: {}", audio_file, e);
+            audio_file.to_string()
+        }
+    }
+}
+
+/// Whether the cleanup may delete a recording named `name`: any of
+/// start_recording's, but a claimed one only once its `stop` has exited
+fn stale_recording(name: &str) -> bool {
+    if let Some(rest) = name.strip_prefix(CLAIMED_PREFIX).and_then(|rest| rest.strip_prefix('-')) {
+        let pid = rest.split('-').next().and_then(|pid| pid.parse().ok());
+        return name.ends_with(".wav") && !pid.is_some_and(is_process_running);
+    }
+    name.starts_with("voice-recording-") && (name.ends_with(".wav") || name.ends_with(".log"))
+}
+
+/// Write the pointer file through a temporary file and a rename, so `stop`
+/// never reads it half-written
+fn write_pointer(paths: &str) -> Result<()> {
+    let path = pointer_file();
+    let tmp = format!("{}.{}.tmp", path, std::process::id());
+    fs::write(&tmp, paths).with_context(|| format!("Failed to write {}", tmp))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to move {} into place", path))
+}
+
+/// Every directory recordings may have been left in, for cleaning up: another
+/// process may have decided differently about the runtime dir's free space
+pub fn recordings_dirs() -> Vec<String> {
+    let mut dirs = vec![recordings_dir(), helpers::runtime_dir(), fallback_recordings_dir()];
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Where a new recording goes: the inbox of the daemon it will be sent to
+/// while that daemon is up, so the daemon deletes it even if this client
+/// dies before it can, else the recordings dir
+fn recording_dir(backend_name: &str, profile: Option<&str>) -> String {
+    let target = crate::daemons::select(&crate::daemons::Selector::resolve(None), backend_name, &helpers::resolve_model(None, profile));
+    target
+        .ok()
+        .and_then(|target| crate::inbox::advertised(&target.ready_file))
+        .unwrap_or_else(recordings_dir)
+}
+
+/// Common function to start recording audio. `delay_start` waits before
+/// pw-record launches, for hotkeys whose sound plays first.
+pub fn start_recording(backend_name: &str, source: AudioSource, delay_start: std::time::Duration, profile: Option<&str>) -> Result<()> {
+    // Kill any existing recording process(es)
+    let old_pidfile = helpers::with_legacy_fallback(pidfile(), LEGACY_PIDFILE);
+    if let Some(pids) = read_pidfile(&old_pidfile) {
+        let mut killed = false;
+        for pid in pids {
             if is_process_running(pid) {
-                let _ = Command::new("kill")
-                    .args(&["-TERM", &pid.to_string()])
+                let _ = child_env::command("kill", &[])
+                    .args(["-TERM", &pid.to_string()])
                     .status();
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                killed = true;
             }
         }
-        let _ = fs::remove_file(&pidfile);
+        if killed {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
     }
+    // Also one that was ignored, so the new pidfile is written as ours
+    let _ = fs::remove_file(&old_pidfile);
     
-    let runtime_dir = crate::helpers::get_runtime_dir();
-    let audio_file = format!("{}/voice-recording-{}.wav", runtime_dir, 
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis());
+    let recording_dir = recording_dir(backend_name, profile);
+    let stem = unique_stem("voice-recording");
+    let audio_file = format!("{}/{}.wav", recording_dir, stem);
+    let monitor_file = match source {
+        AudioSource::Stereo => format!("{}/{}{}", recording_dir, stem, RIGHT_CHANNEL_SUFFIX),
+        _ => format!("{}/{}-monitor.wav", recording_dir, stem),
+    };
 
-    // Clean up old recording files
-    if let Ok(entries) = fs::read_dir(&runtime_dir) {
+    // Clean up old recording files, named by the millisecond in older releases
+    // and by unique_stem now, which share the prefix; the daemon sweeps its
+    // inbox itself
+    for entries in recordings_dirs().iter().filter_map(|dir| fs::read_dir(dir).ok()) {
         for entry in entries.flatten() {
             if let Some(name) = entry.file_name().to_str() {
-                if name.starts_with("voice-recording-") && name.ends_with(".wav") {
-                    if entry.path().to_str() != Some(&audio_file) {
-                        let _ = fs::remove_file(entry.path());
+                if stale_recording(name) {
+                    let path = entry.path();
+                    if path.to_str() != Some(&audio_file) && path.to_str() != Some(&monitor_file) {
+                        let _ = fs::remove_file(path);
                     }
                 }
             }
         }
     }
     
-    // Store the audio file path for later retrieval
-    fs::write(format!("/run/user/{}/voice-audio-file.tmp", uid), &audio_file)
-        .context("Failed to write audio file path")?;
+    let sink = match source {
+        AudioSource::Mic => None,
+        AudioSource::Monitor | AudioSource::Both | AudioSource::Stereo => Some(default_sink_name()?),
+    };
+    
+    // Output files in pointer order: the first one is what gets transcribed,
+    // any further ones are mixed into it (or become its right channel) at stop time
+    let files: Vec<(&str, Option<&str>)> = match source {
+        AudioSource::Mic => vec![(&audio_file, None)],
+        AudioSource::Monitor => vec![(&audio_file, sink.as_deref())],
+        AudioSource::Both | AudioSource::Stereo => vec![(&audio_file, None), (&monitor_file, sink.as_deref())],
+    };
+    
+    let delay_start = if delay_start > MAX_DELAY_START {
+        eprintln!("WARNING: Capping --delay-start of {} ms at {} ms", delay_start.as_millis(), MAX_DELAY_START.as_millis());
+        MAX_DELAY_START
+    } else {
+        delay_start
+    };
+    if !delay_start.is_zero() {
+        eprintln!("DEBUG: Delaying recording start by {} ms", delay_start.as_millis());
+        std::thread::sleep(delay_start);
+    }
+    
+    // Store the audio file path(s) for later retrieval
+    let pointer = files.iter().map(|(path, _)| *path).collect::<Vec<_>>().join("\n");
+    write_pointer(&pointer).context("Failed to write audio file path")?;
 
     // Start recording
-    let child = Command::new("pw-record")
-        .args(&[
-            "--channels", "1",
-            "--rate", "16000",
-            "--format", "s16",
-            "--volume", "1.5",
-            &audio_file,
-        ])
-        .spawn()
-        .context("Failed to start pw-record")?;
+    let mut children: Vec<(std::process::Child, String)> = Vec::new();
+    for (path, monitor_of) in &files {
+        let child = match spawn_recorder(path, *monitor_of) {
+            Ok(child) => child,
+            Err(e) => {
+                // Don't leave a half-started pair behind
+                for (child, _) in &children {
+                    stop_recorder(child.id());
+                }
+                let _ = fs::remove_file(pointer_file());
+                return Err(e);
+            }
+        };
+        children.push((child, path.to_string()));
+    }
+    let pids: Vec<u32> = children.iter().map(|(child, _)| child.id()).collect();
+    // Only now, so the probe never holds up the recording
+    let daemon_probe = probe_daemon(backend_name, profile);
 
-    fs::write(&pidfile, child.id().to_string())
+    // Written before the startup check so a quick stop still finds the recording
+    let pid_lines = pids.iter().map(|pid| pid.to_string()).collect::<Vec<_>>().join("\n");
+    fs::write(pidfile(), pid_lines)
         .context("Failed to write PID file")?;
+    
+    if let Err(e) = check_recorders(&mut children) {
+        let _ = fs::remove_file(pidfile());
+        let _ = fs::remove_file(pointer_file());
+        crate::events::emit(crate::events::Event::Error { code: "recording_failed".to_string() });
+        notify::Notification::error("Voice Input", &format!("❌ Recording failed to start\n{}", e))
+            .urgent()
+            .timeout(5000)
+            .send();
+        return Err(e);
+    }
+    crate::events::emit(crate::events::Event::RecordingStarted);
 
     // Get model from environment/state for notification
-    let model = crate::helpers::resolve_model(None);
+    let model = crate::helpers::resolve_model(None, profile);
     let acceleration = crate::helpers::get_acceleration_type();
-    let recording_msg = format!("🎤 Recording... (release to stop)\nBackend: {} ({}) | Model: {}", backend_name, acceleration, model);
+    let source_note = if source == AudioSource::Mic {
+        String::new()
+    } else {
+        format!(" | Source: {}", source.label())
+    };
+    let daemon_state = daemon_probe.recv_timeout(DAEMON_PROBE_TIMEOUT).unwrap_or(DaemonState::NoAnswer);
+    crate::events::emit(crate::events::Event::DaemonState {
+        state: daemon_state.code().to_string(),
+        model: daemon_state.model(),
+    });
+    let daemon_note = if daemon_hint_enabled() { format!(" | Daemon: {}", daemon_state) } else { String::new() };
+    let detail = format!("Backend: {} ({}) | Model: {}{}{}", backend_name, acceleration, model, source_note, daemon_note);
+    let recording_msg = format!("🎤 Recording... (release to stop)\n{}", detail);
+    
+    notify::Notification::transient("Voice Input", &recording_msg)
+        .timeout(30000)
+        .send();
+
+    // The timer process is also what notices the pause that ends a recording
+    if timer_enabled() || auto_stop_enabled() {
+        if let Err(e) = spawn_timer(pids[0], &audio_file, &detail) {
+            eprintln!("Warning: Failed to start recording timer: {}", e);
+        }
+    }
     
-    Command::new("notify-send")
-        .args(&[
+    if !recording_grows(&audio_file) {
+        eprintln!("WARNING: {} isn't growing; pw-record may not be getting audio", audio_file);
+        notify::Notification::normal(
             "Voice Input",
-            &recording_msg,
-            "-t", "30000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
+            &format!("⚠️ No audio arriving yet, check the input device\n{}", detail),
+        )
+        .timeout(5000)
+        .send();
+    }
+    
+    Ok(())
+}
+
+/// How long the start notification waits to hear about the daemon
+const DAEMON_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(100);
 
+/// What the daemon a recording will be sent to is doing, as of its start
+enum DaemonState {
+    Ready { model: String, queue: Option<crate::ready::QueueStatus> },
+    /// Socket up but no ready file yet: the model is still loading
+    Starting,
+    /// Nothing running, so stop transcribes directly
+    Offline,
+    /// The probe didn't finish within DAEMON_PROBE_TIMEOUT
+    NoAnswer,
+}
+
+impl DaemonState {
+    /// Stable name for the events FIFO
+    fn code(&self) -> &'static str {
+        match self {
+            DaemonState::Ready { queue: Some(queue), .. } if queue.active.is_some() => "busy",
+            DaemonState::Ready { .. } => "ready",
+            DaemonState::Starting => "starting",
+            DaemonState::Offline => "offline",
+            DaemonState::NoAnswer => "no_answer",
+        }
+    }
+    
+    fn model(&self) -> Option<String> {
+        match self {
+            DaemonState::Ready { model, .. } => Some(model.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DaemonState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonState::Ready { model, queue: Some(queue) } if queue.active.is_some() => write!(f, "busy ({}), {}", model, queue),
+            DaemonState::Ready { model, .. } => write!(f, "ready ({})", model),
+            DaemonState::Starting => write!(f, "loading the model, may be slow"),
+            DaemonState::Offline => write!(f, "offline — fallback will be slow"),
+            DaemonState::NoAnswer => write!(f, "not answering"),
+        }
+    }
+}
+
+/// Look up the daemon `stop` will send this recording to, on a thread so a
+/// slow filesystem can't hold up the recording; the answer arrives on the
+/// returned channel
+fn probe_daemon(backend_name: &str, profile: Option<&str>) -> std::sync::mpsc::Receiver<DaemonState> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let backend_name = backend_name.to_string();
+    let profile = profile.map(str::to_string);
+    std::thread::spawn(move || {
+        let selector = crate::daemons::Selector::resolve(None);
+        let state = match crate::daemons::select(&selector, &backend_name, &helpers::resolve_model(None, profile.as_deref())) {
+            Ok(target) => match crate::ready::check(&target.ready_file, &target.socket_path) {
+                Ok(info) => DaemonState::Ready { model: info.model, queue: info.queue },
+                Err(crate::ready::NotReady::Starting { .. }) => DaemonState::Starting,
+                Err(_) => DaemonState::Offline,
+            },
+            Err(_) => DaemonState::Offline,
+        };
+        let _ = sender.send(state);
+    });
+    receiver
+}
+
+/// The daemon's state is in the start notification unless WA_DAEMON_HINT is
+/// "0" or "false"
+fn daemon_hint_enabled() -> bool {
+    !matches!(std::env::var("WA_DAEMON_HINT").as_deref(), Ok("0") | Ok("false"))
+}
+
+/// The live recording timer is on unless WA_RECORDING_TIMER is "0" or "false"
+fn timer_enabled() -> bool {
+    !matches!(std::env::var("WA_RECORDING_TIMER").as_deref(), Ok("0") | Ok("false"))
+}
+
+/// Start `recording-timer` as a detached process so `start` returns right away
+fn spawn_timer(pid: u32, audio_file: &str, detail: &str) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    
+    let binary_path = std::env::current_exe()
+        .context("Failed to get current executable path")?;
+    child_env::command(&binary_path, &[])
+        .args(["recording-timer", "--pid", &pid.to_string(), "--audio-file", audio_file, "--detail", detail])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to spawn recording timer")?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// The last `ms` written to a recording, which pw-record writes as 16 kHz s16 mono
+fn recording_tail(audio_file: &str, ms: u64) -> Option<Vec<f32>> {
+    use std::io::{Read, Seek, SeekFrom};
+    
+    let tail_bytes = crate::wav::TARGET_RATE as u64 * 2 * ms / 1000;
+    let mut file = fs::File::open(audio_file).ok()?;
+    let len = file.metadata().ok()?.len();
+    
+    // Find the data chunk in the header rather than reading the whole file;
+    // while pw-record writes it, the data size is 0 and the samples run to the end
+    let mut head = Vec::new();
+    (&mut file).take(4096).read_to_end(&mut head).ok()?;
+    let data_start = crate::wav::parse(&head).ok()?.data_offset as u64;
+    let size = u32::from_le_bytes(head[data_start as usize - 4..data_start as usize].try_into().ok()?) as u64;
+    let data_end = if size == 0 || data_start + size > len { len } else { data_start + size };
+    if data_end < data_start + tail_bytes {
+        return None;
+    }
+    // Stay sample-aligned
+    file.seek(SeekFrom::Start(data_end - tail_bytes - (data_end - data_start) % 2)).ok()?;
+    let mut bytes = vec![0u8; tail_bytes as usize];
+    file.read_exact(&mut bytes).ok()?;
+    
+    Some(bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+        .collect())
+}
+
+/// Level of the last 100 ms written to a recording as a 10-segment meter
+fn level_meter(audio_file: &str) -> Option<String> {
+    let peak = recording_tail(audio_file, 100)?
+        .iter()
+        .map(|sample| sample.abs())
+        .fold(0.0f32, f32::max);
+    let lit = ((peak * 10.0).ceil() as usize).min(10);
+    Some(format!("{}{}", "▮".repeat(lit), "▯".repeat(10 - lit)))
+}
+
+/// Listens for the pause that ends a recording when auto-stop is on
+struct PauseWatch {
+    thresholds: Thresholds,
+    heard_speech: bool,
+}
+
+impl PauseWatch {
+    /// Whether the recording has been quiet for the preset's pause, checked
+    /// once a second. Silence before the first word doesn't count.
+    fn paused(&mut self, audio_file: &str) -> bool {
+        let level = self.thresholds.speech_level;
+        let has_speech = |samples: Vec<f32>| !crate::wav::voiced_duration(&samples, level).is_zero();
+        if !self.heard_speech {
+            self.heard_speech = recording_tail(audio_file, 1000).is_some_and(has_speech);
+            return false;
+        }
+        recording_tail(audio_file, self.thresholds.silence_ms).is_some_and(|samples| !has_speech(samples))
+    }
+}
+
+/// Run `stop` as if the hotkey had been released, detached so it outlives the timer
+fn stop_after_pause() -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    
+    let binary_path = std::env::current_exe()
+        .context("Failed to get current executable path")?;
+    child_env::command(&binary_path, &[])
+        .arg("stop")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to run stop after a pause")?;
+    Ok(())
+}
+
+/// Update the recording notification every second with the elapsed time and
+/// input level until the recorder `pid` exits, and stop the recording after
+/// a pause when auto-stop is on
+pub fn run_timer(pid: u32, audio_file: &str, detail: &str) -> Result<()> {
+    let pidfile = pidfile();
+    let started = std::time::Instant::now();
+    let recording = || is_process_running(pid) && std::path::Path::new(&pidfile).exists();
+    let show_timer = timer_enabled();
+    // Settings are read once, so changing them applies from the next recording
+    let mut pause_watch = auto_stop_enabled().then(|| PauseWatch {
+        thresholds: sensitivity().thresholds(),
+        heard_speech: false,
+    });
+    
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        
+        if let Some(watch) = pause_watch.as_mut() {
+            if recording() && watch.paused(audio_file) {
+                eprintln!("DEBUG: {} ms pause after speech, stopping the recording", watch.thresholds.silence_ms);
+                return stop_after_pause();
+            }
+        }
+        
+        let elapsed = started.elapsed().as_secs();
+        let level = level_meter(audio_file).unwrap_or_default();
+        let body = format!("🎤 Recording… {}:{:02} {}\n{}", elapsed / 60, elapsed % 60, level, detail);
+        
+        // Check right before sending so a final update can't overwrite
+        // the "Transcribing..." notification that follows stop
+        if !recording() {
+            return Ok(());
+        }
+        if show_timer {
+            notify::Notification::progress("Voice Input", &body)
+                .timeout(2000)
+                .send();
+        }
+    }
+}
+
+/// Set by Ctrl-C or SIGTERM while `record` runs
+static RECORD_INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+extern "C" fn interrupt_record(_signal: libc::c_int) {
+    RECORD_INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Record the input device to `out` in the foreground until Ctrl-C, SIGTERM
+/// or `max_duration`, showing the elapsed time and level like a dictation
+/// does. Kept apart from dictation: no pidfile or pointer file, so `start`
+/// and `stop` keep working meanwhile. Fails when no audio was captured.
+pub fn record_to_file(out: &str, max_duration: Option<std::time::Duration>) -> Result<()> {
+    use std::sync::atomic::Ordering;
+    
+    let path = std::path::Path::new(out);
+    if path.exists() {
+        return Err(anyhow::anyhow!("{} already exists; pick another name or remove it first", out));
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    
+    // Ctrl-C reaches pw-record too, which finishes its file; we stay to check it
+    let handler = interrupt_record as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    
+    let mut children = vec![(spawn_recorder(out, None)?, out.to_string())];
+    if let Err(e) = check_recorders(&mut children) {
+        notify::Notification::error("Voice Input", &format!("❌ Recording failed to start\n{}", e))
+            .timeout(5000)
+            .send();
+        return Err(e);
+    }
+    let child = &mut children[0].0;
+    let started = std::time::Instant::now();
+    let limit = max_duration.map(|max| format!(" (stops at {}:{:02})", max.as_secs() / 60, max.as_secs() % 60)).unwrap_or_default();
+    let detail = format!("To: {}{}\nDevice: {}", out, limit, input_device().unwrap_or_else(|| "default".to_string()));
+    eprintln!("DEBUG: Recording to {}, Ctrl-C to stop", out);
+    if !recording_grows(out) {
+        eprintln!("WARNING: {} isn't growing; pw-record may not be getting audio", out);
+    }
+    
+    let mut shown = None;
+    loop {
+        if RECORD_INTERRUPTED.load(Ordering::SeqCst) {
+            eprintln!("DEBUG: Interrupted, finishing {}", out);
+            break;
+        }
+        if max_duration.is_some_and(|max| started.elapsed() >= max) {
+            eprintln!("DEBUG: Reached --max-duration, finishing {}", out);
+            break;
+        }
+        if let Ok(Some(status)) = child.try_wait() {
+            eprintln!("WARNING: pw-record stopped on its own ({})", status);
+            break;
+        }
+        let elapsed = started.elapsed().as_secs();
+        if shown != Some(elapsed) {
+            shown = Some(elapsed);
+            let level = level_meter(out).unwrap_or_default();
+            notify::Notification::progress("Voice Input", &format!("⏺️ Recording… {}:{:02} {}\n{}", elapsed / 60, elapsed % 60, level, detail))
+                .timeout(2000)
+                .send();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    if let Ok(None) = child.try_wait() {
+        stop_recorder(child.id());
+    }
+    let _ = child.wait();
+    
+    // pw-record may not have got to write the sizes, e.g. when it was killed
+    let duration = crate::wav::finalize_file(out).unwrap_or_default();
+    if let Some(info) = inspect_recording(out) {
+        eprintln!("DEBUG: Recorded {} ({} bytes)", info.recorded, info.bytes);
+    }
+    normalize_recording(out);
+    if duration.is_zero() {
+        let _ = fs::remove_file(out);
+        notify::Notification::error("Voice Input", "❌ No audio was captured\nCheck the input device")
+            .timeout(5000)
+            .send();
+        return Err(anyhow::anyhow!("No audio was captured, nothing written to {}", out));
+    }
+    
+    let seconds = duration.as_secs();
+    notify::Notification::normal("Voice Input", &format!("💾 Recorded {}:{:02} to {}", seconds / 60, seconds % 60, out))
+        .timeout(3000)
+        .send();
+    println!("{}", out);
+    Ok(())
+}
+
+/// Microphone to record instead of the default source (WA_AUDIO_DEVICE, a
+/// source name from `whisp-away devices`)
+pub fn input_device() -> Option<String> {
+    std::env::var("WA_AUDIO_DEVICE").ok().filter(|device| !device.is_empty())
+}
+
+/// Index and name of every PipeWire/PulseAudio source, monitors included
+pub fn sources() -> Result<Vec<(String, String)>> {
+    let output = child_env::command("pactl", &[])
+        .args(["list", "short", "sources"])
+        .output()
+        .context("Failed to run pactl")?;
+    
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("pactl failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    
+    // Format: index<TAB>name<TAB>driver<TAB>sample spec<TAB>state
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect())
+}
+
+/// List PipeWire/PulseAudio sources, marking monitors of output devices
+pub fn list_devices() -> Result<()> {
+    let default_sink = default_sink_name().ok();
+    let default_monitor = default_sink.map(|sink| format!("{}.monitor", sink));
+    let configured = input_device();
+    
+    for (index, name) in sources()? {
+        let name = name.as_str();
+        let mut markers = Vec::new();
+        if name.ends_with(".monitor") {
+            markers.push("monitor");
+        }
+        if default_monitor.as_deref() == Some(name) {
+            markers.push("used by --source monitor");
+        }
+        if configured.as_deref() == Some(name) {
+            markers.push("WA_AUDIO_DEVICE");
+        }
+        
+        if markers.is_empty() {
+            println!("{:>4}  {}", index, name);
+        } else {
+            println!("{:>4}  {}  [{}]", index, name, markers.join(", "));
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn claimed_recordings_outlive_the_cleanup_while_their_stop_runs() {
+        let ours = format!("{}-{}-0-0000abcd.wav", CLAIMED_PREFIX, std::process::id());
+        assert!(!stale_recording(&ours));
+        // Past the largest pid_max Linux allows
+        assert!(stale_recording(&format!("{}-4194305-0-0000abcd.wav", CLAIMED_PREFIX)));
+        assert!(stale_recording("voice-recording-1700000000000.wav"));
+        assert!(stale_recording("voice-recording-12-3-0000abcd.log"));
+        assert!(!stale_recording("voice-audio-file.tmp"));
+    }
+    
+    #[test]
+    fn runtime_dir_needs_headroom_for_recordings() {
+        assert!(!runtime_has_headroom(Some(0)));
+        assert!(!runtime_has_headroom(Some(RUNTIME_HEADROOM_BYTES - 1)));
+        assert!(runtime_has_headroom(Some(RUNTIME_HEADROOM_BYTES)));
+        assert!(runtime_has_headroom(Some(u64::MAX)));
+        // Unknown free space keeps recordings in the runtime dir
+        assert!(runtime_has_headroom(None));
+        assert!(free_bytes(std::env::temp_dir().to_str().unwrap()).is_some());
+        assert_eq!(free_bytes("/nonexistent/whisp-away"), None);
+    }
+    
+    #[test]
+    fn only_a_clipped_recording_is_reported() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-clipping", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("voice-recording.wav").to_string_lossy().into_owned();
+        let square = |amplitude: f32| (0..16000).map(|i| if i % 40 < 20 { amplitude } else { -amplitude }).collect::<Vec<f32>>();
+        
+        fs::write(&recording, crate::wav::encode_s16_mono(&square(1.0), crate::wav::TARGET_RATE)).unwrap();
+        assert_eq!(check_clipping(&recording), Some(1.0));
+        fs::write(&recording, crate::wav::encode_s16_mono(&square(0.95), crate::wav::TARGET_RATE)).unwrap();
+        assert_eq!(check_clipping(&recording), None);
+        assert_eq!(check_clipping(&format!("{}.missing", recording)), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+    
+    #[test]
+    fn claim_renames_in_place() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-claim", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("voice-recording-1-2-3.wav");
+        fs::write(&recording, b"RIFF").unwrap();
+        let claimed = claim(recording.to_str().unwrap());
+        assert!(!recording.exists());
+        assert_eq!(std::path::Path::new(&claimed).parent(), Some(dir.as_path()));
+        assert!(!stale_recording(std::path::Path::new(&claimed).file_name().unwrap().to_str().unwrap()));
+        assert_eq!(fs::read(&claimed).unwrap(), b"RIFF");
+        let _ = fs::remove_dir_all(dir);
+    }
+    
+    /// An s16 mono WAV with a LIST chunk between fmt and data and `trailer`
+    /// chunks after the samples
+    fn wav_with_chunks(samples: &[f32], trailer: &[u8]) -> Vec<u8> {
+        let canonical = crate::wav::encode_s16_mono(samples, crate::wav::TARGET_RATE);
+        let mut bytes = canonical[..36].to_vec();
+        bytes.extend_from_slice(b"LIST\x0c\0\0\0INFOISFT\0\0\0\0");
+        bytes.extend_from_slice(&canonical[36..]);
+        bytes.extend_from_slice(trailer);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        bytes
+    }
+    
+    #[test]
+    fn mixing_keeps_chunks_around_the_samples() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-mix", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("mic.wav");
+        let other = dir.join("monitor.wav");
+        fs::write(&base, wav_with_chunks(&[0.25; 4], b"id3 \x02\0\0\0ab")).unwrap();
+        fs::write(&other, wav_with_chunks(&[0.25; 8], b"")).unwrap();
+        
+        mix_wav_files(base.to_str().unwrap(), &[other.to_str().unwrap()]).unwrap();
+        let bytes = fs::read(&base).unwrap();
+        let wav = crate::wav::parse(&bytes).unwrap();
+        assert_eq!(wav.frames(), 8);
+        assert!(wav.channels()[0][..4].iter().all(|sample| (sample - 0.5).abs() < 0.001));
+        assert!(wav.channels()[0][4..].iter().all(|sample| (sample - 0.25).abs() < 0.001));
+        assert!(bytes.ends_with(b"id3 \x02\0\0\0ab"));
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize, bytes.len() - 8);
+        let _ = fs::remove_dir_all(dir);
+    }
+    
+    #[test]
+    fn tail_skips_chunks_around_the_samples() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-tail", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("voice-recording.wav");
+        let mut samples = vec![0.0; 1600];
+        samples.extend([0.5; 1600]);
+        fs::write(&recording, wav_with_chunks(&samples, b"id3 \x04\0\0\0\x7f\x7f\x7f\x7f")).unwrap();
+        
+        let tail = recording_tail(recording.to_str().unwrap(), 100).unwrap();
+        assert_eq!(tail.len(), 1600);
+        assert!(tail.iter().all(|sample| (sample - 0.5).abs() < 0.001));
+        assert!(recording_tail(recording.to_str().unwrap(), 300).is_none());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Section whose rules apply to every language
+const GLOBAL: &str = "*";
+/// Punctuation whisper puts around a phrase, ignored when matching it
+const EDGE_PUNCTUATION: &[char] = &[',', '.', ';', ':', '!', '?', '"', '\'', '«', '»', '„', '“', '”'];
+
+/// What a spoken command phrase does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Newline,
+    Paragraph,
+    Tab,
+}
+
+impl Command {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "newline" => Some(Command::Newline),
+            "paragraph" => Some(Command::Paragraph),
+            "tab" => Some(Command::Tab),
+            _ => None,
+        }
+    }
+    
+    fn text(self) -> &'static str {
+        match self {
+            Command::Newline => "\n",
+            Command::Paragraph => "\n\n",
+            Command::Tab => "\t",
+        }
+    }
+}
+
+/// Replacements and command phrases for one language, keyed by the phrase
+/// in lowercase
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rules {
+    pub replacements: HashMap<String, String>,
+    pub commands: HashMap<String, Command>,
+}
+
+impl Rules {
+    /// Add `other`'s rules, replacing ours for the same phrase
+    fn merge(&mut self, other: &Rules) {
+        self.replacements.extend(other.replacements.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.commands.extend(other.commands.iter().map(|(k, v)| (k.clone(), *v)));
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.replacements.is_empty() && self.commands.is_empty()
+    }
+}
+
+/// replacements.toml: rules by section, `*` for every language
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Replacements {
+    sections: HashMap<String, Rules>,
+}
+
+/// `~/.config/whisp-away/replacements.toml`
+pub fn path() -> String {
+    format!("{}/whisp-away/replacements.toml", crate::helpers::config_dir())
+}
+
+/// A TOML string or bare key, with the basic string escapes
+pub fn unquote(number: usize, value: &str) -> Result<String> {
+    let value = value.trim();
+    let Some(inner) = value.strip_prefix('"') else {
+        return Ok(value.to_string());
+    };
+    let inner = inner
+        .strip_suffix('"')
+        .ok_or_else(|| anyhow!("line {}: unterminated string {}", number + 1, value))?;
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unquoted.push('\n'),
+            Some('t') => unquoted.push('\t'),
+            Some('"') => unquoted.push('"'),
+            Some('\\') => unquoted.push('\\'),
+            other => return Err(anyhow!("line {}: unknown escape \\{}", number + 1, other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(unquoted)
+}
+
+/// Split `"a phrase" = "value"` at the `=` outside the quotes
+pub fn split_entry(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '=' if !quoted => return Some((&line[..i], &line[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse replacements.toml. `[en]`, `[de]` or `[*]` (also `["*"]`) start a
+/// section of `"phrase" = "replacement"` lines; `[commands.de]` and the like
+/// one of `"phrase" = "newline"` (or `paragraph`, `tab`) lines. Lines before
+/// the first section are for every language. `#` starts a comment.
+pub fn parse(content: &str) -> Result<Replacements> {
+    let mut replacements = Replacements::default();
+    let mut section = (GLOBAL.to_string(), false);
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            let (name, commands) = match header.trim().strip_prefix("commands") {
+                Some("") => (GLOBAL, true),
+                Some(rest) => match rest.strip_prefix('.') {
+                    Some(language) => (language, true),
+                    None => (header.trim(), false),
+                },
+                None => (header.trim(), false),
+            };
+            let name = unquote(number, name)?.to_lowercase();
+            if name.is_empty() {
+                return Err(anyhow!("line {}: empty section name", number + 1));
+            }
+            section = (name, commands);
+            continue;
+        }
+        
+        let (phrase, value) = split_entry(line)
+            .ok_or_else(|| anyhow!("line {}: expected \"phrase\" = \"replacement\", got {:?}", number + 1, line))?;
+        // Matched the way transcript words are: bare and single-spaced
+        let phrase = unquote(number, phrase)?.split_whitespace().map(bare).collect::<Vec<_>>().join(" ");
+        let value = unquote(number, value)?;
+        if phrase.is_empty() {
+            return Err(anyhow!("line {}: empty phrase", number + 1));
+        }
+        let rules = replacements.sections.entry(section.0.clone()).or_default();
+        if section.1 {
+            let command = Command::parse(&value)
+                .ok_or_else(|| anyhow!("line {}: unknown command {:?}, expected newline, paragraph or tab", number + 1, value))?;
+            rules.commands.insert(phrase, command);
+        } else {
+            rules.replacements.insert(phrase, value);
+        }
+    }
+    Ok(replacements)
+}
+
+impl Replacements {
+    /// The rules for `language` (e.g. "de-AT"): the global section, then the
+    /// language family's (`de`), then the exact language's (`de-at`), each
+    /// replacing the one before for the same phrase. With no language, or
+    /// "auto" that wasn't resolved, only the global rules apply.
+    pub fn select(&self, language: Option<&str>) -> Rules {
+        let mut rules = self.sections.get(GLOBAL).cloned().unwrap_or_default();
+        let Some(language) = language.map(str::to_lowercase).filter(|language| !language.is_empty() && language != "auto") else {
+            return rules;
+        };
+        let family = language.split(['-', '_']).next().unwrap_or(&language).to_string();
+        if family != language {
+            if let Some(family_rules) = self.sections.get(&family) {
+                rules.merge(family_rules);
+            }
+        }
+        if let Some(exact) = self.sections.get(&language).or_else(|| self.sections.get(&language.replace('_', "-"))) {
+            rules.merge(exact);
+        }
+        rules
+    }
+}
+
+/// A word as it is matched against phrases: lowercase, without the
+/// punctuation whisper put around it
+fn bare(word: &str) -> String {
+    word.trim_matches(EDGE_PUNCTUATION).to_lowercase()
+}
+
+/// What a matched phrase becomes in the output
+enum Piece {
+    Word(String),
+    /// Typed with no space on either side
+    Command(&'static str),
+}
+
+/// Apply `rules` to `text`, longest phrase first. Replacements keep the
+/// punctuation after the last word they replace; commands swallow it and
+/// the spaces around them.
+pub fn apply_rules(text: &str, rules: &Rules) -> String {
+    if rules.is_empty() {
+        return text.to_string();
+    }
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let bare_words: Vec<String> = words.iter().map(|word| bare(word)).collect();
+    let longest = rules.replacements.keys().chain(rules.commands.keys())
+        .map(|phrase| phrase.split_whitespace().count())
+        .max()
+        .unwrap_or(0);
+    
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    'words: while i < words.len() {
+        for len in (1..=longest.min(words.len() - i)).rev() {
+            let candidate = bare_words[i..i + len].join(" ");
+            if let Some(command) = rules.commands.get(&candidate) {
+                pieces.push(Piece::Command(command.text()));
+                i += len;
+                continue 'words;
+            }
+            if let Some(replacement) = rules.replacements.get(&candidate) {
+                let last = words[i + len - 1];
+                let trailing = &last[last.trim_end_matches(EDGE_PUNCTUATION).len()..];
+                pieces.push(Piece::Word(format!("{}{}", replacement, trailing)));
+                i += len;
+                continue 'words;
+            }
+        }
+        pieces.push(Piece::Word(words[i].to_string()));
+        i += 1;
+    }
+    
+    let mut output = String::with_capacity(text.len());
+    let mut glued = true;
+    for piece in pieces {
+        match piece {
+            Piece::Word(word) => {
+                if !glued {
+                    output.push(' ');
+                }
+                output.push_str(&word);
+                glued = false;
+            }
+            Piece::Command(text) => {
+                output.push_str(text);
+                glued = true;
+            }
+        }
+    }
+    output
+}
+
+/// Load replacements.toml, if there is one. A broken file is reported and
+/// changes nothing, rather than costing the dictation.
+fn load() -> Option<Replacements> {
+    let content = std::fs::read_to_string(path()).ok()?;
+    match parse(&content) {
+        Ok(replacements) => Some(replacements),
+        Err(e) => {
+            eprintln!("WARNING: Ignoring {}: {:#}", path(), e);
+            None
+        }
+    }
+}
+
+/// The transcript with replacements.toml's rules for `language` applied:
+/// the requested one, or the detected one for "auto" (see Run::output_language)
+pub fn apply(text: &str, language: Option<&str>) -> String {
+    let Some(replacements) = load() else {
+        return text.to_string();
+    };
+    let rules = replacements.select(language);
+    eprintln!(
+        "DEBUG: {} replacement(s) and {} command phrase(s) for language {}",
+        rules.replacements.len(),
+        rules.commands.len(),
+        language.unwrap_or("unknown")
+    );
+    apply_rules(text, &rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const FILE: &str = r#"
+# Before any section: for every language
+"gonna" = "going to"
+
+[*]
+"whisp away" = "whisp-away"
+"okay" = "OK"
+
+[en]
+"okay" = "okay"
+
+[de]
+"okay" = "in Ordnung"
+"straße" = "Strasse"
+
+[de-at]
+"in ordnung" = "passt"
+"okay" = "passt scho"
+
+[commands]
+"new line" = "newline"
+
+[commands.de]
+"neue zeile" = "newline"
+"neuer absatz" = "paragraph"
+"new line" = "tab"
+
+["commands.fr"]
+"#;
+
+    fn replacement(rules: &Rules, phrase: &str) -> Option<String> {
+        rules.replacements.get(phrase).cloned()
+    }
+    
+    #[test]
+    fn sections_are_read_by_language() {
+        let parsed = parse(FILE).unwrap();
+        let mut names: Vec<&str> = parsed.sections.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, vec!["*", "de", "de-at", "en"]);
+        assert_eq!(replacement(&parsed.sections["*"], "gonna").as_deref(), Some("going to"));
+        assert_eq!(parsed.sections["*"].commands["new line"], Command::Newline);
+        assert_eq!(parsed.sections["de"].commands["neuer absatz"], Command::Paragraph);
+    }
+    
+    #[test]
+    fn the_global_section_applies_to_every_language() {
+        let parsed = parse(FILE).unwrap();
+        for language in [None, Some("auto"), Some(""), Some("fr")] {
+            let rules = parsed.select(language);
+            assert_eq!(rules, parsed.sections["*"], "{:?}", language);
+        }
+    }
+    
+    #[test]
+    fn the_exact_language_beats_the_global_section() {
+        let rules = parse(FILE).unwrap().select(Some("en"));
+        assert_eq!(replacement(&rules, "okay").as_deref(), Some("okay"));
+        assert_eq!(replacement(&rules, "whisp away").as_deref(), Some("whisp-away"));
+        assert_eq!(rules.commands["new line"], Command::Newline);
+    }
+    
+    #[test]
+    fn global_then_family_then_exact_language_merge_in_that_order() {
+        let parsed = parse(FILE).unwrap();
+        
+        let de = parsed.select(Some("de"));
+        assert_eq!(replacement(&de, "okay").as_deref(), Some("in Ordnung"));
+        assert_eq!(replacement(&de, "gonna").as_deref(), Some("going to"));
+        assert_eq!(replacement(&de, "in ordnung"), None);
+        assert_eq!(de.commands["new line"], Command::Tab);
+        
+        for language in ["de-AT", "DE_at"] {
+            let at = parsed.select(Some(language));
+            assert_eq!(replacement(&at, "okay").as_deref(), Some("passt scho"), "{}", language);
+            assert_eq!(replacement(&at, "in ordnung").as_deref(), Some("passt"));
+            assert_eq!(replacement(&at, "straße").as_deref(), Some("Strasse"));
+            assert_eq!(replacement(&at, "whisp away").as_deref(), Some("whisp-away"));
+            assert_eq!(at.commands["neue zeile"], Command::Newline);
+            assert_eq!(at.commands["new line"], Command::Tab);
+        }
+        
+        // A family section alone still applies to its variants
+        let ch = parsed.select(Some("de-CH"));
+        assert_eq!(replacement(&ch, "okay").as_deref(), Some("in Ordnung"));
+    }
+    
+    #[test]
+    fn phrases_are_stored_bare_and_values_unescaped() {
+        let parsed = parse("[en]\n\"  Whisp,  Away! \" = \"a\\tb\\\"c\\\\\"\nbare = word\n").unwrap();
+        assert_eq!(replacement(&parsed.sections["en"], "whisp away").as_deref(), Some("a\tb\"c\\"));
+        assert_eq!(replacement(&parsed.sections["en"], "bare").as_deref(), Some("word"));
+        assert!(parse("\"a = b\" = \"c\"").unwrap().sections["*"].replacements.contains_key("a = b"));
+    }
+    
+    #[test]
+    fn mistakes_name_their_line() {
+        for (content, error) in [
+            ("[en]\n\"x\" \"y\"", "line 2: expected"),
+            ("\n\n\"x\" = \"y", "line 3: unterminated string"),
+            ("\"x\" = \"\\q\"", "line 1: unknown escape \\q"),
+            ("[commands.de]\n\"x\" = \"jump\"", "line 2: unknown command"),
+            ("[]", "line 1: empty section name"),
+            ("\"...\" = \"y\"", "line 1: empty phrase"),
+        ] {
+            let message = parse(content).unwrap_err().to_string();
+            assert!(message.starts_with(error), "{:?} gave {:?}", content, message);
+        }
+    }
+    
+    #[test]
+    fn longer_phrases_win_and_punctuation_is_kept() {
+        let rules = parse(FILE).unwrap().select(Some("de-at"));
+        assert_eq!(apply_rules("Okay, in Ordnung. Okay!", &rules), "passt scho, passt. passt scho!");
+    }
+    
+    #[test]
+    fn commands_swallow_the_spaces_and_punctuation_around_them() {
+        let rules = parse(FILE).unwrap().select(Some("de"));
+        assert_eq!(
+            apply_rules("Erste Zeile. Neue Zeile. Zweite Zeile, neuer Absatz dritte", &rules),
+            "Erste Zeile.\nZweite Zeile,\n\ndritte"
+        );
+        assert_eq!(apply_rules("nichts zu tun", &Rules::default()), "nichts zu tun");
+    }
+}
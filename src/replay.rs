@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use crate::history::{self, JournalEntry};
+
+/// What `replay` re-transcribes and with which models
+pub struct ReplayOptions {
+    /// Oldest journal entry to replay, "YYYY-MM-DD" or a longer prefix of the time
+    pub since: String,
+    pub backend: String,
+    pub model: String,
+    /// Second model to run side by side with the first
+    pub compare: Option<String>,
+    pub language: String,
+}
+
+/// Lowercase words with punctuation stripped: the unit errors are counted in
+pub(crate) fn tokens(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric() || *c == '\'').collect::<String>().to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// One step of the alignment between what was typed and a new transcript
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Edit {
+    Same(String),
+    Substituted(String, String),
+    Deleted(String),
+    Inserted(String),
+}
+
+/// Minimal word-level edit script turning `reference` into `hypothesis`
+pub(crate) fn align(reference: &[String], hypothesis: &[String]) -> Vec<Edit> {
+    let (n, m) = (reference.len(), hypothesis.len());
+    // cost[i][j]: edits between reference[..i] and hypothesis[..j]
+    let mut cost = vec![vec![0usize; m + 1]; n + 1];
+    for (i, row) in cost.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in cost[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let substitution = cost[i - 1][j - 1] + usize::from(reference[i - 1] != hypothesis[j - 1]);
+            cost[i][j] = substitution.min(cost[i - 1][j] + 1).min(cost[i][j - 1] + 1);
+        }
+    }
+    
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && cost[i][j] == cost[i - 1][j - 1] + usize::from(reference[i - 1] != hypothesis[j - 1]) {
+            edits.push(if reference[i - 1] == hypothesis[j - 1] {
+                Edit::Same(reference[i - 1].clone())
+            } else {
+                Edit::Substituted(reference[i - 1].clone(), hypothesis[j - 1].clone())
+            });
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && cost[i][j] == cost[i - 1][j] + 1 {
+            edits.push(Edit::Deleted(reference[i - 1].clone()));
+            i -= 1;
+        } else {
+            edits.push(Edit::Inserted(hypothesis[j - 1].clone()));
+            j -= 1;
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+pub(crate) fn error_count(edits: &[Edit]) -> usize {
+    edits.iter().filter(|edit| !matches!(edit, Edit::Same(_))).count()
+}
+
+/// Inline diff: `[typed→new]` for substitutions, `[-typed-]` and `{+new+}` for
+/// words only on one side (typed being whatever the reference is)
+pub(crate) fn render_diff(edits: &[Edit]) -> String {
+    edits
+        .iter()
+        .map(|edit| match edit {
+            Edit::Same(word) => word.clone(),
+            Edit::Substituted(old, new) => format!("[{}→{}]", old, new),
+            Edit::Deleted(word) => format!("[-{}-]", word),
+            Edit::Inserted(word) => format!("{{+{}+}}", word),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Running totals for one model
+#[derive(Default)]
+struct Totals {
+    errors: usize,
+    words: usize,
+    failed: usize,
+}
+
+impl Totals {
+    fn summary(&self, model: &str) -> String {
+        let rate = if self.words == 0 { 0.0 } else { 100.0 * self.errors as f64 / self.words as f64 };
+        let failed = if self.failed > 0 { format!(", {} failed", self.failed) } else { String::new() };
+        format!("{}: WER {:.1}% ({} errors / {} words{})", model, rate, self.errors, self.words, failed)
+    }
+}
+
+/// Re-transcribe one kept recording with `model` and print how it compares to what was typed
+fn replay_one(entry: &JournalEntry, reference: &[String], model: &str, options: &ReplayOptions, totals: &mut Totals) {
+    // Same prompt as the original dictation, so only the model differs
+    let prompt = entry.prompt.as_deref();
+    let audio = match crate::crypt::PlainFile::open(&entry.audio) {
+        Ok(audio) => audio,
+        Err(e) => {
+            totals.failed += 1;
+            println!("  {}: failed: {:#}", model, e);
+            return;
+        }
+    };
+    match crate::transcribe::transcribe_direct(&options.backend, &audio.path, Some(model.to_string()), &options.language, prompt) {
+        Ok(text) => {
+            let edits = align(reference, &tokens(&text));
+            let errors = error_count(&edits);
+            totals.errors += errors;
+            totals.words += reference.len();
+            println!("  {}: {} error(s)  {}", model, errors, render_diff(&edits));
+        }
+        Err(e) => {
+            totals.failed += 1;
+            println!("  {}: failed: {}", model, e);
+        }
+    }
+}
+
+/// Re-transcribe kept recordings and compare each against the text that was
+/// originally typed from it
+pub fn run(options: ReplayOptions) -> Result<()> {
+    let entries = history::read_since(&options.since)?;
+    if entries.is_empty() {
+        return Err(anyhow!(
+            "No journal entries since {} in {} (recordings are only kept with WA_KEEP_AUDIO=1)",
+            options.since,
+            history::journal_path()
+        ));
+    }
+    
+    let mut totals = Totals::default();
+    let mut compare_totals = Totals::default();
+    let mut skipped = 0;
+    let mut replayed = 0;
+    
+    for entry in &entries {
+        // Compressed since, or left as WAV when compressing it failed
+        let Some(audio) = history::locate(&entry.audio) else {
+            skipped += 1;
+            continue;
+        };
+        let entry = &JournalEntry { audio, ..entry.clone() };
+        replayed += 1;
+        
+        let reference = tokens(&entry.text);
+        println!("{}  {} (typed by {}, {})", entry.time, entry.audio, entry.source, entry.model);
+        println!("  typed: {}", reference.join(" "));
+        replay_one(entry, &reference, &options.model, &options, &mut totals);
+        if let Some(compare) = &options.compare {
+            replay_one(entry, &reference, compare, &options, &mut compare_totals);
+        }
+    }
+    
+    println!();
+    println!("{} recording(s) replayed, {} skipped (audio not kept)", replayed, skipped);
+    println!("{}", totals.summary(&options.model));
+    if let Some(compare) = &options.compare {
+        println!("{}", compare_totals.summary(compare));
+    }
+    Ok(())
+}
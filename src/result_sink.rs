@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::time::Instant;
+use crate::run::Run;
+
+/// Where `stop` writes its result as one JSON document, for wrapper scripts
+/// that can't pick it out of output shared with logs and notifications
+#[derive(Debug)]
+pub enum ResultSink {
+    Stdout,
+    /// Replaced whole, like the last result file
+    File(String),
+    /// Inherited from the caller (`--result-fd`); written once, then closed
+    Fd(RawFd),
+}
+
+impl ResultSink {
+    /// The sink for `stop --result-fd N`. Standard output is fine, if shared
+    /// with whatever else prints there; stdin and stderr (the logs) aren't.
+    pub fn fd(fd: RawFd) -> Result<Self> {
+        match fd {
+            1 => return Ok(ResultSink::Stdout),
+            0 | 2 => return Err(anyhow!("--result-fd {} is stdin or stderr; use 1 or an fd of 3 or more", fd)),
+            _ => {}
+        }
+        if fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(anyhow!("--result-fd {} isn't an open file descriptor", fd));
+        }
+        // Kept from the programs stop runs, so the reader sees the end of
+        // the result when stop exits rather than when the last of them does
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        Ok(ResultSink::Fd(fd))
+    }
+    
+    fn write(self, document: &[u8]) -> Result<()> {
+        match self {
+            ResultSink::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(document)?;
+                stdout.flush()?;
+            }
+            ResultSink::File(path) => {
+                let tmp = format!("{}.{}.tmp", path, std::process::id());
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(&tmp)
+                    .and_then(|mut file| file.write_all(document))
+                    .with_context(|| format!("Failed to write {}", tmp))?;
+                fs::rename(&tmp, &path).with_context(|| format!("Failed to move {} into place", path))?;
+            }
+            ResultSink::Fd(fd) => {
+                // Owned from here on, so it is closed once written
+                let mut file = unsafe { fs::File::from_raw_fd(fd) };
+                file.write_all(document).with_context(|| format!("Failed to write to fd {}", fd))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What `stop` came to, as written to the sink
+#[derive(Debug, Default, Serialize)]
+struct Outcome {
+    success: bool,
+    /// The transcript, also when typing it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// What produced the text, as shown in the notification
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    /// Code of what went wrong, as in the `error` event ("no_text" when
+    /// nothing was heard)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// What stop said went wrong, when it exited with an error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio_seconds: Option<f64>,
+    /// From sending the recording off until the text was in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transcription_ms: Option<u64>,
+    /// From the start of stop until the result was written
+    total_ms: u64,
+}
+
+/// The sink and what is known so far, written once when `stop` finishes
+pub struct Pending {
+    sink: ResultSink,
+    began: Instant,
+    outcome: Outcome,
+}
+
+impl Pending {
+    /// Write to `sink` when `stop` finishes; the reported total starts now
+    pub fn new(sink: ResultSink) -> Self {
+        Pending { sink, began: Instant::now(), outcome: Outcome::default() }
+    }
+
+    /// Take what `run` came to into the result
+    pub fn note(&mut self, run: &Run) {
+        let outcome = &mut self.outcome;
+        outcome.text = run.text.clone();
+        outcome.backend = run.backend.clone();
+        outcome.model = run.model.clone();
+        outcome.error = run.error.clone();
+        // Once transcribed, like the other details of the transcript
+        outcome.audio_seconds = run.backend.as_ref().and(run.duration).map(|duration| duration.as_secs_f64());
+        outcome.transcription_ms = run.transcription_ms;
+    }
+
+    /// Write the result of a stop that returned `result`
+    pub fn finish(self, result: &Result<()>) {
+        let Pending { sink, began, mut outcome } = self;
+        if let Err(e) = result {
+            outcome.message = Some(format!("{:#}", e));
+            outcome.error.get_or_insert_with(|| "failed".to_string());
+        }
+        if outcome.text.is_none() {
+            // Dropped as too short or silent, or nothing was heard
+            outcome.error.get_or_insert_with(|| "no_text".to_string());
+        }
+        outcome.success = outcome.error.is_none();
+        outcome.total_ms = began.elapsed().as_millis() as u64;
+        
+        let document = serde_json::to_vec(&outcome).map(|mut document| {
+            document.push(b'\n');
+            document
+        });
+        if let Err(e) = document.map_err(anyhow::Error::from).and_then(|document| sink.write(&document)) {
+            eprintln!("WARNING: Could not write the result: {:#}", e);
+        }
+    }
+}
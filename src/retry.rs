@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// A beam search pass with temperature fallback takes about this many greedy
+/// passes, which is how long a retry is expected to take
+const RETRY_COST_FACTOR: u32 = 3;
+
+/// Whether a transcript that came back empty is transcribed once more with
+/// slower, more thorough decoding (WA_RETRY_ON_EMPTY=1)
+pub fn enabled() -> bool {
+    matches!(std::env::var("WA_RETRY_ON_EMPTY").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// WA_RETRY_MAX_MS: the most a retry may add to a dictation (default 5000)
+fn max_extra() -> Duration {
+    let ms = std::env::var("WA_RETRY_MAX_MS").ok().and_then(|ms| ms.parse().ok()).unwrap_or(5000);
+    Duration::from_millis(ms)
+}
+
+/// Whether to retry a first pass that produced `text` in `first_pass`. A retry
+/// that would likely take longer than WA_RETRY_MAX_MS is skipped, since it
+/// can't be stopped halfway.
+pub fn worth_retrying(text: &str, first_pass: Duration) -> bool {
+    if !enabled() || !text.trim().is_empty() {
+        return false;
+    }
+    let estimate = first_pass * RETRY_COST_FACTOR;
+    if estimate > max_extra() {
+        eprintln!("DEBUG: Not retrying the empty transcript: a retry would take about {:?}, over WA_RETRY_MAX_MS", estimate);
+        return false;
+    }
+    true
+}
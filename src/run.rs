@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+use crate::events::{self, Event};
+use crate::focus::TargetWindow;
+use crate::history::AudioFormat;
+use crate::recording::{Preprocessing, RecordingInfo};
+
+/// What one dictation came to, from stopping the recording until the text is
+/// out. The pipeline fills it in as it goes; notifications, hooks, the
+/// journal, the last result and `stop --result-fd` read it.
+#[derive(Debug, Default)]
+pub struct Run {
+    /// Language transcription was asked for, "auto" to detect it
+    pub language: String,
+    /// Language whisper reported, when it did
+    pub detected_language: Option<String>,
+    /// Model transcribing, once transcription started
+    pub model: Option<String>,
+    /// When transcription started
+    pub transcribing_since: Option<Instant>,
+    /// Initial prompt whisper was given, glossary included
+    pub prompt: Option<String>,
+    /// Kept copy of the recording, with the format it is archived as
+    pub kept: Option<(String, AudioFormat)>,
+    /// Share of the recording that clipped, when over wav::CLIP_WARN_FRACTION
+    pub clipped: Option<f32>,
+    /// The recording as inspected when it was stopped
+    pub recording: Option<RecordingInfo>,
+    /// How the recording was preprocessed, if it was
+    pub preprocessing: Option<Preprocessing>,
+    /// Length of the recording, once measured
+    pub duration: Option<Duration>,
+    /// ", step 2/3: remote tcp://desktop:7777" when the fallback chain step
+    /// transcribing is worth naming
+    pub step_note: String,
+    /// "\nBackend from ..., model ... from ..." with --verbose-notify
+    pub resolution_note: String,
+    /// Window the text went to, if it was found in time
+    pub target: Option<TargetWindow>,
+    /// The transcript, also when typing it failed
+    pub text: Option<String>,
+    /// What produced the text, as shown in the notification
+    pub backend: Option<String>,
+    /// From sending the recording off until the text was in
+    pub transcription_ms: Option<u64>,
+    /// Code of what went wrong, as in the `error` event
+    pub error: Option<String>,
+}
+
+impl Run {
+    /// A dictation to be transcribed in `language`
+    pub fn new(language: &str) -> Self {
+        Run { language: language.to_string(), ..Default::default() }
+    }
+    
+    /// Note that transcription with `model` starts now
+    pub fn started(&mut self, model: &str) {
+        self.model = Some(model.to_string());
+        self.transcribing_since = Some(Instant::now());
+    }
+    
+    /// Note the transcript, about to be typed. It supersedes the failures of
+    /// fallback steps before the one that produced it.
+    pub fn transcribed(&mut self, text: &str, backend: &str) {
+        self.error = None;
+        self.text = Some(text.trim().to_string()).filter(|text| !text.is_empty());
+        self.backend = Some(backend.to_string());
+        self.transcription_ms = self.transcribing_since.map(|at| at.elapsed().as_millis() as u64);
+    }
+    
+    /// Report an `error` event with `code` and note it as the run's error
+    pub fn fail(&mut self, code: &str) {
+        events::emit(Event::Error { code: code.to_string() });
+        self.error = Some(code.to_string());
+    }
+    
+    /// Language of the transcript: the requested one, or the detected one
+    /// when "auto" was requested (None if none was reported)
+    pub fn output_language(&self) -> Option<String> {
+        if self.language == "auto" {
+            self.detected_language.clone()
+        } else {
+            Some(self.language.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn auto_reports_the_detected_language() {
+        let mut run = Run::new("auto");
+        assert_eq!(run.output_language(), None);
+        run.detected_language = Some("de".to_string());
+        assert_eq!(run.output_language().as_deref(), Some("de"));
+        
+        let mut run = Run::new("en");
+        run.detected_language = Some("de".to_string());
+        assert_eq!(run.output_language().as_deref(), Some("en"));
+    }
+    
+    #[test]
+    fn a_transcript_supersedes_earlier_failures() {
+        let mut run = Run::new("en");
+        run.error = Some("daemon_busy".to_string());
+        run.transcribed("  hello  ", "mock daemon");
+        assert_eq!(run.error, None);
+        assert_eq!(run.text.as_deref(), Some("hello"));
+        
+        run.transcribed("   ", "mock daemon");
+        assert_eq!(run.text, None);
+    }
+}
@@ -0,0 +1,548 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::helpers::OutputMode;
+use crate::recording;
+
+/// User service running the daemon, with @EXEC@ for the binary
+const SERVICE_TEMPLATE: &str = include_str!("../assets/whisp-away-daemon.service");
+/// Autostart entry for the tray
+const AUTOSTART_TEMPLATE: &str = include_str!("../whisp-away.desktop");
+
+/// Settings `setup` writes; the rest of the file is left alone
+const CONFIG_KEYS: &[&str] = &["WA_WHISPER_BACKEND", "WA_WHISPER_MODEL", "WA_AUDIO_DEVICE", "WA_OUTPUT_MODE", "WA_ENCRYPT"];
+
+/// How long the test dictation records
+const TEST_RECORDING: Duration = Duration::from_secs(4);
+
+/// A part of the setup, for --skip and --only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Step {
+    /// Detect the compositor and whether wtype can type into it
+    Detect,
+    /// Pick the microphone to record
+    Device,
+    /// Pick whisper-cpp or faster-whisper
+    Backend,
+    /// Download the model
+    Model,
+    /// Encrypt kept recordings and the journal, generating the key
+    Encrypt,
+    /// Print the settings, and write them to the settings file when allowed
+    Config,
+    /// Install a user systemd service running the daemon
+    Service,
+    /// Start the tray on login
+    Autostart,
+    /// Check the setup and record a test dictation
+    Check,
+}
+
+pub struct SetupOptions {
+    /// Take the recommended answer to every question
+    pub non_interactive: bool,
+    pub skip: Vec<Step>,
+    /// Run only these steps
+    pub only: Vec<Step>,
+    pub backend: Option<String>,
+    pub model: Option<String>,
+    pub device: Option<String>,
+    /// Write the settings file without asking; otherwise --non-interactive
+    /// only prints what would go in it
+    pub write_config: bool,
+}
+
+impl SetupOptions {
+    fn runs(&self, step: Step) -> bool {
+        !self.skip.contains(&step) && (self.only.is_empty() || self.only.contains(&step))
+    }
+}
+
+/// Asks the questions, or takes the defaults with --non-interactive
+struct Prompt {
+    interactive: bool,
+}
+
+impl Prompt {
+    fn read_answer(&self, question: &str) -> Result<String> {
+        print!("{} ", question);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        Ok(answer.trim().to_string())
+    }
+    
+    fn confirm(&self, question: &str, default: bool) -> Result<bool> {
+        if !self.interactive {
+            println!("{} {}", question, if default { "yes" } else { "no" });
+            return Ok(default);
+        }
+        let answer = self.read_answer(&format!("{} [{}]", question, if default { "Y/n" } else { "y/N" }))?;
+        Ok(match answer.as_str() {
+            "" => default,
+            answer => matches!(answer, "y" | "Y" | "yes"),
+        })
+    }
+    
+    /// Pick one of `options` by number; returns its index
+    fn choose(&self, question: &str, options: &[String], default: usize) -> Result<usize> {
+        println!("{}", question);
+        for (i, option) in options.iter().enumerate() {
+            println!("  {}) {}{}", i + 1, option, if i == default { " (recommended)" } else { "" });
+        }
+        if !self.interactive {
+            println!("Using {}", options[default]);
+            return Ok(default);
+        }
+        loop {
+            let answer = self.read_answer(&format!("Choice [{}]:", default + 1))?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match answer.parse::<usize>() {
+                Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+                _ => println!("Enter a number from 1 to {}", options.len()),
+            }
+        }
+    }
+}
+
+/// What `setup` has settled on so far. Starts from the settings file, so a
+/// re-run (or a skipped step) keeps what was configured before.
+struct Choices {
+    backend: String,
+    model: String,
+    device: Option<String>,
+    output_mode: Option<OutputMode>,
+    encrypt: bool,
+}
+
+/// The environment.d file `setup` writes. whisp-away has no config file of its
+/// own; systemd hands these variables to the session and user services.
+pub fn config_path() -> String {
+    format!("{}/environment.d/60-whisp-away.conf", crate::helpers::config_dir())
+}
+
+/// KEY=VALUE lines of an environment.d file, comments and blanks skipped
+fn read_config(path: &str) -> Vec<(String, String)> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// KEY=VALUE lines for the settings that are set
+fn config_snippet(values: &[(&str, Option<String>)]) -> String {
+    values
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}={}\n", key, value)))
+        .collect()
+}
+
+/// Replace our keys in the file, keeping every other line as it was
+fn write_config(path: &str, values: &[(&str, Option<String>)]) -> Result<()> {
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter(|line| {
+            let key = line.split_once('=').map(|(key, _)| key.trim());
+            !key.is_some_and(|key| CONFIG_KEYS.contains(&key))
+        })
+        .map(str::to_string)
+        .collect();
+    if lines.is_empty() {
+        lines.push("# Written by `whisp-away setup`; re-run it or edit by hand".to_string());
+    }
+    for (key, value) in values {
+        if let Some(value) = value {
+            lines.push(format!("{}={}", key, value));
+        }
+    }
+    
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, lines.join("\n") + "\n").with_context(|| format!("Failed to write {}", path))
+}
+
+/// Show the settings, then write them to `path` with --write-config or when
+/// the user agrees. The file changes the environment of the whole session,
+/// so it is never written unasked.
+fn save_config(prompt: &Prompt, path: &str, values: &[(&str, Option<String>)], write: bool) -> Result<()> {
+    println!("Settings for {}:\n{}", path, config_snippet(values));
+    let write = write || (prompt.interactive && prompt.confirm(&format!("Write them to {}?", path), true)?);
+    if !write {
+        println!("Not written; add them yourself, or re-run with --write-config");
+        return Ok(());
+    }
+    write_config(path, values)?;
+    println!("Wrote {} (log out and back in for the session to pick it up)", path);
+    Ok(())
+}
+
+/// Whether `name` is an executable on PATH
+fn on_path(name: &str) -> bool {
+    crate::helpers::find_program(name).is_some()
+}
+
+/// Compositor or desktop we're running under, as far as the environment tells
+fn compositor() -> String {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return "Hyprland".to_string();
+    }
+    if std::env::var_os("SWAYSOCK").is_some() {
+        return "sway".to_string();
+    }
+    match std::env::var("XDG_CURRENT_DESKTOP") {
+        Ok(desktop) if !desktop.is_empty() => desktop,
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Report the session and whether wtype can type into it. GNOME lacks the
+/// virtual keyboard protocol wtype needs, so transcripts go to the clipboard there.
+fn detect(choices: &mut Choices) {
+    let compositor = compositor();
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    println!("Compositor: {} ({})", compositor, if wayland { "Wayland" } else { "no Wayland display" });
+    // The same lookup `stop` does before transcribing
+    let tools = crate::typing::Tools::resolve("wtype");
+    println!("wtype: {}", tools.describe_wtype());
+    println!("wl-copy: {}", tools.describe_wl_copy());
+    
+    let can_type = wayland && tools.wtype.is_some() && !compositor.to_lowercase().contains("gnome");
+    if can_type {
+        println!("Transcripts will be typed with wtype");
+        choices.output_mode = None;
+    } else {
+        println!("wtype can't type here; transcripts will be copied to the clipboard instead");
+        choices.output_mode = Some(OutputMode::Clipboard);
+    }
+}
+
+/// Let the user pick a microphone; the default source follows whatever the
+/// desktop's sound settings pick
+fn pick_device(prompt: &Prompt, choices: &mut Choices, requested: Option<String>) -> Result<()> {
+    if let Some(device) = requested {
+        choices.device = Some(device).filter(|device| device != "default");
+        return Ok(());
+    }
+    let inputs: Vec<String> = recording::sources()?
+        .into_iter()
+        .map(|(_, name)| name)
+        .filter(|name| !name.ends_with(".monitor"))
+        .collect();
+    
+    let mut options = vec!["System default".to_string()];
+    options.extend(inputs.iter().cloned());
+    let current = choices.device.as_ref()
+        .and_then(|device| inputs.iter().position(|name| name == device))
+        .map_or(0, |i| i + 1);
+    let picked = prompt.choose("Microphone to record:", &options, current)?;
+    choices.device = (picked > 0).then(|| options[picked].clone());
+    Ok(())
+}
+
+/// GPU vendor, from the kernel's DRM devices
+fn detect_gpu() -> Option<&'static str> {
+    if Path::new("/dev/nvidia0").exists() {
+        return Some("NVIDIA");
+    }
+    let entries = fs::read_dir("/sys/class/drm").ok()?;
+    entries.flatten().find_map(|entry| {
+        match fs::read_to_string(entry.path().join("device/vendor")).ok()?.trim() {
+            "0x10de" => Some("NVIDIA"),
+            "0x1002" => Some("AMD"),
+            "0x8086" => Some("Intel"),
+            _ => None,
+        }
+    })
+}
+
+/// Recommend faster-whisper only with an NVIDIA GPU and its Python environment
+/// present; whisper.cpp runs everywhere else
+fn pick_backend(prompt: &Prompt, choices: &mut Choices, requested: Option<String>) -> Result<()> {
+    if let Some(backend) = requested {
+        choices.backend = backend;
+        return Ok(());
+    }
+    let gpu = detect_gpu();
+    let python = std::env::var_os("FASTER_WHISPER_PYTHON").is_some();
+    println!("GPU: {}", gpu.unwrap_or("none found"));
+    println!("faster-whisper Python environment: {}", if python { "found" } else { "not found (FASTER_WHISPER_PYTHON unset)" });
+    
+    let options = vec!["whisper-cpp".to_string(), "faster-whisper".to_string()];
+    let recommended = if python && gpu == Some("NVIDIA") { 1 } else { 0 };
+    let picked = prompt.choose("Backend:", &options, recommended)?;
+    choices.backend = options[picked].clone();
+    Ok(())
+}
+
+/// Model worth its speed on this machine, English-only unless another
+/// language is configured
+fn recommended_model() -> String {
+    let size = if detect_gpu().is_some() { "small" } else { "base" };
+    if crate::helpers::resolve_language(None, None) == "en" {
+        format!("{}.en", size)
+    } else {
+        size.to_string()
+    }
+}
+
+/// Ask for a model and download it unless it's already there and intact
+fn download_model(prompt: &Prompt, choices: &mut Choices, requested: Option<String>) -> Result<()> {
+    choices.model = match requested {
+        Some(model) => model,
+        None if prompt.interactive => {
+            let default = recommended_model();
+            let answer = prompt.read_answer(&format!("Model [{}]:", default))?;
+            if answer.is_empty() { default } else { answer }
+        }
+        None => recommended_model(),
+    };
+    let model = choices.model.as_str();
+    
+    match choices.backend.as_str() {
+        "whisper-cpp" => {
+            let model_path = crate::helpers::whisper_cpp_model_path(model);
+            if crate::helpers::is_model_path(model) {
+                let format = crate::whisper_cpp::model::validate_model(&model_path, model)?;
+                println!("Model file {} is a {} model", model_path, format);
+                return Ok(());
+            }
+            if crate::whisper_cpp::model::validate_model(&model_path, model).is_ok() {
+                println!("Model {} is already downloaded ({})", model, model_path);
+                return Ok(());
+            }
+            println!("Downloading {}...", model);
+            crate::whisper_cpp::model::download_model_with_progress(model, &model_path)?;
+            crate::whisper_cpp::model::validate_model(&model_path, model)?;
+        }
+        "faster-whisper" => {
+            if let Some(path) = crate::faster_whisper::model::cached_path(model) {
+                println!("Model {} is already downloaded ({})", model, path);
+                return Ok(());
+            }
+            println!("Downloading {} (progress is shown in notifications)...", model);
+            crate::faster_whisper::model::download(model)?;
+        }
+        backend => println!("{} needs no model", backend),
+    }
+    println!("Model {} is ready", model);
+    Ok(())
+}
+
+/// Ask whether to encrypt what WA_KEEP_AUDIO keeps, and make sure there is
+/// a key: in the keyring through secret-tool, or in a file only the user
+/// can read
+fn pick_encryption(prompt: &Prompt, choices: &mut Choices) -> Result<()> {
+    if !cfg!(feature = "encrypt") {
+        println!("Encryption needs a build with the encrypt feature");
+        return Ok(());
+    }
+    choices.encrypt = prompt.confirm("Encrypt kept recordings and the journal?", choices.encrypt)?;
+    if !choices.encrypt {
+        return Ok(());
+    }
+    let location = crate::crypt::ensure_key()?;
+    println!("Encryption key is in {}", location);
+    Ok(())
+}
+
+/// Render a template, pointing it at the whisp-away on PATH (a profile link
+/// that survives upgrades) or at this binary when there is none. systemd
+/// wants an absolute path.
+fn render(template: &str) -> Result<String> {
+    let exec = match crate::helpers::find_program("whisp-away") {
+        Some(path) => path,
+        None => std::env::current_exe().context("Failed to get current executable path")?,
+    };
+    let exec = exec.to_string_lossy();
+    Ok(template.replace("@EXEC@", &exec).replace("Exec=whisp-away", &format!("Exec={}", exec)))
+}
+
+fn install_file(path: &str, contents: &str) -> Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path))?;
+    println!("Wrote {}", path);
+    Ok(())
+}
+
+/// Install and enable the daemon's user service. The tray starts its own
+/// daemon, so this is for setups without it.
+fn install_service(prompt: &Prompt) -> Result<()> {
+    let path = format!("{}/systemd/user/whisp-away-daemon.service", crate::helpers::config_dir());
+    if !prompt.confirm("Run the daemon as a user service? (not needed with the tray)", Path::new(&path).exists())? {
+        return Ok(());
+    }
+    install_file(&path, &render(SERVICE_TEMPLATE)?)?;
+    let status = crate::child_env::command("systemctl", &[])
+        .args(["--user", "daemon-reload"])
+        .status()
+        .and_then(|_| {
+            crate::child_env::command("systemctl", &[])
+                .args(["--user", "enable", "--now", "whisp-away-daemon.service"])
+                .status()
+        })
+        .context("Failed to run systemctl")?;
+    if !status.success() {
+        return Err(anyhow!("Enabling whisp-away-daemon.service failed ({})", status));
+    }
+    Ok(())
+}
+
+fn install_autostart(prompt: &Prompt) -> Result<()> {
+    let path = format!("{}/autostart/whisp-away.desktop", crate::helpers::config_dir());
+    if !prompt.confirm("Start the tray on login?", true)? {
+        return Ok(());
+    }
+    install_file(&path, &render(AUTOSTART_TEMPLATE)?)
+}
+
+/// Check the tools and model are in place, then record a short dictation and
+/// print what the backend made of it. Returns how many checks failed.
+fn check(prompt: &Prompt, choices: &Choices) -> Result<usize> {
+    let mut failed = 0;
+    let mut tools = vec!["pw-record", "pactl", "notify-send"];
+    tools.push(if choices.output_mode == Some(OutputMode::Clipboard) { "wl-copy" } else { "wtype" });
+    for tool in tools {
+        if on_path(tool) {
+            println!("{}: ok", tool);
+        } else {
+            println!("{}: not found on PATH", tool);
+            failed += 1;
+        }
+    }
+    // Prints what it found itself
+    if crate::whisper_cpp::model::print_check(&choices.backend, &choices.model).is_err() {
+        failed += 1;
+    }
+    
+    // Without a terminal there may be no one to talk; the mock backend still
+    // exercises recording when it's built in
+    let backend = if prompt.interactive || cfg!(not(feature = "mock")) { choices.backend.as_str() } else { "mock" };
+    if !prompt.confirm(&format!("Record a {} second test dictation with {}?", TEST_RECORDING.as_secs(), backend), true)? {
+        return Ok(failed);
+    }
+    if let Some(device) = &choices.device {
+        std::env::set_var("WA_AUDIO_DEVICE", device);
+    }
+    if let Err(e) = test_dictation(backend, choices) {
+        println!("Test dictation failed: {:#}", e);
+        failed += 1;
+    }
+    Ok(failed)
+}
+
+fn test_dictation(backend: &str, choices: &Choices) -> Result<()> {
+    println!("Speak now...");
+    recording::start_recording(backend, recording::AudioSource::Mic, Duration::ZERO, None)?;
+    std::thread::sleep(TEST_RECORDING);
+    let audio_file = recording::stop_recording(None, None, None, None, false, &mut crate::run::Run::default())?
+        .ok_or_else(|| anyhow!("The recording went missing"))?;
+    
+    let language = crate::helpers::resolve_language(None, None);
+    let result = crate::transcribe::transcribe_direct(backend, &audio_file, Some(choices.model.clone()), &language, None);
+    let _ = fs::remove_file(&audio_file);
+    let text = crate::normalize::transcript(&result?);
+    if text.trim().is_empty() {
+        return Err(anyhow!("No speech detected; check the microphone with `whisp-away devices`"));
+    }
+    println!("Heard: {}", text);
+    Ok(())
+}
+
+/// Walk through first-run configuration. Every step can be skipped and re-run:
+/// answers start from what the settings file already holds.
+pub fn run(options: SetupOptions) -> Result<()> {
+    if !options.non_interactive && !std::io::stdin().is_terminal() {
+        return Err(anyhow!("Not asking questions without a terminal; pass --non-interactive to take the defaults"));
+    }
+    let prompt = Prompt { interactive: !options.non_interactive };
+    
+    let path = config_path();
+    let saved = read_config(&path);
+    let saved = |key: &str| saved.iter().find(|(k, _)| k == key).map(|(_, value)| value.clone());
+    let mut choices = Choices {
+        backend: saved("WA_WHISPER_BACKEND").unwrap_or_else(|| crate::helpers::resolve_backend_source(None, None).value),
+        model: saved("WA_WHISPER_MODEL").unwrap_or_else(|| crate::helpers::resolve_model(None, None)),
+        device: saved("WA_AUDIO_DEVICE"),
+        output_mode: saved("WA_OUTPUT_MODE").and_then(|mode| OutputMode::from_str(&mode, true).ok()),
+        encrypt: matches!(saved("WA_ENCRYPT").or_else(|| std::env::var("WA_ENCRYPT").ok()).as_deref(), Some("1") | Some("true")),
+    };
+    
+    let steps: Vec<Step> = Step::value_variants().iter().copied().filter(|step| options.runs(*step)).collect();
+    for step in steps {
+        let name = step.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+        println!("\n== {} ==", name);
+        let result = match step {
+            Step::Detect => {
+                detect(&mut choices);
+                Ok(())
+            }
+            Step::Device => pick_device(&prompt, &mut choices, options.device.clone()),
+            Step::Backend => pick_backend(&prompt, &mut choices, options.backend.clone()),
+            Step::Model => download_model(&prompt, &mut choices, options.model.clone()),
+            Step::Encrypt => pick_encryption(&prompt, &mut choices),
+            Step::Config => {
+                let values = [
+                    ("WA_WHISPER_BACKEND", Some(choices.backend.clone())),
+                    ("WA_WHISPER_MODEL", Some(choices.model.clone())),
+                    ("WA_AUDIO_DEVICE", choices.device.clone()),
+                    ("WA_OUTPUT_MODE", choices.output_mode.map(|mode| mode.to_string())),
+                    ("WA_ENCRYPT", choices.encrypt.then(|| "1".to_string())),
+                ];
+                save_config(&prompt, &path, &values, options.write_config)
+            }
+            Step::Service => install_service(&prompt),
+            Step::Autostart => install_autostart(&prompt),
+            Step::Check => match check(&prompt, &choices)? {
+                0 => {
+                    println!("All checks passed");
+                    Ok(())
+                }
+                failed => Err(anyhow!("{} check(s) failed", failed)),
+            },
+        };
+        match result {
+            Err(e) if step == Step::Check => return Err(e),
+            // One step failing shouldn't lose the others; re-run it with --only
+            Err(e) => eprintln!("WARNING: {} step failed: {:#}", name, e),
+            Ok(()) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn snippet_leaves_out_unset_settings() {
+        let values = [("WA_WHISPER_BACKEND", Some("whisper-cpp".to_string())), ("WA_AUDIO_DEVICE", None)];
+        assert_eq!(config_snippet(&values), "WA_WHISPER_BACKEND=whisper-cpp\n");
+    }
+    
+    #[test]
+    fn write_keeps_lines_that_are_not_ours() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-setup-{}", std::process::id()));
+        let path = dir.join("environment.d/60-whisp-away.conf").to_string_lossy().into_owned();
+        fs::create_dir_all(dir.join("environment.d")).unwrap();
+        fs::write(&path, "# mine\nFOO=bar\nWA_WHISPER_MODEL=tiny.en\n").unwrap();
+        write_config(&path, &[("WA_WHISPER_MODEL", Some("base.en".to_string())), ("WA_OUTPUT_MODE", None)]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# mine\nFOO=bar\nWA_WHISPER_MODEL=base.en\n");
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -1,84 +1,477 @@
 use anyhow::{Context, Result};
 use std::io::{Read, Write};
+use std::net::TcpStream;
 use std::os::unix::net::UnixStream;
-use std::process::Command;
+use crate::run::Run;
+use crate::notify;
+use serde::de::DeserializeOwned;
+use crate::protocol::{self, Command, DaemonEvent, Framing, Hello, HelloReply, Priority, TranscriptionRequest, TranscriptionResponse, UNAUTHORIZED, UNSUPPORTED_PROTOCOL, WRONG_BACKEND};
+use crate::ready::NotReady;
 use crate::typing;
 
-/// Send a transcription request to the daemon via Unix socket
-pub fn send_transcription_request(
+/// The daemon refused the request because it runs a different backend
+#[derive(Debug)]
+pub struct WrongBackend {
+    pub running: String,
+}
+
+impl std::fmt::Display for WrongBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the running daemon is {}", self.running)
+    }
+}
+
+impl std::error::Error for WrongBackend {}
+
+/// The daemon and this client have no protocol version in common, or the
+/// daemon predates the hello exchange
+#[derive(Debug)]
+pub struct ProtocolMismatch(pub String);
+
+impl std::fmt::Display for ProtocolMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProtocolMismatch {}
+
+/// A TCP daemon turned the client away for a missing or wrong token
+#[derive(Debug)]
+pub struct Unauthorized(pub String);
+
+impl std::fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Unauthorized {}
+
+/// The daemon didn't get to the request within the queue wait
+#[derive(Debug)]
+pub struct QueueTimeout(pub std::time::Duration);
+
+impl std::fmt::Display for QueueTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Gave up after {} s queued behind the daemon's other requests", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for QueueTimeout {}
+
+/// The daemon didn't answer within the timeout of its fallback chain step
+#[derive(Debug)]
+pub struct StepTimeout(pub std::time::Duration);
+
+impl std::fmt::Display for StepTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No answer within {:?}", self.0)
+    }
+}
+
+impl std::error::Error for StepTimeout {}
+
+/// Why the daemon couldn't be used, for the fallback notification
+pub fn fallback_reason(error: &anyhow::Error) -> String {
+    if let Some(wrong) = error.downcast_ref::<WrongBackend>() {
+        return format!("{} daemon is running", wrong.running);
+    }
+    if let Some(not_ready) = error.downcast_ref::<NotReady>() {
+        return not_ready.to_string();
+    }
+    if let Some(mismatch) = error.downcast_ref::<ProtocolMismatch>() {
+        return mismatch.to_string();
+    }
+    if let Some(timeout) = error.downcast_ref::<QueueTimeout>() {
+        return timeout.to_string();
+    }
+    if let Some(timeout) = error.downcast_ref::<StepTimeout>() {
+        return timeout.to_string();
+    }
+    if let Some(unauthorized) = error.downcast_ref::<Unauthorized>() {
+        return unauthorized.to_string();
+    }
+    "Daemon not running".to_string()
+}
+
+/// How a client reaches the daemon
+#[derive(Clone)]
+pub struct DaemonOptions {
+    /// Unix socket path, or `tcp://HOST:PORT` from --daemon-url
+    pub socket_path: String,
+    /// Use the running daemon even if its backend differs from the requested one
+    pub use_running_backend: bool,
+    /// Ready file of the daemon, for --require-ready and to see how busy it is
+    pub ready_file: String,
+    /// The ready file must name a live daemon before connecting (--require-ready)
+    pub require_ready: bool,
+    /// Give up instead of queueing behind requests the daemon is already busy with
+    pub no_queue: bool,
+    /// Give up on a daemon that hasn't answered within this long (a fallback
+    /// chain step's timeout)
+    pub timeout: Option<std::time::Duration>,
+    /// Let the daemon delete a recording in its inbox once it has answered.
+    /// Off while a later chain step may still need the file.
+    pub hand_over: bool,
+}
+
+/// Read the next framed message. `None` means the daemon closed the
+/// connection first or sent something that doesn't parse as a `T`.
+fn read_message<T: DeserializeOwned>(stream: &mut impl Read, buffer: &mut Vec<u8>) -> Result<Option<T>> {
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(message) = protocol::take_message(buffer) {
+            return Ok(message.map_err(|e| eprintln!("DEBUG: Unreadable message from daemon: {}", e)).ok());
+        }
+        let n = stream.read(&mut chunk).context("Failed to read response from daemon")?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Agree on a protocol version with the daemon before sending a request.
+/// Requests with `audio_bytes` set need the daemon to take inline audio, and
+/// present the daemon token (see daemons::tcp_token) since they go over TCP.
+fn hello(stream: &mut (impl Read + Write), buffer: &mut Vec<u8>, request: &TranscriptionRequest) -> Result<Hello> {
+    let mut wanted = vec![protocol::CAP_TRANSCRIBE, protocol::CAP_DETECT_LANGUAGE];
+    if request.priority == Priority::Batch {
+        wanted.push(protocol::CAP_PRIORITY);
+    }
+    if request.allow_long {
+        wanted.push(protocol::CAP_ALLOW_LONG);
+    }
+    if request.initial_prompt.is_some() {
+        wanted.push(protocol::CAP_INITIAL_PROMPT);
+    }
+    if request.fast {
+        wanted.push(protocol::CAP_FAST);
+    }
+    if request.segments {
+        wanted.push(protocol::CAP_SEGMENTS);
+    }
+    if request.no_gpu {
+        wanted.push(protocol::CAP_NO_GPU);
+    }
+    // Inline audio means TCP, where compression pays off by default
+    if protocol::compression_wanted(request.audio_bytes.is_some()) {
+        wanted.push(protocol::CAP_COMPRESS);
+    }
+    let mut opening = Hello::new(&wanted);
+    if request.audio_bytes.is_some() {
+        opening.capabilities.push(protocol::CAP_INLINE_AUDIO.to_string());
+        opening.token = crate::daemons::tcp_token();
+    }
+    stream.write_all(&protocol::frame(&opening)?)
+        .context("Failed to send hello to daemon")?;
+    
+    let reply = match read_message::<HelloReply>(stream, buffer)? {
+        Some(HelloReply::Hello(reply)) => reply,
+        Some(HelloReply::Refused(TranscriptionResponse { error_code: Some(code), error, .. })) if code == UNAUTHORIZED => {
+            return Err(Unauthorized(error.unwrap_or(code)).into());
+        }
+        Some(HelloReply::Refused(TranscriptionResponse { error_code: Some(code), error, .. })) if code == UNSUPPORTED_PROTOCOL => {
+            return Err(ProtocolMismatch(error.unwrap_or(code)).into());
+        }
+        Some(HelloReply::Refused(_)) | None => {
+            return Err(ProtocolMismatch(format!(
+                "Daemon predates protocol {}, restart it",
+                protocol::PROTOCOL_VERSION
+            ))
+            .into());
+        }
+    };
+    protocol::check_reply(&reply).map_err(ProtocolMismatch)?;
+    crate::upgrade::warn_once(reply.version.as_deref());
+    if !reply.has(protocol::CAP_TRANSCRIBE) {
+        return Err(ProtocolMismatch("Daemon doesn't offer transcription".to_string()).into());
+    }
+    if request.audio_bytes.is_some() && !reply.has(protocol::CAP_INLINE_AUDIO) {
+        return Err(ProtocolMismatch("Daemon doesn't take audio over the connection".to_string()).into());
+    }
+    eprintln!(
+        "DEBUG: Daemon speaks protocol {} with {}",
+        reply.protocol_version,
+        reply.capabilities.join(", ")
+    );
+    if request.initial_prompt.is_some() && !reply.has(protocol::CAP_INITIAL_PROMPT) {
+        eprintln!("DEBUG: Daemon ignores initial prompts, the glossary won't apply");
+    }
+    if request.fast && !reply.has(protocol::CAP_FAST) {
+        eprintln!("DEBUG: Daemon has no fast model (daemon --fast-model), its main model will transcribe");
+    }
+    if request.segments && !reply.has(protocol::CAP_SEGMENTS) {
+        eprintln!("DEBUG: Daemon doesn't return segments, the transcript will be one cue without a confidence");
+    }
+    if request.no_gpu && !reply.has(protocol::CAP_NO_GPU) {
+        eprintln!("DEBUG: Daemon can't leave the GPU, it transcribes with the model it has loaded");
+    }
+    Ok(reply)
+}
+
+/// Send a request to the daemon and return its reply.
+/// `Ok(None)` means the daemon answered with something that isn't a valid response.
+pub fn request_transcription(
     socket_path: &str,
+    request: &TranscriptionRequest,
+) -> Result<Option<TranscriptionResponse>> {
+    request_transcription_within(socket_path, request, None)
+}
+
+/// Like request_transcription, giving up with a QueueTimeout if the response
+/// takes longer than `timeout`. A `tcp://` address gets the audio file's
+/// bytes sent along with the request, since the daemon can't read the path.
+fn request_transcription_within(
+    socket_path: &str,
+    request: &TranscriptionRequest,
+    timeout: Option<std::time::Duration>,
+) -> Result<Option<TranscriptionResponse>> {
+    match crate::daemons::tcp_address(socket_path) {
+        Some(address) => {
+            let stream = connect_tcp(address, timeout)
+                .map_err(|e| anyhow::anyhow!("Failed to connect to daemon at {}: {}", address, e))?;
+            stream.set_read_timeout(timeout)?;
+            let audio = std::fs::read(&request.audio_path)
+                .with_context(|| format!("Failed to read {}", request.audio_path))?;
+            exchange(stream, request, Some(&audio), timeout)
+        }
+        None => {
+            let stream = UnixStream::connect(socket_path)
+                .map_err(|e| anyhow::anyhow!("Failed to connect to daemon: {}", e))?;
+            stream.set_read_timeout(timeout)?;
+            exchange(stream, request, None, timeout)
+        }
+    }
+}
+
+/// Connect to `address`, giving up after `timeout` on each of the addresses
+/// it resolves to rather than after the system's minutes
+fn connect_tcp(address: &str, timeout: Option<std::time::Duration>) -> std::io::Result<TcpStream> {
+    let Some(timeout) = timeout else {
+        return TcpStream::connect(address);
+    };
+    let mut last_error = None;
+    for resolved in std::net::ToSocketAddrs::to_socket_addrs(address)? {
+        match TcpStream::connect_timeout(&resolved, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address")))
+}
+
+/// A read that ran into `timeout` as the QueueTimeout it stands for
+fn gave_up(error: anyhow::Error, timeout: Option<std::time::Duration>) -> anyhow::Error {
+    let timed_out = error
+        .root_cause()
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|io| matches!(io.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+    match timeout {
+        Some(timeout) if timed_out => QueueTimeout(timeout).into(),
+        _ => error,
+    }
+}
+
+/// Negotiate, send the request (and `audio` inline, if given) and read the reply
+fn exchange(
+    mut stream: impl Read + Write,
+    request: &TranscriptionRequest,
+    audio: Option<&[u8]>,
+    timeout: Option<std::time::Duration>,
+) -> Result<Option<TranscriptionResponse>> {
+    let mut request = request.clone();
+    request.audio_bytes = audio.map(|audio| audio.len() as u64);
+    let mut buffer = Vec::new();
+    let reply = hello(&mut stream, &mut buffer, &request).map_err(|e| gave_up(e, timeout))?;
+    
+    // Send request
+    stream.write_all(&Framing::agreed(&reply).frame(&request)?)
+        .context("Failed to send request to daemon")?;
+    if let Some(audio) = audio {
+        stream.write_all(audio).context("Failed to send audio to daemon")?;
+    }
+    
+    // Daemons in other languages (faster-whisper) don't share the Rust artifact
+    // filter, so apply it here too; stripping is idempotent
+    let mut response: Option<TranscriptionResponse> = read_message(&mut stream, &mut buffer).map_err(|e| gave_up(e, timeout))?;
+    if let Some(text) = response.as_mut().and_then(|r| r.text.as_mut()) {
+        *text = crate::artifacts::strip(text);
+    }
+    Ok(response)
+}
+
+/// An open `subscribe` connection to the daemon
+pub struct Subscription {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+}
+
+impl Subscription {
+    /// Subscribe to the daemon's events. A daemon that doesn't push them
+    /// (the faster-whisper one) is a ProtocolMismatch.
+    pub fn open(socket_path: &str) -> Result<Self> {
+        let mut stream = UnixStream::connect(socket_path)
+            .map_err(|e| anyhow::anyhow!("Failed to connect to daemon: {}", e))?;
+        let mut buffer = Vec::new();
+        stream.write_all(&protocol::frame(&Hello::new(&[protocol::CAP_SUBSCRIBE]))?)
+            .context("Failed to send hello to daemon")?;
+        let reply = match read_message::<HelloReply>(&mut stream, &mut buffer)? {
+            Some(HelloReply::Hello(reply)) => reply,
+            _ => return Err(ProtocolMismatch("Daemon refused the hello".to_string()).into()),
+        };
+        protocol::check_reply(&reply).map_err(ProtocolMismatch)?;
+        if !reply.has(protocol::CAP_SUBSCRIBE) {
+            return Err(ProtocolMismatch("Daemon doesn't push events".to_string()).into());
+        }
+        stream.write_all(&protocol::frame(&Command::Subscribe)?)
+            .context("Failed to subscribe")?;
+        match read_message::<DaemonEvent>(&mut stream, &mut buffer)? {
+            Some(DaemonEvent::Subscribed) => Ok(Subscription { stream, buffer }),
+            _ => Err(ProtocolMismatch("Daemon didn't confirm the subscription".to_string()).into()),
+        }
+    }
+    
+    /// Wait for the next event. `None` means the daemon closed the connection.
+    pub fn next(&mut self) -> Result<Option<DaemonEvent>> {
+        read_message(&mut self.stream, &mut self.buffer)
+    }
+}
+
+/// Requests the daemon will work on before a new dictation: the one it is
+/// transcribing and the interactive ones waiting, from its ready file
+fn requests_ahead(ready_file: &str, socket_path: &str) -> usize {
+    match crate::ready::read(ready_file) {
+        Some(crate::ready::ReadyInfo { socket_path: ready_socket, queue: Some(queue), .. }) if ready_socket == socket_path => {
+            queue.active.is_some() as usize + queue.interactive_waiting
+        }
+        _ => 0,
+    }
+}
+
+/// Whether `audio_file` was recorded into the inbox of the daemon the request
+/// goes to, which then deletes it. Never over TCP, whose address no ready
+/// file names: the audio is copied there.
+pub fn managed(daemon: &DaemonOptions, audio_file: &str) -> bool {
+    daemon.hand_over && crate::ready::read(&daemon.ready_file)
+        .filter(|info| info.socket_path == daemon.socket_path)
+        .and_then(|info| info.inbox)
+        .is_some_and(|inbox| crate::inbox::contains(&inbox, audio_file))
+}
+
+/// Send a transcription request to the daemon via Unix socket, in `run`'s
+/// language, and type the result, noting it in `run`.
+/// Unless `use_running_backend` is set, a daemon running a different backend
+/// refuses the request and a `WrongBackend` error is returned; with
+/// `require_ready`, a daemon that isn't ready yields a `NotReady` error.
+pub fn send_transcription_request(
+    daemon: &DaemonOptions,
     audio_file: &str,
-    wtype_path: &str,
+    delivery: &typing::Delivery,
+    run: &mut Run,
     backend_name: &str,
+    prompt_file: Option<&str>,
+    fast_since: Option<std::time::Instant>,
 ) -> Result<()> {
-    match UnixStream::connect(socket_path) {
-        Ok(mut stream) => {
-            // Send request
-            let request = format!(r#"{{"audio_path": "{}"}}"#, audio_file);
-            stream.write_all(request.as_bytes())
-                .context("Failed to send request to daemon")?;
-            
-            // Read response
-            let mut response = String::new();
-            stream.read_to_string(&mut response)
-                .context("Failed to read response from daemon")?;
-            
-            // Check if transcription was successful
-            let success = response.contains(r#""success":true"#) || response.contains(r#""success": true"#);
+    if daemon.require_ready {
+        crate::ready::check(&daemon.ready_file, &daemon.socket_path)?;
+    }
+    
+    // Dictation that has to wait says so, rather than looking stuck
+    let ahead = requests_ahead(&daemon.ready_file, &daemon.socket_path);
+    if ahead > 0 {
+        if daemon.no_queue {
+            run.fail("daemon_busy");
+            notify::Notification::normal(
+                "Voice Input",
+                &format!("⏭️ Dropped: daemon busy with {} request(s) (--no-queue)\nBackend: {}", ahead, backend_name),
+            )
+            .send();
+            return Ok(());
+        }
+        // Replaces the "Transcribing..." notification the client just showed
+        notify::Notification::progress("Voice Input", &format!("⏳ Queued behind {} request(s)\nBackend: {}", ahead, backend_name))
+            .send();
+    }
+    // Typed in the order dictations were submitted, whichever finishes first
+    crate::order::take_turn();
+    
+    // Connection errors are returned so the caller can handle fallback logic
+    let mut request = TranscriptionRequest::new(audio_file, &run.language);
+    request.fast = fast_since.is_some();
+    // A command has no use for the glossary, which would only slow the fast model down
+    if !request.fast {
+        request.initial_prompt = crate::prompt::current(prompt_file, delivery.profile.as_deref());
+        run.prompt = request.initial_prompt.clone();
+    }
+    request.managed = managed(daemon, audio_file);
+    // Sentence breaks are put at the pauses between segments
+    request.segments = crate::punctuation::enabled(delivery.profile.as_deref());
+    request.no_gpu = crate::helpers::no_gpu();
+    if !daemon.use_running_backend {
+        request.backend = Some(backend_name.to_string());
+    }
+    let timeout = match ((ahead > 0).then(crate::order::max_wait), daemon.timeout) {
+        (Some(queue_wait), Some(step)) => Some(queue_wait.min(step)),
+        (queue_wait, step) => queue_wait.or(step),
+    };
+    let response = request_transcription_within(&daemon.socket_path, &request, timeout).map_err(|e| {
+        match e.downcast_ref::<QueueTimeout>() {
+            Some(QueueTimeout(waited)) if Some(*waited) == daemon.timeout => StepTimeout(*waited).into(),
+            _ => e,
+        }
+    })?;
             
-            if success {
-                // Parse the transcribed text from JSON response
-                let text = extract_text_from_response(&response);
-                
-                if let Some(transcribed_text) = text {
-                    typing::type_text(transcribed_text.trim(), wtype_path, &format!("{} daemon", backend_name))?;
-                } else {
-                    Command::new("notify-send")
-                        .args(&[
-                            "Voice Input",
-                            &format!("⚠️ Could not parse response\nBackend: {}", backend_name),
-                            "-t", "2000",
-                            "-h", "string:x-canonical-private-synchronous:voice"
-                        ])
-                        .spawn()?;
-                }
-            } else {
-                Command::new("notify-send")
-                    .args(&[
-                        "Voice Input",
-                        &format!("❌ Transcription failed\nBackend: {}", backend_name),
-                        "-t", "2000",
-                        "-h", "string:x-canonical-private-synchronous:voice"
-                    ])
-                    .spawn()?;
+    // Check if transcription was successful
+    match response {
+        Some(TranscriptionResponse { error_code: Some(code), backend: Some(running), .. }) if code == WRONG_BACKEND => {
+            return Err(WrongBackend { running }.into());
+        }
+        Some(TranscriptionResponse { success: true, text: Some(transcribed_text), language: detected, backend, retried, model, latency_ms, segments, cpu, audio_seconds, .. }) => {
+            // The daemon measured what it transcribed
+            if let Some(seconds) = audio_seconds {
+                run.duration = Some(std::time::Duration::from_secs_f64(seconds));
             }
-            
-            Ok(())
+            let transcribed_text = if request.segments {
+                crate::punctuation::apply(transcribed_text, &crate::subtitles::clean(segments))
+            } else {
+                transcribed_text
+            };
+            let daemon = match backend {
+                Some(running) if running != backend_name => format!("{} (running instead of {})", running, backend_name),
+                _ => backend_name.to_string(),
+            };
+            let label = format!(
+                "{} daemon{}{}{}{}",
+                daemon,
+                if cpu { ", CPU" } else { "" },
+                if retried { ", retried" } else { "" },
+                crate::client::fast_report(fast_since, model.as_deref(), latency_ms),
+                crate::helpers::language_note(&run.language, detected.as_deref())
+            );
+            run.detected_language = detected;
+            typing::type_text(transcribed_text.trim(), delivery, run, &label)?;
         }
-        Err(e) => {
-            // Return the error so the caller can handle fallback logic
-            Err(anyhow::anyhow!("Failed to connect to daemon: {}", e))
+        Some(TranscriptionResponse { success: true, .. }) | None => {
+            run.fail("bad_response");
+            notify::Notification::error("Voice Input", &format!("⚠️ Could not parse response\nBackend: {}", backend_name))
+                .send();
         }
-    }
-}
-
-/// Extract the "text" field value from a JSON response string
-fn extract_text_from_response(response: &str) -> Option<String> {
-    if let Some(text_start_idx) = response.find(r#""text":"#) {
-        let after_text = &response[text_start_idx + 7..];
-        let content_start = after_text.trim_start();
-        
-        if content_start.starts_with('"') {
-            let text_content = &content_start[1..];
-            if let Some(end_quote) = text_content.find('"') {
-                Some(text_content[..end_quote].to_string())
-            } else {
-                None
-            }
-        } else {
-            None
+        Some(TranscriptionResponse { error_code: Some(code), error, .. }) => {
+            run.fail(&code);
+            // Coded errors (e.g. audio_too_long) carry a message meant for the user
+            let error = error.unwrap_or_else(|| "Transcription failed".to_string());
+            notify::Notification::error("Voice Input", &format!("❌ {}\nBackend: {}", error, backend_name))
+                .timeout(5000)
+                .send();
+        }
+        Some(_) => {
+            run.fail("transcription_failed");
+            notify::Notification::error("Voice Input", &format!("❌ Transcription failed\nBackend: {}", backend_name))
+                .send();
         }
-    } else {
-        None
     }
-}
\ No newline at end of file
+    
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+/// Words in a transcript: what is left between whitespace once it is
+/// normalized, counting only pieces with a letter or digit in them, so a
+/// lone dash or ellipsis isn't a word. Letters and digits of any script count.
+pub fn word_count(text: &str) -> usize {
+    crate::normalize::normalize(text, false)
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Words per minute over `duration`, to one decimal; none for a recording
+/// with no length
+pub fn words_per_minute(words: usize, duration: Duration) -> Option<f64> {
+    let minutes = duration.as_secs_f64() / 60.0;
+    (minutes > 0.0).then(|| (words as f64 / minutes * 10.0).round() / 10.0)
+}
+
+/// "12.4 s → 31 words"
+pub fn summary(duration: Duration, words: usize) -> String {
+    format!("{:.1} s → {} word{}", duration.as_secs_f64(), words, if words == 1 { "" } else { "s" })
+}
+
+/// How long the recording that produced `text` was, how many words it came
+/// to and how fast they were spoken, when the length is known
+pub struct Stats {
+    pub duration: Duration,
+    pub words: usize,
+    pub words_per_minute: Option<f64>,
+}
+
+impl Stats {
+    /// Stats for `text` from a recording `duration` long, if that is known
+    pub fn of(text: &str, duration: Option<Duration>) -> Option<Stats> {
+        let duration = duration?;
+        let words = word_count(text);
+        Some(Stats { duration, words, words_per_minute: words_per_minute(words, duration) })
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::time::Duration;
+use crate::protocol::Segment;
+
+/// Mean token probability below which a cue counts as low confidence
+const DEFAULT_THRESHOLD: f32 = 0.6;
+/// Put around low-confidence cues; players show SRT and WebVTT `<i>` as italics
+const DEFAULT_MARKERS: (&str, &str) = ("<i>", "</i>");
+
+/// What `transcribe` prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The transcript as one line of text
+    Text,
+    /// SRT subtitles, one cue per segment
+    Srt,
+    /// WebVTT subtitles, one cue per segment
+    Vtt,
+    /// The text, language and each segment's timing and confidence
+    Json,
+}
+
+/// How SRT and WebVTT cues show whisper's confidence in them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfidenceMark {
+    /// Just the text
+    Off,
+    /// Append each cue's confidence, e.g. "[0.83]"
+    Value,
+    /// Put the low-confidence markers around cues below the threshold
+    Wrap,
+}
+
+/// Confidence marking for subtitle cues, from flags and WA_CONFIDENCE_* env vars
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfidenceStyle {
+    pub mark: ConfidenceMark,
+    pub threshold: f32,
+    pub open: String,
+    pub close: String,
+}
+
+impl ConfidenceStyle {
+    /// Flags given on the command line win over WA_CONFIDENCE_MARK,
+    /// WA_CONFIDENCE_THRESHOLD and WA_CONFIDENCE_MARKERS
+    pub fn resolve(mark: Option<ConfidenceMark>, threshold: Option<f32>, markers: Option<String>) -> Result<Self> {
+        let mark = match mark {
+            Some(mark) => mark,
+            None => match std::env::var("WA_CONFIDENCE_MARK") {
+                Ok(mark) => ConfidenceMark::from_str(&mark, true)
+                    .map_err(|_| anyhow!("WA_CONFIDENCE_MARK must be off, value or wrap, not {:?}", mark))?,
+                Err(_) => ConfidenceMark::Off,
+            },
+        };
+        let threshold = match threshold {
+            Some(threshold) => threshold,
+            None => match std::env::var("WA_CONFIDENCE_THRESHOLD") {
+                Ok(threshold) => threshold.parse()
+                    .map_err(|_| anyhow!("WA_CONFIDENCE_THRESHOLD must be a number from 0 to 1, not {:?}", threshold))?,
+                Err(_) => DEFAULT_THRESHOLD,
+            },
+        };
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(anyhow!("The confidence threshold must be from 0 to 1, not {}", threshold));
+        }
+        let (open, close) = match markers.or_else(|| std::env::var("WA_CONFIDENCE_MARKERS").ok()) {
+            Some(markers) => {
+                let (open, close) = markers.split_once(',')
+                    .ok_or_else(|| anyhow!("Confidence markers are OPEN,CLOSE (e.g. \"<i>,</i>\" or \", [?]\"), not {:?}", markers))?;
+                (open.to_string(), close.to_string())
+            }
+            None => (DEFAULT_MARKERS.0.to_string(), DEFAULT_MARKERS.1.to_string()),
+        };
+        Ok(Self { mark, threshold, open, close })
+    }
+    
+    /// A segment's text as its cue shows it
+    fn cue(&self, segment: &Segment) -> String {
+        match (self.mark, segment.confidence) {
+            (ConfidenceMark::Value, Some(confidence)) => format!("{} [{:.2}]", segment.text, confidence),
+            (ConfidenceMark::Wrap, Some(confidence)) if confidence < self.threshold => {
+                format!("{}{}{}", self.open, segment.text, self.close)
+            }
+            _ => segment.text.clone(),
+        }
+    }
+}
+
+/// SRT timestamp, `HH:MM:SS,mmm`
+pub fn srt_time(time: Duration) -> String {
+    let ms = time.as_millis();
+    format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+/// WebVTT timestamp, `HH:MM:SS.mmm`
+fn vtt_time(time: Duration) -> String {
+    srt_time(time).replace(',', ".")
+}
+
+/// Tidy the segments a backend returned the way transcripts are: artifacts
+/// stripped and punctuation and whitespace normalized, dropping segments
+/// left empty
+pub fn clean(segments: Vec<Segment>) -> Vec<Segment> {
+    segments
+        .into_iter()
+        .filter_map(|segment| {
+            let text = crate::normalize::transcript(&crate::artifacts::strip(&segment.text));
+            (!text.is_empty()).then_some(Segment { text, ..segment })
+        })
+        .collect()
+}
+
+/// Render segments as SRT subtitles
+pub fn render_srt(segments: &[Segment], style: &ConfidenceStyle) -> String {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                srt_time(Duration::from_millis(segment.start_ms)),
+                srt_time(Duration::from_millis(segment.end_ms)),
+                style.cue(segment)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render segments as WebVTT subtitles
+pub fn render_vtt(segments: &[Segment], style: &ConfidenceStyle) -> String {
+    let cues = segments.iter().map(|segment| {
+        format!(
+            "{} --> {}\n{}\n",
+            vtt_time(Duration::from_millis(segment.start_ms)),
+            vtt_time(Duration::from_millis(segment.end_ms)),
+            style.cue(segment)
+        )
+    });
+    std::iter::once("WEBVTT\n".to_string()).chain(cues).collect::<Vec<_>>().join("\n")
+}
+
+/// JSON output of `transcribe --output-format json`
+#[derive(Serialize)]
+struct JsonTranscript<'a> {
+    text: &'a str,
+    language: Option<&'a str>,
+    segments: &'a [Segment],
+}
+
+/// Render a transcript as JSON. Confidences are always the raw numbers
+/// (null when the backend has none); markers are for subtitles only.
+pub fn render_json(text: &str, language: Option<&str>, segments: &[Segment]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&JsonTranscript { text, language, segments })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn segments() -> Vec<Segment> {
+        let segment = |start_ms, end_ms, text: &str, confidence| Segment { start_ms, end_ms, text: text.to_string(), confidence };
+        vec![
+            segment(0, 1500, "Hello there.", Some(0.91)),
+            segment(1500, 3_725_040, "Mumbled words", Some(0.42)),
+            segment(3_725_040, 3_726_000, "No confidence", None),
+        ]
+    }
+    
+    fn style(mark: ConfidenceMark, threshold: f32) -> ConfidenceStyle {
+        ConfidenceStyle::resolve(Some(mark), Some(threshold), Some("<i>,</i>".to_string())).unwrap()
+    }
+    
+    /// SRT of segments() with these three cue texts
+    fn srt(cues: [&str; 3]) -> String {
+        format!(
+            "1\n00:00:00,000 --> 00:00:01,500\n{}\n\n\
+             2\n00:00:01,500 --> 01:02:05,040\n{}\n\n\
+             3\n01:02:05,040 --> 01:02:06,000\n{}\n",
+            cues[0], cues[1], cues[2]
+        )
+    }
+    
+    /// WebVTT of segments() with these three cue texts
+    fn vtt(cues: [&str; 3]) -> String {
+        format!(
+            "WEBVTT\n\n\
+             00:00:00.000 --> 00:00:01.500\n{}\n\n\
+             00:00:01.500 --> 01:02:05.040\n{}\n\n\
+             01:02:05.040 --> 01:02:06.000\n{}\n",
+            cues[0], cues[1], cues[2]
+        )
+    }
+    
+    #[test]
+    fn unmarked_cues_are_just_the_text() {
+        let style = style(ConfidenceMark::Off, 0.6);
+        let cues = ["Hello there.", "Mumbled words", "No confidence"];
+        assert_eq!(render_srt(&segments(), &style), srt(cues));
+        assert_eq!(render_vtt(&segments(), &style), vtt(cues));
+    }
+    
+    #[test]
+    fn value_appends_every_known_confidence() {
+        let style = style(ConfidenceMark::Value, 0.6);
+        let cues = ["Hello there. [0.91]", "Mumbled words [0.42]", "No confidence"];
+        assert_eq!(render_srt(&segments(), &style), srt(cues));
+        assert_eq!(render_vtt(&segments(), &style), vtt(cues));
+    }
+    
+    #[test]
+    fn wrap_marks_only_cues_below_the_threshold() {
+        for (threshold, cues) in [
+            (0.0, ["Hello there.", "Mumbled words", "No confidence"]),
+            (0.42, ["Hello there.", "Mumbled words", "No confidence"]),
+            (0.6, ["Hello there.", "<i>Mumbled words</i>", "No confidence"]),
+            (0.95, ["<i>Hello there.</i>", "<i>Mumbled words</i>", "No confidence"]),
+        ] {
+            let style = style(ConfidenceMark::Wrap, threshold);
+            assert_eq!(render_srt(&segments(), &style), srt(cues), "threshold {}", threshold);
+            assert_eq!(render_vtt(&segments(), &style), vtt(cues), "threshold {}", threshold);
+        }
+    }
+    
+    #[test]
+    fn markers_may_be_empty_on_one_side() {
+        let style = ConfidenceStyle::resolve(Some(ConfidenceMark::Wrap), Some(0.6), Some(", [?]".to_string())).unwrap();
+        assert_eq!(
+            render_vtt(&segments(), &style),
+            vtt(["Hello there.", "Mumbled words [?]", "No confidence"])
+        );
+    }
+    
+    #[test]
+    fn bad_thresholds_and_markers_are_errors() {
+        assert!(ConfidenceStyle::resolve(Some(ConfidenceMark::Wrap), Some(1.5), Some("<i>,</i>".to_string())).is_err());
+        assert!(ConfidenceStyle::resolve(Some(ConfidenceMark::Wrap), Some(-0.1), Some("<i>,</i>".to_string())).is_err());
+        assert!(ConfidenceStyle::resolve(Some(ConfidenceMark::Wrap), Some(0.6), Some("<i>".to_string())).is_err());
+    }
+    
+    #[test]
+    fn json_always_has_the_raw_confidence() {
+        let json = render_json("Hello there. Mumbled words No confidence", Some("en"), &segments()).unwrap();
+        assert_eq!(
+            json,
+            r#"{
+  "text": "Hello there. Mumbled words No confidence",
+  "language": "en",
+  "segments": [
+    {
+      "start_ms": 0,
+      "end_ms": 1500,
+      "text": "Hello there.",
+      "confidence": 0.91
+    },
+    {
+      "start_ms": 1500,
+      "end_ms": 3725040,
+      "text": "Mumbled words",
+      "confidence": 0.42
+    },
+    {
+      "start_ms": 3725040,
+      "end_ms": 3726000,
+      "text": "No confidence",
+      "confidence": null
+    }
+  ]
+}"#
+        );
+    }
+    
+    #[test]
+    fn an_empty_transcript_is_just_the_header() {
+        let style = style(ConfidenceMark::Value, 0.6);
+        assert_eq!(render_srt(&[], &style), "");
+        assert_eq!(render_vtt(&[], &style), "WEBVTT\n");
+    }
+}
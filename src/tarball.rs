@@ -0,0 +1,292 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{self, Read, Write};
+
+/// Tar blocks are this big; headers take one, data is padded to a multiple
+const BLOCK: usize = 512;
+/// Longest name a plain header holds; longer ones go in a GNU long-name entry
+const NAME_LEN: usize = 100;
+/// A GNU long name beyond this is not from a bundle of ours
+const MAX_LONG_NAME: u64 = 4096;
+/// A pax header beyond this is not from a bundle of ours
+const MAX_PAX_HEADER: u64 = 64 * 1024;
+
+/// What a member is. Anything else (links, devices, FIFOs) is refused while
+/// reading, so a bundle can only ever create plain files and directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+}
+
+/// A member's header, with the long name or pax path applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub path: String,
+    pub kind: Kind,
+    pub mode: u32,
+    pub size: u64,
+}
+
+fn octal(field: &[u8]) -> Result<u64> {
+    // Base-256, which GNU tar uses for sizes of 8 GiB and up
+    if field.first().is_some_and(|b| b & 0x80 != 0) {
+        return Ok(field[1..].iter().fold(0u64, |n, b| (n << 8) | *b as u64));
+    }
+    let text = std::str::from_utf8(field).context("Corrupt tar header")?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| anyhow!("Corrupt tar header: {:?} isn't a number", text))
+}
+
+fn text(field: &[u8]) -> Result<String> {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8(field[..end].to_vec()).map_err(|_| anyhow!("Tar member name isn't UTF-8"))
+}
+
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    header.iter().enumerate().map(|(i, b)| if (148..156).contains(&i) { b' ' as u64 } else { *b as u64 }).sum()
+}
+
+/// Read `len` bytes of a member's data and the padding after it
+fn read_data(reader: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        return Err(anyhow!("Tar archive ends in the middle of a member"));
+    }
+    skip_padding(reader, len)?;
+    Ok(data)
+}
+
+fn skip_padding(reader: &mut impl Read, len: u64) -> Result<()> {
+    let padding = (BLOCK as u64 - len % BLOCK as u64) % BLOCK as u64;
+    io::copy(&mut reader.take(padding), &mut io::sink())?;
+    Ok(())
+}
+
+/// `path` from pax records ("LEN key=value\n" each)
+fn pax_path(records: &[u8]) -> Option<String> {
+    let mut rest = records;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|b| *b == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?;
+        let record = record.strip_suffix(b"\n")?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return String::from_utf8(path.to_vec()).ok();
+        }
+        rest = &rest[len..];
+    }
+    None
+}
+
+/// Go through a (decompressed) tar archive, handing each file and directory
+/// to `each` with a reader over its data. Hard links, symlinks and every
+/// other kind of member fail the whole read.
+pub fn read(mut reader: impl Read, mut each: impl FnMut(&Member, &mut dyn Read) -> Result<()>) -> Result<()> {
+    let mut long_name = None;
+    loop {
+        let mut header = [0u8; BLOCK];
+        reader.read_exact(&mut header).context("Tar archive ends without an end marker")?;
+        if header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        if octal(&header[148..156])? != checksum(&header) {
+            return Err(anyhow!("Corrupt tar header (checksum mismatch)"));
+        }
+        let size = octal(&header[124..136])?;
+        let typeflag = header[156];
+        match typeflag {
+            b'L' | b'x' => {
+                let limit = if typeflag == b'L' { MAX_LONG_NAME } else { MAX_PAX_HEADER };
+                if size > limit {
+                    return Err(anyhow!("Tar header of {} bytes is too big for a bundle", size));
+                }
+                let data = read_data(&mut reader, size)?;
+                long_name = if typeflag == b'L' { Some(text(&data)?) } else { pax_path(&data).or(long_name) };
+                continue;
+            }
+            b'g' => {
+                // Global pax settings; nothing in them matters here
+                if size > MAX_PAX_HEADER {
+                    return Err(anyhow!("Tar header of {} bytes is too big for a bundle", size));
+                }
+                read_data(&mut reader, size)?;
+                continue;
+            }
+            _ => {}
+        }
+        
+        let path = match long_name.take() {
+            Some(path) => path,
+            None => {
+                let name = text(&header[..NAME_LEN])?;
+                // Only POSIX ustar has a prefix there; GNU tar keeps times in it
+                let prefix = if &header[257..263] == b"ustar\0" { text(&header[345..500])? } else { String::new() };
+                if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+            }
+        };
+        let kind = match typeflag {
+            b'0' | 0 => Kind::File,
+            b'5' => Kind::Dir,
+            b'1' => return Err(anyhow!("Tar member {:?} is a hard link", path)),
+            b'2' => return Err(anyhow!("Tar member {:?} is a symlink", path)),
+            other => return Err(anyhow!("Tar member {:?} is of unsupported type {:?}", path, other as char)),
+        };
+        let member = Member { path, kind, mode: octal(&header[100..108])? as u32 & 0o7777, size };
+        let mut data = (&mut reader).take(size);
+        each(&member, &mut data)?;
+        // Whatever `each` left unread
+        io::copy(&mut data, &mut io::sink())?;
+        if data.limit() > 0 {
+            return Err(anyhow!("Tar archive ends in the middle of {}", member.path));
+        }
+        skip_padding(&mut reader, size)?;
+    }
+}
+
+/// Writes a tar archive of plain files, in the subset `read` takes
+pub struct Builder<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Builder<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+    
+    fn header(&mut self, name: &[u8], mode: u32, size: u64, typeflag: u8) -> Result<()> {
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name);
+        header[100..107].copy_from_slice(format!("{:07o}", mode).as_bytes());
+        header[108..115].copy_from_slice(b"0000000");
+        header[116..123].copy_from_slice(b"0000000");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[136..147].copy_from_slice(b"00000000000");
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        let sum = checksum(&header);
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header[155] = b' ';
+        self.out.write_all(&header)?;
+        Ok(())
+    }
+    
+    fn pad(&mut self, len: u64) -> Result<()> {
+        let padding = (BLOCK as u64 - len % BLOCK as u64) % BLOCK as u64;
+        self.out.write_all(&vec![0u8; padding as usize])?;
+        Ok(())
+    }
+    
+    /// Add a file of `size` bytes read from `data`, as `path`
+    pub fn append_file(&mut self, path: &str, mode: u32, size: u64, data: &mut impl Read) -> Result<()> {
+        if size >= 1 << 33 {
+            return Err(anyhow!("{} is too big to bundle", path));
+        }
+        let name = path.as_bytes();
+        if name.len() > NAME_LEN {
+            // GNU long name: the real name is the data of an 'L' entry before it
+            let mut long = name.to_vec();
+            long.push(0);
+            self.header(b"././@LongLink", 0o644, long.len() as u64, b'L')?;
+            self.out.write_all(&long)?;
+            self.pad(long.len() as u64)?;
+            self.header(&name[..NAME_LEN], mode, size, b'0')?;
+        } else {
+            self.header(name, mode, size, b'0')?;
+        }
+        let copied = io::copy(&mut data.take(size), &mut self.out)?;
+        if copied != size {
+            return Err(anyhow!("{} changed while it was being bundled", path));
+        }
+        self.pad(size)
+    }
+    
+    /// Write the end marker and hand back the output
+    pub fn finish(mut self) -> Result<W> {
+        self.out.write_all(&[0u8; BLOCK * 2])?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for (path, data) in files {
+            builder.append_file(path, 0o644, data.len() as u64, &mut &data[..]).unwrap();
+        }
+        builder.finish().unwrap()
+    }
+    
+    fn members(bytes: &[u8]) -> Result<Vec<(Member, Vec<u8>)>> {
+        let mut found = Vec::new();
+        read(bytes, |member, data| {
+            let mut content = Vec::new();
+            data.read_to_end(&mut content)?;
+            found.push((member.clone(), content));
+            Ok(())
+        })?;
+        Ok(found)
+    }
+    
+    #[test]
+    fn reads_back_what_it_wrote() {
+        let long = format!("cache/faster-whisper/{}/model.bin", "snapshots/".repeat(12));
+        let bytes = archive(&[("a.json", b"{}"), (&long, &[7u8; 1000]), ("empty", b"")]);
+        let found = members(&bytes).unwrap();
+        let paths: Vec<&str> = found.iter().map(|(member, _)| member.path.as_str()).collect();
+        assert_eq!(paths, ["a.json", long.as_str(), "empty"]);
+        assert_eq!(found[1].1, vec![7u8; 1000]);
+        assert!(found.iter().all(|(member, _)| member.kind == Kind::File && member.mode == 0o644));
+    }
+    
+    /// A header like GNU tar writes for `typeflag`, with a valid checksum
+    fn raw_header(name: &str, typeflag: u8, size: u64) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        builder.header(name.as_bytes(), 0o777, size, typeflag).unwrap();
+        builder.out
+    }
+    
+    #[test]
+    fn refuses_links_and_special_files() {
+        for (typeflag, what) in [(b'1', "hard link"), (b'2', "symlink"), (b'3', "unsupported"), (b'6', "unsupported")] {
+            let mut bytes = raw_header("config/whisp-away/x", typeflag, 0);
+            bytes.extend_from_slice(&[0u8; BLOCK * 2]);
+            let error = members(&bytes).unwrap_err().to_string();
+            assert!(error.contains(what), "{}: {}", typeflag as char, error);
+        }
+    }
+    
+    #[test]
+    fn reads_directories_and_pax_paths() {
+        let mut bytes = raw_header("config/whisp-away/", b'5', 0);
+        let body = "path=config/whisp-away/a.txt\n";
+        let record = format!("{} {}", body.len() + 3, body);
+        bytes.extend(raw_header("PaxHeaders/a.txt", b'x', record.len() as u64));
+        bytes.extend_from_slice(record.as_bytes());
+        bytes.extend_from_slice(&vec![0u8; BLOCK - record.len()]);
+        bytes.extend(raw_header("a.txt", b'0', 2));
+        bytes.extend_from_slice(b"hi");
+        bytes.extend_from_slice(&vec![0u8; BLOCK - 2]);
+        bytes.extend_from_slice(&[0u8; BLOCK * 2]);
+        let found = members(&bytes).unwrap();
+        assert_eq!(found[0].0.kind, Kind::Dir);
+        assert_eq!(found[1].0.path, "config/whisp-away/a.txt");
+        assert_eq!(found[1].1, b"hi");
+    }
+    
+    #[test]
+    fn refuses_corrupt_and_truncated_archives() {
+        let mut bytes = archive(&[("a", b"data")]);
+        bytes[0] = b'b';
+        assert!(members(&bytes).unwrap_err().to_string().contains("checksum"));
+        let bytes = archive(&[("a", &[1u8; 2000])]);
+        assert!(members(&bytes[..BLOCK * 2]).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use crate::audio::{Buffer, Pipeline, Stage};
+use crate::protocol::{Priority, Segment, TranscriptionRequest, TranscriptionResponse};
+use crate::socket;
+use crate::subtitles::{self, ConfidenceStyle, OutputFormat};
+use crate::wav;
+
+/// Shortest input worth sending to whisper (0.1 s at 16 kHz)
+const MIN_SAMPLES: usize = 1600;
+
+/// Encoding of the audio given to `transcribe`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// WAV file with any common rate/sample format
+    Wav,
+    /// FLAC file, like a compressed kept recording (needs the flac feature)
+    Flac,
+    /// Headerless s16le mono PCM at --rate
+    Raw,
+}
+
+/// Failures of `transcribe` that map to their own exit codes
+#[derive(Debug)]
+pub enum TranscribeError {
+    /// Input was empty, too short or not decodable (exit code 2)
+    InvalidInput(String),
+    /// Transcription worked but produced no text (exit code 3)
+    NoSpeech,
+}
+
+impl TranscribeError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TranscribeError::InvalidInput(_) => 2,
+            TranscribeError::NoSpeech => 3,
+        }
+    }
+}
+
+impl fmt::Display for TranscribeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscribeError::InvalidInput(reason) => write!(f, "Invalid audio input: {}", reason),
+            TranscribeError::NoSpeech => write!(f, "No speech detected"),
+        }
+    }
+}
+
+impl std::error::Error for TranscribeError {}
+
+fn invalid(reason: impl Into<String>) -> anyhow::Error {
+    TranscribeError::InvalidInput(reason.into()).into()
+}
+
+/// Decode the input into 16 kHz mono samples
+fn decode_input(bytes: &[u8], format: InputFormat, rate: u32) -> Result<Vec<f32>> {
+    if bytes.is_empty() {
+        return Err(invalid("no audio data received"));
+    }
+    
+    let samples = match format {
+        InputFormat::Wav => Pipeline::target().samples(bytes).map_err(|e| invalid(e.to_string()))?,
+        InputFormat::Flac => {
+            if !crate::flac::is_flac(bytes) {
+                return Err(invalid("not a FLAC stream"));
+            }
+            Pipeline::target().samples(bytes).map_err(|e| invalid(e.to_string()))?
+        }
+        InputFormat::Raw => {
+            if rate == 0 {
+                return Err(invalid("--rate must be greater than 0"));
+            }
+            if !bytes.len().is_multiple_of(2) {
+                return Err(invalid(format!("{} bytes is not a whole number of s16le samples", bytes.len())));
+            }
+            let samples: Vec<f32> = bytes
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+                .collect();
+            Pipeline::target().run(Buffer::mono(rate, samples)).buffer.into_samples()
+        }
+    };
+    
+    if samples.len() < MIN_SAMPLES {
+        return Err(invalid(format!(
+            "only {} ms of audio, need at least {} ms",
+            samples.len() * 1000 / wav::TARGET_RATE as usize,
+            MIN_SAMPLES * 1000 / wav::TARGET_RATE as usize
+        )));
+    }
+    Ok(samples)
+}
+
+/// Transcribe a file via the backend's direct path, used when no daemon is running
+pub(crate) fn transcribe_direct(
+    backend: &str,
+    audio_file: &str,
+    model: Option<String>,
+    language: &str,
+    prompt: Option<&str>,
+) -> Result<String> {
+    let model = crate::helpers::resolve_model(model, None);
+    match backend {
+        "whisper-cpp" => crate::whisper_cpp::direct::transcribe_audio(audio_file, &model, language, prompt).map(|(text, _, _)| text),
+        "faster-whisper" => crate::faster_whisper::direct::transcribe_audio(audio_file, &model, language, prompt).map(|(text, _)| text),
+        #[cfg(feature = "mock")]
+        "mock" => crate::mock::direct::transcribe_direct(audio_file),
+        unknown => Err(anyhow!("Unknown backend: {}", unknown)),
+    }
+}
+
+/// Where and how to transcribe, as given on the command line
+pub struct TranscribeOptions {
+    pub backend: String,
+    pub model: Option<String>,
+    pub language: String,
+    /// `--prompt-file`, over the focused application's glossary
+    pub prompt_file: Option<String>,
+    pub socket_path: String,
+    pub allow_long: bool,
+    pub priority: Priority,
+}
+
+/// Read the whole input, from a file or stdin, decrypting a kept recording
+fn read_input(input: Option<&str>) -> Result<Vec<u8>> {
+    let bytes = match input {
+        Some(path) => fs::read(path).with_context(|| format!("Failed to read {}", path))?,
+        None => {
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes).context("Failed to read stdin")?;
+            bytes
+        }
+    };
+    crate::crypt::open(bytes).with_context(|| format!("Failed to decrypt {}", input.unwrap_or("stdin")))
+}
+    
+/// A transcription with what the daemon told about it
+struct Transcript {
+    text: String,
+    language: Option<String>,
+    // Only filled in when asked for, and by daemons that return them
+    segments: Vec<Segment>,
+}
+
+/// Transcribe 16 kHz mono samples through the daemon, or directly if none is
+/// running, and return the normalized text
+pub(crate) fn transcribe_samples(samples: &[f32], options: &TranscribeOptions, prompt: Option<String>) -> Result<String> {
+    transcribe_segments(samples, options, prompt, false).map(|transcript| transcript.text)
+}
+
+/// Like transcribe_samples, asking the daemon for the transcript's segments
+/// too when `segments` is set
+fn transcribe_segments(samples: &[f32], options: &TranscribeOptions, prompt: Option<String>, segments: bool) -> Result<Transcript> {
+    // The daemon protocol only takes file paths, so spool the normalized audio
+    let audio_file = crate::recording::spool_file(&format!("voice-transcribe-{}.wav", std::process::id()));
+    fs::write(&audio_file, wav::encode_s16_mono(samples, wav::TARGET_RATE))
+        .context("Failed to write temporary audio file")?;
+    
+    let mut request = TranscriptionRequest::new(&audio_file, &options.language);
+    request.allow_long = options.allow_long;
+    request.priority = options.priority;
+    request.initial_prompt = prompt;
+    request.segments = segments;
+    
+    let result = match socket::request_transcription(&options.socket_path, &request) {
+        Ok(Some(TranscriptionResponse { success: true, text: Some(text), language, segments, .. })) => {
+            Ok(Transcript { text, language, segments: subtitles::clean(segments) })
+        }
+        Ok(Some(TranscriptionResponse { error, .. })) => {
+            Err(anyhow!("Daemon failed to transcribe: {}", error.unwrap_or_else(|| "unknown error".to_string())))
+        }
+        Ok(None) => Err(anyhow!("Could not parse daemon response")),
+        Err(e) => {
+            eprintln!("DEBUG: {}, transcribing directly", e);
+            let prompt = request.initial_prompt.as_deref();
+            transcribe_direct(&options.backend, &audio_file, options.model.clone(), &options.language, prompt)
+                .map(|text| Transcript { text, language: None, segments: Vec::new() })
+        }
+    };
+    let _ = fs::remove_file(&audio_file);
+    
+    let transcript = result?;
+    Ok(Transcript { text: crate::normalize::transcript(&transcript.text), ..transcript })
+}
+
+/// Transcribe audio from a file or stdin and print it to stdout as text,
+/// subtitles or JSON, for scripting
+pub fn run(input: Option<&str>, format: InputFormat, rate: u32, output: OutputFormat, style: ConfidenceStyle, options: TranscribeOptions) -> Result<()> {
+    let bytes = read_input(input)?;
+    let samples = decode_input(&bytes, format, rate)?;
+    
+    let restore_punctuation = crate::punctuation::enabled(None);
+    let Transcript { text, language, mut segments } =
+        transcribe_segments(&samples, &options, crate::prompt::current(options.prompt_file.as_deref(), None), output != OutputFormat::Text || restore_punctuation)?;
+    let text = if restore_punctuation { crate::punctuation::apply(text, &segments) } else { text };
+    if text.is_empty() {
+        return Err(TranscribeError::NoSpeech.into());
+    }
+    if segments.is_empty() && output != OutputFormat::Text {
+        // No daemon, or one that doesn't segment: the whole input is one cue
+        segments.push(Segment {
+            start_ms: 0,
+            end_ms: wav::sample_time(samples.len()).as_millis() as u64,
+            text: text.clone(),
+            confidence: None,
+        });
+    }
+    match output {
+        OutputFormat::Text => println!("{}", text),
+        OutputFormat::Srt => print!("{}", subtitles::render_srt(&segments, &style)),
+        OutputFormat::Vtt => print!("{}", subtitles::render_vtt(&segments, &style)),
+        OutputFormat::Json => println!("{}", subtitles::render_json(&text, language.as_deref(), &segments)?),
+    }
+    Ok(())
+}
+
+/// Who is speaking on each channel of a `stereo` recording: the mic on the
+/// left, everyone else (the monitor of the call) on the right
+const CHANNEL_SPEAKERS: [&str; 2] = ["Me", "Them"];
+
+/// Render a conversation as `Me: ...` / `Them: ...` lines, one per change of speaker
+fn render_text(turns: &[wav::Turn]) -> String {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for turn in turns {
+        match lines.last_mut() {
+            Some((channel, text)) if *channel == turn.channel => {
+                text.push(' ');
+                text.push_str(&turn.text);
+            }
+            _ => lines.push((turn.channel, turn.text.clone())),
+        }
+    }
+    lines
+        .iter()
+        .map(|(channel, text)| format!("{}: {}", CHANNEL_SPEAKERS[*channel], text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render a conversation as SRT subtitles, one cue per utterance
+fn render_srt(turns: &[wav::Turn]) -> String {
+    turns
+        .iter()
+        .enumerate()
+        .map(|(i, turn)| {
+            format!(
+                "{}\n{} --> {}\n{}: {}\n",
+                i + 1,
+                subtitles::srt_time(turn.start),
+                subtitles::srt_time(turn.end),
+                CHANNEL_SPEAKERS[turn.channel],
+                turn.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Transcribe a two-channel recording one channel at a time and print it as a
+/// conversation. Each channel is cut into utterances, which go to the backend
+/// one after another and are put back in order by when they started.
+pub fn run_diarized(input: Option<&str>, srt: bool, options: TranscribeOptions) -> Result<()> {
+    let bytes = read_input(input)?;
+    if bytes.is_empty() {
+        return Err(invalid("no audio data received"));
+    }
+    let channels = Buffer::decode(&bytes)
+        .map(|buffer| Pipeline::new().then(Stage::Resample(wav::TARGET_RATE)).run(buffer).buffer.channels)
+        .map_err(|e| invalid(e.to_string()))?;
+    if channels.len() != CHANNEL_SPEAKERS.len() {
+        return Err(invalid(format!(
+            "--diarize-channels needs a 2-channel recording (mic left, monitor right), this one has {}",
+            channels.len()
+        )));
+    }
+    
+    let prompt = crate::prompt::current(options.prompt_file.as_deref(), None);
+    let mut turns = Vec::new();
+    for (channel, samples) in channels.iter().enumerate() {
+        let spans = wav::speech_spans(samples);
+        eprintln!("DEBUG: {} utterance(s) on the {} channel", spans.len(), CHANNEL_SPEAKERS[channel]);
+        for span in spans {
+            let text = transcribe_samples(&samples[span.clone()], &options, prompt.clone())?;
+            if text.is_empty() {
+                continue;
+            }
+            turns.push(wav::Turn {
+                channel,
+                start: wav::sample_time(span.start),
+                end: wav::sample_time(span.end),
+                text,
+            });
+        }
+    }
+    
+    let turns = wav::interleave(turns);
+    if turns.is_empty() {
+        return Err(TranscribeError::NoSpeech.into());
+    }
+    if srt {
+        print!("{}", render_srt(&turns));
+    } else {
+        println!("{}", render_text(&turns));
+    }
+    Ok(())
+}
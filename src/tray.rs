@@ -1,13 +1,19 @@
 use anyhow::{Context, Result};
-use ksni::{menu::StandardItem, MenuItem, Tray, TrayService};
+use ksni::{menu::{CheckmarkItem, Disposition, StandardItem, SubMenu}, MenuItem, Tray, TrayService};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::net::UnixStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::process::{Child, Command, Stdio};
-use crate::helpers::{TrayState, write_tray_state};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, Stdio};
+use crate::helpers::{OutputMode, RotatingWriter, TrayState, write_tray_state};
+use crate::child_env;
+use crate::daemons;
+use crate::notify;
+use crate::protocol::DaemonEvent;
+use crate::ready;
+use crate::socket::Subscription;
+use crate::recording::{self, Sensitivity};
 
 // Embed icon files
 const ICON_OFF: &[u8] = include_bytes!("../assets/mic-off.png");
@@ -44,11 +50,180 @@ fn png_to_argb32(png_bytes: &[u8]) -> Result<ksni::Icon> {
     })
 }
 
+/// Daemon logs rotate at this size
+const DAEMON_LOG_MAX_BYTES: u64 = 1024 * 1024;
+/// Number of daemon log files kept (live file plus rotated generations)
+const DAEMON_LOG_FILES: usize = 3;
+
+/// How long a freshly spawned daemon gets to write its ready file
+/// (loading a large model can take a while) before it's assumed up anyway
+const DAEMON_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a stopped daemon gets to exit on SIGTERM before it's killed
+const DAEMON_STOP_GRACE: Duration = Duration::from_secs(1);
+
+/// How often the daemon's ready file is checked for a model that stopped working
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wait before subscribing to the daemon's events again, doubling after
+/// every failed attempt up to the max
+const SUBSCRIBE_RETRY_MIN: Duration = Duration::from_secs(1);
+const SUBSCRIBE_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// Why the daemon with `ready_file` can't transcribe any more, if it can't
+/// The daemon's ready file without its queue, which changes with every request;
+/// any other change (a restart, a rebuilt state, a broken model) means a redraw
+fn daemon_snapshot(ready_file: &str) -> Option<ready::ReadyInfo> {
+    let info = ready::read(ready_file)?;
+    // Memory use changes all the time; only an unloaded model is worth a redraw
+    let memory = info.memory.filter(|memory| memory.unloaded);
+    Some(ready::ReadyInfo { queue: None, memory, ..info })
+}
+
+/// The daemon just unloaded its model for lack of memory: what to tell the user
+fn unloaded_notice(last: Option<&ready::ReadyInfo>, now: Option<&ready::ReadyInfo>) -> Option<notify::Notification> {
+    let was_unloaded = last.and_then(|info| info.memory.as_ref()).is_some();
+    let memory = now?.memory.as_ref()?;
+    if was_unloaded {
+        return None;
+    }
+    Some(notify::Notification::normal("Voice Input", &format!(
+        "⚠️ Model unloaded to free memory\n{}\nIt is loaded again on the next dictation once memory recovers",
+        memory
+    ))
+        .timeout(10000)
+        .tag(None))
+}
+
+/// What the tray last saw of its daemon, shared by the health poll and the
+/// event subscription
+#[derive(Default)]
+struct DaemonWatch {
+    last: Option<ready::ReadyInfo>,
+    /// Events arrive, so the poll leaves the ready file to the subscription
+    subscribed: bool,
+}
+
+/// Read the daemon's ready file again, telling the user if the model was just
+/// unloaded. Returns whether anything the tray shows changed.
+fn resync_snapshot(shared: &Mutex<TrayShared>, watch: &Mutex<DaemonWatch>) -> bool {
+    let snapshot = daemon_snapshot(&shared.lock().unwrap().ready_file());
+    let mut watch = watch.lock().unwrap();
+    if let Some(notice) = unloaded_notice(watch.last.as_ref(), snapshot.as_ref()) {
+        notice.send();
+    }
+    let changed = snapshot != watch.last;
+    watch.last = snapshot;
+    changed
+}
+
+/// Keep a subscription to the running daemon's events open and redraw from
+/// them, subscribing again with backoff whenever it drops. Each new
+/// subscription starts from the ready file, for what happened in between.
+fn follow_daemon_events(shared: Arc<Mutex<TrayShared>>, watch: Arc<Mutex<DaemonWatch>>, handle: ksni::Handle<VoiceInputTray>) {
+    let set_processing = |processing: bool| {
+        shared.lock().unwrap().status.processing = processing;
+        handle.update(|_| {});
+    };
+    let mut backoff = SUBSCRIBE_RETRY_MIN;
+    let mut last_error = None;
+    loop {
+        let (running, socket_path, ready_file) = {
+            let shared = shared.lock().unwrap();
+            (shared.status.running, shared.socket_path(), shared.ready_file())
+        };
+        if !running {
+            backoff = SUBSCRIBE_RETRY_MIN;
+            std::thread::sleep(SUBSCRIBE_RETRY_MIN);
+            continue;
+        }
+        
+        match Subscription::open(&socket_path) {
+            Ok(mut subscription) => {
+                eprintln!("DEBUG: Subscribed to daemon events on {}", socket_path);
+                backoff = SUBSCRIBE_RETRY_MIN;
+                last_error = None;
+                // Requests the model is on; the fast model runs next to the main one
+                let mut active = ready::read(&ready_file)
+                    .and_then(|info| info.queue)
+                    .map_or(0, |queue| queue.active.is_some() as usize);
+                watch.lock().unwrap().subscribed = true;
+                resync_snapshot(&shared, &watch);
+                set_processing(active > 0);
+                loop {
+                    match subscription.next() {
+                        Ok(Some(DaemonEvent::ProcessingStarted)) => active += 1,
+                        Ok(Some(DaemonEvent::ProcessingDone { .. })) => active = active.saturating_sub(1),
+                        Ok(Some(DaemonEvent::ModelReloaded { .. } | DaemonEvent::LowMemory { .. } | DaemonEvent::DeviceFallback { .. })) => {
+                            resync_snapshot(&shared, &watch);
+                        }
+                        Ok(Some(DaemonEvent::Subscribed | DaemonEvent::Other)) => continue,
+                        Ok(Some(DaemonEvent::ShuttingDown) | None) => break,
+                        Err(e) => {
+                            eprintln!("DEBUG: Lost the daemon event subscription: {:#}", e);
+                            break;
+                        }
+                    }
+                    set_processing(active > 0);
+                }
+                eprintln!("DEBUG: Daemon event subscription ended, subscribing again in {:?}", backoff);
+                watch.lock().unwrap().subscribed = false;
+                set_processing(false);
+            }
+            Err(e) => {
+                // A daemon without events (faster-whisper) would log this every time
+                let error = format!("{:#}", e);
+                if last_error.as_ref() != Some(&error) {
+                    eprintln!("DEBUG: Could not subscribe to daemon events ({}), polling its ready file meanwhile", error);
+                    last_error = Some(error);
+                }
+            }
+        }
+        std::thread::sleep(backoff);
+        backoff = (backoff * 2).min(SUBSCRIBE_RETRY_MAX);
+    }
+}
+
+fn open_log(log_path: &str) {
+    if let Err(e) = child_env::command("xdg-open", &[]).arg(log_path).spawn() {
+        eprintln!("Failed to open daemon log: {}", e);
+    }
+}
+
+/// Copy a child's output stream line by line into the shared log
+fn forward_to_log<R: Read + Send + 'static>(stream: Option<R>, log: Arc<Mutex<RotatingWriter>>) {
+    let Some(stream) = stream else { return };
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut line) {
+            if n == 0 {
+                break;
+            }
+            if let Ok(mut writer) = log.lock() {
+                let _ = writer.write_all(&line);
+            }
+            line.clear();
+        }
+    });
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct DaemonStatus {
     running: bool,
     model: String,
+    language: String,
+    /// Shared with hotkey-triggered clients through the state file
+    output_mode: OutputMode,
+    /// Profile picked from the Profiles menu, shared through the state file too
+    profile: Option<String>,
+    /// Recording settings menu, read by clients from the state file at stop
+    sensitivity: Sensitivity,
+    vad: bool,
+    auto_stop: bool,
     processing: bool,
+    /// A `start` is recording, as of the last look at the pidfile
+    recording: bool,
 }
 
 impl Default for DaemonStatus {
@@ -56,35 +231,425 @@ impl Default for DaemonStatus {
         // Use resolve_model to get the initial model (respects env vars)
         Self {
             running: false,
-            model: crate::helpers::resolve_model(None),
+            model: crate::helpers::resolve_model(None, None),
+            language: crate::helpers::resolve_language(None, None),
+            output_mode: crate::helpers::resolve_output_mode(None, None),
+            profile: crate::profile::active_name(None),
+            sensitivity: recording::sensitivity(),
+            vad: recording::vad_enabled(),
+            auto_stop: recording::auto_stop_enabled(),
             processing: false,
+            recording: false,
+        }
+    }
+}
+
+/// Recording settings for the tooltip, e.g. "strict, VAD, auto-stop"
+fn recording_label(status: &DaemonStatus) -> String {
+    let mut parts = vec![status.sensitivity.to_string()];
+    if status.vad {
+        parts.push("VAD".to_string());
+    }
+    if status.auto_stop {
+        parts.push("auto-stop".to_string());
+    }
+    parts.join(", ")
+}
+
+/// A backend as named in the menu and tooltip
+fn backend_display(daemon_type: &str) -> &'static str {
+    match daemon_type {
+        "faster-whisper" => "Faster Whisper",
+        "mock" => "Mock",
+        _ => "Whisper.cpp",
+    }
+}
+
+/// One line of the tray's status, both a menu item and a tooltip line
+struct StatusLine {
+    text: String,
+    /// Something the user has to see to, marked as a warning in the menu
+    warning: bool,
+    /// Log the menu item opens, instead of refreshing the status
+    log: Option<String>,
+}
+
+impl StatusLine {
+    fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into(), warning: false, log: None }
+    }
+    
+    fn warning(text: impl Into<String>) -> Self {
+        Self { text: text.into(), warning: true, log: None }
+    }
+}
+
+/// `text` in a menu label, where a single underscore marks the access key:
+/// model paths and profile names keep theirs
+fn escape_mnemonics(text: &str) -> String {
+    text.replace('_', "__")
+}
+
+/// Menu labels as shown, through the same text rendering as notifications
+/// (no emoji under WA_NO_EMOJI)
+fn render_labels<T>(items: &mut [MenuItem<T>]) {
+    for item in items {
+        match item {
+            MenuItem::Standard(item) => item.label = notify::text(&item.label),
+            MenuItem::Checkmark(item) => item.label = notify::text(&item.label),
+            MenuItem::SubMenu(menu) => {
+                menu.label = notify::text(&menu.label);
+                render_labels(&mut menu.submenu);
+            }
+            MenuItem::RadioGroup(_) | MenuItem::Separator => {}
+        }
+    }
+}
+
+/// What's on disk for the selected model, shown in the tooltip
+#[derive(Debug, Clone)]
+struct ModelInfo {
+    daemon_type: String,
+    model: String,
+    missing: bool,
+    /// Size, download date and OpenVINO encoder presence, when known
+    details: Option<String>,
+}
+
+impl ModelInfo {
+    fn probe(daemon_type: &str, model: &str) -> Self {
+        let mut info = Self {
+            daemon_type: daemon_type.to_string(),
+            model: model.to_string(),
+            missing: false,
+            details: None,
+        };
+        
+        // Only whisper.cpp models are single files we can inspect; faster-whisper
+        // downloads its models on first use
+        if daemon_type != "whisper-cpp" {
+            return info;
+        }
+        
+        let model_path = crate::helpers::whisper_cpp_model_path(model);
+        let metadata = match std::fs::metadata(&model_path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                info.missing = true;
+                return info;
+            }
+        };
+        
+        let mut details = vec![format!("{} MB", metadata.len() / 1_000_000)];
+        if let Ok(format) = crate::whisper_cpp::model::validate_model(&model_path, model) {
+            details.push(format.to_string());
+        }
+        if let Ok(modified) = metadata.modified() {
+            let secs = modified.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+            let timestamp = crate::helpers::format_local_time(secs as libc::time_t);
+            details.push(timestamp[..10].to_string());
+        }
+        let openvino_encoder = crate::whisper_cpp::model::openvino_encoder_path(&model_path);
+        if Path::new(&openvino_encoder).exists() {
+            details.push("OpenVINO encoder".to_string());
+        }
+        info.details = Some(details.join(", "));
+        info
+    }
+    
+    fn label(&self) -> String {
+        let name = crate::helpers::model_display_name(&self.model);
+        if self.missing && crate::helpers::is_model_path(&self.model) {
+            format!("{} (file not found!)", name)
+        } else if self.missing {
+            format!("{} (not downloaded!)", name)
+        } else if let Some(details) = &self.details {
+            format!("{} ({})", name, details)
+        } else {
+            name
         }
     }
 }
 
+/// State the menu handlers and the watcher thread share, behind a single
+/// lock. It's only held to read or publish: starting, stopping and switching
+/// snapshot what they need, wait on the daemon without it and lock again to
+/// record the outcome. Nothing else is locked while it is held.
 #[derive(Debug)]
-struct VoiceInputTray {
-    status: Arc<Mutex<DaemonStatus>>,
+struct TrayShared {
     daemon_type: String, // "faster-whisper" or "whisper-cpp"
-    daemon_process: Arc<Mutex<Option<Child>>>, // The actual daemon process
+    status: DaemonStatus,
+    daemon_process: Option<Child>, // The actual daemon process
+}
+
+/// Notifications queued during a start, stop or switch, sent once it's done
+type Notices = Vec<notify::Notification>;
+
+/// A daemon to bring up, as the shared state had it when asked
+#[derive(Debug)]
+struct Launch {
+    daemon_type: String,
+    model: String,
+    language: String,
+    socket_path: String,
+    ready_file: String,
+}
+
+/// Spawns the daemon process for a launch; tests swap in one that needs
+/// neither a model nor our binary
+type Spawner = fn(&Launch, &mut Notices) -> Result<Child>;
+
+/// Run `whisp-away daemon` for the launch in its own process group, fetching
+/// a missing whisper.cpp model first and logging to the backend's daemon log
+fn spawn_daemon(launch: &Launch, notices: &mut Notices) -> Result<Child> {
+    let daemon_type = launch.daemon_type.as_str();
+    let model = launch.model.as_str();
+    let home = std::env::var("HOME").unwrap_or_default();
+    
+    // Get the path to our own binary
+    let binary_path = std::env::current_exe()
+        .context("Failed to get current executable path")?;
+    
+    // Build the daemon command
+    let mut cmd = child_env::command(&binary_path, child_env::MODEL_ENV);
+    cmd.arg("daemon")
+       .arg("--backend")
+       .arg(daemon_type)
+       .arg("--model")
+       .arg(model)
+       .arg("--language")
+       .arg(&launch.language)
+       .arg("--ready-file")
+       .arg(&launch.ready_file)
+       .arg("--socket-path")
+       .arg(&launch.socket_path);
+    
+    if daemon_type == "faster-whisper" {
+        // Faster-whisper specific environment
+        cmd.env("WA_WHISPER_SOCKET", &launch.socket_path);
+        
+        // Device and compute type for faster-whisper
+        if std::env::var("CUDA_VISIBLE_DEVICES").is_ok() {
+            cmd.env("WHISPER_DEVICE", "cuda");
+            cmd.env("WHISPER_COMPUTE", "float16");
+        } else {
+            cmd.env("WHISPER_DEVICE", "cpu");
+            cmd.env("WHISPER_COMPUTE", "int8");
+        }
+    } else if daemon_type == "mock" {
+        // Mock daemon needs no model, just the socket
+    } else {
+        // Whisper.cpp specific - set model path
+        let model_path = crate::helpers::whisper_cpp_model_path(model);
+        
+        // A model file that isn't there can't be downloaded
+        if crate::helpers::is_model_path(model) && !std::path::Path::new(&model_path).exists() {
+            notices.push(notify::Notification::error("Voice Input", &format!("⚠️ Model file {} not found", model_path))
+                .timeout(10000)
+                .tag(None)
+                .urgent());
+            eprintln!("Warning: Model file {} not found", model_path);
+        } else if !std::path::Path::new(&model_path).exists() {
+            // Check if model exists, if not try to download it
+            println!("Model {} not found, attempting to download...", model);
+            
+            // Try to run download-whisper-model if available
+            match crate::whisper_cpp::model::download_model(model, &model_path) {
+                Ok(_) => {
+                    println!("Model downloaded successfully");
+                }
+                Err(_) => {
+                    // Send notification about missing model
+                    notices.push(notify::Notification::error("Voice Input", &format!("⚠️ Model {} not found. Please download it manually:\ndownload-whisper-model {}", model, model))
+                        .timeout(10000)
+                        .tag(None)
+                        .urgent());
+                    
+                    eprintln!("Warning: Model {} not found and couldn't download", model);
+                    // Continue anyway - daemon will fail if model is really needed
+                }
+            }
+        }
+        
+        cmd.env("WHISPER_CPP_MODEL_PATH", &model_path);
+    }
+    
+    // Common environment variables
+    cmd.env("HOME", &home);
+    cmd.env("WA_WHISPER_MODEL", model);
+    
+    // Ensure cache directories exist
+    let cache_base = crate::helpers::cache_dir();
+    std::fs::create_dir_all(format!("{}/whisp-away", cache_base)).ok();
+    std::fs::create_dir_all(format!("{}/faster-whisper", cache_base)).ok();
+    std::fs::create_dir_all(crate::helpers::whisper_cpp_models_dir()).ok();
+    
+    // Route output through a rotating log so previous runs survive a restart
+    let log_path = crate::helpers::daemon_log_path(daemon_type);
+    let log = match RotatingWriter::open(&log_path, DAEMON_LOG_MAX_BYTES, DAEMON_LOG_FILES) {
+        Ok(mut writer) => {
+            let _ = writeln!(writer, "=== daemon started {} ({}, model {}) ===",
+                crate::helpers::local_timestamp(), daemon_type, model);
+            Some(Arc::new(Mutex::new(writer)))
+        }
+        Err(e) => {
+            eprintln!("Warning: Failed to open daemon log {}: {}", log_path, e);
+            None
+        }
+    };
+    
+    if log.is_some() {
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+    } else {
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::null());
+    }
+    
+    // Spawn the daemon process in its own process group
+    // This allows us to kill the entire group later
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);  // Create new process group
+    
+    let mut child = cmd.spawn()
+        .context("Failed to spawn daemon process")?;
+    
+    if let Some(log) = log {
+        forward_to_log(child.stdout.take(), Arc::clone(&log));
+        forward_to_log(child.stderr.take(), log);
+    }
+    Ok(child)
+}
+
+impl TrayShared {
+    fn tray_state(&self) -> TrayState {
+        TrayState {
+            model: self.status.model.clone(),
+            backend: self.daemon_type.clone(),
+            language: Some(self.status.language.clone()),
+            output_mode: Some(self.status.output_mode),
+            profile: self.status.profile.clone(),
+            sensitivity: Some(self.status.sensitivity),
+            vad: Some(self.status.vad),
+            auto_stop: Some(self.status.auto_stop),
+        }
+    }
+    
+    /// Socket of the daemon for the tray's backend. Each backend has its own,
+    /// so a daemon of the other backend isn't the tray's to touch.
+    fn socket_path(&self) -> String {
+        daemons::default_socket(&self.daemon_type)
+    }
+    
+    fn ready_file(&self) -> String {
+        daemons::default_ready_file(&self.daemon_type)
+    }
+    
+    /// Notice a daemon that died on its own: forget it and return how it
+    /// exited, so the tray can stop claiming it's running
+    fn reap_exited_daemon(&mut self) -> Option<std::process::ExitStatus> {
+        let status = match self.daemon_process.as_mut()?.try_wait() {
+            Ok(Some(status)) => status,
+            _ => return None,
+        };
+        self.daemon_process = None;
+        self.status.running = false;
+        self.status.processing = false;
+        Some(status)
+    }
+    
+    /// Look at everything the tray shows again (Refresh status): our daemon
+    /// may have exited, one a previous tray left may be answering on our
+    /// socket, and a recording only shows in the pidfile
+    fn refresh(&mut self) {
+        if let Some(status) = self.reap_exited_daemon() {
+            eprintln!("DEBUG: {} daemon exited ({})", self.daemon_type, status);
+        }
+        match ready::check(&self.ready_file(), &self.socket_path()) {
+            Ok(info) => {
+                self.status.running = true;
+                // Daemons without a queue report processing only in events
+                if let Some(queue) = info.queue {
+                    self.status.processing = queue.active.is_some();
+                }
+                self.status.model = info.model;
+                self.status.language = info.language;
+            }
+            // Ours may still be loading its model
+            Err(e) if self.daemon_process.is_none() => {
+                eprintln!("DEBUG: {}", e);
+                self.status.running = false;
+                self.status.processing = false;
+            }
+            Err(_) => {}
+        }
+        self.status.recording = !recording::active_recorders().is_empty();
+    }
+}
+
+/// Daemons a previous tray left running: on our backend's socket, or on the
+/// socket all trays shared before each backend had its own
+fn leftover_daemons(socket_path: &str, ready_file: &str) -> Vec<ready::ReadyInfo> {
+    [(ready_file.to_string(), socket_path), (daemons::legacy_ready_file(), daemons::LEGACY_SOCKET)]
+        .into_iter()
+        .filter_map(|(ready_file, socket_path)| ready::read(&ready_file).filter(|info| info.socket_path == socket_path))
+        .filter(|info| crate::helpers::is_process_running(info.pid))
+        .collect()
+}
+    
+/// Stop daemons a previous tray left running (the tray died without stopping
+/// them). Daemons on other sockets aren't ours to touch.
+fn stop_orphaned_daemon(socket_path: &str, ready_file: &str) {
+    let leftovers = leftover_daemons(socket_path, ready_file);
+    for info in &leftovers {
+        eprintln!("DEBUG: Stopping orphaned daemon (pid {}) on {}", info.pid, info.socket_path);
+        unsafe {
+            // Trays start daemons in their own process group, which also holds
+            // the Python process for faster-whisper
+            let pgid = libc::getpgid(info.pid as i32);
+            if pgid > 0 && pgid != libc::getpgid(0) {
+                libc::kill(-pgid, libc::SIGTERM);
+            } else {
+                libc::kill(info.pid as i32, libc::SIGTERM);
+            }
+        }
+    }
+    if !leftovers.is_empty() {
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+#[derive(Debug)]
+struct VoiceInputTray {
+    shared: Arc<Mutex<TrayShared>>,
+    /// Taken for a whole start, stop or switch, so they run one at a time
+    lifecycle: Mutex<()>,
+    spawner: Spawner,
+    /// Reads the daemon's ready file; tests hand the tray made-up ones
+    read_ready: fn(&str) -> Option<ready::ReadyInfo>,
+    listen_process: Mutex<Option<Child>>, // Wake word listener, when enabled
+    model_info: Mutex<Option<ModelInfo>>, // Cached; re-probed when backend or model changes
 }
 
 impl Drop for VoiceInputTray {
     fn drop(&mut self) {
-        // Clean up daemon process when tray exits
-        if let Err(e) = self.stop_daemon_process() {
-            eprintln!("Failed to stop daemon on exit: {}", e);
+        // Clean up daemon process when tray exits. One a refresh only found
+        // on the socket wasn't started by this tray and outlives it too.
+        let owned = self.shared.lock().unwrap().daemon_process.is_some();
+        if owned {
+            if let Err(e) = self.stop_daemon() {
+                eprintln!("Failed to stop daemon on exit: {}", e);
+            }
         }
+        self.stop_listening();
     }
 }
 
 impl VoiceInputTray {
-    fn new(daemon_type: String) -> Self {
-        let tray = Self {
-            status: Arc::new(Mutex::new(DaemonStatus::default())),
-            daemon_type,
-            daemon_process: Arc::new(Mutex::new(None)),
-        };
+    /// Model and language given on the command line take precedence over a
+    /// state file left behind by a previous tray
+    fn new(daemon_type: String, model: Option<String>, language: Option<String>) -> Self {
+        let tray = Self::unsaved(daemon_type, model, language);
         
         // Save initial state
         if let Err(e) = tray.save_state() {
@@ -94,362 +659,478 @@ impl VoiceInputTray {
         tray
     }
     
+    /// The tray without writing its state for clients
+    fn unsaved(daemon_type: String, model: Option<String>, language: Option<String>) -> Self {
+        let mut status = DaemonStatus::default();
+        if let Some(model) = model {
+            status.model = model;
+        }
+        if let Some(language) = language {
+            status.language = language;
+        }
+        
+        Self {
+            shared: Arc::new(Mutex::new(TrayShared { daemon_type, status, daemon_process: None })),
+            lifecycle: Mutex::new(()),
+            spawner: spawn_daemon,
+            read_ready: ready::read,
+            listen_process: Mutex::new(None),
+            model_info: Mutex::new(None),
+        }
+    }
+    
+    /// Run a start, stop or switch on its own, then send what it queued. A
+    /// backend switch (stop, switch, start) is one step no click can land in
+    /// the middle of, while the shared state stays free for the menu, the
+    /// watcher and the event thread.
+    fn with_lifecycle<R>(&self, op: impl FnOnce(&mut Notices) -> R) -> R {
+        let (result, notices) = self.in_turn(op);
+        for notice in notices {
+            notice.send();
+        }
+        result
+    }
+    
+    /// Run `op` once no other start, stop or switch is, returning what it queued
+    fn in_turn<R>(&self, op: impl FnOnce(&mut Notices) -> R) -> (R, Notices) {
+        let _turn = self.lifecycle.lock().unwrap();
+        let mut notices = Vec::new();
+        let result = op(&mut notices);
+        (result, notices)
+    }
+    
+    fn daemon_type(&self) -> String {
+        self.shared.lock().unwrap().daemon_type.clone()
+    }
+    
+    fn status(&self) -> DaemonStatus {
+        self.shared.lock().unwrap().status.clone()
+    }
+    
     fn save_state(&self) -> Result<()> {
-        let model = self.status.lock().unwrap().model.clone();
-        let state = TrayState {
-            model,
-            backend: self.daemon_type.clone(),
-        };
+        let state = self.shared.lock().unwrap().tray_state();
         write_tray_state(&state)
     }
+
+    fn start_daemon(&self) -> Result<()> {
+        let result = self.with_lifecycle(|notices| self.bring_up(notices));
+        // The model may have just been downloaded; re-probe it for the tooltip
+        *self.model_info.lock().unwrap() = None;
+        result
+    }
+
+    /// A daemon a previous tray left on our socket may be from before an
+    /// upgrade: restart it with this build (--auto-restart-on-upgrade), or
+    /// suggest doing so
+    fn check_leftover_daemon(&mut self, auto_restart: bool) {
+        let (socket_path, ready_file) = {
+            let shared = self.shared.lock().unwrap();
+            (shared.socket_path(), shared.ready_file())
+        };
+        let Some(info) = leftover_daemons(&socket_path, &ready_file).into_iter().next() else {
+            return;
+        };
+        let Some(daemon) = crate::upgrade::mismatch(info.version.as_deref()) else {
+            return;
+        };
+        if !auto_restart {
+            crate::upgrade::warn_once(info.version.as_deref());
+            return;
+        }
+        
+        eprintln!("DEBUG: Restarting daemon (pid {}) left running by {}", info.pid, daemon);
+        let notice = match self.start_daemon() {
+            Ok(()) => notify::Notification::normal("Voice Input", &format!(
+                "🔄 Restarted the daemon left running by {} with {}",
+                daemon, crate::upgrade::VERSION
+            )),
+            Err(e) => notify::Notification::error("Voice Input", &format!(
+                "❌ Could not restart the daemon left running by {}\n{}",
+                daemon, e
+            )),
+        };
+        notice.timeout(10000).tag(None).send();
+    }
     
-    fn start_daemon_process(&self) -> Result<()> {
-        // First, clean up any orphaned processes from previous runs
-        if self.daemon_type == "faster-whisper" {
-            // Kill any existing Python daemon processes
-            let _ = Command::new("pkill")
-                .args(&["-f", "whisper_daemon.py"])
-                .output();
-            
-            // Remove stale socket file if it exists  
-            std::fs::remove_file("/tmp/whisp-away-daemon.sock").ok();
-        } else {
-            // Remove stale socket file (same path for both backends now)
-            std::fs::remove_file("/tmp/whisp-away-daemon.sock").ok();
-        }
-        
-        // Check if already running
-        if let Ok(mut process_guard) = self.daemon_process.lock() {
-            if let Some(ref mut child) = *process_guard {
-                // Check if process is still running
-                match child.try_wait() {
-                    Ok(Some(_)) => {
-                        // Process has exited, we can start a new one
-                        *process_guard = None;
-                    }
-                    Ok(None) => {
-                        // Still running
-                        return Ok(());
-                    }
-                    Err(_) => {
-                        *process_guard = None;
-                    }
-                }
-            }
-            
-            // Get configuration from current state
-            let model = {
-                let status = self.status.lock().unwrap();
-                status.model.clone()
-            };
-            let socket_path = std::env::var("WA_WHISPER_SOCKET").unwrap_or_else(|_| "/tmp/whisp-away-daemon.sock".to_string());
-            let home = std::env::var("HOME").unwrap_or_default();
-            
-            // Get the path to our own binary
-            let binary_path = std::env::current_exe()
-                .context("Failed to get current executable path")?;
-            
-            // Build the daemon command
-            let mut cmd = Command::new(&binary_path);
-            cmd.arg("daemon")
-               .arg("--backend")
-               .arg(&self.daemon_type)
-               .arg("--model")
-               .arg(&model);
-            
-            // Add socket path for faster-whisper
-            if self.daemon_type == "faster-whisper" {
-                cmd.arg("--socket-path")
-                   .arg(&socket_path);
-                
-                // Faster-whisper specific environment
-                cmd.env("WA_WHISPER_SOCKET", &socket_path);
-                
-                // Device and compute type for faster-whisper
-                if std::env::var("CUDA_VISIBLE_DEVICES").is_ok() {
-                    cmd.env("WHISPER_DEVICE", "cuda");
-                    cmd.env("WHISPER_COMPUTE", "float16");
-                } else {
-                    cmd.env("WHISPER_DEVICE", "cpu");
-                    cmd.env("WHISPER_COMPUTE", "int8");
-                }
+    /// Start the daemon on launch (--autostart-daemon). A failure is reported
+    /// but leaves the tray running so the user can retry from the menu.
+    fn autostart_daemon(&mut self) {
+        if let Err(e) = self.start_daemon() {
+            eprintln!("Failed to autostart daemon: {}", e);
+            notify::Notification::error("Voice Input", &format!("❌ Could not autostart {} daemon\n{}", self.daemon_type(), e))
+                .timeout(10000)
+                .tag(None)
+                .send();
+        }
+    }
+    
+    fn stop_daemon(&self) -> Result<()> {
+        self.with_lifecycle(|notices| self.shut_down(notices))
+    }
+    
+    /// Stop the daemon if it's running, start it otherwise, deciding in the
+    /// same turn that does it
+    fn toggle_daemon(&self) -> Result<()> {
+        let result = self.with_lifecycle(|notices| {
+            if self.status().running {
+                self.shut_down(notices).context("Failed to stop daemon")
             } else {
-                // Whisper.cpp specific - set model path
-                let model_path = format!("{}/.cache/whisper-cpp/models/ggml-{}.bin", home, model);
-                
-                // Check if model exists, if not try to download it
-                if !std::path::Path::new(&model_path).exists() {
-                    println!("Model {} not found, attempting to download...", model);
-                    
-                    // Try to run download-whisper-model if available
-                    let download_result = Command::new("download-whisper-model")
-                        .arg(&model)
-                        .output();
-                    
-                    match download_result {
-                        Ok(output) if output.status.success() => {
-                            println!("Model downloaded successfully");
-                        }
-                        _ => {
-                            // Send notification about missing model
-                            let _ = Command::new("notify-send")
-                                .args(&[
-                                    "Voice Input",
-                                    &format!("⚠️ Model {} not found. Please download it manually:\ndownload-whisper-model {}", model, model),
-                                    "-t", "10000",
-                                    "-u", "critical"
-                                ])
-                                .spawn();
-                            
-                            eprintln!("Warning: Model {} not found and couldn't download", model);
-                            // Continue anyway - daemon will fail if model is really needed
-                        }
-                    }
-                }
-                
-                cmd.env("WHISPER_CPP_MODEL_PATH", &model_path);
+                self.bring_up(notices).context("Failed to start daemon")
             }
-            
-            // Common environment variables
-            cmd.env("HOME", &home);
-            cmd.env("WA_WHISPER_MODEL", &model);
-            
-            // Pass through important environment variables from parent
-            for (key, value) in std::env::vars() {
-                match key.as_str() {
-                    // GPU/CUDA related
-                    "CUDA_VISIBLE_DEVICES" | "CUDA_HOME" | "CUDA_PATH" |
-                    // Library paths
-                    "LD_LIBRARY_PATH" | "LIBRARY_PATH" |
-                    // Python paths (for faster-whisper)
-                    "FASTER_WHISPER_PYTHON" | "FASTER_WHISPER_PYTHONPATH" |
-                    "FASTER_WHISPER_SCRIPT" | "FASTER_WHISPER_DAEMON_SCRIPT" |
-                    // Whisper.cpp paths
-                    "WHISPER_CPP_PATH" |
-                    // Display (might be needed for some operations)
-                    "DISPLAY" | "WAYLAND_DISPLAY" | "XDG_RUNTIME_DIR" => {
-                        cmd.env(key, value);
-                    }
-                    _ => {}
+        });
+        *self.model_info.lock().unwrap() = None;
+        result
+    }
+    
+    /// Probe the daemon, the recording and the model again
+    fn refresh_status(&self) {
+        self.shared.lock().unwrap().refresh();
+        *self.model_info.lock().unwrap() = None;
+    }
+    
+    fn switch_backend(&self, daemon_type: &str) -> Result<()> {
+        let result = self.with_lifecycle(|notices| self.switch_to(daemon_type, notices));
+        *self.model_info.lock().unwrap() = None;
+        result
+    }
+    
+    fn select_profile(&self, name: &str) -> Result<()> {
+        let result = self.with_lifecycle(|notices| self.apply_profile(name, notices));
+        *self.model_info.lock().unwrap() = None;
+        result
+    }
+    
+    /// Mark the daemon as not running
+    fn mark_stopped(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.status.running = false;
+        shared.status.processing = false;
+    }
+    
+    /// Start the daemon for the current backend, model and language, unless
+    /// ours is still running. The new process is only published once it's
+    /// ready (or gave no sign within the timeout).
+    fn bring_up(&self, notices: &mut Notices) -> Result<()> {
+        let launch = {
+            let mut shared = self.shared.lock().unwrap();
+            if let Some(child) = shared.daemon_process.as_mut() {
+                if let Ok(None) = child.try_wait() {
+                    // Still running
+                    return Ok(());
                 }
+                // Process has exited (or can't be asked), we can start a new one
+                shared.daemon_process = None;
             }
-            
-            // Ensure cache directories exist
-            let cache_base = format!("{}/.cache", home);
-            std::fs::create_dir_all(format!("{}/whisp-away", cache_base)).ok();
-            std::fs::create_dir_all(format!("{}/faster-whisper", cache_base)).ok();
-            std::fs::create_dir_all(format!("{}/whisper-cpp/models", cache_base)).ok();
-            
-            // Redirect output to files for debugging
-            let log_dir = format!("{}/whisp-away", cache_base);
-            std::fs::create_dir_all(&log_dir).ok();
-            
-            let stdout_file = std::fs::File::create(format!("{}/daemon-{}.log", log_dir, self.daemon_type)).ok();
-            let stderr_file = std::fs::File::create(format!("{}/daemon-{}.err", log_dir, self.daemon_type)).ok();
-            
-            if let Some(stdout) = stdout_file {
-                cmd.stdout(Stdio::from(stdout));
-            } else {
-                cmd.stdout(Stdio::null());
+            Launch {
+                daemon_type: shared.daemon_type.clone(),
+                model: shared.status.model.clone(),
+                language: shared.status.language.clone(),
+                socket_path: shared.socket_path(),
+                ready_file: shared.ready_file(),
             }
+        };
+        
+        // First, clean up after a previous tray that didn't shut down cleanly
+        stop_orphaned_daemon(&launch.socket_path, &launch.ready_file);
+        // A leftover file from a killed daemon would make the new one look ready
+        ready::remove(&launch.ready_file);
+        let mut child = (self.spawner)(&launch, notices)?;
+        
+        // Wait for the daemon's ready file, watching for an early exit
+        // (bad model, missing Python env, ...) so we don't claim it's running
+        let started = std::time::Instant::now();
+        let mut ready_info = None;
+        while started.elapsed() < DAEMON_READY_TIMEOUT {
+            std::thread::sleep(Duration::from_millis(100));
             
-            if let Some(stderr) = stderr_file {
-                cmd.stderr(Stdio::from(stderr));
-            } else {
-                cmd.stderr(Stdio::null());
+            if let Ok(info) = ready::check(&launch.ready_file, &launch.socket_path) {
+                ready_info = Some(info);
+                break;
             }
             
-            // Spawn the daemon process in its own process group
-            // This allows us to kill the entire group later
-            use std::os::unix::process::CommandExt;
-            cmd.process_group(0);  // Create new process group
-            
-            let child = cmd.spawn()
-                .context("Failed to spawn daemon process")?;
-            
-            *process_guard = Some(child);
-            
-            // Give the daemon a moment to start
-            std::thread::sleep(Duration::from_secs(2));
+            let Ok(Some(exit_status)) = child.try_wait() else {
+                continue;
+            };
+            self.mark_stopped();
+            let log_path = crate::helpers::daemon_log_path(&launch.daemon_type);
+            notices.push(notify::Notification::error("Voice Input", &format!("❌ {} daemon exited during startup ({})\nSee {}", launch.daemon_type, exit_status, log_path))
+                .timeout(10000)
+                .tag(None)
+                .urgent());
             
-            // Update status
-            if let Ok(mut status) = self.status.lock() {
-                status.running = true;
-                status.model = model;
+            return Err(anyhow::anyhow!("{} daemon exited during startup: {}", launch.daemon_type, exit_status));
+        }
+        
+        if ready_info.is_none() {
+            eprintln!("Warning: {} daemon wrote no ready file within {:?}", launch.daemon_type, DAEMON_READY_TIMEOUT);
+        }
+        
+        // Update status, preferring what the daemon says it loaded
+        {
+            let mut shared = self.shared.lock().unwrap();
+            shared.daemon_process = Some(child);
+            shared.status.running = true;
+            match ready_info {
+                Some(info) => {
+                    shared.status.model = info.model;
+                    shared.status.language = info.language;
+                }
+                None => shared.status.model = launch.model,
             }
-            
-            // Send notification
-            let acceleration = crate::helpers::get_acceleration_type();
-            let _ = Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    &format!("✅ {} daemon started ({})", self.daemon_type, acceleration),
-                    "-t", "3000",
-                ])
-                .spawn();
-            
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Failed to acquire process lock"))
         }
+        
+        // Send notification
+        let acceleration = crate::helpers::get_acceleration_type();
+        notices.push(notify::Notification::normal("Voice Input", &format!("✅ {} daemon started ({})", launch.daemon_type, acceleration))
+            .timeout(3000)
+            .tag(None));
+        
+        Ok(())
     }
     
-    fn stop_daemon_process(&self) -> Result<()> {
-        if let Ok(mut process_guard) = self.daemon_process.lock() {
-            if let Some(ref mut child) = *process_guard {
-                let pid = child.id() as i32;
-                
-                // For faster-whisper, we need to be more aggressive about cleanup
-                // because Python processes with GPU resources can be stubborn
-                if self.daemon_type == "faster-whisper" {
-                    // First, try to find and kill any Python processes that might be the actual daemon
-                    // The daemon script name would be in the process list
-                    let _ = Command::new("pkill")
-                        .args(&["-f", "whisper_daemon.py"])
-                        .output();
-                    
-                    // Also kill any process with the daemon socket in its command line
-                    let _ = Command::new("pkill")
-                        .args(&["-f", "/tmp/whisp-away-daemon.sock"])
-                        .output();
-                }
-                
-                // Kill the entire process group (negative PID kills the group)
+    /// Stop our daemon, or one a refresh found on our socket. The process is
+    /// taken out of the shared state first, so the watcher doesn't report
+    /// the exit it's about to see.
+    fn shut_down(&self, notices: &mut Notices) -> Result<()> {
+        let (child, running, daemon_type, socket_path, ready_file) = {
+            let mut shared = self.shared.lock().unwrap();
+            (shared.daemon_process.take(), shared.status.running, shared.daemon_type.clone(), shared.socket_path(), shared.ready_file())
+        };
+        let Some(mut child) = child else {
+            // A daemon a refresh found on our socket, left by a previous tray
+            if running {
+                stop_orphaned_daemon(&socket_path, &ready_file);
+                self.mark_stopped();
+            }
+            return Ok(());
+        };
+        let pid = child.id() as i32;
+        
+        // Kill the entire process group (negative PID kills the group).
+        // The faster-whisper Python process is in it too; pkill by name
+        // would also hit named daemons the tray doesn't own.
+        unsafe {
+            // First try SIGTERM to the process group
+            libc::kill(-pid, libc::SIGTERM);
+        }
+        
+        // Give it up to a second to shut down gracefully
+        let asked = std::time::Instant::now();
+        while matches!(child.try_wait(), Ok(None)) && asked.elapsed() < DAEMON_STOP_GRACE {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        
+        // Check if the main process is still running
+        match child.try_wait() {
+            Ok(None) => {
+                // Still running, force kill the process group
                 unsafe {
-                    // First try SIGTERM to the process group
-                    libc::kill(-pid, libc::SIGTERM);
-                }
-                
-                // Give it a moment to shut down gracefully
-                std::thread::sleep(Duration::from_secs(1));
-                
-                // Check if the main process is still running
-                match child.try_wait() {
-                    Ok(None) => {
-                        // Still running, force kill the process group
-                        unsafe {
-                            libc::kill(-pid, libc::SIGKILL);
-                        }
-                        
-                        // Also force kill the direct child
-                        child.kill().ok();
-                        child.wait().ok();
-                        
-                        // For faster-whisper, do one more aggressive cleanup
-                        if self.daemon_type == "faster-whisper" {
-                            std::thread::sleep(Duration::from_millis(200));
-                            // Force kill any remaining Python daemon processes
-                            let _ = Command::new("pkill")
-                                .args(&["-9", "-f", "whisper_daemon.py"])
-                                .output();
-                        }
-                    }
-                    _ => {
-                        // Process already exited, but for faster-whisper still check for orphans
-                        if self.daemon_type == "faster-whisper" {
-                            // Clean up any orphaned Python processes
-                            let _ = Command::new("pkill")
-                                .args(&["-f", "whisper_daemon.py"])
-                                .output();
-                        }
-                    }
-                }
-                
-                // Clean up the socket file if it exists
-                if self.daemon_type == "faster-whisper" {
-                    std::fs::remove_file("/tmp/whisp-away-daemon.sock").ok();
-                } else {
-                    std::fs::remove_file("/tmp/whisp-away-daemon.sock").ok();
+                    libc::kill(-pid, libc::SIGKILL);
                 }
                 
-                *process_guard = None;
-                
-                // Update status
-                if let Ok(mut status) = self.status.lock() {
-                    status.running = false;
-                    status.processing = false;
+                // Also force kill the direct child
+                child.kill().ok();
+                child.wait().ok();
+            }
+            _ => {
+                // Process already exited; make sure nothing is left in its group
+                unsafe {
+                    libc::kill(-pid, libc::SIGKILL);
                 }
-                
-                // Send notification
-                let _ = Command::new("notify-send")
-                    .args(&[
-                        "Voice Input",
-                        &format!("⏹️ {} daemon stopped", self.daemon_type),
-                        "-t", "3000",
-                    ])
-                    .spawn();
-                
-                Ok(())
-            } else {
-                Ok(()) // No process to stop
             }
-        } else {
-            Err(anyhow::anyhow!("Failed to acquire process lock"))
         }
+        
+        // A SIGKILLed daemon can't remove its own ready file
+        ready::remove(&ready_file);
+        
+        // Clean up our backend's socket file if it exists
+        std::fs::remove_file(&socket_path).ok();
+        
+        self.mark_stopped();
+        notices.push(notify::Notification::normal("Voice Input", &format!("⏹️ {} daemon stopped", daemon_type))
+            .timeout(3000)
+            .tag(None));
+        
+        Ok(())
+    }
+    
+    /// Stop the current daemon, switch backend and start the new one. A daemon
+    /// that won't stop keeps its backend.
+    fn switch_to(&self, daemon_type: &str, notices: &mut Notices) -> Result<()> {
+        if self.status().running {
+            let current = self.daemon_type();
+            self.shut_down(notices)
+                .with_context(|| format!("Failed to stop {} for switch", current))?;
+        }
+        
+        let state = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.daemon_type = daemon_type.to_string();
+            // The profile would otherwise override the backend picked by hand
+            shared.status.profile = None;
+            shared.tray_state()
+        };
+        
+        // Save new backend state
+        if let Err(e) = write_tray_state(&state) {
+            eprintln!("Warning: Failed to save tray state after backend switch: {}", e);
+        }
+        
+        // Start the new daemon, on the new backend's socket
+        self.bring_up(notices)
+            .with_context(|| format!("Failed to start {}", daemon_type))
+    }
+    
+    /// Switch to profile `name`: take its backend, model, language and output
+    /// mode, save them for clients, and restart a running daemon if what it
+    /// loaded changed
+    fn apply_profile(&self, name: &str, notices: &mut Notices) -> Result<()> {
+        let profile = crate::profile::load(name)?;
+        let (reload, current) = {
+            let shared = self.shared.lock().unwrap();
+            let backend = profile.backend.as_ref().unwrap_or(&shared.daemon_type);
+            let model = profile.model.as_ref().unwrap_or(&shared.status.model);
+            let language = profile.language.as_ref().unwrap_or(&shared.status.language);
+            let reload = shared.status.running
+                && (*backend != shared.daemon_type || *model != shared.status.model || *language != shared.status.language);
+            (reload, shared.daemon_type.clone())
+        };
+        if reload {
+            self.shut_down(notices)
+                .with_context(|| format!("Failed to stop {} for profile {}", current, name))?;
+        }
+        
+        let (state, summary) = {
+            let mut shared = self.shared.lock().unwrap();
+            if let Some(backend) = profile.backend {
+                shared.daemon_type = backend;
+            }
+            if let Some(model) = profile.model {
+                shared.status.model = model;
+            }
+            if let Some(language) = profile.language {
+                shared.status.language = language;
+            }
+            if let Some(output_mode) = profile.output_mode {
+                shared.status.output_mode = output_mode;
+            }
+            shared.status.profile = Some(name.to_string());
+            let summary = format!("{} {} ({})", shared.daemon_type, shared.status.model, shared.status.language);
+            (shared.tray_state(), summary)
+        };
+        if let Err(e) = write_tray_state(&state) {
+            eprintln!("Warning: Failed to save tray state after profile switch: {}", e);
+        }
+        notices.push(notify::Notification::normal("Voice Input", &format!("👤 Profile {}: {}", name, summary))
+            .timeout(3000)
+            .tag(None));
+        
+        if reload {
+            let daemon_type = self.daemon_type();
+            self.bring_up(notices)
+                .with_context(|| format!("Failed to start {} for profile {}", daemon_type, name))?;
+        }
+        Ok(())
     }
-    
-    fn check_daemon_process_status(&self) -> bool {
-        if let Ok(mut process_guard) = self.daemon_process.lock() {
-            if let Some(ref mut child) = *process_guard {
-                // Check if process is still running
-                match child.try_wait() {
-                    Ok(None) => true,  // Still running
-                    _ => {
-                        // Process has exited
-                        *process_guard = None;
-                        false
-                    }
-                }
-            } else {
+
+    /// Whether the wake word listener is running; forgets it if it has exited
+    fn is_listening(&self) -> bool {
+        let mut guard = self.listen_process.lock().unwrap();
+        match guard.as_mut().map(|child| child.try_wait()) {
+            Some(Ok(None)) => true,
+            _ => {
+                *guard = None;
                 false
             }
+        }
+    }
+    
+    /// Spawn `whisp-away listen` for the current backend, logging next to the daemon logs
+    fn start_listening(&self) -> Result<()> {
+        if self.is_listening() {
+            return Ok(());
+        }
+        
+        let status = self.status();
+        let binary_path = std::env::current_exe()
+            .context("Failed to get current executable path")?;
+        
+        let mut cmd = child_env::command(&binary_path, child_env::MODEL_ENV);
+        cmd.arg("listen")
+           .arg("--backend")
+           .arg(self.daemon_type())
+           .arg("--model")
+           .arg(&status.model)
+           .arg("--language")
+           .arg(&status.language);
+        
+        let log_path = crate::helpers::daemon_log_path("listen");
+        let log = RotatingWriter::open(&log_path, DAEMON_LOG_MAX_BYTES, DAEMON_LOG_FILES)
+            .map(|writer| Arc::new(Mutex::new(writer)))
+            .map_err(|e| eprintln!("Warning: Failed to open listener log {}: {}", log_path, e))
+            .ok();
+        if log.is_some() {
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
         } else {
-            false
+            cmd.stdout(Stdio::null());
+            cmd.stderr(Stdio::null());
+        }
+        
+        let mut child = cmd.spawn()
+            .context("Failed to spawn wake word listener")?;
+        if let Some(log) = log {
+            forward_to_log(child.stdout.take(), Arc::clone(&log));
+            forward_to_log(child.stderr.take(), log);
         }
+        
+        *self.listen_process.lock().unwrap() = Some(child);
+        Ok(())
     }
-
-    async fn check_daemon_status(&self) -> Result<bool> {
-        let socket_path = match self.daemon_type.as_str() {
-            "faster-whisper" => "/tmp/whisp-away-daemon.sock",
-            "whisper-cpp" => "/tmp/whisp-away-daemon.sock",
-            _ => return Ok(false),
-        };
-
-        if !Path::new(socket_path).exists() {
-            return Ok(false);
+    
+    fn stop_listening(&self) {
+        if let Some(mut child) = self.listen_process.lock().unwrap().take() {
+            child.kill().ok();
+            child.wait().ok();
         }
-
-        // Try to connect to the daemon
-        match UnixStream::connect(socket_path).await {
-            Ok(mut stream) => {
-                // Send a status request
-                let request = r#"{"command": "status"}"#;
-                stream.write_all(request.as_bytes()).await?;
-                
-                // Try to read response
-                let mut buffer = vec![0; 1024];
-                match tokio::time::timeout(
-                    Duration::from_secs(1),
-                    stream.read(&mut buffer)
-                ).await {
-                    Ok(Ok(n)) if n > 0 => Ok(true),
-                    _ => Ok(false),
-                }
+    }
+    
+    /// Info about the selected model's file, probing the disk only when the model changed
+    fn model_info(&self) -> ModelInfo {
+        let (daemon_type, model) = {
+            let shared = self.shared.lock().unwrap();
+            (shared.daemon_type.clone(), shared.status.model.clone())
+        };
+        let mut cached = self.model_info.lock().unwrap();
+        match cached.as_ref() {
+            Some(info) if info.model == model && info.daemon_type == daemon_type => info.clone(),
+            _ => {
+                let info = ModelInfo::probe(&daemon_type, &model);
+                *cached = Some(info.clone());
+                info
             }
-            Err(_) => Ok(false),
         }
     }
-
-    fn start_daemon(&self) -> Result<()> {
-        self.start_daemon_process()
+    
+    /// What our daemon reports about itself, while it's running
+    fn daemon_info(&self) -> Option<ready::ReadyInfo> {
+        if !self.status().running {
+            return None;
+        }
+        let ready_file = self.shared.lock().unwrap().ready_file();
+        (self.read_ready)(&ready_file)
     }
-
-    fn stop_daemon(&self) -> Result<()> {
-        self.stop_daemon_process()
+    
+    /// Our daemon is up but reported its model unusable (it needs a restart)
+    fn unavailable_reason(&self) -> Option<String> {
+        self.daemon_info()?.health?.unavailable
     }
-
+    
     fn get_icon_name(&self) -> String {
-        let status = self.status.lock().unwrap();
+        if self.model_info().missing || self.unavailable_reason().is_some() {
+            return "dialog-warning-symbolic".to_string();
+        }
+        let status = self.status();
         if !status.running {
             "microphone-disabled-symbolic"
         } else if status.processing {
@@ -459,24 +1140,109 @@ impl VoiceInputTray {
         }.to_string()
     }
 
-    fn get_tooltip(&self) -> String {
-        let status = self.status.lock().unwrap();
-        if !status.running {
-            format!("Voice Input ({}) - Stopped\nLeft-click to start", self.daemon_type)
+    /// The state in a word or two, first in the tooltip and on the
+    /// Refresh status item
+    fn headline(&self, status: &DaemonStatus) -> &'static str {
+        if self.unavailable_reason().is_some() {
+            "Model unavailable"
+        } else if status.recording {
+            "Recording"
         } else if status.processing {
-            format!("Voice Input ({}) - Processing...", self.daemon_type)
+            "Processing"
+        } else if status.running {
+            "Ready"
         } else {
-            format!(
-                "Voice Input ({}) - Ready\nModel: {}\nLeft-click to stop",
-                self.daemon_type, status.model
-            )
+            "Stopped"
+        }
+    }
+    
+    /// Everything the tray knows about its daemon, the recording and the
+    /// model, in words: the menu's status items and the tooltip
+    fn status_lines(&self) -> Vec<StatusLine> {
+        let (daemon_type, status) = {
+            let shared = self.shared.lock().unwrap();
+            (shared.daemon_type.clone(), shared.status.clone())
+        };
+        let mut lines = vec![
+            StatusLine::new(if status.running { "Daemon: ✅ running" } else { "Daemon: ⏸️ stopped" }),
+            StatusLine::new(format!("Backend: {}", backend_display(&daemon_type))),
+            StatusLine::new(if status.recording { "Recording: 🎤 in progress" } else { "Recording: none" }),
+            StatusLine::new(if status.processing { "Processing: ⏳ transcribing" } else { "Processing: idle" }),
+        ];
+        
+        let daemon_info = self.daemon_info();
+        if let Some(info) = &daemon_info {
+            let unavailable = info.health.as_ref().and_then(|health| health.unavailable.as_ref());
+            // Model the daemon dropped because memory ran low
+            let unloaded = info.memory.as_ref().filter(|memory| memory.unloaded);
+            if let Some(reason) = unavailable {
+                lines.push(StatusLine::warning(format!("Health: model unavailable, restart the daemon ({})", reason)));
+            }
+            if let Some(memory) = unloaded {
+                let available = memory.available_mb.map(|mb| format!(", {} MB available", mb)).unwrap_or_default();
+                lines.push(StatusLine::warning(format!("Health: model unloaded (low memory{})", available)));
+            }
+            if unavailable.is_none() && unloaded.is_none() {
+                lines.push(StatusLine::new("Health: ok"));
+            }
         }
+        
+        let model = self.model_info();
+        let model_line = format!("Model: {}", model.label());
+        lines.push(if model.missing { StatusLine::warning(model_line) } else { StatusLine::new(model_line) });
+        lines.push(StatusLine::new(format!("Language: {}", status.language)));
+        
+        // Acceleration, with what the running daemon actually uses: OpenVINO
+        // can fail to initialize and leave it on the CPU
+        let acceleration = daemon_info.as_ref()
+            .and_then(|info| info.acceleration.clone())
+            .unwrap_or_else(crate::helpers::get_acceleration_type)
+            .to_uppercase();
+        match &daemon_info {
+            Some(info) if info.acceleration_degraded() => lines.push(StatusLine {
+                text: format!("Acceleration: {} inactive, using CPU (open log)", acceleration),
+                warning: true,
+                log: Some(crate::helpers::daemon_log_path(&daemon_type)),
+            }),
+            _ => {
+                let active = match daemon_info.as_ref().and_then(|info| info.openvino_active) {
+                    Some(true) => " (active)",
+                    _ => "",
+                };
+                lines.push(StatusLine::new(format!("Acceleration: {}{}", acceleration, active)));
+            }
+        }
+        
+        // Model file the daemon loaded
+        if let Some(model_path) = daemon_info.and_then(|info| info.model_path) {
+            lines.push(StatusLine::new(format!("Model file: {}", model_path)));
+        }
+        lines
+    }
+    
+    /// All of the state as text, for screen readers as much as for the eye
+    fn get_tooltip(&self) -> String {
+        let status = self.status();
+        let mut lines = vec![format!("Voice Input ({}) - {}", self.daemon_type(), self.headline(&status))];
+        lines.extend(self.status_lines().into_iter().map(|line| line.text));
+        lines.push(format!("Output: {}", status.output_mode));
+        lines.push(format!("Recording settings: {}", recording_label(&status)));
+        lines.push(format!("Profile: {}", status.profile.as_deref().unwrap_or("none")));
+        lines.push(format!("Wake word: {}", if self.is_listening() { "listening" } else { "off" }));
+        lines.push(if self.unavailable_reason().is_some() {
+            "Restart the daemon to transcribe again"
+        } else if status.running {
+            "Left-click to stop"
+        } else {
+            "Left-click to start"
+        }.to_string());
+        notify::text(&lines.join("\n"))
     }
 }
 
 impl Tray for VoiceInputTray {
     fn id(&self) -> String {
-        format!("voice-input-{}", self.daemon_type)
+        format!("voice-input-{}", self.daemon_type())
     }
 
     fn title(&self) -> String {
@@ -488,7 +1254,11 @@ impl Tray for VoiceInputTray {
     }
 
     fn icon_pixmap(&self) -> Vec<ksni::Icon> {
-        let status = self.status.lock().unwrap();
+        // No pixmap makes the host fall back to icon_name's warning icon
+        if self.model_info().missing || self.unavailable_reason().is_some() {
+            return vec![];
+        }
+        let status = self.status();
         let icon_bytes = if !status.running {
             ICON_OFF
         } else if status.processing {
@@ -515,176 +1285,214 @@ impl Tray for VoiceInputTray {
     
     fn activate(&mut self, _x: i32, _y: i32) {
         // Left-click toggles daemon start/stop
-        let is_running = {
-            let status = self.status.lock().unwrap();
-            status.running
-        };
-        
-        if is_running {
-            // Stop the daemon
-            match self.stop_daemon() {
-                Ok(_) => {
-                    // Only update status on success
-                    if let Ok(mut status) = self.status.lock() {
-                        status.running = false;
-                        status.processing = false;
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to stop daemon: {}", e);
-                }
-            }
-        } else {
-            // Start the daemon
-            match self.start_daemon() {
-                Ok(_) => {
-                    // Only update status on success
-                    if let Ok(mut status) = self.status.lock() {
-                        status.running = true;
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to start daemon: {}", e);
-                }
-            }
+        if let Err(e) = self.toggle_daemon() {
+            eprintln!("{:#}", e);
         }
     }
 
     fn menu(&self) -> Vec<MenuItem<Self>> {
-        let status = self.status.lock().unwrap();
+        let (daemon_type, status) = {
+            let shared = self.shared.lock().unwrap();
+            (shared.daemon_type.clone(), shared.status.clone())
+        };
         let is_running = status.running;
-        let model = status.model.clone();
-        drop(status);
+        let output_mode = status.output_mode;
 
         let mut items = vec![];
 
-        // Status indicator
-        items.push(MenuItem::Standard(StandardItem {
-            label: if is_running {
-                "Status: ✅ Running".to_string()
-            } else {
-                "Status: ⏸️  Stopped".to_string()
-            },
-            enabled: false,
-            ..Default::default()
-        }));
-        
-        // Backend/daemon type indicator
-        let daemon_display = if self.daemon_type == "faster-whisper" {
-            "Faster Whisper"
-        } else {
-            "Whisper.cpp"
-        };
-        items.push(MenuItem::Standard(StandardItem {
-            label: format!("Backend: {}", daemon_display),
-            enabled: false,
-            ..Default::default()
-        }));
-        
-        // Acceleration type indicator
-        let acceleration = crate::helpers::get_acceleration_type();
+        // What the tray knows, each line an item that probes again when
+        // activated, so all of it can be reached from the keyboard and read
+        // out rather than only shown as disabled labels
+        let refresh = |tray: &mut Self| tray.refresh_status();
         items.push(MenuItem::Standard(StandardItem {
-            label: format!("Acceleration: {}", acceleration.to_uppercase()),
-            enabled: false,
+            label: format!("_Refresh status: {}", self.headline(&status)),
+            icon_name: "view-refresh".to_string(),
+            activate: Box::new(refresh),
             ..Default::default()
         }));
+        for line in self.status_lines() {
+            let activate: Box<dyn Fn(&mut Self)> = match line.log {
+                Some(log_path) => Box::new(move |_tray: &mut Self| open_log(&log_path)),
+                None => Box::new(refresh),
+            };
+            items.push(MenuItem::Standard(StandardItem {
+                label: escape_mnemonics(&line.text),
+                icon_name: if line.warning { "dialog-warning".to_string() } else { String::new() },
+                disposition: if line.warning { Disposition::Warning } else { Disposition::Normal },
+                activate,
+                ..Default::default()
+            }));
+        }
 
         items.push(MenuItem::Separator);
 
         // Start/Stop control
         if is_running {
             items.push(MenuItem::Standard(StandardItem {
-                label: "Stop Daemon".to_string(),
+                label: "_Stop daemon".to_string(),
                 activate: Box::new(|tray: &mut Self| {
-                    let _ = tray.stop_daemon();
-                    if let Ok(mut status) = tray.status.lock() {
-                        status.running = false;
-                        status.processing = false;
+                    if let Err(e) = tray.stop_daemon() {
+                        eprintln!("Failed to stop daemon: {}", e);
                     }
                 }),
                 ..Default::default()
             }));
         } else {
             items.push(MenuItem::Standard(StandardItem {
-                label: "Start Daemon".to_string(),
+                label: "_Start daemon".to_string(),
                 activate: Box::new(|tray: &mut Self| {
-                    let _ = tray.start_daemon();
-                    if let Ok(mut status) = tray.status.lock() {
-                        status.running = true;
+                    if let Err(e) = tray.start_daemon() {
+                        eprintln!("Failed to start daemon: {}", e);
                     }
                 }),
                 ..Default::default()
             }));
         }
 
-        // Model selection submenu
-        items.push(MenuItem::Separator);
-        items.push(MenuItem::Standard(StandardItem {
-            label: format!("Model: {}", model),
-            enabled: false,
+        // Wake word listener toggle
+        if self.is_listening() {
+            items.push(MenuItem::Standard(StandardItem {
+                label: "Stop _wake word listening".to_string(),
+                activate: Box::new(|tray: &mut Self| tray.stop_listening()),
+                ..Default::default()
+            }));
+        } else {
+            items.push(MenuItem::Standard(StandardItem {
+                label: "Start _wake word listening".to_string(),
+                activate: Box::new(|tray: &mut Self| {
+                    if let Err(e) = tray.start_listening() {
+                        eprintln!("Failed to start wake word listener: {}", e);
+                        notify::Notification::error("Voice Input", &format!("❌ Could not start wake word listener\n{}", e))
+                            .tag(None)
+                            .send();
+                    }
+                }),
+                ..Default::default()
+            }));
+        }
+        
+        // Copy transcripts instead of typing them, for every client
+        items.push(MenuItem::Checkmark(CheckmarkItem {
+            label: "_Copy instead of type".to_string(),
+            checked: output_mode == OutputMode::Clipboard,
+            activate: Box::new(|tray: &mut Self| {
+                {
+                    let mut shared = tray.shared.lock().unwrap();
+                    shared.status.output_mode = match shared.status.output_mode {
+                        OutputMode::Type => OutputMode::Clipboard,
+                        OutputMode::Clipboard => OutputMode::Type,
+                    };
+                    shared.status.profile = None;
+                }
+                if let Err(e) = tray.save_state() {
+                    eprintln!("Warning: Failed to save tray state after output mode change: {}", e);
+                }
+            }),
             ..Default::default()
         }));
-
+        
+        // Recording settings: a threshold preset plus VAD and auto-stop,
+        // picked up by the next `stop`
+        let mut submenu: Vec<MenuItem<Self>> = Sensitivity::ALL
+            .into_iter()
+            .map(|sensitivity| {
+                let thresholds = sensitivity.thresholds();
+                MenuItem::Checkmark(CheckmarkItem {
+                    label: format!(
+                        "{} (min {} ms, trim {} ms)",
+                        sensitivity, thresholds.min_duration_ms, thresholds.trim_lead_ms
+                    ),
+                    checked: status.sensitivity == sensitivity,
+                    activate: Box::new(move |tray: &mut Self| {
+                        tray.shared.lock().unwrap().status.sensitivity = sensitivity;
+                        if let Err(e) = tray.save_state() {
+                            eprintln!("Warning: Failed to save tray state after recording preset change: {}", e);
+                        }
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        submenu.push(MenuItem::Separator);
+        submenu.push(MenuItem::Checkmark(CheckmarkItem {
+            label: "Drop recordings without speech".to_string(),
+            checked: status.vad,
+            activate: Box::new(|tray: &mut Self| {
+                {
+                    let mut shared = tray.shared.lock().unwrap();
+                    shared.status.vad = !shared.status.vad;
+                }
+                if let Err(e) = tray.save_state() {
+                    eprintln!("Warning: Failed to save tray state after VAD change: {}", e);
+                }
+            }),
+            ..Default::default()
+        }));
+        submenu.push(MenuItem::Checkmark(CheckmarkItem {
+            label: format!("Stop after a pause ({} ms)", status.sensitivity.thresholds().silence_ms),
+            checked: status.auto_stop,
+            activate: Box::new(|tray: &mut Self| {
+                {
+                    let mut shared = tray.shared.lock().unwrap();
+                    shared.status.auto_stop = !shared.status.auto_stop;
+                }
+                if let Err(e) = tray.save_state() {
+                    eprintln!("Warning: Failed to save tray state after auto-stop change: {}", e);
+                }
+            }),
+            ..Default::default()
+        }));
+        items.push(MenuItem::SubMenu(SubMenu {
+            label: "Recording se_ttings".to_string(),
+            submenu,
+            ..Default::default()
+        }));
+        
+        // Profiles submenu, when any exist
+        let profiles = crate::profile::names();
+        if !profiles.is_empty() {
+            let submenu = profiles
+                .into_iter()
+                .map(|name| {
+                    let checked = status.profile.as_deref() == Some(name.as_str());
+                    MenuItem::Checkmark(CheckmarkItem {
+                        label: escape_mnemonics(&name),
+                        checked,
+                        activate: Box::new(move |tray: &mut Self| {
+                            if let Err(e) = tray.select_profile(&name) {
+                                eprintln!("{:#}", e);
+                                notify::Notification::error("Voice Input", &format!("❌ Could not switch to profile {}\n{:#}", name, e))
+                                    .tag(None)
+                                    .send();
+                            }
+                        }),
+                        ..Default::default()
+                    })
+                })
+                .collect();
+            items.push(MenuItem::SubMenu(SubMenu {
+                label: format!("_Profile: {}", escape_mnemonics(status.profile.as_deref().unwrap_or("none"))),
+                submenu,
+                ..Default::default()
+            }));
+        }
 
         items.push(MenuItem::Separator);
 
         // Switch daemon type
-        let other_daemon = if self.daemon_type == "faster-whisper" {
+        let other_daemon = if daemon_type == "faster-whisper" {
             "whisper-cpp"
         } else {
             "faster-whisper"
         };
         
-        let other_daemon_display = if self.daemon_type == "faster-whisper" {
-            "Whisper.cpp"
-        } else {
-            "Faster Whisper"
-        };
-        
+        let other_daemon_display = backend_display(other_daemon);
         let other_daemon_clone = other_daemon.to_string();
         items.push(MenuItem::Standard(StandardItem {
-            label: format!("Switch to {}", other_daemon_display),
+            label: format!("Switch _backend to {}", other_daemon_display),
             activate: Box::new(move |tray: &mut Self| {
-                // Stop current daemon if running
-                let was_running = {
-                    let status = tray.status.lock().unwrap();
-                    status.running
-                };
-                
-                if was_running {
-                    match tray.stop_daemon() {
-                        Ok(_) => {
-                            if let Ok(mut status) = tray.status.lock() {
-                                status.running = false;
-                                status.processing = false;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to stop {} for switch: {}", tray.daemon_type, e);
-                            // Don't switch if we can't stop the current daemon
-                            return;
-                        }
-                    }
-                }
-                
-                // Switch daemon type
-                tray.daemon_type = other_daemon_clone.clone();
-                
-                // Save new backend state
-                if let Err(e) = tray.save_state() {
-                    eprintln!("Warning: Failed to save tray state after backend switch: {}", e);
-                }
-                
-                // Start the new daemon
-                match tray.start_daemon() {
-                    Ok(_) => {
-                        if let Ok(mut status) = tray.status.lock() {
-                            status.running = true;
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to start {}: {}", tray.daemon_type, e);
-                    }
+                if let Err(e) = tray.switch_backend(&other_daemon_clone) {
+                    eprintln!("{:#}", e);
                 }
             }),
             ..Default::default()
@@ -692,31 +1500,591 @@ impl Tray for VoiceInputTray {
 
         items.push(MenuItem::Separator);
 
+        // Open the current daemon log for debugging crashes
+        let log_path = crate::helpers::daemon_log_path(&daemon_type);
+        items.push(MenuItem::Standard(StandardItem {
+            label: "Open daemon _log".to_string(),
+            enabled: Path::new(&log_path).exists(),
+            activate: Box::new(move |_tray: &mut Self| open_log(&log_path)),
+            ..Default::default()
+        }));
+
+        items.push(MenuItem::Separator);
+
         // Quit
         items.push(MenuItem::Standard(StandardItem {
-            label: "Quit".to_string(),
+            label: "_Quit".to_string(),
             activate: Box::new(|_tray: &mut Self| {
                 std::process::exit(0);
             }),
             ..Default::default()
         }));
 
+        render_labels(&mut items);
         items
     }
 }
 
-pub async fn run_tray(daemon_type: String) -> Result<()> {
-    let tray = VoiceInputTray::new(daemon_type.clone());
-    
-    // DISABLED: Background status checker causes issues when switching daemon types
-    // The checker doesn't know about daemon type changes and checks the wrong service
-    // TODO: Fix this by making daemon_type mutable and shared
-    
-    // For now, we rely on manual status updates when starting/stopping daemons
+pub async fn run_tray(
+    daemon_type: String,
+    model: Option<String>,
+    language: Option<String>,
+    autostart_daemon: bool,
+    auto_restart_on_upgrade: bool,
+) -> Result<()> {
+    let tray = VoiceInputTray::new(daemon_type.clone(), model, language);
+    let shared = Arc::clone(&tray.shared);
 
     // Create and run the tray service
     let service = TrayService::new(tray);
-    service.run();
-
-    Ok(())
-}
\ No newline at end of file
+    
+    if autostart_daemon {
+        // Start the daemon off the D-Bus thread so the icon registers right away;
+        // the update marks the tray dirty so the new status shows once it's up.
+        // Starting replaces a leftover daemon anyway.
+        let handle = service.handle();
+        std::thread::spawn(move || handle.update(|tray| tray.autostart_daemon()));
+    } else {
+        let handle = service.handle();
+        std::thread::spawn(move || handle.update(|tray| tray.check_leftover_daemon(auto_restart_on_upgrade)));
+    }
+    
+    // Processing and model changes are pushed by the daemon
+    let watch = Arc::new(Mutex::new(DaemonWatch::default()));
+    let handle = service.handle();
+    let (events_shared, events_watch) = (Arc::clone(&shared), Arc::clone(&watch));
+    std::thread::spawn(move || follow_daemon_events(events_shared, events_watch, handle));
+    
+    // Watch for a daemon that died on its own, for a recording starting or
+    // ending and, while there is no event subscription, for what a daemon only
+    // reports through its ready file (a broken model, its model file, whether
+    // OpenVINO came up), redrawing when anything changes. The shared lock is released before the update, which
+    // takes the tray service's own lock.
+    let handle = service.handle();
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(HEALTH_POLL_INTERVAL);
+            let exited = {
+                let mut shared = shared.lock().unwrap();
+                shared.reap_exited_daemon().map(|status| (shared.daemon_type.clone(), status))
+            };
+            if let Some((daemon_type, status)) = &exited {
+                let log_path = crate::helpers::daemon_log_path(daemon_type);
+                notify::Notification::error("Voice Input", &format!("❌ {} daemon exited ({})\nSee {}", daemon_type, status, log_path))
+                    .timeout(10000)
+                    .tag(None)
+                    .send();
+            }
+            let recording = !recording::active_recorders().is_empty();
+            let recording_changed = {
+                let mut shared = shared.lock().unwrap();
+                std::mem::replace(&mut shared.status.recording, recording) != recording
+            };
+            let subscribed = watch.lock().unwrap().subscribed;
+            let changed = !subscribed && resync_snapshot(&shared, &watch);
+            if exited.is_some() || recording_changed || changed {
+                handle.update(|_| {});
+            }
+        }
+    });
+    
+    service.run().context("Tray service failed")
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::time::Instant;
+    
+    /// How long the stub daemon takes to come up, as a model load would
+    const STUB_STARTUP: Duration = Duration::from_millis(300);
+    
+    /// Every process the stub spawner started
+    static SPAWNED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+    
+    /// A daemon that is only a `sleep` in its own process group, reported
+    /// ready once it has "loaded its model"
+    fn stub_spawner(launch: &Launch, _notices: &mut Notices) -> Result<Child> {
+        let child = std::process::Command::new("sleep").arg("60").process_group(0).spawn()?;
+        SPAWNED.lock().unwrap().push(child.id());
+        std::thread::sleep(STUB_STARTUP);
+        let info = serde_json::json!({
+            "pid": child.id(),
+            "backend": launch.daemon_type,
+            "socket_path": launch.socket_path,
+            "model": launch.model,
+            "language": launch.language,
+        });
+        std::fs::write(&launch.ready_file, info.to_string())?;
+        Ok(child)
+    }
+    
+    /// The menu as text, one item per line and submenus indented: labels as
+    /// sent (with their access keys), whether they are checked, disabled or
+    /// warnings
+    fn describe<T>(items: &[MenuItem<T>], depth: usize, out: &mut String) {
+        for item in items {
+            let mut line = "  ".repeat(depth);
+            match item {
+                MenuItem::Standard(item) => {
+                    if !item.enabled {
+                        line.push_str("(disabled) ");
+                    }
+                    if item.disposition == Disposition::Warning {
+                        line.push_str("(warning) ");
+                    }
+                    line.push_str(&item.label);
+                }
+                MenuItem::Checkmark(item) => {
+                    line.push_str(if item.checked { "[x] " } else { "[ ] " });
+                    line.push_str(&item.label);
+                }
+                MenuItem::SubMenu(menu) => {
+                    line.push_str("> ");
+                    line.push_str(&menu.label);
+                }
+                MenuItem::RadioGroup(_) => line.push_str("(radio group)"),
+                MenuItem::Separator => line.push_str("---"),
+            }
+            out.push_str(&line);
+            out.push('\n');
+            if let MenuItem::SubMenu(menu) = item {
+                describe(&menu.submenu, depth + 1, out);
+            }
+        }
+    }
+    
+    /// A tray in a fixed state, whatever the environment and tray state say,
+    /// its daemon's ready file as `read_ready` makes it up
+    fn tray(daemon_type: &str, model: &str, running: bool, read_ready: fn(&str) -> Option<ready::ReadyInfo>) -> VoiceInputTray {
+        let mut tray = VoiceInputTray::unsaved(daemon_type.to_string(), None, None);
+        tray.read_ready = read_ready;
+        tray.shared.lock().unwrap().status = DaemonStatus {
+            running,
+            model: model.to_string(),
+            language: "en".to_string(),
+            output_mode: OutputMode::Type,
+            profile: None,
+            sensitivity: Sensitivity::Normal,
+            vad: false,
+            auto_stop: false,
+            processing: false,
+            recording: false,
+        };
+        tray
+    }
+    
+    /// The icon, the tooltip and the menu up to starting or stopping the daemon
+    fn shown(tray: &VoiceInputTray) -> String {
+        let mut menu = String::new();
+        describe(&tray.menu(), 1, &mut menu);
+        let end = menu.find("daemon\n").map_or(menu.len(), |end| end + "daemon\n".len());
+        let tooltip: String = tray.get_tooltip().lines().map(|line| format!("  {}\n", line)).collect();
+        format!("Icon: {}\nTooltip:\n{}Menu:\n{}", tray.get_icon_name(), tooltip, &menu[..end])
+    }
+    
+    /// `expected` as this environment renders it: the acceleration the
+    /// tray falls back on comes from WA_ACCELERATION_TYPE, and WA_NO_EMOJI
+    /// drops the emoji
+    fn rendered(expected: &str) -> String {
+        let acceleration = crate::helpers::get_acceleration_type().to_uppercase();
+        notify::text(&expected.replace("{acceleration}", &acceleration))
+    }
+    
+    fn stub_ready(extra: serde_json::Value) -> Option<ready::ReadyInfo> {
+        let mut info = serde_json::json!({
+            "pid": std::process::id(),
+            "backend": "mock",
+            "socket_path": "/run/user/1000/whisp-away/daemon-mock.sock",
+            "model": "mock",
+            "language": "en",
+            "acceleration": "cpu",
+        });
+        info.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        Some(serde_json::from_value(info).unwrap())
+    }
+    
+    fn no_ready(_: &str) -> Option<ready::ReadyInfo> {
+        None
+    }
+    
+    fn healthy_ready(_: &str) -> Option<ready::ReadyInfo> {
+        stub_ready(serde_json::json!({}))
+    }
+    
+    #[test]
+    fn stopped_and_recording_show_in_words() {
+        let tray = tray("mock", "base.en", false, no_ready);
+        assert_eq!(shown(&tray), rendered("\
+Icon: microphone-disabled-symbolic
+Tooltip:
+  Voice Input (mock) - Stopped
+  Daemon: ⏸️ stopped
+  Backend: Mock
+  Recording: none
+  Processing: idle
+  Model: base.en
+  Language: en
+  Acceleration: {acceleration}
+  Output: type
+  Recording settings: normal
+  Profile: none
+  Wake word: off
+  Left-click to start
+Menu:
+  _Refresh status: Stopped
+  Daemon: ⏸️ stopped
+  Backend: Mock
+  Recording: none
+  Processing: idle
+  Model: base.en
+  Language: en
+  Acceleration: {acceleration}
+  ---
+  _Start daemon
+"));
+        
+        tray.shared.lock().unwrap().status.recording = true;
+        let shown = shown(&tray);
+        assert!(shown.contains("  Voice Input (mock) - Recording\n"), "{}", shown);
+        assert!(shown.contains(&rendered("  Recording: 🎤 in progress\n")), "{}", shown);
+        assert!(shown.contains("  _Refresh status: Recording\n"), "{}", shown);
+    }
+    
+    #[test]
+    fn without_emoji_only_the_emoji_go() {
+        let tray = tray("mock", "mock", true, healthy_ready);
+        {
+            let mut shared = tray.shared.lock().unwrap();
+            shared.status.recording = true;
+            shared.status.processing = true;
+        }
+        let shown = shown(&tray);
+        let stripped: String = shown.lines().map(|line| notify::without_emoji(line) + "\n").collect();
+        assert_eq!(stripped, "\
+Icon: microphone-sensitivity-high-symbolic
+Tooltip:
+  Voice Input (mock) - Recording
+  Daemon: running
+  Backend: Mock
+  Recording: in progress
+  Processing: transcribing
+  Health: ok
+  Model: mock
+  Language: en
+  Acceleration: CPU
+  Output: type
+  Recording settings: normal
+  Profile: none
+  Wake word: off
+  Left-click to stop
+Menu:
+  _Refresh status: Recording
+  Daemon: running
+  Backend: Mock
+  Recording: in progress
+  Processing: transcribing
+  Health: ok
+  Model: mock
+  Language: en
+  Acceleration: CPU
+  ---
+  _Stop daemon
+");
+        assert_eq!(notify::without_emoji("❌ Could not start"), "Error: Could not start");
+    }
+    
+    #[test]
+    fn a_missing_model_file_is_a_warning() {
+        let model = format!("{}/whisp-away-test-{}/ggml_missing.bin", std::env::temp_dir().display(), std::process::id());
+        let tray = tray("whisper-cpp", &model, false, no_ready);
+        let shown = shown(&tray);
+        assert!(shown.starts_with("Icon: dialog-warning-symbolic\n"), "{}", shown);
+        assert!(shown.contains("  Model: ggml_missing (file not found!)\n"), "{}", shown);
+        // Its underscores kept apart from the access keys
+        assert!(shown.contains("  (warning) Model: ggml__missing (file not found!)\n"), "{}", shown);
+    }
+    
+    #[test]
+    fn an_unavailable_model_says_to_restart() {
+        fn unavailable(_: &str) -> Option<ready::ReadyInfo> {
+            stub_ready(serde_json::json!({"health": {"state_recreations": 2, "unavailable": "whisper state could not be recreated"}}))
+        }
+        let tray = tray("mock", "mock", true, unavailable);
+        assert_eq!(shown(&tray), rendered("\
+Icon: dialog-warning-symbolic
+Tooltip:
+  Voice Input (mock) - Model unavailable
+  Daemon: ✅ running
+  Backend: Mock
+  Recording: none
+  Processing: idle
+  Health: model unavailable, restart the daemon (whisper state could not be recreated)
+  Model: mock
+  Language: en
+  Acceleration: CPU
+  Output: type
+  Recording settings: normal
+  Profile: none
+  Wake word: off
+  Restart the daemon to transcribe again
+Menu:
+  _Refresh status: Model unavailable
+  Daemon: ✅ running
+  Backend: Mock
+  Recording: none
+  Processing: idle
+  (warning) Health: model unavailable, restart the daemon (whisper state could not be recreated)
+  Model: mock
+  Language: en
+  Acceleration: CPU
+  ---
+  _Stop daemon
+"));
+    }
+    
+    #[test]
+    fn an_unloaded_model_and_inactive_openvino_are_warnings() {
+        fn unloaded(_: &str) -> Option<ready::ReadyInfo> {
+            stub_ready(serde_json::json!({
+                "memory": {"available_mb": 300, "unloaded": true},
+                "acceleration": "openvino",
+                "openvino_active": false,
+                "model_path": "/models/ggml_base.bin",
+            }))
+        }
+        let tray = tray("mock", "mock", true, unloaded);
+        assert_eq!(shown(&tray), rendered("\
+Icon: microphone-sensitivity-medium-symbolic
+Tooltip:
+  Voice Input (mock) - Ready
+  Daemon: ✅ running
+  Backend: Mock
+  Recording: none
+  Processing: idle
+  Health: model unloaded (low memory, 300 MB available)
+  Model: mock
+  Language: en
+  Acceleration: OPENVINO inactive, using CPU (open log)
+  Model file: /models/ggml_base.bin
+  Output: type
+  Recording settings: normal
+  Profile: none
+  Wake word: off
+  Left-click to stop
+Menu:
+  _Refresh status: Ready
+  Daemon: ✅ running
+  Backend: Mock
+  Recording: none
+  Processing: idle
+  (warning) Health: model unloaded (low memory, 300 MB available)
+  Model: mock
+  Language: en
+  (warning) Acceleration: OPENVINO inactive, using CPU (open log)
+  Model file: /models/ggml__base.bin
+  ---
+  _Stop daemon
+"));
+    }
+    
+    #[test]
+    fn every_action_has_its_own_access_key() {
+        let tray = tray("mock", "base.en", false, no_ready);
+        let menu = tray.menu();
+        let actions = menu.iter().skip_while(|item| !matches!(item, MenuItem::Separator)).filter_map(|item| match item {
+            MenuItem::Standard(item) => Some(item.label.as_str()),
+            MenuItem::Checkmark(item) => Some(item.label.as_str()),
+            MenuItem::SubMenu(menu) => Some(menu.label.as_str()),
+            _ => None,
+        });
+        let mut keys = vec!['r'];
+        for label in actions {
+            let key = label.replace("__", "").split_once('_').and_then(|(_, rest)| rest.chars().next());
+            let key = key.unwrap_or_else(|| panic!("{:?} has no access key", label)).to_ascii_lowercase();
+            assert!(!keys.contains(&key), "{:?} reuses access key {:?}", label, key);
+            keys.push(key);
+        }
+        assert!(keys.len() >= 7, "{:?}", keys);
+    }
+    
+    #[test]
+    fn start_stop_and_switch_interleave_without_blocking_readers() {
+        let backends = [format!("stress-a-{}", std::process::id()), format!("stress-b-{}", std::process::id())];
+        let mut tray = VoiceInputTray::unsaved(backends[0].clone(), Some("tiny".to_string()), Some("en".to_string()));
+        tray.spawner = stub_spawner;
+        let tray = Arc::new(tray);
+        
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader = {
+            let (tray, done) = (Arc::clone(&tray), Arc::clone(&done));
+            std::thread::spawn(move || {
+                let mut slowest = Duration::ZERO;
+                while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                    let asked = Instant::now();
+                    let _ = tray.status();
+                    let _ = tray.daemon_type();
+                    slowest = slowest.max(asked.elapsed());
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                slowest
+            })
+        };
+        
+        let workers: Vec<_> = (0..3)
+            .map(|worker| {
+                let (tray, backends) = (Arc::clone(&tray), backends.clone());
+                std::thread::spawn(move || {
+                    for step in 0..6 {
+                        let result = match (worker + step) % 4 {
+                            0 => tray.in_turn(|notices| tray.bring_up(notices)).0,
+                            1 => tray.in_turn(|notices| tray.shut_down(notices)).0,
+                            2 => tray.in_turn(|notices| tray.switch_to(&backends[step % 2], notices)).0,
+                            _ => tray.in_turn(|notices| {
+                                if tray.status().running { tray.shut_down(notices) } else { tray.bring_up(notices) }
+                            }).0,
+                        };
+                        result.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        done.store(true, std::sync::atomic::Ordering::SeqCst);
+        let slowest = reader.join().unwrap();
+        
+        // Reading the state never waited out a daemon's startup
+        assert!(slowest < STUB_STARTUP / 2, "a status read took {:?}", slowest);
+        
+        // Whatever happened last, the state agrees with the processes: at most
+        // one daemon, the one the tray holds, and only while it says running
+        let (running, daemon_type, current) = {
+            let mut shared = tray.shared.lock().unwrap();
+            let current = shared.daemon_process.as_mut().and_then(|child| match child.try_wait() {
+                Ok(None) => Some(child.id()),
+                _ => None,
+            });
+            (shared.status.running, shared.daemon_type.clone(), current)
+        };
+        assert_eq!(running, current.is_some());
+        let alive: Vec<u32> = SPAWNED.lock().unwrap().iter().copied().filter(|pid| crate::helpers::is_process_running(*pid)).collect();
+        assert_eq!(alive, current.into_iter().collect::<Vec<_>>());
+        if running {
+            let info = ready::read(&daemons::default_ready_file(&daemon_type)).unwrap();
+            assert_eq!(Some(info.pid), current);
+        }
+        
+        tray.in_turn(|notices| tray.shut_down(notices)).0.unwrap();
+        for backend in &backends {
+            ready::remove(&daemons::default_ready_file(backend));
+        }
+    }
+    
+    #[test]
+    fn tray_state_round_trips_with_the_profile() {
+        let tray = tray("faster-whisper", "small.en", true, no_ready);
+        {
+            let mut shared = tray.shared.lock().unwrap();
+            shared.status.profile = Some("work".to_string());
+            shared.status.output_mode = OutputMode::Clipboard;
+            shared.status.sensitivity = Sensitivity::Strict;
+            shared.status.vad = true;
+        }
+        let state = tray.shared.lock().unwrap().tray_state();
+        let json = serde_json::to_string_pretty(&state).unwrap();
+        let read: TrayState = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, state);
+        assert_eq!(read.profile.as_deref(), Some("work"));
+        assert_eq!(read.backend, "faster-whisper");
+        assert_eq!(read.output_mode, Some(OutputMode::Clipboard));
+        
+        // Without a profile there is no profile key for older clients to trip on
+        tray.shared.lock().unwrap().status.profile = None;
+        let json = serde_json::to_string(&tray.shared.lock().unwrap().tray_state()).unwrap();
+        assert!(!json.contains("profile"), "{}", json);
+    }
+    
+    #[test]
+    fn tray_state_from_before_profiles_still_loads() {
+        let read: TrayState = serde_json::from_str(r#"{"model": "base.en", "backend": "whisper-cpp", "language": "de"}"#).unwrap();
+        assert_eq!(read.model, "base.en");
+        assert_eq!(read.language.as_deref(), Some("de"));
+        assert_eq!(read.profile, None);
+        assert_eq!(read.output_mode, None);
+    }
+    
+    /// The state file a tray with these settings would leave for clients
+    fn state_file(output_mode: OutputMode, sensitivity: Sensitivity, vad: bool, auto_stop: bool) -> String {
+        let tray = tray("whisper-cpp", "base.en", false, no_ready);
+        {
+            let mut shared = tray.shared.lock().unwrap();
+            shared.status.output_mode = output_mode;
+            shared.status.sensitivity = sensitivity;
+            shared.status.vad = vad;
+            shared.status.auto_stop = auto_stop;
+        }
+        let state = tray.shared.lock().unwrap().tray_state();
+        serde_json::to_string_pretty(&state).unwrap()
+    }
+    
+    #[test]
+    fn output_mode_round_trips_through_the_state_file() {
+        for mode in [OutputMode::Type, OutputMode::Clipboard] {
+            let json = state_file(mode, Sensitivity::Normal, false, false);
+            let read: TrayState = serde_json::from_str(&json).unwrap();
+            assert_eq!(read.output_mode, Some(mode));
+        }
+        assert!(state_file(OutputMode::Clipboard, Sensitivity::Normal, false, false).contains(r#""output_mode": "clipboard""#));
+        
+        // Files from before the toggle leave the mode to WA_OUTPUT_MODE and the default
+        let read: TrayState = serde_json::from_str(r#"{"model": "base.en", "backend": "faster-whisper"}"#).unwrap();
+        assert_eq!(read.output_mode, None);
+        assert_eq!(read.output_mode.unwrap_or_default(), OutputMode::Type);
+    }
+    
+    #[test]
+    fn recording_settings_round_trip_through_the_state_file() {
+        for (sensitivity, vad, auto_stop) in [(Sensitivity::Strict, true, false), (Sensitivity::Lenient, false, true), (Sensitivity::Normal, true, true)] {
+            let json = state_file(OutputMode::Type, sensitivity, vad, auto_stop);
+            let read: TrayState = serde_json::from_str(&json).unwrap();
+            assert_eq!((read.sensitivity, read.vad, read.auto_stop), (Some(sensitivity), Some(vad), Some(auto_stop)));
+        }
+        assert!(state_file(OutputMode::Type, Sensitivity::Strict, false, false).contains(r#""sensitivity": "strict""#));
+        
+        // Files from before the menu leave the settings to the environment
+        let read: TrayState = serde_json::from_str(r#"{"model": "base.en", "backend": "faster-whisper", "output_mode": "type"}"#).unwrap();
+        assert_eq!((read.sensitivity, read.vad, read.auto_stop), (None, None, None));
+    }
+    
+    #[test]
+    fn profile_ranks_between_command_line_and_tray_state() {
+        use crate::helpers::{choose_setting, Source};
+        let state = || Source::TrayState { path: "whisp-away-state.json".to_string(), modified: None };
+        let fallbacks = |state_model: Option<&str>| vec![
+            (state(), state_model.map(str::to_string)),
+            (Source::Env("WA_WHISPER_MODEL"), Some("tiny.en".to_string())),
+            (Source::Default, Some("base.en".to_string())),
+        ];
+        let work = || Some(("work".to_string(), Some("medium".to_string())));
+        
+        let chosen = choose_setting(Some("large-v3".to_string()), work(), fallbacks(Some("small.en")));
+        assert_eq!((chosen.value.as_str(), chosen.source), ("large-v3", Source::CommandLine));
+        
+        let chosen = choose_setting(None, work(), fallbacks(Some("small.en")));
+        assert_eq!((chosen.value.as_str(), chosen.source), ("medium", Source::Profile("work".to_string())));
+        
+        // A profile that doesn't set the model, or no profile at all, leaves
+        // the precedence as it was
+        let chosen = choose_setting(None, Some(("work".to_string(), None)), fallbacks(Some("small.en")));
+        assert_eq!((chosen.value.as_str(), chosen.source), ("small.en", state()));
+        let chosen = choose_setting(None, None, fallbacks(Some("small.en")));
+        assert_eq!((chosen.value.as_str(), chosen.source), ("small.en", state()));
+        assert_eq!(chosen.chain.len(), 4);
+        
+        // Empty values count as unset
+        let chosen = choose_setting(Some(String::new()), Some(("work".to_string(), Some(String::new()))), fallbacks(None));
+        assert_eq!((chosen.value.as_str(), chosen.source), ("tiny.en", Source::Env("WA_WHISPER_MODEL")));
+    }
+}
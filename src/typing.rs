@@ -1,39 +1,920 @@
-use anyhow::{Context, Result};
-use std::process::Command;
-
-/// Type out transcribed text using wtype and show notification
-pub fn type_text(text: &str, wtype_path: &str, backend_name: &str) -> Result<()> {
-    if text.trim().is_empty() {
-        Command::new("notify-send")
-            .args(&[
-                "Voice Input",
-                &format!("⚠️ No speech detected\nBackend: {}", backend_name),
-                "-t", "2000",
-                "-h", "string:x-canonical-private-synchronous:voice"
-            ])
-            .spawn()?;
-        return Ok(());
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Stdio;
+use crate::child_env;
+use crate::focus;
+use crate::helpers::{self, OutputMode};
+use crate::normalize;
+use crate::notify;
+use crate::run::Run;
+
+/// How many times wtype is tried before giving up
+const TYPE_ATTEMPTS: usize = 3;
+/// Pause between wtype attempts
+const TYPE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+/// Text longer than this (in bytes) goes to wtype on stdin rather than as an
+/// argument, well clear of the kernel's per-argument limit
+const STDIN_THRESHOLD_BYTES: usize = 4096;
+/// Transcripts this long are typed in paced chunks with a progress notification
+const LONG_TEXT_CHARS: usize = 3000;
+/// Chunk size for long transcripts when WA_TYPE_CHUNK_CHARS isn't set
+const LONG_TEXT_CHUNK_CHARS: usize = 500;
+/// Pause between chunks so the receiving application keeps up
+const CHUNK_PAUSE: std::time::Duration = std::time::Duration::from_millis(50);
+/// Longest transcript typed when WA_MAX_TYPE_CHARS isn't set; longer ones
+/// would take minutes to type and go to the clipboard instead
+const DEFAULT_MAX_TYPE_CHARS: usize = 20000;
+/// Delay between keystrokes into slow targets when WA_SLOW_TYPE_DELAY_MS isn't set
+const SLOW_TYPE_DELAY_MS: u64 = 15;
+/// Chunk size for slow targets when WA_SLOW_TYPE_CHUNK_CHARS isn't set
+const SLOW_TYPE_CHUNK_CHARS: usize = 40;
+
+/// How much of a transcript made it out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Typed {
+    /// Characters typed, or copied in clipboard output mode
+    pub chars: usize,
+}
+
+/// Why a transcript didn't get typed
+#[derive(Debug)]
+pub enum TypingError {
+    /// The transcript was empty
+    NoText,
+    /// wtype kept failing; the transcript itself is fine and is kept here
+    Failed {
+        text: String,
+        on_clipboard: bool,
+        source: anyhow::Error,
+    },
+    /// wtype died after typing the first `typed` characters; the `remaining`
+    /// ones are kept in `text`
+    Partial {
+        text: String,
+        typed: usize,
+        remaining: usize,
+        on_clipboard: bool,
+        source: anyhow::Error,
+    },
+    /// The focused window is on the never-type-into list (e.g. a password prompt)
+    Refused {
+        text: String,
+        on_clipboard: bool,
+        window: String,
+    },
+    /// Clipboard output mode, and wl-copy failed
+    CopyFailed {
+        text: String,
+        source: anyhow::Error,
+    },
+}
+
+impl TypingError {
+    /// The transcript that didn't get typed, if there was one
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            TypingError::NoText => None,
+            TypingError::Failed { text, .. }
+            | TypingError::Partial { text, .. }
+            | TypingError::Refused { text, .. }
+            | TypingError::CopyFailed { text, .. } => Some(text),
+        }
+    }
+    
+    /// Code of the `error` event it is reported as
+    fn code(&self) -> &'static str {
+        match self {
+            TypingError::NoText => "no_text",
+            TypingError::Failed { .. } => "typing_failed",
+            TypingError::Partial { .. } => "typing_partial",
+            TypingError::Refused { .. } => "typing_refused",
+            TypingError::CopyFailed { .. } => "copy_failed",
+        }
+    }
+}
+
+impl fmt::Display for TypingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypingError::NoText => write!(f, "No speech detected"),
+            TypingError::Failed { on_clipboard: true, source, .. } => {
+                write!(f, "Typing failed, text copied to clipboard: {}", source)
+            }
+            TypingError::Failed { source, .. } => write!(f, "Typing failed: {}", source),
+            TypingError::Partial { typed, remaining, on_clipboard, source, .. } => {
+                let percent = typed * 100 / (typed + remaining).max(1);
+                if *on_clipboard {
+                    write!(f, "Typed {}%, remainder copied to clipboard: {}", percent, source)
+                } else {
+                    write!(f, "Typed {}%, the rest was lost: {}", percent, source)
+                }
+            }
+            TypingError::Refused { on_clipboard: true, window, .. } => {
+                write!(f, "Not typing into {} (password prompt), text copied to clipboard", window)
+            }
+            TypingError::Refused { window, .. } => write!(f, "Not typing into {} (password prompt)", window),
+            TypingError::CopyFailed { source, .. } => write!(f, "Copying to clipboard failed: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for TypingError {}
+
+/// Resolved per transcript, so a long-running `listen` follows the tray
+/// toggle; `output` is `--output`, which wins over it
+pub fn output_mode(output: Option<OutputMode>, profile: Option<&str>) -> OutputMode {
+    helpers::resolve_output_mode(output, profile)
+}
+
+/// How a command's transcripts get out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// wtype to type with
+    pub wtype_path: String,
+    /// `--output`, if given, or the clipboard when there is no wtype
+    pub output: Option<OutputMode>,
+    /// `--notify-preview`, if given
+    pub preview: Option<NotifyPreview>,
+    /// `--profile`, if given
+    pub profile: Option<String>,
+}
+
+/// The typing and clipboard programs, looked up before transcribing so a
+/// missing wtype is found out while the text can still go somewhere else
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tools {
+    /// `--wtype-path` as given, for messages
+    pub wtype_path: String,
+    pub wtype: Option<PathBuf>,
+    pub wl_copy: Option<PathBuf>,
+}
+
+impl Tools {
+    /// Look up `wtype_path` (a name on PATH or a path) and wl-copy
+    pub fn resolve(wtype_path: &str) -> Self {
+        let tools = Self {
+            wtype_path: wtype_path.to_string(),
+            wtype: helpers::find_program(wtype_path),
+            wl_copy: helpers::find_program("wl-copy"),
+        };
+        eprintln!("DEBUG: wtype: {}, wl-copy: {}", tools.describe_wtype(), tools.describe_wl_copy());
+        tools
+    }
+    
+    pub fn describe_wtype(&self) -> String {
+        match &self.wtype {
+            Some(path) => path.display().to_string(),
+            None => format!("{} not found", self.wtype_path),
+        }
+    }
+    
+    pub fn describe_wl_copy(&self) -> String {
+        match &self.wl_copy {
+            Some(path) => path.display().to_string(),
+            None => "not found".to_string(),
+        }
+    }
+}
+
+/// How a transcript is handed to wtype. Some applications garble non-ASCII
+/// text arriving through the compositor's text input (ß and umlauts turning
+/// into mojibake), so it can also be typed as one key per codepoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum UnicodeMode {
+    /// As a command line argument, or on stdin when it is long
+    #[default]
+    Argv,
+    /// Always on wtype's stdin, as UTF-8
+    Stdin,
+    /// Every codepoint as its own Unicode keysym (`-k U00DF`)
+    Codepoints,
+}
+
+/// Languages written with many accented letters, typed codepoint by codepoint
+/// unless a profile or WA_UNICODE_MODE says otherwise
+const CODEPOINT_LANGUAGES: &[&str] = &[
+    "de", "fr", "es", "it", "pt", "ca", "nl", "sv", "da", "no", "nn", "fi", "is",
+    "pl", "cs", "sk", "sl", "hr", "hu", "ro", "tr", "et", "lv", "lt", "vi",
+];
+
+/// The active profile's unicode_mode, else WA_UNICODE_MODE, else that of
+/// `language`, the one transcription was asked for
+fn unicode_mode(profile: Option<&str>, language: &str) -> UnicodeMode {
+    let profile = crate::profile::active(profile).and_then(|profile| profile.unicode_mode);
+    choose_unicode_mode(profile, std::env::var("WA_UNICODE_MODE").ok().as_deref(), language)
+}
+
+/// `profile` if set, else `env` if valid, else codepoints for languages in
+/// CODEPOINT_LANGUAGES and argv for the rest
+fn choose_unicode_mode(profile: Option<UnicodeMode>, env: Option<&str>, language: &str) -> UnicodeMode {
+    use clap::ValueEnum;
+    
+    if let Some(mode) = profile {
+        return mode;
+    }
+    if let Some(value) = env {
+        match UnicodeMode::from_str(value, true) {
+            Ok(mode) => return mode,
+            Err(_) => eprintln!("WARNING: Ignoring WA_UNICODE_MODE={:?}; expected argv, stdin or codepoints", value),
+        }
+    }
+    if CODEPOINT_LANGUAGES.contains(&language) {
+        UnicodeMode::Codepoints
+    } else {
+        UnicodeMode::Argv
+    }
+}
+
+/// Keysym wtype presses for `c`: Unicode keysyms are named U<hex>, except
+/// that line breaks and tabs need their own keys
+fn keysym(c: char) -> String {
+    match c {
+        '\n' => "Return".to_string(),
+        '\t' => "Tab".to_string(),
+        c => format!("U{:04X}", c as u32),
+    }
+}
+
+/// Arguments and stdin bytes that make wtype type `text` in `mode`
+fn wtype_input(text: &str, mode: UnicodeMode) -> (Vec<String>, Option<Vec<u8>>) {
+    match mode {
+        UnicodeMode::Argv if text.len() <= STDIN_THRESHOLD_BYTES => (vec![text.to_string()], None),
+        UnicodeMode::Argv | UnicodeMode::Stdin => (vec!["-".to_string()], Some(text.as_bytes().to_vec())),
+        UnicodeMode::Codepoints => (
+            text.chars().flat_map(|c| ["-k".to_string(), keysym(c)]).collect(),
+            None,
+        ),
+    }
+}
+
+/// Characters of transcript the `firstline` preview shows, about one line of a notification
+const FIRSTLINE_PREVIEW_CHARS: usize = 60;
+/// Characters of transcript the `full` preview shows when no limit is given
+const FULL_PREVIEW_CHARS: usize = 200;
+
+/// How much of the transcript the success notification shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotifyPreview {
+    /// Just "Transcribed"
+    #[default]
+    None,
+    /// About one line of the transcript
+    FirstLine,
+    /// The transcript up to this many characters
+    Full(usize),
+}
+
+impl std::str::FromStr for NotifyPreview {
+    type Err = String;
+    
+    /// "none", "firstline", "full" or "full(MAXCHARS)"
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let value = value.trim().to_lowercase();
+        match value.as_str() {
+            "none" => Ok(NotifyPreview::None),
+            "firstline" => Ok(NotifyPreview::FirstLine),
+            "full" => Ok(NotifyPreview::Full(FULL_PREVIEW_CHARS)),
+            _ => value
+                .strip_prefix("full(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .and_then(|max| max.trim().parse::<usize>().ok())
+                .filter(|max| *max > 0)
+                .map(NotifyPreview::Full)
+                .ok_or_else(|| format!("expected none, firstline, full or full(MAXCHARS), got {:?}", value)),
+        }
+    }
+}
+
+/// `preview` (`--notify-preview`), else WA_NOTIFY_PREVIEW, else no preview
+fn notify_preview(preview: Option<NotifyPreview>) -> NotifyPreview {
+    if let Some(preview) = preview {
+        return preview;
+    }
+    match std::env::var("WA_NOTIFY_PREVIEW") {
+        Ok(value) => value.parse().unwrap_or_else(|e| {
+            eprintln!("WARNING: Ignoring WA_NOTIFY_PREVIEW: {}", e);
+            NotifyPreview::None
+        }),
+        Err(_) => NotifyPreview::None,
+    }
+}
+
+/// Line of transcript for a success notification, empty with previews off.
+/// Notification servers may render the body as markup, so it's escaped.
+fn preview_line(text: &str, preview: Option<NotifyPreview>) -> String {
+    let max_chars = match notify_preview(preview) {
+        NotifyPreview::None => return String::new(),
+        NotifyPreview::FirstLine => FIRSTLINE_PREVIEW_CHARS,
+        NotifyPreview::Full(max_chars) => max_chars,
+    };
+    let preview = normalize::preview(text, max_chars)
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!("\n“{}”", preview)
+}
+
+/// "12.4 s → 31 words" line for a success notification, when the length of
+/// the recording is known
+fn speech_line(text: &str, run: &Run) -> String {
+    match crate::speech::Stats::of(text, run.duration) {
+        Some(stats) => format!("\n🎙️ {}", crate::speech::summary(stats.duration, stats.words)),
+        None => String::new(),
+    }
+}
+
+/// Warning line for a success notification when the recording clipped
+fn clipping_line(run: &Run) -> String {
+    match run.clipped {
+        Some(fraction) => format!("\n⚠️ Audio clipped ({:.0}%), consider lowering the gain (WA_RECORD_GAIN)", fraction * 100.0),
+        None => String::new(),
+    }
+}
+
+/// Run `attempt` up to TYPE_ATTEMPTS times, pausing between tries.
+/// wtype can fail transiently right after an output hotplug.
+fn with_retries(text: &str, mut attempt: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut last_error = None;
+    for n in 1..=TYPE_ATTEMPTS {
+        match attempt(text) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                eprintln!("DEBUG: wtype attempt {}/{} failed: {}", n, TYPE_ATTEMPTS, e);
+                last_error = Some(e);
+            }
+        }
+        if n < TYPE_ATTEMPTS {
+            std::thread::sleep(TYPE_RETRY_DELAY);
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow!("wtype was never run")))
+}
+
+fn env_number<T: std::str::FromStr>(var: &str) -> Option<T> {
+    std::env::var(var).ok().and_then(|value| value.parse().ok())
+}
+
+/// How fast a transcript is typed. Remote desktop and VM viewers drop
+/// keystrokes at full speed, so they get a slower profile of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pacing {
+    /// Passed to `wtype -d` between keystrokes; 0 types at full speed
+    type_delay_ms: u64,
+    /// Type in pieces of about this many characters, one wtype call each, so
+    /// a wtype that dies mid-way leaves a known amount typed. 0 types short
+    /// transcripts in one call and long ones in chunks of LONG_TEXT_CHUNK_CHARS.
+    chunk_size: usize,
+    unicode_mode: UnicodeMode,
+}
+
+impl Pacing {
+    /// WA_TYPE_DELAY_MS and WA_TYPE_CHUNK_CHARS, full speed by default
+    fn normal(profile: Option<&str>, language: &str) -> Self {
+        Pacing {
+            type_delay_ms: env_number("WA_TYPE_DELAY_MS").unwrap_or(0),
+            chunk_size: env_number("WA_TYPE_CHUNK_CHARS").unwrap_or(0),
+            unicode_mode: unicode_mode(profile, language),
+        }
+    }
+    
+    /// WA_SLOW_TYPE_DELAY_MS and WA_SLOW_TYPE_CHUNK_CHARS, for slow targets
+    fn slow(profile: Option<&str>, language: &str) -> Self {
+        Pacing {
+            type_delay_ms: env_number("WA_SLOW_TYPE_DELAY_MS").unwrap_or(SLOW_TYPE_DELAY_MS),
+            chunk_size: env_number("WA_SLOW_TYPE_CHUNK_CHARS").unwrap_or(SLOW_TYPE_CHUNK_CHARS),
+            unicode_mode: unicode_mode(profile, language),
+        }
+    }
+    
+    /// The slow profile when the focused window is a slow target
+    fn for_window(slow_window: Option<&str>, profile: Option<&str>, language: &str) -> Self {
+        match slow_window {
+            Some(_) => Pacing::slow(profile, language),
+            None => Pacing::normal(profile, language),
+        }
+    }
+    
+    fn chunk_chars(&self, text_chars: usize) -> usize {
+        match self.chunk_size {
+            0 if text_chars >= LONG_TEXT_CHARS => LONG_TEXT_CHUNK_CHARS,
+            size => size,
+        }
+    }
+}
+
+/// Success notification line saying a slow target was typed into slowly
+fn pacing_line(slow_window: Option<&str>) -> String {
+    match slow_window {
+        Some(window) => format!("\n🐢 Typed slowly into {} (remote/VM window, WA_SLOW_TARGETS)", window),
+        None => String::new(),
+    }
+}
+
+/// WA_MAX_TYPE_CHARS, or DEFAULT_MAX_TYPE_CHARS
+fn max_type_chars() -> usize {
+    env_number("WA_MAX_TYPE_CHARS").unwrap_or(DEFAULT_MAX_TYPE_CHARS)
+}
+
+/// Split `text` into pieces of at least `size` characters, breaking only after
+/// whitespace so no word is split across wtype calls
+fn split_chunks(text: &str, size: usize) -> Vec<&str> {
+    if size == 0 {
+        return vec![text];
+    }
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut count = 0;
+    for (index, c) in text.char_indices() {
+        count += 1;
+        if count >= size && c.is_whitespace() {
+            let end = index + c.len_utf8();
+            chunks.push(&text[start..end]);
+            start = end;
+            count = 0;
+        }
+    }
+    if start < text.len() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+}
+
+/// Type `text` chunk by chunk, returning how many characters were confirmed
+/// typed along with the error if a chunk failed. Only the first chunk is
+/// retried: once something is on screen, a retry could type a piece twice.
+fn type_chunks(wtype_path: &str, text: &str, pacing: Pacing) -> (usize, Result<()>) {
+    let total = text.chars().count();
+    let mut typed = 0;
+    for (n, chunk) in split_chunks(text, pacing.chunk_chars(total)).into_iter().enumerate() {
+        if n > 0 {
+            std::thread::sleep(CHUNK_PAUSE);
+        }
+        if total >= LONG_TEXT_CHARS {
+            let body = format!("⌨️ Typing... {}% of {} characters", typed * 100 / total, total);
+            notify::Notification::progress("Voice Input", &body).send();
+        }
+        
+        let result = if n == 0 {
+            with_retries(chunk, |chunk| run_wtype(wtype_path, chunk, pacing))
+        } else {
+            run_wtype(wtype_path, chunk, pacing)
+        };
+        if let Err(e) = result {
+            return (typed, Err(e));
+        }
+        typed += chunk.chars().count();
+    }
+    (typed, Ok(()))
+}
+
+/// Run wtype once, treating a non-zero exit as failure. Long text is piped
+/// to `wtype -` so it can't hit the argument length limit (E2BIG).
+fn run_wtype(wtype_path: &str, text: &str, pacing: Pacing) -> Result<()> {
+    let mut cmd = child_env::command(wtype_path, &[]);
+    if pacing.type_delay_ms > 0 {
+        cmd.args(["-d", &pacing.type_delay_ms.to_string()]);
+    }
+    let (args, stdin_bytes) = wtype_input(text, pacing.unicode_mode);
+    cmd.args(args);
+    let output = if let Some(bytes) = stdin_bytes {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run wtype")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&bytes).context("Failed to send text to wtype")?;
+        }
+        child.wait_with_output().context("Failed to run wtype")?
+    } else {
+        cmd.output()
+            .context("Failed to run wtype")?
+    };
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if stderr.is_empty() {
+            return Err(anyhow!("wtype exited with {}", output.status));
+        }
+        return Err(anyhow!("wtype failed: {}", stderr));
+    }
+    Ok(())
+}
+
+/// Put text on the Wayland clipboard so it isn't lost when typing fails
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let mut child = child_env::command("wl-copy", &[])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run wl-copy")?;
+    
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    
+    if !child.wait()?.success() {
+        return Err(anyhow!("wl-copy failed"));
+    }
+    Ok(())
+}
+
+/// Copy text that couldn't be typed to the clipboard, reporting whether that worked
+fn keep_on_clipboard(text: &str) -> bool {
+    match copy_to_clipboard(text) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Failed to copy transcript to clipboard: {}", e);
+            false
+        }
     }
+}
 
+/// Clipboard output mode: copy instead of typing, nothing to check focus for
+fn copy_text(text: String, delivery: &Delivery, run: &mut Run, backend_name: &str) -> Result<Typed, TypingError> {
+    if let Err(source) = copy_to_clipboard(&text) {
+        let error = TypingError::CopyFailed { text, source };
+        notify::Notification::error("Voice Input", &format!("❌ {}\nBackend: {}", error, backend_name))
+            .timeout(5000)
+            .send();
+        return Err(error);
+    }
+    
+    crate::history::record_typed(&text, backend_name, run);
+    crate::last_result::record(&text, backend_name, OutputMode::Clipboard, run);
+    crate::hooks::transcribed(&text, run, backend_name);
+    
+    let body = format!(
+        "📋 Copied to clipboard{}{}{}\nBackend: {}{}",
+        preview_line(&text, delivery.preview),
+        speech_line(&text, run),
+        clipping_line(run),
+        backend_name,
+        run.resolution_note
+    );
+    notify::Notification::normal("Voice Input", &body)
+        .timeout(1000)
+        .send();
+    Ok(Typed { chars: text.chars().count() })
+}
+
+/// Type out transcribed text using wtype (or copy it, in clipboard output
+/// mode) and show notification, noting the outcome in `run`. Dictations
+/// submitted earlier type first.
+pub fn try_type_text(text: &str, delivery: &Delivery, run: &mut Run, backend_name: &str) -> Result<Typed, TypingError> {
+    run.transcribed(text, backend_name);
+    // Which fallback chain step got this far, when it's worth saying
+    let backend_name = &format!("{}{}", backend_name, run.step_note);
+    crate::order::wait_for_turn();
+    crate::events::emit(crate::events::Event::TranscriptionDone { chars: text.trim().chars().count() });
+    let result = deliver(text, delivery, run, backend_name);
+    crate::order::finish_turn();
+    match &result {
+        // An empty transcript is already told by its zero chars
+        Err(TypingError::NoText) | Ok(_) => {}
+        Err(error) => run.fail(error.code()),
+    }
+    result
+}
+
+fn deliver(text: &str, delivery: &Delivery, run: &mut Run, backend_name: &str) -> Result<Typed, TypingError> {
+    // Type exactly what was said: no stray non-breaking or doubled spaces
+    let text = normalize::transcript(text);
+    if text.is_empty() {
+        notify::Notification::normal("Voice Input", &format!("⚠️ No speech detected\nBackend: {}", backend_name))
+            .send();
+        return Err(TypingError::NoText);
+    }
+    // External post-processors get the last word on the text
+    let text = normalize::transcript(&crate::hooks::pre_type(text, run, backend_name));
+    // After normalizing, which would flatten the line breaks commands type
+    let text = crate::replacements::apply(&text, run.output_language().as_deref());
+    // For history, while the window the text goes to still has focus
+    run.target = focus::capture_target();
+    
+    if output_mode(delivery.output, delivery.profile.as_deref()) == OutputMode::Clipboard {
+        return copy_text(text, delivery, run, backend_name);
+    }
+
+    // Checked right before typing, as focus may have moved while transcribing
+    if let Some(window) = focus::denied_focused_window() {
+        let on_clipboard = keep_on_clipboard(&text);
+        let error = TypingError::Refused { text, on_clipboard, window };
+        notify::Notification::error("Voice Input", &format!("🔒 {}\nBackend: {}", error, backend_name))
+            .timeout(5000)
+            .send();
+        return Err(error);
+    }
+    
+    // Typing this much would take minutes and tie up the focused window
+    let chars = text.chars().count();
+    if chars > max_type_chars() {
+        let on_clipboard = keep_on_clipboard(&text);
+        let source = anyhow!(
+            "{} characters is too long to type (WA_MAX_TYPE_CHARS is {}); use --output clipboard for long recordings",
+            chars,
+            max_type_chars()
+        );
+        let error = TypingError::Failed { text, on_clipboard, source };
+        notify::Notification::error("Voice Input", &format!("❌ {}\nBackend: {}", error, backend_name))
+            .timeout(5000)
+            .send();
+        return Err(error);
+    }
+    
+    // Remote desktops and VM viewers drop characters typed at full speed
+    let slow_window = focus::slow_focused_window();
+    let pacing = Pacing::for_window(slow_window.as_deref(), delivery.profile.as_deref(), &run.language);
+    if let Some(window) = &slow_window {
+        eprintln!("DEBUG: {} is a slow target, typing with {:?}", window, pacing);
+    } else if pacing.unicode_mode != UnicodeMode::Argv {
+        eprintln!("DEBUG: Typing with {:?}", pacing.unicode_mode);
+    }
+    
     // Small delay before typing
     std::thread::sleep(std::time::Duration::from_millis(30));
     
     // Type the text
-    Command::new(wtype_path)
-        .arg(text.trim())
-        .spawn()
-        .context("Failed to run wtype")?
-        .wait()?;
+    let (typed, result) = type_chunks(&delivery.wtype_path, &text, pacing);
+    if let Err(source) = result {
+        let error = if typed == 0 {
+            let on_clipboard = keep_on_clipboard(&text);
+            TypingError::Failed { text, on_clipboard, source }
+        } else {
+            // Only what's missing goes to the clipboard, ready to paste after it
+            let remainder: String = text.chars().skip(typed).collect();
+            let on_clipboard = keep_on_clipboard(&remainder);
+            let remaining = remainder.chars().count();
+            TypingError::Partial { text: remainder, typed, remaining, on_clipboard, source }
+        };
+        notify::Notification::error("Voice Input", &format!("❌ {}\nBackend: {}", error, backend_name))
+            .timeout(5000)
+            .send();
+        return Err(error);
+    }
+    
+    crate::history::record_typed(&text, backend_name, run);
+    crate::last_result::record(&text, backend_name, OutputMode::Type, run);
+    crate::hooks::transcribed(&text, run, backend_name);
     
     // Show success notification
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input",
-            &format!("✅ Transcribed\nBackend: {}", backend_name),
-            "-t", "1000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
+    let body = format!(
+        "✅ Transcribed{}{}{}{}\nBackend: {}{}",
+        preview_line(&text, delivery.preview),
+        speech_line(&text, run),
+        clipping_line(run),
+        pacing_line(slow_window.as_deref()),
+        backend_name,
+        run.resolution_note
+    );
+    notify::Notification::normal("Voice Input", &body)
+        .timeout(1000)
+        .send();
 
-    Ok(())
-}
\ No newline at end of file
+    Ok(Typed { chars: typed })
+}
+
+/// Like try_type_text, but an empty transcript isn't an error. A typing failure
+/// stays a TypingError inside the anyhow::Error so callers can downcast to it.
+pub fn type_text(text: &str, delivery: &Delivery, run: &mut Run, backend_name: &str) -> Result<Typed> {
+    match try_type_text(text, delivery, run, backend_name) {
+        Ok(typed) => Ok(typed),
+        Err(TypingError::NoText) => Ok(Typed::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    
+    /// A wtype stand-in in a directory of its own. Each call that gets past
+    /// the shell test `fails` (`$n` is the call's number, from 1) logs its
+    /// arguments as a line of `calls` and appends the text to `typed`.
+    fn fake_typer(name: &str, fails: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("whisp-away-test-{}-typer-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wtype");
+        std::fs::write(&path, format!(r#"#!/bin/sh
+cd "{}"
+n=$(( $(cat count 2>/dev/null || echo 0) + 1 ))
+echo "$n" > count
+if {}; then
+    echo "failed to connect to display" >&2
+    exit 1
+fi
+for text; do :; done
+[ "$text" = - ] && text=$(cat)
+echo "$*" >> calls
+printf '%s' "$text" >> typed
+"#, dir.display(), fails)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+    
+    /// What the fake typer next to `typer` logged in `file`
+    fn logged(typer: &std::path::Path, file: &str) -> String {
+        std::fs::read_to_string(typer.with_file_name(file)).unwrap_or_default()
+    }
+    
+    /// "é" spelled as e and a combining acute accent, then an emoji with a
+    /// skin tone modifier, a line break and a tab
+    const TRICKY: &str = "Cafe\u{301} \u{1F44B}\u{1F3FD}\n\tß";
+    
+    #[test]
+    fn argv_passes_the_text_as_one_argument() {
+        let (args, stdin) = wtype_input(TRICKY, UnicodeMode::Argv);
+        assert_eq!(args, vec![TRICKY.to_string()]);
+        assert_eq!(
+            args[0].as_bytes(),
+            b"Cafe\xcc\x81 \xf0\x9f\x91\x8b\xf0\x9f\x8f\xbd\n\t\xc3\x9f"
+        );
+        assert_eq!(stdin, None);
+    }
+    
+    #[test]
+    fn argv_moves_long_text_to_stdin() {
+        let long = "ü".repeat(STDIN_THRESHOLD_BYTES / 2 + 1);
+        let (args, stdin) = wtype_input(&long, UnicodeMode::Argv);
+        assert_eq!(args, vec!["-"]);
+        assert_eq!(stdin, Some(long.into_bytes()));
+    }
+    
+    #[test]
+    fn stdin_sends_the_utf8_bytes() {
+        let (args, stdin) = wtype_input(TRICKY, UnicodeMode::Stdin);
+        assert_eq!(args, vec!["-"]);
+        assert_eq!(
+            stdin.as_deref(),
+            Some(&b"Cafe\xcc\x81 \xf0\x9f\x91\x8b\xf0\x9f\x8f\xbd\n\t\xc3\x9f"[..])
+        );
+    }
+    
+    #[test]
+    fn codepoints_press_one_keysym_per_codepoint() {
+        let (args, stdin) = wtype_input(TRICKY, UnicodeMode::Codepoints);
+        let expected: Vec<String> = [
+            "U0043", "U0061", "U0066", "U0065", "U0301", "U0020", "U1F44B", "U1F3FD", "Return", "Tab", "U00DF",
+        ]
+        .iter()
+        .flat_map(|keysym| ["-k".to_string(), keysym.to_string()])
+        .collect();
+        assert_eq!(args, expected);
+        assert_eq!(stdin, None);
+    }
+    
+    #[test]
+    fn the_profile_then_the_environment_then_the_language_decide() {
+        assert_eq!(choose_unicode_mode(Some(UnicodeMode::Stdin), Some("argv"), "de"), UnicodeMode::Stdin);
+        assert_eq!(choose_unicode_mode(None, Some("STDIN"), "de"), UnicodeMode::Stdin);
+        assert_eq!(choose_unicode_mode(None, Some("keys"), "de"), UnicodeMode::Codepoints);
+        assert_eq!(choose_unicode_mode(None, None, "de"), UnicodeMode::Codepoints);
+        assert_eq!(choose_unicode_mode(None, None, "fr"), UnicodeMode::Codepoints);
+        assert_eq!(choose_unicode_mode(None, None, "en"), UnicodeMode::Argv);
+        assert_eq!(choose_unicode_mode(None, None, "auto"), UnicodeMode::Argv);
+    }
+    
+    #[test]
+    fn text_moves_to_stdin_past_the_threshold() {
+        let at = "a".repeat(STDIN_THRESHOLD_BYTES);
+        assert_eq!(wtype_input(&at, UnicodeMode::Argv).1, None);
+        let past = "a".repeat(STDIN_THRESHOLD_BYTES + 1);
+        assert_eq!(wtype_input(&past, UnicodeMode::Argv).0, vec!["-"]);
+    }
+    
+    #[test]
+    fn long_transcripts_are_typed_in_chunks() {
+        let normal = Pacing { type_delay_ms: 0, chunk_size: 0, unicode_mode: UnicodeMode::Argv };
+        assert_eq!(normal.chunk_chars(LONG_TEXT_CHARS - 1), 0);
+        assert_eq!(normal.chunk_chars(LONG_TEXT_CHARS), LONG_TEXT_CHUNK_CHARS);
+        // A chunk size that is set applies to short transcripts too
+        let paced = Pacing { chunk_size: 40, ..normal };
+        assert_eq!(paced.chunk_chars(10), 40);
+        assert_eq!(paced.chunk_chars(LONG_TEXT_CHARS), 40);
+    }
+    
+    #[test]
+    fn chunks_break_after_whitespace_and_cover_the_text() {
+        let text = "one two three four five";
+        assert_eq!(split_chunks(text, 5), ["one two ", "three ", "four ", "five"]);
+        assert_eq!(split_chunks(text, 0), [text]);
+        assert_eq!(split_chunks("unbreakable", 3), ["unbreakable"]);
+        assert_eq!(split_chunks("über öl äh", 3), ["über ", "öl ", "äh"]);
+        
+        let long = "word ".repeat(LONG_TEXT_CHARS / 5 * 2);
+        let chunks = split_chunks(&long, LONG_TEXT_CHUNK_CHARS);
+        assert_eq!(chunks.len(), long.len() / LONG_TEXT_CHUNK_CHARS);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() == LONG_TEXT_CHUNK_CHARS));
+        assert_eq!(chunks.concat(), long);
+    }
+    
+    #[test]
+    fn slow_targets_get_the_slow_pacing() {
+        assert_eq!(Pacing::for_window(Some("looking-glass-client"), None, "en"), Pacing::slow(None, "en"));
+        assert_eq!(Pacing::for_window(None, None, "en"), Pacing::normal(None, "en"));
+        assert!(pacing_line(Some("looking-glass-client")).contains("looking-glass-client"));
+        assert_eq!(pacing_line(None), "");
+    }
+    
+    #[test]
+    fn pacing_reaches_the_typer() {
+        let typer = fake_typer("pacing", "false");
+        let text = "the quick brown fox jumps over the lazy dog";
+        let pacing = Pacing { type_delay_ms: 15, chunk_size: 10, unicode_mode: UnicodeMode::Argv };
+        let (typed, result) = type_chunks(typer.to_str().unwrap(), text, pacing);
+        result.unwrap();
+        assert_eq!(typed, text.chars().count());
+        assert_eq!(logged(&typer, "typed"), text);
+        // Chunks end in the space they were split after
+        assert_eq!(
+            logged(&typer, "calls").lines().collect::<Vec<_>>(),
+            ["-d 15 the quick ", "-d 15 brown fox ", "-d 15 jumps over ", "-d 15 the lazy dog"]
+        );
+        let _ = std::fs::remove_dir_all(typer.parent().unwrap());
+        
+        // Full speed passes no delay and types in one call
+        let typer = fake_typer("full-speed", "false");
+        let pacing = Pacing { type_delay_ms: 0, chunk_size: 0, unicode_mode: UnicodeMode::Stdin };
+        type_chunks(typer.to_str().unwrap(), text, pacing).1.unwrap();
+        assert_eq!(logged(&typer, "calls"), "-\n");
+        assert_eq!(logged(&typer, "typed"), text);
+        let _ = std::fs::remove_dir_all(typer.parent().unwrap());
+    }
+    
+    #[test]
+    fn a_typer_that_fails_fewer_times_than_the_attempts_gets_there() {
+        let typer = fake_typer("flaky", "[ $n -le 2 ]");
+        let (typed, result) = type_chunks(typer.to_str().unwrap(), "hello world", Pacing::normal(None, "en"));
+        result.unwrap();
+        assert_eq!(typed, 11);
+        assert_eq!(logged(&typer, "count").trim(), "3");
+        assert_eq!(logged(&typer, "typed"), "hello world");
+        let _ = std::fs::remove_dir_all(typer.parent().unwrap());
+    }
+    
+    #[test]
+    fn a_typer_that_keeps_failing_gives_up_after_the_attempts() {
+        let typer = fake_typer("broken", "true");
+        let (typed, result) = type_chunks(typer.to_str().unwrap(), "hello world", Pacing::normal(None, "en"));
+        let error = result.unwrap_err();
+        assert_eq!(typed, 0);
+        assert!(error.to_string().contains("failed to connect to display"), "{}", error);
+        assert_eq!(logged(&typer, "count").trim(), TYPE_ATTEMPTS.to_string());
+        assert_eq!(logged(&typer, "typed"), "");
+        let _ = std::fs::remove_dir_all(typer.parent().unwrap());
+    }
+    
+    #[test]
+    fn retries_stop_at_the_first_success() {
+        let mut attempts = 0;
+        with_retries("text", |_| {
+            attempts += 1;
+            if attempts < 2 { Err(anyhow!("failed to connect to display")) } else { Ok(()) }
+        }).unwrap();
+        assert_eq!(attempts, 2);
+    }
+    
+    #[test]
+    fn a_typing_failure_keeps_the_text_and_stays_a_typing_error() {
+        let error = TypingError::Failed { text: "hello".to_string(), on_clipboard: true, source: anyhow!("wtype exited with 1") };
+        assert_eq!(error.text(), Some("hello"));
+        assert_eq!(error.to_string(), "Typing failed, text copied to clipboard: wtype exited with 1");
+        // What the client checks to skip the transcription fallback
+        let error = anyhow::Error::from(error);
+        assert!(error.downcast_ref::<TypingError>().is_some());
+        assert_eq!(TypingError::NoText.text(), None);
+    }
+    
+    #[test]
+    fn a_typer_that_dies_mid_way_reports_what_was_typed() {
+        let typer = fake_typer("dies", "[ $n -gt 2 ]");
+        let text = "the quick brown fox jumps over the lazy dog";
+        let pacing = Pacing { type_delay_ms: 0, chunk_size: 10, unicode_mode: UnicodeMode::Argv };
+        let (typed, result) = type_chunks(typer.to_str().unwrap(), text, pacing);
+        assert!(result.is_err());
+        assert_eq!(typed, "the quick brown fox ".len());
+        assert_eq!(logged(&typer, "typed"), "the quick brown fox ");
+        // Past the first chunk a failure isn't retried, or a piece could be typed twice
+        assert_eq!(logged(&typer, "count").trim(), "3");
+        let _ = std::fs::remove_dir_all(typer.parent().unwrap());
+    }
+    
+    #[test]
+    fn a_partial_failure_tells_how_far_it_got() {
+        let partial = |on_clipboard| TypingError::Partial {
+            text: "lazy dog".to_string(),
+            typed: 12,
+            remaining: 18,
+            on_clipboard,
+            source: anyhow!("wtype exited with 1"),
+        };
+        assert_eq!(partial(true).to_string(), "Typed 40%, remainder copied to clipboard: wtype exited with 1");
+        assert_eq!(partial(false).to_string(), "Typed 40%, the rest was lost: wtype exited with 1");
+        assert_eq!(partial(true).text(), Some("lazy dog"));
+        assert_eq!(partial(true).code(), "typing_partial");
+    }
+}
@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::fs;
+use crate::helpers;
+use crate::notify;
+
+/// Version of this build, which daemons report in their hello and ready file
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What the daemon runs, e.g. "whisp-away 0.3.0", when it isn't this build.
+/// Every daemon reports its version, so one that doesn't predates that.
+pub fn mismatch(daemon_version: Option<&str>) -> Option<String> {
+    match daemon_version {
+        Some(version) if version == VERSION => None,
+        Some(version) => Some(format!("whisp-away {}", version)),
+        None => Some("an older whisp-away".to_string()),
+    }
+}
+
+/// Order two versions like semver: numerically part by part, so 1.10 is
+/// after 1.9, and a pre-release (1.0.0-rc.1) before its release. None when
+/// either doesn't parse.
+pub fn compare(a: &str, b: &str) -> Option<Ordering> {
+    let (a_release, a_pre) = split_version(a)?;
+    let (b_release, b_pre) = split_version(b)?;
+    let width = a_release.len().max(b_release.len());
+    let part = |release: &[u64], i: usize| release.get(i).copied().unwrap_or(0);
+    for i in 0..width {
+        match part(&a_release, i).cmp(&part(&b_release, i)) {
+            Ordering::Equal => {}
+            order => return Some(order),
+        }
+    }
+    Some(match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a_pre), Some(b_pre)) => compare_pre_release(a_pre, b_pre),
+    })
+}
+
+/// Release numbers and pre-release of a version, without build metadata
+fn split_version(version: &str) -> Option<(Vec<u64>, Option<&str>)> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split('+').next()?;
+    let (release, pre) = match version.split_once('-') {
+        Some((release, pre)) => (release, Some(pre)),
+        None => (version, None),
+    };
+    let release = release.split('.').map(|part| part.parse().ok()).collect::<Option<Vec<u64>>>()?;
+    Some((release, pre))
+}
+
+/// Semver pre-release order: numeric identifiers numerically and before
+/// alphanumeric ones, which compare as text; more identifiers come later
+fn compare_pre_release(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        let order = match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_id), Some(b_id)) => match (a_id.parse::<u64>(), b_id.parse::<u64>()) {
+                (Ok(a_num), Ok(b_num)) => a_num.cmp(&b_num),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a_id.cmp(b_id),
+            },
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// Last mismatch the user was told about, as "<daemon> -> <this build>"
+fn notice_file() -> String {
+    helpers::runtime_file("whisp-away-version-notice")
+}
+
+/// Whether the mismatch between what the daemon runs and this build is new,
+/// recording it as told. Only the latest one is kept, so the next upgrade is
+/// announced again.
+fn first_notice(notice_file: &str, daemon: &str) -> bool {
+    let key = format!("{} -> {}", daemon, VERSION);
+    if fs::read_to_string(notice_file).is_ok_and(|seen| seen.trim() == key) {
+        return false;
+    }
+    if let Err(e) = fs::write(notice_file, &key) {
+        eprintln!("WARNING: Could not record the version notice: {}", e);
+    }
+    true
+}
+
+/// Suggest restarting a daemon that runs another whisp-away than this one,
+/// e.g. one started before an upgrade. The notification is sent once per
+/// mismatch; every check is logged.
+pub fn warn_once(daemon_version: Option<&str>) {
+    let Some(daemon) = mismatch(daemon_version) else {
+        return;
+    };
+    eprintln!("WARNING: The daemon runs {}, this is {}", daemon, VERSION);
+    if !first_notice(&notice_file(), &daemon) {
+        return;
+    }
+    // A newer daemon means this binary is the one left over from before the upgrade
+    let hint = if daemon_version.and_then(|version| compare(version, VERSION)) == Some(Ordering::Greater) {
+        "Restart whatever runs this whisp-away (e.g. the tray) to pick up the upgrade"
+    } else {
+        "Restart it to pick up the upgrade (tray: stop and start the daemon)"
+    };
+    notify::Notification::normal("Voice Input", &format!(
+        "🔄 The daemon runs {}, this is {}\n{}",
+        daemon, VERSION, hint
+    ))
+        .timeout(10000)
+        .tag(None)
+        .send();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_versions_numerically() {
+        assert_eq!(compare("1.10.0", "1.9.0"), Some(Ordering::Greater));
+        assert_eq!(compare("0.3.9", "0.3.10"), Some(Ordering::Less));
+        assert_eq!(compare("2.0.0", "1.99.99"), Some(Ordering::Greater));
+        assert_eq!(compare("1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(compare("v1.2.0", "1.2.0+nix"), Some(Ordering::Equal));
+        assert_eq!(compare("1.2.x", "1.2.0"), None);
+    }
+
+    #[test]
+    fn orders_pre_releases_before_their_release() {
+        assert_eq!(compare("1.0.0-rc.1", "1.0.0"), Some(Ordering::Less));
+        assert_eq!(compare("1.0.0-rc.1", "0.9.0"), Some(Ordering::Greater));
+        assert_eq!(compare("1.0.0-rc.10", "1.0.0-rc.9"), Some(Ordering::Greater));
+        assert_eq!(compare("1.0.0-alpha", "1.0.0-beta"), Some(Ordering::Less));
+        assert_eq!(compare("1.0.0-alpha", "1.0.0-alpha.1"), Some(Ordering::Less));
+        assert_eq!(compare("1.0.0-1", "1.0.0-alpha"), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn reports_a_mismatch_only_for_another_version() {
+        assert_eq!(mismatch(Some(VERSION)), None);
+        assert_eq!(mismatch(Some("0.0.1")).as_deref(), Some("whisp-away 0.0.1"));
+        assert_eq!(mismatch(None).as_deref(), Some("an older whisp-away"));
+    }
+
+    #[test]
+    fn notices_each_mismatch_once() {
+        let path = std::env::temp_dir().join(format!("whisp-away-test-{}-version-notice", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        assert!(first_notice(path, "whisp-away 0.0.1"));
+        assert!(!first_notice(path, "whisp-away 0.0.1"));
+        // Another mismatch replaces the one recorded, and is told again
+        assert!(first_notice(path, "whisp-away 0.0.2"));
+        assert!(!first_notice(path, "whisp-away 0.0.2"));
+        assert!(first_notice(path, "whisp-away 0.0.1"));
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,637 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::time::Duration;
+use crate::audio::{Buffer, Pipeline, Stage, HIGH_PASS_HZ};
+
+/// Sample rate whisper expects
+pub const TARGET_RATE: u32 = 16000;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    S16,
+    S24,
+    S32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    fn bytes(&self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::S16 => 2,
+            SampleFormat::S24 => 3,
+            SampleFormat::S32 | SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+
+    fn decode(&self, b: &[u8]) -> f32 {
+        match self {
+            SampleFormat::U8 => (b[0] as f32 - 128.0) / 128.0,
+            SampleFormat::S16 => i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32,
+            SampleFormat::S24 => (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_607.0,
+            SampleFormat::S32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / i32::MAX as f32,
+            SampleFormat::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            SampleFormat::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        }
+    }
+}
+
+/// Format of the audio data in a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+}
+
+/// What start_recording asks pw-record for
+pub const EXPECTED_FORMAT: WavFormat = WavFormat {
+    sample_rate: TARGET_RATE,
+    channels: 1,
+    sample_format: SampleFormat::S16,
+};
+
+impl fmt::Display for WavFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = format!("{:?}", self.sample_format).to_lowercase();
+        write!(f, "{} Hz {} {}ch", self.sample_rate, format, self.channels)
+    }
+}
+
+/// Parsed WAV file: its format, the raw sample bytes, and where they start
+pub struct Wav<'a> {
+    pub format: WavFormat,
+    pub data: &'a [u8],
+    pub data_offset: usize,
+}
+
+impl Wav<'_> {
+    /// Whole frames in the data chunk
+    pub fn frames(&self) -> usize {
+        self.data.len() / (self.format.sample_format.bytes() * self.format.channels as usize)
+    }
+
+    /// Length of the audio, from the size of the data chunk, to the sample
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos((self.frames() as u128 * 1_000_000_000 / self.format.sample_rate as u128) as u64)
+    }
+
+    /// Each channel decoded to f32 samples at the file's rate
+    pub fn channels(&self) -> Vec<Vec<f32>> {
+        let sample_bytes = self.format.sample_format.bytes();
+        let mut channels = vec![Vec::with_capacity(self.frames()); self.format.channels as usize];
+        for frame in self.data.chunks_exact(sample_bytes * channels.len()) {
+            for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(sample_bytes)) {
+                channel.push(self.format.sample_format.decode(sample));
+            }
+        }
+        channels
+    }
+}
+
+/// How much audio a recording holds, measured against a minimum length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioLength {
+    /// Not a single frame, whatever the file size
+    Empty,
+    /// Some audio, but less than the minimum
+    TooShort(Duration),
+    Ok(Duration),
+}
+
+/// Measure the data chunk of a WAV file against `min`
+pub fn measure(bytes: &[u8], min: Duration) -> Result<AudioLength> {
+    let wav = parse(bytes)?;
+    if wav.frames() == 0 {
+        return Ok(AudioLength::Empty);
+    }
+    let duration = wav.duration();
+    Ok(if duration < min { AudioLength::TooShort(duration) } else { AudioLength::Ok(duration) })
+}
+
+pub fn measure_file(path: &str, min: Duration) -> Result<AudioLength> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    measure(&bytes, min)
+}
+
+/// Walk the RIFF chunks of a WAV file rather than assuming a 44-byte header
+pub fn parse(bytes: &[u8]) -> Result<Wav<'_>> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(anyhow!("Invalid WAV file: missing RIFF/WAVE header"));
+    }
+
+    let riff_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]]) as usize;
+        let body = pos + 8;
+
+        match id {
+            b"fmt " => {
+                if size < 16 || body + 16 > bytes.len() {
+                    return Err(anyhow!("Invalid WAV file: truncated fmt chunk"));
+                }
+                let fmt = &bytes[body..body + size.min(bytes.len() - body)];
+                let mut tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+                let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+                if tag == WAVE_FORMAT_EXTENSIBLE && fmt.len() >= 26 {
+                    // The real format tag is the first two bytes of the SubFormat GUID
+                    tag = u16::from_le_bytes([fmt[24], fmt[25]]);
+                }
+
+                let sample_format = match (tag, bits) {
+                    (WAVE_FORMAT_PCM, 8) => SampleFormat::U8,
+                    (WAVE_FORMAT_PCM, 16) => SampleFormat::S16,
+                    (WAVE_FORMAT_PCM, 24) => SampleFormat::S24,
+                    (WAVE_FORMAT_PCM, 32) => SampleFormat::S32,
+                    (WAVE_FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+                    (WAVE_FORMAT_IEEE_FLOAT, 64) => SampleFormat::F64,
+                    _ => return Err(anyhow!("Unsupported WAV encoding: format tag {:#06x}, {} bits", tag, bits)),
+                };
+                if channels == 0 || sample_rate == 0 {
+                    return Err(anyhow!("Invalid WAV file: {} channels at {} Hz", channels, sample_rate));
+                }
+                format = Some(WavFormat { sample_rate, channels, sample_format });
+            }
+            b"data" => {
+                let format = format.ok_or_else(|| anyhow!("Invalid WAV file: data chunk before fmt chunk"))?;
+                // pw-record leaves the sizes at 0 or 0xFFFFFFFF if it was killed before
+                // finalizing the header, so trust the file length over the header.
+                // A data size of 0 in a finalized file (the RIFF size matches the
+                // file) is real, though, even with metadata chunks after it.
+                let finalized = riff_size == bytes.len() - 8;
+                let unfinalized_empty = size == 0 && !finalized;
+                let end = if unfinalized_empty || body + size > bytes.len() { bytes.len() } else { body + size };
+                return Ok(Wav { format, data: &bytes[body..end], data_offset: body });
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        pos = body + size + (size & 1);
+    }
+
+    Err(anyhow!("Invalid WAV file: no data chunk"))
+}
+
+/// Encode mono f32 samples as a canonical 44-byte-header s16 WAV
+pub fn encode_s16_mono(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    encode_s16(&[samples], sample_rate)
+}
+
+/// Encode one slice of samples per channel as an interleaved s16 WAV with a
+/// 44-byte header. Shorter channels are padded with silence.
+pub fn encode_s16(channels: &[&[f32]], sample_rate: u32) -> Vec<u8> {
+    let frames = channels.iter().map(|channel| channel.len()).max().unwrap_or(0);
+    let block_align = channels.len() as u16 * 2;
+    let data_len = (frames * block_align as usize) as u32;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(data_len + 36).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+    out.extend_from_slice(&(channels.len() as u16).to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for frame in 0..frames {
+        for channel in channels {
+            let sample = channel.get(frame).copied().unwrap_or(0.0);
+            let s = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+    }
+    out
+}
+
+/// Make sure a recording is 16 kHz s16 mono with a plain 44-byte header,
+/// converting it in place if pw-record negotiated something else
+pub fn normalize_file(path: &str) -> Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    let wav = parse(&bytes)?;
+    if wav.format == EXPECTED_FORMAT && wav.data_offset == 44 {
+        return Ok(());
+    }
+
+    if wav.format != EXPECTED_FORMAT {
+        eprintln!("WARNING: {} was recorded as {} instead of {}; converting", path, wav.format, EXPECTED_FORMAT);
+    }
+    let samples = Pipeline::target().samples(&bytes)?;
+    fs::write(path, encode_s16_mono(&samples, TARGET_RATE))
+        .with_context(|| format!("Failed to write converted recording {}", path))?;
+    Ok(())
+}
+
+/// Make the RIFF and data chunk sizes of a recording match the file, for a
+/// recorder stopped before it wrote them. Only the header is touched, so
+/// long recordings aren't rewritten. Returns the length of the audio.
+pub fn finalize_file(path: &str) -> Result<Duration> {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    let wav = parse(&bytes)?;
+    let riff_size = u32::try_from(bytes.len() - 8).unwrap_or(u32::MAX);
+    let data_size = u32::try_from(wav.data.len()).unwrap_or(u32::MAX);
+    let size_at = |pos: usize| u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+    if size_at(4) == riff_size && size_at(wav.data_offset - 4) == data_size {
+        return Ok(wav.duration());
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open recording {}", path))?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+    file.seek(SeekFrom::Start(wav.data_offset as u64 - 4))?;
+    file.write_all(&data_size.to_le_bytes())
+        .with_context(|| format!("Failed to write the header of {}", path))?;
+    Ok(wav.duration())
+}
+
+/// Length of the audio in a WAV file
+pub fn file_duration(path: &str) -> Result<Duration> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    Ok(parse(&bytes)?.duration())
+}
+
+/// Drop `lead` from the start of a recording in place, never more than the
+/// whole clip, keeping its channels and rate. Returns how much was dropped
+/// and how long the clip was before.
+pub fn trim_lead_file(path: &str, lead: Duration) -> Result<(Duration, Duration)> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    let clip = parse(&bytes)?.duration();
+    let output = Pipeline::new().then(Stage::TrimLead(lead)).run(Buffer::decode(&bytes)?);
+    if output.trimmed.is_zero() {
+        return Ok((Duration::ZERO, clip));
+    }
+
+    let channels: Vec<&[f32]> = output.buffer.channels.iter().map(Vec::as_slice).collect();
+    fs::write(path, encode_s16(&channels, output.buffer.sample_rate))
+        .with_context(|| format!("Failed to write trimmed recording {}", path))?;
+    Ok((output.trimmed, clip))
+}
+
+/// Combine two mono recordings into one stereo file at `left`, `left` in the
+/// left channel and `right` in the right, so each source can be transcribed
+/// on its own later
+pub fn merge_stereo_files(left: &str, right: &str) -> Result<()> {
+    let read = |path: &str| -> Result<Vec<f32>> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+        Pipeline::target().samples(&bytes)
+    };
+    let left_samples = read(left)?;
+    let right_samples = read(right)?;
+    fs::write(left, encode_s16(&[&left_samples, &right_samples], TARGET_RATE))
+        .with_context(|| format!("Failed to write stereo recording {}", left))?;
+    Ok(())
+}
+
+/// Root mean square level of a block of samples
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Samples at least this loud count as clipped; s16 can't go past full scale
+/// and pw-record's gain pins louder input there
+const CLIP_LEVEL: f32 = 0.99;
+
+/// Share of clipped samples above which a recording is worth warning about
+pub const CLIP_WARN_FRACTION: f32 = 0.02;
+
+/// Fraction of samples at or near full scale
+pub fn clipped_fraction(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().filter(|s| s.abs() >= CLIP_LEVEL).count() as f32 / samples.len() as f32
+}
+
+/// High-pass and peak-normalize a 16 kHz recording in place, returning the
+/// normalization gain in dB
+pub fn preprocess_file(path: &str) -> Result<f32> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    let output = Pipeline::target()
+        .then(Stage::HighPass(HIGH_PASS_HZ))
+        .then(Stage::Normalize)
+        .run(Buffer::decode(&bytes)?);
+    fs::write(path, encode_s16_mono(&output.buffer.into_samples(), TARGET_RATE))
+        .with_context(|| format!("Failed to write preprocessed recording {}", path))?;
+    Ok(output.gain_db.unwrap_or(0.0))
+}
+
+/// Speech detection works on 30 ms frames
+const SPAN_FRAME: usize = TARGET_RATE as usize * 30 / 1000;
+/// Level a frame needs to count as speech
+const SPAN_THRESHOLD: f32 = 0.01;
+/// A pause of this many frames (600 ms) ends an utterance
+const SPAN_GAP_FRAMES: usize = 20;
+/// Bursts shorter than this many frames (240 ms) are clicks, not speech
+const SPAN_MIN_FRAMES: usize = 8;
+/// Frames (150 ms) kept either side of an utterance so word edges aren't cut off
+const SPAN_PAD_FRAMES: usize = 5;
+
+/// Split 16 kHz samples into utterances by level, as sample ranges in order.
+/// There are no timestamps from the backends, so this is where the timing of
+/// a channel-diarized transcript comes from.
+pub fn speech_spans(samples: &[f32]) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    // First and last voiced frame of the utterance in progress
+    let mut current: Option<(usize, usize)> = None;
+    for (i, frame) in samples.chunks(SPAN_FRAME).enumerate() {
+        if rms(frame) < SPAN_THRESHOLD {
+            continue;
+        }
+        current = match current {
+            Some((first, last)) if i - last <= SPAN_GAP_FRAMES => Some((first, i)),
+            Some(span) => {
+                spans.push(span);
+                Some((i, i))
+            }
+            None => Some((i, i)),
+        };
+    }
+    spans.extend(current);
+
+    spans
+        .into_iter()
+        .filter(|(first, last)| last - first + 1 >= SPAN_MIN_FRAMES)
+        .map(|(first, last)| {
+            let start = first.saturating_sub(SPAN_PAD_FRAMES) * SPAN_FRAME;
+            let end = ((last + 1 + SPAN_PAD_FRAMES) * SPAN_FRAME).min(samples.len());
+            start..end
+        })
+        .collect()
+}
+
+/// Total length of the 30 ms frames of 16 kHz samples at or above `level`
+pub fn voiced_duration(samples: &[f32], level: f32) -> Duration {
+    let voiced = samples.chunks(SPAN_FRAME).filter(|frame| rms(frame) >= level).map(<[f32]>::len).sum();
+    sample_time(voiced)
+}
+
+/// Position of a 16 kHz sample as a time
+pub fn sample_time(sample: usize) -> Duration {
+    Duration::from_millis(sample as u64 * 1000 / TARGET_RATE as u64)
+}
+
+/// One utterance of a channel-diarized transcript
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub channel: usize,
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+/// Put the turns of every channel into one conversation, ordered by when
+/// each utterance started; at the same instant the lower channel goes first
+pub fn interleave(mut turns: Vec<Turn>) -> Vec<Turn> {
+    turns.sort_by_key(|turn| (turn.start, turn.channel));
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WAV of 32-bit float samples, as pw-record writes when it negotiates
+    /// f32: a 440 Hz tone at half scale on every channel
+    fn float_wav(sample_rate: u32, channels: u16, frames: usize) -> Vec<u8> {
+        let block_align = channels * 4;
+        let data_len = (frames * block_align as usize) as u32;
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(data_len + 36).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&sample_rate.to_le_bytes());
+        out.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        out.extend_from_slice(&block_align.to_le_bytes());
+        out.extend_from_slice(&32u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for frame in 0..frames {
+            let sample = 0.5 * (2.0 * std::f32::consts::PI * 440.0 * frame as f32 / sample_rate as f32).sin();
+            for _ in 0..channels {
+                out.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    fn temp_file(name: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("whisp-away-test-{}-{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn parses_48k_float() {
+        let bytes = float_wav(48000, 2, 4800);
+        let wav = parse(&bytes).unwrap();
+        assert_eq!(wav.format, WavFormat { sample_rate: 48000, channels: 2, sample_format: SampleFormat::F32 });
+        assert_eq!(wav.frames(), 4800);
+        assert_eq!(wav.duration(), Duration::from_millis(100));
+        assert_eq!(wav.channels()[1][0..3], wav.channels()[0][0..3]);
+    }
+
+    #[test]
+    fn converts_a_48k_float_recording() {
+        let path = temp_file("48k-float.wav", &float_wav(48000, 1, 72000));
+        normalize_file(&path).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let wav = parse(&bytes).unwrap();
+        assert_eq!(wav.format, EXPECTED_FORMAT);
+        assert_eq!(wav.data_offset, 44);
+        // 1.5 s at 48 kHz is 24000 samples at 16 kHz
+        assert_eq!(wav.frames(), 24000);
+        let samples = &wav.channels()[0];
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((0.45..=0.51).contains(&peak), "peak {}", peak);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn leaves_a_16k_s16_recording_alone() {
+        let original = encode_s16_mono(&[0.25; 1600], TARGET_RATE);
+        let path = temp_file("16k-s16.wav", &original);
+        normalize_file(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), original);
+        let _ = fs::remove_file(&path);
+    }
+
+    /// `frames` 30 ms frames of silence with a 440 Hz tone over the `voiced`
+    /// frames, each given as (first, end)
+    fn call_channel(frames: usize, voiced: &[(usize, usize)]) -> Vec<f32> {
+        (0..frames * SPAN_FRAME)
+            .map(|i| {
+                let frame = i / SPAN_FRAME;
+                if !voiced.iter().any(|(first, end)| (*first..*end).contains(&frame)) {
+                    return 0.0;
+                }
+                0.3 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / TARGET_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn turn(channel: usize, start_ms: u64, text: &str) -> Turn {
+        let start = Duration::from_millis(start_ms);
+        Turn { channel, start, end: start + Duration::from_millis(500), text: text.to_string() }
+    }
+
+    #[test]
+    fn merged_streams_split_back_into_their_channels() {
+        let mic = call_channel(100, &[(10, 30)]);
+        let monitor = call_channel(80, &[(40, 70)]);
+        let left = temp_file("call-left.wav", &encode_s16_mono(&mic, TARGET_RATE));
+        let right = temp_file("call-right.wav", &encode_s16_mono(&monitor, TARGET_RATE));
+        merge_stereo_files(&left, &right).unwrap();
+
+        let bytes = fs::read(&left).unwrap();
+        let channels = Buffer::decode(&bytes).unwrap().channels;
+        assert_eq!(channels.len(), 2);
+        // The shorter stream is padded with silence
+        assert_eq!(channels[1].len(), mic.len());
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.001);
+        assert!(close(&channels[0], &mic));
+        assert!(close(&channels[1][..monitor.len()], &monitor));
+        assert!(channels[1][monitor.len()..].iter().all(|sample| *sample == 0.0));
+        let _ = fs::remove_file(&left);
+        let _ = fs::remove_file(&right);
+    }
+
+    #[test]
+    fn speech_spans_find_utterances_and_skip_clicks() {
+        // Two bursts 300 ms apart are one utterance, a 60 ms click is none
+        let samples = call_channel(120, &[(10, 20), (30, 40), (80, 82)]);
+        assert_eq!(speech_spans(&samples), vec![5 * SPAN_FRAME..45 * SPAN_FRAME]);
+        // A pause past the gap splits them
+        let samples = call_channel(120, &[(10, 20), (50, 60)]);
+        assert_eq!(speech_spans(&samples), vec![5 * SPAN_FRAME..25 * SPAN_FRAME, 45 * SPAN_FRAME..65 * SPAN_FRAME]);
+        assert!(speech_spans(&call_channel(50, &[])).is_empty());
+        // Padding stops at the ends of the buffer
+        assert_eq!(speech_spans(&call_channel(20, &[(0, 20)])), vec![0..20 * SPAN_FRAME]);
+    }
+
+    #[test]
+    fn channels_interleave_by_when_each_utterance_started() {
+        let mic = call_channel(150, &[(10, 30), (100, 120)]);
+        let monitor = call_channel(150, &[(40, 70)]);
+        let mut turns = Vec::new();
+        for (channel, samples) in [mic, monitor].iter().enumerate() {
+            for span in speech_spans(samples) {
+                turns.push(Turn { channel, start: sample_time(span.start), end: sample_time(span.end), text: format!("{}@{}", channel, span.start) });
+            }
+        }
+        let order: Vec<(usize, Duration)> = interleave(turns).iter().map(|turn| (turn.channel, turn.start)).collect();
+        assert_eq!(order, vec![
+            (0, Duration::from_millis(150)),
+            (1, Duration::from_millis(1050)),
+            (0, Duration::from_millis(2850)),
+        ]);
+    }
+
+    #[test]
+    fn interleaving_puts_the_lower_channel_first_at_the_same_instant() {
+        let turns = interleave(vec![turn(1, 1000, "them"), turn(0, 2000, "me later"), turn(0, 1000, "me")]);
+        let texts: Vec<&str> = turns.iter().map(|turn| turn.text.as_str()).collect();
+        assert_eq!(texts, ["me", "them", "me later"]);
+    }
+
+    /// One second of a 440 Hz sine at `amplitude`, hard-clipped at full scale
+    fn sine(amplitude: f32) -> Vec<f32> {
+        (0..TARGET_RATE as usize)
+            .map(|i| (amplitude * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / TARGET_RATE as f32).sin()).clamp(-1.0, 1.0))
+            .collect()
+    }
+
+    #[test]
+    fn a_clipped_recording_is_over_the_warning() {
+        // Twice full scale spends most of each cycle pinned at the rails
+        let fraction = clipped_fraction(&sine(2.0));
+        assert!(fraction > 0.5, "{}", fraction);
+        assert!(fraction > CLIP_WARN_FRACTION);
+    }
+
+    #[test]
+    fn a_near_clip_recording_is_not() {
+        assert_eq!(clipped_fraction(&sine(0.98)), 0.0);
+        // A few plosives hitting full scale aren't worth a warning
+        let mut samples = sine(0.9);
+        for sample in samples.iter_mut().step_by(100) {
+            *sample = 1.0;
+        }
+        let fraction = clipped_fraction(&samples);
+        assert!(fraction > 0.0 && fraction <= CLIP_WARN_FRACTION, "{}", fraction);
+    }
+
+    #[test]
+    fn a_clean_recording_has_nothing_clipped() {
+        assert_eq!(clipped_fraction(&sine(0.5)), 0.0);
+        assert_eq!(clipped_fraction(&[0.0; 1600]), 0.0);
+        assert_eq!(clipped_fraction(&[]), 0.0);
+    }
+
+    const MIN: Duration = Duration::from_millis(300);
+
+    #[test]
+    fn measures_a_header_without_samples_as_empty() {
+        assert_eq!(measure(&encode_s16_mono(&[], TARGET_RATE), MIN).unwrap(), AudioLength::Empty);
+    }
+
+    #[test]
+    fn measures_an_empty_data_chunk_before_metadata_as_empty() {
+        // A finalized file: the data size of 0 holds even with chunks after it
+        let mut bytes = encode_s16_mono(&[], TARGET_RATE);
+        bytes.extend_from_slice(b"LIST\x0c\0\0\0INFOISFT\0\0\0\0");
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        assert_eq!(measure(&bytes, MIN).unwrap(), AudioLength::Empty);
+    }
+
+    #[test]
+    fn measures_a_short_recording_as_too_short() {
+        let bytes = encode_s16_mono(&[0.1; 1600], TARGET_RATE);
+        assert_eq!(measure(&bytes, MIN).unwrap(), AudioLength::TooShort(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn measures_an_unfinalized_recording_by_the_file_length() {
+        // pw-record killed before it wrote the sizes
+        let mut bytes = encode_s16_mono(&[0.1; 8000], TARGET_RATE);
+        bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+        bytes[40..44].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(measure(&bytes, MIN).unwrap(), AudioLength::Ok(Duration::from_millis(500)));
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(measure(&bytes, MIN).unwrap(), AudioLength::Ok(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn rejects_a_file_that_is_not_a_wav() {
+        assert!(measure(b"ID3\x04\0\0\0\0\0\0", MIN).is_err());
+    }
+}
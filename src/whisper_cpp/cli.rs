@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::notify;
+use crate::run::Run;
+use crate::typing;
+use crate::artifacts;
+use crate::normalize;
+
+/// Which whisper.cpp binary a probe result is for: it is asked again once the
+/// file is replaced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Binary {
+    path: String,
+    size: u64,
+    modified: u64,
+}
+
+/// What `--help` said the binary supports
+#[derive(Debug, Serialize, Deserialize)]
+struct Probe {
+    binary: Binary,
+    output_json: bool,
+}
+
+fn probe_cache_path() -> String {
+    format!("{}/whisp-away/whisper-cli-probe.json", crate::helpers::cache_dir())
+}
+
+/// The binary `whisper_path` runs, looked up in PATH unless it is a path
+fn locate(whisper_path: &str) -> Option<PathBuf> {
+    if whisper_path.contains('/') {
+        return Some(PathBuf::from(whisper_path));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(whisper_path))
+        .find(|candidate| candidate.is_file())
+}
+
+fn identify(whisper_path: &str) -> Option<Binary> {
+    let path = fs::canonicalize(locate(whisper_path)?).ok()?;
+    let meta = fs::metadata(&path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    Some(Binary { path: path.to_string_lossy().into_owned(), size: meta.len(), modified })
+}
+
+fn read_probes() -> Vec<Probe> {
+    fs::read_to_string(probe_cache_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Whether the binary can write its transcript as JSON (`--output-json`).
+/// Its `--help` is only read the first time; the answer is cached per binary.
+fn supports_output_json(whisper_path: &str) -> bool {
+    let binary = identify(whisper_path);
+    let mut probes = read_probes();
+    if let Some(probe) = probes.iter().find(|probe| Some(&probe.binary) == binary.as_ref()) {
+        return probe.output_json;
+    }
+    
+    // Builds differ in whether --help goes to stdout or stderr
+    let help = crate::child_env::command(whisper_path, &[])
+        .arg("--help")
+        .output()
+        .map(|output| format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)))
+        .unwrap_or_default();
+    let output_json = help.contains("--output-json");
+    eprintln!("DEBUG: {} {} --output-json", whisper_path, if output_json { "supports" } else { "doesn't support" });
+    
+    if let Some(binary) = binary {
+        // A binary replaced at the same path takes its old entry's place
+        probes.retain(|probe| probe.binary.path != binary.path);
+        probes.push(Probe { binary, output_json });
+        let path = probe_cache_path();
+        if let Some(dir) = Path::new(&path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(json) = serde_json::to_string_pretty(&probes) {
+            let _ = fs::write(&path, json);
+        }
+    }
+    output_json
+}
+
+/// Extra arguments for the CLI from WA_WHISPER_CLI_ARGS, split on whitespace
+fn extra_args() -> Vec<String> {
+    std::env::var("WA_WHISPER_CLI_ARGS")
+        .map(|args| args.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+struct CliOutput {
+    #[serde(default)]
+    result: Option<CliResult>,
+    #[serde(default)]
+    transcription: Vec<CliSegment>,
+}
+
+#[derive(Deserialize)]
+struct CliResult {
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CliSegment {
+    text: String,
+}
+
+/// Text and detected language from the file `--output-json` writes
+pub fn parse_json(content: &str) -> Result<(String, Option<String>)> {
+    let output: CliOutput = serde_json::from_str(content).context("Invalid whisper-cpp JSON output")?;
+    let text = normalize::join_segments(output.transcription.iter().map(|segment| segment.text.trim()));
+    Ok((text, output.result.and_then(|result| result.language)))
+}
+
+/// Text from what the CLI prints to stdout, for binaries without JSON output.
+/// Segments come as `[00:00:00.000 --> 00:00:02.000]  text`, or as bare lines
+/// when timestamps are turned off.
+pub fn parse_stdout(stdout: &str) -> String {
+    let bracketed = stdout.lines().any(|line| line.contains(" --> ") && line.contains(']'));
+    let segments: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| {
+            if !bracketed {
+                return Some(line.trim());
+            }
+            if !line.contains(" --> ") {
+                return None;
+            }
+            Some(line[line.rfind(']')? + 1..].trim())
+        })
+        .filter(|text| !text.is_empty() && !text.starts_with('(') && !text.ends_with(')'))
+        .collect();
+    normalize::join_segments(segments)
+}
+
+/// Transcribe with the whisper.cpp command line tool and type the result
+pub fn transcribe_with_cli(audio_file: &str, model: &str, whisper_path: &str, prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+    let acceleration = crate::helpers::get_acceleration_type();
+    let transcribe_msg = format!("⏳ Transcribing with CLI... ({})", acceleration);
+    
+    notify::Notification::transient("Voice Input (whisper.cpp)", &transcribe_msg)
+        .send();
+    
+    let model_path = crate::helpers::whisper_cpp_model_path(model);
+    super::model::ensure_memory(model)?;
+    
+    // The CLI loads the model too, so it queues like the bindings do
+    let _lock = crate::direct_lock::acquire()?;
+    
+    let threads = crate::helpers::threads().to_string();
+    let mut cmd = crate::child_env::command(whisper_path, crate::child_env::MODEL_ENV);
+    cmd.args([
+        "-m", &model_path,
+        "-f", audio_file,
+        "-l", &run.language,
+        "-t", &threads,
+        "-np",
+        "-nt",
+    ]);
+    // Written next to the other runtime files as <prefix>.json
+    let json_prefix = supports_output_json(whisper_path)
+        .then(|| crate::helpers::runtime_file(&format!("whisp-away-cli-{}", std::process::id())));
+    if let Some(prefix) = &json_prefix {
+        cmd.args(["-oj", "-of", prefix]);
+    }
+    if artifacts::suppress_non_speech() {
+        cmd.arg("-sns");
+    }
+    run.prompt = crate::prompt::current(prompt_file, delivery.profile.as_deref());
+    if let Some(prompt) = &run.prompt {
+        cmd.args(["--prompt", prompt]);
+    }
+    cmd.args(extra_args());
+    let output = cmd
+        .output()
+        .context("Failed to run whisper-cpp")?;
+    
+    if !output.status.success() {
+        notify::Notification::error("Voice Input (whisper.cpp)", "❌ Transcription failed")
+            .send();
+        return Err(anyhow!("whisper-cpp failed: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+    
+    let (text, detected) = match &json_prefix {
+        Some(prefix) => {
+            let json_path = format!("{}.json", prefix);
+            let content = fs::read_to_string(&json_path);
+            let _ = fs::remove_file(&json_path);
+            parse_json(&content.with_context(|| format!("whisper-cpp wrote no {}", json_path))?)?
+        }
+        None => (parse_stdout(&String::from_utf8_lossy(&output.stdout)), None),
+    };
+    let label = format!("whisper-cpp CLI{}", crate::helpers::language_note(&run.language, detected.as_deref()));
+    run.detected_language = detected;
+    typing::type_text(&artifacts::strip(&text), delivery, run, &label)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trimmed from what whisper-cli 1.7 writes with --output-json
+    const JSON_SAMPLE: &str = r#"{
+        "systeminfo": "AVX = 1 | AVX2 = 1 | FMA = 1",
+        "model": {"type": "base", "multilingual": true, "vocab": 51865},
+        "params": {"model": "ggml-base.en.bin", "language": "auto", "translate": false},
+        "result": {"language": "en"},
+        "transcription": [
+            {
+                "timestamps": {"from": "00:00:00,000", "to": "00:00:07,600"},
+                "offsets": {"from": 0, "to": 7600},
+                "text": " And so my fellow Americans, ask not what your country can do for you,"
+            },
+            {
+                "timestamps": {"from": "00:00:07,600", "to": "00:00:10,600"},
+                "offsets": {"from": 7600, "to": 10600},
+                "text": " ask what you can do for your country."
+            }
+        ]
+    }"#;
+
+    // What older binaries without --output-json print to stdout
+    const STDOUT_SAMPLE: &str = "
+[00:00:00.000 --> 00:00:07.600]   And so my fellow Americans, ask not what your country can do for you,
+[00:00:07.600 --> 00:00:08.000]   (wind blowing)
+[00:00:08.000 --> 00:00:10.600]   ask what you can do for your country.
+
+";
+
+    #[test]
+    fn parses_json_output() {
+        let (text, language) = parse_json(JSON_SAMPLE).unwrap();
+        assert_eq!(text, "And so my fellow Americans, ask not what your country can do for you, ask what you can do for your country.");
+        assert_eq!(language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn parses_json_output_without_a_language() {
+        let (text, language) = parse_json(r#"{"transcription": [{"text": " Hello."}]}"#).unwrap();
+        assert_eq!(text, "Hello.");
+        assert_eq!(language, None);
+        assert!(parse_json("whisper_init_from_file: failed").is_err());
+    }
+
+    #[test]
+    fn parses_bracketed_stdout() {
+        assert_eq!(
+            parse_stdout(STDOUT_SAMPLE),
+            "And so my fellow Americans, ask not what your country can do for you, ask what you can do for your country."
+        );
+    }
+
+    #[test]
+    fn parses_stdout_without_timestamps() {
+        assert_eq!(parse_stdout(" Hello there.\n\n General Kenobi.\n"), "Hello there. General Kenobi.");
+    }
+}
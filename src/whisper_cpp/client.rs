@@ -1,115 +1,47 @@
 use anyhow::Result;
-use std::fs;
-use std::process::Command;
-use crate::recording;
-use crate::socket;
-use super::direct::{transcribe_with_whisper_rs, transcribe_with_cli};
+use crate::client::{self, StopOptions, TranscriptionBackend};
+use crate::run::Run;
+use crate::typing;
+use super::cli::transcribe_with_cli;
+use super::direct::transcribe_with_whisper_rs;
 
-pub fn stop_and_transcribe_daemon(wtype_path: &str, socket_path: &str, audio_file_override: Option<&str>, model: Option<String>, bindings: bool, whisper_path: Option<String>) -> Result<()> {
-    let audio_file = match recording::stop_recording(audio_file_override)? {
-        Some(path) => path,
-        None => {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input (whisper.cpp daemon)",
-                    "❌ No recording found",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            return Ok(());
-        }
-    };
+/// whisper.cpp, falling back to the whisper-rs bindings (same as the daemon)
+/// or, with `--bindings` off, the whisper-cpp CLI binary
+pub struct WhisperCpp {
+    pub bindings: bool,
+    pub whisper_path: Option<String>,
+}
 
-    let audio_path = std::path::Path::new(&audio_file);
-    if !audio_path.exists() {
-        Command::new("notify-send")
-            .args(&[
-                "Voice Input (whisper.cpp daemon)",
-                "❌ No audio recorded",
-                "-t", "2000",
-                "-h", "string:x-canonical-private-synchronous:voice"
-            ])
-            .spawn()?;
-        return Ok(());
+impl TranscriptionBackend for WhisperCpp {
+    fn name(&self) -> &'static str {
+        "whisper-cpp"
     }
     
-    if let Ok(metadata) = fs::metadata(&audio_file) {
-        if metadata.len() <= 44 {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    "❌ Audio file is empty\nBackend: whisper-cpp",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            let _ = fs::remove_file(&audio_file);
-            return Ok(());
-        }
+    fn daemon_summary(&self) -> &'static str {
+        "Voice Input (whisper.cpp daemon)"
     }
-
-    let start_time = std::time::Instant::now();
-    eprintln!("DEBUG: Starting transcription at {:?}", start_time);
     
-    // Get model for notification
-    let resolved_model = crate::helpers::resolve_model(model.clone());
-    let acceleration = crate::helpers::get_acceleration_type();
-    let transcribe_msg = format!("⏳ Transcribing...\nBackend: whisper-cpp ({}) | Model: {}", acceleration, resolved_model);
+    fn no_audio_notice(&self) -> (String, String) {
+        (self.daemon_summary().to_string(), "❌ No audio recorded".to_string())
+    }
     
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
-
-    eprintln!("DEBUG: Connecting to daemon socket at: {}", socket_path);
+    fn fallback_notice(&self, reason: &str, model: &str) -> (String, String) {
+        let via = if self.bindings { "bindings" } else { "CLI" };
+        ("Voice Input".to_string(), format!("⚠️ {}, using fallback\nBackend: whisper-cpp ({}) | Model: {}", reason, via, crate::helpers::model_display_name(model)))
+    }
     
-    match socket::send_transcription_request(socket_path, &audio_file, wtype_path, "whisper-cpp") {
-        Ok(_) => {
-            eprintln!("DEBUG: Total time: {:?}", start_time.elapsed());
-            let _ = fs::remove_file(&audio_file);
-        }
-        Err(e) => {
-            // Use the model parameter if provided, otherwise resolve from env
-            let model = crate::helpers::resolve_model(model);
-            
-            let fallback_msg = if bindings {
-                format!("⚠️ Daemon not running, using fallback\nBackend: whisper-cpp (bindings) | Model: {}", model)
-            } else {
-                format!("⚠️ Daemon not running, using fallback\nBackend: whisper-cpp (CLI) | Model: {}", model)
-            };
-            
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    &fallback_msg,
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            
-            // By default, fallback uses whisper-rs bindings (same as daemon)
-            // With --no-bindings flag, it uses the CLI binary instead
-            let result = if !bindings {
-                // Use whisper-cpp CLI binary for fallback
-                let whisper_path = whisper_path.unwrap_or_else(|| 
-                    std::env::var("WHISPER_CPP_PATH").unwrap_or_else(|_| "whisper-cpp".to_string())
-                );
-                transcribe_with_cli(&audio_file, &model, &whisper_path, wtype_path)
-            } else {
-                // Use whisper-rs bindings for fallback (default, same as daemon)
-                transcribe_with_whisper_rs(&audio_file, &model, "", wtype_path)
-            };
-            
-            let _ = fs::remove_file(&audio_file);
-            
-            return result.map_err(|err| anyhow::anyhow!("Fallback transcription failed (daemon was: {}): {}", e, err));
+    fn transcribe_direct(&self, audio_file: &str, model: &str, prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+        if self.bindings {
+            transcribe_with_whisper_rs(audio_file, model, prompt_file, delivery, run)
+        } else {
+            let whisper_path = self.whisper_path.clone().unwrap_or_else(||
+                std::env::var("WHISPER_CPP_PATH").unwrap_or_else(|_| "whisper-cpp".to_string())
+            );
+            transcribe_with_cli(audio_file, model, &whisper_path, prompt_file, delivery, run)
         }
     }
-
-    Ok(())
-}
\ No newline at end of file
+}
+    
+pub fn stop_and_transcribe_daemon(options: &StopOptions, run: &mut Run, bindings: bool, whisper_path: Option<String>) -> Result<()> {
+    client::stop_and_transcribe(&WhisperCpp { bindings, whisper_path }, options, run)
+}
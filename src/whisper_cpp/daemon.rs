@@ -1,131 +1,767 @@
 use anyhow::{anyhow, Context, Result};
-use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::{Read, Write};
-use std::os::unix::net::{UnixListener, UnixStream};
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tokio::sync::{broadcast, Notify};
 use tracing::{error, info, warn};
-use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
+use whisper_rs::{WhisperContext, WhisperSegment, FullParams, SamplingStrategy};
 #[cfg(feature = "openvino")]
 use whisper_rs::WhisperState;
+#[cfg(feature = "openvino")]
+use super::device_fallback::{DeviceFallback, EncoderDevice, Inference};
 use crate::helpers::wav_to_samples;
+use crate::wav::AudioLength;
+use serde::de::DeserializeOwned;
+use crate::protocol::{self, ClientMessage, Command, DaemonEvent, Framing, Hello, Opening, Priority, Segment, TranscriptionRequest, TranscriptionResponse, AUDIO_TOO_LONG, LOW_MEMORY, MODEL_UNAVAILABLE, UNAUTHORIZED, UNSUPPORTED_PROTOCOL};
+use super::model::{available_ram_mb, download_model, memory_floor_mb, notify_invalid_model, own_rss_mb, validate_model};
+use crate::artifacts;
+use crate::normalize;
+use crate::ready::{self, EngineHealth, MemoryStatus, QueueStatus, ReadyInfo};
+
+/// Backend name this daemon answers to in requests and responses
+const BACKEND: &str = "whisper-cpp";
+/// What this daemon offers clients in the hello exchange
+const CAPABILITIES: &[&str] = &[
+    protocol::CAP_TRANSCRIBE,
+    protocol::CAP_DETECT_LANGUAGE,
+    protocol::CAP_SEGMENTS,
+    protocol::CAP_PRIORITY,
+    protocol::CAP_ALLOW_LONG,
+    protocol::CAP_INITIAL_PROMPT,
+    protocol::CAP_INLINE_AUDIO,
+    protocol::CAP_NO_GPU,
+    protocol::CAP_SUBSCRIBE,
+];
+/// How long a client gets to send its request, and to take the response
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Requests are a few hundred bytes of JSON; anything this big is not a client of ours
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+/// Inline audio up to about two hours of 16 kHz s16 mono
+const MAX_INLINE_AUDIO_BYTES: u64 = 256 * 1024 * 1024;
+/// How long a client gets to send its inline audio, which may cross a slow network
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+/// How often the daemon checks its own and the system's memory
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// How long subscribers get to read shutting_down before the daemon exits
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(200);
 
-const SOCKET_PATH: &str = "/tmp/whisp-away-daemon.sock";
+/// What `daemon --backend whisper-cpp` was started with
+pub struct DaemonOptions {
+    pub model: String,
+    pub language: String,
+    pub socket_path: String,
+    pub ready_file: String,
+    /// Longest recording accepted without allow_long, in seconds
+    pub max_audio_seconds: u64,
+    /// Download the model when it isn't on disk
+    pub auto_download: bool,
+    /// `--listen`: also accept clients over TCP
+    pub tcp: Option<crate::daemons::TcpListen>,
+    /// Small model kept loaded for `fast` requests
+    pub fast_model: Option<String>,
+}
 
 #[tokio::main]
-pub async fn run_daemon(model_path: &str) -> Result<()> {
+pub async fn run_daemon(options: DaemonOptions) -> Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
     
     // Create and run daemon
-    let daemon = WhisperDaemon::new(model_path)?;
-    daemon.run().await
+    let daemon = WhisperDaemon::new(&options)?;
+    daemon.run(&options.ready_file, options.tcp).await
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TranscriptionRequest {
-    audio_path: String,
+/// Where a connection came in, which decides what the client has to send
+#[derive(Clone)]
+enum Transport {
+    /// Local clients, who may record straight into the daemon's inbox
+    Unix { inbox: Arc<String> },
+    /// `--listen`: the client shares no files with the daemon, so it must
+    /// negotiate, present the token and send its audio inline
+    Tcp { token: Arc<String> },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TranscriptionResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    text: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+impl Transport {
+    fn inbox(&self) -> Option<&str> {
+        match self {
+            Transport::Unix { inbox } => Some(inbox),
+            Transport::Tcp { .. } => None,
+        }
+    }
 }
 
-pub struct WhisperDaemon {
-    ctx: Arc<WhisperContext>,
-    socket_path: String,
-    // Single reusable state with OpenVINO initialized
-    #[cfg(feature = "openvino")]
-    state: Arc<tokio::sync::Mutex<WhisperState>>,
+/// After this many interactive requests in a row, a waiting batch request goes
+/// next, so a steady stream of dictation can't starve batch jobs forever
+const MAX_INTERACTIVE_STREAK: usize = 4;
+
+/// Requests waiting for the model, by priority, and the one using it
+#[derive(Default)]
+struct QueueState {
+    next_ticket: u64,
+    interactive: VecDeque<u64>,
+    batch: VecDeque<u64>,
+    active: Option<Priority>,
+    // Interactive requests started in a row while batch ones were waiting
+    interactive_streak: usize,
 }
 
-impl WhisperDaemon {
-    pub fn new(model_path: &str) -> Result<Self> {
-        // If model_path doesn't contain a path separator, treat it as a model name
-        // and construct the full path
-        let final_model_path = if !model_path.contains('/') {
-            let home = std::env::var("HOME").unwrap_or_else(|_| "/home/martin".to_string());
-            let model_extension = if model_path.ends_with(".bin") { "" } else { ".bin" };
-            format!("{}/.cache/whisper-cpp/models/ggml-{}{}", home, model_path, model_extension)
+impl QueueState {
+    /// Ticket that gets the model next, if it is free
+    fn next(&self) -> Option<u64> {
+        if self.active.is_some() {
+            return None;
+        }
+        let batch_due = self.interactive.is_empty() || self.interactive_streak >= MAX_INTERACTIVE_STREAK;
+        match (self.interactive.front(), self.batch.front()) {
+            (_, Some(batch)) if batch_due => Some(*batch),
+            (Some(interactive), _) => Some(*interactive),
+            (None, batch) => batch.copied(),
+        }
+    }
+
+    fn start(&mut self, ticket: u64, priority: Priority) {
+        match priority {
+            Priority::Interactive => {
+                self.interactive.retain(|t| *t != ticket);
+                self.interactive_streak = if self.batch.is_empty() { 0 } else { self.interactive_streak + 1 };
+            }
+            Priority::Batch => {
+                self.batch.retain(|t| *t != ticket);
+                self.interactive_streak = 0;
+            }
+        }
+        self.active = Some(priority);
+    }
+    
+    fn status(&self) -> QueueStatus {
+        QueueStatus {
+            active: self.active,
+            interactive_waiting: self.interactive.len(),
+            batch_waiting: self.batch.len(),
+        }
+    }
+}
+
+/// Two-level queue in front of the model. One request transcribes at a time;
+/// interactive requests are dequeued before batch ones.
+#[derive(Default)]
+struct Scheduler {
+    state: std::sync::Mutex<QueueState>,
+    changed: Notify,
+    /// Ready file and its contents, rewritten with the queue for `status`
+    ready: OnceLock<(String, ReadyInfo)>,
+    /// Model trouble so far, published in the ready file too
+    health: std::sync::Mutex<EngineHealth>,
+    /// Whether OpenVINO initialized on the current state, in OpenVINO builds
+    openvino_active: std::sync::Mutex<Option<bool>>,
+    /// OpenVINO device the current state is on, in OpenVINO builds
+    openvino_device: std::sync::Mutex<Option<String>>,
+    /// Memory use as of the last check
+    memory: std::sync::Mutex<Option<MemoryStatus>>,
+}
+
+/// A place in the queue, and then the model, until dropped
+struct Slot<'a> {
+    scheduler: &'a Scheduler,
+    ticket: u64,
+    priority: Priority,
+    running: bool,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        if self.running {
+            state.active = None;
         } else {
-            model_path.to_string()
-        };
-        
-        info!("Loading whisper.cpp model from: {}", final_model_path);
-        
-        // Check if model file exists
-        if !Path::new(&final_model_path).exists() {
-            return Err(anyhow::anyhow!("Model file not found: {}", final_model_path));
+            // Gave up while waiting (the connection task failed)
+            let ticket = self.ticket;
+            match self.priority {
+                Priority::Interactive => state.interactive.retain(|t| *t != ticket),
+                Priority::Batch => state.batch.retain(|t| *t != ticket),
+            }
         }
+        self.scheduler.publish(&state);
+        drop(state);
+        self.scheduler.changed.notify_waiters();
+    }
+}
+    
+impl Scheduler {
+    /// Queue a request and wait until it may use the model
+    async fn acquire(&self, priority: Priority) -> Slot<'_> {
+        let mut slot = {
+            let mut state = self.state.lock().unwrap();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            match priority {
+                Priority::Interactive => state.interactive.push_back(ticket),
+                Priority::Batch => state.batch.push_back(ticket),
+            }
+            self.publish(&state);
+            Slot { scheduler: self, ticket, priority, running: false }
+        };
         
-        // Create whisper context with GPU configuration
-        let mut ctx_params = WhisperContextParameters::default();
-        ctx_params.use_gpu(true);  // Enable GPU acceleration
-        ctx_params.gpu_device(0);   // Use GPU device 0
-        
-        // Don't configure OpenVINO at context level - we'll do it at state level
-        // This avoids the systemd initialization issue
-        
-        info!("Initializing WhisperContext with configured acceleration");
-        let t_ctx = std::time::Instant::now();
-        let ctx = WhisperContext::new_with_params(&final_model_path, ctx_params)
-            .context("Failed to create WhisperContext")?;
-        eprintln!("DEBUG DAEMON: Context creation took {:?}", t_ctx.elapsed());
-        
-        info!("Model loaded successfully into memory");
-        
-        // Create a single state with OpenVINO initialized
-        #[cfg(feature = "openvino")]
-        let state = {
-            eprintln!("DEBUG DAEMON: Creating reusable state with OpenVINO...");
-            let t_state = std::time::Instant::now();
-            let mut state = ctx.create_state()
-                .context("Failed to create whisper state")?;
-            eprintln!("DEBUG DAEMON: State creation took {:?}", t_state.elapsed());
-            
-            // Initialize OpenVINO at state level
-            let model_base = final_model_path.trim_end_matches(".bin");
-            let openvino_model = format!("{}-encoder-openvino.xml", model_base);
-            if std::path::Path::new(&openvino_model).exists() {
-                let t_ov = std::time::Instant::now();
-                eprintln!("DEBUG DAEMON: Initializing OpenVINO at state level...");
-                // Use RAM-based cache in /dev/shm for faster access
-                // Extract model name from path (e.g., "base.en" from "/path/to/ggml-base.en.bin")
-                // Set cache directory as subdirectory next to the model files
-                let cache_dir = format!("{}-encoder-openvino-cache", model_base);
-                // Ensure cache directory exists
-                if let Err(e) = std::fs::create_dir_all(&cache_dir) {
-                    eprintln!("DEBUG DAEMON: Warning: Could not create cache dir: {:?}", e);
+        loop {
+            // Register before checking so a wakeup in between isn't missed
+            let notified = self.changed.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.next() == Some(slot.ticket) {
+                    state.start(slot.ticket, priority);
+                    slot.running = true;
+                    self.publish(&state);
+                    return slot;
                 }
-                eprintln!("DEBUG DAEMON: Using cache dir: {}", cache_dir);
-                // Use AUTO to let OpenVINO choose the best device
-                match state.init_openvino_encoder_state_level(None, "AUTO", Some(&cache_dir)) {
-                    Ok(_) => eprintln!("DEBUG DAEMON: OpenVINO initialized with AUTO device selection in {:?}", t_ov.elapsed()),
-                    Err(e) => {
-                        eprintln!("DEBUG DAEMON: Failed to init OpenVINO: {:?}", e);
-                        eprintln!("DEBUG DAEMON: Will use regular CPU inference");
-                    }
+            }
+            notified.await;
+        }
+    }
+    
+    /// Put the queue in the ready file, so `whisp-away status` can show it
+    fn publish(&self, state: &QueueState) {
+        let Some((path, info)) = self.ready.get() else {
+            return;
+        };
+        let health = Some(self.health.lock().unwrap().clone()).filter(|health| *health != EngineHealth::default());
+        let openvino_active = *self.openvino_active.lock().unwrap();
+        let openvino_device = self.openvino_device.lock().unwrap().clone();
+        let memory = self.memory.lock().unwrap().clone();
+        let info = ReadyInfo { queue: Some(state.status()), health, openvino_active, openvino_device, memory, ..info.clone() };
+        if let Err(e) = ready::write(path, &info) {
+            warn!("Could not update ready file: {}", e);
+        }
+    }
+    
+    /// Count a rebuilt whisper state, or with `failure`, record why it
+    /// couldn't be rebuilt and the model is now unavailable
+    #[cfg_attr(not(feature = "openvino"), allow(dead_code))]
+    fn record_state_recreation(&self, failure: Option<String>) {
+        {
+            let mut health = self.health.lock().unwrap();
+            match failure {
+                Some(reason) => health.unavailable = Some(reason),
+                None => health.state_recreations += 1,
+            }
+        }
+        let state = self.state.lock().unwrap();
+        self.publish(&state);
+    }
+    
+    /// Note the device a newly created state is on, and whether OpenVINO
+    /// initialized there
+    #[cfg(feature = "openvino")]
+    fn record_openvino(&self, active: bool, device: EncoderDevice) {
+        *self.openvino_active.lock().unwrap() = Some(active);
+        *self.openvino_device.lock().unwrap() = Some(device.to_string());
+        let state = self.state.lock().unwrap();
+        self.publish(&state);
+    }
+    
+    /// Publish memory use when it changed
+    fn record_memory(&self, memory: MemoryStatus) {
+        {
+            let mut current = self.memory.lock().unwrap();
+            if current.as_ref() == Some(&memory) {
+                return;
+            }
+            *current = Some(memory);
+        }
+        let state = self.state.lock().unwrap();
+        self.publish(&state);
+    }
+    
+    /// Why requests can't be served any more, if they can't
+    fn unavailable(&self) -> Option<String> {
+        self.health.lock().unwrap().unavailable.clone()
+    }
+}
+
+/// Settings, the model and queue state shared by all connections
+struct DaemonConfig {
+    // Language used when a request doesn't specify one
+    language: String,
+    // Longer requests are rejected unless they set allow_long
+    max_audio_seconds: u64,
+    scheduler: Scheduler,
+    model_path: String,
+    model_name: String,
+    // None while unloaded because memory ran low
+    engine: std::sync::Mutex<Option<Engine>>,
+    // Loaded for the daemon's whole life, and never unloaded for memory
+    fast: Option<FastEngine>,
+    // CPU-only copy of the model for no_gpu requests, loaded on the first one
+    cpu_engine: std::sync::Mutex<Option<Engine>>,
+    // Every subscription forwards from a receiver of its own
+    events: broadcast::Sender<DaemonEvent>,
+}
+
+/// The small model `fast` requests use. It has its own lock instead of a
+/// place in the queue, so a command isn't held up by a long dictation.
+struct FastEngine {
+    name: String,
+    engine: Engine,
+    busy: tokio::sync::Mutex<()>,
+}
+
+impl DaemonConfig {
+    /// What this daemon offers clients in the hello exchange
+    fn capabilities(&self) -> Vec<&'static str> {
+        let mut capabilities = CAPABILITIES.to_vec();
+        if self.fast.is_some() {
+            capabilities.push(protocol::CAP_FAST);
+        }
+        if cfg!(feature = "compress") {
+            capabilities.push(protocol::CAP_COMPRESS);
+        }
+        capabilities
+    }
+    
+    /// Push an event to the subscribers. Never waits: one that hasn't read
+    /// the last EVENT_BUFFER events loses the oldest.
+    fn emit(&self, event: DaemonEvent) {
+        // Err only means nobody is subscribed
+        let _ = self.events.send(event);
+    }
+    
+    /// Read the daemon's and the system's memory, unload the model when
+    /// available memory is below WA_MEMORY_FLOOR_MB, and publish the result
+    fn check_memory(&self) {
+        let floor_mb = memory_floor_mb();
+        let available_mb = available_ram_mb();
+        let mut low_memory = None;
+        let unloaded = {
+            let mut engine = self.engine.lock().unwrap();
+            if let (Some(floor), Some(available), Some(_)) = (floor_mb, available_mb, engine.as_ref()) {
+                if available < floor {
+                    warn!("Only {} MB of memory available, below the {} MB floor; unloading the model", available, floor);
+                    // A transcription still running holds its own handle and
+                    // frees the model when it finishes
+                    *engine = None;
+                    self.cpu_engine.lock().unwrap().take();
+                    low_memory = Some(DaemonEvent::LowMemory { available_mb: available, floor_mb: floor });
                 }
             }
-            Arc::new(tokio::sync::Mutex::new(state))
+            engine.is_none()
+        };
+        self.scheduler.record_memory(MemoryStatus { rss_mb: own_rss_mb(), available_mb, floor_mb, unloaded });
+        // After the ready file says so, for subscribers that read it
+        if let Some(event) = low_memory {
+            self.emit(event);
+        }
+    }
+}
+
+/// Check memory every MEMORY_POLL_INTERVAL for as long as the daemon runs
+async fn watch_memory(config: Arc<DaemonConfig>) {
+    loop {
+        config.check_memory();
+        tokio::time::sleep(MEMORY_POLL_INTERVAL).await;
+    }
+}
+
+/// The loaded model, loading it again first if it was unloaded for lack of
+/// memory and enough is available now. Called holding the queue slot, so only
+/// one request reloads. The Err is the response to send back.
+async fn current_engine(config: &Arc<DaemonConfig>) -> std::result::Result<Engine, TranscriptionResponse> {
+    if let Some(engine) = config.engine.lock().unwrap().clone() {
+        return Ok(engine);
+    }
+    if let (Some(floor), Some(available)) = (memory_floor_mb(), available_ram_mb()) {
+        if available < floor {
+            warn!("Rejecting request: model unloaded and only {} MB available", available);
+            return Err(TranscriptionResponse::err_with_code(
+                LOW_MEMORY,
+                format!(
+                    "Model unloaded to free memory: only {} MB available, below the {} MB floor. Close something and try again",
+                    available, floor
+                ),
+            ));
+        }
+    }
+    
+    info!("Reloading the model from {}", config.model_path);
+    let loader = Arc::clone(config);
+    let loaded = tokio::task::spawn_blocking(move || load_engine(&loader.model_path, &loader.scheduler, &loader.events)).await;
+    match loaded {
+        Ok(Ok(engine)) => {
+            *config.engine.lock().unwrap() = Some(engine.clone());
+            config.check_memory();
+            config.emit(DaemonEvent::ModelReloaded { model: config.model_name.clone() });
+            Ok(engine)
+        }
+        Ok(Err(e)) => {
+            error!("Could not reload the model: {:#}", e);
+            Err(TranscriptionResponse::err(format!("Could not reload the model: {:#}", e)))
+        }
+        Err(e) => Err(TranscriptionResponse::err(format!("Model loading task failed: {}", e))),
+    }
+}
+
+/// The CPU-only copy of the model, loaded alongside the GPU one the first
+/// time a request asks to stay off the GPU. Called holding the queue slot,
+/// like current_engine. The Err is the response to send back.
+async fn cpu_engine(config: &Arc<DaemonConfig>) -> std::result::Result<Engine, TranscriptionResponse> {
+    if let Some(engine) = config.cpu_engine.lock().unwrap().clone() {
+        return Ok(engine);
+    }
+    
+    info!("Loading a CPU-only copy of {} for no_gpu requests", config.model_path);
+    let model_path = config.model_path.clone();
+    let loaded = tokio::task::spawn_blocking(move || super::direct::create_context(&model_path, false)).await;
+    match loaded {
+        Ok(Ok((ctx, _))) => {
+            let engine = Engine::Context(Arc::new(ctx));
+            *config.cpu_engine.lock().unwrap() = Some(engine.clone());
+            config.check_memory();
+            Ok(engine)
+        }
+        Ok(Err(e)) => {
+            error!("Could not load the model on the CPU: {:#}", e);
+            Err(TranscriptionResponse::err(format!("Could not load the model on the CPU: {:#}", e)))
+        }
+        Err(e) => Err(TranscriptionResponse::err(format!("Model loading task failed: {}", e))),
+    }
+}
+
+/// How hard whisper tries: greedy for every request, beam search with
+/// temperature fallback for the retry of one that came back empty, and greedy
+/// into a single segment for `fast` requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Decoding {
+    Greedy,
+    Thorough,
+    Fast,
+}
+
+impl Decoding {
+    fn strategy(self) -> SamplingStrategy {
+        match self {
+            Decoding::Greedy | Decoding::Fast => SamplingStrategy::Greedy { best_of: 1 },
+            Decoding::Thorough => SamplingStrategy::BeamSearch { beam_size: 5, patience: -1.0 },
+        }
+    }
+    
+    /// Set the decoding on `params`, which start out greedy at temperature 0
+    fn apply(self, params: &mut FullParams) {
+        if self == Decoding::Thorough {
+            // Re-decode at rising temperatures when a pass looks like a failure
+            params.set_temperature_inc(0.2);
+        }
+    }
+}
+
+/// What whisper made of a request
+struct Transcript {
+    text: String,
+    // Language whisper used (the detected one when "auto" was requested)
+    language: Option<String>,
+    segments: Vec<Segment>,
+}
+
+/// What runs the model for a request
+#[derive(Clone)]
+enum Engine {
+    // A fresh state per request; also the fast model's, in OpenVINO builds too
+    Context(Arc<WhisperContext>),
+    // Single reusable state with OpenVINO initialized
+    #[cfg(feature = "openvino")]
+    State(Arc<ReusableState>),
+}
+
+impl Engine {
+    /// Run whisper on a blocking thread so slow transcriptions don't stall
+    /// the runtime's workers (and with them every other connection)
+    async fn transcribe(
+        &self,
+        samples: Vec<f32>,
+        language: String,
+        prompt: Option<String>,
+        decoding: Decoding,
+        #[cfg_attr(not(feature = "openvino"), allow(unused_variables))] config: &Arc<DaemonConfig>,
+    ) -> Result<Transcript> {
+        let task = match self {
+            Engine::Context(ctx) => {
+                let ctx = Arc::clone(ctx);
+                tokio::task::spawn_blocking(move || transcribe_audio(samples, ctx, &language, prompt.as_deref(), decoding))
+            }
+            #[cfg(feature = "openvino")]
+            Engine::State(reusable) => {
+                let reusable = Arc::clone(reusable);
+                let config = Arc::clone(config);
+                eprintln!("DEBUG DAEMON: Using pre-initialized state with OpenVINO");
+                tokio::task::spawn_blocking(move || {
+                    reusable.transcribe(samples, &language, prompt.as_deref(), decoding, &config.scheduler)
+                })
+            }
+        };
+        task.await.context("Transcription task failed")?
+    }
+}
+
+/// The OpenVINO-initialized state every request shares, and what it takes to
+/// rebuild it. A failed `full` can leave the state inconsistent, after which
+/// every later request would fail too, so it is replaced instead of reused;
+/// when that keeps happening, on the next device (see `DeviceFallback`).
+#[cfg(feature = "openvino")]
+struct ReusableState {
+    ctx: WhisperContext,
+    model_path: String,
+    shared: tokio::sync::Mutex<SharedState>,
+}
+
+#[cfg(feature = "openvino")]
+struct SharedState {
+    // None once rebuilding it failed
+    state: Option<WhisperState>,
+    fallback: DeviceFallback,
+}
+
+/// One request's input for the shared state
+#[cfg(feature = "openvino")]
+struct StateRequest<'a> {
+    samples: Vec<f32>,
+    language: &'a str,
+    prompt: Option<&'a str>,
+    decoding: Decoding,
+}
+
+/// The shared state as `DeviceFallback` drives it
+#[cfg(feature = "openvino")]
+struct StateInference<'a> {
+    ctx: &'a WhisperContext,
+    model_path: &'a str,
+    state: &'a mut Option<WhisperState>,
+    scheduler: &'a Scheduler,
+}
+
+#[cfg(feature = "openvino")]
+impl<'a> Inference for StateInference<'a> {
+    type Input = StateRequest<'a>;
+    type Output = Transcript;
+    
+    fn infer(&mut self, request: StateRequest<'a>) -> Result<Transcript> {
+        let Some(state) = self.state.as_mut() else {
+            return Err(anyhow!("The whisper state could not be recreated"));
+        };
+        let result = transcribe_with_state(request.samples, state, request.language, request.prompt, request.decoding);
+        if let Err(e) = &result {
+            warn!("Transcription failed ({:#}); recreating the whisper state", e);
+        }
+        result
+    }
+    
+    fn rebuild(&mut self, device: EncoderDevice) {
+        // Free the old state before allocating its replacement
+        *self.state = None;
+        match create_openvino_state(self.ctx, self.model_path, device) {
+            Ok((state, openvino_active)) => {
+                *self.state = Some(state);
+                info!("Whisper state recreated");
+                self.scheduler.record_openvino(openvino_active, device);
+                self.scheduler.record_state_recreation(None);
+            }
+            Err(e) => {
+                error!("Could not recreate the whisper state, requests will fail until restart: {:#}", e);
+                self.scheduler.record_state_recreation(Some(format!("{:#}", e)));
+                crate::notify::Notification::error(
+                    "Voice Input (whisper-cpp)",
+                    "❌ Model unavailable after a failed transcription\nRestart the daemon",
+                )
+                .send();
+            }
+        }
+    }
+}
+
+#[cfg(feature = "openvino")]
+impl ReusableState {
+    fn new(ctx: WhisperContext, model_path: &str, scheduler: &Scheduler, events: &broadcast::Sender<DaemonEvent>) -> Result<Self> {
+        let device = EncoderDevice::Auto;
+        let (state, openvino_active) = create_openvino_state(&ctx, model_path, device)?;
+        scheduler.record_openvino(openvino_active, device);
+        Ok(Self {
+            ctx,
+            model_path: model_path.to_string(),
+            shared: tokio::sync::Mutex::new(SharedState { state: Some(state), fallback: DeviceFallback::new(device, events.clone()) }),
+        })
+    }
+    
+    /// Transcribe with the shared state, rebuilding it (OpenVINO init included)
+    /// before the next request if this one failed. Runs on a blocking thread.
+    fn transcribe(&self, samples: Vec<f32>, language: &str, prompt: Option<&str>, decoding: Decoding, scheduler: &Scheduler) -> Result<Transcript> {
+        let mut guard = self.shared.blocking_lock();
+        let shared = &mut *guard;
+        if shared.state.is_none() {
+            return Err(anyhow!("The whisper state could not be recreated"));
+        }
+        let mut inference = StateInference {
+            ctx: &self.ctx,
+            model_path: &self.model_path,
+            state: &mut shared.state,
+            scheduler,
+        };
+        shared.fallback.run(&mut inference, StateRequest { samples, language, prompt, decoding })
+    }
+}
+
+/// Create a whisper state and initialize OpenVINO on it for `device` when the
+/// model has an OpenVINO encoder next to it. Also says whether OpenVINO is in
+/// use; when it isn't, the state runs on the CPU.
+#[cfg(feature = "openvino")]
+fn create_openvino_state(ctx: &WhisperContext, model_path: &str, device: EncoderDevice) -> Result<(WhisperState, bool)> {
+    eprintln!("DEBUG DAEMON: Creating reusable state with OpenVINO...");
+    let t_state = std::time::Instant::now();
+    let mut state = ctx.create_state()
+        .context("Failed to create whisper state")?;
+    eprintln!("DEBUG DAEMON: State creation took {:?}", t_state.elapsed());
+    
+    // Initialize OpenVINO at state level
+    let openvino_model = super::model::openvino_encoder_path(model_path);
+    let mut openvino_active = false;
+    let Some(device_name) = device.openvino_name() else {
+        eprintln!("DEBUG DAEMON: OpenVINO dropped after repeated failures, using regular CPU inference");
+        return Ok((state, openvino_active));
+    };
+    if std::path::Path::new(&openvino_model).exists() {
+        let t_ov = std::time::Instant::now();
+        eprintln!("DEBUG DAEMON: Initializing OpenVINO at state level...");
+        // Cache dir is keyed by model content, so moving/symlinking the
+        // model doesn't trigger a fresh 20s+ compile
+        let cache_dir = match super::openvino_cache::cache_dir_for_model(model_path) {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                eprintln!("DEBUG DAEMON: Warning: Could not prepare cache dir: {:?}", e);
+                None
+            }
+        };
+        eprintln!("DEBUG DAEMON: Using cache dir: {:?}", cache_dir);
+        // AUTO lets OpenVINO choose the best device
+        match state.init_openvino_encoder_state_level(None, device_name, cache_dir.as_deref()) {
+            Ok(_) => {
+                eprintln!("DEBUG DAEMON: OpenVINO initialized with {} device selection in {:?}", device_name, t_ov.elapsed());
+                openvino_active = true;
+            }
+            Err(e) => {
+                eprintln!("DEBUG DAEMON: Failed to init OpenVINO: {:?}", e);
+                eprintln!("DEBUG DAEMON: Will use regular CPU inference");
+            }
+        }
+    } else {
+        eprintln!("DEBUG DAEMON: No OpenVINO encoder at {}, using regular CPU inference", openvino_model);
+    }
+    Ok((state, openvino_active))
+}
+
+/// Load the model: a context, plus the shared OpenVINO-initialized state in
+/// OpenVINO builds
+fn load_engine(
+    model_path: &str,
+    #[cfg_attr(not(feature = "openvino"), allow(unused_variables))] scheduler: &Scheduler,
+    #[cfg_attr(not(feature = "openvino"), allow(unused_variables))] events: &broadcast::Sender<DaemonEvent>,
+) -> Result<Engine> {
+    let ctx = load_context(model_path)?;
+    
+    // Create a single state with OpenVINO initialized
+    #[cfg(feature = "openvino")]
+    let engine = Engine::State(Arc::new(ReusableState::new(ctx, model_path, scheduler, events)?));
+    #[cfg(not(feature = "openvino"))]
+    let engine = Engine::Context(Arc::new(ctx));
+    Ok(engine)
+}
+
+fn load_context(model_path: &str) -> Result<WhisperContext> {
+    // Don't configure OpenVINO at context level - we'll do it at state level
+    // This avoids the systemd initialization issue
+    
+    info!("Initializing WhisperContext with configured acceleration");
+    let t_ctx = std::time::Instant::now();
+    let (ctx, device) = super::direct::create_context(model_path, true)?;
+    eprintln!("DEBUG DAEMON: Context creation took {:?}", t_ctx.elapsed());
+    if device == super::direct::Device::CpuAfterHang {
+        warn!("GPU init hung, {} is loaded on the CPU; restart the daemon once the driver recovers", model_path);
+    }
+    
+    info!("Model loaded successfully into memory");
+    Ok(ctx)
+}
+    
+/// Path and name of a model given by name or path, checked to be present and
+/// intact (and downloaded again with `auto_download`) before whisper.cpp sees it.
+/// A model given as a path goes by its absolute path, the way clients resolve
+/// it, so they recognize the daemon as having it loaded.
+fn prepare_model(model: &str, auto_download: bool) -> Result<(String, String)> {
+    let final_model_path = crate::helpers::whisper_cpp_model_path(model);
+    let model_name = crate::helpers::absolute_model(model);
+    
+    info!("Loading whisper.cpp model from: {}", final_model_path);
+    
+    let format = match validate_model(&final_model_path, &model_name) {
+        Ok(format) => format,
+        Err(e) => {
+            if !auto_download || crate::helpers::is_model_path(&model_name) {
+                error!("{}", e);
+                notify_invalid_model(&model_name, &e);
+                return Err(e);
+            }
+            warn!("{}; re-downloading because --auto-download is set", e);
+            download_model(&model_name, &final_model_path)?;
+            validate_model(&final_model_path, &model_name)?
+        }
+    };
+    info!("{} is a {} model", final_model_path, format);
+    super::model::ensure_memory(&model_name)?;
+    Ok((final_model_path, model_name))
+}
+
+pub struct WhisperDaemon {
+    socket_path: String,
+    config: Arc<DaemonConfig>,
+}
+
+impl WhisperDaemon {
+    pub fn new(options: &DaemonOptions) -> Result<Self> {
+        let auto_download = options.auto_download;
+        let (final_model_path, model_name) = prepare_model(&options.model, auto_download)?;
+        let scheduler = Scheduler::default();
+        let events = broadcast::channel(protocol::EVENT_BUFFER).0;
+        let engine = load_engine(&final_model_path, &scheduler, &events)?;
+        
+        // Loaded up front so the first command is as quick as the rest
+        let fast = match &options.fast_model {
+            Some(fast_model) => {
+                let (fast_path, name) = prepare_model(fast_model, auto_download)?;
+                let engine = Engine::Context(Arc::new(load_context(&fast_path)?));
+                info!("Fast model {} loaded for short commands", name);
+                Some(FastEngine { name, engine, busy: tokio::sync::Mutex::new(()) })
+            }
+            None => None,
         };
+        let config = Arc::new(DaemonConfig {
+            language: options.language.clone(),
+            max_audio_seconds: options.max_audio_seconds,
+            scheduler,
+            model_path: final_model_path,
+            model_name,
+            engine: std::sync::Mutex::new(Some(engine)),
+            fast,
+            cpu_engine: std::sync::Mutex::new(None),
+            events,
+        });
         
         Ok(Self {
-            ctx: Arc::new(ctx),
-            socket_path: SOCKET_PATH.to_string(),
-            #[cfg(feature = "openvino")]
-            state,
+            socket_path: options.socket_path.clone(),
+            config,
         })
     }
     
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(&self, ready_file: &str, tcp: Option<crate::daemons::TcpListen>) -> Result<()> {
         // Remove existing socket if it exists
         if Path::new(&self.socket_path).exists() {
             fs::remove_file(&self.socket_path)?;
@@ -135,205 +771,546 @@ impl WhisperDaemon {
         let listener = UnixListener::bind(&self.socket_path)
             .context("Failed to bind Unix socket")?;
         
-        // Set socket permissions
+        // Only this user may connect
         let mut perms = fs::metadata(&self.socket_path)?.permissions();
-        perms.set_mode(0o666);
+        perms.set_mode(0o600);
         fs::set_permissions(&self.socket_path, perms)?;
         
         info!("Daemon listening on {}", self.socket_path);
         
+        let tcp = match tcp {
+            Some(tcp) => {
+                let listener = TcpListener::bind(tcp.address)
+                    .await
+                    .with_context(|| format!("Failed to listen on {}", tcp.address))?;
+                info!("Daemon also listening on {}{} (token required)", crate::daemons::TCP_SCHEME, tcp.address);
+                Some((listener, Transport::Tcp { token: Arc::new(tcp.token) }))
+            }
+            None => None,
+        };
+        
+        let inbox = Arc::new(crate::inbox::open(ready_file)?);
+        
+        // Model is loaded and the socket is up: tell supervisors and clients
+        let ready = ReadyInfo {
+            pid: std::process::id(),
+            backend: BACKEND.to_string(),
+            socket_path: self.socket_path.clone(),
+            model: self.config.model_name.clone(),
+            language: self.config.language.clone(),
+            queue: Some(QueueStatus::default()),
+            health: None,
+            acceleration: Some(crate::helpers::get_acceleration_type()),
+            openvino_active: *self.config.scheduler.openvino_active.lock().unwrap(),
+            openvino_device: self.config.scheduler.openvino_device.lock().unwrap().clone(),
+            model_path: Some(self.config.model_path.clone()),
+            memory: None,
+            version: Some(crate::upgrade::VERSION.to_string()),
+            inbox: Some(inbox.to_string()),
+            fast_model: self.config.fast.as_ref().map(|fast| fast.name.clone()),
+        };
+        // Instead of ready::remove_on_exit, which exits in the signal handler
+        // before subscribers could hear about it
+        let signals = Signals::new()?;
+        ready::write(ready_file, &ready)?;
+        tokio::spawn(signals.shut_down(ready_file.to_string(), Arc::clone(&self.config)));
+        info!("Ready file written to {}", ready_file);
+        let _ = self.config.scheduler.ready.set((ready_file.to_string(), ready));
+        tokio::spawn(watch_memory(Arc::clone(&self.config)));
+        
         // Accept connections in a loop
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    #[cfg(feature = "openvino")]
-                    {
-                        let state = Arc::clone(&self.state);
-                        // Spawn a task to handle the connection
-                        tokio::spawn(async move {
-                            let result = handle_connection_with_state(stream, state).await;
-                            
-                            if let Err(e) = result {
-                                error!("Error handling connection: {}", e);
-                            }
-                        });
-                    }
-                    #[cfg(not(feature = "openvino"))]
-                    {
-                        let ctx = Arc::clone(&self.ctx);
-                        // Spawn a task to handle the connection
-                        tokio::spawn(async move {
-                            let result = handle_connection(stream, ctx).await;
-                            
-                            if let Err(e) = result {
-                                error!("Error handling connection: {}", e);
-                            }
-                        });
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => self.serve(stream, Transport::Unix { inbox: Arc::clone(&inbox) }),
+                    Err(e) => error!("Error accepting connection: {}", e),
+                },
+                accepted = accept_tcp(&tcp) => match accepted {
+                    Ok((stream, peer, transport)) => {
+                        info!("TCP connection from {}", peer);
+                        self.serve(stream, transport);
                     }
-                }
-                Err(e) => {
-                    error!("Error accepting connection: {}", e);
-                }
+                    Err(e) => error!("Error accepting TCP connection: {}", e),
+                },
             }
         }
-        
-        Ok(())
+    }
+    
+    /// Handle a connection on its own task
+    fn serve<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(&self, stream: S, transport: Transport) {
+        let config = Arc::clone(&self.config);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, config, transport).await {
+                error!("Error handling connection: {}", e);
+            }
+        });
     }
 }
 
-async fn handle_connection(
-    mut stream: UnixStream,
-    ctx: Arc<WhisperContext>,
-) -> Result<()> {
-    // Read request
-    let mut buffer = vec![0; 4096];
-    let n = stream.read(&mut buffer)?;
-    let request_str = String::from_utf8_lossy(&buffer[..n]);
-    
-    // Parse request
-    let request: TranscriptionRequest = serde_json::from_str(&request_str)
-        .context("Failed to parse request")?;
-    
-    info!("Processing audio file: {}", request.audio_path);
+/// SIGTERM and SIGINT, which stop the daemon
+struct Signals {
+    terminate: tokio::signal::unix::Signal,
+    interrupt: tokio::signal::unix::Signal,
+}
+
+impl Signals {
+    fn new() -> Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Signals {
+            terminate: signal(SignalKind::terminate()).context("Failed to handle SIGTERM")?,
+            interrupt: signal(SignalKind::interrupt()).context("Failed to handle SIGINT")?,
+        })
+    }
     
-    // Check if file exists
-    if !Path::new(&request.audio_path).exists() {
-        let response = TranscriptionResponse {
-            success: false,
-            text: None,
-            error: Some(format!("Audio file not found: {}", request.audio_path)),
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
-    }
-    
-    // Check file size (WAV header is 44 bytes)
-    let metadata = fs::metadata(&request.audio_path)?;
-    if metadata.len() <= 44 {
-        warn!("Audio file is empty (only header): {}", request.audio_path);
-        let response = TranscriptionResponse {
-            success: true,
-            text: Some(String::new()),
-            error: None,
+    /// Wait for either signal, tell subscribers the daemon is going away and
+    /// exit, removing the ready file
+    async fn shut_down(mut self, ready_file: String, config: Arc<DaemonConfig>) {
+        tokio::select! {
+            _ = self.terminate.recv() => {}
+            _ = self.interrupt.recv() => {}
+        }
+        info!("Shutting down");
+        config.emit(DaemonEvent::ShuttingDown);
+        if config.events.receiver_count() > 0 {
+            tokio::time::sleep(SHUTDOWN_GRACE).await;
+        }
+        ready::remove(&ready_file);
+        std::process::exit(0);
+    }
+}
+
+/// Next connection on the TCP listener; never resolves without one
+async fn accept_tcp(tcp: &Option<(TcpListener, Transport)>) -> std::io::Result<(TcpStream, std::net::SocketAddr, Transport)> {
+    match tcp {
+        Some((listener, transport)) => listener.accept().await.map(|(stream, peer)| (stream, peer, transport.clone())),
+        None => std::future::pending().await,
+    }
+}
+        
+/// Read the next message, leaving whatever follows it in `buffer`
+async fn read_message<T: DeserializeOwned, S: AsyncRead + Unpin>(stream: &mut S, buffer: &mut Vec<u8>) -> Result<T> {
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(message) = protocol::take_message(buffer) {
+            return message.context("Failed to parse request");
+        }
+        if buffer.len() >= MAX_REQUEST_BYTES {
+            return Err(anyhow!("Request is over {} bytes", MAX_REQUEST_BYTES));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("Client hung up before sending a complete request"));
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Compare tokens without stopping at the first byte that differs
+fn token_matches(given: Option<&str>, expected: &str) -> bool {
+    let given = given.unwrap_or("").as_bytes();
+    given.len() == expected.len() && given.iter().zip(expected.as_bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Why a TCP client's hello can't be served, as an error code and message
+fn tcp_refusal(hello: &Hello, token: &str) -> Option<(&'static str, String)> {
+    if !token_matches(hello.token.as_deref(), token) {
+        return Some((UNAUTHORIZED, "Missing or wrong daemon token (WA_DAEMON_TOKEN)".to_string()));
+    }
+    if !hello.has(protocol::CAP_INLINE_AUDIO) {
+        return Some((UNSUPPORTED_PROTOCOL, "Clients connecting over TCP must send their audio inline".to_string()));
+    }
+    None
+}
+
+/// Do the hello exchange and read the request or command that follows it,
+/// or take one as is from a client that skips the exchange, along with the
+/// framing agreed for the rest of the connection. `None` means the client
+/// was refused: no common protocol version, or a TCP client that doesn't
+/// meet the listener's terms. Bytes read past the request stay in `buffer`.
+async fn read_request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    buffer: &mut Vec<u8>,
+    transport: &Transport,
+    capabilities: &[&str],
+) -> Result<Option<(ClientMessage, Framing)>> {
+    let opening = read_message::<Opening, _>(stream, buffer).await?;
+    if let Transport::Tcp { token } = transport {
+        let refusal = match &opening {
+            Opening::Legacy(_) | Opening::Command(_) => Some((UNSUPPORTED_PROTOCOL, "Clients connecting over TCP must start with a hello".to_string())),
+            Opening::Hello(hello) => tcp_refusal(hello, token),
         };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
+        if let Some((code, error)) = refusal {
+            warn!("Refusing TCP client: {}", error);
+            send_response(stream, Framing::default(), TranscriptionResponse::err_with_code(code, error)).await?;
+            return Ok(None);
+        }
     }
+    match opening {
+        Opening::Legacy(request) => {
+            info!("Serving a client without protocol negotiation");
+            Ok(Some((ClientMessage::Request(request), Framing::default())))
+        }
+        Opening::Command(command) => Ok(Some((ClientMessage::Command(command), Framing::default()))),
+        Opening::Hello(hello) => match protocol::negotiate(&hello, capabilities, BACKEND, transport.inbox()) {
+            Ok(reply) => {
+                stream.write_all(&protocol::frame(&reply)?).await?;
+                Ok(Some((read_message(stream, buffer).await?, Framing::agreed(&reply))))
+            }
+            Err(error) => {
+                warn!("Refusing client: {}", error);
+                let response = TranscriptionResponse::err_with_code(protocol::UNSUPPORTED_PROTOCOL, error);
+                send_response(stream, Framing::default(), response).await?;
+                Ok(None)
+            }
+        },
+    }
+}
+
+/// Audio a client sent inline, spooled to the runtime dir for as long as its
+/// request is handled
+struct Upload {
+    path: String,
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Receive the `len` bytes of audio that follow a request, starting with
+/// whatever of them was already read into `buffer`
+async fn receive_audio<S: AsyncRead + Unpin>(stream: &mut S, buffer: &mut Vec<u8>, len: u64) -> Result<Upload> {
+    if len > MAX_INLINE_AUDIO_BYTES {
+        return Err(anyhow!("Inline audio is over {} bytes", MAX_INLINE_AUDIO_BYTES));
+    }
+    let len = len as usize;
+    let mut audio = std::mem::take(buffer);
+    audio.truncate(len);
+    let mut chunk = vec![0u8; 64 * 1024];
+    tokio::time::timeout(UPLOAD_TIMEOUT, async {
+        while audio.len() < len {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(anyhow!("Client hung up after {} of {} bytes of audio", audio.len(), len));
+            }
+            audio.extend_from_slice(&chunk[..n.min(len - audio.len())]);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|_| anyhow!("Timed out receiving audio"))??;
     
-    // Transcribe using a fresh state for each request
-    let text = transcribe_audio(&request.audio_path, ctx)?;
-    
-    // Send response
-    let response = TranscriptionResponse {
-        success: true,
-        text: Some(text),
-        error: None,
-    };
-    
-    let response_json = serde_json::to_string(&response)?;
-    stream.write_all(response_json.as_bytes())?;
-    
-    Ok(())
+    let name = format!("{}.wav", crate::recording::unique_stem("voice-remote"));
+    let upload = Upload { path: crate::recording::spool_file(&name) };
+    fs::write(&upload.path, audio).context("Failed to store inline audio")?;
+    Ok(upload)
 }
 
-#[cfg(feature = "openvino")]
-async fn handle_connection_with_state(
-    mut stream: UnixStream,
-    state: Arc<tokio::sync::Mutex<WhisperState>>,
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    config: Arc<DaemonConfig>,
+    transport: Transport,
 ) -> Result<()> {
     // Read request
-    let mut buffer = vec![0; 4096];
-    let n = stream.read(&mut buffer)?;
-    let request_str = String::from_utf8_lossy(&buffer[..n]);
+    let mut buffer = Vec::new();
+    let capabilities = config.capabilities();
+    let request = tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream, &mut buffer, &transport, &capabilities))
+        .await
+        .map_err(|_| anyhow!("Timed out waiting for the request"))??;
+    let (mut request, framing) = match request {
+        Some((ClientMessage::Request(request), framing)) => (request, framing),
+        Some((ClientMessage::Command(Command::Subscribe), _)) => return forward_events(stream, config.events.subscribe()).await,
+        None => return Ok(()),
+    };
+    let received = std::time::Instant::now();
     
-    // Parse request
-    let request: TranscriptionRequest = serde_json::from_str(&request_str)
-        .context("Failed to parse request")?;
+    // Inline audio takes the place of the path for the rest of the request
+    let _upload = match request.audio_bytes {
+        Some(len) => match receive_audio(&mut stream, &mut buffer, len).await {
+            Ok(upload) => {
+                request.audio_path = upload.path.clone();
+                Some(upload)
+            }
+            Err(e) => {
+                warn!("Failed to receive inline audio: {}", e);
+                return send_response(&mut stream, framing, TranscriptionResponse::err(e.to_string())).await;
+            }
+        },
+        None if matches!(transport, Transport::Tcp { .. }) => {
+            let response = TranscriptionResponse::err("Requests over TCP must carry their audio inline".to_string());
+            return send_response(&mut stream, framing, response).await;
+        }
+        None => None,
+    };
+    // Recorded into the inbox: ours to delete once answered
+    let _claim = crate::inbox::claim(transport.inbox(), request.managed, &request.audio_path);
     
     info!("Processing audio file: {}", request.audio_path);
     
+    if let Some(response) = request.check_backend(BACKEND) {
+        warn!("Rejecting request for backend {:?}", request.backend);
+        return send_response(&mut stream, framing, response).await;
+    }
+    
     // Check if file exists
     if !Path::new(&request.audio_path).exists() {
-        let response = TranscriptionResponse {
-            success: false,
-            text: None,
-            error: Some(format!("Audio file not found: {}", request.audio_path)),
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
-    }
-    
-    // Check file size (WAV header is 44 bytes)
-    let metadata = fs::metadata(&request.audio_path)?;
-    if metadata.len() <= 44 {
-        warn!("Audio file is empty (only header): {}", request.audio_path);
-        let response = TranscriptionResponse {
-            success: true,
-            text: Some(String::new()),
-            error: None,
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
+        let response = TranscriptionResponse::err(format!("Audio file not found: {}", request.audio_path));
+        return send_response(&mut stream, framing, response).await;
     }
     
-    // Transcribe using the reusable state
-    let text = transcribe_with_state(&request.audio_path, state).await?;
+    // Nothing worth loading the model for; unreadable files are reported by load_samples
+    let audio = match crate::wav::measure_file(&request.audio_path, crate::recording::min_duration(None)) {
+        Ok(AudioLength::Empty) => {
+            warn!("Audio file holds no audio: {}", request.audio_path);
+            return send_response(&mut stream, framing, TranscriptionResponse::ok(String::new())).await;
+        }
+        Ok(AudioLength::TooShort(duration)) => {
+            warn!("Audio file is only {} ms: {}", duration.as_millis(), request.audio_path);
+            return send_response(&mut stream, framing, TranscriptionResponse::ok(String::new())).await;
+        }
+        Ok(AudioLength::Ok(duration)) => Some(duration),
+        Err(_) => None,
+    };
     
-    // Send response
-    let response = TranscriptionResponse {
-        success: true,
-        text: Some(text),
-        error: None,
+    let max_audio_seconds = config.max_audio_seconds;
+    let (request, loaded) = tokio::task::spawn_blocking(move || {
+        let loaded = load_samples(&request, max_audio_seconds);
+        (request, loaded)
+    })
+    .await
+    .context("Audio loading task failed")?;
+    let samples = match loaded? {
+        Ok(samples) => samples,
+        Err(response) => {
+            return send_response(&mut stream, framing, response).await;
+        }
     };
     
-    let response_json = serde_json::to_string(&response)?;
-    stream.write_all(response_json.as_bytes())?;
+    // The fast model is on the GPU, so staying off it wins over speed
+    if let Some(fast) = config.fast.as_ref().filter(|_| request.fast && !request.no_gpu) {
+        let response = transcribe_fast(fast, samples, request, &config).await;
+        return send_response(&mut stream, framing, response.with_audio(audio).with_latency(received)).await;
+    }
+    
+    // Batch jobs let pending dictation go first
+    let _slot = config.scheduler.acquire(request.priority).await;
+    info!("Transcribing {} request {}", request.priority, request.audio_path);
     
-    Ok(())
+    if let Some(reason) = config.scheduler.unavailable() {
+        let response = TranscriptionResponse::err_with_code(
+            MODEL_UNAVAILABLE,
+            format!("The whisper.cpp daemon can't transcribe until it is restarted: {}", reason),
+        );
+        return send_response(&mut stream, framing, response).await;
+    }
+    
+    let no_gpu = request.no_gpu;
+    let engine = if no_gpu { cpu_engine(&config).await } else { current_engine(&config).await };
+    let engine = match engine {
+        Ok(engine) => engine,
+        Err(response) => return send_response(&mut stream, framing, response).await,
+    };
+    let language = request_language(request.language, &config);
+    // Kept for a retry, which reuses the audio instead of asking for it again
+    let retry_samples = crate::retry::enabled().then(|| samples.clone());
+    let started = std::time::Instant::now();
+    config.emit(DaemonEvent::ProcessingStarted);
+    let first = engine.transcribe(samples, language.clone(), request.initial_prompt.clone(), Decoding::Greedy, &config).await;
+    let response = match first {
+        Ok(transcript) => {
+            let text = artifacts::strip(&transcript.text);
+            match retry_samples.filter(|_| crate::retry::worth_retrying(&text, started.elapsed())) {
+                Some(samples) => {
+                    info!("Empty transcript after {:?}, retrying with beam search", started.elapsed());
+                    match engine.transcribe(samples, language, request.initial_prompt, Decoding::Thorough, &config).await {
+                        Ok(retry) => {
+                            info!("Retry finished after {:?} in total", started.elapsed());
+                            respond(artifacts::strip(&retry.text), retry, request.segments).with_retried(true)
+                        }
+                        Err(e) => {
+                            warn!("Retry of {} failed: {:#}", request.audio_path, e);
+                            respond(text, transcript, request.segments).with_retried(true)
+                        }
+                    }
+                }
+                None => respond(text, transcript, request.segments),
+            }
+        }
+        Err(e) => {
+            error!("Transcription of {} failed: {:#}", request.audio_path, e);
+            TranscriptionResponse::err(format!("Transcription failed: {}", e))
+        }
+    };
+    config.emit(processing_done(&response, started));
+    let cpu = no_gpu && response.success;
+    send_response(&mut stream, framing, response.with_cpu(cpu).with_audio(audio).with_latency(received)).await
 }
 
-#[cfg(feature = "openvino")]
-async fn transcribe_with_state(
-    audio_path: &str,
-    state: Arc<tokio::sync::Mutex<WhisperState>>,
-) -> Result<String> {
-    use std::time::Instant;
-    let start = Instant::now();
+/// The language a request names, else the daemon's own, saying so since
+/// clients always send the language they resolved
+fn request_language(requested: Option<String>, config: &DaemonConfig) -> String {
+    match requested {
+        Some(language) => {
+            info!("Language: {}", language);
+            language
+        }
+        None => {
+            info!("Request names no language, using the daemon's {}", config.language);
+            config.language.clone()
+        }
+    }
+}
+
+/// The response carrying `text`, the cleaned-up transcript, and the
+/// segments if the request asked for them
+fn respond(text: String, transcript: Transcript, segments: bool) -> TranscriptionResponse {
+    let response = TranscriptionResponse::ok(text).with_language(transcript.language);
+    if segments {
+        response.with_segments(transcript.segments)
+    } else {
+        response
+    }
+}
+
+/// Transcribe a short command with the fast model: one segment, no glossary
+/// prompt and no retry, which would only cost time on a couple of words
+async fn transcribe_fast(fast: &FastEngine, samples: Vec<f32>, request: TranscriptionRequest, config: &Arc<DaemonConfig>) -> TranscriptionResponse {
+    let _busy = fast.busy.lock().await;
+    info!("Transcribing fast request {} with {}", request.audio_path, fast.name);
+    let language = request_language(request.language, config);
+    let started = std::time::Instant::now();
+    config.emit(DaemonEvent::ProcessingStarted);
+    let response = match fast.engine.transcribe(samples, language, None, Decoding::Fast, config).await {
+        Ok(transcript) => respond(artifacts::strip(&transcript.text), transcript, request.segments).with_model(&fast.name),
+        Err(e) => {
+            error!("Fast transcription of {} failed: {:#}", request.audio_path, e);
+            TranscriptionResponse::err(format!("Transcription failed: {}", e))
+        }
+    };
+    config.emit(processing_done(&response, started));
+    response
+}
+
+/// processing_done for `response`, whose transcription began at `started`
+fn processing_done(response: &TranscriptionResponse, started: std::time::Instant) -> DaemonEvent {
+    DaemonEvent::ProcessingDone {
+        chars: response.text.as_deref().map_or(0, |text| text.chars().count()),
+        ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// Send a subscriber the daemon's events until it hangs up or the daemon
+/// shuts down. One that falls EVENT_BUFFER events behind is disconnected,
+/// since it missed some and has to re-sync anyway.
+async fn forward_events<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, mut events: broadcast::Receiver<DaemonEvent>) -> Result<()> {
+    info!("Client subscribed to events");
+    send_event(&mut stream, &DaemonEvent::Subscribed).await?;
+    let mut chunk = [0u8; 1024];
+    loop {
+        tokio::select! {
+            // Subscribers have nothing more to say; reading only notices them leave
+            read = stream.read(&mut chunk) => match read {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => {}
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    send_event(&mut stream, &event).await?;
+                    if event == DaemonEvent::ShuttingDown {
+                        return Ok(());
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Subscriber missed {} events, disconnecting it", missed);
+                    return Ok(());
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+async fn send_event<S: AsyncWrite + Unpin>(stream: &mut S, event: &DaemonEvent) -> Result<()> {
+    let frame = protocol::frame(event)?;
+    tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(&frame))
+        .await
+        .map_err(|_| anyhow!("Timed out sending an event"))??;
+    Ok(())
+}
     
-    // Load and convert audio 
-    let t1 = Instant::now();
-    let audio_data = std::fs::read(audio_path)
+/// Write a response, tagged with this daemon's backend, framed as agreed
+async fn send_response<S: AsyncWrite + Unpin>(stream: &mut S, framing: Framing, response: TranscriptionResponse) -> Result<()> {
+    let response_json = framing.frame(&response.with_backend(BACKEND))?;
+    tokio::time::timeout(WRITE_TIMEOUT, async {
+        stream.write_all(&response_json).await?;
+        stream.shutdown().await
+    })
+    .await
+    .map_err(|_| anyhow!("Timed out sending the response"))??;
+    Ok(())
+}
+
+/// Read and decode the request's audio. The inner Err is the response to send
+/// back when the audio is over the duration cap and the request didn't allow it.
+fn load_samples(
+    request: &TranscriptionRequest,
+    max_audio_seconds: u64,
+) -> Result<std::result::Result<Vec<f32>, TranscriptionResponse>> {
+    let t1 = std::time::Instant::now();
+    let audio_data = std::fs::read(&request.audio_path)
         .context("Failed to read audio file")?;
     eprintln!("DEBUG DAEMON: File read took {:?}", t1.elapsed());
     
-    let t2 = Instant::now();
+    let t2 = std::time::Instant::now();
     let samples = wav_to_samples(&audio_data)?;
     eprintln!("DEBUG DAEMON: WAV conversion took {:?}", t2.elapsed());
     
-    // Lock the state for exclusive use
-    let mut state = state.lock().await;
-    eprintln!("DEBUG DAEMON: Using pre-initialized state with OpenVINO");
+    // Exactly max_audio_seconds is still accepted
+    let seconds = samples.len() as f64 / crate::wav::TARGET_RATE as f64;
+    if !request.allow_long && samples.len() as u64 > max_audio_seconds * crate::wav::TARGET_RATE as u64 {
+        warn!("Rejecting {:.0} s of audio (limit {} s): {}", seconds, max_audio_seconds, request.audio_path);
+        return Ok(Err(TranscriptionResponse::err_with_code(
+            AUDIO_TOO_LONG,
+            format!(
+                "Audio is {:.0} s, over the daemon's {} s limit. Transcribe long recordings with `whisp-away transcribe --allow-long --priority batch`",
+                seconds, max_audio_seconds
+            ),
+        )));
+    }
+    
+    Ok(Ok(samples))
+}
+
+#[cfg(feature = "openvino")]
+fn transcribe_with_state(
+    samples: Vec<f32>,
+    state: &mut WhisperState,
+    language: &str,
+    prompt: Option<&str>,
+    decoding: Decoding,
+) -> Result<Transcript> {
+    use std::time::Instant;
+    let start = Instant::now();
     
     // Set up parameters - optimized for speed
     let t4 = Instant::now();
-    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-    let num_threads = std::thread::available_parallelism()
-        .map(|n| n.get() as i32)
-        .unwrap_or(8);
-    params.set_n_threads(num_threads);
+    let mut params = FullParams::new(decoding.strategy());
+    params.set_n_threads(crate::helpers::threads() as i32);
     params.set_translate(false);
-    params.set_language(Some("en"));
+    params.set_language(Some(language));
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_timestamps(false);
     params.set_suppress_blank(true);
+    params.set_suppress_nst(artifacts::suppress_non_speech());
     params.set_temperature(0.0);
-    params.set_single_segment(false);
+    params.set_single_segment(decoding == Decoding::Fast);
     params.set_no_context(true);
+    decoding.apply(&mut params);
+    if let Some(prompt) = prompt {
+        params.set_initial_prompt(prompt);
+    }
     eprintln!("DEBUG DAEMON: Params setup took {:?}", t4.elapsed());
     
     // Run transcription
@@ -343,41 +1320,43 @@ async fn transcribe_with_state(
         .context("Failed to transcribe audio")?;
     eprintln!("DEBUG DAEMON: Whisper transcription completed in {:?}", t5.elapsed());
     
-    // Get the transcribed text from segments
+    // Language whisper used (the detected one when "auto" was requested)
+    let detected = state.full_lang_id_from_state().ok()
+        .and_then(whisper_rs::get_lang_str)
+        .map(str::to_string);
+    
+    // Get the transcribed text from segments; their timestamps are in centiseconds
     let t6 = Instant::now();
-    let mut text = String::new();
+    let mut segments = Vec::new();
     let num_segments = state.full_n_segments();
     for i in 0..num_segments {
         let segment = state.get_segment(i)
             .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
-        let segment_text = segment.to_str()?;
-        text.push_str(segment_text);
-        text.push(' ');
+        segments.push(Segment {
+            start_ms: segment.start_timestamp().max(0) as u64 * 10,
+            end_ms: segment.end_timestamp().max(0) as u64 * 10,
+            text: segment.to_str()?.to_string(),
+            confidence: confidence(&segment),
+        });
     }
+    let text = normalize::join_segments(segments.iter().map(|segment| segment.text.as_str()));
     eprintln!("DEBUG DAEMON: Segment extraction took {:?}", t6.elapsed());
     
     eprintln!("DEBUG DAEMON: Total transcription time: {:?}", start.elapsed());
     
-    Ok(text.trim().to_string())
+    Ok(Transcript { text: text.trim().to_string(), language: detected, segments })
 }
 
 fn transcribe_audio(
-    audio_path: &str,
+    samples: Vec<f32>,
     ctx: Arc<WhisperContext>,
-) -> Result<String> {
+    language: &str,
+    prompt: Option<&str>,
+    decoding: Decoding,
+) -> Result<Transcript> {
     use std::time::Instant;
     let start = Instant::now();
     
-    // Load and convert audio 
-    let t1 = Instant::now();
-    let audio_data = std::fs::read(audio_path)
-        .context("Failed to read audio file")?;
-    eprintln!("DEBUG DAEMON: File read took {:?}", t1.elapsed());
-    
-    let t2 = Instant::now();
-    let samples = wav_to_samples(&audio_data)?;
-    eprintln!("DEBUG DAEMON: WAV conversion took {:?}", t2.elapsed());
-    
     // Create a fresh state for this transcription
     let t3 = Instant::now();
     let mut state = ctx.create_state()
@@ -387,20 +1366,22 @@ fn transcribe_audio(
     
     // Set up parameters - optimized for speed
     let t4 = Instant::now();
-    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-    let num_threads = std::thread::available_parallelism()
-        .map(|n| n.get() as i32)
-        .unwrap_or(8);
-    params.set_n_threads(num_threads);
+    let mut params = FullParams::new(decoding.strategy());
+    params.set_n_threads(crate::helpers::threads() as i32);
     params.set_translate(false);
-    params.set_language(Some("en"));
+    params.set_language(Some(language));
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_timestamps(false);
     params.set_suppress_blank(true);
+    params.set_suppress_nst(artifacts::suppress_non_speech());
     params.set_temperature(0.0);
-    params.set_single_segment(false);
+    params.set_single_segment(decoding == Decoding::Fast);
     params.set_no_context(true);
+    decoding.apply(&mut params);
+    if let Some(prompt) = prompt {
+        params.set_initial_prompt(prompt);
+    }
     eprintln!("DEBUG DAEMON: Params setup took {:?}", t4.elapsed());
     
     // Run transcription
@@ -410,21 +1391,122 @@ fn transcribe_audio(
         .context("Failed to transcribe audio")?;
     eprintln!("DEBUG DAEMON: Whisper transcription completed in {:?}", t5.elapsed());
     
-    // Get the transcribed text from segments
+    // Language whisper used (the detected one when "auto" was requested)
+    let detected = state.full_lang_id_from_state().ok()
+        .and_then(whisper_rs::get_lang_str)
+        .map(str::to_string);
+    
+    // Get the transcribed text from segments; their timestamps are in centiseconds
     let t6 = Instant::now();
-    let mut text = String::new();
+    let mut segments = Vec::new();
     let num_segments = state.full_n_segments();
     for i in 0..num_segments {
         let segment = state.get_segment(i)
             .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
-        let segment_text = segment.to_str()?;
-        text.push_str(segment_text);
-        text.push(' ');
+        segments.push(Segment {
+            start_ms: segment.start_timestamp().max(0) as u64 * 10,
+            end_ms: segment.end_timestamp().max(0) as u64 * 10,
+            text: segment.to_str()?.to_string(),
+            confidence: confidence(&segment),
+        });
     }
+    let text = normalize::join_segments(segments.iter().map(|segment| segment.text.as_str()));
     eprintln!("DEBUG DAEMON: Segment extraction took {:?}", t6.elapsed());
     
     eprintln!("DEBUG DAEMON: Total transcription time: {:?}", start.elapsed());
     
-    Ok(text.trim().to_string())
+    Ok(Transcript { text: text.trim().to_string(), language: detected, segments })
+}
+
+/// Mean probability of a segment's text tokens. Timestamp and other special
+/// tokens (`[_BEG_]`, `[_TT_150]`) say nothing about what was heard, so they
+/// are left out.
+fn confidence(segment: &WhisperSegment) -> Option<f32> {
+    let probabilities: Vec<f32> = (0..segment.n_tokens())
+        .filter_map(|i| segment.get_token(i))
+        .filter(|token| !token.to_str().is_ok_and(|text| text.starts_with("[_")))
+        .map(|token| token.token_probability())
+        .collect();
+    if probabilities.is_empty() {
+        return None;
+    }
+    Some(probabilities.iter().sum::<f32>() / probabilities.len() as f32)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn tcp_hello(token: Option<&str>) -> Hello {
+        let mut hello = Hello::new(&[protocol::CAP_TRANSCRIBE, protocol::CAP_INLINE_AUDIO]);
+        hello.token = token.map(str::to_string);
+        hello
+    }
+    
+    #[test]
+    fn tcp_clients_without_the_token_are_refused() {
+        let refusal = tcp_refusal(&tcp_hello(None), "0123abcd");
+        assert_eq!(refusal.map(|(code, _)| code), Some(UNAUTHORIZED));
+    }
+    
+    #[test]
+    fn tcp_clients_with_a_wrong_token_are_refused() {
+        for token in ["", "0123abce", "0123abcd0", "0123abc"] {
+            let refusal = tcp_refusal(&tcp_hello(Some(token)), "0123abcd");
+            assert_eq!(refusal.map(|(code, _)| code), Some(UNAUTHORIZED), "token {:?}", token);
+        }
+    }
+    
+    #[test]
+    fn tcp_clients_with_the_token_are_served() {
+        assert!(tcp_refusal(&tcp_hello(Some("0123abcd")), "0123abcd").is_none());
+    }
+    
+    #[test]
+    fn a_failed_rebuild_leaves_the_model_unavailable() {
+        let scheduler = Scheduler::default();
+        scheduler.record_state_recreation(None);
+        scheduler.record_state_recreation(None);
+        assert_eq!(scheduler.health.lock().unwrap().state_recreations, 2);
+        assert_eq!(scheduler.unavailable(), None);
+        
+        scheduler.record_state_recreation(Some("OpenVINO init failed".to_string()));
+        assert_eq!(scheduler.unavailable().as_deref(), Some("OpenVINO init failed"));
+    }
+    
+    /// Cap on the test recordings, in seconds
+    const CAP: u64 = 2;
+    
+    /// A request for a 16 kHz recording of `samples` samples
+    fn request_for(name: &str, samples: usize) -> TranscriptionRequest {
+        let path = std::env::temp_dir().join(format!("whisp-away-test-{}-{}.wav", std::process::id(), name));
+        fs::write(&path, crate::wav::encode_s16_mono(&vec![0.1; samples], crate::wav::TARGET_RATE)).unwrap();
+        TranscriptionRequest::new(&path.to_string_lossy(), "en")
+    }
+    
+    #[test]
+    fn audio_exactly_at_the_cap_is_accepted() {
+        let at_cap = CAP as usize * crate::wav::TARGET_RATE as usize;
+        let request = request_for("at-cap", at_cap);
+        let samples = load_samples(&request, CAP).unwrap().unwrap();
+        assert_eq!(samples.len(), at_cap);
+        let _ = fs::remove_file(&request.audio_path);
+    }
+    
+    #[test]
+    fn audio_a_sample_over_the_cap_is_rejected() {
+        let request = request_for("over-cap", CAP as usize * crate::wav::TARGET_RATE as usize + 1);
+        let response = load_samples(&request, CAP).unwrap().unwrap_err();
+        assert_eq!(response.error_code.as_deref(), Some(AUDIO_TOO_LONG));
+        assert!(response.error.as_deref().unwrap_or_default().contains("--allow-long"), "{:?}", response.error);
+        let _ = fs::remove_file(&request.audio_path);
+    }
+    
+    #[test]
+    fn allow_long_lifts_the_cap() {
+        let mut request = request_for("allow-long", CAP as usize * crate::wav::TARGET_RATE as usize * 3);
+        request.allow_long = true;
+        assert!(load_samples(&request, CAP).unwrap().is_ok());
+        let _ = fs::remove_file(&request.audio_path);
+    }
+}
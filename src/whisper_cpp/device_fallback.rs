@@ -0,0 +1,219 @@
+use anyhow::Result;
+use std::fmt;
+use tokio::sync::broadcast;
+use crate::events::{self, Event};
+use crate::protocol::DaemonEvent;
+
+/// Where the shared whisper state runs its encoder, best first. A state that
+/// keeps failing on one is rebuilt on the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderDevice {
+    /// OpenVINO's pick, the GPU when there is one
+    Auto,
+    /// OpenVINO on the CPU
+    Cpu,
+    /// Plain whisper.cpp, OpenVINO left out
+    Plain,
+}
+
+impl EncoderDevice {
+    /// Device OpenVINO is initialized with, or None to leave OpenVINO out
+    pub fn openvino_name(self) -> Option<&'static str> {
+        match self {
+            EncoderDevice::Auto => Some("AUTO"),
+            EncoderDevice::Cpu => Some("CPU"),
+            EncoderDevice::Plain => None,
+        }
+    }
+    
+    /// Where the state goes once this device keeps failing
+    fn next(self) -> Option<Self> {
+        match self {
+            EncoderDevice::Auto => Some(EncoderDevice::Cpu),
+            EncoderDevice::Cpu => Some(EncoderDevice::Plain),
+            EncoderDevice::Plain => None,
+        }
+    }
+}
+
+/// As published in the ready file and events: AUTO, CPU or none
+impl fmt::Display for EncoderDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.openvino_name().unwrap_or("none"))
+    }
+}
+
+/// Failed transcriptions in a row after which the state moves to the next
+/// device (WA_OPENVINO_MAX_FAILURES, default 3)
+fn max_failures() -> u32 {
+    std::env::var("WA_OPENVINO_MAX_FAILURES").ok()
+        .and_then(|n| n.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(3)
+}
+
+/// A transcription on the shared state, and rebuilding that state. The
+/// daemon's runs whisper; the mock daemon's fails on the devices it is told
+/// to, so moving off a failing device can be exercised without one.
+pub trait Inference {
+    type Input;
+    type Output;
+    
+    fn infer(&mut self, input: Self::Input) -> Result<Self::Output>;
+    
+    /// Replace the state with a fresh one on `device`. Reporting a rebuild
+    /// that failed is up to the implementation.
+    fn rebuild(&mut self, device: EncoderDevice);
+}
+
+/// The device the shared state is on, how many transcriptions in a row
+/// have failed there, and the daemon's subscribers to tell about a move
+pub struct DeviceFallback {
+    device: EncoderDevice,
+    failures: u32,
+    max_failures: u32,
+    subscribers: broadcast::Sender<DaemonEvent>,
+}
+
+impl DeviceFallback {
+    pub fn new(device: EncoderDevice, subscribers: broadcast::Sender<DaemonEvent>) -> Self {
+        Self { device, failures: 0, max_failures: max_failures(), subscribers }
+    }
+    
+    pub fn device(&self) -> EncoderDevice {
+        self.device
+    }
+    
+    /// Transcribe `input`. A success resets the count. A failure rebuilds the
+    /// state, since a failed `full` can leave it inconsistent, and once it is
+    /// the last of `max_failures` in a row, rebuilds it on the next device.
+    pub fn run<I: Inference>(&mut self, inference: &mut I, input: I::Input) -> Result<I::Output> {
+        let result = inference.infer(input);
+        if result.is_ok() {
+            self.failures = 0;
+            return result;
+        }
+        
+        self.failures += 1;
+        if self.failures >= self.max_failures {
+            if let Some(next) = self.device.next() {
+                eprintln!(
+                    "WARNING: {} transcriptions in a row failed with OpenVINO device {}, moving to {}",
+                    self.failures, self.device, next
+                );
+                events::emit(Event::DeviceFallback {
+                    from: self.device.to_string(),
+                    to: next.to_string(),
+                    failures: self.failures,
+                });
+                // Err only means nobody is subscribed
+                let _ = self.subscribers.send(DaemonEvent::DeviceFallback {
+                    from: self.device.to_string(),
+                    to: next.to_string(),
+                    failures: self.failures,
+                });
+                self.device = next;
+                self.failures = 0;
+            }
+        }
+        inference.rebuild(self.device);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    
+    /// A transcriber whose calls fail when their number (from 1) is in
+    /// `failing`, noting the devices it was rebuilt on
+    struct MockTranscriber {
+        failing: Vec<u32>,
+        calls: u32,
+        rebuilds: Vec<EncoderDevice>,
+    }
+    
+    impl MockTranscriber {
+        fn failing(failing: impl IntoIterator<Item = u32>) -> Self {
+            Self { failing: failing.into_iter().collect(), calls: 0, rebuilds: Vec::new() }
+        }
+    }
+    
+    impl Inference for MockTranscriber {
+        type Input = &'static str;
+        type Output = String;
+        
+        fn infer(&mut self, audio: &'static str) -> Result<String> {
+            self.calls += 1;
+            if self.failing.contains(&self.calls) {
+                return Err(anyhow!("whisper_full_with_state failed"));
+            }
+            Ok(format!("transcript of {}", audio))
+        }
+        
+        fn rebuild(&mut self, device: EncoderDevice) {
+            self.rebuilds.push(device);
+        }
+    }
+    
+    /// A fallback on AUTO that moves after 3 failures whatever
+    /// WA_OPENVINO_MAX_FAILURES says, and its subscriber
+    fn fallback() -> (DeviceFallback, broadcast::Receiver<DaemonEvent>) {
+        let (subscribers, events) = broadcast::channel(8);
+        (DeviceFallback { device: EncoderDevice::Auto, failures: 0, max_failures: 3, subscribers }, events)
+    }
+    
+    #[test]
+    fn each_failure_rebuilds_the_state_before_the_next_request() {
+        let (mut fallback, mut events) = fallback();
+        let mut transcriber = MockTranscriber::failing(1..=2);
+        assert!(fallback.run(&mut transcriber, "a.wav").is_err());
+        assert!(fallback.run(&mut transcriber, "b.wav").is_err());
+        assert_eq!(fallback.run(&mut transcriber, "c.wav").unwrap(), "transcript of c.wav");
+        assert_eq!(transcriber.rebuilds, [EncoderDevice::Auto, EncoderDevice::Auto]);
+        assert_eq!(fallback.device(), EncoderDevice::Auto);
+        assert!(events.try_recv().is_err());
+    }
+    
+    #[test]
+    fn a_success_resets_the_count() {
+        let (mut fallback, _events) = fallback();
+        let mut transcriber = MockTranscriber::failing([1, 2, 4, 5]);
+        for _ in 0..6 {
+            let _ = fallback.run(&mut transcriber, "a.wav");
+        }
+        assert_eq!(fallback.device(), EncoderDevice::Auto);
+        assert_eq!(transcriber.rebuilds, [EncoderDevice::Auto; 4]);
+    }
+    
+    #[test]
+    fn failures_in_a_row_move_the_state_to_the_next_device() {
+        let (mut fallback, mut events) = fallback();
+        let mut transcriber = MockTranscriber::failing(1..=3);
+        for _ in 0..3 {
+            assert!(fallback.run(&mut transcriber, "a.wav").is_err());
+        }
+        assert_eq!(fallback.device(), EncoderDevice::Cpu);
+        assert_eq!(transcriber.rebuilds, [EncoderDevice::Auto, EncoderDevice::Auto, EncoderDevice::Cpu]);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            DaemonEvent::DeviceFallback { from: "AUTO".to_string(), to: "CPU".to_string(), failures: 3 }
+        );
+        assert!(fallback.run(&mut transcriber, "b.wav").is_ok());
+    }
+    
+    #[test]
+    fn the_last_device_keeps_being_rebuilt_in_place() {
+        let (mut fallback, mut events) = fallback();
+        let mut transcriber = MockTranscriber::failing(1..=9);
+        for _ in 0..9 {
+            assert!(fallback.run(&mut transcriber, "a.wav").is_err());
+        }
+        assert_eq!(fallback.device(), EncoderDevice::Plain);
+        assert_eq!(transcriber.rebuilds.last(), Some(&EncoderDevice::Plain));
+        assert_eq!(transcriber.rebuilds.len(), 9);
+        let moves: Vec<DaemonEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(moves.len(), 2);
+    }
+}
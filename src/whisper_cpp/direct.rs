@@ -1,21 +1,103 @@
 use anyhow::{anyhow, Context, Result};
 use std::fs;
-use std::process::Command;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use crate::notify;
 use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
 use crate::helpers::wav_to_samples;
+use crate::run::Run;
 use crate::typing;
+use crate::artifacts;
+use crate::normalize;
 
-/// Core transcription function using whisper-rs library
-pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
+/// Seconds GPU context creation may take before it's given up on for the CPU,
+/// unless WA_GPU_INIT_TIMEOUT says otherwise
+const DEFAULT_GPU_INIT_TIMEOUT_SECS: u64 = 20;
+
+fn gpu_init_timeout() -> Duration {
+    let secs = std::env::var("WA_GPU_INIT_TIMEOUT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_GPU_INIT_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Where a whisper context ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Gpu,
+    /// Asked for with --no-gpu (WA_NO_GPU)
+    Cpu,
+    /// GPU init hung past WA_GPU_INIT_TIMEOUT
+    CpuAfterHang,
+}
+
+impl Device {
+    /// What notifications add to the backend for it
+    pub fn note(self) -> &'static str {
+        match self {
+            Device::Gpu => "",
+            Device::Cpu => ", CPU",
+            Device::CpuAfterHang => ", CPU: GPU init hung",
+        }
+    }
+}
+
+fn cpu_context(model_path: &str) -> Result<WhisperContext> {
+    let mut ctx_params = WhisperContextParameters::default();
+    ctx_params.use_gpu(false);
+    WhisperContext::new_with_params(model_path, ctx_params)
+        .context("Failed to create WhisperContext")
+}
+
+/// Create a context for `model_path`, on the GPU unless `gpu` is false. A
+/// wedged driver can hang GPU init for a long time, so it runs on its own
+/// thread: past gpu_init_timeout() that thread is left to finish (or not) on
+/// its own, and the context is created on the CPU instead.
+pub fn create_context(model_path: &str, gpu: bool) -> Result<(WhisperContext, Device)> {
+    if !gpu {
+        return Ok((cpu_context(model_path)?, Device::Cpu));
+    }
+    
+    let (sender, receiver) = mpsc::channel();
+    let path = model_path.to_string();
+    std::thread::Builder::new()
+        .name("gpu-init".to_string())
+        .spawn(move || {
+            let mut ctx_params = WhisperContextParameters::default();
+            ctx_params.use_gpu(true);
+            ctx_params.gpu_device(0);
+            // Nobody is listening any more if the watchdog gave up
+            let _ = sender.send(WhisperContext::new_with_params(&path, ctx_params));
+        })
+        .context("Failed to start GPU init thread")?;
+    
+    let timeout = gpu_init_timeout();
+    match receiver.recv_timeout(timeout) {
+        Ok(ctx) => Ok((ctx.context("Failed to create WhisperContext")?, Device::Gpu)),
+        Err(RecvTimeoutError::Timeout) => {
+            eprintln!("WARNING: GPU init still running after {:?}, creating the context on the CPU", timeout);
+            Ok((cpu_context(model_path)?, Device::CpuAfterHang))
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(anyhow!("GPU init thread died creating the WhisperContext")),
+    }
+}
+
+/// Core transcription function using whisper-rs library.
+/// Returns the text, the language whisper detected, if any, and where it ran.
+pub fn transcribe_audio(audio_file: &str, model: &str, language: &str, prompt: Option<&str>) -> Result<(String, Option<String>, Device)> {
     let total_start = std::time::Instant::now();
     
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/home/martin".to_string());
-    let model_extension = if model.ends_with(".bin") { "" } else { ".bin" };
-    let model_path = format!("{}/.cache/whisper-cpp/models/ggml-{}{}", home, model, model_extension);
+    let model_path = crate::helpers::whisper_cpp_model_path(model);
     
     if !std::path::Path::new(&model_path).exists() {
         return Err(anyhow::anyhow!("Model file not found: {}", model_path));
     }
+    super::model::validate_model(&model_path, model)?;
+    super::model::ensure_memory(model)?;
+    
+    // One model load at a time across the machine
+    let _lock = crate::direct_lock::acquire()?;
     
     let t1 = std::time::Instant::now();
     let audio_data = fs::read(audio_file)
@@ -30,15 +112,11 @@ pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
     eprintln!("DEBUG FALLBACK: Model path: {}", model_path);
     eprintln!("DEBUG FALLBACK: Audio samples: {} samples", samples.len());
     
-    let mut ctx_params = WhisperContextParameters::default();
-    ctx_params.use_gpu(true);
-    ctx_params.gpu_device(0);
-    
-    eprintln!("DEBUG FALLBACK: Creating WhisperContext with GPU enabled...");
+    let gpu = !crate::helpers::no_gpu();
+    eprintln!("DEBUG FALLBACK: Creating WhisperContext with GPU {}...", if gpu { "enabled" } else { "disabled" });
     let t3 = std::time::Instant::now();
-    let ctx = WhisperContext::new_with_params(&model_path, ctx_params)
-        .context("Failed to create WhisperContext")?;
-    eprintln!("DEBUG FALLBACK: WhisperContext creation took {:?}", t3.elapsed());
+    let (ctx, device) = create_context(&model_path, gpu)?;
+    eprintln!("DEBUG FALLBACK: WhisperContext creation took {:?} ({:?})", t3.elapsed(), device);
     
     eprintln!("DEBUG FALLBACK: Creating whisper state...");
     let t4 = std::time::Instant::now();
@@ -52,22 +130,23 @@ pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
         eprintln!("DEBUG FALLBACK: Initializing OpenVINO encoder at STATE level...");
         let t5 = std::time::Instant::now();
         // Check if OpenVINO model files exist
-        let model_base = model_path.trim_end_matches(".bin");
-        let openvino_model = format!("{}-encoder-openvino.xml", model_base);
+        let openvino_model = super::model::openvino_encoder_path(&model_path);
         
         if std::path::Path::new(&openvino_model).exists() {
             eprintln!("DEBUG FALLBACK: Found OpenVINO model: {}", openvino_model);
-            // Set cache directory as subdirectory next to the model files
-            let cache_dir = format!("{}-encoder-openvino-cache", model_base);
-            // Ensure cache directory exists
-            if let Err(e) = std::fs::create_dir_all(&cache_dir) {
-                eprintln!("DEBUG FALLBACK: Warning: Could not create cache dir: {:?}", e);
-            }
-            eprintln!("DEBUG FALLBACK: Using cache dir: {}", cache_dir);
+            // Cache dir is keyed by model content, shared with the daemon
+            let cache_dir = match super::openvino_cache::cache_dir_for_model(&model_path) {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    eprintln!("DEBUG FALLBACK: Warning: Could not prepare cache dir: {:?}", e);
+                    None
+                }
+            };
+            eprintln!("DEBUG FALLBACK: Using cache dir: {:?}", cache_dir);
             // Use AUTO to let OpenVINO choose the best available device
-            if let Err(e) = state.init_openvino_encoder_state_level(None, "AUTO", Some(&cache_dir)) {
+            if let Err(e) = state.init_openvino_encoder_state_level(None, "AUTO", cache_dir.as_deref()) {
                 eprintln!("DEBUG FALLBACK: AUTO device selection failed: {:?}, trying CPU...", e);
-                if let Err(e) = state.init_openvino_encoder_state_level(None, "CPU", Some(&cache_dir)) {
+                if let Err(e) = state.init_openvino_encoder_state_level(None, "CPU", cache_dir.as_deref()) {
                     eprintln!("DEBUG FALLBACK: CPU initialization also failed: {:?}", e);
                     eprintln!("DEBUG FALLBACK: Will use regular CPU inference without OpenVINO");
                 } else {
@@ -85,18 +164,21 @@ pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
     let t6 = std::time::Instant::now();
     let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
     
-    // Match the native CLI's thread count more closely
-    let num_threads = 4;  // Try with 4 threads like CLI default
+    let num_threads = crate::helpers::threads() as i32;
     params.set_n_threads(num_threads);
-    eprintln!("DEBUG FALLBACK: Using {} threads (forced to 4 to match CLI)", num_threads);
+    eprintln!("DEBUG FALLBACK: Using {} threads", num_threads);
     
     params.set_translate(false);
-    params.set_language(Some("en"));
+    params.set_language(Some(language));
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_timestamps(false);
     params.set_suppress_blank(true);
+    params.set_suppress_nst(artifacts::suppress_non_speech());
     params.set_temperature(0.0);
+    if let Some(prompt) = prompt {
+        params.set_initial_prompt(prompt);
+    }
     eprintln!("DEBUG FALLBACK: Param setup took {:?}", t6.elapsed());
     
     eprintln!("DEBUG FALLBACK: Starting transcription...");
@@ -106,116 +188,51 @@ pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
     eprintln!("DEBUG FALLBACK: Whisper transcription (state.full) took {:?}", t7.elapsed());
     
     let t8 = std::time::Instant::now();
-    let mut transcribed_text = String::new();
+    let mut segments = Vec::new();
     let num_segments = state.full_n_segments();
     for i in 0..num_segments {
         let segment = state.get_segment(i)
             .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
         let segment_text = segment.to_str()?;
         eprintln!("DEBUG FALLBACK: Segment: {:?}", segment_text);
-        transcribed_text.push_str(segment_text);
-        transcribed_text.push(' ');
+        segments.push(segment_text.to_string());
     }
+    let transcribed_text = normalize::join_segments(segments.iter().map(String::as_str));
     eprintln!("DEBUG FALLBACK: Segment extraction took {:?}", t8.elapsed());
     
-    let clean_text = transcribed_text.trim().to_string();
+    let clean_text = artifacts::strip(&transcribed_text);
+    let detected = state.full_lang_id_from_state().ok()
+        .and_then(whisper_rs::get_lang_str)
+        .map(str::to_string);
     eprintln!("DEBUG FALLBACK: Final transcription: {:?}", clean_text);
+    eprintln!("DEBUG FALLBACK: Detected language: {:?}", detected);
     eprintln!("DEBUG FALLBACK: TOTAL TIME: {:?}", total_start.elapsed());
     
-    Ok(clean_text)
-}
-
-
-/// Transcribe audio using whisper-cpp CLI binary
-pub fn transcribe_with_cli(audio_file: &str, model: &str, whisper_path: &str, wtype_path: &str) -> Result<()> {
-    let acceleration = crate::helpers::get_acceleration_type();
-    let transcribe_msg = format!("⏳ Transcribing with CLI... ({})", acceleration);
-    
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input (whisper.cpp)",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
-
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/home/martin".to_string());
-    let model_extension = if model.ends_with(".bin") { "" } else { ".bin" };
-    let model_path = format!("{}/.cache/whisper-cpp/models/ggml-{}{}", home, model, model_extension);
-    
-    let output = Command::new(whisper_path)
-        .args(&[
-            "-m", &model_path,
-            "-f", audio_file,
-            "-t", "8",
-            "-np",
-            "-nt"
-        ])
-        .output()
-        .context("Failed to run whisper-cpp")?;
-
-    if !output.status.success() {
-        Command::new("notify-send")
-            .args(&[
-                "Voice Input (whisper.cpp)",
-                "❌ Transcription failed",
-                "-t", "2000",
-                "-h", "string:x-canonical-private-synchronous:voice"
-            ])
-            .spawn()?;
-        return Err(anyhow!("whisper-cpp failed: {}", String::from_utf8_lossy(&output.stderr)));
-    }
-
-    let stdout_text = String::from_utf8_lossy(&output.stdout);
-    let mut result = String::new();
-    
-    for line in stdout_text.lines() {
-        if line.contains(" --> ") && line.contains("]") {
-            if let Some(end_bracket) = line.rfind(']') {
-                let text = &line[end_bracket + 1..].trim();
-                if !text.is_empty() && !text.starts_with("(") && !text.ends_with(")") {
-                    if !result.is_empty() {
-                        result.push(' ');
-                    }
-                    result.push_str(text);
-                }
-            }
-        }
-    }
-
-    typing::type_text(result.trim(), wtype_path, "whisper-cpp CLI")?;
-    Ok(())
+    Ok((clean_text, detected, device))
 }
 
 /// Transcribe audio from file and type the result using wtype
-pub fn transcribe_with_whisper_rs(audio_file: &str, model: &str, _whisper_path: &str, wtype_path: &str) -> Result<()> {
-    let acceleration = crate::helpers::get_acceleration_type();
-    let transcribe_msg = format!("⏳ Transcribing with GPU... ({})", acceleration);
-    
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input (whisper.cpp)",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
+pub fn transcribe_with_whisper_rs(audio_file: &str, model: &str, prompt_file: Option<&str>, delivery: &typing::Delivery, run: &mut Run) -> Result<()> {
+    let transcribe_msg = if crate::helpers::no_gpu() {
+        "⏳ Transcribing on CPU... (--no-gpu)".to_string()
+    } else {
+        format!("⏳ Transcribing with GPU... ({})", crate::helpers::get_acceleration_type())
+    };
+    
+    notify::Notification::transient("Voice Input (whisper.cpp)", &transcribe_msg)
+        .send();
 
-    match transcribe_audio(audio_file, model) {
-        Ok(clean_text) => {
-        typing::type_text(&clean_text, wtype_path, "whisper-cpp")?;
+    run.prompt = crate::prompt::current(prompt_file, delivery.profile.as_deref());
+    match transcribe_audio(audio_file, model, &run.language, run.prompt.as_deref()) {
+        Ok((clean_text, detected, device)) => {
+            let label = format!("whisper-cpp{}{}", device.note(), crate::helpers::language_note(&run.language, detected.as_deref()));
+            run.detected_language = detected;
+            typing::type_text(&clean_text, delivery, run, &label)?;
             Ok(())
         }
         Err(e) => {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input (whisper.cpp)",
-                    "❌ Model file not found",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
+            notify::Notification::error("Voice Input (whisper.cpp)", &format!("❌ {}", e))
+                .send();
             Err(e)
         }
     }
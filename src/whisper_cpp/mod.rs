@@ -1,6 +1,11 @@
+pub mod cli;
 pub mod client;
 pub mod daemon;
+#[cfg(any(feature = "mock", feature = "openvino"))]
+pub mod device_fallback;
 pub mod direct;
+pub mod model;
+pub mod openvino_cache;
 
 pub use client::stop_and_transcribe_daemon;
-pub use daemon::run_daemon;
\ No newline at end of file
+pub use daemon::{run_daemon, DaemonOptions};
\ No newline at end of file
@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::Read;
+use crate::notify;
+use crate::faster_whisper::environment::Checked;
+
+/// Legacy GGML magic (0x67676d6c) as stored little-endian on disk
+const GGML_MAGIC: [u8; 4] = *b"lmgg";
+/// GGUF container magic
+const GGUF_MAGIC: [u8; 4] = *b"GGUF";
+
+/// Container format of a model file, told by its magic rather than its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFormat {
+    Ggml,
+    Gguf,
+}
+
+impl std::fmt::Display for ModelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelFormat::Ggml => write!(f, "GGML"),
+            ModelFormat::Gguf => write!(f, "GGUF"),
+        }
+    }
+}
+
+/// Approximate sizes in MB of the official ggml models; used to spot truncated downloads
+const KNOWN_MODEL_SIZES_MB: &[(&str, u64)] = &[
+    ("tiny", 77),
+    ("tiny.en", 77),
+    ("base", 147),
+    ("base.en", 147),
+    ("small", 487),
+    ("small.en", 487),
+    ("medium", 1533),
+    ("medium.en", 1533),
+    ("large-v1", 3094),
+    ("large-v2", 3094),
+    ("large-v3", 3095),
+    ("large-v3-turbo", 1624),
+];
+
+/// Approximate memory in MB whisper.cpp needs to load and run each official
+/// model (multilingual and .en alike), from the whisper.cpp README
+const KNOWN_MODEL_RAM_MB: &[(&str, u64)] = &[
+    ("tiny", 273),
+    ("base", 388),
+    ("small", 852),
+    ("large-v3-turbo", 1800),
+    ("medium", 2100),
+    ("large-v1", 3900),
+    ("large-v2", 3900),
+    ("large-v3", 3900),
+];
+
+/// Name a model goes by in the size and memory tables: "small.en" for
+/// small.en, small.en.bin or a file such as /models/ggml-small.en.bin.
+/// Other model files keep their file name and are simply not in the tables.
+pub fn known_name(model: &str) -> String {
+    let name = if crate::helpers::is_model_path(model) {
+        std::path::Path::new(model).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+    } else {
+        model.to_string()
+    };
+    name.trim_start_matches("ggml-").trim_end_matches(".gguf").trim_end_matches(".bin").to_string()
+}
+
+/// OpenVINO encoder whisper.cpp looks for next to a model file: the model's
+/// path without its extension, plus "-encoder-openvino.xml"
+pub fn openvino_encoder_path(model_path: &str) -> String {
+    format!("{}-encoder-openvino.xml", std::path::Path::new(model_path).with_extension("").display())
+}
+
+/// Memory a model needs, if it is one of the official ones
+pub fn required_ram_mb(model_name: &str) -> Option<u64> {
+    let name = known_name(model_name);
+    let size = name.strip_suffix(".en").unwrap_or(&name);
+    KNOWN_MODEL_RAM_MB.iter().find(|(known, _)| *known == size).map(|(_, mb)| *mb)
+}
+
+/// Value of a `Key:   1234 kB` line from a /proc file, in MB
+fn proc_kb_field_mb(path: &str, key: &str) -> Option<u64> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let line = content.lines().find(|line| line.starts_with(key))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
+
+/// MemAvailable from /proc/meminfo, in MB
+pub fn available_ram_mb() -> Option<u64> {
+    proc_kb_field_mb("/proc/meminfo", "MemAvailable:")
+}
+
+/// Resident set size of this process, in MB
+pub fn own_rss_mb() -> Option<u64> {
+    proc_kb_field_mb("/proc/self/status", "VmRSS:")
+}
+
+/// WA_MEMORY_FLOOR_MB: available memory below which the daemon unloads its
+/// model. Unset or 0 keeps the model loaded no matter what.
+pub fn memory_floor_mb() -> Option<u64> {
+    std::env::var("WA_MEMORY_FLOOR_MB").ok()?.trim().parse().ok().filter(|mb| *mb > 0)
+}
+
+/// Whether a model fits in the memory that is available right now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryCheck {
+    /// Not an official model, or /proc/meminfo is unreadable
+    Unknown,
+    Enough { required_mb: u64, available_mb: u64 },
+    Short { required_mb: u64, available_mb: u64, suggestion: Option<String> },
+}
+
+impl std::fmt::Display for MemoryCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryCheck::Unknown => write!(f, "memory needs unknown"),
+            MemoryCheck::Enough { required_mb, available_mb } => {
+                write!(f, "needs ~{} MB, {} MB available", required_mb, available_mb)
+            }
+            MemoryCheck::Short { required_mb, available_mb, suggestion } => {
+                write!(f, "needs ~{} MB but only {} MB is available", required_mb, available_mb)?;
+                match suggestion {
+                    Some(smaller) => write!(f, "; try {}", smaller),
+                    None => write!(f, "; no smaller model fits either"),
+                }
+            }
+        }
+    }
+}
+
+/// Compare a model's memory needs against MemAvailable. When it doesn't fit,
+/// suggest the largest official model that does, keeping an `.en` suffix.
+pub fn check_memory(model_name: &str) -> MemoryCheck {
+    let (Some(required_mb), Some(available_mb)) = (required_ram_mb(model_name), available_ram_mb()) else {
+        return MemoryCheck::Unknown;
+    };
+    if required_mb <= available_mb {
+        return MemoryCheck::Enough { required_mb, available_mb };
+    }
+
+    let english = known_name(model_name).ends_with(".en");
+    let suggestion = KNOWN_MODEL_RAM_MB
+        .iter()
+        .filter(|(_, mb)| *mb <= available_mb)
+        .max_by_key(|(_, mb)| *mb)
+        .map(|(size, _)| match *size {
+            // Only the sizes up to medium have English-only variants
+            "tiny" | "base" | "small" | "medium" if english => format!("{}.en", size),
+            size => size.to_string(),
+        });
+    MemoryCheck::Short { required_mb, available_mb, suggestion }
+}
+
+/// Whether a model that doesn't fit is loaded anyway (`daemon --force` or WA_FORCE_LOAD=1)
+fn force_load() -> bool {
+    matches!(std::env::var("WA_FORCE_LOAD").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Refuse to load a model that clearly won't fit in memory rather than let
+/// the OOM killer end the process halfway through loading it
+pub fn ensure_memory(model_name: &str) -> Result<()> {
+    let check = check_memory(model_name);
+    match check {
+        MemoryCheck::Unknown => return Ok(()),
+        MemoryCheck::Enough { .. } => {
+            eprintln!("DEBUG: Memory check for {}: {}", model_name, check);
+            return Ok(());
+        }
+        MemoryCheck::Short { .. } => {}
+    }
+
+    if force_load() {
+        eprintln!("WARNING: Loading {} anyway: {}", model_name, check);
+        return Ok(());
+    }
+    let error = anyhow!("Not enough memory for model {}: {} (set WA_FORCE_LOAD=1 to load it anyway)", model_name, check);
+    notify::Notification::error("Voice Input", &format!("❌ Not enough memory for {}\n{}", crate::helpers::model_display_name(model_name), check))
+        .timeout(10000)
+        .tag(None)
+        .urgent()
+        .send();
+    Err(error)
+}
+
+/// Report whether the daemon could load `model`, for `daemon --check`. Only
+/// whisper.cpp models can be validated and sized up front; for faster-whisper
+/// this reports whether the model is downloaded.
+pub fn print_check(backend: &str, model: &str) -> Result<()> {
+    if backend == "faster-whisper" {
+        // The interpreter direct transcription falls back to without a daemon
+        let python = std::env::var("FASTER_WHISPER_PYTHON").unwrap_or_else(|_| "python3".to_string());
+        let pythonpath = std::env::var("FASTER_WHISPER_PYTHONPATH").unwrap_or_default();
+        let environment = crate::faster_whisper::environment::check(&python, &pythonpath);
+        match &environment {
+            Ok(Checked::Cached) => println!("Python: ok, checked before ({})", python),
+            Ok(Checked::Passed(took)) => println!("Python: ok, imports took {:.1?} ({})", took, python),
+            Err(e) => println!("Python: {:#}", e),
+        }
+        match crate::faster_whisper::model::cached_path(model) {
+            Some(path) => println!("Model: ok ({})", path),
+            None if crate::faster_whisper::model::download_allowed() => {
+                println!("Model: not downloaded yet, fetched on first use")
+            }
+            None => return Err(anyhow!("Model {} is not downloaded and downloads are off", model)),
+        }
+        return environment.map(|_| ());
+    }
+    if backend != "whisper-cpp" {
+        println!("{}: nothing to check before loading {}", backend, model);
+        return Ok(());
+    }
+
+    let model_path = crate::helpers::whisper_cpp_model_path(model);
+    let file = validate_model(&model_path, model);
+    match &file {
+        Ok(format) => println!("Model file: ok, {} ({})", format, model_path),
+        Err(e) => println!("Model file: {}", e),
+    }
+    let memory = check_memory(model);
+    match &memory {
+        MemoryCheck::Short { .. } => println!("Memory: not enough, {}", memory),
+        MemoryCheck::Enough { .. } => println!("Memory: ok, {}", memory),
+        MemoryCheck::Unknown => println!("Memory: not checked, {}", memory),
+    }
+
+    file?;
+    if matches!(memory, MemoryCheck::Short { .. }) {
+        return Err(anyhow!("Not enough memory for model {}", model));
+    }
+    Ok(())
+}
+
+/// Check that a model file looks loadable before handing it to whisper.cpp,
+/// which otherwise fails with an opaque error on partial downloads, and say
+/// which format it is in
+pub fn validate_model(model_path: &str, model_name: &str) -> Result<ModelFormat> {
+    let mut file = File::open(model_path)
+        .with_context(|| format!("Model file not found: {}", model_path))?;
+    let size = file.metadata()?.len();
+
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() {
+        return Err(corrupt(model_path, model_name, "file is too short to be a model"));
+    }
+    let format = match magic {
+        GGML_MAGIC => ModelFormat::Ggml,
+        GGUF_MAGIC => ModelFormat::Gguf,
+        _ => return Err(corrupt(model_path, model_name, "missing GGML/GGUF header")),
+    };
+
+    let name = known_name(model_name);
+    if let Some((_, expected_mb)) = KNOWN_MODEL_SIZES_MB.iter().find(|(known, _)| *known == name) {
+        // Allow a little slack for format revisions, but catch truncated downloads
+        let expected = expected_mb * 1_000_000;
+        if size < expected * 98 / 100 {
+            return Err(corrupt(
+                model_path,
+                model_name,
+                &format!("only {} MB of ~{} MB, download looks truncated", size / 1_000_000, expected_mb),
+            ));
+        }
+    }
+
+    Ok(format)
+}
+
+fn corrupt(model_path: &str, model_name: &str, reason: &str) -> anyhow::Error {
+    if crate::helpers::is_model_path(model_name) {
+        return anyhow!("Model file {} is corrupt or incomplete ({}). Replace it with a working GGML or GGUF model", model_path, reason);
+    }
+    anyhow!(
+        "Model file {} is corrupt or incomplete ({}). Re-download it with: download-whisper-model {}",
+        model_path, reason, model_name
+    )
+}
+
+/// Only official models can be downloaded; a model file that is missing has
+/// to be put back by hand
+fn check_downloadable(model_name: &str) -> Result<()> {
+    if crate::helpers::is_model_path(model_name) {
+        return Err(anyhow!("Model file {} not found; only named models (e.g. base.en) can be downloaded", model_name));
+    }
+    Ok(())
+}
+
+/// Download a model with the download-whisper-model helper, replacing any existing
+/// (presumably broken) file so the helper doesn't skip it
+pub fn download_model(model_name: &str, model_path: &str) -> Result<()> {
+    check_downloadable(model_name)?;
+    let _ = std::fs::remove_file(model_path);
+
+    let output = crate::child_env::command("download-whisper-model", crate::child_env::NETWORK_ENV)
+        .arg(model_name)
+        .output()
+        .context("Failed to run download-whisper-model")?;
+
+    if !output.status.success() {
+        return Err(anyhow!("Downloading {} failed: {}", model_name, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Same as download_model, but the helper's progress bar goes to our terminal
+pub fn download_model_with_progress(model_name: &str, model_path: &str) -> Result<()> {
+    check_downloadable(model_name)?;
+    let _ = std::fs::remove_file(model_path);
+
+    let status = crate::child_env::command("download-whisper-model", crate::child_env::NETWORK_ENV)
+        .arg(model_name)
+        .status()
+        .context("Failed to run download-whisper-model")?;
+
+    if !status.success() {
+        return Err(anyhow!("Downloading {} failed ({})", model_name, status));
+    }
+    Ok(())
+}
+
+/// Notify the user that a model failed validation
+pub fn notify_invalid_model(model_name: &str, error: &anyhow::Error) {
+    notify::Notification::error("Voice Input", &format!("❌ Model {} is unusable\n{}", crate::helpers::model_display_name(model_name), error))
+        .timeout(10000)
+        .tag(None)
+        .urgent()
+        .send();
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use crate::helpers::{fnv1a, FNV1A_OFFSET};
+
+/// How much of the head and tail of the model file feeds the fingerprint
+const SAMPLE_BYTES: u64 = 1024 * 1024;
+
+/// File inside each cache dir recording which model it was compiled from
+const MODEL_MARKER: &str = "model-path";
+
+/// Quick content fingerprint of a model: its size plus the first and last MB.
+/// Reading 2 MB keeps this in the low milliseconds even for large-v3.
+pub fn model_fingerprint(model_path: &str) -> Result<String> {
+    let mut file = File::open(model_path)
+        .with_context(|| format!("Failed to open model {}", model_path))?;
+    let size = file.metadata()?.len();
+
+    let mut hash = fnv1a(FNV1A_OFFSET, &size.to_le_bytes());
+    let mut buffer = vec![0u8; SAMPLE_BYTES.min(size) as usize];
+
+    file.read_exact(&mut buffer)?;
+    hash = fnv1a(hash, &buffer);
+
+    if size > SAMPLE_BYTES {
+        file.seek(SeekFrom::Start(size - buffer.len() as u64))?;
+        file.read_exact(&mut buffer)?;
+        hash = fnv1a(hash, &buffer);
+    }
+
+    Ok(format!("{:016x}", hash))
+}
+
+/// Root of all OpenVINO compiled-blob caches
+fn cache_root() -> String {
+    format!("{}/whisp-away/openvino", crate::helpers::cache_dir())
+}
+
+/// Cache directory for a model's compiled OpenVINO encoder, keyed by content so
+/// moving or symlinking the model doesn't force a recompile
+#[cfg(feature = "openvino")]
+pub fn cache_dir_for_model(model_path: &str) -> Result<String> {
+    let cache_dir = format!("{}/{}", cache_root(), model_fingerprint(model_path)?);
+    fs::create_dir_all(&cache_dir)
+        .with_context(|| format!("Failed to create cache dir {}", cache_dir))?;
+
+    // Remember where the model lives so `cache prune` can tell if it's gone
+    let canonical = fs::canonicalize(model_path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| model_path.to_string());
+    fs::write(format!("{}/{}", cache_dir, MODEL_MARKER), canonical).ok();
+
+    Ok(cache_dir)
+}
+
+/// Delete cache dirs whose model no longer exists or has changed content
+pub fn prune() -> Result<()> {
+    let root = cache_root();
+    let entries = match fs::read_dir(&root) {
+        Ok(entries) => entries,
+        Err(_) => {
+            println!("No OpenVINO cache at {}", root);
+            return Ok(());
+        }
+    };
+
+    let mut removed = 0;
+    let mut kept = 0;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let key = entry.file_name().to_string_lossy().to_string();
+
+        let model_path = fs::read_to_string(dir.join(MODEL_MARKER)).ok();
+        let still_valid = match model_path.as_deref().map(str::trim) {
+            Some(path) if Path::new(path).exists() => {
+                model_fingerprint(path).map(|fp| fp == key).unwrap_or(false)
+            }
+            _ => false,
+        };
+
+        if still_valid {
+            kept += 1;
+            continue;
+        }
+
+        match fs::remove_dir_all(&dir) {
+            Ok(_) => {
+                println!("Removed {} (model: {})", dir.display(), model_path.as_deref().map(str::trim).unwrap_or("unknown"));
+                removed += 1;
+            }
+            Err(e) => eprintln!("Failed to remove {}: {}", dir.display(), e),
+        }
+    }
+
+    println!("Pruned {} cache dir(s), kept {}", removed, kept);
+    Ok(())
+}
@@ -0,0 +1,194 @@
+#!/usr/bin/env bash
+
+# Regression test: responses are compressed when both sides offer zstd in
+# the hello, and only past the size threshold. Transcripts of every size
+# arrive whole. Corrupted compressed frames give the client and the daemon
+# a clean error rather than a panic, and the daemon keeps serving. Talks to
+# the mock daemon over its Unix socket with WA_COMPRESS=1 (compression is
+# only on by default over TCP); python3 plays a raw client and a rogue
+# daemon. Needs a build with the compress feature, and skips without one.
+# Run from the repo root:
+#   cargo build --features compress && tests/compression.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+ROGUE_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null || true
+    [ -n "$ROGUE_PID" ] && kill "$ROGUE_PID" 2>/dev/null || true
+}
+
+# Keep the test away from real daemons and history, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_FALLBACK_CHAIN=daemon
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy
+stub_notify_send
+LOGS=(stop.log daemon.log)
+
+# A raw client and a rogue daemon speaking the protocol byte for byte
+cat > "$WORK/raw.py" <<'RAW'
+import json, os, socket, sys
+
+HELLO = {"protocol_version": 1, "min_protocol_version": 1, "capabilities": ["transcribe", "zstd"]}
+
+def frame(message):
+    return json.dumps(message).encode() + b"\n"
+
+def read_line(conn):
+    line = b""
+    while not line.endswith(b"\n"):
+        chunk = conn.recv(1)
+        if not chunk:
+            break
+        line += chunk
+    return line
+
+def read_all(conn):
+    data = b""
+    while chunk := conn.recv(65536):
+        data += chunk
+    return data
+
+mode, path = sys.argv[1], sys.argv[2]
+if mode == "capture":
+    # Ask the daemon for a transcript and keep the response frame as sent
+    conn = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    conn.connect(path)
+    conn.sendall(frame(HELLO))
+    print(json.loads(read_line(conn))["capabilities"], file=sys.stderr)
+    conn.sendall(frame({"audio_path": sys.argv[3]}))
+    sys.stdout.buffer.write(read_all(conn))
+elif mode == "send":
+    # Send the frame in a file as the request and print the answer, if any
+    conn = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    conn.connect(path)
+    conn.sendall(frame(HELLO))
+    read_line(conn)
+    conn.sendall(open(sys.argv[3], "rb").read())
+    conn.shutdown(socket.SHUT_WR)
+    sys.stdout.buffer.write(read_all(conn))
+elif mode == "rogue":
+    # Answer every request with the frame in the file named by `frame-file`
+    server = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    server.bind(path)
+    server.listen()
+    while True:
+        conn, _ = server.accept()
+        read_line(conn)
+        conn.sendall(frame({**HELLO, "backend": "mock"}))
+        read_line(conn)
+        conn.sendall(open(open(sys.argv[3]).read().strip(), "rb").read())
+        conn.close()
+elif mode == "corrupt":
+    # Spoil a compressed response frame in every way the decoder must survive
+    good = open(path, "rb").read()
+    header, body = good.split(b"\n", 1)
+    out = sys.argv[3]
+    def write(name, data):
+        open(os.path.join(out, name), "wb").write(data)
+    flipped = bytearray(body)
+    for i in range(len(flipped) // 3, len(flipped), 97):
+        flipped[i] ^= 0xFF
+    write("flipped", header + b"\n" + bytes(flipped))
+    write("short-claim", frame({"compressed": "zstd", "bytes": len(body) // 2}) + body)
+    write("huge-claim", frame({"compressed": "zstd", "bytes": 1 << 40}) + body)
+    write("other-codec", frame({"compressed": "brotli", "bytes": len(body)}) + body)
+    write("garbage", frame({"compressed": "zstd", "bytes": 4096}) + os.urandom(4096))
+    write("empty", frame({"compressed": "zstd", "bytes": 0}))
+    write("bad-header", b'{"compressed":zstd,"bytes":12}\n' + body[:12])
+    write("truncated", header + b"\n" + body[: len(body) // 2])
+RAW
+
+start_daemon() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null && wait "$DAEMON_PID" 2>/dev/null || true
+    rm -f "$WA_WHISPER_SOCKET"
+    WA_MOCK_REPEAT="$1" "$BIN" daemon --backend mock > "$WORK/daemon.log" 2>&1 &
+    DAEMON_PID=$!
+    for _ in $(seq 50); do
+        [ -S "$WA_WHISPER_SOCKET" ] && return 0
+        sleep 0.1
+    done
+    fail "the mock daemon did not start"
+}
+
+# The mock transcript is "mock transcription", 18 characters, repeated with a space between
+expected_chars() {
+    echo $(( $1 * 19 - 1 ))
+}
+
+start_daemon 1
+python3 "$WORK/raw.py" capture "$WA_WHISPER_SOCKET" "$TEST_AUDIO" 2> "$WORK/capabilities" > /dev/null
+if ! grep -q zstd "$WORK/capabilities"; then
+    skip "built without the compress feature"
+fi
+
+# 1. Round trips from below the threshold to several megabytes; only frames
+# past the threshold are compressed
+for repeat in 1 900 20000 200000; do
+    start_daemon "$repeat"
+    python3 "$WORK/raw.py" capture "$WA_WHISPER_SOCKET" "$TEST_AUDIO" 2> /dev/null > "$WORK/frame-$repeat"
+    if [ "$repeat" = 1 ]; then
+        head -c 14 "$WORK/frame-$repeat" | grep -q '{"compressed":' && fail "a $repeat-word response was compressed"
+    else
+        head -c 14 "$WORK/frame-$repeat" | grep -q '{"compressed":' || fail "a response repeating the text $repeat times wasn't compressed"
+    fi
+    WA_COMPRESS=1 timeout 120 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard > "$WORK/stop.log" 2>&1 \
+        || fail "stop with $repeat repeats exited with an error"
+    grep -q "Daemon speaks protocol .*zstd" "$WORK/stop.log" || fail "the client didn't agree on compression"
+    chars="$("$BIN" last --field text | tr -d '\n' | wc -c)"
+    [ "$chars" = "$(expected_chars "$repeat")" ] || fail "$repeat repeats arrived as $chars characters, expected $(expected_chars "$repeat")"
+done
+[ "$(wc -c < "$WORK/frame-200000")" -lt 100000 ] || fail "a 3.8 MB transcript was sent as $(wc -c < "$WORK/frame-200000") bytes"
+
+# 2. Over the Unix socket compression is off unless asked for
+start_daemon 900
+timeout 60 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard > "$WORK/stop.log" 2>&1 \
+    || fail "stop without WA_COMPRESS exited with an error"
+grep "Daemon speaks protocol" "$WORK/stop.log" | grep -q zstd && fail "compression was used over the Unix socket by default"
+
+# 3. Corrupted frames reach the client from a rogue daemon: no panic, no text
+python3 "$WORK/raw.py" corrupt "$WORK/frame-20000" "$WORK"
+kill "$DAEMON_PID"; wait "$DAEMON_PID" 2>/dev/null || true; DAEMON_PID=""
+rm -f "$WA_WHISPER_SOCKET"
+python3 "$WORK/raw.py" rogue "$WA_WHISPER_SOCKET" "$WORK/frame-file" &
+ROGUE_PID=$!
+for _ in $(seq 50); do
+    [ -S "$WA_WHISPER_SOCKET" ] && break
+    sleep 0.1
+done
+CORRUPTIONS="flipped short-claim huge-claim other-codec garbage empty bad-header truncated"
+for corruption in $CORRUPTIONS; do
+    echo "$WORK/$corruption" > "$WORK/frame-file"
+    rm -f "$XDG_RUNTIME_DIR/whisp-away/last-result.json"
+    status=0
+    WA_COMPRESS=1 timeout 60 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard > "$WORK/stop.log" 2>&1 || status=$?
+    [ "$status" -lt 100 ] || fail "the client died with status $status on a $corruption frame"
+    grep -q "panicked" "$WORK/stop.log" && fail "the client panicked on a $corruption frame"
+    if [ "$corruption" != truncated ]; then
+        grep -q "Unreadable message from daemon" "$WORK/stop.log" || fail "the client didn't report the $corruption frame"
+    fi
+    "$BIN" last --field text > /dev/null 2>&1 && fail "the client took text from a $corruption frame"
+done
+kill "$ROGUE_PID"; wait "$ROGUE_PID" 2>/dev/null || true; ROGUE_PID=""
+
+# 4. The same frames as requests: the daemon drops the connection and keeps serving
+start_daemon 1
+for corruption in $CORRUPTIONS; do
+    timeout 10 python3 "$WORK/raw.py" send "$WA_WHISPER_SOCKET" "$WORK/$corruption" > "$WORK/reply" \
+        || fail "the daemon didn't close the connection after a $corruption request"
+    grep -q '"success": *true' "$WORK/reply" && fail "the daemon answered a $corruption request"
+    kill -0 "$DAEMON_PID" 2>/dev/null || fail "the daemon died on a $corruption request"
+done
+grep -q "panicked" "$WORK/daemon.log" && fail "the daemon panicked"
+[ "$(grep -c "Error handling connection" "$WORK/daemon.log")" -ge 7 ] || fail "the daemon didn't report the corrupted requests"
+timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard > "$WORK/stop.log" 2>&1 \
+    || fail "stop after the corrupted requests exited with an error"
+[ "$("$BIN" last --field text)" = "mock transcription" ] || fail "the daemon didn't transcribe after the corrupted requests"
+
+echo "PASS: compressed responses round-tripped, and corrupted frames were refused cleanly"
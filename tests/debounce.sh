@@ -0,0 +1,82 @@
+#!/usr/bin/env bash
+
+# Regression test: a burst of identical hotkey commands, like key repeat on
+# a held hotkey, does what one command would. The others exit at once with
+# status 0. Different commands and runs spaced past the window
+# (WA_DEBOUNCE_MS, default 250 ms) aren't held back, and 0 turns it off.
+# Uses a fake pw-record, so no audio stack is needed. Run from the repo root:
+#   cargo build && tests/debounce.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+on_exit() {
+    WA_DEBOUNCE_MS=0 "$BIN" stop --backend mock --output clipboard > /dev/null 2>&1
+}
+
+# Keep the test away from real recordings, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_FALLBACK_CHAIN=direct
+mkdir -p "$XDG_RUNTIME_DIR"
+stub pw-record <<RECORDER
+#!/bin/sh
+echo started >> "$WORK/recorders"
+for last; do :; done
+: > "\$last"
+trap 'exit 0' INT TERM
+while true; do sleep 0.05; done
+RECORDER
+stub_wl_copy
+stub_notify_send
+LOGS=("*.log")
+
+recorders() {
+    wc -l < "$WORK/recorders" 2>/dev/null || echo 0
+}
+
+# Run `$@` $1 times at once, failing if any run exits with an error
+burst() {
+    local count="$1" pids=() i
+    shift
+    rm -f "$WORK"/burst-*.log
+    for i in $(seq "$count"); do
+        "$@" > "$WORK/burst-$i.log" 2>&1 &
+        pids+=($!)
+    done
+    for i in "${!pids[@]}"; do
+        wait "${pids[$i]}" || fail "run $((i + 1)) of the burst of $* exited with an error"
+    done
+}
+
+ignored() {
+    grep -l "Ignoring" "$WORK"/burst-*.log 2>/dev/null | wc -l
+}
+
+# 1. Twenty concurrent starts record once
+burst 20 "$BIN" start --backend mock
+[ "$(ignored)" = 19 ] || fail "$(ignored) of 20 concurrent starts were ignored, expected 19"
+sleep 0.3
+[ "$(recorders)" = 1 ] || fail "20 concurrent starts ran pw-record $(recorders) times"
+
+# 2. Stop right after is another command, and isn't held back; a burst of
+# stops stops once
+burst 10 "$BIN" stop --backend mock --output clipboard
+[ "$(ignored)" = 9 ] || fail "$(ignored) of 10 concurrent stops were ignored, expected 9"
+
+# 3. A start past the window runs; one within a longer window doesn't
+sleep 0.3
+"$BIN" start --backend mock > "$WORK/start.log" 2>&1 || fail "a start past the window exited with an error"
+grep -q "Ignoring" "$WORK/start.log" && fail "a start 300 ms after the last was ignored"
+sleep 0.5
+WA_DEBOUNCE_MS=2000 "$BIN" start --backend mock > "$WORK/start.log" 2>&1 || fail "a start within the window exited with an error"
+grep -q "Ignoring" "$WORK/start.log" || fail "a start within WA_DEBOUNCE_MS=2000 wasn't ignored"
+sleep 0.3
+[ "$(recorders)" = 2 ] || fail "pw-record ran $(recorders) times, expected 2"
+
+# 4. WA_DEBOUNCE_MS=0 runs every start of a burst
+burst 3 env WA_DEBOUNCE_MS=0 "$BIN" start --backend mock
+[ "$(ignored)" = 0 ] || fail "starts were ignored with WA_DEBOUNCE_MS=0"
+sleep 0.3
+[ "$(recorders)" = 5 ] || fail "pw-record ran $(recorders) times, expected 5"
+
+echo "PASS: bursts of hotkey commands ran once"
@@ -0,0 +1,104 @@
+#!/usr/bin/env bash
+
+# Regression test: WA_BEAM_SIZE, WA_VAD_FILTER, WA_WORD_TIMESTAMPS and
+# WA_CONDITION_ON_PREVIOUS_TEXT reach faster-whisper's transcribe() from a
+# daemon request and from direct transcription, unset ones keep the
+# scripts' defaults, and parameters the daemon script doesn't know are
+# ignored. A stub faster_whisper module answers with the options it was
+# called with, so neither faster-whisper nor a model is needed; python3 is.
+# Run from the repo root:
+#   cargo build && tests/decode_parameters.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+SCRIPTS="$(pwd)/src/faster_whisper/scripts"
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons and models, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_CACHE_HOME="$WORK/cache"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export FASTER_WHISPER_PYTHON=python3
+export FASTER_WHISPER_PYTHONPATH="$WORK/stub"
+export FASTER_WHISPER_DAEMON_SCRIPT="$SCRIPTS/whisper_daemon.py"
+export FASTER_WHISPER_SCRIPT="$SCRIPTS/transcribe_faster.py"
+export PYTHONDONTWRITEBYTECODE=1
+mkdir -p "$XDG_RUNTIME_DIR" "$WORK/stub/faster_whisper" "$WORK/stub/ctranslate2" "$WORK/model"
+stub_wl_copy
+stub_notify_send
+LOGS=(stop.log daemon.log)
+
+# The daemon checks that both import before starting
+: > "$WORK/stub/ctranslate2/__init__.py"
+
+# The transcript is the decoding options transcribe() got, e.g. "beam_size 5 vad_filter True ..."
+cat > "$WORK/stub/faster_whisper/__init__.py" <<'STUB'
+from types import SimpleNamespace
+
+OPTIONS = ["beam_size", "vad_filter", "word_timestamps", "condition_on_previous_text"]
+
+class WhisperModel:
+    def __init__(self, *args, **kwargs):
+        pass
+
+    def transcribe(self, audio, **kwargs):
+        text = " ".join(f"{key} {kwargs.get(key, 'unset')}" for key in OPTIONS)
+        segment = SimpleNamespace(text=text, start=0.0, end=1.0, avg_logprob=0.0)
+        return iter([segment]), SimpleNamespace(language="en", duration=1.0)
+STUB
+
+run_stop() {
+    WA_FALLBACK_CHAIN="$1" timeout 60 "$BIN" stop --backend faster-whisper --model "$WORK/model" \
+        --audio-file "$TEST_AUDIO" --output clipboard > "$WORK/stop.log" 2>&1 || fail "stop via $1 exited with an error"
+}
+
+# The last transcript was made with options $1
+transcribed_with() {
+    local text
+    text="$("$BIN" last --field text 2>/dev/null)"
+    grep -qi -- "$1" <<< "$text" || fail "transcribed with \"$text\", expected \"$1\""
+}
+
+DEFAULTS="beam_size 5 vad_filter True word_timestamps False condition_on_previous_text True"
+SET="beam_size 2 vad_filter False word_timestamps True condition_on_previous_text False"
+
+# 1. Direct: the defaults, then every option set
+run_stop direct
+transcribed_with "$DEFAULTS"
+WA_BEAM_SIZE=2 WA_VAD_FILTER=0 WA_WORD_TIMESTAMPS=1 WA_CONDITION_ON_PREVIOUS_TEXT=false run_stop direct
+transcribed_with "$SET"
+
+# 2. Through the daemon: the same
+"$BIN" daemon --backend faster-whisper --model "$WORK/model" > "$WORK/daemon.log" 2>&1 &
+DAEMON_PID=$!
+for _ in $(seq 100); do
+    [ -S "$WA_WHISPER_SOCKET" ] && break
+    sleep 0.1
+done
+[ -S "$WA_WHISPER_SOCKET" ] || fail "the faster-whisper daemon did not start"
+run_stop daemon
+transcribed_with "$DEFAULTS"
+WA_BEAM_SIZE=2 WA_VAD_FILTER=0 WA_WORD_TIMESTAMPS=1 WA_CONDITION_ON_PREVIOUS_TEXT=false run_stop daemon
+transcribed_with "$SET"
+# Only what is set is sent
+WA_BEAM_SIZE=3 run_stop daemon
+transcribed_with "beam_size 3 vad_filter True word_timestamps False condition_on_previous_text True"
+
+# 3. An option the daemon doesn't know, as from a newer client, is ignored
+python3 - "$WA_WHISPER_SOCKET" "$TEST_AUDIO" > "$WORK/reply.json" <<'CLIENT' || fail "the daemon didn't answer a request with an unknown parameter"
+import json, socket, sys
+conn = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+conn.connect(sys.argv[1])
+request = {"audio_path": sys.argv[2], "parameters": {"beam_size": 4, "hotwords": "whisp-away"}}
+conn.sendall(json.dumps(request).encode() + b"\n")
+print(conn.makefile().readline())
+CLIENT
+grep -q '"success": true' "$WORK/reply.json" || fail "a request with an unknown parameter failed: $(cat "$WORK/reply.json")"
+grep -q "beam_size 4" "$WORK/reply.json" || fail "the known parameter next to an unknown one wasn't used"
+grep -q "Ignoring unknown parameter hotwords" "$WORK/daemon.log" || fail "the unknown parameter wasn't logged"
+
+echo "PASS: decoding options reached faster-whisper, and unknown ones were ignored"
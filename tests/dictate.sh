@@ -0,0 +1,55 @@
+#!/usr/bin/env bash
+
+# Regression test: `dictate`, the terminal client of the Dictation API, runs
+# headless with the mock backend. It shows the input level, reports each
+# phrase once the speaker pauses, prints the whole text on Enter and nothing
+# on Ctrl-D. A fake pw-record plays two tones split by a pause; python3 is
+# needed. Run from the repo root:
+#   cargo build && tests/dictate.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+
+# Keep the test away from real daemons and data; with no daemon answering,
+# each phrase is transcribed directly
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/none.sock"
+export WA_MOCK_MODE=duration
+mkdir -p "$XDG_RUNTIME_DIR"
+# Raw s16 mono at 16 kHz, in real time: 0.5 s of silence, a 1.5 s tone,
+# 1.2 s of silence, a 1 s tone, then silence
+stub pw-record <<'RECORDER'
+#!/usr/bin/env python3
+import math, struct, sys, time
+tone = b"".join(struct.pack("<h", int(8000 * math.sin(i / 10))) for i in range(1600))
+silence = bytes(3200)
+frames = [silence] * 5 + [tone] * 15 + [silence] * 12 + [tone] * 10
+try:
+    while True:
+        sys.stdout.buffer.write(frames.pop(0) if frames else silence)
+        sys.stdout.buffer.flush()
+        time.sleep(0.1)
+except BrokenPipeError:
+    pass
+RECORDER
+stub_notify_send
+LOGS=("*.log" "*.out")
+
+# 1. Enter after both tones: two phrases as they end, then the whole text
+(sleep 5.5; echo) | timeout 30 "$BIN" dictate --backend mock > "$WORK/dictate.out" 2> "$WORK/dictate.log" \
+    || fail "dictate exited with an error"
+grep -q "\[####################\]" "$WORK/dictate.log" || fail "the level meter never showed the tones"
+# Each phrase is 0.3 s of lead-in, the tone and the 0.8 s pause that ended it
+phrases="$(grep -o "… .*" "$WORK/dictate.log" | sed 's/^… //')"
+[ "$phrases" = "$(printf '2.60 seconds\n2.10 seconds')" ] || fail "unexpected phrases: $(echo "$phrases" | tr '\n' '|')"
+[ "$(cat "$WORK/dictate.out")" = "2.60 seconds 2.10 seconds" ] || fail "the final text isn't the phrases joined: $(cat "$WORK/dictate.out")"
+
+# 2. Ctrl-D discards the dictation: no text
+(sleep 1) | timeout 30 "$BIN" dictate --backend mock > "$WORK/discard.out" 2> "$WORK/discard.log" \
+    || fail "a discarded dictate exited with an error"
+grep -q "Discarded" "$WORK/discard.log" || fail "dictate didn't say the dictation was discarded"
+[ ! -s "$WORK/discard.out" ] || fail "a discarded dictation printed text"
+
+echo "PASS: dictate reported levels and phrases and printed the whole text"
@@ -0,0 +1,98 @@
+#!/usr/bin/env bash
+
+# Regression test: `stop` tries the steps of WA_FALLBACK_CHAIN in order,
+# gives up on a step after its timeout, and leaves out the steps given to
+# --skip. Two mock daemons stand in for the local and the remote one, so no
+# model is needed. Run from the repo root:
+#   cargo build && tests/fallback_chain.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+PIDS=()
+on_exit() {
+    for pid in "${PIDS[@]}"; do
+        kill -CONT "$pid" 2>/dev/null || true
+        kill "$pid" 2>/dev/null || true
+    done
+}
+
+# Keep the test away from real daemons and data, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/local.sock"
+REMOTE="$WORK/remote.sock"
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy "$WORK/clipboard"
+stub_notify_send stderr
+LOGS=(stop.log)
+
+# Start a mock daemon on socket $1, printing its pid
+start_daemon() {
+    "$BIN" daemon --backend mock --socket-path "$1" --ready-file "$1.ready" > "$1.log" 2>&1 &
+    for _ in $(seq 50); do
+        [ -S "$1" ] && break
+        sleep 0.1
+    done
+    [ -S "$1" ] || fail "daemon did not bind $1"
+    echo $!
+}
+
+# Run stop with the chain $1 and any further arguments, succeeding if it did
+run_stop() {
+    local chain="$1"
+    shift
+    rm -f "$WORK/clipboard"
+    WA_FALLBACK_CHAIN="$chain" timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard "$@" \
+        > "$WORK/stop.log" 2>&1
+}
+
+# Line number of the first match of $1 in the stop log, or fail
+line_of() {
+    grep -n -m1 -- "$1" "$WORK/stop.log" | cut -d: -f1 | grep . || fail "no \"$1\" in the stop log"
+}
+
+CHAIN="daemon@2s,remote:$REMOTE@2s,direct"
+
+# 1. Nothing running: daemon, then remote, then direct
+run_stop "$CHAIN" || fail "stop with no daemons exited with an error"
+daemon_at=$(line_of "Chain step 1/3: daemon")
+remote_at=$(line_of "Chain step 2/3: remote")
+direct_at=$(line_of "Chain step 3/3: direct")
+[ "$daemon_at" -lt "$remote_at" ] && [ "$remote_at" -lt "$direct_at" ] || fail "steps were not tried in order"
+[ -s "$WORK/clipboard" ] || fail "direct step copied nothing"
+grep -q "step 3/3: direct" "$WORK/stop.log" || fail "the notification doesn't name the direct step"
+
+# 2. Only the remote daemon is up: it answers, direct isn't tried
+PIDS+=("$(start_daemon "$REMOTE")")
+run_stop "$CHAIN" || fail "stop with the remote daemon up exited with an error"
+grep -q "Backend: mock daemon.*step 2/3: remote" "$WORK/stop.log" || fail "the remote step didn't transcribe"
+grep -q "Chain step 3/3" "$WORK/stop.log" && fail "direct was tried after the remote step answered"
+
+# 3. --skip remote goes from the daemon straight to direct
+run_stop "$CHAIN" --skip remote || fail "stop --skip remote exited with an error"
+grep -q "Chain step 2/2: direct" "$WORK/stop.log" || fail "--skip remote didn't leave direct as step 2 of 2"
+grep -q "Chain step.*remote" "$WORK/stop.log" && fail "--skip remote still tried the remote daemon"
+
+# 4. A local daemon that hangs is given up on after its 2 s
+LOCAL_PID="$(start_daemon "$WA_WHISPER_SOCKET")"
+PIDS+=("$LOCAL_PID")
+kill -STOP "$LOCAL_PID"
+started=$(date +%s)
+run_stop "$CHAIN" || fail "stop with a hung local daemon exited with an error"
+elapsed=$(( $(date +%s) - started ))
+grep -q "No answer within 2s" "$WORK/stop.log" || fail "the hung daemon wasn't timed out"
+grep -q "step 2/3: remote" "$WORK/stop.log" || fail "the remote step didn't take over from the hung daemon"
+[ "$elapsed" -lt 10 ] || fail "stop took ${elapsed}s, the step timeout didn't apply"
+kill -CONT "$LOCAL_PID"
+
+# 5. With direct skipped and no daemon answering, stop fails and keeps the recording
+kill "$LOCAL_PID" "${PIDS[0]}"
+PIDS=()
+sleep 0.3
+run_stop "$CHAIN" --skip direct && fail "stop succeeded with every step failing"
+grep -q "Every step of the fallback chain failed" "$WORK/stop.log" || fail "exhausting the chain wasn't reported"
+# Out of the recordings dir, which the next start cleans up
+ls "$XDG_DATA_HOME"/whisp-away/kept/voice-recording-*.wav > /dev/null 2>&1 || fail "the recording wasn't kept"
+
+echo "PASS: the fallback chain ran in order, timed out and skipped steps"
@@ -0,0 +1,90 @@
+#!/usr/bin/env bash
+
+# Regression test: files another user owns are never acted on. A pidfile or
+# ready file of someone else's doesn't get its pids signalled, by `start`,
+# `stop` or the tray's cleanup, and a legacy pidfile in /tmp of someone
+# else's is ignored. Making files another user owns takes root, so the test
+# skips otherwise. Uses a fake pw-record and the mock backend. Run from the
+# repo root:
+#   cargo build && sudo tests/foreign_files.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+[ "$(id -u)" = 0 ] || skip "files of another user can only be made as root"
+LEGACY_PIDFILE=/tmp/whisp-away-recording.pid
+NOBODY=65534
+VICTIM=""
+OWN_LEGACY=""
+on_exit() {
+    [ -n "$VICTIM" ] && kill "$VICTIM" 2>/dev/null || true
+    [ -n "$OWN_LEGACY" ] && rm -f "$LEGACY_PIDFILE"
+}
+
+# Keep the test away from real recordings, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_FALLBACK_CHAIN=direct
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+stub pw-record <<'RECORDER'
+#!/bin/sh
+for last; do :; done
+: > "$last"
+trap 'exit 0' INT TERM
+while true; do sleep 0.05; done
+RECORDER
+stub_wl_copy
+stub_notify_send
+LOGS=("*.log")
+
+# A process of ours that a planted pidfile names
+sleep 300 &
+VICTIM=$!
+
+# Write a pidfile at $1 naming the victim, owned by someone else
+plant() {
+    echo "$VICTIM" > "$1"
+    chown "$NOBODY" "$1"
+}
+
+alive() {
+    kill -0 "$VICTIM" 2>/dev/null || fail "$1 killed the process named in a pidfile of another user"
+}
+
+# 1. Someone else's pidfile in the runtime dir: stop and start leave its pid alone
+PIDFILE="$XDG_RUNTIME_DIR/whisp-away-recording.pid"
+plant "$PIDFILE"
+timeout 30 "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 || true
+alive stop
+grep -q "WARNING: Ignoring $PIDFILE, which belongs to uid $NOBODY" "$WORK/stop.log" || fail "stop didn't say why it ignored the pidfile"
+plant "$PIDFILE"
+timeout 30 "$BIN" start --backend mock > "$WORK/start.log" 2>&1 || fail "start next to a pidfile of another user exited with an error"
+alive start
+sleep 0.3
+# Our own recording, started in place of the foreign pidfile, stops as usual
+timeout 30 "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 || fail "stopping our own recording exited with an error"
+[ "$("$BIN" last --field text 2>/dev/null)" = "mock transcription" ] || fail "our own recording wasn't transcribed"
+alive "stopping our own recording"
+
+# 2. Someone else's ready file: the tray's cleanup of orphaned daemons leaves its pid alone
+printf '{"pid": %s, "socket_path": "%s", "backend": "mock", "model": "base.en", "language": "en"}' \
+    "$VICTIM" "$XDG_RUNTIME_DIR/whisp-away/daemon-mock.sock" > "$XDG_RUNTIME_DIR/whisp-away/daemon-mock.ready"
+chown "$NOBODY" "$XDG_RUNTIME_DIR/whisp-away/daemon-mock.ready"
+timeout 30 "$BIN" status > "$WORK/status.log" 2>&1 || true
+grep -q "Ignoring $XDG_RUNTIME_DIR/whisp-away/daemon-mock.ready" "$WORK/status.log" || fail "status believed a ready file of another user"
+alive status
+rm -f "$XDG_RUNTIME_DIR/whisp-away/daemon-mock.ready"
+
+# 3. The pidfile older releases kept in /tmp, when someone else owns it
+if [ -e "$LEGACY_PIDFILE" ]; then
+    echo "NOTE: $LEGACY_PIDFILE exists, not replacing it"
+else
+    OWN_LEGACY=1
+    plant "$LEGACY_PIDFILE"
+    timeout 30 "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 || true
+    alive "stop with a legacy pidfile"
+    grep -q "WARNING: Ignoring $LEGACY_PIDFILE, which belongs to uid $NOBODY" "$WORK/stop.log" \
+        || fail "stop didn't say why it ignored the legacy pidfile"
+fi
+
+echo "PASS: files of another user were ignored"
@@ -0,0 +1,68 @@
+# Fixture shared by the regression tests. Source it from the repo root,
+# after `set -euo pipefail`:
+#   . tests/lib.sh
+#
+# It sets BIN (WHISP_AWAY overrides it), TEST_AUDIO and a scratch directory
+# WORK with a bin/ for stand-ins at the front of PATH. WORK goes away on
+# exit, after the test's own `on_exit` if it defines one.
+
+BIN="${WHISP_AWAY:-$(pwd)/target/debug/whisp-away}"
+TEST_AUDIO="$(pwd)/tests/jfk.wav"
+
+WORK="$(mktemp -d)"
+mkdir -p "$WORK/bin"
+export PATH="$WORK/bin:$PATH"
+
+cleanup() {
+    if declare -F on_exit > /dev/null; then
+        on_exit || true
+    fi
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# Files under WORK that fail() prints, globs allowed
+LOGS=()
+
+# Report a failure with the logs in LOGS and stop
+fail() {
+    echo "FAIL: $1"
+    local pattern file
+    for pattern in "${LOGS[@]}"; do
+        for file in "$WORK"/$pattern; do
+            [ -f "$file" ] || continue
+            echo "--- ${file#"$WORK/"}"
+            cat "$file"
+        done
+    done
+    exit 1
+}
+
+# Report why the test can't run here and stop; tests/run.sh counts it as
+# skipped rather than failed
+skip() {
+    echo "SKIP: $1"
+    exit 77
+}
+
+# Put an executable `$1` on PATH, its script read from stdin
+stub() {
+    cat > "$WORK/bin/$1"
+    chmod +x "$WORK/bin/$1"
+}
+
+# Stand in for wl-copy, keeping what is copied in $1 (by default, dropping it)
+stub_wl_copy() {
+    printf '#!/bin/sh\ncat > "%s"\n' "${1:-/dev/null}" | stub wl-copy
+}
+
+# Stand in for notify-send: without an argument notifications are dropped,
+# with `stderr` they are printed there as "NOTIFY: ...", and with a file
+# they are appended to it
+stub_notify_send() {
+    case "${1:-}" in
+        "") printf '#!/bin/sh\nexit 0\n' ;;
+        stderr) printf '#!/bin/sh\necho "NOTIFY: $*" >&2\n' ;;
+        *) printf '#!/bin/sh\necho "$*" >> "%s"\n' "$1" ;;
+    esac | stub notify-send
+}
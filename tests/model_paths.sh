@@ -0,0 +1,75 @@
+#!/usr/bin/env bash
+
+# Regression test: --model takes the name of an official model or the path
+# of a GGML or GGUF file, relative or absolute. `daemon --check` and setup
+# read paths as they are, tell the format by the file's magic rather than
+# its name, and never download in their place; compare skips a path that
+# isn't there as not found. The model files are stand-ins, as nothing here
+# loads them. Run from the repo root:
+#   cargo build && tests/model_paths.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+
+# Keep the test away from real daemons, models and settings
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_CACHE_HOME="$WORK/cache"
+export XDG_CONFIG_HOME="$WORK/config"
+export WA_AUTO_DOWNLOAD=false
+MODELS="$XDG_CACHE_HOME/whisper-cpp/models"
+mkdir -p "$XDG_RUNTIME_DIR" "$MODELS" "$WORK/project/models"
+LOGS=(out)
+
+# A stand-in model file: $1 is the magic, $2 the path
+fake_model() {
+    { printf '%s' "$1"; head -c 4096 /dev/zero; } > "$2"
+}
+
+fake_model lmgg "$MODELS/ggml-custom.bin"
+fake_model GGUF "$WORK/project/models/finetune.gguf"
+# Named like GGML, but GGUF inside
+fake_model GGUF "$WORK/project/models/ggml-renamed.bin"
+
+cd "$WORK/project"
+
+# Check model $1, expecting a file of format $2 at $3, or an error with $3
+# in it when $2 is "missing"
+check() {
+    local model="$1" format="$2" wanted="$3"
+    if [ "$format" = missing ]; then
+        timeout 30 "$BIN" daemon --backend whisper-cpp --model "$model" --check > "$WORK/out" 2>&1 \
+            && fail "daemon --check passed for the missing $model"
+        grep -q "Model file: Model file not found: $wanted" "$WORK/out" || fail "$model wasn't reported missing at $wanted"
+    else
+        timeout 30 "$BIN" daemon --backend whisper-cpp --model "$model" --check > "$WORK/out" 2>&1 || true
+        grep -q "Model file: ok, $format ($wanted)" "$WORK/out" || fail "$model wasn't checked as $format at $wanted"
+    fi
+}
+
+# 1. daemon --check: a name, relative and absolute paths, a misleading name, a missing path
+check custom GGML "$MODELS/ggml-custom.bin"
+check models/finetune.gguf GGUF "$WORK/project/models/finetune.gguf"
+check ./models/finetune.gguf GGUF "$WORK/project/models/finetune.gguf"
+check "$WORK/project/models/finetune.gguf" GGUF "$WORK/project/models/finetune.gguf"
+check models/ggml-renamed.bin GGUF "$WORK/project/models/ggml-renamed.bin"
+check models/missing.gguf missing "$WORK/project/models/missing.gguf"
+
+# 2. setup: a path is checked in place, a missing one isn't downloaded
+setup_model() {
+    timeout 30 "$BIN" setup --non-interactive --only backend --only model --backend whisper-cpp --model "$1" > "$WORK/out" 2>&1 \
+        || fail "setup with the model $1 exited with an error"
+}
+setup_model models/finetune.gguf
+grep -q "Model file $WORK/project/models/finetune.gguf is a GGUF model" "$WORK/out" || fail "setup didn't check the model file"
+setup_model models/missing.gguf
+grep -q "model step failed: Model file not found: $WORK/project/models/missing.gguf" "$WORK/out" \
+    || fail "setup didn't report the missing model path"
+grep -q "Downloading" "$WORK/out" && fail "setup tried to download a missing model path"
+[ "$(ls "$MODELS")" = ggml-custom.bin ] || fail "setup put a model in the cache for a path"
+
+# 3. compare: a missing path is skipped as not found, not as not downloaded
+timeout 30 "$BIN" compare --backend whisper-cpp --audio "$TEST_AUDIO" --models models/missing.gguf > "$WORK/out" 2>&1 || true
+grep -q "not found ($WORK/project/models/missing.gguf)" "$WORK/out" || fail "compare didn't skip the missing path as not found"
+
+echo "PASS: model names and paths were resolved, checked and never downloaded"
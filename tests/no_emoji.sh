@@ -0,0 +1,37 @@
+#!/usr/bin/env bash
+
+# --no-emoji and WA_NO_EMOJI take the emoji out of notifications, spelling
+# out the ones that mean something ("Error:"). The tray's menu and tooltip
+# are covered by the unit tests in src/tray.rs. Run from the repo root:
+#   cargo build && tests/no_emoji.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+
+# Keep the test away from the real daemons and settings, and stand in for
+# the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_CONFIG_HOME="$WORK/config"
+export XDG_CACHE_HOME="$WORK/cache"
+export XDG_DATA_HOME="$WORK/data"
+export WA_FALLBACK_CHAIN=direct
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR/whisp-away"
+stub_wl_copy
+stub_notify_send "$WORK/notifications"
+LOGS=(notifications)
+
+"$BIN" --no-emoji stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard --notify desktop > /dev/null 2>&1 \
+    || fail "stop --no-emoji failed"
+"$BIN" stop --backend mock --output clipboard --notify desktop --no-emoji > /dev/null 2>&1 || true
+WA_NO_EMOJI=1 "$BIN" stop --backend mock --output clipboard --notify desktop > /dev/null 2>&1 || true
+sleep 0.3
+grep -q "Copied to clipboard" "$WORK/notifications" || fail "the clipboard notification kept its emoji"
+[ "$(grep -c "Error: No recording found" "$WORK/notifications")" = 2 ] || fail "the error wasn't spelled out by --no-emoji and WA_NO_EMOJI"
+python3 - "$WORK/notifications" <<'EMOJI' || fail "a notification still had emoji"
+import re, sys
+sys.exit(1 if re.search("[⌀-⏿☀-➿\U0001f000-\U0001faff]", open(sys.argv[1]).read()) else 0)
+EMOJI
+
+echo "PASS: notifications went without emoji"
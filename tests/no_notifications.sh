@@ -0,0 +1,110 @@
+#!/usr/bin/env bash
+
+# Regression test: recording and transcribing work without a notification
+# daemon. With no notify-send on PATH, a notify-send that fails or one that
+# hangs, start and stop still succeed; notifications are given up once per
+# process and only logged after that, and a hung notify-send is killed.
+# Uses a fake pw-record and the mock backend, so no audio stack or model is
+# needed. Run from the repo root:
+#   cargo build && tests/no_notifications.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+on_exit() {
+    PATH="$WORK/bin" "$BIN" stop --backend mock --output clipboard > /dev/null 2>&1 || true
+    [ -f "$WORK/notify-pids" ] && xargs kill < "$WORK/notify-pids" 2>/dev/null
+}
+
+# Keep the test away from real recordings, and stand in for the desktop
+# tools. PATH holds only these and the few tools they need.
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_FALLBACK_CHAIN=direct
+export WA_DEBOUNCE_MS=0
+export WA_NOTIFY=desktop
+mkdir -p "$XDG_RUNTIME_DIR"
+stub pw-record <<'RECORDER'
+#!/bin/sh
+for last; do :; done
+: > "$last"
+trap 'exit 0' INT TERM
+while true; do sleep 0.05; done
+RECORDER
+stub_wl_copy
+for tool in sh sleep cat kill; do
+    ln -s "$(type -P "$tool")" "$WORK/bin/$tool"
+done
+LOGS=("*.log")
+
+# Forget earlier notifications, so none of these are rate limited
+unthrottle() {
+    rm -f "$XDG_RUNTIME_DIR/whisp-away-notify.json"
+}
+
+# Record and transcribe, with $1 describing the notify-send in place
+record() {
+    unthrottle
+    timeout 20 env PATH="$WORK/bin" "$BIN" start --backend mock > "$WORK/start.log" 2>&1 \
+        || fail "start with $1 exited with an error"
+    sleep 0.3
+    timeout 20 env PATH="$WORK/bin" "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 \
+        || fail "stop with $1 exited with an error"
+    grep -q "Copied to clipboard" "$WORK/stop.log" || fail "stop with $1 didn't copy the transcript"
+}
+
+# The processes logging to $2... each gave up on notifications once, with
+# $1 in the warning
+gave_up() {
+    local reason="$1" log
+    shift
+    for log in "$@"; do
+        [ "$(grep -c "WARNING: Notifications unavailable" "$WORK/$log.log")" = 1 ] \
+            || fail "$log didn't give up on notifications exactly once"
+        grep -q "Notifications unavailable ($reason" "$WORK/$log.log" || fail "$log didn't give up because $reason"
+    done
+}
+
+# The result's notification went to the stop log instead
+shown_instead() {
+    grep -q "Notification not shown: Voice Input: 📋 Copied to clipboard" "$WORK/stop.log" \
+        || fail "the notification wasn't logged in place of notify-send"
+}
+
+# 1. No notify-send at all
+record "no notify-send"
+gave_up "could not run notify-send" start stop
+shown_instead
+
+# 2. notify-send fails, as without a notification daemon
+printf '#!/bin/sh\necho "Failed to connect to the notification daemon" >&2\nexit 1\n' | stub notify-send
+# notify-send isn't waited for, so start may be gone before it fails; the
+# delay lets stop see it fail before the result's notification
+WA_MOCK_DELAY_MS=500 record "a failing notify-send"
+gave_up "notify-send failed" stop
+shown_instead
+
+# 3. notify-send hangs: stop isn't held up, and the one showing
+# "Transcribing..." is killed once it has had 2 s
+stub notify-send <<NOTIFY
+#!/bin/sh
+echo \$\$ >> "$WORK/notify-pids"
+case "\$2" in *Transcribing*) echo \$\$ >> "$WORK/transcribing-pids" ;; esac
+exec sleep 30
+NOTIFY
+unthrottle
+timeout 20 env PATH="$WORK/bin" "$BIN" start --backend mock > "$WORK/start.log" 2>&1 \
+    || fail "start with a hanging notify-send exited with an error"
+sleep 0.3
+SECONDS=0
+WA_MOCK_DELAY_MS=3000 timeout 20 env PATH="$WORK/bin" "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 \
+    || fail "stop with a hanging notify-send exited with an error"
+[ "$SECONDS" -lt 10 ] || fail "a hanging notify-send held stop up for $SECONDS s"
+grep -q "Copied to clipboard" "$WORK/stop.log" || fail "stop with a hanging notify-send didn't copy the transcript"
+gave_up "notify-send took over 2 s" stop
+shown_instead
+[ -s "$WORK/transcribing-pids" ] || fail "stop never ran notify-send"
+while read -r pid; do
+    ! kill -0 "$pid" 2>/dev/null || fail "the hanging notify-send is still running"
+done < "$WORK/transcribing-pids"
+
+echo "PASS: recording and transcribing worked without notifications"
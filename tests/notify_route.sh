@@ -0,0 +1,64 @@
+#!/usr/bin/env bash
+
+# Regression test: notifications are printed to stderr when it is a terminal
+# and sent with notify-send otherwise, and --notify, --force-notify and
+# WA_NOTIFY override that either way, the flags winning over the variable.
+# `script` provides the terminal. Uses the mock backend, so no model is
+# needed. Run from the repo root:
+#   cargo build && tests/notify_route.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+
+# Keep the test away from real daemons, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_FALLBACK_CHAIN=direct
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy
+stub_notify_send "$WORK/sent"
+LOGS=(out sent)
+
+# Run stop with stderr on a terminal ("tty") or a file ("file"), passing the
+# remaining arguments, and check the notification went to $2
+expect() {
+    local where="$1" wanted="$2"
+    shift 2
+    rm -f "$WORK/sent" "$WORK/out"
+    # Clear notify-send's throttle so every run may notify
+    rm -f "$XDG_RUNTIME_DIR/whisp-away-notify.json"
+    local command=("$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard "$@")
+    if [ "$where" = tty ]; then
+        # The terminal stays open a moment, so closing it doesn't hang up
+        # on a notify-send still starting
+        timeout 30 script -qec "$(printf '%q ' "${command[@]}"); status=\$?; sleep 0.3; exit \$status" /dev/null > "$WORK/out" 2>&1 \
+            || fail "stop on a terminal exited with an error ($*)"
+    else
+        timeout 30 "${command[@]}" > "$WORK/out" 2>&1 || fail "stop exited with an error ($*)"
+    fi
+    # notify-send isn't waited for
+    sleep 0.3
+    local printed=no sent=no
+    grep -q "Voice Input: 📋 Copied to clipboard" "$WORK/out" && printed=yes
+    grep -q "Copied to clipboard" "$WORK/sent" 2>/dev/null && sent=yes
+    case "$wanted" in
+        terminal) [ "$printed/$sent" = yes/no ] || fail "$where ${WA_NOTIFY:-} $*: expected a printed notification (printed $printed, sent $sent)" ;;
+        desktop) [ "$printed/$sent" = no/yes ] || fail "$where ${WA_NOTIFY:-} $*: expected notify-send (printed $printed, sent $sent)" ;;
+    esac
+}
+
+# Auto: the terminal when there is one
+expect file desktop
+expect tty terminal
+# The flags, both ways
+expect tty desktop --force-notify
+expect tty desktop --notify desktop
+expect file terminal --notify terminal
+expect tty terminal --notify auto
+# The variable, both ways, and the flags over it
+WA_NOTIFY=terminal expect file terminal
+WA_NOTIFY=desktop expect tty desktop
+WA_NOTIFY=terminal expect file desktop --force-notify
+WA_NOTIFY=desktop expect tty terminal --notify terminal
+
+echo "PASS: notifications were printed or sent as routed"
@@ -0,0 +1,136 @@
+#!/usr/bin/env bash
+
+# Regression test: after WA_OPENVINO_MAX_FAILURES failed transcriptions in a
+# row (default 3) the daemon's state moves to the next OpenVINO device, AUTO
+# then CPU then none. The move is logged, written to the events FIFO, pushed
+# to the daemon's subscribers and shown by `status`, and a success in between
+# resets the count. The mock daemon stands in for whisper with
+# WA_MOCK_FAIL_DEVICES, which makes its inference fail on the listed devices.
+# Run from the repo root:
+#   cargo build && tests/openvino_fallback.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+EVENTS_PID=""
+SUBSCRIBER_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null || true
+    [ -n "$EVENTS_PID" ] && kill "$EVENTS_PID" 2>/dev/null || true
+    [ -n "$SUBSCRIBER_PID" ] && kill "$SUBSCRIBER_PID" 2>/dev/null || true
+}
+
+# Keep the test away from real daemons and history, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_FALLBACK_CHAIN=daemon
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy
+stub_notify_send
+LOGS=(result stop.log daemon.log events.log subscriber.log status.log)
+
+start_daemon() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null && wait "$DAEMON_PID" 2>/dev/null || true
+    rm -f "$WA_WHISPER_SOCKET"
+    "$@" "$BIN" daemon --backend mock > "$WORK/daemon.log" 2>&1 &
+    DAEMON_PID=$!
+    for _ in $(seq 50); do
+        [ -S "$WA_WHISPER_SOCKET" ] && return 0
+        sleep 0.1
+    done
+    fail "the mock daemon did not start"
+}
+
+# Transcribe `$1` (the test audio by default); says whether the daemon
+# answered with text. `stop` exits 0 either way; a failure shows in its
+# result, not in the notification, which isn't waited for.
+transcribe() {
+    timeout 30 "$BIN" stop --backend mock --audio-file "${1:-$TEST_AUDIO}" --output clipboard --result-fd 3 \
+        3> "$WORK/result" > "$WORK/stop.log" 2>&1 || fail "stop exited with an error"
+    grep -q '"success":true' "$WORK/result"
+}
+
+device() {
+    timeout 30 "$BIN" status > "$WORK/status.log" 2>&1 || true
+    sed -n 's/^ *OpenVINO device: //p' "$WORK/status.log"
+}
+
+# 1. Two failures on AUTO stay on AUTO, a third moves the state to CPU
+start_daemon env WA_MOCK_FAIL_DEVICES=AUTO
+"$BIN" events --follow --raw > "$WORK/events.log" 2>/dev/null &
+EVENTS_PID=$!
+# Subscribe on the daemon's socket like the tray does, writing each event pushed
+python3 - "$WA_WHISPER_SOCKET" > "$WORK/subscriber.log" <<'SUBSCRIBE' &
+import json, socket, sys
+sock = socket.socket(socket.AF_UNIX)
+sock.connect(sys.argv[1])
+lines = sock.makefile("r")
+hello = {"protocol_version": 1, "min_protocol_version": 1, "capabilities": ["subscribe"]}
+sock.sendall((json.dumps(hello) + "\n").encode())
+lines.readline()
+sock.sendall(b'{"command":"subscribe"}\n')
+for line in lines:
+    print(line.strip(), flush=True)
+SUBSCRIBE
+SUBSCRIBER_PID=$!
+sleep 0.3
+[ "$(device)" = AUTO ] || fail "the daemon didn't start on AUTO"
+transcribe && fail "a transcription on the failing AUTO device succeeded"
+transcribe && fail "a transcription on the failing AUTO device succeeded"
+[ "$(device)" = AUTO ] || fail "two failures moved the state off AUTO"
+transcribe && fail "a transcription on the failing AUTO device succeeded"
+[ "$(device)" = CPU ] || fail "three failures in a row didn't move the state to CPU"
+grep -q "WARNING: 3 transcriptions in a row failed with OpenVINO device AUTO, moving to CPU" "$WORK/daemon.log" \
+    || fail "the move to CPU wasn't logged"
+transcribe || fail "the transcription on CPU failed"
+[ "$("$BIN" last --field text)" = "mock transcription" ] || fail "the transcription on CPU had the wrong text"
+grep -q '"event":"device_fallback","from":"AUTO","to":"CPU","failures":3' "$WORK/events.log" \
+    || fail "no device_fallback event for the move to CPU"
+grep -q '"event":"device_fallback","from":"AUTO","to":"CPU","failures":3' "$WORK/subscriber.log" \
+    || fail "the daemon's subscribers weren't told about the move to CPU"
+
+# 2. WA_OPENVINO_MAX_FAILURES sets how many, and the state ends up off
+# OpenVINO once CPU fails too
+start_daemon env WA_MOCK_FAIL_DEVICES=AUTO,CPU WA_OPENVINO_MAX_FAILURES=2
+for _ in 1 2; do
+    transcribe && fail "a transcription on the failing AUTO device succeeded"
+done
+[ "$(device)" = CPU ] || fail "WA_OPENVINO_MAX_FAILURES=2 didn't move the state after two failures"
+for _ in 1 2; do
+    transcribe && fail "a transcription on the failing CPU device succeeded"
+done
+[ "$(device)" = none ] || fail "failures on CPU didn't move the state off OpenVINO"
+for _ in 1 2 3; do
+    transcribe || fail "a transcription without OpenVINO failed"
+done
+[ "$(device)" = none ] || fail "the state moved on after successes"
+grep -q '"from":"CPU","to":"none"' "$WORK/events.log" || fail "no device_fallback event for the move off OpenVINO"
+
+# 3. Failures broken up by a success never add up to a move; the mock
+# daemon transcribes recordings under a second on any device
+python3 - "$TEST_AUDIO" "$WORK/short.wav" <<'SHORT'
+import sys, wave
+with wave.open(sys.argv[1]) as src, wave.open(sys.argv[2], "wb") as out:
+    out.setparams(src.getparams())
+    out.writeframes(src.readframes(src.getframerate() * 8 // 10))
+SHORT
+start_daemon env WA_MOCK_FAIL_DEVICES=AUTO WA_OPENVINO_MAX_FAILURES=2
+for _ in 1 2 3; do
+    transcribe && fail "a transcription on the failing AUTO device succeeded"
+    transcribe "$WORK/short.wav" || fail "the transcription between failures failed"
+done
+[ "$(device)" = AUTO ] || fail "failures with successes between them moved the state off AUTO"
+[ "$(grep -c "Mock state rebuilt on OpenVINO device AUTO" "$WORK/daemon.log")" = 3 ] || fail "the state wasn't rebuilt after every failure"
+transcribe && fail "a transcription on the failing AUTO device succeeded"
+transcribe && fail "a transcription on the failing AUTO device succeeded"
+[ "$(device)" = CPU ] || fail "two failures in a row after a success didn't move the state"
+
+# 4. Without the knob the mock daemon has no device to report
+start_daemon env
+[ -z "$(device)" ] || fail "the mock daemon reported a device without WA_MOCK_FAIL_DEVICES"
+grep -q "OpenVINO device" "$WORK/status.log" && fail "status showed a device without one"
+
+echo "PASS: the state moved AUTO -> CPU -> none after failures in a row, with a log line, events and status"
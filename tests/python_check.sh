@@ -0,0 +1,109 @@
+#!/usr/bin/env bash
+
+# Regression test: the faster-whisper daemon imports faster_whisper with its
+# Python before anything else, and fails fast with the ImportError in a
+# notification when that doesn't work. A passed check is cached, and done
+# again once the interpreter, PYTHONPATH or a site-packages dir changes;
+# failures are never cached. `daemon --check` runs the same check. A fake
+# interpreter stands in for Python. Run from the repo root:
+#   cargo build && tests/python_check.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+
+# Keep the test away from real daemons and caches, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_CACHE_HOME="$WORK/cache"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+mkdir -p "$XDG_RUNTIME_DIR" "$WORK/env/bin" "$WORK/env/lib/python3.12/site-packages" "$WORK/extra"
+# Imports fail while $WORK/broken exists, and hang while $WORK/slow does
+cat > "$WORK/env/bin/python3" <<PYTHON
+#!/bin/sh
+echo "\$*" >> "$WORK/calls"
+[ -e "$WORK/slow" ] && sleep 30
+if [ -e "$WORK/broken" ]; then
+    echo "Traceback (most recent call last):" >&2
+    echo '  File "<string>", line 1, in <module>' >&2
+    echo "ModuleNotFoundError: No module named 'ctranslate2'" >&2
+    exit 1
+fi
+exit 0
+PYTHON
+chmod +x "$WORK/env/bin/python3"
+stub_notify_send "$WORK/notifications"
+: > "$WORK/whisper_daemon.py"
+export FASTER_WHISPER_PYTHON="$WORK/env/bin/python3"
+export FASTER_WHISPER_PYTHONPATH="$WORK/site-packages"
+export FASTER_WHISPER_DAEMON_SCRIPT="$WORK/whisper_daemon.py"
+mkdir -p "$FASTER_WHISPER_PYTHONPATH"
+LOGS=(calls check.log)
+
+checks() {
+    grep -c "^-c import faster_whisper" "$WORK/calls" 2>/dev/null || echo 0
+}
+
+run_check() {
+    "$BIN" daemon --backend faster-whisper --model tiny --check > "$WORK/check.log" 2>&1
+}
+
+# Moves a file's modification time ahead, as an install would; further each
+# time, so two changes within a second still differ
+LATER=0
+touch_later() {
+    LATER=$(( LATER + 60 ))
+    touch -d "@$(( $(date +%s) + LATER ))" "$1"
+}
+
+# 1. The first check imports, the second is answered from the cache
+run_check || fail "the check failed in a working environment"
+[ "$(checks)" = 1 ] || fail "the first check didn't import faster_whisper"
+grep -q "Python: ok, imports took" "$WORK/check.log" || fail "the check didn't report the imports"
+run_check || fail "the cached check failed"
+[ "$(checks)" = 1 ] || fail "a passed check wasn't cached"
+grep -q "Python: ok, checked before" "$WORK/check.log" || fail "the check didn't say it was cached"
+
+# 2. Each part of the environment invalidates the cache
+touch_later "$FASTER_WHISPER_PYTHONPATH"
+run_check || fail "the check failed after a package was installed"
+[ "$(checks)" = 2 ] || fail "a change on PYTHONPATH's site-packages didn't invalidate the cache"
+touch_later "$WORK/env/lib/python3.12/site-packages"
+run_check || fail "the check failed after a package was installed next to the interpreter"
+[ "$(checks)" = 3 ] || fail "a change in the interpreter's own site-packages didn't invalidate the cache"
+touch_later "$FASTER_WHISPER_PYTHON"
+run_check || fail "the check failed after the interpreter was replaced"
+[ "$(checks)" = 4 ] || fail "a replaced interpreter didn't invalidate the cache"
+FASTER_WHISPER_PYTHONPATH="$FASTER_WHISPER_PYTHONPATH:$WORK/extra" run_check || fail "the check failed with a longer PYTHONPATH"
+[ "$(checks)" = 5 ] || fail "a different PYTHONPATH didn't invalidate the cache"
+run_check || fail "the check failed back on the first PYTHONPATH"
+[ "$(checks)" = 5 ] || fail "each PYTHONPATH didn't keep its own cached check"
+WA_PYTHON_CHECK_CACHE=0 run_check || fail "the uncached check failed"
+[ "$(checks)" = 6 ] || fail "WA_PYTHON_CHECK_CACHE=0 used the cache"
+
+# 3. A broken environment fails with the ImportError, and isn't cached
+touch "$WORK/broken"
+touch_later "$FASTER_WHISPER_PYTHONPATH"
+run_check && fail "the check passed in a broken environment"
+grep -q "No module named 'ctranslate2'" "$WORK/check.log" || fail "the check didn't show the ImportError"
+run_check && fail "a failed check was cached"
+[ "$(checks)" = 8 ] || fail "a failed check wasn't run again"
+
+# 4. The daemon fails fast in a broken environment, with a notification,
+# and never starts the daemon script
+rm -f "$WORK/notifications"
+timeout 20 "$BIN" daemon --backend faster-whisper --model tiny > "$WORK/check.log" 2>&1 \
+    && fail "the daemon started in a broken environment"
+grep -q "whisper_daemon.py" "$WORK/calls" && fail "the daemon script ran in a broken environment"
+grep -q "Python environment broken" "$WORK/notifications" || fail "no notification about the broken environment"
+grep -q "No module named 'ctranslate2'" "$WORK/notifications" || fail "the notification didn't carry the ImportError"
+
+# 5. Imports that hang count as broken after WA_PYTHON_CHECK_TIMEOUT
+rm -f "$WORK/broken"
+touch "$WORK/slow"
+began="$(date +%s)"
+WA_PYTHON_CHECK_TIMEOUT=1 run_check && fail "the check passed with hanging imports"
+[ $(( $(date +%s) - began )) -lt 10 ] || fail "the check waited for the hanging imports"
+grep -q "took over 1s to import faster_whisper" "$WORK/check.log" || fail "the timeout wasn't reported"
+
+echo "PASS: the Python environment was checked, cached until it changed, and reported when broken"
@@ -0,0 +1,129 @@
+#!/usr/bin/env bash
+
+# Regression test: `record` writes a WAV file with a correct header whether
+# it stops at --max-duration, on Ctrl-C or on SIGTERM, even when pw-record
+# leaves the sizes unwritten. It records from --device, refuses to overwrite
+# a file, fails without audio, and leaves dictation alone: a start/stop
+# while it records transcribes as usual and doesn't end the recording. A
+# fake pw-record stands in for PipeWire; python3 is needed. Run from the
+# repo root:
+#   cargo build && tests/record.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+RECORD_PID=""
+on_exit() {
+    [ -n "$RECORD_PID" ] && kill "$RECORD_PID" 2>/dev/null || true
+    "$BIN" stop --backend mock --output clipboard > /dev/null 2>&1
+}
+
+# Keep the test away from real recordings, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_FALLBACK_CHAIN=direct
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+# Writes 100 ms of tone every 100 ms after a header whose sizes are never
+# filled in, as if killed; with $WORK/silent it writes nothing at all
+stub pw-record <<RECORDER
+#!/usr/bin/env python3
+import math, os, signal, struct, sys, time
+open("$WORK/pw-args", "a").write(" ".join(sys.argv[1:]) + "\n")
+signal.signal(signal.SIGINT, lambda *_: sys.exit(0))
+signal.signal(signal.SIGTERM, lambda *_: sys.exit(0))
+out = open(sys.argv[-1], "wb")
+silent = os.path.exists("$WORK/silent")
+if not silent:
+    out.write(b"RIFF" + struct.pack("<I", 0) + b"WAVE")
+    out.write(b"fmt " + struct.pack("<IHHIIHH", 16, 1, 1, 16000, 32000, 2, 16))
+    out.write(b"data" + struct.pack("<I", 0xFFFFFFFF))
+chunk = b"".join(struct.pack("<h", int(8000 * math.sin(i / 10))) for i in range(1600))
+while True:
+    if not silent:
+        out.write(chunk)
+        out.flush()
+    time.sleep(0.1)
+RECORDER
+stub_wl_copy
+stub_notify_send
+LOGS=("*.log")
+
+# Seconds of audio in WAV file $1, read by python's wave module, which
+# trusts the header: an unfinalized one reads as garbage or fails
+duration() {
+    python3 - "$1" <<'DURATION'
+import os, sys, wave
+with wave.open(sys.argv[1]) as w:
+    frames = w.getnframes()
+    assert 44 + frames * 2 == os.path.getsize(sys.argv[1]), "the header doesn't match the file"
+    print(f"{frames / w.getframerate():.1f}")
+DURATION
+}
+
+# Whether $1 is between $2 and $3
+between() {
+    python3 -c 'import sys; sys.exit(not float(sys.argv[2]) <= float(sys.argv[1]) <= float(sys.argv[3]))' "$1" "$2" "$3"
+}
+
+# Wait for the background record to write its file, then give it $1 more seconds
+recording_for() {
+    for _ in $(seq 50); do
+        [ -s "$1" ] && break
+        sleep 0.1
+    done
+    sleep "$2"
+}
+
+# 1. --max-duration stops it, with the header fixed up and no pw-record log left behind
+timeout 30 "$BIN" record --out "$WORK/out/max.wav" --max-duration 2 --device test-mic > "$WORK/record.log" 2>&1 \
+    || fail "record --max-duration exited with an error"
+seconds="$(duration "$WORK/out/max.wav")" || fail "max.wav isn't a valid WAV file"
+between "$seconds" 1.5 2.6 || fail "a 2 s recording holds $seconds s"
+[ ! -e "$WORK/out/max.log" ] || fail "the pw-record log was left next to the recording"
+grep -q -- "--target test-mic" "$WORK/pw-args" || fail "--device didn't reach pw-record"
+
+# 2. Ctrl-C and SIGTERM: what was recorded is kept
+for signal in INT TERM; do
+    "$BIN" record --out "$WORK/$signal.wav" > "$WORK/record.log" 2>&1 &
+    RECORD_PID=$!
+    recording_for "$WORK/$signal.wav" 1.5
+    kill -"$signal" "$RECORD_PID"
+    status=0
+    wait "$RECORD_PID" || status=$?
+    RECORD_PID=""
+    [ "$status" = 0 ] || fail "record exited with $status on SIG$signal"
+    seconds="$(duration "$WORK/$signal.wav")" || fail "the recording stopped by SIG$signal isn't a valid WAV file"
+    between "$seconds" 1 3 || fail "the recording stopped by SIG$signal holds $seconds s"
+done
+
+# 3. A dictation during a recording works and leaves the recording running
+"$BIN" record --out "$WORK/long.wav" > "$WORK/record.log" 2>&1 &
+RECORD_PID=$!
+recording_for "$WORK/long.wav" 0.5
+timeout 20 "$BIN" start --backend mock > "$WORK/start.log" 2>&1 || fail "start during a recording exited with an error"
+sleep 0.5
+timeout 30 "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 || fail "stop during a recording exited with an error"
+[ "$("$BIN" last --field text 2>/dev/null)" = "mock transcription" ] || fail "the dictation during a recording wasn't transcribed"
+kill -0 "$RECORD_PID" 2>/dev/null || fail "the dictation ended the recording"
+sleep 0.5
+kill -INT "$RECORD_PID"
+wait "$RECORD_PID" || fail "record exited with an error after a dictation"
+RECORD_PID=""
+seconds="$(duration "$WORK/long.wav")" || fail "the recording around a dictation isn't a valid WAV file"
+between "$seconds" 2 6 || fail "the recording around a dictation holds $seconds s"
+
+# 4. An existing file is never overwritten
+before="$(md5sum < "$WORK/long.wav")"
+timeout 10 "$BIN" record --out "$WORK/long.wav" --max-duration 1 > "$WORK/record.log" 2>&1 \
+    && fail "record overwrote an existing file"
+grep -q "already exists" "$WORK/record.log" || fail "record didn't say the file exists"
+[ "$(md5sum < "$WORK/long.wav")" = "$before" ] || fail "the existing file was changed"
+
+# 5. No audio at all: an error, and no file
+touch "$WORK/silent"
+timeout 30 "$BIN" record --out "$WORK/silent.wav" --max-duration 2 > "$WORK/record.log" 2>&1 \
+    && fail "record without audio succeeded"
+grep -q "No audio was captured" "$WORK/record.log" || fail "record didn't say no audio was captured"
+[ ! -e "$WORK/silent.wav" ] || fail "an empty recording was left behind"
+
+echo "PASS: recordings were finished with a valid header and kept apart from dictation"
@@ -0,0 +1,49 @@
+#!/usr/bin/env bash
+
+# Regression test: two recordings started back to back get distinct files,
+# and the pointer file names the newer one. Both used to be named after the
+# millisecond they started in, so starts within one millisecond collided.
+# Uses a fake pw-record, so no audio stack is needed. Run from the repo root:
+#   cargo build && tests/recording_names.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+on_exit() {
+    "$BIN" stop --backend mock --output clipboard > /dev/null 2>&1
+}
+
+# Keep the test away from real recordings, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+# The two starts are identical and run back to back on purpose
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+stub pw-record <<'RECORDER'
+#!/bin/sh
+for last; do :; done
+: > "$last"
+trap 'exit 0' INT TERM
+while true; do sleep 0.05; done
+RECORDER
+stub_wl_copy
+stub_notify_send
+POINTER="$XDG_RUNTIME_DIR/voice-audio-file.tmp"
+LOGS=("start-*.log")
+
+# Left behind by a release that named recordings by the millisecond
+LEFTOVER="$XDG_RUNTIME_DIR/voice-recording-1700000000000.wav"
+: > "$LEFTOVER"
+
+"$BIN" start --backend mock > "$WORK/start-1.log" 2>&1 || fail "first start exited with an error"
+FIRST="$(cat "$POINTER")"
+"$BIN" start --backend mock > "$WORK/start-2.log" 2>&1 || fail "second start exited with an error"
+SECOND="$(cat "$POINTER")"
+
+[ -n "$FIRST" ] && [ -n "$SECOND" ] || fail "the pointer file was empty"
+[ "$FIRST" != "$SECOND" ] || fail "both recordings went to $FIRST"
+[ -e "$SECOND" ] || fail "the pointer names $SECOND, which doesn't exist"
+[ ! -e "$LEFTOVER" ] || fail "the old-style leftover $LEFTOVER was not cleaned up"
+[ ! -e "$FIRST" ] || fail "the first recording $FIRST was not cleaned up"
+ls "$XDG_RUNTIME_DIR"/voice-audio-file.tmp.*.tmp > /dev/null 2>&1 && fail "a temporary pointer file was left behind"
+
+echo "PASS: back-to-back recordings went to $FIRST and $SECOND"
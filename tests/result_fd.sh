@@ -0,0 +1,110 @@
+#!/usr/bin/env bash
+
+# Regression test: `stop --result-fd 3` writes exactly one JSON document to
+# fd 3, and nothing else does: not the logs, not stdout, not the programs
+# stop runs (the fake wl-copy lingers in the background like the real one,
+# and still the reader sees the end as soon as stop exits). Failures get a
+# document too, with the error code, and --result-file writes the same to a
+# file. Uses the mock daemon. Run from the repo root:
+#   cargo build && tests/result_fd.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons and history, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_FALLBACK_CHAIN=daemon
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+stub wl-copy <<'COPY'
+#!/bin/sh
+cat > /dev/null
+# Serves the clipboard from the background, holding whatever fds it got
+sleep 5 &
+COPY
+stub_notify_send
+LOGS=(result stop.log daemon.log)
+
+start_daemon() {
+    rm -f "$WA_WHISPER_SOCKET"
+    "$BIN" daemon --backend mock > "$WORK/daemon.log" 2>&1 &
+    DAEMON_PID=$!
+    for _ in $(seq 50); do
+        [ -S "$WA_WHISPER_SOCKET" ] && return 0
+        sleep 0.1
+    done
+    fail "the mock daemon did not start"
+}
+
+# Run stop with a pipe on fd 3, reading it to the end. Stop's own status
+# goes to $WORK/status, and how long the reader waited to $WORK/waited.
+stop_with_fd() {
+    local began
+    began="$(date +%s%N)"
+    { "$BIN" stop --backend mock --output clipboard --result-fd 3 "$@" 3>&1 > "$WORK/stdout" 2> "$WORK/stop.log"; echo $? > "$WORK/status"; } \
+        | timeout 20 cat > "$WORK/result" || fail "fd 3 wasn't closed within 20 s"
+    echo $(( ($(date +%s%N) - began) / 1000000 )) > "$WORK/waited"
+}
+
+# The one document on fd 3, or a failure if there isn't exactly one
+field() {
+    python3 - "$WORK/result" "$1" <<'PARSE'
+import json, sys
+lines = open(sys.argv[1]).read().splitlines()
+if len(lines) != 1:
+    sys.exit(f"{len(lines)} lines instead of one JSON document")
+value = json.loads(lines[0]).get(sys.argv[2])
+print("" if value is None else json.dumps(value).strip('"'))
+PARSE
+}
+
+# 1. A transcript: text, backend, model and timings, and the rest of stop's
+# output left alone
+start_daemon
+stop_with_fd --audio-file "$TEST_AUDIO"
+[ "$(cat "$WORK/status")" = 0 ] || fail "stop exited with $(cat "$WORK/status")"
+[ "$(field success)" = true ] || fail "a transcript wasn't reported as a success"
+[ "$(field text)" = "mock transcription" ] || fail "the result had the wrong text"
+field backend | grep -q mock || fail "the result didn't name the backend"
+[ -n "$(field model)" ] || fail "the result didn't name the model"
+[ -z "$(field error)" ] || fail "a success came with an error code"
+[ "$(field total_ms)" -ge "$(field transcription_ms)" ] || fail "the timings didn't add up"
+[ "$(cat "$WORK/waited")" -lt 4000 ] || fail "fd 3 stayed open after stop, held by a program it ran"
+grep -q total_ms "$WORK/stdout" "$WORK/stop.log" && fail "the result leaked into stdout or the logs"
+
+# 2. A failure still writes one document, with the code of the error event
+kill "$DAEMON_PID"; wait "$DAEMON_PID" 2>/dev/null || true; DAEMON_PID=""
+stop_with_fd --audio-file "$TEST_AUDIO"
+[ "$(cat "$WORK/status")" != 0 ] || fail "stop with no daemon exited with 0"
+[ "$(field success)" = false ] || fail "a failure was reported as a success"
+[ "$(field error)" = chain_exhausted ] || fail "the failure had code $(field error), expected chain_exhausted"
+[ -n "$(field message)" ] || fail "the failure had no message"
+
+# 3. Giving up before transcribing too: a broken fallback chain
+WA_FALLBACK_CHAIN="nonsense@" stop_with_fd --audio-file "$TEST_AUDIO"
+[ "$(field success)" = false ] || fail "a broken chain was reported as a success"
+[ "$(field error)" = failed ] || fail "a broken chain had code $(field error), expected failed"
+
+# 4. Nothing transcribed, nothing recorded
+stop_with_fd
+[ "$(field error)" = no_recording ] || fail "stop without a recording had code $(field error), expected no_recording"
+
+# 5. Unusable descriptors are refused before stopping anything
+"$BIN" stop --backend mock --result-fd 9 > /dev/null 2> "$WORK/stop.log" && fail "stop accepted a closed fd"
+grep -q "isn't an open file descriptor" "$WORK/stop.log" || fail "a closed fd wasn't reported"
+"$BIN" stop --backend mock --result-fd 2 > /dev/null 2> "$WORK/stop.log" && fail "stop accepted stderr as the result fd"
+
+# 6. --result-file writes the same document
+start_daemon
+"$BIN" stop --backend mock --output clipboard --audio-file "$TEST_AUDIO" --result-file "$WORK/result" > /dev/null 2> "$WORK/stop.log" \
+    || fail "stop --result-file exited with an error"
+[ "$(field text)" = "mock transcription" ] || fail "--result-file had the wrong text"
+
+echo "PASS: stop wrote exactly one JSON result to fd 3 or a file, on success and failure"
@@ -0,0 +1,69 @@
+#!/usr/bin/env bash
+
+# Regression test: with WA_RETRY_ON_EMPTY=1 the daemon transcribes an empty
+# first pass once more, and the transcript says it was retried. Without it,
+# or when WA_RETRY_MAX_MS leaves no room for a retry, the empty transcript
+# stands. A mock daemon whose first pass hears nothing (WA_MOCK_EMPTY_FIRST)
+# stands in for the model. Run from the repo root:
+#   cargo build && tests/retry_on_empty.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons and data, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_FALLBACK_CHAIN=daemon
+export WA_DEBOUNCE_MS=0
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy "$WORK/clipboard"
+stub_notify_send "$WORK/notifications"
+LOGS=("*.log" notifications)
+
+# (Re)start the mock daemon with the environment given as arguments
+start_daemon() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null && wait "$DAEMON_PID" 2>/dev/null
+    rm -f "$WA_WHISPER_SOCKET"
+    env WA_MOCK_EMPTY_FIRST=1 "$@" "$BIN" daemon --backend mock --socket-path "$WA_WHISPER_SOCKET" \
+        --ready-file "$WA_WHISPER_SOCKET.ready" > "$WORK/daemon.log" 2>&1 &
+    DAEMON_PID=$!
+    for _ in $(seq 50); do
+        [ -S "$WA_WHISPER_SOCKET" ] && return
+        sleep 0.1
+    done
+    fail "the mock daemon did not bind its socket"
+}
+
+# Dictate the test audio into the clipboard
+dictate() {
+    rm -f "$WORK/clipboard" "$WORK/notifications"
+    timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard \
+        --ready-file "$WA_WHISPER_SOCKET.ready" > "$WORK/stop.log" 2>&1 || true
+}
+
+# 1. Retrying turns the empty first pass into a transcript, and says so
+start_daemon WA_RETRY_ON_EMPTY=1
+dictate
+[ "$(cat "$WORK/clipboard" 2>/dev/null)" = "mock transcription" ] || fail "the retried transcript wasn't output"
+grep -q "mock daemon, retried" "$WORK/notifications" || fail "the notification didn't say the transcript was retried"
+
+# 2. Off by default: the empty transcript stands
+start_daemon
+dictate
+[ ! -s "$WORK/clipboard" ] || fail "an empty first pass was retried without WA_RETRY_ON_EMPTY"
+grep -q "No speech detected" "$WORK/notifications" || fail "the empty transcript wasn't reported"
+grep -q "retried" "$WORK/notifications" && fail "a transcript that wasn't retried was said to be"
+
+# 3. No room under WA_RETRY_MAX_MS: not retried
+start_daemon WA_RETRY_ON_EMPTY=1 WA_RETRY_MAX_MS=0
+dictate
+[ ! -s "$WORK/clipboard" ] || fail "the retry ran past WA_RETRY_MAX_MS=0"
+grep -q "over WA_RETRY_MAX_MS" "$WORK/daemon.log" || fail "the daemon didn't say why it skipped the retry"
+
+echo "PASS: empty transcripts were retried once, and only when enabled and affordable"
@@ -0,0 +1,38 @@
+#!/usr/bin/env bash
+
+# Run the regression tests (all of them, or the ones named) against one
+# build and sum up. A test exiting with 77 was skipped, e.g. for a feature
+# the build lacks. Run from the repo root:
+#   cargo build && tests/run.sh
+#   tests/run.sh tests/socket_path.sh tests/record.sh
+
+set -uo pipefail
+
+export WHISP_AWAY="${WHISP_AWAY:-$(pwd)/target/debug/whisp-away}"
+[ -x "$WHISP_AWAY" ] || { echo "No whisp-away at $WHISP_AWAY; run cargo build first"; exit 1; }
+
+if [ $# -eq 0 ]; then
+    # The benchmark needs real models and takes minutes
+    set -- $(ls tests/*.sh | grep -v -e /lib.sh -e /run.sh -e /benchmark_whisp.sh)
+fi
+
+LOGS="$(mktemp -d)"
+trap 'rm -rf "$LOGS"' EXIT
+passed=0 skipped=0 failed=()
+for test in "$@"; do
+    log="$LOGS/$(basename "$test").log"
+    timeout "${WA_TEST_TIMEOUT:-300}" bash "$test" > "$log" 2>&1
+    status=$?
+    case $status in
+        0) echo "ok    $test"; passed=$((passed + 1)) ;;
+        77) echo "skip  $test: $(grep -m1 '^SKIP: ' "$log" | cut -c7-)"; skipped=$((skipped + 1)) ;;
+        *)
+            echo "FAIL  $test (exit $status)"
+            sed 's/^/    /' "$log"
+            failed+=("$test")
+            ;;
+    esac
+done
+
+echo "$passed passed, $skipped skipped, ${#failed[@]} failed"
+[ ${#failed[@]} -eq 0 ]
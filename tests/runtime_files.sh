@@ -0,0 +1,83 @@
+#!/usr/bin/env bash
+
+# Regression test: start and stop keep their bookkeeping (the pidfile and
+# the pointer file naming the recording) in XDG_RUNTIME_DIR, creating it
+# private if it doesn't exist, and clean it up on stop. A recorder left in
+# the pidfile an older release kept in /tmp is stopped and the file read
+# once. A fake pw-record stands in for PipeWire. Run from the repo root:
+#   cargo build && tests/runtime_files.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+LEGACY_PIDFILE=/tmp/whisp-away-recording.pid
+OLD_PID=""
+on_exit() {
+    [ -n "$OLD_PID" ] && kill "$OLD_PID" 2>/dev/null
+    "$BIN" stop --backend mock --output clipboard > /dev/null 2>&1
+}
+
+# Keep the test away from real recordings, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_FALLBACK_CHAIN=direct
+export WA_DEBOUNCE_MS=0
+stub pw-record <<RECORDER
+#!/bin/sh
+echo \$\$ >> "$WORK/recorders"
+for last; do :; done
+cp "$TEST_AUDIO" "\$last"
+trap 'exit 0' INT TERM
+while true; do sleep 0.05; done
+RECORDER
+stub_wl_copy
+stub_notify_send
+LOGS=("*.log")
+
+PIDFILE="$XDG_RUNTIME_DIR/whisp-away-recording.pid"
+POINTER="$XDG_RUNTIME_DIR/voice-audio-file.tmp"
+
+# Whether process $1 is gone, waiting up to 2 s for it to exit
+gone() {
+    for _ in $(seq 20); do
+        kill -0 "$1" 2>/dev/null || return 0
+        sleep 0.1
+    done
+    return 1
+}
+
+# 1. start creates the runtime dir and keeps its bookkeeping there
+timeout 20 "$BIN" start --backend mock > "$WORK/start.log" 2>&1 || fail "start exited with an error"
+sleep 0.3
+[ -d "$XDG_RUNTIME_DIR" ] || fail "start didn't create XDG_RUNTIME_DIR"
+[ "$(stat -c %a "$XDG_RUNTIME_DIR")" = 700 ] || fail "the runtime dir was created with mode $(stat -c %a "$XDG_RUNTIME_DIR")"
+[ -f "$PIDFILE" ] || fail "no pidfile in the runtime dir"
+[ "$(cat "$PIDFILE")" = "$(cat "$WORK/recorders")" ] || fail "the pidfile holds $(cat "$PIDFILE"), not the recorder's pid"
+[ -f "$POINTER" ] || fail "no pointer file in the runtime dir"
+recording="$(cat "$POINTER")"
+[ -f "$recording" ] || fail "the pointer file names $recording, which doesn't exist"
+
+# 2. stop stops the recorder, transcribes what the pointer file names and
+# removes both files
+recorder="$(cat "$PIDFILE")"
+timeout 30 "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 || fail "stop exited with an error"
+gone "$recorder" || fail "stop left pw-record running"
+[ "$("$BIN" last --field text 2>/dev/null)" = "mock transcription" ] || fail "the recording wasn't transcribed"
+[ ! -e "$PIDFILE" ] || fail "stop left the pidfile behind"
+[ ! -e "$POINTER" ] || fail "stop left the pointer file behind"
+
+# 3. A recorder an older release left in /tmp is stopped by the next start,
+# and its pidfile is gone afterwards
+[ -e "$LEGACY_PIDFILE" ] && skip "$LEGACY_PIDFILE exists, which may be a real recording"
+sleep 60 &
+OLD_PID=$!
+echo "$OLD_PID" > "$LEGACY_PIDFILE"
+timeout 20 "$BIN" start --backend mock > "$WORK/start.log" 2>&1 || fail "start after an upgrade exited with an error"
+gone "$OLD_PID" || fail "the recorder in the legacy pidfile was left running"
+OLD_PID=""
+[ ! -e "$LEGACY_PIDFILE" ] || fail "the legacy pidfile was left behind"
+sleep 0.3
+[ "$(cat "$PIDFILE")" = "$(tail -n1 "$WORK/recorders")" ] || fail "the new recording's pid isn't in the runtime dir's pidfile"
+timeout 30 "$BIN" stop --backend mock --output clipboard > "$WORK/stop.log" 2>&1 || fail "stop after an upgrade exited with an error"
+[ ! -e "$PIDFILE" ] || fail "stop left the pidfile behind after an upgrade"
+
+echo "PASS: recording bookkeeping stayed in the runtime dir and the legacy pidfile was read once"
@@ -0,0 +1,40 @@
+#!/usr/bin/env bash
+
+# Regression test: a daemon given its socket through WA_WHISPER_SOCKET is the
+# one a plain `stop` connects to, with no --socket-path on either side, and
+# only its own user may connect to it. Uses the mock backend, so no model is needed. Run from the repo root:
+#   cargo build && tests/socket_path.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export WA_WHISPER_SOCKET="$WORK/custom.sock"
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy "$WORK/clipboard"
+stub_notify_send stderr
+LOGS=(daemon.log stop.log)
+
+"$BIN" daemon --backend mock > "$WORK/daemon.log" 2>&1 &
+DAEMON_PID=$!
+for _ in $(seq 50); do
+    [ -S "$WA_WHISPER_SOCKET" ] && break
+    sleep 0.1
+done
+[ -S "$WA_WHISPER_SOCKET" ] || fail "daemon did not bind $WA_WHISPER_SOCKET"
+[ "$(stat -c %a "$WA_WHISPER_SOCKET")" = 600 ] || fail "the daemon socket is mode $(stat -c %a "$WA_WHISPER_SOCKET")"
+grep -q "from WA_WHISPER_SOCKET" "$WORK/daemon.log" || fail "daemon did not log where its socket came from"
+
+"$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard > "$WORK/stop.log" 2>&1 \
+    || fail "stop exited with an error"
+grep -q "Connecting to daemon socket at: $WA_WHISPER_SOCKET" "$WORK/stop.log" || fail "stop did not connect to $WA_WHISPER_SOCKET"
+grep -q "Backend: mock daemon" "$WORK/stop.log" || fail "stop transcribed directly instead of through the daemon"
+[ -s "$WORK/clipboard" ] || fail "nothing was copied"
+
+echo "PASS: stop reached the daemon on $WA_WHISPER_SOCKET, which only its user may use"
@@ -0,0 +1,75 @@
+#!/usr/bin/env bash
+
+# Regression test: the length of the recording, the words transcribed from
+# it and the words per minute reach the notification, the last-result file
+# and the journal, whether the text came from a daemon or was transcribed
+# directly. Words are counted across scripts, and a lone dash isn't one.
+# Uses the mock backend, so no model is needed. Run from the repo root:
+#   cargo build && tests/speech_stats.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons and history, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_KEEP_AUDIO=1
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy
+stub_notify_send stderr
+LOGS=(stop.log)
+
+# Six words: the dash isn't one, the non-breaking space separates two
+export WA_MOCK_TEXT=$'Grüße aus\xc2\xa0Köln — schön, oder? 東京'
+
+run_stop() {
+    WA_FALLBACK_CHAIN="$1" timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard \
+        > "$WORK/stop.log" 2>&1 || fail "stop via $1 exited with an error"
+}
+
+# Whether $1 reaches the stop log within two seconds: notify-send isn't waited for
+logged() {
+    for _ in $(seq 20); do
+        grep -q -- "$1" "$WORK/stop.log" && return 0
+        sleep 0.1
+    done
+    return 1
+}
+
+# The 11 s of jfk.wav and six words, wherever they are reported
+check() {
+    local how="$1"
+    logged "11.0 s → 6 words" || fail "$how: the notification doesn't say 11.0 s → 6 words"
+    [ "$("$BIN" last --field audio_seconds 2>/dev/null)" = "11.0" ] || fail "$how: last-result audio_seconds isn't 11.0"
+    [ "$("$BIN" last --field words 2>/dev/null)" = "6" ] || fail "$how: last-result words isn't 6"
+    [ "$("$BIN" last --field words_per_minute 2>/dev/null)" = "32.7" ] || fail "$how: last-result words_per_minute isn't 32.7"
+    local entry
+    entry="$("$BIN" journal last 2>/dev/null)"
+    grep -q '"audio_seconds": 11.0' <<< "$entry" || fail "$how: the journal entry has no audio_seconds of 11.0"
+    grep -q '"words": 6' <<< "$entry" || fail "$how: the journal entry has no words count of 6"
+    grep -q '"words_per_minute": 32.7' <<< "$entry" || fail "$how: the journal entry has no words_per_minute of 32.7"
+}
+
+# 1. Transcribed directly: measured by stop itself
+run_stop direct
+check "direct"
+
+# 2. Transcribed by a daemon, which reports the length it measured
+"$BIN" daemon --backend mock > "$WORK/daemon.log" 2>&1 &
+DAEMON_PID=$!
+for _ in $(seq 50); do
+    [ -S "$WA_WHISPER_SOCKET" ] && break
+    sleep 0.1
+done
+[ -S "$WA_WHISPER_SOCKET" ] || fail "the mock daemon did not start"
+run_stop daemon
+[[ "$("$BIN" last --field backend 2>/dev/null)" == "mock daemon"* ]] || fail "the daemon didn't transcribe"
+check "daemon"
+
+echo "PASS: recording length, words and words per minute were reported"
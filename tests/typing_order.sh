@@ -0,0 +1,82 @@
+#!/usr/bin/env bash
+
+# Regression test: two dictations stopped close together type in the order
+# they were submitted, even when the first takes longer to type. A mock
+# daemon stands in for the model and a fake wtype that is slow to type the
+# first transcript for the desktop; python3 is needed. Run from the repo root:
+#   cargo build && tests/typing_order.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons and data, and stand in for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_FALLBACK_CHAIN=daemon
+export WA_DEBOUNCE_MS=0
+unset HYPRLAND_INSTANCE_SIGNATURE SWAYSOCK
+mkdir -p "$XDG_RUNTIME_DIR"
+# Types "2.00 seconds" at once and anything else a second later
+stub wtype <<TYPER
+#!/bin/sh
+for text; do :; done
+[ "\$text" = - ] && text=\$(cat)
+case "\$text" in
+    2.00*) ;;
+    *) sleep 1 ;;
+esac
+printf '%s\n' "\$text" >> "$WORK/typed"
+TYPER
+stub_wl_copy
+stub_notify_send
+LOGS=("*.log")
+QUEUE="$XDG_RUNTIME_DIR/whisp-away-typing.order"
+
+# The first 2 s of the test audio, which the mock transcribes as "2.00 seconds"
+python3 - "$TEST_AUDIO" "$WORK/short.wav" <<'CUT'
+import sys, wave
+with wave.open(sys.argv[1]) as src, wave.open(sys.argv[2], "wb") as dst:
+    dst.setparams(src.getparams())
+    dst.writeframes(src.readframes(2 * src.getframerate()))
+CUT
+
+# Transcripts are the audio's length, and each takes a moment, so the stops overlap
+WA_MOCK_MODE=duration WA_MOCK_DELAY_MS=300 "$BIN" daemon --backend mock --socket-path "$WA_WHISPER_SOCKET" \
+    --ready-file "$WA_WHISPER_SOCKET.ready" > "$WORK/daemon.log" 2>&1 &
+DAEMON_PID=$!
+for _ in $(seq 50); do
+    [ -S "$WA_WHISPER_SOCKET" ] && break
+    sleep 0.1
+done
+[ -S "$WA_WHISPER_SOCKET" ] || fail "the mock daemon did not bind its socket"
+
+# 1. The long recording is stopped first and types slowly; the short one,
+# stopped just after, still types second
+timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --ready-file "$WA_WHISPER_SOCKET.ready" > "$WORK/first.log" 2>&1 &
+first=$!
+# Submitted once it is in line
+for _ in $(seq 50); do
+    [ -s "$QUEUE" ] && break
+    sleep 0.05
+done
+[ -s "$QUEUE" ] || fail "the first stop never got in line"
+timeout 30 "$BIN" stop --backend mock --audio-file "$WORK/short.wav" --ready-file "$WA_WHISPER_SOCKET.ready" > "$WORK/second.log" 2>&1 &
+second=$!
+wait "$first" || fail "the first stop exited with an error"
+wait "$second" || fail "the second stop exited with an error"
+
+grep -q "1 earlier dictation(s) will type first" "$WORK/second.log" || fail "the second stop didn't get in line behind the first"
+[ "$(wc -l < "$WORK/typed")" = 2 ] || fail "expected 2 transcripts typed, got: $(cat "$WORK/typed")"
+[ "$(tail -n1 "$WORK/typed")" = "2.00 seconds" ] || fail "typed out of order: $(tr '\n' '|' < "$WORK/typed")"
+head -n1 "$WORK/typed" | grep -q "^11\.[0-9]* seconds" || fail "the first transcript typed was: $(head -n1 "$WORK/typed")"
+
+# 2. The line is left empty for the next dictation
+[ ! -s "$QUEUE" ] || fail "the typing queue wasn't emptied: $(cat "$QUEUE")"
+
+echo "PASS: overlapping dictations typed in submission order"
@@ -0,0 +1,107 @@
+#!/usr/bin/env bash
+
+# Regression test: a profile with a `window` pattern is picked when a
+# matching window is focused, and the language it carries is what the
+# daemon is asked for, whatever the daemon's own default, and what the
+# last-result file and the journal record. With `language = auto` the
+# detected language reaches the notification. A stand-in hyprctl reports
+# the focused window. Uses the mock backend, so no model is needed. Run
+# from the repo root:
+#   cargo build && tests/window_language.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+DAEMON_PID=""
+on_exit() {
+    [ -n "$DAEMON_PID" ] && kill "$DAEMON_PID" 2>/dev/null
+}
+
+# Keep the test away from real daemons, history and settings, and stand in
+# for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export XDG_CONFIG_HOME="$WORK/config"
+export WA_WHISPER_SOCKET="$WORK/daemon.sock"
+export WA_KEEP_AUDIO=1
+export WA_WHISPER_LANGUAGE=it
+export WA_MOCK_LANGUAGE=fr
+export HYPRLAND_INSTANCE_SIGNATURE=test
+mkdir -p "$XDG_RUNTIME_DIR" "$XDG_CONFIG_HOME/whisp-away/profiles"
+stub_wl_copy
+stub_notify_send stderr
+printf '#!/bin/sh\nprintf '"'"'{"class": "%%s", "initialClass": "%%s"}'"'"' "$(cat "%s")" "$(cat "%s")"\n' \
+    "$WORK/focused" "$WORK/focused" | stub hyprctl
+LOGS=(stop.log daemon.log)
+
+PROFILES="$XDG_CONFIG_HOME/whisp-away/profiles"
+printf 'window = slack\nlanguage = en\n' > "$PROFILES/chat.conf"
+printf 'window = ^(thunderbird|evolution)$\nlanguage = de\n' > "$PROFILES/mail.conf"
+printf 'window = firefox\nlanguage = auto\n' > "$PROFILES/web.conf"
+
+# Stop with window $2 focused, transcribing via $1 (daemon or direct)
+run_stop() {
+    echo "$2" > "$WORK/focused"
+    WA_FALLBACK_CHAIN="$1" timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard \
+        > "$WORK/stop.log" 2>&1 || fail "stop via $1 in $2 exited with an error"
+    if [ "$1" = daemon ]; then
+        [[ "$("$BIN" last --field backend 2>/dev/null)" == "mock daemon"* ]] || fail "the daemon didn't transcribe in $2"
+    fi
+}
+
+# Whether $1 reaches the stop log within two seconds: notify-send isn't waited for
+logged() {
+    for _ in $(seq 20); do
+        grep -q -- "$1" "$WORK/stop.log" && return 0
+        sleep 0.1
+    done
+    return 1
+}
+
+# The requested language $2 and the detected one $3, wherever they are recorded
+check() {
+    local how="$1" language="$2" detected="$3"
+    [ "$("$BIN" last --field language 2>/dev/null)" = "$language" ] || fail "$how: last-result language isn't $language"
+    [ "$("$BIN" last --field detected_language 2>/dev/null)" = "$detected" ] || fail "$how: last-result detected_language isn't $detected"
+    local entry
+    entry="$("$BIN" journal last 2>/dev/null)"
+    grep -q "\"language\": \"$language\"" <<< "$entry" || fail "$how: the journal entry's language isn't $language"
+    grep -q "\"detected_language\": \"$detected\"" <<< "$entry" || fail "$how: the journal entry's detected_language isn't $detected"
+}
+
+# The daemon's own default differs from every profile's language
+WA_WHISPER_LANGUAGE=en "$BIN" daemon --backend mock > "$WORK/daemon.log" 2>&1 &
+DAEMON_PID=$!
+for _ in $(seq 50); do
+    [ -S "$WA_WHISPER_SOCKET" ] && break
+    sleep 0.1
+done
+[ -S "$WA_WHISPER_SOCKET" ] || fail "the mock daemon did not start"
+
+for via in daemon direct; do
+    # 1. Two window classes, two profiles, two languages
+    run_stop "$via" Slack
+    grep -q "Using profile chat for the focused window" "$WORK/stop.log" || fail "$via: Slack didn't pick the chat profile"
+    check "$via, Slack" en en
+    run_stop "$via" thunderbird
+    grep -q "Using profile mail for the focused window" "$WORK/stop.log" || fail "$via: thunderbird didn't pick the mail profile"
+    check "$via, thunderbird" de de
+
+    # 2. No profile for the window: the configured language, not the daemon's
+    run_stop "$via" org.gnome.Nautilus
+    grep -q "Using profile" "$WORK/stop.log" && fail "$via: a profile was picked for Nautilus"
+    check "$via, Nautilus" it it
+
+    # 3. Detection: the notification names the language that was detected
+    run_stop "$via" firefox
+    check "$via, firefox" auto fr
+    logged "Language: fr" || fail "$via: the notification doesn't name the detected language"
+done
+
+# 4. --profile wins over the focused window
+echo Slack > "$WORK/focused"
+WA_FALLBACK_CHAIN=daemon timeout 30 "$BIN" stop --backend mock --profile mail --audio-file "$TEST_AUDIO" --output clipboard \
+    > "$WORK/stop.log" 2>&1 || fail "stop --profile mail exited with an error"
+check "--profile mail in Slack" de de
+
+echo "PASS: the focused window's profile set the language that was requested and recorded"
@@ -0,0 +1,125 @@
+#!/usr/bin/env bash
+
+# Regression test: the window a transcript goes to is recorded in the
+# last-result file and the journal as window_app_id and window_title. Long
+# titles are cut to 200 characters, WA_RECORD_WINDOW=hash keeps a hash of
+# the title instead and WA_RECORD_WINDOW=off nothing, and a compositor slower
+# than 100 ms to answer is given up on. Stand-in hyprctl and swaymsg report
+# the focused window. Uses the mock backend, so no model is needed; python3
+# is. Run from the repo root:
+#   cargo build && tests/window_title.sh
+
+set -euo pipefail
+
+. tests/lib.sh
+
+# Keep the test away from real daemons, history and settings, and stand in
+# for the desktop tools
+export XDG_RUNTIME_DIR="$WORK/runtime"
+export XDG_DATA_HOME="$WORK/data"
+export XDG_CONFIG_HOME="$WORK/config"
+export WA_FALLBACK_CHAIN=direct
+export WA_DEBOUNCE_MS=0
+export WA_KEEP_AUDIO=1
+mkdir -p "$XDG_RUNTIME_DIR"
+stub_wl_copy
+stub_notify_send
+# Each call is logged when it starts and when it answers, after $WORK/delay seconds if set
+stub hyprctl <<TOOL
+#!/bin/sh
+echo \$\$ >> "$WORK/started"
+[ -f "$WORK/delay" ] && sleep "\$(cat "$WORK/delay")"
+cat "$WORK/window.json"
+echo \$\$ >> "$WORK/answered"
+TOOL
+stub swaymsg < "$WORK/bin/hyprctl"
+LOGS=(stop.log)
+
+# Hyprland's active window: class $1, title $2
+hyprland_window() {
+    python3 -c 'import json, sys; print(json.dumps({"class": sys.argv[1], "initialClass": sys.argv[1], "title": sys.argv[2]}))' \
+        "$1" "$2" > "$WORK/window.json"
+}
+
+run_stop() {
+    timeout 30 "$BIN" stop --backend mock --audio-file "$TEST_AUDIO" --output clipboard \
+        > "$WORK/stop.log" 2>&1 || fail "stop exited with an error"
+}
+
+# Field $1 of the last result and of the last journal entry, each on a line
+# ("-" when missing)
+recorded() {
+    "$BIN" last 2>/dev/null | python3 -c 'import json, sys; print(json.load(sys.stdin).get(sys.argv[1], "-"))' "$1"
+    "$BIN" journal last 2>/dev/null | python3 -c 'import json, sys; print(json.load(sys.stdin).get(sys.argv[1], "-"))' "$1"
+}
+
+# Both places recorded $2 as field $1
+check() {
+    local field="$1" expected="$2" value
+    while IFS= read -r value; do
+        [ "$value" = "$expected" ] || fail "$field was recorded as \"$value\", expected \"$expected\""
+    done < <(recorded "$field")
+}
+
+export HYPRLAND_INSTANCE_SIGNATURE=test
+
+# 1. App id and title as the compositor reports them
+hyprland_window firefox "Release notes — Mozilla Firefox"
+run_stop
+check window_app_id firefox
+check window_title "Release notes — Mozilla Firefox"
+
+# 2. A long title is cut to 200 characters, the last an ellipsis
+LONG="$(printf 'ä%.0s' $(seq 300))"
+hyprland_window kitty "$LONG"
+run_stop
+check window_title "$(printf 'ä%.0s' $(seq 199))…"
+
+# 3. Hashed: the same title always gives the same hash, which isn't the title
+hyprland_window Slack "Direct message with Alex"
+WA_RECORD_WINDOW=hash run_stop
+check window_app_id Slack
+HASH="$("$BIN" last --field window_title)"
+[[ "$HASH" == fnv1a:* ]] || fail "the hashed title is \"$HASH\""
+WA_RECORD_WINDOW=hash run_stop
+check window_title "$HASH"
+hyprland_window Slack "Direct message with Sam"
+WA_RECORD_WINDOW=hash run_stop
+[ "$("$BIN" last --field window_title)" != "$HASH" ] || fail "two titles hashed the same"
+
+# 4. Off: neither app id nor title
+WA_RECORD_WINDOW=off run_stop
+check window_app_id -
+check window_title -
+
+# 5. A compositor slower than the budget is given up on without holding up
+# output: stop is done before the last lookup answers. Earlier lookups, for
+# profiles and glossaries, are still waited for.
+echo 1 > "$WORK/delay"
+rm -f "$WORK/started" "$WORK/answered"
+run_stop
+[ "$(wc -l < "$WORK/started")" = "$(( $(wc -l < "$WORK/answered") + 1 ))" ] \
+    || fail "stop waited for the slow compositor to report the window"
+grep -q "focused window took over 100 ms" "$WORK/stop.log" || fail "giving up on the slow compositor wasn't logged"
+check window_app_id -
+rm "$WORK/delay"
+
+# 6. sway: app_id and name of the focused node, or the class of an Xwayland one
+unset HYPRLAND_INSTANCE_SIGNATURE
+export SWAYSOCK="$WORK/sway.sock"
+cat > "$WORK/window.json" <<'TREE'
+{"nodes": [{"nodes": [{"focused": false, "app_id": "foot", "name": "shell"},
+                      {"focused": true, "app_id": "org.gnome.Nautilus", "name": "Downloads"}]}]}
+TREE
+run_stop
+check window_app_id org.gnome.Nautilus
+check window_title Downloads
+cat > "$WORK/window.json" <<'TREE'
+{"nodes": [{"floating_nodes": [{"focused": true, "app_id": null, "name": "Steam",
+                                "window_properties": {"class": "steam"}}]}]}
+TREE
+run_stop
+check window_app_id steam
+check window_title Steam
+
+echo "PASS: the focused window was recorded with the transcript, cut, hashed or left out as configured"